@@ -0,0 +1,82 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// validator: Times the parts of a release that grow with its file count, on a synthetic release from bench_fixtures.
+// ------------------------------------------------------------------------------------------------------------
+
+#[macro_use] extern crate criterion;
+extern crate ges_scriptutility;
+
+use std::env;
+use std::path::PathBuf;
+
+use criterion::Criterion;
+
+use ges_scriptutility::argument_handler::Arguments;
+use ges_scriptutility::{bench_fixtures, folder_compressor, reslist_builder, shared};
+
+/// Returns arguments for releasing the synthetic release, writing it first if it isn't there yet.  Its size comes from
+/// GESMRA_BENCH_FILES, and each size gets its own directory so changing it never benchmarks a stale release.
+fn get_bench_args() -> Arguments
+{
+    let file_count = env::var("GESMRA_BENCH_FILES").ok().and_then(|x| x.parse().ok()).unwrap_or( bench_fixtures::DEFAULT_FILE_COUNT );
+
+    let mut bench_dir = PathBuf::from( env!("CARGO_MANIFEST_DIR") );
+    bench_dir.push("target");
+    bench_dir.push("bench-fixtures");
+    bench_dir.push( file_count.to_string() );
+
+    let mut args = shared::get_barebones_args();
+    args.rootdir = bench_dir.join("gesource");
+    args.gesdir = args.rootdir.clone();
+    args.compressed_out = Some(bench_dir.join("gesource_compressed"));
+    args.compress = true;
+
+    if !args.rootdir.join("maps").join( format!( "{}.res", bench_fixtures::BENCH_MAP_NAME ) ).is_file()
+    {
+        bench_fixtures::generate_bench_install( &args.rootdir, file_count ).expect("Failed to write the benchmark release!");
+    }
+
+    args
+}
+
+/// Walking the whole release, which everything else is built on.
+fn bench_directory_walk( c: &mut Criterion )
+{
+    let args = get_bench_args();
+
+    c.bench_function( "directory walk", |b| b.iter(|| shared::get_files_and_info_in_directory( &args.rootdir, "", &[] ).unwrap()) );
+}
+
+/// Checking a reslist that lists every file, against a walk that's already been made.
+fn bench_reslist_validation( c: &mut Criterion )
+{
+    let args = get_bench_args();
+    let reslist_path = args.rootdir.join("maps").join( format!( "{}.res", bench_fixtures::BENCH_MAP_NAME ) );
+    let directory_tree = reslist_builder::generate_directory_tree( &args ).unwrap();
+
+    c.bench_function( "reslist validation", |b| b.iter(|| reslist_builder::check_reslist_with_tree( &args, &reslist_path, &directory_tree ).unwrap()) );
+}
+
+/// Compressing a release that's already compressed, so every file should be skipped.
+fn bench_compression_skip( c: &mut Criterion )
+{
+    let args = get_bench_args();
+
+    // The first run does the actual compressing, so it's left out of the timing.
+    folder_compressor::construct_compressed_filesystem( &args, bench_fixtures::BENCH_MAP_NAME ).unwrap();
+
+    c.bench_function( "compression skip", |b| b.iter(|| folder_compressor::construct_compressed_filesystem( &args, bench_fixtures::BENCH_MAP_NAME ).unwrap()) );
+}
+
+criterion_group!
+{
+    name = benches;
+    // Each sample covers tens of thousands of files, so a handful of them is plenty to see a regression.
+    config = Criterion::default().sample_size(10);
+    targets = bench_directory_walk, bench_reslist_validation, bench_compression_skip
+}
+criterion_main!(benches);
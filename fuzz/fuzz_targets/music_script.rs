@@ -0,0 +1,15 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate ges_scriptutility;
+
+use ges_scriptutility::fuzz_targets;
+
+fuzz_target!(|data: &[u8]|
+{
+    fuzz_targets::fuzz_music_script( data );
+});
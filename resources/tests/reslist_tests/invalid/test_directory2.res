@@ -0,0 +1,8 @@
+"resources"
+{
+	"scripts/maps/test_map.txt"	"file"
+	"scripts/music/level_music_test_map.txt"	"file"
+	"scripts/soundscapes_test_map.txt"	"file"
+	"sound/music/custom_song1.mp3"	"file"
+	"sound/music/..."	"dir"
+}
@@ -0,0 +1,7 @@
+"resources"
+{
+	"scripts/maps/test_map.txt"	"file"
+	"scripts/music/level_music_test_map.txt"	"file"
+	"scripts/soundscapes_test_map.txt"	"file"
+	"sound/music/..."	"dir"
+}
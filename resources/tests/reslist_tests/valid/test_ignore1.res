@@ -0,0 +1,12 @@
+// Reslist with comments, and a duplicate that's there on purpose.
+"resources"
+{
+	"scripts/maps/test_map.txt"	"file"
+	"scripts/music/level_music_test_map.txt"	"file"
+	"scripts/soundscapes_test_map.txt"	"file"	// Ambience for the bunker.
+	"sound/music/custom_song1.mp3"	"file"
+	"sound/music/custom_song1.mp3"	"file"	// ges-ignore: reslist-duplicate-entry
+	"sound/music/custom_song2.mp3"	"file"
+	"sound/music/custom_song3.mp3"	"file"
+	"sound/music/custom_song4.mp3"	"file"
+}
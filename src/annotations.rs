@@ -0,0 +1,104 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// --------------------------------------------------------------------------------------------------------
+// annotations: Prints every problem found during a run as GitHub Actions annotations on the right script.
+// --------------------------------------------------------------------------------------------------------
+
+use rules;
+use rules::{Finding, Severity};
+use shared;
+
+/// Prints a GitHub Actions workflow command for every problem reported so far.
+pub fn print_github_annotations()
+{
+    for finding in rules::get_findings()
+    {
+        println!( "{}", get_github_annotation( &finding ) );
+    }
+}
+
+/// Returns the workflow command that annotates the finding's script with it.  Suppressed problems become notices, so
+/// they're still visible on a pull request without failing it.
+fn get_github_annotation( finding: &Finding ) -> String
+{
+    let command = if finding.suppressed
+    {
+        "notice"
+    }
+    else
+    {
+        match finding.severity
+        {
+            Severity::Error => "error",
+            _ => "warning",
+        }
+    };
+
+    let mut properties: Vec<String> = Vec::new();
+
+    match finding.file
+    {
+        Some(ref x) =>
+        {
+            let file_path = shared::get_working_relative_path( x ).to_string_lossy().replace("\\", "/");
+            properties.push( format!( "file={}", escape_property( file_path.trim_start_matches("./") ) ) );
+
+            if finding.line > 0
+            {
+                properties.push( format!( "line={}", finding.line ) );
+            }
+        },
+        None => {},
+    }
+
+    properties.push( format!( "title={}", escape_property( finding.rule_id ) ) );
+
+    let mut message = finding.message.clone();
+
+    if finding.suppressed
+    {
+        message.push_str("  (Suppressed by a ges-ignore comment.)");
+    }
+
+    format!( "::{} {}::{}", command, properties.join(","), escape_data( &message ) )
+}
+
+/// Escapes the characters GitHub would otherwise read as the end of an annotation's message.
+fn escape_data( data: &str ) -> String
+{
+    data.replace("%", "%25").replace("\r", "%0D").replace("\n", "%0A")
+}
+
+/// Escapes the characters GitHub would otherwise read as the end of an annotation property.
+fn escape_property( property: &str ) -> String
+{
+    escape_data( property ).replace(":", "%3A").replace(",", "%2C")
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::PathBuf;
+    use super::*;
+
+    #[test]
+    fn test_get_github_annotation()
+    {
+        let mut finding = Finding { rule_id: "map-script-duplicate-entry", severity: Severity::Error, message: String::from("100% wrong!\nFix it."),
+                                    file: Some(PathBuf::from("gesource/scripts/maps/a,b.txt")), line: 27, suppressed: false };
+
+        assert_eq!( get_github_annotation( &finding ), "::error file=gesource/scripts/maps/a%2Cb.txt,line=27,title=map-script-duplicate-entry::100%25 wrong!%0AFix it." );
+
+        finding.suppressed = true;
+        assert!( get_github_annotation( &finding ).starts_with("::notice ") );
+
+        // Problems that aren't in any one file still get an annotation, just not on a file.
+        let finding = Finding { rule_id: "reslist-missing", severity: Severity::Warning, message: String::from("No reslist!"),
+                                file: None, line: 0, suppressed: false };
+
+        assert_eq!( get_github_annotation( &finding ), "::warning title=reslist-missing::No reslist!" );
+    }
+}
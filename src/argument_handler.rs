@@ -7,15 +7,48 @@
 // argument_handler: Parses commandline input and ensures its validity.
 // ---------------------------------------------------------------------
 
-use clap::{Arg, App};
+use clap::{Arg, App, ArgMatches, SubCommand};
 
 use std::env;
 use std::path::PathBuf;
 use std::fs;
-use std::io::{Error, ErrorKind};
+
+use shared;
+use preflight;
+use manifest;
+use manifest::HashAlgorithm;
+use release_signing;
+use rules;
+use file_categories;
+use map_script_schema;
+use stock_content;
+use ges_version;
+use bench_fixtures;
+use daemon;
+use smoke_test;
+use playtest;
+use map_script_builder;
+use presets;
+use user_config;
+use release_lock;
+use music_script_builder;
+use rules::Severity;
+use watchdog;
+use file_writer;
+use compile_import;
+use error::GesError;
+
+/// The extra ways problems can be printed, on top of the usual console messages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat
+{
+    Text,
+    /// GitHub Actions annotations, so problems show up on the scripts in a pull request.
+    Github,
+}
 
 /// Struct that holds the core arguments of the program.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Arguments
 {
     pub rootdir: PathBuf,
@@ -25,23 +58,202 @@ pub struct Arguments
     pub maxplayers: i32,
     pub resintensity: i32,
     pub teamthresh: i32,
+    pub map_group: Option<String>,
+    /// The only gamemodes a newly created map script lets the map be played with.
+    pub only_gamemodes: Vec<String>,
+    /// The last day a playtest release should be run, as YYYY-MM-DD.
+    pub playtest_expires: Option<String>,
+    /// What a playtest release is testing.
+    pub playtest_notes: Option<String>,
+    pub preset: Option<String>,
+    pub presets_file: Option<PathBuf>,
+    /// The value arguments given on the commandline or through the environment, which a preset won't change.
+    pub given_values: Vec<&'static str>,
+    /// Weights a preset gives entries in the map script's sections, as (section, entry, weight).
+    pub section_weights: Vec<(String, String, i32)>,
     pub compress: bool,
     pub recompress: bool,
     pub verbose: bool,
+    /// Print why every file was or wasn't included in the reslist and compression.
+    pub trace: bool,
     pub fullcheck: bool,
+    pub daemon: bool,
+    /// How many seconds daemon mode waits between fullchecks.
+    pub daemon_interval: u64,
+    pub report_dir: PathBuf,
+    pub notify_hook: Option<String>,
     pub noexitprompt: bool,
+    /// How many seconds the exit prompt waits for input before exiting anyway, or 0 to wait forever.
+    pub pause_timeout: u64,
+    pub compressed_out: Option<PathBuf>,
+    /// Where to copy everything a server needs, including the server only files clients never download.
+    pub server_package: Option<PathBuf>,
+    pub include_sources: bool,
+    /// List directories named after the map as a single entry when generating a reslist.
+    pub reslist_dirs: bool,
+    /// Hold scripts this program didn't generate to the looser legacy rules in fullcheck mode.
+    pub legacy_format: bool,
+    pub stdin: bool,
+    pub decompress: Option<PathBuf>,
+    pub decompress_out: Option<PathBuf>,
+    pub keygen: Option<PathBuf>,
+    pub verify_release: Option<PathBuf>,
+    pub verify_key: Option<PathBuf>,
+    pub gen_manifest: Option<PathBuf>,
+    pub gen_manifest_out: PathBuf,
+    /// The gesource directories of two GE:S installs to compare.
+    pub compare_installs: Option<(PathBuf, PathBuf)>,
+    /// The gesource directories of the GE:S install to copy maps and scripts from, and the one to copy them to.
+    pub mirror: Option<(PathBuf, PathBuf)>,
+    pub mirror_dry_run: bool,
+    /// A console log from a GE:S server or client to look for problems in.
+    pub analyze_log: Option<PathBuf>,
+    pub preview_weight: bool,
+    /// An existing map script to preview the selection weight of, instead of the weight and player count arguments.
+    pub preview_weight_script: Option<PathBuf>,
+    pub preview_weight_csv: bool,
+    /// The tests directory to generate map script fixtures in.
+    pub gen_fixtures: Option<PathBuf>,
+    /// The map whose music script the release's map should get a copy of.
+    pub copy_music_from: Option<String>,
+    /// The gesource directory to write a synthetic release into for the benchmarks.
+    pub bench_fixtures: Option<PathBuf>,
+    /// How many content files the synthetic release has.
+    pub bench_file_count: usize,
+    pub sign_key: Option<PathBuf>,
+    /// How the manifest hashes the files it lists.
+    pub hash_algorithm: HashAlgorithm,
+    pub delta_from: Option<PathBuf>,
+    pub delta_out: Option<PathBuf>,
+    pub torrent: Option<PathBuf>,
+    /// Where to write a changelog of what changed since the previous compressed release.
+    pub changelog: Option<PathBuf>,
+    /// Who made the release, credited in its changelog and torrent.
+    pub author: Option<String>,
+    /// Start from the defaults rather than the settings the last release was made with.
+    pub ignore_lock: bool,
+    /// Generate the release twice in temporary directories and make sure both runs give the same files.
+    pub verify_deterministic: bool,
+    /// A Hammer or VBCT compile output to build the release in the root directory from before releasing it.
+    pub from_compile: Option<PathBuf>,
+    pub server_config: bool,
+    pub dedupe: bool,
+    pub trackers: Vec<String>,
+    pub web_seeds: Vec<String>,
+    pub walk_timeout: usize,
+    pub hydrate: bool,
+    pub check_only: bool,
+    /// Stage anything meant for a GE:S install that needs elevation to write in a user writable directory instead.
+    pub user_scope: bool,
+    pub check_update: bool,
+    pub auto_update_check: bool,
+    pub crash_report: bool,
+    pub music_areas: Vec<String>,
+    pub fix: bool,
+    pub min_tracks: usize,
+    /// How many stock tracks to pick at random for a new music script without music of its own, or 0 for the defaults.
+    pub sample_music: usize,
+    pub music_seed: Option<u64>,
+    pub match_length: u32,
+    pub rules_file: Option<PathBuf>,
+    pub rule_overrides: Vec<(String, Severity)>,
+    pub list_rules: bool,
+    pub categories_file: Option<PathBuf>,
+    /// A map script schema that adds to or replaces versions of the built in one.
+    pub schema_file: Option<PathBuf>,
+    pub stock_content_file: Option<PathBuf>,
+    pub offline: bool,
+    /// The GE:S version the release is for, which decides what its scripts are checked against.
+    pub ges_version: Option<String>,
+    pub sarif: Option<PathBuf>,
+    pub discord_webhook: Option<String>,
+    /// The server executable to load the map on after every check passes.
+    pub smoke_test: Option<PathBuf>,
+    /// How many seconds the smoke test keeps the server running.
+    pub smoke_test_time: u64,
+    pub output: OutputFormat,
 }
 
 /// Takes the program arguments input by the user, validates them, and returns them as an Arguments object.
 /// Also infers the map name.
-pub fn parse_and_validate_arguments() -> Result<( Arguments, String ), Error>
+pub fn parse_and_validate_arguments() -> Result<( Arguments, String ), GesError>
 {
-    let program_arguments = parse_arguments();
+    // The user config only fills in what the commandline and environment leave out, so it has to be read first.
+    let user_config_path = user_config::load_user_config()?;
+
+    let mut program_arguments = parse_arguments()?;
+
+    match user_config_path
+    {
+        Some(ref x) => user_config::warn_unused_settings( x ),
+        None => {},
+    }
+
+    // Checking the arguments already scans directories, so the timeout has to be in place before that.
+    watchdog::set_walk_timeout( program_arguments.walk_timeout );
+    file_writer::set_read_only( program_arguments.check_only );
+
+    // Checking for updates doesn't involve a map at all, so there's nothing to validate.
+    if program_arguments.check_update
+    {
+        return Ok((program_arguments, String::from("invalid")));
+    }
+
+    // The rules file decides how every later check is reported, so a broken one is as bad as a broken argument.
+    match program_arguments.rules_file.clone()
+    {
+        Some(x) => program_arguments.rule_overrides.extend( rules::read_rules_file( &x ).map_err(|e| e.in_file( &x ))? ),
+        None => {},
+    }
+
+    // The schema decides what every map script is checked against, so it's read before any of them are.
+    match program_arguments.schema_file.clone()
+    {
+        Some(x) => map_script_schema::set_schema_overrides( map_script_schema::read_schema_file( &x ).map_err(|e| e.in_file( &x ))? ),
+        None => {},
+    }
+
+    // Categories decide which files are scanned and shipped, so they have to be in place before anything looks at a file.
+    match program_arguments.categories_file.clone()
+    {
+        Some(x) => file_categories::set_category_overrides( file_categories::read_categories_file( &x ).map_err(|e| e.in_file( &x ))? ),
+        None => {},
+    }
+
+    // Stock content is what releases are compared against, so a broken file would quietly let problems through.
+    match program_arguments.stock_content_file.clone()
+    {
+        Some(x) => stock_content::set_added_stock_files( stock_content::read_stock_content_file( &x ).map_err(|e| e.in_file( &x ))? ),
+        None => {},
+    }
+
+    // Presets only fill in values, so they have to be applied before anything shows or checks them.
+    match program_arguments.preset.clone()
+    {
+        Some(x) => presets::apply_preset( &mut program_arguments, &x )?,
+        None => {},
+    }
+
+    release_lock::apply_locked_section_weights( &mut program_arguments );
+
+    // Listing the rules doesn't involve a map either.
+    if program_arguments.list_rules
+    {
+        return Ok((program_arguments, String::from("invalid")));
+    }
+
+    // Every script check depends on what the targeted GE:S version expects, so it has to be known before any of them.
+    ges_version::resolve_ges_version( &mut program_arguments )?;
+
     let map_name = get_map_name( &program_arguments );
 
     if program_arguments.verbose
     {
-        if program_arguments.fullcheck
+        if program_arguments.decompress != None
+        {
+            println!( "Running in decompress mode with arguments:" );
+        }
+        else if program_arguments.fullcheck
         {
             println!( "Running in fullcheck mode with arguments:" );
         }
@@ -58,6 +270,12 @@ pub fn parse_and_validate_arguments() -> Result<( Arguments, String ), Error>
         println!( "\t{} as the maxplayers!", program_arguments.maxplayers );
         println!( "\t{} as the resintensity!", program_arguments.resintensity );
         println!( "\t{} as the teamthresh!", program_arguments.teamthresh );
+
+        match user_config_path
+        {
+            Some(ref x) => println!( "\t{} as the user config!", x.display() ),
+            None => {},
+        }
     }
 
     // Make sure all of our arguments make sense, exit if not.
@@ -68,7 +286,7 @@ pub fn parse_and_validate_arguments() -> Result<( Arguments, String ), Error>
 }
 
 /// Collects the arguments into an easy to reference struct.
-fn parse_arguments() -> Arguments
+fn parse_arguments() -> Result<Arguments, GesError>
 {
     let matches = App::new("GoldenEye: Source 5.0 Map Script Utility")
         .version("1.0.2")
@@ -116,11 +334,59 @@ fn parse_arguments() -> Arguments
             .value_name("INT")
             .help( "How many players need to be present before we switch to teamplay" )
             .takes_value(true))
+        .arg(Arg::with_name("preset")
+            .long("preset")
+            .value_name("PRESET")
+            .help( "Fill in a new map script with settings suited to casual, competitive, or tournament servers.  Values given with their own options still win." )
+            .takes_value(true))
+        .arg(Arg::with_name("presets")
+            .long("presets")
+            .value_name("FILE")
+            .help( "A presets file that changes or adds to the built in presets.  Each line is a preset name, a map script setting like BaseWeight or GamemodeWeights/YOLT, and its value." )
+            .takes_value(true))
+        .arg(Arg::with_name("map-group")
+            .long("map-group")
+            .value_name("MAP")
+            .help( "The map this one is a variant of, such as ge_foo for ge_foo_classic.  Written into a newly created map script so its group can be checked." )
+            .takes_value(true))
+        .arg(Arg::with_name("only-gamemodes")
+            .long("only-gamemodes")
+            .value_name("NAMES")
+            .help( "Comma separated list of the only gamemodes the map can be played with, like LTK,YOLT.  A newly created map script weights each of them and gives every other gamemode a weight of 0." )
+            .takes_value(true))
+        .arg(Arg::with_name("playtest-expires")
+            .long("playtest-expires")
+            .value_name("DATE")
+            .help( "Stamp the map script as a playtest release that expires after the given YYYY-MM-DD date.  Checks warn about map scripts whose playtest has expired." )
+            .takes_value(true))
+        .arg(Arg::with_name("playtest-notes")
+            .long("playtest-notes")
+            .value_name("TEXT")
+            .help( "Stamp the map script as a playtest release, noting what's being tested." )
+            .takes_value(true))
         .arg(Arg::with_name("fullcheck")
             .short("f")
             .long("fullcheck")
             .help( "With this flag set, the program will instead not do map release checks but instead check all script files in the supplied or detected GE:S directory.  Good for server owners who want to check all of their script files at once." )
             .takes_value(false))
+        .arg(Arg::with_name("daemon")
+            .long("daemon")
+            .help( "Keep running fullcheck mode on a schedule instead of exiting, writing a report of each run to the report directory.  Needs --fullcheck." ))
+        .arg(Arg::with_name("interval")
+            .long("interval")
+            .value_name("INTERVAL")
+            .help( "How long daemon mode waits between fullchecks, like 24h, 30m, or 2d.  A bare number is in hours.  If none is supplied a day is used." )
+            .takes_value(true))
+        .arg(Arg::with_name("report-dir")
+            .long("report-dir")
+            .value_name("DIRECTORY")
+            .help( "Where daemon mode writes its reports.  If none is supplied fullcheck_reports in the current directory is used." )
+            .takes_value(true))
+        .arg(Arg::with_name("notify-hook")
+            .long("notify-hook")
+            .value_name("COMMAND")
+            .help( "A shell command daemon mode runs when a fullcheck finds failures the one before it didn't.  It's given the report's path in GESMRA_REPORT, and the new failures in GESMRA_NEW_FAILURES." )
+            .takes_value(true))
         .arg(Arg::with_name("compress")
             .short("c")
             .long("compress")
@@ -129,27 +395,372 @@ fn parse_arguments() -> Arguments
         .arg(Arg::with_name("recompress")
             .short("z")
             .long("recompress")
-            .help( "Same as compressed, but will delete all existing compressed files before starting.  Its usage implies the compressed flag." )
+            .help( "Same as compressed, but will delete the release's existing compressed files, and those of the last release compressed to the same place, before starting.  Other files in the compressed directory are left alone.  Its usage implies the compressed flag." )
+            .takes_value(false))
+        .arg(Arg::with_name("compressed-out")
+            .long("compressed-out")
+            .value_name("DIRECTORY")
+            .help( "Where to place the compressed files instead of the adjacent gesource_compressed/gesource directory.  Files are placed directly inside it, so point it at a gesource folder if you want one." )
+            .takes_value(true))
+        .arg(Arg::with_name("server-package")
+            .long("server-package")
+            .value_name("DIRECTORY")
+            .help( "Also copy everything a server needs to run the map into this directory, laid out like the root directory.  That's the map, its reslist, and every file clients download, along with server only files like python gamemodes, navigation meshes, and configs." )
+            .takes_value(true))
+        .arg(Arg::with_name("sign-key")
+            .long("sign-key")
+            .value_name("FILE")
+            .help( "Sign the manifest written during compression with the secret key in the given file, made with the keygen command." )
+            .takes_value(true))
+        .arg(Arg::with_name("hash-algorithm")
+            .long("hash-algorithm")
+            .value_name("ALGORITHM")
+            .help( "Either blake3 or sha256, the algorithm the manifest hashes files with.  Defaults to blake3, which is much faster on large releases." )
+            .takes_value(true))
+        .arg(Arg::with_name("delta-from")
+            .long("delta-from")
+            .value_name("FILE")
+            .help( "The manifest of the previous release.  After compressing, also build a delta package holding only the files that changed since then, along with a list of files to delete." )
+            .takes_value(true))
+        .arg(Arg::with_name("delta-out")
+            .long("delta-out")
+            .value_name("DIRECTORY")
+            .help( "Where to place the delta package instead of the adjacent gesource_delta/gesource directory." )
+            .takes_value(true))
+        .arg(Arg::with_name("server-config")
+            .long("server-config")
+            .help( "After compressing, also write .htaccess and web.config files so Apache and IIS fast download servers serve the compressed files correctly." )
+            .takes_value(false))
+        .arg(Arg::with_name("dedupe")
+            .long("dedupe")
+            .help( "Compress files that are identical to another one in the release only once, and hard link the rest to it.  Saves space and upload time on the fast download server." )
+            .takes_value(false))
+        .arg(Arg::with_name("torrent")
+            .long("torrent")
+            .value_name("FILE")
+            .help( "After compressing, also write a .torrent file holding the compressed release to the given path." )
+            .takes_value(true))
+        .arg(Arg::with_name("changelog")
+            .long("changelog")
+            .value_name("FILE")
+            .help( "After compressing, also write a Markdown changelog of the files and script values that changed since the previous release to the given path." )
+            .takes_value(true))
+        .arg(Arg::with_name("author")
+            .long("author")
+            .value_name("NAME")
+            .help( "Who made the release, like \"Entropy-Soldier <entropysoldierprojects@gmail.com>\".  Credited in the changelog and torrent, and usually set once in the user config." )
+            .takes_value(true))
+        .arg(Arg::with_name("trackers")
+            .long("trackers")
+            .value_name("URLS")
+            .help( "Comma separated list of trackers to announce the torrent to, tried in the order given." )
+            .takes_value(true))
+        .arg(Arg::with_name("web-seeds")
+            .long("web-seeds")
+            .value_name("URLS")
+            .help( "Comma separated list of web servers that also host the compressed release, such as fast download servers, for torrent clients to download from." )
+            .takes_value(true))
+        .arg(Arg::with_name("include-sources")
+            .long("include-sources")
+            .help( "Include map sources, editor backups, and compile logs (.vmf, .vmx, .log, .prt, .lin, etc.) in the reslist and compressed files instead of leaving them out." )
+            .takes_value(false))
+        .arg(Arg::with_name("reslist-dirs")
+            .long("reslist-dirs")
+            .help( "When generating a reslist, list directories named after the map as a single \"dir\" entry instead of every file in them.  Only use this if the servers running the map expand directory entries." )
+            .takes_value(false))
+        .arg(Arg::with_name("legacy-format")
+            .long("legacy-format")
+            .help( "In fullcheck mode, check map and music scripts this program didn't generate, such as those left over from GE:S 4.2, with looser rules that only fail problems that stop GE:S from using them." )
+            .takes_value(false))
+        .arg(Arg::with_name("stdin")
+            .long("stdin")
+            .help( "Batch mode for editors and other tools.  Reads script paths from stdin, one per line, and writes a JSON result for each one to stdout.  Lines that don't start with { are diagnostics and can be ignored." )
+            .takes_value(false))
+        .arg(Arg::with_name("check-update")
+            .long("check-update")
+            .help( "Check GitHub for a newer version of this program, print its changelog if there is one, and exit." )
+            .takes_value(false))
+        .arg(Arg::with_name("auto-update-check")
+            .long("auto-update-check")
+            .help( "Check GitHub for a newer version of this program before running as normal.  Being offline won't stop the run." )
+            .takes_value(false))
+        .arg(Arg::with_name("crash-report")
+            .long("crash-report")
+            .help( "If anything goes wrong, write a diagnostic bundle you can attach to a GitHub issue without asking first.  Nothing is sent anywhere." )
+            .takes_value(false))
+        .arg(Arg::with_name("music-areas")
+            .long("music-areas")
+            .value_name("NAMES")
+            .help( "Comma separated list of music area names that music script sections may use, on top of the trigger names found in the map itself." )
+            .takes_value(true))
+        .arg(Arg::with_name("min-tracks")
+            .long("min-tracks")
+            .value_name("INT")
+            .help( "Warn about music scripts whose main list has fewer than this many different tracks, since they get repetitive in long matches.  0 turns the warning off.  Defaults to 4." )
+            .takes_value(true))
+        .arg(Arg::with_name("sample-music")
+            .long("sample-music")
+            .value_name("INT")
+            .help( "When a new music script has no music of the map's own to use, fill it with this many tracks picked at random from the stock music instead of the same six defaults." )
+            .takes_value(true))
+        .arg(Arg::with_name("music-seed")
+            .long("music-seed")
+            .value_name("INT")
+            .help( "Seed for --sample-music, so the same tracks are picked every time.  The seed that was used is printed when none is given." )
+            .takes_value(true))
+        .arg(Arg::with_name("match-length")
+            .long("match-length")
+            .value_name("MINUTES")
+            .help( "How long a typical match on the map lasts, used to warn about music that will loop too often.  0 turns the warning off.  Defaults to 15." )
+            .takes_value(true))
+        .arg(Arg::with_name("hydrate")
+            .long("hydrate")
+            .help( "Download any files OneDrive, Dropbox, or a similar client only stores online before compressing, so compression can't stall or fail partway." )
+            .takes_value(false))
+        .arg(Arg::with_name("check-only")
+            .long("check-only")
+            .help( "Only check the release, without ever writing to disk.  Missing scripts are reported instead of created, so this is safe to run on directories you don't own." )
+            .takes_value(false))
+        .arg(Arg::with_name("user-scope")
+            .long("user-scope")
+            .help( "If a GE:S install can't be written to without running as administrator, like one under Program Files, write what was meant for it to a staging directory in your user folder instead." )
+            .takes_value(false))
+        .arg(Arg::with_name("walk-timeout")
+            .long("walk-timeout")
+            .value_name("SECONDS")
+            .help( "Warn about any directory scan that takes longer than this, which usually means a slow or unresponsive drive.  0 turns the warning off.  Defaults to 120." )
+            .takes_value(true))
+        .arg(Arg::with_name("verify-deterministic")
+            .long("verify-deterministic")
+            .help( "Generate the release twice in temporary copies of it and make sure both give exactly the same files, instead of generating it in place.  Nothing in the release itself is changed." )
+            .takes_value(false))
+        .arg(Arg::with_name("from-compile")
+            .long("from-compile")
+            .value_name("DIRECTORY")
+            .help( "A Hammer or VBCT compile output to build the release from.  Its bsp and any custom content beside it in materials, models, sound, particles, resource, and scripts are copied into the root directory, which is then released as normal." )
+            .takes_value(true))
+        .arg(Arg::with_name("ignore-lock")
+            .long("ignore-lock")
+            .help( "Start from the defaults instead of the settings in the gesrelease.lock the last release was made with.  The lock is still rewritten once the release succeeds." )
+            .takes_value(false))
+        .arg(Arg::with_name("rules")
+            .long("rules")
+            .value_name("FILE")
+            .help( "A rules file that changes how seriously checks are taken.  Each line is a rule id followed by error, warning, or off." )
+            .takes_value(true))
+        .arg(Arg::with_name("list-rules")
+            .long("list-rules")
+            .help( "Print every rule along with how seriously it's taken, then exit." ))
+        .arg(Arg::with_name("categories")
+            .long("categories")
+            .value_name("FILE")
+            .help( "A categories file that changes what kind of asset each extension is, which decides what's shipped and how it's reported.  Each line is an extension followed by map, reslist, script, material, model, sound, source, executable, or other." )
+            .takes_value(true))
+        .arg(Arg::with_name("schema")
+            .long("schema")
+            .value_name("FILE")
+            .help( "A map script schema that changes which parameters map scripts are checked for and the values they can have, such as one made for a newer GE:S version.  Each line is a GE:S version, a parameter, integer or weights, and its smallest and largest value, with - for no limit." )
+            .takes_value(true))
+        .arg(Arg::with_name("stock-content")
+            .long("stock-content")
+            .value_name("FILE")
+            .help( "A stock content file that adds to or updates the built in hashes of stock GE:S content, such as one made for a newer GE:S patch.  Each line is a SHA-256 hash followed by a path relative to the gesource directory, the same as sha256sum prints." )
+            .takes_value(true))
+        .arg(Arg::with_name("offline")
+            .long("offline")
+            .help( "Check references to stock content against the stock content database built into the program instead of a GE:S install, even if one is found.  This happens anyway when there's no GE:S install." ))
+        .arg(Arg::with_name("ges-version")
+            .long("ges-version")
+            .value_name("VERSION")
+            .help( "The GE:S version the release is for, like 5.0.  Scripts are checked against what that version expects.  If none is supplied the version of the GE:S install is used." )
+            .takes_value(true))
+        .arg(Arg::with_name("sarif")
+            .long("sarif")
+            .value_name("FILE")
+            .help( "Also write every problem found to the given file as a SARIF log, for GitHub code scanning and other tools." )
+            .takes_value(true))
+        .arg(Arg::with_name("smoke-test")
+            .long("smoke-test")
+            .value_name("SERVER")
+            .help( "After every check passes, run the given srcds or game executable on the map for a little while and report any missing resources or unreadable scripts it complains about.  The map has to be installed in the GE:S install." )
+            .takes_value(true))
+        .arg(Arg::with_name("smoke-test-time")
+            .long("smoke-test-time")
+            .value_name("SECONDS")
+            .help( "How many seconds the smoke test keeps the server running.  If none is supplied 30 is used." )
+            .takes_value(true))
+        .arg(Arg::with_name("discord-webhook")
+            .long("discord-webhook")
+            .value_name("URL")
+            .help( "A Discord webhook to post a summary of the run to: whether it passed, how many problems it found, and the first few failures.  Daemon mode posts one whenever a fullcheck finds new failures." )
+            .takes_value(true))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .value_name("FORMAT")
+            .help( "Either text, or github to also print every problem as a GitHub Actions annotation.  Defaults to text." )
+            .takes_value(true))
+        .arg(Arg::with_name("fix")
+            .long("fix")
+            .help( "Automatically fix problems that only have one sensible fix, such as duplicate music entries or inconsistent reslist paths, instead of failing on them." )
             .takes_value(false))
         .arg(Arg::with_name("verbose")
             .short("v")
             .long("verbose")
             .help( "Should the program display output to inform the user of what it's doing?" )
             .takes_value(false))
+        .arg(Arg::with_name("trace")
+            .long("trace")
+            .help( "Print why every file in the release was included in or left out of the reslist and compression.  Implies --verbose." )
+            .takes_value(false))
         .arg(Arg::with_name("noexitprompt")
             .short("e")
             .long("noexitprompt")
             .help( "Don't wait for user input to close the program after it finishes, do so immediately." )
             .takes_value(false))
+        .arg(Arg::with_name("pause-timeout")
+            .long("pause-timeout")
+            .value_name("SECONDS")
+            .help( "Close the program anyway if nobody answers the exit prompt within this many seconds.  0 waits forever, which is the default." )
+            .takes_value(true))
+        .subcommand(SubCommand::with_name("decompress")
+            .about( "Decompresses a directory of .bz2 files, such as one pulled from a fast download server, back into a normal gesource file tree.  Files are verified against any manifests found in the directory." )
+            .arg(Arg::with_name("directory")
+                .value_name("DIRECTORY")
+                .help( "The compressed directory to decompress." )
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("out")
+                .short("o")
+                .long("out")
+                .value_name("DIRECTORY")
+                .help( "Where to place the decompressed files.  If none is supplied an adjacent gesource_decompressed/gesource directory is used." )
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("keygen")
+            .about( "Generates a key pair for signing releases.  The secret key is written to NAME.key and the public key to NAME.pub." )
+            .arg(Arg::with_name("name")
+                .value_name("NAME")
+                .help( "Where to write the key pair, without an extension." )
+                .required(true)
+                .index(1)))
+        .subcommand(SubCommand::with_name("verify-release")
+            .about( "Checks that every manifest in a release directory was signed with the given public key, and that every file matches its manifest.  Works on both compressed and uncompressed releases." )
+            .arg(Arg::with_name("directory")
+                .value_name("DIRECTORY")
+                .help( "The release directory to verify." )
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("key")
+                .short("k")
+                .long("key")
+                .value_name("FILE")
+                .help( "The public key the release should have been signed with." )
+                .required(true)
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("gen-manifest")
+            .about( "Writes the hash of every file in a clean GE:S install to a stock content file, which --stock-content and the built in stock content database use.  Run it on each new GE:S patch." )
+            .arg(Arg::with_name("directory")
+                .value_name("DIRECTORY")
+                .help( "The gesource directory of a clean GE:S install." )
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("out")
+                .short("o")
+                .long("out")
+                .value_name("FILE")
+                .help( "Where to write the stock content file.  If none is supplied stock_content.txt in the current directory is used." )
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("compare-installs")
+            .about( "Lists the maps, map and music scripts, and reslisted map content that differ between two GE:S installs, such as a test server and the live one it should match." )
+            .arg(Arg::with_name("first")
+                .value_name("DIRECTORY")
+                .help( "The gesource directory of the first GE:S install." )
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("second")
+                .value_name("DIRECTORY")
+                .help( "The gesource directory of the second GE:S install." )
+                .required(true)
+                .index(2)))
+        .subcommand(SubCommand::with_name("mirror")
+            .about( "Copies the maps, map and music scripts, and reslisted map content that one GE:S install is missing or has a different version of from another.  Nothing is ever removed from the destination." )
+            .arg(Arg::with_name("source")
+                .value_name("SOURCE")
+                .help( "The gesource directory of the GE:S install to copy from." )
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("destination")
+                .value_name("DESTINATION")
+                .help( "The gesource directory of the GE:S install to copy to." )
+                .required(true)
+                .index(2))
+            .arg(Arg::with_name("dry-run")
+                .short("n")
+                .long("dry-run")
+                .help( "List what would be copied without copying anything." )))
+        .subcommand(SubCommand::with_name("analyze-log")
+            .about( "Lists the missing map scripts, sounds that failed to load, and failed downloads in a GE:S console log, along with the reslist and music script entries in the GE:S install that need fixing for each." )
+            .arg(Arg::with_name("log")
+                .value_name("FILE")
+                .help( "The console log to analyze, such as the console.log written with -condebug." )
+                .required(true)
+                .index(1)))
+        .subcommand(SubCommand::with_name("preview-weight")
+            .about( "Prints how likely the map is to be picked at each player count, given the --weight, --minplayers, and --maxplayers it would be released with or the settings of an existing map script." )
+            .arg(Arg::with_name("script")
+                .value_name("FILE")
+                .help( "A map script to read BaseWeight, MinPlayers, and MaxPlayers from instead." )
+                .index(1))
+            .arg(Arg::with_name("csv")
+                .long("csv")
+                .help( "Print the curve as CSV with a players and weight column, for graphing elsewhere." )))
+        .subcommand(SubCommand::with_name("copy-music")
+            .about( "Gives the map in the root directory a copy of another map's music script, so the two share a soundtrack.  Music sections for areas the map doesn't have are left out, and the copy is checked against the GE:S install before it's written." )
+            .arg(Arg::with_name("from")
+                .long("from")
+                .value_name("MAP")
+                .help( "The map to copy the music script of, such as ge_runway.  Its music script is looked for in the root directory first, then the GE:S directory." )
+                .required(true)
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("gen-fixtures")
+            .about( "Developer command that writes a valid map script fixture for each way of writing a generated script and an invalid one for each map script rule, checking each against the map script checker.  Replaces any fixtures it generated before." )
+            .arg(Arg::with_name("directory")
+                .value_name("DIRECTORY")
+                .help( "The tests directory to write the fixtures to.  If none is supplied resources/tests is used." )
+                .index(1)))
+        .subcommand(SubCommand::with_name("bench-fixtures")
+            .about( "Developer command that writes a synthetic release with thousands of content files and a reslist listing them, for the benchmarks to walk, check, and compress.  The same file count always makes the same release." )
+            .arg(Arg::with_name("directory")
+                .value_name("DIRECTORY")
+                .help( "The gesource directory to write the release into." )
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("files")
+                .long("files")
+                .value_name("COUNT")
+                .help( "How many content files the release has.  Defaults to 20000, about the size of a full GE:S install." )
+                .takes_value(true)))
         .get_matches();
 
 
     // Fullcheck mode triggers different program behavior and makes the root directory the same as the GE:S directory.
     // If such a mode is enabled, make sure this change is reflected.
-    let fullcheck_arg = matches.is_present("fullcheck");
+    let fullcheck_arg = is_flag_present( &matches, "fullcheck" );
+
+    let daemon_arg = is_flag_present( &matches, "daemon" );
+
+    let daemon_interval_arg = match get_argument_value( &matches, "interval" ).map(|x| daemon::parse_interval( &x ))
+    {
+        Some(Some(x)) => x, // User specified a valid interval
+        Some(None) => { shared::print_warning( "Invalid value given for interval!  Assuming 24h." ); daemon::DEFAULT_INTERVAL },
+        None => daemon::DEFAULT_INTERVAL,
+    };
+
+    let report_dir_arg = get_argument_value( &matches, "report-dir" ).map(PathBuf::from).unwrap_or( PathBuf::from("fullcheck_reports") );
+
+    let notify_hook_arg = get_argument_value( &matches, "notify-hook" );
 
     // Gets the ges directory if supplied, otherwise assumes it to be in one of the default locations.
-    let gesdir_arg = match matches.value_of("gesdir")
+    let gesdir_arg = match get_argument_value( &matches, "gesdir" )
     {
         Some(x) => PathBuf::from(x), // User specified a ges directory
         None    =>                   // If not let's search for one
@@ -179,53 +790,300 @@ fn parse_arguments() -> Arguments
     else
     {
         // Gets the root directory if supplied, otherwise assumes it to be the directory the program is running in.
-        rootdir_arg = match matches.value_of("rootdir")
+        rootdir_arg = match get_argument_value( &matches, "rootdir" )
         {
             Some(x) => PathBuf::from(x), // User specified a root directory
             None    => env::current_dir().unwrap(), // But if not we'll always have a valid current directory.
         };
     }
 
-    let baseweight_arg = match matches.value_of("weight").unwrap_or("500").parse::<i32>()
+    // Every argument read after this point changes what the release is made with, so they have to be able to come from
+    // the lockfile of the last one.  Subcommands don't make a release at all.
+    let ignore_lock_arg = is_flag_present( &matches, "ignore-lock" );
+
+    let verify_deterministic_arg = is_flag_present( &matches, "verify-deterministic" );
+    let from_compile_arg = get_argument_value( &matches, "from-compile" ).map(PathBuf::from);
+
+    if !fullcheck_arg && !ignore_lock_arg && matches.subcommand_name() == None
+    {
+        match release_lock::load_release_lock( &rootdir_arg )?
+        {
+            Some(x) => println!( "Starting from the settings of the last release in {}.  Run with --ignore-lock to use the defaults instead.", x.display() ),
+            None => {},
+        }
+    }
+
+    let baseweight_arg = match get_argument_value( &matches, "weight" ).unwrap_or(String::from("500")).parse::<i32>()
     {
         Ok(x) => x, // User specified a valid int
-        Err(_) => { println!("[Warning] Invalid value given for baseweight!  Assuming 500."); 500}, // But if not we'll just assume a midline value   
+        Err(_) => { shared::print_warning( "Invalid value given for baseweight!  Assuming 500." ); 500}, // But if not we'll just assume a midline value   
     };
 
-    let minplayers_arg = match matches.value_of("minplayers").unwrap_or("0").parse::<i32>()
+    let minplayers_arg = match get_argument_value( &matches, "minplayers" ).unwrap_or(String::from("0")).parse::<i32>()
     {
         Ok(x) => x, // User specified a valid int
-        Err(_) => { println!("[Warning] Invalid value given for minplayers!  Assuming 0."); 0}, // But if not we'll just assume a midline value   
+        Err(_) => { shared::print_warning( "Invalid value given for minplayers!  Assuming 0." ); 0}, // But if not we'll just assume a midline value   
     };
 
-    let maxplayers_arg = match matches.value_of("maxplayers").unwrap_or("16").parse::<i32>()
+    let maxplayers_arg = match get_argument_value( &matches, "maxplayers" ).unwrap_or(String::from("16")).parse::<i32>()
     {
         Ok(x) => x, // User specified a valid int
-        Err(_) => { println!("[Warning] Invalid value given for maxplayers!  Assuming 16."); 16}, // But if not we'll just assume a midline value   
+        Err(_) => { shared::print_warning( "Invalid value given for maxplayers!  Assuming 16." ); 16}, // But if not we'll just assume a midline value   
     };
 
-    let resintensity_arg = match matches.value_of("resintensity").unwrap_or("7").parse::<i32>()
+    let resintensity_arg = match get_argument_value( &matches, "resintensity" ).unwrap_or(String::from("7")).parse::<i32>()
     {
         Ok(x) => x, // User specified a valid int
-        Err(_) => { println!("[Warning] Invalid value given for resintensity!  Assuming 7."); 7}, // But if not we'll just assume a midline value   
+        Err(_) => { shared::print_warning( "Invalid value given for resintensity!  Assuming 7." ); 7}, // But if not we'll just assume a midline value   
     };
 
-    let teamthresh_arg = match matches.value_of("teamthresh").unwrap_or("12").parse::<i32>()
+    let teamthresh_arg = match get_argument_value( &matches, "teamthresh" ).unwrap_or(String::from("12")).parse::<i32>()
     {
         Ok(x) => x, // User specified a valid int
-        Err(_) => { println!("[Warning] Invalid value given for teamthresh!  Assuming 12."); 12}, // But if not we'll just assume a midline value   
+        Err(_) => { shared::print_warning( "Invalid value given for teamthresh!  Assuming 12." ); 12}, // But if not we'll just assume a midline value   
     };
 
-    let verbose_arg = matches.is_present("verbose");
+    let map_group_arg = get_argument_value( &matches, "map-group" );
 
-    let noexitprompt_arg = matches.is_present("noexitprompt");
+    let only_gamemodes_arg = match get_argument_value( &matches, "only-gamemodes" )
+    {
+        Some(x) => x.split(',').map(|x| String::from( x.trim() )).filter(|x| !x.is_empty()).collect(),
+        None => Vec::new(),
+    };
+
+    let playtest_expires_arg = match get_argument_value( &matches, "playtest-expires" )
+    {
+        Some(ref x) if !playtest::is_valid_date( x ) => { shared::print_warning( "Invalid value given for playtest-expires!  Dates look like 2018-06-01.  Ignoring it." ); None },
+        x => x,
+    };
+
+    let playtest_notes_arg = get_argument_value( &matches, "playtest-notes" );
+
+    let preset_arg = get_argument_value( &matches, "preset" );
+
+    let presets_file_arg = get_argument_value( &matches, "presets" ).map(PathBuf::from);
+
+    let given_values_arg = ["weight", "minplayers", "maxplayers", "resintensity", "teamthresh"].iter()
+                                                                                              .filter(|x| get_argument_value( &matches, x ) != None)
+                                                                                              .cloned()
+                                                                                              .collect();
+
+    let trace_arg = is_flag_present( &matches, "trace" );
+
+    // trace implies verbose
+    let verbose_arg = is_flag_present( &matches, "verbose" ) || trace_arg;
+
+    let noexitprompt_arg = is_flag_present( &matches, "noexitprompt" );
+
+    let pause_timeout_arg = match get_argument_value( &matches, "pause-timeout" ).map(|x| x.parse::<u64>())
+    {
+        Some(Ok(x)) => x, // User specified a valid int
+        Some(Err(_)) => { shared::print_warning( "Invalid value given for pause-timeout!  Assuming 0." ); 0 },
+        None => 0,
+    };
 
-    let recompress_arg = matches.is_present("recompress");
+    let recompress_arg = is_flag_present( &matches, "recompress" );
 
     // recompress implies compress
-    let compress_arg = matches.is_present("compress") || recompress_arg;
+    let compress_arg = is_flag_present( &matches, "compress" ) || recompress_arg;
+
+    let compressed_out_arg = get_argument_value( &matches, "compressed-out" ).map(PathBuf::from);
+    let server_package_arg = get_argument_value( &matches, "server-package" ).map(PathBuf::from);
+
+    let sign_key_arg = get_argument_value( &matches, "sign-key" ).map(PathBuf::from);
+
+    let hash_algorithm_arg = match get_argument_value( &matches, "hash-algorithm" )
+    {
+        Some(x) => match HashAlgorithm::from_name( &x )
+        {
+            Some(y) => y,
+            None => { shared::print_warning( "Invalid value given for hash-algorithm!  Assuming blake3." ); HashAlgorithm::Blake3 },
+        },
+        None => HashAlgorithm::Blake3,
+    };
+
+    let delta_from_arg = get_argument_value( &matches, "delta-from" ).map(PathBuf::from);
+
+    let delta_out_arg = get_argument_value( &matches, "delta-out" ).map(PathBuf::from);
+
+    let torrent_arg = get_argument_value( &matches, "torrent" ).map(PathBuf::from);
+
+    let changelog_arg = get_argument_value( &matches, "changelog" ).map(PathBuf::from);
+
+    let author_arg = get_argument_value( &matches, "author" );
+
+    let server_config_arg = is_flag_present( &matches, "server-config" );
+
+    let dedupe_arg = is_flag_present( &matches, "dedupe" );
+
+    let trackers_arg = match get_argument_value( &matches, "trackers" )
+    {
+        Some(x) => x.split(',').map(|x| String::from( x.trim() )).filter(|x| !x.is_empty()).collect(),
+        None => Vec::new(),
+    };
+
+    let web_seeds_arg = match get_argument_value( &matches, "web-seeds" )
+    {
+        Some(x) => x.split(',').map(|x| String::from( x.trim() )).filter(|x| !x.is_empty()).collect(),
+        None => Vec::new(),
+    };
+
+    let include_sources_arg = is_flag_present( &matches, "include-sources" );
+
+    let reslist_dirs_arg = is_flag_present( &matches, "reslist-dirs" );
+
+    let legacy_format_arg = is_flag_present( &matches, "legacy-format" );
+
+    let stdin_arg = matches.is_present("stdin");
+
+    let check_update_arg = matches.is_present("check-update");
+
+    let auto_update_check_arg = is_flag_present( &matches, "auto-update-check" );
+
+    let crash_report_arg = is_flag_present( &matches, "crash-report" );
+
+    let music_areas_arg = match get_argument_value( &matches, "music-areas" )
+    {
+        Some(x) => x.split(',').map(|x| x.trim().to_lowercase()).filter(|x| !x.is_empty()).collect(),
+        None => Vec::new(),
+    };
+
+    let fix_arg = is_flag_present( &matches, "fix" );
+
+    let min_tracks_arg = match get_argument_value( &matches, "min-tracks" ).unwrap_or(String::from("4")).parse::<usize>()
+    {
+        Ok(x) => x, // User specified a valid int
+        Err(_) => { shared::print_warning( "Invalid value given for min-tracks!  Assuming 4." ); 4},
+    };
+
+    let sample_music_arg = match get_argument_value( &matches, "sample-music" ).unwrap_or(String::from("0")).parse::<usize>()
+    {
+        Ok(x) => x,
+        Err(_) => { shared::print_warning( "Invalid value given for sample-music!  The default music will be used." ); 0},
+    };
+
+    let music_seed_arg = match get_argument_value( &matches, "music-seed" )
+    {
+        Some(x) => match x.parse::<u64>()
+        {
+            Ok(y) => Some(y),
+            Err(_) => { shared::print_warning( "Invalid value given for music-seed!  A random seed will be used." ); None },
+        },
+        None => None,
+    };
+
+    // Picked now rather than when the music is sampled, so the lockfile can record it.
+    let music_seed_arg = match music_seed_arg
+    {
+        None if sample_music_arg > 0 => Some(music_script_builder::get_random_seed()),
+        x => x,
+    };
+
+    let match_length_arg = match get_argument_value( &matches, "match-length" ).unwrap_or(String::from("15")).parse::<u32>()
+    {
+        Ok(x) => x, // User specified a valid int
+        Err(_) => { shared::print_warning( "Invalid value given for match-length!  Assuming 15." ); 15},
+    };
+
+    let walk_timeout_arg = match get_argument_value( &matches, "walk-timeout" ).map(|x| x.parse::<usize>())
+    {
+        Some(Ok(x)) => x, // User specified a valid int
+        Some(Err(_)) => { shared::print_warning( &format!( "Invalid value given for walk-timeout!  Assuming {}.", watchdog::DEFAULT_WALK_TIMEOUT ) ); watchdog::DEFAULT_WALK_TIMEOUT },
+        None => watchdog::DEFAULT_WALK_TIMEOUT,
+    };
+
+    let hydrate_arg = is_flag_present( &matches, "hydrate" );
+
+    let check_only_arg = is_flag_present( &matches, "check-only" );
+
+    let user_scope_arg = is_flag_present( &matches, "user-scope" );
+
+    let rules_file_arg = get_argument_value( &matches, "rules" ).map(PathBuf::from);
+
+    let list_rules_arg = matches.is_present("list-rules");
+
+    let categories_file_arg = get_argument_value( &matches, "categories" ).map(PathBuf::from);
+
+    let schema_file_arg = get_argument_value( &matches, "schema" ).map(PathBuf::from);
+
+    let stock_content_file_arg = get_argument_value( &matches, "stock-content" ).map(PathBuf::from);
+
+    let offline_arg = is_flag_present( &matches, "offline" );
+
+    let ges_version_arg = get_argument_value( &matches, "ges-version" );
+
+    let sarif_arg = get_argument_value( &matches, "sarif" ).map(PathBuf::from);
+
+    let discord_webhook_arg = get_argument_value( &matches, "discord-webhook" );
+
+    let smoke_test_arg = get_argument_value( &matches, "smoke-test" ).map(PathBuf::from);
+
+    let smoke_test_time_arg = match get_argument_value( &matches, "smoke-test-time" ).map(|x| x.parse::<u64>())
+    {
+        Some(Ok(x)) => x, // User specified a valid int
+        Some(Err(_)) => { shared::print_warning( &format!( "Invalid value given for smoke-test-time!  Assuming {}.", smoke_test::DEFAULT_SMOKE_TEST_TIME ) ); smoke_test::DEFAULT_SMOKE_TEST_TIME },
+        None => smoke_test::DEFAULT_SMOKE_TEST_TIME,
+    };
+
+    let output_arg = match get_argument_value( &matches, "output" ).unwrap_or(String::from("text")).to_lowercase().as_str()
+    {
+        "text" => OutputFormat::Text,
+        "github" => OutputFormat::Github,
+        _ => { shared::print_warning( "Invalid value given for output!  Assuming text." ); OutputFormat::Text },
+    };
+
+    let (decompress_arg, decompress_out_arg) = match matches.subcommand_matches("decompress")
+    {
+        Some(x) => (x.value_of("directory").map(PathBuf::from), x.value_of("out").map(PathBuf::from)),
+        None => (None, None),
+    };
+
+    let keygen_arg = matches.subcommand_matches("keygen").and_then(|x| x.value_of("name")).map(PathBuf::from);
+
+    let (verify_release_arg, verify_key_arg) = match matches.subcommand_matches("verify-release")
+    {
+        Some(x) => (x.value_of("directory").map(PathBuf::from), x.value_of("key").map(PathBuf::from)),
+        None => (None, None),
+    };
+
+    let (gen_manifest_arg, gen_manifest_out_arg) = match matches.subcommand_matches("gen-manifest")
+    {
+        Some(x) => (x.value_of("directory").map(PathBuf::from), x.value_of("out").map(PathBuf::from)),
+        None => (None, None),
+    };
+
+    let compare_installs_arg = matches.subcommand_matches("compare-installs")
+                                      .map(|x| (PathBuf::from( x.value_of("first").unwrap() ), PathBuf::from( x.value_of("second").unwrap() )));
+
+    let (mirror_arg, mirror_dry_run_arg) = match matches.subcommand_matches("mirror")
+    {
+        Some(x) => (Some((PathBuf::from( x.value_of("source").unwrap() ), PathBuf::from( x.value_of("destination").unwrap() ))), x.is_present("dry-run")),
+        None => (None, false),
+    };
+
+    let analyze_log_arg = matches.subcommand_matches("analyze-log").map(|x| PathBuf::from( x.value_of("log").unwrap() ));
+
+    let gen_fixtures_arg = matches.subcommand_matches("gen-fixtures")
+                                  .map(|x| PathBuf::from( x.value_of("directory").unwrap_or("resources/tests") ));
+
+    let bench_fixtures_arg = matches.subcommand_matches("bench-fixtures").map(|x| PathBuf::from( x.value_of("directory").unwrap() ));
+
+    let bench_file_count_arg = match matches.subcommand_matches("bench-fixtures").and_then(|x| x.value_of("files")).map(|x| x.parse::<usize>())
+    {
+        Some(Ok(x)) => x,
+        Some(Err(_)) => { shared::print_warning( &format!( "Invalid value given for files!  Assuming {}.", bench_fixtures::DEFAULT_FILE_COUNT ) ); bench_fixtures::DEFAULT_FILE_COUNT },
+        None => bench_fixtures::DEFAULT_FILE_COUNT,
+    };
+
+    let copy_music_from_arg = matches.subcommand_matches("copy-music").map(|x| String::from( x.value_of("from").unwrap() ));
 
-    Arguments
+    let (preview_weight_arg, preview_weight_script_arg, preview_weight_csv_arg) = match matches.subcommand_matches("preview-weight")
+    {
+        Some(x) => (true, x.value_of("script").map(PathBuf::from), x.is_present("csv")),
+        None => (false, None, false),
+    };
+
+    Ok(Arguments
     {
         rootdir: rootdir_arg,
         gesdir: gesdir_arg,
@@ -234,18 +1092,164 @@ fn parse_arguments() -> Arguments
         maxplayers: maxplayers_arg,
         resintensity: resintensity_arg,
         teamthresh: teamthresh_arg,
+        map_group: map_group_arg,
+        only_gamemodes: only_gamemodes_arg,
+        playtest_expires: playtest_expires_arg,
+        playtest_notes: playtest_notes_arg,
+        preset: preset_arg,
+        presets_file: presets_file_arg,
+        given_values: given_values_arg,
+        section_weights: Vec::new(),
         compress: compress_arg,
         recompress: recompress_arg,
         verbose: verbose_arg,
+        trace: trace_arg,
         fullcheck: fullcheck_arg,
+        daemon: daemon_arg,
+        daemon_interval: daemon_interval_arg,
+        report_dir: report_dir_arg,
+        notify_hook: notify_hook_arg,
         noexitprompt: noexitprompt_arg,
+        pause_timeout: pause_timeout_arg,
+        compressed_out: compressed_out_arg,
+        server_package: server_package_arg,
+        include_sources: include_sources_arg,
+        reslist_dirs: reslist_dirs_arg,
+        legacy_format: legacy_format_arg,
+        stdin: stdin_arg,
+        decompress: decompress_arg,
+        decompress_out: decompress_out_arg,
+        keygen: keygen_arg,
+        verify_release: verify_release_arg,
+        verify_key: verify_key_arg,
+        gen_manifest: gen_manifest_arg,
+        gen_manifest_out: gen_manifest_out_arg.unwrap_or( PathBuf::from("stock_content.txt") ),
+        compare_installs: compare_installs_arg,
+        mirror: mirror_arg,
+        mirror_dry_run: mirror_dry_run_arg,
+        analyze_log: analyze_log_arg,
+        preview_weight: preview_weight_arg,
+        preview_weight_script: preview_weight_script_arg,
+        preview_weight_csv: preview_weight_csv_arg,
+        gen_fixtures: gen_fixtures_arg,
+        copy_music_from: copy_music_from_arg,
+        bench_fixtures: bench_fixtures_arg,
+        bench_file_count: bench_file_count_arg,
+        sign_key: sign_key_arg,
+        hash_algorithm: hash_algorithm_arg,
+        delta_from: delta_from_arg,
+        delta_out: delta_out_arg,
+        torrent: torrent_arg,
+        changelog: changelog_arg,
+        author: author_arg,
+        ignore_lock: ignore_lock_arg,
+        verify_deterministic: verify_deterministic_arg,
+        from_compile: from_compile_arg,
+        server_config: server_config_arg,
+        dedupe: dedupe_arg,
+        trackers: trackers_arg,
+        web_seeds: web_seeds_arg,
+        walk_timeout: walk_timeout_arg,
+        hydrate: hydrate_arg,
+        check_only: check_only_arg,
+        user_scope: user_scope_arg,
+        check_update: check_update_arg,
+        auto_update_check: auto_update_check_arg,
+        crash_report: crash_report_arg,
+        music_areas: music_areas_arg,
+        fix: fix_arg,
+        min_tracks: min_tracks_arg,
+        sample_music: sample_music_arg,
+        music_seed: music_seed_arg,
+        match_length: match_length_arg,
+        rules_file: rules_file_arg,
+        rule_overrides: release_lock::get_locked_rules(),
+        list_rules: list_rules_arg,
+        categories_file: categories_file_arg,
+        schema_file: schema_file_arg,
+        stock_content_file: stock_content_file_arg,
+        offline: offline_arg,
+        ges_version: ges_version_arg,
+        sarif: sarif_arg,
+        discord_webhook: discord_webhook_arg,
+        smoke_test: smoke_test_arg,
+        smoke_test_time: smoke_test_time_arg,
+        output: output_arg,
+    })
+}
+
+/// Returns the name of the environment variable that can stand in for the given argument.
+pub fn get_environment_variable_name( argument_name: &str ) -> String
+{
+    let mut variable_name = String::from("GESMRA_");
+    variable_name.push_str( &argument_name.to_uppercase().replace("-", "_") );
+
+    variable_name
+}
+
+/// Returns the value of the given argument from the commandline, falling back to its environment variable if it
+/// wasn't supplied there, then to the lockfile of the last release, and then to the user config.  Returns None if none
+/// of them have it, in which case the default should be used.
+fn get_argument_value( matches: &ArgMatches, argument_name: &str ) -> Option<String>
+{
+    // Always looked up, so the user config can tell which of its settings were never used.
+    let user_setting = user_config::get_user_setting( argument_name );
+
+    match matches.value_of(argument_name)
+    {
+        Some(x) => Some(String::from(x)),
+        None => env::var( get_environment_variable_name(argument_name) ).ok().or_else(|| release_lock::get_locked_argument( argument_name )).or(user_setting),
     }
 }
 
+/// Checks to see if the given flag was set on the commandline, through its environment variable, in the lockfile of the
+/// last release, or in the user config.
+fn is_flag_present( matches: &ArgMatches, argument_name: &str ) -> bool
+{
+    let user_setting = user_config::get_user_setting( argument_name );
+
+    if matches.is_present(argument_name)
+    {
+        return true;
+    }
+
+    match env::var( get_environment_variable_name(argument_name) ).ok().or_else(|| release_lock::get_locked_argument( argument_name )).or(user_setting)
+    {
+        Some(x) => is_environment_flag_value_set( &x ),
+        None => false,
+    }
+}
+
+/// Checks to see if the value of a flag's environment variable means the flag should be set.
+/// Wrapper batch files tend to use all sorts of values here, so anything but an obvious "off" counts.
+fn is_environment_flag_value_set( value: &str ) -> bool
+{
+    let off_values = ["", "0", "false", "no", "off"];
+
+    !off_values.contains( &value.trim().to_lowercase().as_str() )
+}
+
 /// Infer the map name from the arguments supplied
 fn get_map_name( args: &Arguments ) -> String
 {
-    let mut mapsdir_path = args.rootdir.clone();
+    // The release will be built from the compile output, so that's where the map is.
+    match args.from_compile
+    {
+        Some(ref x) => match compile_import::find_compiled_map( x ).ok().and_then(|y| y.file_stem().and_then(|z| z.to_str()).map(String::from))
+        {
+            Some(y) => return y,
+            None => return String::from("invalid"),
+        },
+        None => {},
+    }
+
+    get_release_map_name( &args.rootdir )
+}
+
+/// Returns the name of the map in the given release directory, or "invalid" if there isn't one.
+fn get_release_map_name( rootdir: &PathBuf ) -> String
+{
+    let mut mapsdir_path = rootdir.clone();
 
     mapsdir_path.push("maps");
 
@@ -277,21 +1281,188 @@ fn get_map_name( args: &Arguments ) -> String
 }
 
 /// Ensure all the supplied arugments are valid and make sense.
-fn check_arguments( args: &Arguments, map_name: &str ) -> Result<(), Error>
+fn check_arguments( args: &Arguments, map_name: &str ) -> Result<(), GesError>
 {
+    if args.check_only
+    {
+        check_read_only_arguments( args )?;
+    }
+
+    // Decompression doesn't touch the root directory or a GE:S install, all it needs is something to decompress.
+    match args.decompress
+    {
+        Some(ref x) => 
+        {
+            if !x.is_dir()
+            {
+                return Err(GesError::Config( String::from("Supplied compressed directory isn't a valid directory!  Aborting!") ));
+            }
+
+            return Ok(());
+        },
+        None => {},
+    }
+
+    // Generating a stock content file only needs the install, which has to be a real one or the file would be useless.
+    match args.gen_manifest
+    {
+        Some(ref x) =>
+        {
+            if !is_directory_root_ges_install( x )
+            {
+                return Err(GesError::Config( String::from("Supplied directory is not the gesource directory of a valid GE:S installation!  Aborting!") ));
+            }
+
+            return Ok(());
+        },
+        None => {},
+    }
+
+    // Comparing or mirroring installs only involves the two of them, and anything that isn't an install would just
+    // have every file missing.
+    match args.compare_installs.as_ref().or( args.mirror.as_ref() )
+    {
+        Some(&(ref x, ref y)) =>
+        {
+            if x == y
+            {
+                return Err(GesError::Config( String::from("Supplied the same GE:S installation twice!  Aborting!") ));
+            }
+
+            for install_dir in &[x, y]
+            {
+                if !is_directory_root_ges_install( install_dir )
+                {
+                    let mut error_text = String::new();
+                    error_text.push_str( &install_dir.display().to_string() );
+                    error_text.push_str(" is not the gesource directory of a valid GE:S installation!  Aborting!");
+
+                    return Err(GesError::Config( error_text ));
+                }
+            }
+
+            if args.mirror != None && !args.mirror_dry_run
+            {
+                check_install_write_access( args, y )?;
+            }
+
+            return Ok(());
+        },
+        None => {},
+    }
+
+    // Analyzing a log only needs the log, the GE:S install just helps trace its problems back to scripts.
+    match args.analyze_log
+    {
+        Some(ref x) =>
+        {
+            if !x.is_file()
+            {
+                return Err(GesError::Config( String::from("Supplied console log isn't a valid file!  Aborting!") ));
+            }
+
+            return Ok(());
+        },
+        None => {},
+    }
+
+    // Fixtures go in the test tree, which has to already exist so a typo doesn't start a new one somewhere else.
+    match args.gen_fixtures
+    {
+        Some(ref x) if !x.is_dir() => return Err(GesError::Config( String::from("Supplied tests directory isn't a valid directory!  Aborting!") )),
+        Some(_) => return Ok(()),
+        None => {},
+    }
+
+    // The synthetic release makes its own directories, so only a file in the way stops it.
+    match args.bench_fixtures
+    {
+        Some(ref x) if x.is_file() => return Err(GesError::Config( String::from("Supplied benchmark directory is a file!  Aborting!") )),
+        Some(_) => return Ok(()),
+        None => {},
+    }
+
+    // Previewing the selection weight only involves the map script settings.
+    if args.preview_weight
+    {
+        match args.preview_weight_script
+        {
+            Some(ref x) if !x.is_file() => return Err(GesError::Config( String::from("Supplied map script isn't a valid file!  Aborting!") )),
+            _ => return Ok(()),
+        }
+    }
+
+    // Neither do the signing commands, key generation checks its own files since it needs to refuse to overwrite them.
+    if args.keygen != None
+    {
+        return Ok(());
+    }
+
+    match (&args.verify_release, &args.verify_key)
+    {
+        (&Some(ref x), &Some(ref y)) =>
+        {
+            if !x.is_dir()
+            {
+                return Err(GesError::Config( String::from("Supplied release directory isn't a valid directory!  Aborting!") ));
+            }
+
+            release_signing::read_verifying_key( y ).map_err(|e| e.in_file( y ))?;
+
+            return Ok(());
+        },
+        _ => {},
+    }
+
+    // The compile output is copied into the root directory before anything else runs, so it has to have a map to copy,
+    // and the root directory doesn't have to exist yet.
+    match args.from_compile
+    {
+        Some(ref x) =>
+        {
+            if args.fullcheck
+            {
+                return Err(GesError::Config( String::from("--from-compile builds a release out of a compile output, so it can't be used in fullcheck mode!") ));
+            }
+
+            if !x.is_dir()
+            {
+                return Err(GesError::Config( String::from("Supplied compile output directory isn't a valid directory!  Aborting!") ));
+            }
+
+            compile_import::find_compiled_map( x ).map_err(|e| e.in_file( x ))?;
+
+            // A release only ever holds one map, so a different one already there would end up shipped alongside it.
+            let existing_map_name = get_release_map_name( &args.rootdir );
+
+            if existing_map_name != "invalid" && existing_map_name != map_name
+            {
+                let mut error_text = String::new();
+                error_text.push_str("The root directory already holds ");
+                error_text.push_str(&existing_map_name);
+                error_text.push_str(", but the compile output is for ");
+                error_text.push_str(map_name);
+                error_text.push_str("!  Use a root directory of its own for each map.");
+
+                return Err(GesError::Config( error_text ));
+            }
+        },
+        None => {},
+    }
+
     // If we're in fullcheck mode we're not actually releasing a map and don't care about the root directory
     if !args.fullcheck
     {
-        // Check to make sure the root directory exists and we have read/write access to it.
-        if !args.rootdir.is_dir()
+        // Check to make sure the root directory exists.  Only the sections that write need write access, and they check for it in preflight.
+        if !args.rootdir.is_dir() && (args.from_compile == None || args.rootdir.exists())
         {
             if args.rootdir.is_file()
             {
-                return Err(Error::new(ErrorKind::InvalidInput, "Supplied root directory is a file, not a directory!  Aborting!" ));
+                return Err(GesError::Config( String::from("Supplied root directory is a file, not a directory!  Aborting!") ));
             }
             else
             {
-                return Err(Error::new(ErrorKind::InvalidInput, "Supplied root directory isn't a valid directory with write access!  Aborting!" ));
+                return Err(GesError::Config( String::from("Supplied root directory isn't a valid directory!  Aborting!") ));
             }
         }
 
@@ -303,92 +1474,223 @@ fn check_arguments( args: &Arguments, map_name: &str ) -> Result<(), Error>
             {
                 if x != "gesource"
                 {
-                    return Err(Error::new(ErrorKind::InvalidInput, "Root directory must end in \"gesource\"!" ));
+                    return Err(GesError::Config( String::from("Root directory must end in \"gesource\"!") ));
                 }
             },
             None => 
             { 
-                return Err(Error::new(ErrorKind::InvalidInput, "Root directory must have an ending!" ));
+                return Err(GesError::Config( String::from("Root directory must have an ending!") ));
             },
         }
         
         if is_directory_root_ges_install( &args.rootdir )
         {
-            return Err(Error::new(ErrorKind::InvalidInput, "Supplied root directory is a full GE:S install!  \
+            return Err(GesError::Config( String::from("Supplied root directory is a full GE:S install!  \
                                                             In normal mode, this program is meant to be run on map releases only. \
                                                             Run with the -f flag for fullcheck mode if you want to inspect all scripts \
                                                             in a given GE:S install.  Be sure to specify the fullcheck target directory \
-                                                            with the -g flag for best results." ));
+                                                            with the -g flag for best results.") ));
+        }
+    }
+    else // Is fullcheck mode.
+    {
+        if args.compress
+        {
+            shared::print_warning( "Cannot compress directory in fullcheck mode but compress flag is set!\nThe compression flag will be ignored." );
         }
 
-        // Make sure maps directory exists.
-        let mut mapsdir = args.rootdir.clone();
-        mapsdir.push("maps");
-
-        if !mapsdir.is_dir()
+        if args.fix
         {
-            return Err(Error::new(ErrorKind::InvalidInput, "Root directory has no maps directory!" ));
+            check_install_write_access( args, &args.gesdir )?;
         }
 
-        // Check that map file actually exists and can be read.  
-        let mut map_path = mapsdir.clone();
-        map_path.push( map_name );
-        map_path.set_extension("bsp");
+        if args.verify_deterministic
+        {
+            shared::print_warning( "Cannot verify that a release is deterministic in fullcheck mode, since nothing is generated!\nThe verify-deterministic flag will be ignored." );
+        }
 
-        if !map_path.is_file()
+        if args.server_package != None
         {
-            return Err(Error::new(ErrorKind::InvalidInput, "Failed to locate any readable .bsp files in maps directory!" ));
+            shared::print_warning( "Cannot build a server package in fullcheck mode, since there's no map being released!\nThe server package directory will be ignored." );
         }
+    }
 
-        // Check to see if there's a music directory
-        let mut musicdir = args.rootdir.clone();
-        musicdir.push("sound");
-        musicdir.push("music");
+    if args.user_scope && !args.fix && (args.mirror == None || args.mirror_dry_run)
+    {
+        shared::print_warning( "--user-scope was given but nothing will be written to a GE:S install!\nIt will be ignored." );
+    }
+
+    if args.music_seed != None && args.sample_music == 0
+    {
+        shared::print_warning( "A music seed was given but --sample-music isn't set!\nThe seed will be ignored." );
+    }
 
-        if !musicdir.is_dir()
+    if args.compressed_out != None
+    {
+        if !args.compress
         {
-            println!( "[Warning] Root directory {} has no music directory!  A default music file will be provided.", args.rootdir.display() );
+            shared::print_warning( "A compressed output directory was given but the compress flag isn't set!\nNo compression will take place." );
         }
     }
-    else // Is fullcheck mode.
+
+    match args.delta_from
     {
-        if args.compress
+        Some(ref x) =>
+        {
+            if !args.compress || args.fullcheck
+            {
+                shared::print_warning( "A previous manifest was given but nothing is being compressed!\nNo delta package will be built." );
+            }
+            else if x.is_file()
+            {
+                // Find out about a bad manifest now, rather than after everything has been compressed.
+                manifest::read_manifest( x ).map_err(|e| e.in_file( x ))?;
+            }
+        },
+        None => {},
+    }
+
+    if args.server_config && (!args.compress || args.fullcheck)
+    {
+        shared::print_warning( "The server config flag is set but nothing is being compressed!\nNo server config will be written." );
+    }
+
+    if args.dedupe && (!args.compress || args.fullcheck)
+    {
+        shared::print_warning( "The dedupe flag is set but nothing is being compressed!\nNo files will be linked." );
+    }
+
+    if args.torrent != None && (!args.compress || args.fullcheck)
+    {
+        shared::print_warning( "A torrent file was given but nothing is being compressed!\nNo torrent will be written." );
+    }
+    else if args.torrent == None && (!args.trackers.is_empty() || !args.web_seeds.is_empty())
+    {
+        shared::print_warning( "Trackers or web seeds were given but no torrent file was!\nThey will be ignored." );
+    }
+
+    if args.changelog != None && (!args.compress || args.fullcheck)
+    {
+        shared::print_warning( "A changelog file was given but nothing is being compressed!\nNo changelog will be written." );
+    }
+
+    // Torrent clients silently skip urls they can't make sense of, so catch typos here instead.
+    for url in args.trackers.iter().chain(args.web_seeds.iter())
+    {
+        if !url.contains("://")
         {
-            println!( "[Warning] Cannot compress directory in fullcheck mode but compress flag is set!\nThe compression flag will be ignored." );
+            let mut error_text = String::new();
+            error_text.push_str(url);
+            error_text.push_str(" isn't a valid url!  Trackers and web seeds need a scheme, such as udp:// or https://.");
+
+            return Err(GesError::Config( error_text ));
         }
     }
 
-    // Check to make sure the GE:S directory exists and we have read/write access to it.
+    match args.sign_key
+    {
+        Some(ref x) =>
+        {
+            if !args.compress || args.fullcheck
+            {
+                shared::print_warning( "A signing key was given but nothing is being compressed!\nNo manifest will be signed." );
+            }
+            else
+            {
+                // Find out about a bad key now, rather than after everything has been compressed.
+                release_signing::read_signing_key( x ).map_err(|e| e.in_file( x ))?;
+            }
+        },
+        None => {},
+    }
+
+    // Daemon mode is just fullcheck mode on a schedule.
+    if args.daemon && !args.fullcheck
+    {
+        return Err(GesError::Config( String::from("Daemon mode only runs fullcheck mode, so it needs --fullcheck too!") ));
+    }
+
+    // The smoke test loads the map from the GE:S install, so it needs a real one and a server to load it with.
+    match args.smoke_test
+    {
+        Some(ref x) =>
+        {
+            if !x.is_file()
+            {
+                return Err(GesError::Config( String::from("Supplied smoke test server isn't a file!  Aborting!") ));
+            }
+
+            if args.fullcheck || args.offline || !is_directory_root_ges_install( &args.gesdir )
+            {
+                return Err(GesError::Config( String::from("The smoke test needs a valid GE:S install to load the map from, and can't be used in fullcheck or offline mode!") ));
+            }
+        },
+        None => {},
+    }
+
+    // Music is copied to the map being released, not a whole install.
+    if args.copy_music_from != None && args.fullcheck
+    {
+        return Err(GesError::Config( String::from("copy-music gives the map in the root directory a music script, so it can't be used in fullcheck mode!") ));
+    }
+
+    // Releases are checked against the current rules no matter how their scripts were written.
+    if args.legacy_format && !args.fullcheck
+    {
+        return Err(GesError::Config( String::from("--legacy-format only applies to fullcheck mode, since a new release should meet the current rules!") ));
+    }
+
+    // Fullcheck mode checks a GE:S install, so there's nothing for it to do without one.
+    if args.offline && args.fullcheck
+    {
+        return Err(GesError::Config( String::from("Offline mode can't be used with fullcheck mode, which checks the GE:S install itself!") ));
+    }
+
+    // Check to make sure the GE:S directory exists.  Only --fix ever writes to it, so a read-only mount is fine otherwise.
     // Not having a valid GE:S directory only costs a few minor features so we'll still allow
     // program execution in spite of it, unless we're in fullcheck mode in which case the gesdir
     // is the entire point of running the program.
-    if !args.gesdir.is_dir()
+    if args.offline
+    {
+        if args.verbose
+        {
+            println!( "Offline mode is on, so stock content will be looked up in the built in stock content database instead of a GE:S install." );
+        }
+    }
+    else if !args.gesdir.is_dir()
     {
         if args.gesdir.is_file()
         {
             if args.fullcheck
             {
-                return Err(Error::new(ErrorKind::InvalidInput, "Supplied GE:S directory is a file, not a directory!  This is needed for fullcheck mode." ));
+                return Err(GesError::Config( String::from("Supplied GE:S directory is a file, not a directory!  This is needed for fullcheck mode.") ));
             }
             else
             {
-                println!( "[Warning] Supplied GE:S directory is a file, not a directory!" );
+                shared::print_warning( "Supplied GE:S directory is a file, not a directory!" );
             }
         }
         else
         {
             if args.fullcheck
             {
-                return Err(Error::new(ErrorKind::InvalidInput, "Supplied or Autodetected GE:S directory isn't a valid directory with write access!  This is needed for fullcheck mode." ));
+                return Err(GesError::Config( String::from("Supplied or Autodetected GE:S directory isn't a valid directory!  This is needed for fullcheck mode.") ));
             }
             else
             {
-                println!( "[Warning] Supplied or Autodetected GE:S directory isn't a valid directory with write access!" );
+                shared::print_warning( "Supplied or Autodetected GE:S directory isn't a valid directory!" );
             }
         }
 
         // Can only get here if we're not in fullcheck mode, so complete the warning messages.
-        println!( "Without a GoldenEye: Source installation to reference, some program features will be limited." );
+        if stock_content::has_stock_files()
+        {
+            println!( "Without a GoldenEye: Source installation to reference, stock content will be looked up in the built in stock content database instead." );
+        }
+        else
+        {
+            println!( "Without a GoldenEye: Source installation to reference, some program features will be limited." );
+        }
     }
     else
     {
@@ -400,33 +1702,115 @@ fn check_arguments( args: &Arguments, map_name: &str ) -> Result<(), Error>
 
         if !is_directory_root_ges_install( &args.gesdir )
         {
-            return Err(Error::new(ErrorKind::InvalidInput, "GE:S directory is not the root directory of a valid GE:S installation!" ));
+            return Err(GesError::Config( String::from("GE:S directory is not the root directory of a valid GE:S installation!") ));
         }
     }
 
     if args.minplayers > args.maxplayers
     {
-        println!( "[Warning] Minplayers is greater than maxplayers!  
+        shared::print_warning( "Minplayers is greater than maxplayers!  
                    Your map will never be picked for normal rotation." );
     }
     else if args.maxplayers < 0 || args.minplayers > 16
     {
-        println!( "[Warning] Your player range is outside the possible range of playercounts.  
+        shared::print_warning( "Your player range is outside the possible range of playercounts.  
                    Your map will never be picked for normal rotation." );
     }
 
     if args.resintensity <= 0
     {
-        println!( "[Warning] Your resintensity is an impossibly low value!  
+        shared::print_warning( "Your resintensity is an impossibly low value!  
                    While this will make servers switch to it more often, it will also cause client crashes." );
     }
     else if args.resintensity > 8
     {
-        println!( "[Warning] Your resintensity is incredibly high!  If your map really has > 400MB worth of 
+        shared::print_warning( "Your resintensity is incredibly high!  If your map really has > 400MB worth of 
                     assets it needs to load into RAM it would be best to cut some content instead of setting 
                     this value above 8." );        
     }
 
+    // A map restricted to gamemodes that all end up with a weight of 0 could never be played at all.
+    for &section in &["GamemodeWeights", "TeamGamemodeWeights"]
+    {
+        if !args.only_gamemodes.is_empty() && !args.only_gamemodes.iter().any(|x| map_script_builder::get_restricted_weight( args, section, x ) > 0)
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Every gamemode given with --only-gamemodes has a weight of 0 in ");
+            error_text.push_str(section);
+            error_text.push_str(", so the map could never be played!  Give at least one of them a weight above 0.");
+
+            return Err(GesError::Config( error_text ));
+        }
+    }
+
+    // The arguments themselves are fine, so let each section of the program check that what it needs is there.
+    preflight::run_preflight( args, map_name )
+}
+
+/// Makes sure the given GE:S install can be written to before anything tries to.  Installs under Program Files usually
+/// can't be without elevation, in which case --user-scope stages the changes somewhere that can be instead.
+fn check_install_write_access( args: &Arguments, install_dir: &PathBuf ) -> Result<(), GesError>
+{
+    if file_writer::is_read_only() || file_writer::can_create_in( install_dir )
+    {
+        return Ok(());
+    }
+
+    if args.user_scope
+    {
+        let staging_dir = file_writer::get_user_staging_directory( "gesource" );
+        file_writer::stage_directory( install_dir, &staging_dir );
+
+        println!( "{} needs elevation to write to, so everything meant for it will be written to {} instead.  \
+                   Copy it over as administrator once the run finishes to apply the changes.", install_dir.display(), staging_dir.display() );
+
+        return Ok(());
+    }
+
+    let mut error_text = String::new();
+    error_text.push_str("Don't have permission to write to ");
+    error_text.push_str( &install_dir.display().to_string() );
+    error_text.push_str("!  GE:S installs under Program Files can only be changed by an administrator, so either run the program ");
+    error_text.push_str("as administrator or pass --user-scope to write the changes to a staging directory you can copy over later.");
+
+    Err(GesError::Config( error_text ))
+}
+
+/// Makes sure nothing that writes files was asked for alongside check-only mode, which would otherwise only fail
+/// partway through the run.
+fn check_read_only_arguments( args: &Arguments ) -> Result<(), GesError>
+{
+    let mut writing_options: Vec<&str> = Vec::new();
+
+    if args.compress || args.recompress { writing_options.push("compression"); }
+    if args.decompress != None { writing_options.push("decompress"); }
+    if args.keygen != None { writing_options.push("keygen"); }
+    if args.gen_manifest != None { writing_options.push("gen-manifest"); }
+    if args.gen_fixtures != None { writing_options.push("gen-fixtures"); }
+    if args.bench_fixtures != None { writing_options.push("bench-fixtures"); }
+    if args.copy_music_from != None { writing_options.push("copy-music"); }
+    if args.mirror != None && !args.mirror_dry_run { writing_options.push("mirror without --dry-run"); }
+    if args.hydrate { writing_options.push("--hydrate"); }
+    if args.daemon { writing_options.push("--daemon"); }
+    if args.smoke_test != None { writing_options.push("--smoke-test"); }
+    if args.playtest_expires != None || args.playtest_notes != None { writing_options.push("--playtest-expires or --playtest-notes"); }
+    if args.fix { writing_options.push("--fix"); }
+    if args.verify_deterministic { writing_options.push("--verify-deterministic"); }
+    if args.from_compile != None { writing_options.push("--from-compile"); }
+    if args.server_package != None { writing_options.push("--server-package"); }
+    if args.sarif != None { writing_options.push("--sarif"); }
+    if args.crash_report { writing_options.push("--crash-report"); }
+
+    if !writing_options.is_empty()
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Check-only mode never writes to disk, but ");
+        error_text.push_str( &writing_options.join(", ") );
+        error_text.push_str(" would have to!  Aborting!");
+
+        return Err(GesError::Config( error_text ));
+    }
+
     Ok(())
 }
 
@@ -547,10 +1931,23 @@ mod tests
         assert!(check_arguments( &args, "some_other_map" ).is_err());
     }
 
+    #[test]
+    fn test_environment_variable_arguments()
+    {
+        assert_eq!( get_environment_variable_name("gesdir"), "GESMRA_GESDIR" );
+        assert_eq!( get_environment_variable_name("compressed-out"), "GESMRA_COMPRESSED_OUT" );
+
+        assert!( is_environment_flag_value_set("1") );
+        assert!( is_environment_flag_value_set("TRUE") );
+        assert!( !is_environment_flag_value_set("0") );
+        assert!( !is_environment_flag_value_set(" off ") );
+        assert!( !is_environment_flag_value_set("") );
+    }
+
     #[test]
     fn test_get_map_name()
     {
-        /// See if we're correctly inferring the map name.
+        // See if we're correctly inferring the map name.
         assert_eq!( get_map_name(&get_barebones_args()), "test_map" );
     }
 }
\ No newline at end of file
@@ -0,0 +1,368 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// --------------------------------------------------------------------------------------------------------
+// audio_scanner: Inspects the music shipped with a map and reports on its bitrate and relative loudness.
+// --------------------------------------------------------------------------------------------------------
+
+use std::io::prelude::*;
+use argument_handler::Arguments;
+
+use std::path::{Path, PathBuf};
+
+use shared;
+use rules;
+use error::GesError;
+
+// Nothing above this is valid for MP3 audio, and even hitting it is overkill for game music.
+const MAX_REASONABLE_BITRATE_KBPS: u32 = 320;
+
+// How far a track can be from the typical loudness of the others before it's worth mentioning.
+const MAX_LOUDNESS_DEVIATION_DB: f64 = 6.0;
+
+// Each step of an MP3 granule's global gain scales the output by 2^(1/4), or roughly 1.5 dB.
+const DB_PER_GAIN_STEP: f64 = 1.505;
+
+// Bitrates in kbps for each bitrate index, for MPEG1 layers 1-3 and then MPEG2/2.5 layer 1 and layers 2-3.
+static BITRATE_TABLE: [[u32; 15]; 5] =
+[
+    [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384],
+    [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320],
+    [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+// Sample rates for each sample rate index, for MPEG1, MPEG2, and MPEG2.5 in that order.
+static SAMPLE_RATE_TABLE: [[u32; 3]; 3] = [[44100, 48000, 32000], [22050, 24000, 16000], [11025, 12000, 8000]];
+
+/// A summary of an MP3 file's frames.
+pub struct Mp3Info
+{
+    pub frame_count: u32,
+    pub average_bitrate: u32,
+    pub max_bitrate: u32,
+    pub average_gain: Option<f64>,
+    pub duration_secs: f64,
+}
+
+/// Scans every music file in the root directory, warning about excessive bitrates and tracks that are much louder
+/// or quieter than the rest.
+pub fn report_music_usage( args: &Arguments ) -> Result<(), GesError>
+{
+    let mut music_dir = args.rootdir.clone();
+    music_dir.push("sound");
+    music_dir.push("music");
+
+    let music_tree = shared::get_cached_directory_tree( &[&music_dir], "mp3", &[], shared::keep_all_files )?;
+    let (ref _music_comp_names, ref music_write_names) = *music_tree;
+
+    let mut track_gains: Vec<(String, f64)> = Vec::new();
+
+    for music_name in music_write_names
+    {
+        let mut music_path = music_dir.clone();
+        music_path.push(&music_name);
+
+        let music_info = match read_mp3_info( &music_path )
+        {
+            Ok(x) => x,
+            Err(e) => { shared::print_warning( &format!( "Could not read music file sound/music/{}: {}", music_name, e ) ); continue; },
+        };
+
+        if music_info.max_bitrate > MAX_REASONABLE_BITRATE_KBPS
+        {
+            rules::report_message( args, "music-bitrate", format!( "Music file sound/music/{} has a bitrate of up to {}kbps!  Anything above {}kbps just wastes download time.",
+                      music_name, music_info.max_bitrate, MAX_REASONABLE_BITRATE_KBPS ) )?;
+        }
+
+        if args.verbose
+        {
+            println!( "sound/music/{} has {} frames averaging {}kbps and lasts {:.0} seconds", music_name, music_info.frame_count, music_info.average_bitrate, music_info.duration_secs );
+        }
+
+        match music_info.average_gain
+        {
+            Some(x) => track_gains.push( (music_name.clone(), x) ),
+            None => {},
+        }
+    }
+
+    // Can't have inconsistent volume with only one track.
+    if track_gains.len() < 2
+    {
+        return Ok(());
+    }
+
+    let mut sorted_gains: Vec<f64> = track_gains.iter().map(|x| x.1).collect();
+    sorted_gains.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median_gain = sorted_gains[sorted_gains.len() / 2];
+
+    for &(ref music_name, gain) in &track_gains
+    {
+        let deviation_db = (gain - median_gain) * DB_PER_GAIN_STEP;
+
+        if deviation_db.abs() > MAX_LOUDNESS_DEVIATION_DB
+        {
+            rules::report_message( args, "music-loudness", format!( "Music file sound/music/{} is roughly {:.1} dB {} than the other tracks!  Consider normalizing your music.",
+                      music_name, deviation_db.abs(), if deviation_db > 0.0 { "louder" } else { "quieter" } ) )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads through every frame of the MP3 file at the given path.
+pub fn read_mp3_info( music_path: &PathBuf ) -> Result<Mp3Info, GesError>
+{
+    shared::set_current_file( music_path );
+
+    let mut contents = Vec::new();
+    shared::open_file_source( music_path )?.read_to_end(&mut contents)?;
+
+    parse_mp3( music_path, &contents )
+}
+
+/// Walks the frames of an MP3 file, collecting their bitrates and the global gain of each layer 3 granule.
+/// The global gain is the overall scale the decoder applies to a granule, so averaging it over the non-silent
+/// granules gives a cheap approximation of loudness without having to decode the audio.
+pub fn parse_mp3( music_path: &Path, contents: &[u8] ) -> Result<Mp3Info, GesError>
+{
+    let mut offset = get_id3v2_tag_size( contents );
+
+    let mut frame_count: u32 = 0;
+    let mut bitrate_total: u64 = 0;
+    let mut max_bitrate: u32 = 0;
+    let mut gain_total: u64 = 0;
+    let mut gain_count: u64 = 0;
+    let mut duration_secs: f64 = 0.0;
+
+    while offset + 4 <= contents.len()
+    {
+        let frame_header = match parse_frame_header( &contents[offset..offset + 4] )
+        {
+            Some(x) => x,
+            None => { offset += 1; continue; }, // Not a frame, keep looking for the next sync word.
+        };
+
+        if frame_header.is_layer3
+        {
+            let side_info_start = offset + 4 + if frame_header.has_crc { 2 } else { 0 };
+
+            let side_info_length = match (frame_header.is_mpeg1, frame_header.is_mono) { (true, false) => 32, (false, true) => 9, _ => 17 };
+
+            if side_info_start + side_info_length <= contents.len()
+            {
+                for (big_values, global_gain) in read_granule_gains( &contents[side_info_start..], &frame_header )
+                {
+                    // Silent granules have no big values and tell us nothing about how loud the music is.
+                    if big_values > 0
+                    {
+                        gain_total += global_gain as u64;
+                        gain_count += 1;
+                    }
+                }
+            }
+        }
+
+        frame_count += 1;
+        bitrate_total += frame_header.bitrate as u64;
+        max_bitrate = max_bitrate.max(frame_header.bitrate);
+        duration_secs += frame_header.sample_count as f64 / frame_header.sample_rate as f64;
+
+        offset += frame_header.frame_length.max(1);
+    }
+
+    if frame_count == 0
+    {
+        return Err(GesError::Parse { file: music_path.to_path_buf(), line: 0, message: String::from("No MP3 frames found!  Is this really an MP3 file?") });
+    }
+
+    Ok(Mp3Info
+    {
+        frame_count,
+        average_bitrate: (bitrate_total / frame_count as u64) as u32,
+        max_bitrate,
+        average_gain: if gain_count > 0 { Some(gain_total as f64 / gain_count as f64) } else { None },
+        duration_secs,
+    })
+}
+
+/// The parts of an MP3 frame header we care about.
+struct FrameHeader
+{
+    is_mpeg1: bool,
+    is_layer3: bool,
+    is_mono: bool,
+    has_crc: bool,
+    bitrate: u32,
+    sample_rate: u32,
+    sample_count: u32,
+    frame_length: usize,
+}
+
+/// Parses the four byte header at the start of an MP3 frame, returning None if it isn't a valid header.
+fn parse_frame_header( header: &[u8] ) -> Option<FrameHeader>
+{
+    // Every frame starts with 11 set bits.
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0
+    {
+        return None;
+    }
+
+    let version_bits = (header[1] >> 3) & 0x03;
+    let layer_bits = (header[1] >> 1) & 0x03;
+    let bitrate_index = (header[2] >> 4) as usize;
+    let sample_rate_index = ((header[2] >> 2) & 0x03) as usize;
+
+    // Reserved and free format values can't be used to find the next frame, so treat them as invalid.
+    if version_bits == 1 || layer_bits == 0 || bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3
+    {
+        return None;
+    }
+
+    let is_mpeg1 = version_bits == 3;
+    let layer = 4 - layer_bits as usize; // 1, 2, or 3
+
+    let bitrate_row = if is_mpeg1 { layer - 1 } else if layer == 1 { 3 } else { 4 };
+    let bitrate = BITRATE_TABLE[bitrate_row][bitrate_index];
+
+    let sample_rate_row = match version_bits { 3 => 0, 2 => 1, _ => 2 };
+    let sample_rate = SAMPLE_RATE_TABLE[sample_rate_row][sample_rate_index];
+
+    let padding = ((header[2] >> 1) & 0x01) as usize;
+
+    let frame_length = match layer
+    {
+        1 => (12 * bitrate as usize * 1000 / sample_rate as usize + padding) * 4,
+        2 => 144 * bitrate as usize * 1000 / sample_rate as usize + padding,
+        _ => (if is_mpeg1 { 144 } else { 72 }) * bitrate as usize * 1000 / sample_rate as usize + padding,
+    };
+
+    // How much audio each frame holds, which is what lets us work out the length of a track without decoding it.
+    let sample_count = match layer
+    {
+        1 => 384,
+        2 => 1152,
+        _ => if is_mpeg1 { 1152 } else { 576 },
+    };
+
+    Some(FrameHeader
+    {
+        is_mpeg1,
+        is_layer3: layer == 3,
+        is_mono: header[3] >> 6 == 3,
+        has_crc: header[1] & 0x01 == 0,
+        bitrate,
+        sample_rate,
+        sample_count,
+        frame_length,
+    })
+}
+
+/// Reads the (big_values, global_gain) pair of every granule and channel in a layer 3 frame's side info.
+fn read_granule_gains( side_info: &[u8], frame_header: &FrameHeader ) -> Vec<(u32, u32)>
+{
+    let read_bits = |bit_offset: usize, bit_count: usize|
+    {
+        let mut value: u32 = 0;
+
+        for bit in bit_offset..bit_offset + bit_count
+        {
+            value = (value << 1) | ((side_info[bit / 8] >> (7 - bit % 8)) & 0x01) as u32;
+        }
+
+        value
+    };
+
+    let channel_count = if frame_header.is_mono { 1 } else { 2 };
+
+    // MPEG1 has two granules and scale factor selection info up front, MPEG2 has one granule and a larger scalefac_compress.
+    let (granule_count, mut bit_offset, granule_bits) = if frame_header.is_mpeg1
+    {
+        (2, 9 + if frame_header.is_mono { 5 } else { 3 } + 4 * channel_count, 59)
+    }
+    else
+    {
+        (1, 8 + channel_count, 63)
+    };
+
+    let mut gains = Vec::new();
+
+    for _ in 0..granule_count * channel_count
+    {
+        // part2_3_length is 12 bits, followed by big_values and then global_gain.
+        gains.push( (read_bits(bit_offset + 12, 9), read_bits(bit_offset + 21, 8)) );
+        bit_offset += granule_bits;
+    }
+
+    gains
+}
+
+/// Returns the size of the ID3v2 tag at the start of the file, or 0 if there isn't one.
+fn get_id3v2_tag_size( contents: &[u8] ) -> usize
+{
+    if contents.len() < 10 || &contents[0..3] != b"ID3"
+    {
+        return 0;
+    }
+
+    // The size is stored as four 7 bit bytes and doesn't include the 10 byte header.
+    let tag_size = contents[6..10].iter().fold(0usize, |acc, x| (acc << 7) | (*x & 0x7F) as usize);
+
+    tag_size + 10
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Builds a 128kbps 44.1kHz stereo MPEG1 layer 3 frame where every granule has the given global gain.
+    fn build_frame( global_gain: u32 ) -> Vec<u8>
+    {
+        let mut frame = vec![0u8; 417];
+        frame[0..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+
+        let mut write_bits = |bit_offset: usize, bit_count: usize, value: u32|
+        {
+            for i in 0..bit_count
+            {
+                let bit = (value >> (bit_count - 1 - i)) & 0x01;
+                frame[4 + (bit_offset + i) / 8] |= (bit as u8) << (7 - (bit_offset + i) % 8);
+            }
+        };
+
+        for granule in 0..4
+        {
+            let granule_offset = 20 + granule * 59;
+            write_bits( granule_offset + 12, 9, 100 );
+            write_bits( granule_offset + 21, 8, global_gain );
+        }
+
+        frame
+    }
+
+    #[test]
+    fn test_parse_mp3()
+    {
+        let mut contents = b"ID3\x03\x00\x00\x00\x00\x00\x05abcde".to_vec();
+
+        for _ in 0..10
+        {
+            contents.extend( build_frame(150) );
+        }
+
+        let info = parse_mp3( Path::new("test.mp3"), &contents ).unwrap();
+
+        assert_eq!( info.frame_count, 10 );
+        assert_eq!( info.average_bitrate, 128 );
+        assert_eq!( info.max_bitrate, 128 );
+        assert_eq!( info.average_gain, Some(150.0) );
+        assert!( (info.duration_secs - 10.0 * 1152.0 / 44100.0).abs() < 0.0001 );
+
+        assert!( parse_mp3( Path::new("test.mp3"), b"definitely not audio" ).is_err() );
+    }
+}
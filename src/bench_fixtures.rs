@@ -0,0 +1,117 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// bench_fixtures: Builds large synthetic releases for the benchmarks to walk, check, and compress.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::path::PathBuf;
+
+use file_writer;
+use error::GesError;
+
+/// The name of the map every synthetic release is for.
+pub static BENCH_MAP_NAME: &'static str = "ge_bench";
+
+/// How many content files a synthetic release has unless told otherwise, which is about the size of a full GE:S install.
+pub static DEFAULT_FILE_COUNT: usize = 20000;
+
+// Content is spread over directories like a real release's, so walks have to go more than one level deep.
+const FILES_PER_DIRECTORY: usize = 100;
+static CONTENT_TYPES: &[(&'static str, &'static str)] = &[("materials", "vmt"), ("materials", "vtf"), ("models", "mdl"), ("sound", "wav"), ("sound", "mp3")];
+
+/// Writes a release for the bench map into the given gesource directory, with the given number of content files and
+/// a reslist listing every one of them.  The same count always makes the same release, so benchmark runs can be
+/// compared.  Returns the path of every file written, relative to the directory.
+pub fn generate_bench_install( install_dir: &PathBuf, file_count: usize ) -> Result<Vec<String>, GesError>
+{
+    let mut content_paths = Vec::new();
+
+    for index in 0..file_count
+    {
+        let (content_dir, extension) = CONTENT_TYPES[index % CONTENT_TYPES.len()];
+        let content_path = format!( "{}/{}/set{:03}/file{:05}.{}", content_dir, BENCH_MAP_NAME, index / FILES_PER_DIRECTORY, index, extension );
+
+        let destination_path = install_dir.join( &content_path );
+
+        match destination_path.parent()
+        {
+            Some(x) => file_writer::create_dir_all( x )?,
+            None => {},
+        }
+
+        file_writer::write( &destination_path, get_bench_contents( index ).as_bytes() )?;
+        content_paths.push( content_path );
+    }
+
+    let map_path = format!( "maps/{}.bsp", BENCH_MAP_NAME );
+    let reslist_path = format!( "maps/{}.res", BENCH_MAP_NAME );
+
+    file_writer::create_dir_all( &install_dir.join("maps") )?;
+    file_writer::write( &install_dir.join( &map_path ), b"VBSP" )?;
+
+    let mut reslist_contents = String::new();
+    reslist_contents.push_str("\"resources\"\n{\n");
+
+    for content_path in &content_paths
+    {
+        reslist_contents.push_str("\t\"");
+        reslist_contents.push_str(content_path);
+        reslist_contents.push_str("\"\t\"file\"\n");
+    }
+
+    reslist_contents.push_str("}\n");
+
+    file_writer::write( &install_dir.join( &reslist_path ), reslist_contents.as_bytes() )?;
+
+    content_paths.push( map_path );
+    content_paths.push( reslist_path );
+
+    Ok(content_paths)
+}
+
+/// Returns the contents of the content file with the given index.  Every file is different, so none of them are
+/// treated as duplicates, and their sizes vary the way real content's do.
+fn get_bench_contents( index: usize ) -> String
+{
+    let mut contents = format!( "// Synthetic benchmark file {}\n", index );
+
+    for line in 0..(index % 16)
+    {
+        contents.push_str( &format!( "\"line{}\" \"{}\"\n", line, index * line ) );
+    }
+
+    contents
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use reslist_builder;
+    use super::*;
+
+    #[test]
+    fn test_generate_bench_install()
+    {
+        let mut args = get_barebones_args();
+        args.rootdir = get_root_test_directory();
+        args.rootdir.push("temp");
+        args.rootdir.push("bench_fixtures");
+        args.rootdir.push("gesource");
+
+        let _ = fs::remove_dir_all( &args.rootdir );
+
+        let written_paths = generate_bench_install( &args.rootdir, 250 ).unwrap();
+        assert_eq!( written_paths.len(), 252 );
+        assert!( args.rootdir.join("sound/ge_bench/set002/file00249.mp3").is_file() );
+
+        // The reslist has to pass, or the benchmark would only be timing how fast it fails.
+        assert!( reslist_builder::check_reslist( &args, &args.rootdir.join("maps/ge_bench.res") ).is_ok() );
+
+        fs::remove_dir_all( args.rootdir.parent().unwrap() ).unwrap();
+    }
+}
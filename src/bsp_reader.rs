@@ -0,0 +1,142 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------
+// bsp_reader: Pulls the bits of information we care about out of compiled Source engine maps.
+// ------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::path::Path;
+
+use regex;
+use regex::Regex;
+
+use error::GesError;
+
+// Every Source map starts with these bytes, followed by the version and the lump directory.
+const BSP_IDENT: &[u8] = b"VBSP";
+const ENTITY_LUMP_OFFSET: usize = 8;
+
+/// Returns the entity lump of the given map, which is the plain text list of every entity and its keyvalues.
+pub fn read_entity_lump( bsp_path: &Path ) -> Result<String, GesError>
+{
+    let mut contents = Vec::new();
+    fs::File::open(bsp_path)?.read_to_end(&mut contents)?;
+
+    if contents.len() < ENTITY_LUMP_OFFSET + 8 || &contents[..4] != BSP_IDENT
+    {
+        return Err(GesError::Parse { file: bsp_path.to_path_buf(), line: 0, message: String::from("Map is not a valid Source engine BSP file!") });
+    }
+
+    // The entity lump is always the first entry in the lump directory, stored as an offset and a length.
+    let lump_offset = read_i32( &contents, ENTITY_LUMP_OFFSET ) as usize;
+    let lump_length = read_i32( &contents, ENTITY_LUMP_OFFSET + 4 ) as usize;
+
+    if lump_offset.checked_add(lump_length) == None || lump_offset + lump_length > contents.len()
+    {
+        return Err(GesError::Parse { file: bsp_path.to_path_buf(), line: 0, message: String::from("Map's entity lump extends past the end of the file!  It may be truncated.") });
+    }
+
+    // The lump is null terminated, which isn't something we want in our string.
+    let entities = &contents[lump_offset..lump_offset + lump_length];
+    let entities = match entities.iter().position(|x| *x == 0)
+    {
+        Some(x) => &entities[..x],
+        None => entities,
+    };
+
+    Ok(String::from_utf8_lossy( entities ).into_owned())
+}
+
+/// Returns every value given to the provided key in the entity lump, such as every "targetname".
+pub fn get_entity_values( entities: &str, key: &str ) -> Vec<String>
+{
+    let key_re = Regex::new( &format!( r#"(?i)"{}"\s+"([^"]*)""#, regex::escape(key) ) ).unwrap();
+
+    key_re.captures_iter( entities ).map(|x| String::from(&x[1])).collect()
+}
+
+/// Returns every value given to the provided key by entities of the provided class, such as the "model" of every
+/// "env_sprite".
+pub fn get_class_entity_values( entities: &str, classname: &str, key: &str ) -> Vec<String>
+{
+    lazy_static!
+    {
+        static ref ENTITY_RE: Regex = Regex::new(r#"\{[^{}]*\}"#).unwrap();
+    }
+
+    let mut values = Vec::new();
+
+    for entity in ENTITY_RE.find_iter( entities ).map(|x| x.as_str())
+    {
+        if get_entity_values( entity, "classname" ).iter().any(|x| x.eq_ignore_ascii_case( classname ))
+        {
+            values.extend( get_entity_values( entity, key ) );
+        }
+    }
+
+    values
+}
+
+/// Returns every value in the entity lump, whatever key it belongs to.
+pub fn get_all_entity_values( entities: &str ) -> Vec<String>
+{
+    lazy_static!
+    {
+        static ref KEYVALUE_RE: Regex = Regex::new(r#""[^"]*"\s+"([^"]*)""#).unwrap();
+    }
+
+    KEYVALUE_RE.captures_iter( entities ).map(|x| String::from(&x[1])).collect()
+}
+
+/// Reads a little endian i32 from the given position in the data.
+fn read_i32( data: &[u8], position: usize ) -> i32
+{
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice( &data[position..position + 4] );
+    i32::from_le_bytes( bytes )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_read_entity_lump()
+    {
+        let entities = "{\n\"classname\" \"worldspawn\"\n}\n{\n\"classname\" \"trigger_multiple\"\n\"targetname\" \"bunker_hall\"\n}\n";
+
+        // Just enough of a map to hold a header and an entity lump right after it.
+        let mut bsp = Vec::new();
+        bsp.extend_from_slice( BSP_IDENT );
+        bsp.extend_from_slice( &20i32.to_le_bytes() );
+        bsp.extend_from_slice( &16i32.to_le_bytes() );
+        bsp.extend_from_slice( &(entities.len() as i32 + 1).to_le_bytes() );
+        bsp.extend_from_slice( entities.as_bytes() );
+        bsp.push( 0 );
+
+        let mut bsp_path = get_root_test_directory();
+        bsp_path.push("temp");
+        fs::create_dir_all(&bsp_path).unwrap();
+        bsp_path.push("test_entities.bsp");
+        fs::File::create(&bsp_path).unwrap().write_all(&bsp).unwrap();
+
+        let lump = read_entity_lump( &bsp_path ).unwrap();
+        assert_eq!( lump, entities );
+        assert_eq!( get_entity_values( &lump, "targetname" ), vec![String::from("bunker_hall")] );
+        assert_eq!( get_all_entity_values( &lump ).len(), 3 );
+        assert_eq!( get_class_entity_values( &lump, "Trigger_Multiple", "targetname" ), vec![String::from("bunker_hall")] );
+        assert!( get_class_entity_values( &lump, "worldspawn", "targetname" ).is_empty() );
+
+        // A map that's just an empty file is a placeholder, not something we can read.
+        fs::File::create(&bsp_path).unwrap();
+        assert!( read_entity_lump( &bsp_path ).is_err() );
+
+        fs::remove_file(&bsp_path).unwrap();
+    }
+}
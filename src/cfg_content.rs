@@ -0,0 +1,163 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// cfg_content: Checks the config files a map ships for commands no map has any business running on a server.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::Path;
+
+use argument_handler::Arguments;
+use reslist_builder;
+use shared;
+use rules;
+use error::GesError;
+
+// Commands that change how the server is run or secured, rather than how the map plays, along with why.  Server owners
+// exec map configs without reading them, so one of these would quietly take effect on every server running the map.
+static DISALLOWED_COMMANDS: &[(&'static str, &'static str)] = &[
+    ("rcon_password", "changes the server's remote console password"),
+    ("sv_password", "locks players out of the server"),
+    ("sv_cheats", "controls whether cheats are allowed on the server"),
+    ("sv_lan", "changes whether players outside the LAN can join"),
+    ("sv_allowdownload", "changes whether clients can download the server's files"),
+    ("sv_allowupload", "changes whether clients can upload files to the server"),
+    ("sv_downloadurl", "points clients at a different fast download server"),
+    ("hostname", "renames the server"),
+    ("rcon", "runs commands on another server"),
+    ("plugin_load", "loads a server plugin"),
+    ("plugin_unload", "unloads a server plugin"),
+    ("quit", "shuts the server down"),
+    ("exit", "shuts the server down"),
+    ("_restart", "restarts the server"),
+];
+
+/// Checks every config file the release ships for disallowed commands.  Problems with every file are collected so they
+/// can all be fixed in one go.
+pub fn check_cfg_files( args: &Arguments ) -> Result<(), GesError>
+{
+    let mut error_problems: Vec<String> = Vec::new();
+
+    for cfg_file in reslist_builder::get_server_files( args )?.iter().filter(|x| shared::get_string_file_extension( x ).to_lowercase() == "cfg")
+    {
+        let file_path = args.rootdir.join( cfg_file );
+
+        shared::print_trace( args, &format!( "Checking config file {}.", cfg_file ) );
+
+        match check_cfg_file( args, &file_path, cfg_file, &mut error_problems )
+        {
+            Ok(_) => {},
+            Err(e) => error_problems.push( e.to_string() ),
+        }
+    }
+
+    if !error_problems.is_empty()
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Some config files run commands that don't belong in a map's config:\n");
+
+        for problem in error_problems
+        {
+            error_text.push_str("  ");
+            error_text.push_str(&problem);
+            error_text.push_str("\n");
+        }
+
+        return Err(GesError::PolicyViolation( error_text ));
+    }
+
+    Ok(())
+}
+
+/// Reports every disallowed command in the config file at the given path, which is shown to the user as its name.
+/// Commands that fail the release are added to the given problems.
+fn check_cfg_file( args: &Arguments, file_path: &Path, file_name: &str, error_problems: &mut Vec<String> ) -> Result<(), GesError>
+{
+    let contents = fs::read( file_path )?;
+
+    for (command, line) in get_disallowed_commands( &String::from_utf8_lossy( &contents ) )
+    {
+        let reason = DISALLOWED_COMMANDS.iter().find(|x| x.0 == command).unwrap().1;
+        let error_text = format!( "{} runs {} on line {}, which {}!", file_name, command, line, reason );
+
+        match rules::report_in_file( args, "cfg-disallowed-command", file_path, line, GesError::PolicyViolation( error_text ) )
+        {
+            Ok(_) => {},
+            Err(e) => error_problems.push( e.to_string() ),
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns each disallowed command in the given config, lowercased, along with the line it's on.  Lines can hold more
+/// than one command split by semicolons, and anything after // is a comment, just like the console reads them.
+fn get_disallowed_commands( contents: &str ) -> Vec<(String, usize)>
+{
+    let mut disallowed_commands = Vec::new();
+
+    for (index, line) in contents.lines().enumerate()
+    {
+        let line = match line.find("//")
+        {
+            Some(x) => &line[..x],
+            None => line,
+        };
+
+        for statement in line.split(';')
+        {
+            let command = match statement.split_whitespace().next()
+            {
+                Some(x) => x.trim_matches('"').to_lowercase(),
+                None => continue,
+            };
+
+            if DISALLOWED_COMMANDS.iter().any(|x| x.0 == command)
+            {
+                disallowed_commands.push( (command, index + 1) );
+            }
+        }
+    }
+
+    disallowed_commands
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_get_disallowed_commands()
+    {
+        let contents = "// ge_test settings\nge_roundtime 300\nsv_cheats 1; \"RCON_PASSWORD\" hunter2\n\nmp_timelimit 20 // not hostname\nsv_cheatsheet 1\n";
+
+        assert_eq!( get_disallowed_commands( contents ), vec![(String::from("sv_cheats"), 3), (String::from("rcon_password"), 3)] );
+        assert!( get_disallowed_commands( "ge_roundtime 300\n" ).is_empty() );
+    }
+
+    #[test]
+    fn test_check_cfg_files()
+    {
+        let mut args = get_barebones_args();
+        args.rootdir = get_root_test_directory();
+        args.rootdir.push("temp");
+        args.rootdir.push("cfg_content");
+        args.rootdir.push("gesource");
+
+        let _ = fs::remove_dir_all( &args.rootdir );
+        fs::create_dir_all( args.rootdir.join("cfg") ).unwrap();
+        fs::write( args.rootdir.join("cfg/ge_test.cfg"), "ge_roundtime 300\n" ).unwrap();
+
+        assert!( check_cfg_files( &args ).is_ok() );
+
+        fs::write( args.rootdir.join("cfg/ge_test.cfg"), "ge_roundtime 300\nsv_cheats 1\n" ).unwrap();
+        assert!( check_cfg_files( &args ).unwrap_err().to_string().contains("cfg/ge_test.cfg runs sv_cheats on line 2") );
+
+        fs::remove_dir_all( args.rootdir.parent().unwrap() ).unwrap();
+    }
+}
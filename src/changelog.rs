@@ -0,0 +1,356 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// changelog: Describes what changed since the previous compressed release, ready to paste into a release post.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use bzip2::read::BzDecoder;
+
+use argument_handler::Arguments;
+use keyvalues::{Tokenizer, TokenKind};
+use manifest;
+use manifest::{HashAlgorithm, Manifest};
+use shared;
+use file_writer;
+use error::GesError;
+
+/// What the previous release of a map held, read before compression replaces any of it.
+pub struct PreviousRelease
+{
+    manifest: Manifest,
+    /// The contents of each of its scripts that still had a compressed copy, by relative path.
+    scripts: HashMap<String, String>,
+}
+
+/// Reads the previous release from the manifest given with --delta-from, or the one the last run left in the compressed
+/// directory.  Returns None if there's no previous release to compare against.
+pub fn read_previous_release( args: &Arguments, map_name: &str, compressed_dir: &PathBuf ) -> Result<Option<PreviousRelease>, GesError>
+{
+    let manifest_path = match args.delta_from
+    {
+        Some(ref x) => x.clone(),
+        None => compressed_dir.join( manifest::get_relative_manifest_path( map_name ) ),
+    };
+
+    if !manifest_path.is_file()
+    {
+        return Ok(None);
+    }
+
+    let previous_manifest = manifest::read_manifest( &manifest_path ).map_err(|e| e.in_file( &manifest_path ))?;
+
+    // Manifests sit in the maps directory of their release, so the compressed scripts are found relative to it.
+    let release_dir = match manifest_path.parent().and_then(|x| x.parent())
+    {
+        Some(x) => x.to_path_buf(),
+        None => PathBuf::new(),
+    };
+
+    let mut scripts = HashMap::new();
+
+    for &(ref relative_path, ref hash) in previous_manifest.entries.iter().filter(|x| is_script_path( &x.0 ))
+    {
+        match read_compressed_script( &release_dir.join( format!( "{}.bz2", relative_path ) ), previous_manifest.algorithm, hash )
+        {
+            Some(x) => { scripts.insert( relative_path.to_lowercase(), x ); },
+            None => {},
+        }
+    }
+
+    Ok(Some(PreviousRelease { manifest: previous_manifest, scripts }))
+}
+
+/// Writes a changelog comparing the release whose manifest is at manifest_path to the previous one.
+pub fn write_changelog( args: &Arguments, map_name: &str, previous_release: Option<&PreviousRelease>, manifest_path: &PathBuf, changelog_path: &PathBuf ) -> Result<(), GesError>
+{
+    let current_manifest = manifest::read_manifest( manifest_path )?;
+    let mut current_scripts = HashMap::new();
+
+    for &(ref relative_path, _) in current_manifest.entries.iter().filter(|x| is_script_path( &x.0 ))
+    {
+        match fs::read_to_string( args.rootdir.join(relative_path) )
+        {
+            Ok(x) => { current_scripts.insert( relative_path.to_lowercase(), x ); },
+            Err(_) => {},
+        }
+    }
+
+    let contents = get_changelog( map_name, args.author.as_deref(), previous_release, &current_manifest, &current_scripts );
+
+    match changelog_path.parent()
+    {
+        Some(x) if x != PathBuf::new() => file_writer::create_dir_all(x)?,
+        _ => {},
+    }
+
+    file_writer::write( changelog_path, contents.as_bytes() )?;
+
+    println!( "Wrote changelog for {} to {}.", map_name, changelog_path.display() );
+
+    Ok(())
+}
+
+/// Returns a Markdown changelog listing every file that was added, removed, or modified since the previous release,
+/// along with how the values of each modified script changed.  Scripts are keyed by their lowercase relative path.
+fn get_changelog( map_name: &str, author: Option<&str>, previous_release: Option<&PreviousRelease>, current_manifest: &Manifest, current_scripts: &HashMap<String, String> ) -> String
+{
+    let current_entries = &current_manifest.entries;
+
+    let mut contents = String::new();
+    contents.push_str("## "); contents.push_str(map_name); contents.push_str("\n\n");
+
+    match author
+    {
+        Some(x) => { contents.push_str("Released by "); contents.push_str(x); contents.push_str(".\n\n"); },
+        None => {},
+    }
+
+    let previous_release = match previous_release
+    {
+        Some(x) => x,
+        None =>
+        {
+            contents.push_str( &format!( "First release, with {} files.\n", current_entries.len() ) );
+            return contents;
+        },
+    };
+
+    let previous_entries = &previous_release.manifest.entries;
+    let same_algorithm = previous_release.manifest.algorithm == current_manifest.algorithm;
+
+    if !same_algorithm
+    {
+        shared::print_warning( &format!( "The previous manifest was hashed with {} rather than {}, so every file still in the release is listed as modified.",
+                                         previous_release.manifest.algorithm, current_manifest.algorithm ) );
+    }
+
+    let find_entry = |entries: &[(String, String)], path: &str| entries.iter().position(|x| x.0.to_lowercase() == path.to_lowercase());
+
+    let added_files: Vec<&String> = current_entries.iter().filter(|x| find_entry( previous_entries, &x.0 ) == None).map(|x| &x.0).collect();
+    let removed_files: Vec<&String> = previous_entries.iter().filter(|x| find_entry( current_entries, &x.0 ) == None).map(|x| &x.0).collect();
+    let modified_files: Vec<&String> = current_entries.iter()
+                                                      .filter(|x| find_entry( previous_entries, &x.0 ).is_some_and(|y| !same_algorithm || previous_entries[y].1 != x.1))
+                                                      .map(|x| &x.0)
+                                                      .collect();
+
+    if added_files.is_empty() && removed_files.is_empty() && modified_files.is_empty()
+    {
+        contents.push_str("No files changed since the previous release.\n");
+        return contents;
+    }
+
+    for &(heading, ref files) in &[("Added", &added_files), ("Removed", &removed_files), ("Modified", &modified_files)]
+    {
+        if files.is_empty()
+        {
+            continue;
+        }
+
+        contents.push_str("### "); contents.push_str(heading); contents.push_str("\n\n");
+
+        for file in files.iter()
+        {
+            contents.push_str("- `"); contents.push_str(file); contents.push_str("`\n");
+
+            if heading != "Modified"
+            {
+                continue;
+            }
+
+            match (previous_release.scripts.get( &file.to_lowercase() ), current_scripts.get( &file.to_lowercase() ))
+            {
+                (Some(x), Some(y)) =>
+                {
+                    for change in get_value_changes( x, y )
+                    {
+                        contents.push_str("  - "); contents.push_str(&change); contents.push_str("\n");
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        contents.push_str("\n");
+    }
+
+    contents
+}
+
+/// Returns a line describing each value that differs between two versions of a script.  A key holding one value in both
+/// shows the old and new value, anything else lists what was added and removed.
+fn get_value_changes( previous_contents: &str, current_contents: &str ) -> Vec<String>
+{
+    let previous_values = get_script_values( previous_contents );
+    let current_values = get_script_values( current_contents );
+
+    let mut keys: Vec<&String> = Vec::new();
+
+    for &(ref key, _) in current_values.iter().chain( previous_values.iter() )
+    {
+        if !keys.contains(&key)
+        {
+            keys.push(key);
+        }
+    }
+
+    let mut changes = Vec::new();
+
+    for key in keys
+    {
+        let previous: Vec<&String> = previous_values.iter().filter(|x| x.0 == *key).map(|x| &x.1).collect();
+        let current: Vec<&String> = current_values.iter().filter(|x| x.0 == *key).map(|x| &x.1).collect();
+
+        if previous == current
+        {
+            continue;
+        }
+
+        if previous.len() == 1 && current.len() == 1
+        {
+            changes.push( format!( "`{}`: {} -> {}", key, previous[0], current[0] ) );
+            continue;
+        }
+
+        for value in get_missing_values( &previous, &current )
+        {
+            changes.push( format!( "`{}`: added {}", key, value ) );
+        }
+
+        for value in get_missing_values( &current, &previous )
+        {
+            changes.push( format!( "`{}`: removed {}", key, value ) );
+        }
+    }
+
+    changes
+}
+
+/// Returns every value in values that isn't matched by one in other_values, counting repeats.
+fn get_missing_values<'a>( other_values: &[&String], values: &[&'a String] ) -> Vec<&'a String>
+{
+    let mut unmatched = other_values.to_vec();
+    let mut missing_values = Vec::new();
+
+    for value in values
+    {
+        match unmatched.iter().position(|x| x == value)
+        {
+            Some(x) => { unmatched.remove(x); },
+            None => missing_values.push(*value),
+        }
+    }
+
+    missing_values
+}
+
+/// Returns every key and value in a KeyValues script in order, with each key named after the sections it's in, like
+/// "GamemodeWeights > LTK".  Reading stops at the first thing that can't be read, since the script was checked already.
+fn get_script_values( contents: &str ) -> Vec<(String, String)>
+{
+    let uncommented_contents = shared::blank_out_comments( contents );
+    let mut tokenizer = Tokenizer::new( &uncommented_contents );
+
+    let mut sections: Vec<&str> = Vec::new();
+    let mut pending_key: Option<&str> = None;
+    let mut values = Vec::new();
+
+    while let Ok(Some(token)) = tokenizer.next_token()
+    {
+        match (pending_key.take(), token.kind)
+        {
+            (Some(x), TokenKind::Text) =>
+            {
+                let mut key_names = sections.clone();
+                key_names.push(x);
+
+                values.push( (key_names.join(" > "), String::from(token.text)) );
+            },
+            (Some(x), TokenKind::Open) => sections.push(x),
+            (None, TokenKind::Text) => pending_key = Some(token.text),
+            (None, TokenKind::Open) => sections.push(""),
+            (_, TokenKind::Close) => { sections.pop(); },
+        }
+    }
+
+    values
+}
+
+/// Returns the decompressed contents of a compressed script, if it can be read and is still the version the previous
+/// manifest expects.
+fn read_compressed_script( compressed_path: &PathBuf, algorithm: HashAlgorithm, expected_hash: &str ) -> Option<String>
+{
+    let mut contents = Vec::new();
+    BzDecoder::new( fs::File::open(compressed_path).ok()? ).read_to_end(&mut contents).ok()?;
+
+    if algorithm.hash_reader( &mut &contents[..] ).ok()? != expected_hash
+    {
+        return None;
+    }
+
+    String::from_utf8(contents).ok()
+}
+
+/// Returns true if the given relative path is a map or music script, whose values are worth listing.
+fn is_script_path( relative_path: &str ) -> bool
+{
+    let lowercase_path = relative_path.to_lowercase();
+
+    lowercase_path.starts_with("scripts/") && lowercase_path.ends_with(".txt")
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_get_changelog()
+    {
+        let previous_entries = vec![(String::from("maps/ge_test.bsp"), String::from("aa")),
+                                    (String::from("scripts/maps/ge_test.txt"), String::from("bb")),
+                                    (String::from("materials/ge_test/old.vtf"), String::from("cc"))];
+
+        let current_entries = vec![(String::from("maps/ge_test.bsp"), String::from("aa")),
+                                   (String::from("Scripts/Maps/ge_test.txt"), String::from("dd")),
+                                   (String::from("materials/ge_test/new.vtf"), String::from("ee"))];
+
+        let mut previous_scripts = HashMap::new();
+        previous_scripts.insert( String::from("scripts/maps/ge_test.txt"), String::from("BaseWeight\t500\r\nGamemodeWeights\r\n{\r\n\tLTK\t100\r\n}\r\n") );
+
+        let mut current_scripts = HashMap::new();
+        current_scripts.insert( String::from("scripts/maps/ge_test.txt"), String::from("// Comment\r\nBaseWeight\t700\r\nGamemodeWeights\r\n{\r\n\tLTK\t100\r\n\tYOLT\t0\r\n}\r\n") );
+
+        let current_manifest = Manifest { algorithm: HashAlgorithm::Blake3, entries: current_entries };
+        let previous_release = PreviousRelease { manifest: Manifest { algorithm: HashAlgorithm::Blake3, entries: previous_entries }, scripts: previous_scripts };
+        let changelog = get_changelog( "ge_test", None, Some(&previous_release), &current_manifest, &current_scripts );
+
+        assert_eq!( changelog, "## ge_test\n\n\
+                                ### Added\n\n- `materials/ge_test/new.vtf`\n\n\
+                                ### Removed\n\n- `materials/ge_test/old.vtf`\n\n\
+                                ### Modified\n\n- `Scripts/Maps/ge_test.txt`\n  - `BaseWeight`: 500 -> 700\n  - `GamemodeWeights > YOLT`: added 0\n\n" );
+
+        // Nothing to compare against, or nothing that changed, is said outright.
+        assert_eq!( get_changelog( "ge_test", Some("Entropy-Soldier"), None, &current_manifest, &current_scripts ), "## ge_test\n\nReleased by Entropy-Soldier.\n\nFirst release, with 3 files.\n" );
+
+        let unchanged_release = PreviousRelease { manifest: Manifest { algorithm: HashAlgorithm::Blake3, entries: current_manifest.entries.clone() }, scripts: HashMap::new() };
+        assert!( get_changelog( "ge_test", None, Some(&unchanged_release), &current_manifest, &current_scripts ).ends_with("No files changed since the previous release.\n") );
+    }
+
+    #[test]
+    fn test_get_value_changes()
+    {
+        let previous_contents = "\"music\"\n{\n\t\"file\"\t\"music/a.mp3\"\n\t\"file\"\t\"music/b.mp3\"\n}\n";
+        let current_contents = "\"music\"\n{\n\t\"file\"\t\"music/b.mp3\"\n\t\"file\"\t\"music/c.mp3\"\n}\n";
+
+        assert_eq!( get_value_changes( previous_contents, current_contents ), vec!["`music > file`: added music/c.mp3", "`music > file`: removed music/a.mp3"] );
+        assert_eq!( get_value_changes( previous_contents, previous_contents ), Vec::<String>::new() );
+    }
+}
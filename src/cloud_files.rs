@@ -0,0 +1,194 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// -----------------------------------------------------------------------------------------------------------
+// cloud_files: Finds online-only cloud files and network shares in a release, and downloads them on request.
+// -----------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io;
+
+use std::path::Path;
+
+use argument_handler::Arguments;
+use reslist_builder;
+use shared;
+use error::GesError;
+
+// Listing every online-only file in a big release would bury everything else, a few is enough to know what's going on.
+const MAX_LISTED_FILES: usize = 5;
+
+/// Warns about any files in the release that are only stored online by OneDrive, Dropbox, or a similar client, and
+/// about a root directory on a network share.  Both still work, but can make scans and compression slow or fail partway.
+pub fn warn_about_cloud_files( args: &Arguments, map_name: &str ) -> Result<(), GesError>
+{
+    if is_network_path( &args.rootdir )
+    {
+        shared::print_warning( &format!( "{} is on a network share.  Scanning and compressing it may be slow, and will fail if the connection drops.", args.rootdir.display() ) );
+    }
+
+    let placeholder_files = find_placeholder_files( args, &get_release_files( args, map_name )? );
+
+    if placeholder_files.is_empty() || args.hydrate
+    {
+        return Ok(());
+    }
+
+    let mut warning_text = String::new();
+    warning_text.push_str( &format!( "{} files are only stored online by a cloud storage client, and will have to be downloaded when they're read:\n", placeholder_files.len() ) );
+
+    for placeholder_file in placeholder_files.iter().take(MAX_LISTED_FILES)
+    {
+        warning_text.push_str("  "); warning_text.push_str(placeholder_file); warning_text.push_str("\n");
+    }
+
+    if placeholder_files.len() > MAX_LISTED_FILES
+    {
+        warning_text.push_str( &format!( "  and {} more.\n", placeholder_files.len() - MAX_LISTED_FILES ) );
+    }
+
+    warning_text.push_str("This can make scans and compression slow, or fail partway if the client is offline.  Run with --hydrate to download them all before compressing, or mark the folder as always kept on this device.");
+
+    shared::print_warning( &warning_text );
+
+    Ok(())
+}
+
+/// Reads every online-only file in the release from start to finish, which makes the cloud storage client download it,
+/// so nothing can stall or fail partway through compression.
+pub fn hydrate_files( args: &Arguments, map_name: &str ) -> Result<(), GesError>
+{
+    let placeholder_files = find_placeholder_files( args, &get_release_files( args, map_name )? );
+
+    if placeholder_files.is_empty()
+    {
+        return Ok(());
+    }
+
+    println!( "Downloading {} online-only files before compressing.", placeholder_files.len() );
+
+    for placeholder_file in &placeholder_files
+    {
+        let mut file_path = args.rootdir.clone();
+        file_path.push(placeholder_file);
+
+        let mut source = fs::File::open(&file_path).map_err(|e| GesError::from(e).in_file( &file_path ))?;
+        io::copy( &mut source, &mut io::sink() ).map_err(|e| GesError::from(e).in_file( &file_path ))?;
+
+        if args.verbose
+        {
+            println!( "Downloaded {}", placeholder_file );
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the relative path of every file that goes out with the release.
+fn get_release_files( args: &Arguments, map_name: &str ) -> Result<Vec<String>, GesError>
+{
+    let directory_tree = reslist_builder::generate_directory_tree( args )?;
+    let (ref _file_comp_list, ref file_write_list) = *directory_tree;
+
+    let mut release_files = vec![format!( "maps/{}.bsp", map_name )];
+    release_files.extend( file_write_list.iter().cloned() );
+
+    Ok(release_files)
+}
+
+/// Returns every one of the given files, relative to the root directory, that's only stored online.
+fn find_placeholder_files( args: &Arguments, relative_paths: &[String] ) -> Vec<String>
+{
+    relative_paths.iter()
+                  .filter(|x|
+                  {
+                      let mut file_path = args.rootdir.clone();
+                      file_path.push(x);
+
+                      // symlink_metadata doesn't follow the file, so it can't trigger a download itself.
+                      match fs::symlink_metadata(&file_path)
+                      {
+                          Ok(ref y) => is_placeholder( y ),
+                          Err(_) => false,
+                      }
+                  })
+                  .cloned()
+                  .collect()
+}
+
+/// Returns true if the file is a cloud placeholder whose contents haven't been downloaded.
+#[cfg(windows)]
+fn is_placeholder( metadata: &fs::Metadata ) -> bool
+{
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x0000_1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x0004_0000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x0040_0000;
+
+    metadata.file_attributes() & (FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS) != 0
+}
+
+/// Returns true if the file is a cloud placeholder whose contents haven't been downloaded.  File Provider clients on
+/// macOS leave these as files with a size but nothing stored on disk.
+#[cfg(target_os = "macos")]
+fn is_placeholder( metadata: &fs::Metadata ) -> bool
+{
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.len() > 0 && metadata.blocks() == 0
+}
+
+/// Returns true if the file is a cloud placeholder whose contents haven't been downloaded.  Other platforms don't
+/// have placeholders that can be told apart from normal files.
+#[cfg(not(any(windows, target_os = "macos")))]
+fn is_placeholder( _metadata: &fs::Metadata ) -> bool
+{
+    false
+}
+
+/// Returns true if the given path is on a network share, such as \\server\share\gesource.  Drive letters mapped to
+/// a share can't be told apart from local drives this way.
+fn is_network_path( path: &Path ) -> bool
+{
+    let path_string = path.to_string_lossy();
+
+    // \\?\ and \\.\ are local paths in the extended Windows format, everything else starting with two slashes is a share.
+    if path_string.starts_with(r"\\?\UNC\")
+    {
+        return true;
+    }
+
+    (path_string.starts_with(r"\\") || path_string.starts_with("//")) && !path_string.starts_with(r"\\?\") && !path_string.starts_with(r"\\.\")
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::PathBuf;
+    use shared::get_barebones_args;
+    use super::*;
+
+    #[test]
+    fn test_is_network_path()
+    {
+        assert!( is_network_path( &PathBuf::from(r"\\server\share\gesource") ) );
+        assert!( is_network_path( &PathBuf::from(r"\\?\UNC\server\share\gesource") ) );
+        assert!( is_network_path( &PathBuf::from("//server/share/gesource") ) );
+        assert!( !is_network_path( &PathBuf::from(r"\\?\C:\maps\gesource") ) );
+        assert!( !is_network_path( &PathBuf::from(r"C:\maps\gesource") ) );
+        assert!( !is_network_path( &PathBuf::from("/home/mapper/gesource") ) );
+    }
+
+    #[test]
+    fn test_find_placeholder_files()
+    {
+        // Everything in the test release is stored locally.
+        let args = get_barebones_args();
+        let release_files = vec![String::from("maps/test_map.bsp"), String::from("scripts/maps/test_map.txt"), String::from("missing.txt")];
+
+        assert!( find_placeholder_files( &args, &release_files ).is_empty() );
+    }
+}
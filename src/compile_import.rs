@@ -0,0 +1,202 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// compile_import: Builds a release directory out of what a Hammer or VBCT compile left behind.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use argument_handler::Arguments;
+use file_categories;
+use file_categories::FileCategory;
+use file_writer;
+use manifest;
+use preflight::PreflightReport;
+use shared;
+use stock_content;
+use error::GesError;
+
+// Directories of a compile output, or the mod folder it compiled against, that hold content a map can ship.
+static CONTENT_DIRECTORIES: &[&'static str] = &["materials", "models", "sound", "particles", "resource", "scripts"];
+
+/// Returns the path of the map a compile made.  Hammer leaves the bsp beside the vmf, while VBCT and anything set up
+/// like a mod folder put it in maps, so both are looked in.  There has to be exactly one, or there's no telling which
+/// map is being released.
+pub fn find_compiled_map( compile_dir: &Path ) -> Result<PathBuf, GesError>
+{
+    let mut map_paths: Vec<PathBuf> = Vec::new();
+
+    for search_dir in &[compile_dir.to_path_buf(), compile_dir.join("maps")]
+    {
+        let entries = match fs::read_dir( search_dir )
+        {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
+
+        for entry in entries
+        {
+            let entry_path = entry?.path();
+
+            if entry_path.is_file() && shared::get_file_extension( &entry_path ).to_lowercase() == "bsp"
+            {
+                map_paths.push( entry_path );
+            }
+        }
+    }
+
+    map_paths.sort();
+
+    match map_paths.len()
+    {
+        1 => Ok(map_paths.remove(0)),
+        0 => Err(GesError::MissingFile { path: compile_dir.to_path_buf(), message: String::from("No compiled map was found here or in its maps directory!  Has the compile finished?") }),
+        _ =>
+        {
+            let map_names: Vec<String> = map_paths.iter().map(|x| x.display().to_string()).collect();
+
+            let mut error_text = String::new();
+            error_text.push_str("The compile output has more than one map, so there's no telling which one to release: ");
+            error_text.push_str( &map_names.join(", ") );
+            error_text.push_str(".  Move the others somewhere else first.");
+
+            Err(GesError::Config( error_text ))
+        },
+    }
+}
+
+/// Copies the compiled map and the custom content beside it into the root directory, laid out the way a release is.
+/// Compile leftovers and files identical to stock content are left behind.  Returns how many files were copied.
+pub fn import_compile_output( args: &Arguments, map_name: &str ) -> Result<usize, GesError>
+{
+    let compile_dir = args.from_compile.as_ref().unwrap();
+    let compiled_map_path = find_compiled_map( compile_dir )?;
+
+    let mut copied_file_count = 0;
+
+    let mut release_map_path = args.rootdir.join("maps");
+    file_writer::create_dir_all( &release_map_path )?;
+
+    release_map_path.push( map_name );
+    release_map_path.set_extension("bsp");
+
+    file_writer::copy( &compiled_map_path, &release_map_path )?;
+    copied_file_count += 1;
+
+    for content_dir_name in CONTENT_DIRECTORIES
+    {
+        let content_dir = compile_dir.join( content_dir_name );
+
+        if !content_dir.is_dir()
+        {
+            continue;
+        }
+
+        let (comp_paths, write_paths) = shared::get_files_in_directory( &content_dir, "", &[] )?;
+
+        for (comp_path, write_path) in comp_paths.iter().zip( write_paths.iter() )
+        {
+            let source_path = content_dir.join( write_path );
+            let release_path = format!( "{}/{}", content_dir_name, write_path );
+
+            if !is_custom_content( &source_path, &format!( "{}/{}", content_dir_name, comp_path ) )?
+            {
+                shared::print_trace( args, &format!( "Left {} out of the release since it isn't custom content.", release_path ) );
+                continue;
+            }
+
+            let destination_path = args.rootdir.join( &release_path );
+
+            match destination_path.parent()
+            {
+                Some(x) => file_writer::create_dir_all( x )?,
+                None => {},
+            }
+
+            file_writer::copy( &source_path, &destination_path )?;
+            copied_file_count += 1;
+
+            shared::print_trace( args, &format!( "Copied {} from the compile output.", release_path ) );
+        }
+    }
+
+    Ok(copied_file_count)
+}
+
+/// Returns true if the file at the given path is something the map brought with it.  Compile byproducts, programs,
+/// and untouched copies of stock content all sit in compile directories without being part of the map.
+fn is_custom_content( file_path: &Path, comp_path: &str ) -> Result<bool, GesError>
+{
+    let category = file_categories::get_file_category( comp_path );
+
+    if category.is_disallowed() || category == FileCategory::Source
+    {
+        return Ok(false);
+    }
+
+    match stock_content::get_stock_file( comp_path )
+    {
+        Some(x) => Ok(manifest::hash_file( file_path )? != x.hash),
+        None => Ok(true),
+    }
+}
+
+/// Adds what importing the compile output needs before it runs to the preflight report.
+pub fn preflight( args: &Arguments, report: &mut PreflightReport )
+{
+    if args.from_compile != None
+    {
+        report.require( "Compile import", &args.rootdir, "write access to copy the compile output into the root directory", file_writer::can_create_in(&args.rootdir) );
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_import_compile_output()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("compile_import");
+
+        let _ = fs::remove_dir_all( &test_dir );
+
+        let compile_dir = test_dir.join("mapsrc");
+        fs::create_dir_all( compile_dir.join("materials/test") ).unwrap();
+        fs::create_dir_all( compile_dir.join("sound/test") ).unwrap();
+        fs::write( compile_dir.join("ge_test.vmf"), "versioninfo {}" ).unwrap();
+        fs::write( compile_dir.join("ge_test.prt"), "PRT1" ).unwrap();
+        fs::write( compile_dir.join("materials/test/wall.vmt"), "\"LightmappedGeneric\" {}" ).unwrap();
+        fs::write( compile_dir.join("sound/test/ambience.wav"), "RIFF" ).unwrap();
+
+        assert!( find_compiled_map( &compile_dir ).is_err() );
+
+        fs::write( compile_dir.join("ge_test.bsp"), "VBSP" ).unwrap();
+        assert_eq!( find_compiled_map( &compile_dir ).unwrap(), compile_dir.join("ge_test.bsp") );
+
+        let mut args = get_barebones_args();
+        args.from_compile = Some(compile_dir.clone());
+        args.rootdir = test_dir.join("gesource");
+
+        assert_eq!( import_compile_output( &args, "ge_test" ).unwrap(), 3 );
+        assert!( args.rootdir.join("maps/ge_test.bsp").is_file() );
+        assert!( args.rootdir.join("materials/test/wall.vmt").is_file() );
+        assert!( args.rootdir.join("sound/test/ambience.wav").is_file() );
+        assert!( !args.rootdir.join("ge_test.vmf").exists() );
+
+        // Two maps could be two different releases.
+        fs::create_dir_all( compile_dir.join("maps") ).unwrap();
+        fs::write( compile_dir.join("maps/ge_test_old.bsp"), "VBSP" ).unwrap();
+        assert!( find_compiled_map( &compile_dir ).is_err() );
+
+        fs::remove_dir_all( &test_dir ).unwrap();
+    }
+}
@@ -0,0 +1,424 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// -----------------------------------------------------------------------------------------------------------
+// consistency: Makes sure the scripts of a map release agree with each other, not just that each one is valid.
+// -----------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use argument_handler::Arguments;
+
+use std::path::PathBuf;
+
+use music_script_builder;
+use bsp_reader;
+use mdl_reader;
+use mdl_reader::ModelMaterials;
+use stock_content;
+use reslist_builder;
+use shared;
+use shared::DirectoryTree;
+use rules;
+use error::GesError;
+
+use regex::Regex;
+
+// The entity classes that name a material, and the key they name it with.  Worldspawn's is the detail sprite sheet.
+static ENTITY_MATERIAL_KEYS: &[(&'static str, &'static str)] = &[("worldspawn", "detailmaterial"), ("env_sprite", "model"), ("env_sprite_oriented", "model"),
+                                                                 ("env_glow", "model"), ("infodecal", "texture"), ("info_overlay", "material")];
+
+// The entity classes that name some other file by its path in the gesource directory, and the key they name it with.
+// Worldspawn's is the detail prop settings, which are detail.vbsp unless the map has its own.
+static ENTITY_FILE_KEYS: &[(&'static str, &'static str)] = &[("color_correction", "filename"), ("worldspawn", "detailvbsp")];
+
+/// Checks that every sound the map's music script and soundscape use from the release is in its reslist, along with
+/// every material the release's models use from it, and that the reslist doesn't ship any scripts GE:S won't load for
+/// the map.
+pub fn check_script_consistency( args: &Arguments, map_name: &str ) -> Result<(), GesError>
+{
+    let directory_tree = reslist_builder::generate_directory_tree( args )?;
+    check_script_consistency_with_tree( args, map_name, &directory_tree )
+}
+
+/// Same as check_script_consistency, but checks against the supplied directory tree instead of the process-wide cache.
+pub fn check_script_consistency_with_tree( args: &Arguments, map_name: &str, directory_tree: &DirectoryTree ) -> Result<(), GesError>
+{
+    let mut reslist_path = args.rootdir.clone();
+    reslist_path.push("maps");
+    reslist_path.push(map_name);
+    reslist_path.set_extension("res");
+
+    // Without a reslist there's nothing to compare against, and the reslist section will have already said why.
+    if !reslist_path.is_file()
+    {
+        return Ok(());
+    }
+
+    let reslist_entries: Vec<String> = reslist_builder::read_reslist_entries( &reslist_path )?.iter().map(|x| x.to_lowercase()).collect();
+    let &( ref file_comp_list, ref file_write_list) = directory_tree;
+
+    // Collect every contradiction so they can all be fixed in one go, instead of one per run.
+    let mut problems: Vec<(&str, String)> = Vec::new();
+
+    let mut music_script_path = args.rootdir.clone();
+    music_script_path.push("scripts");
+    music_script_path.push("music");
+    music_script_path.push( format!( "level_music_{}", map_name ) );
+    music_script_path.set_extension("txt");
+
+    if music_script_path.is_file()
+    {
+        for track in music_script_builder::read_music_tracks( &music_script_path )?
+        {
+            check_sound_is_reslisted( &track, "music script", file_comp_list, &reslist_entries, &mut problems );
+        }
+    }
+
+    let mut soundscape_path = args.rootdir.clone();
+    soundscape_path.push("scripts");
+    soundscape_path.push( format!( "soundscapes_{}", map_name ) );
+    soundscape_path.set_extension("txt");
+
+    if soundscape_path.is_file()
+    {
+        for wave in read_soundscape_waves( &soundscape_path )?
+        {
+            check_sound_is_reslisted( &wave, "soundscape", file_comp_list, &reslist_entries, &mut problems );
+        }
+    }
+
+    // A model whose materials clients don't have shows up as purple and black checkers for them.
+    for (comp_path, write_path) in file_comp_list.iter().zip( file_write_list.iter() )
+    {
+        if shared::get_string_file_extension( comp_path ) == "mdl"
+        {
+            let model_materials = mdl_reader::read_model_materials( &args.rootdir.join( write_path ) )?;
+            check_model_materials( args, comp_path, &model_materials, file_comp_list, &reslist_entries, &mut problems );
+        }
+    }
+
+    // Detail sprites, sprites, decals, color correction, and detail prop settings are only named in the map itself, so nothing else would notice them missing.
+    // A map that can't be read has already failed the map origin check.
+    match bsp_reader::read_entity_lump( &reslist_path.with_extension("bsp") )
+    {
+        Ok(x) =>
+        {
+            check_entity_files( args, &get_entity_materials( &x ), ("consistency-unreslisted-material", "consistency-missing-material"), file_comp_list, &reslist_entries, &mut problems );
+            check_entity_files( args, &get_entity_files( &x ), ("consistency-unreslisted-map-file", "consistency-missing-map-file"), file_comp_list, &reslist_entries, &mut problems );
+        },
+        Err(_) => {},
+    }
+
+    // GE:S only ever loads these scripts for a map, so anything else under scripts/ is dead weight for clients.
+    let expected_scripts = get_expected_script_names( map_name );
+
+    for entry in &reslist_entries
+    {
+        if entry.starts_with("scripts/") && shared::get_string_file_extension( entry ) == "txt" && !expected_scripts.contains(entry)
+        {
+            problems.push( ("consistency-unexpected-script", format!( "Reslist includes {}, which isn't a script GE:S loads for {}.  Expected scripts are named {}.",
+                                    entry, map_name, expected_scripts.join(", ") )) );
+        }
+    }
+
+    // Anything that isn't an error under the current rules is reported on its own, the rest are failed together.
+    let mut error_problems: Vec<String> = Vec::new();
+
+    for (rule_id, problem) in problems
+    {
+        // None of these are on any one line of the reslist, but it's where they'd be fixed, so it can ignore them.
+        match rules::report_in_file( args, rule_id, &reslist_path, 0, GesError::PolicyViolation( problem ) )
+        {
+            Ok(_) => {},
+            Err(e) => error_problems.push( e.to_string() ),
+        }
+    }
+
+    if !error_problems.is_empty()
+    {
+        let mut error_text = String::new();
+        error_text.push_str("The scripts for ");
+        error_text.push_str(map_name);
+        error_text.push_str(" don't agree with each other:\n");
+
+        for problem in error_problems
+        {
+            error_text.push_str("  ");
+            error_text.push_str(&problem);
+            error_text.push_str("\n");
+        }
+
+        return Err(GesError::PolicyViolation( error_text ));
+    }
+
+    Ok(())
+}
+
+/// Returns the lowercase relative path of every script GE:S will load for the given map.
+fn get_expected_script_names( map_name: &str ) -> Vec<String>
+{
+    let map_name = map_name.to_lowercase();
+
+    vec![format!( "scripts/maps/{}.txt", map_name ),
+         format!( "scripts/music/level_music_{}.txt", map_name ),
+         format!( "scripts/soundscapes_{}.txt", map_name )]
+}
+
+/// Adds a problem if the given sound, relative to the sound directory, ships with the release but isn't in its reslist.
+/// Sounds that aren't in the release are stock sounds, and clients already have those.
+fn check_sound_is_reslisted( sound_path: &str, script_name: &str, file_comp_list: &[String], reslist_entries: &[String], problems: &mut Vec<(&str, String)> )
+{
+    let mut resource_path = String::from("sound/");
+    resource_path.push_str( &sound_path.replace("\\", "/").to_lowercase() );
+
+    if file_comp_list.contains(&resource_path) && !reslist_entries.contains(&resource_path)
+    {
+        problems.push( ("consistency-unreslisted-sound", format!( "The {} uses {}, but it isn't in the reslist so clients won't download it.", script_name, resource_path )) );
+    }
+}
+
+/// Adds a problem for each material the given model uses that ships with the release but isn't in its reslist, and
+/// for each one that can't be found at all.  The engine uses the first search path a material is found in, so that's
+/// the one that has to be reslisted.
+fn check_model_materials( args: &Arguments, model_path: &str, model_materials: &ModelMaterials, file_comp_list: &[String], reslist_entries: &[String], problems: &mut Vec<(&str, String)> )
+{
+    for texture_name in &model_materials.texture_names
+    {
+        let candidate_paths = model_materials.get_candidate_paths( texture_name );
+
+        match candidate_paths.iter().find(|x| file_comp_list.contains(x))
+        {
+            Some(x) =>
+            {
+                if !reslist_entries.contains(x)
+                {
+                    problems.push( ("consistency-unreslisted-material", format!( "{} uses {}, but it isn't in the reslist so the model will be purple for clients.", model_path, x )) );
+                }
+            },
+            None =>
+            {
+                if can_find_stock( args ) && !candidate_paths.iter().any(|x| is_stock_file( args, x ))
+                {
+                    problems.push( ("consistency-missing-material", format!( "{} uses a material named {}, but it isn't in the release or GE:S under any of the model's material paths: {}.",
+                                            model_path, texture_name, candidate_paths.join(", ") )) );
+                }
+            },
+        }
+    }
+}
+
+/// Returns the lowercase path of every material the given map entities use directly, relative to the gesource
+/// directory.  Sprites can be given as the old .spr files, which the engine loads the material of the same name for.
+fn get_entity_materials( entities: &str ) -> Vec<String>
+{
+    let mut material_names = Vec::new();
+
+    for &(classname, key) in ENTITY_MATERIAL_KEYS
+    {
+        material_names.extend( bsp_reader::get_class_entity_values( entities, classname, key ) );
+    }
+
+    let mut material_paths: Vec<String> = material_names.iter()
+                                                        .map(|x| x.replace("\\", "/").to_lowercase())
+                                                        .filter(|x| !x.is_empty())
+                                                        .map(|x| format!( "materials/{}.vmt", x.trim_end_matches(".vmt").trim_end_matches(".spr") ))
+                                                        .collect();
+    material_paths.sort();
+    material_paths.dedup();
+
+    material_paths
+}
+
+/// Returns the lowercase path of every other file the given map entities use, relative to the gesource directory.
+fn get_entity_files( entities: &str ) -> Vec<String>
+{
+    let mut file_paths = Vec::new();
+
+    for &(classname, key) in ENTITY_FILE_KEYS
+    {
+        file_paths.extend( bsp_reader::get_class_entity_values( entities, classname, key ) );
+    }
+
+    let mut file_paths: Vec<String> = file_paths.iter()
+                                                .map(|x| x.replace("\\", "/").to_lowercase())
+                                                .filter(|x| !x.is_empty())
+                                                .collect();
+    file_paths.sort();
+    file_paths.dedup();
+
+    file_paths
+}
+
+/// Adds a problem under the first of the given rules for each of the given files that ships with the release but isn't
+/// in its reslist, and under the second for each one that can't be found at all.
+fn check_entity_files( args: &Arguments, file_paths: &[String], rule_ids: (&'static str, &'static str), file_comp_list: &[String], reslist_entries: &[String], problems: &mut Vec<(&str, String)> )
+{
+    for file_path in file_paths
+    {
+        if file_comp_list.contains(file_path)
+        {
+            if !reslist_entries.contains(file_path)
+            {
+                problems.push( (rule_ids.0, format!( "The map uses {}, but it isn't in the reslist so clients won't download it.", file_path )) );
+            }
+        }
+        else if can_find_stock( args ) && !is_stock_file( args, file_path )
+        {
+            problems.push( (rule_ids.1, format!( "The map uses {}, but it isn't in the release or GE:S.", file_path )) );
+        }
+    }
+}
+
+/// Returns true if there's an install or the stock content database to tell whether a file is stock.
+fn can_find_stock( args: &Arguments ) -> bool
+{
+    args.gesdir.is_dir() || stock_content::has_stock_files()
+}
+
+/// Returns true if the file at the given path, relative to the gesource directory, is stock content.
+fn is_stock_file( args: &Arguments, relative_path: &str ) -> bool
+{
+    args.gesdir.join( relative_path ).is_file() || stock_content::get_stock_file( relative_path ) != None
+}
+
+/// Returns every sound file the given soundscape plays, relative to the sound directory.
+fn read_soundscape_waves( soundscape_path: &PathBuf ) -> Result<Vec<String>, GesError>
+{
+    shared::set_current_file( soundscape_path );
+
+    let mut contents = String::new();
+    fs::File::open(soundscape_path)?.read_to_string(&mut contents)?;
+
+    lazy_static!
+    {
+        static ref WAVE_RE: Regex = Regex::new(r#"(?i)"wave"\s+"([^"]+)""#).unwrap();
+    }
+
+    // Sound paths can start with characters that tell the engine how to play them, which aren't part of the file name.
+    Ok(WAVE_RE.captures_iter(&contents)
+              .map(|x| String::from( x[1].trim_start_matches(|c| "*#@><^)(}$!?".contains(c)) ))
+              .collect())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_check_sound_is_reslisted()
+    {
+        let file_comp_list = vec![String::from("sound/music/custom_song1.mp3"), String::from("sound/ambient/hum.wav")];
+        let reslist_entries = vec![String::from("sound/music/custom_song1.mp3")];
+
+        let mut problems = Vec::new();
+        check_sound_is_reslisted( "music/custom_song1.mp3", "music script", &file_comp_list, &reslist_entries, &mut problems );
+        check_sound_is_reslisted( "music/base_song1.mp3", "music script", &file_comp_list, &reslist_entries, &mut problems );
+        assert!( problems.is_empty() );
+
+        check_sound_is_reslisted( "ambient\\Hum.wav", "soundscape", &file_comp_list, &reslist_entries, &mut problems );
+        assert_eq!( problems.len(), 1 );
+        assert!( problems[0].1.contains("sound/ambient/hum.wav") );
+    }
+
+    #[test]
+    fn test_check_model_materials()
+    {
+        let args = get_barebones_args();
+        let model_materials = ModelMaterials { texture_names: vec![String::from("Crate"), String::from("Lamp"), String::from("nothing_here")],
+                                               search_paths: vec![String::from("models/ge_test/"), String::from("models/props")] };
+
+        let file_comp_list = vec![String::from("materials/models/ge_test/crate.vmt"), String::from("materials/models/props/lamp.vmt")];
+        let reslist_entries = vec![String::from("materials/models/ge_test/crate.vmt")];
+
+        let mut problems = Vec::new();
+        check_model_materials( &args, "models/ge_test/crate.mdl", &model_materials, &file_comp_list, &reslist_entries, &mut problems );
+
+        // The test install doesn't have the last material either, so every search path for it is listed.
+        assert_eq!( problems.len(), 2 );
+        assert_eq!( problems[0].0, "consistency-unreslisted-material" );
+        assert!( problems[0].1.contains("materials/models/props/lamp.vmt") );
+        assert_eq!( problems[1].0, "consistency-missing-material" );
+        assert!( problems[1].1.contains("materials/models/ge_test/nothing_here.vmt, materials/models/props/nothing_here.vmt") );
+    }
+
+    #[test]
+    fn test_get_entity_materials()
+    {
+        let entities = "{\n\"classname\" \"worldspawn\"\n\"detailmaterial\" \"detail/ge_test_sprites\"\n}\n\
+                        {\n\"classname\" \"env_sprite\"\n\"model\" \"sprites\\Glow01.spr\"\n}\n\
+                        {\n\"classname\" \"infodecal\"\n\"texture\" \"decals/ge_test/logo\"\n}\n\
+                        {\n\"classname\" \"prop_static\"\n\"model\" \"models/ge_test/crate.mdl\"\n}\n";
+
+        assert_eq!( get_entity_materials( entities ), vec!["materials/decals/ge_test/logo.vmt", "materials/detail/ge_test_sprites.vmt", "materials/sprites/glow01.vmt"] );
+
+        let args = get_barebones_args();
+        let file_comp_list = vec![String::from("materials/decals/ge_test/logo.vmt")];
+
+        let mut problems = Vec::new();
+        check_entity_files( &args, &get_entity_materials( entities ), ("consistency-unreslisted-material", "consistency-missing-material"), &file_comp_list, &[], &mut problems );
+
+        // The test install has none of them, so the two that don't ship are missing.
+        assert_eq!( problems.iter().map(|x| x.0).collect::<Vec<&str>>(), vec!["consistency-unreslisted-material", "consistency-missing-material", "consistency-missing-material"] );
+    }
+
+    #[test]
+    fn test_get_entity_files()
+    {
+        let entities = "{\n\"classname\" \"worldspawn\"\n\"detailvbsp\" \"detail_ge_test.vbsp\"\n}\n\
+                        {\n\"classname\" \"color_correction\"\n\"filename\" \"materials\\correction\\GE_Test.raw\"\n}\n";
+
+        assert_eq!( get_entity_files( entities ), vec!["detail_ge_test.vbsp", "materials/correction/ge_test.raw"] );
+
+        let args = get_barebones_args();
+        let file_comp_list = vec![String::from("materials/correction/ge_test.raw")];
+        let reslist_entries = vec![String::from("materials/correction/ge_test.raw")];
+
+        let mut problems = Vec::new();
+        check_entity_files( &args, &get_entity_files( entities ), ("consistency-unreslisted-map-file", "consistency-missing-map-file"), &file_comp_list, &reslist_entries, &mut problems );
+
+        assert_eq!( problems.len(), 1 );
+        assert_eq!( problems[0].0, "consistency-missing-map-file" );
+        assert!( problems[0].1.contains("detail_ge_test.vbsp") );
+    }
+
+    #[test]
+    fn test_script_consistency()
+    {
+        let mut args = get_barebones_args();
+        args.rootdir = get_root_test_directory();
+        args.rootdir.push("temp");
+        args.rootdir.push("consistency");
+        args.rootdir.push("gesource");
+
+        let mut reslist_path = args.rootdir.clone();
+        reslist_path.push("maps");
+        fs::create_dir_all(&reslist_path).unwrap();
+        reslist_path.push("test_map.res");
+        fs::File::create(&reslist_path).unwrap().write_all(b"\"resources\"\r\n{\r\n\t\"scripts/maps/other_map.txt\"\t\"file\"\r\n}\r\n").unwrap();
+
+        let mut music_script_path = args.rootdir.clone();
+        music_script_path.push("scripts");
+        music_script_path.push("music");
+        fs::create_dir_all(&music_script_path).unwrap();
+        music_script_path.push("level_music_test_map.txt");
+        fs::File::create(&music_script_path).unwrap().write_all(b"\"music\"\r\n{\r\n\t\"file\"\t\"music/custom_song1.mp3\"\r\n}\r\n").unwrap();
+
+        let directory_tree = (vec![String::from("sound/music/custom_song1.mp3")], vec![String::from("sound/music/custom_song1.mp3")]);
+
+        // Both problems should be reported at once.
+        let error_text = check_script_consistency_with_tree( &args, "test_map", &directory_tree ).unwrap_err().to_string();
+        assert!( error_text.contains("sound/music/custom_song1.mp3") );
+        assert!( error_text.contains("scripts/maps/other_map.txt") );
+
+        fs::File::create(&reslist_path).unwrap().write_all(b"\"resources\"\r\n{\r\n\t\"sound/music/custom_song1.mp3\"\t\"file\"\r\n}\r\n").unwrap();
+        assert!( check_script_consistency_with_tree( &args, "test_map", &directory_tree ).is_ok() );
+
+        fs::remove_dir_all( args.rootdir.parent().unwrap() ).unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// -------------------------------------------------------------------------------------------------------------
+// crash_report: Bundles up everything needed to diagnose a crash or failure into a zip for a GitHub issue.
+// Nothing is ever sent anywhere, the user decides whether to attach the bundle.
+// -------------------------------------------------------------------------------------------------------------
+
+use argument_handler;
+use argument_handler::Arguments;
+
+use std::env;
+use std::fs;
+use std::io::prelude::*;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::panic;
+use std::backtrace::Backtrace;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zip::ZipWriter;
+use zip::write::FileOptions;
+
+use shared;
+use update_checker;
+use file_writer;
+use file_writer::FileWriter;
+use error::GesError;
+
+// Only the start of the offending file is included, since that's almost always enough to reproduce the problem.
+const EXCERPT_LINE_COUNT: usize = 200;
+
+// Arguments whose values let anyone holding them act as the user, so they never make it into a bundle meant to be
+// attached to a public issue.
+static SECRET_ARGUMENTS: &[&'static str] = &["discord-webhook"];
+const REDACTED_TEXT: &str = "<redacted>";
+
+lazy_static!
+{
+    static ref PANIC_REPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static ref ARGUMENTS: Mutex<Option<Arguments>> = Mutex::new(None);
+}
+
+/// Replaces the default panic message with one that records the details needed for a crash report.
+pub fn install_panic_hook()
+{
+    panic::set_hook( Box::new(|info|
+    {
+        let message = shared::get_panic_message( info.payload() );
+
+        let mut report = String::new();
+        report.push_str( &format!( "Thread: {}\n", thread::current().name().unwrap_or("unnamed") ) );
+        report.push_str( &format!( "Message: {}\n", message ) );
+
+        match info.location()
+        {
+            Some(x) => report.push_str( &format!( "Location: {}:{}\n", x.file(), x.line() ) ),
+            None => {},
+        }
+
+        match shared::get_current_file()
+        {
+            Some(x) => report.push_str( &format!( "Processing: {}\n", x.display() ) ),
+            None => {},
+        }
+
+        report.push_str( &format!( "Backtrace:\n{}\n", Backtrace::force_capture() ) );
+
+        // Worker threads turn their panics into section errors, so this just lets the user know where it happened.
+        println!( "[Error] The {} thread crashed with message: {}", thread::current().name().unwrap_or("unnamed"), message );
+
+        match PANIC_REPORTS.lock()
+        {
+            Ok(mut x) => x.push(report),
+            Err(_) => {},
+        }
+    }));
+}
+
+/// Remembers the arguments of this run so they can be included in a crash report.
+pub fn set_arguments( args: &Arguments )
+{
+    match ARGUMENTS.lock()
+    {
+        Ok(mut x) => *x = Some(args.clone()),
+        Err(_) => {},
+    }
+}
+
+/// Checks to see if any thread has panicked so far.
+pub fn has_panicked() -> bool
+{
+    match PANIC_REPORTS.lock()
+    {
+        Ok(x) => !x.is_empty(),
+        Err(_) => true, // The lock is only poisoned if something panicked while holding it.
+    }
+}
+
+/// Returns the arguments of this run, if they were parsed before things went wrong.
+pub fn get_arguments() -> Option<Arguments>
+{
+    match ARGUMENTS.lock()
+    {
+        Ok(x) => x.clone(),
+        Err(_) => None,
+    }
+}
+
+/// Lets the user write a crash report after a failed run.  It's written straight away if they asked for one with
+/// --crash-report, otherwise they're asked if there's someone at the console to answer.
+pub fn offer_crash_report( show_prompt: bool )
+{
+    if file_writer::is_read_only()
+    {
+        println!( "\nRun again without --check-only and with --crash-report to write a diagnostic bundle you can attach to a GitHub issue." );
+        return;
+    }
+
+    let write_report = match get_arguments()
+    {
+        Some(ref x) if x.crash_report => true,
+        _ if show_prompt =>
+        {
+            println!( "\nWould you like to write a diagnostic bundle you can attach to a GitHub issue?  Nothing is sent anywhere.  [y/N]" );
+
+            let pause_timeout = match get_arguments()
+            {
+                Some(ref x) => x.pause_timeout,
+                None => 0,
+            };
+
+            // Nobody answering counts as a no, same as just pressing Enter.
+            shared::read_line_with_timeout( pause_timeout ).is_some_and(|x| x.trim().to_lowercase().starts_with('y'))
+        },
+        _ =>
+        {
+            println!( "\nRun again with --crash-report to write a diagnostic bundle you can attach to a GitHub issue." );
+            false
+        },
+    };
+
+    if write_report
+    {
+        match write_crash_report()
+        {
+            Ok(x) => println!( "Wrote diagnostic bundle to {}", x.display() ),
+            Err(e) => println!( "[Error] Failed to write diagnostic bundle with error:\n{}", e ),
+        }
+    }
+}
+
+/// Writes a zip containing a description of this run and everything that went wrong with it, along with an excerpt of the
+/// file being processed when it happened.  Returns the path of the zip.
+pub fn write_crash_report() -> Result<PathBuf, GesError>
+{
+    let args = get_arguments();
+    let report_path = get_crash_report_path( &args );
+
+    let mut zip = ZipWriter::new( FileWriter::create(&report_path)? );
+    let options = FileOptions::default();
+
+    zip.start_file( "report.txt", options ).map_err(convert_zip_error)?;
+    zip.write_all( build_report_text( &args ).as_bytes() )?;
+
+    // Prefer the file a crashed thread was working on, since that's the one most likely to be the culprit.
+    let offending_file = match get_panicked_file()
+    {
+        Some(x) => Some(x),
+        None => shared::get_last_file(),
+    };
+
+    match offending_file
+    {
+        Some(ref x) if x.is_file() =>
+        {
+            let file_name = match x.file_name()
+            {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => String::from("unknown"),
+            };
+
+            zip.start_file( format!( "excerpt/{}", file_name ), options ).map_err(convert_zip_error)?;
+            zip.write_all( get_file_excerpt( x )?.as_bytes() )?;
+        },
+        _ => {},
+    }
+
+    zip.finish().map_err(convert_zip_error)?;
+
+    Ok(report_path)
+}
+
+/// Builds the human readable part of the crash report.
+fn build_report_text( args: &Option<Arguments> ) -> String
+{
+    let mut report = String::new();
+
+    report.push_str( &format!( "GE:S Map Script Utility {}\n", update_checker::CURRENT_VERSION ) );
+    report.push_str( &format!( "Platform: {} {}\n", env::consts::OS, env::consts::ARCH ) );
+
+    match env::current_dir()
+    {
+        Ok(x) => report.push_str( &format!( "Working directory: {}\n", x.display() ) ),
+        Err(_) => {},
+    }
+
+    report.push_str( &format!( "Commandline: {:?}\n", get_redacted_commandline( &env::args().collect::<Vec<String>>() ) ) );
+
+    // Only our own variables are included, the rest of the environment is none of our business.
+    for (name, value) in env::vars().filter(|x| x.0.starts_with("GESMRA_"))
+    {
+        let is_secret = SECRET_ARGUMENTS.iter().any(|x| argument_handler::get_environment_variable_name(x) == name);
+        report.push_str( &format!( "{}={}\n", name, if is_secret { REDACTED_TEXT } else { &value } ) );
+    }
+
+    match *args
+    {
+        Some(ref x) =>
+        {
+            let mut redacted_args = x.clone();
+            redacted_args.discord_webhook = redacted_args.discord_webhook.map(|_| String::from(REDACTED_TEXT));
+
+            report.push_str( &format!( "\nArguments:\n{:#?}\n", redacted_args ) );
+        },
+        None => report.push_str( "\nArguments: failed to parse\n" ),
+    }
+
+    report.push_str( &format!( "\nWarnings printed: {}\n", shared::get_warning_count() ) );
+
+    report.push_str( "\nErrors:\n" );
+
+    for error in shared::get_printed_errors()
+    {
+        report.push_str( &error );
+        report.push_str( "\n" );
+    }
+
+    match PANIC_REPORTS.lock()
+    {
+        Ok(x) =>
+        {
+            for panic_report in x.iter()
+            {
+                report.push_str( "\nCrash:\n" );
+                report.push_str( panic_report );
+            }
+        },
+        Err(_) => report.push_str( "\nCrash details were lost.\n" ),
+    }
+
+    report
+}
+
+/// Returns the given commandline with the value of every secret argument replaced, whether it was given as its own
+/// argument or after an equals sign.
+fn get_redacted_commandline( commandline: &[String] ) -> Vec<String>
+{
+    let mut redacted_commandline = Vec::new();
+    let mut is_secret_value = false;
+
+    for argument in commandline
+    {
+        if is_secret_value
+        {
+            redacted_commandline.push( String::from(REDACTED_TEXT) );
+            is_secret_value = false;
+            continue;
+        }
+
+        let secret_name = SECRET_ARGUMENTS.iter().find(|x| argument == &format!( "--{}", x ) || argument.starts_with( &format!( "--{}=", x ) ));
+
+        match secret_name
+        {
+            Some(x) if argument.contains('=') => redacted_commandline.push( format!( "--{}={}", x, REDACTED_TEXT ) ),
+            Some(_) => { redacted_commandline.push( argument.clone() ); is_secret_value = true; },
+            None => redacted_commandline.push( argument.clone() ),
+        }
+    }
+
+    redacted_commandline
+}
+
+/// Returns the file that was being processed by the first thread to crash, if any.
+fn get_panicked_file() -> Option<PathBuf>
+{
+    let panic_reports = match PANIC_REPORTS.lock()
+    {
+        Ok(x) => x,
+        Err(_) => return None,
+    };
+
+    for panic_report in panic_reports.iter()
+    {
+        for line in panic_report.lines()
+        {
+            match line.strip_prefix("Processing: ")
+            {
+                Some(x) => return Some( PathBuf::from(x) ),
+                None => {},
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns the first few lines of the given file, even if it isn't valid text.
+fn get_file_excerpt( file_path: &Path ) -> Result<String, GesError>
+{
+    let mut contents = Vec::new();
+    fs::File::open(file_path)?.read_to_end(&mut contents)?;
+
+    let contents = String::from_utf8_lossy( &contents );
+
+    let mut excerpt = String::new();
+
+    for line in contents.lines().take(EXCERPT_LINE_COUNT)
+    {
+        excerpt.push_str(line);
+        excerpt.push_str("\n");
+    }
+
+    Ok(excerpt)
+}
+
+/// Returns where the crash report should be written.  Next to the root directory if we can, since that's where the user
+/// is already looking, and never inside it so the report doesn't end up in the reslist.
+fn get_crash_report_path( args: &Option<Arguments> ) -> PathBuf
+{
+    let timestamp = match SystemTime::now().duration_since(UNIX_EPOCH)
+    {
+        Ok(x) => x.as_secs(),
+        Err(_) => 0,
+    };
+
+    let mut report_path = match *args
+    {
+        Some(ref x) if x.rootdir.parent() != None && x.rootdir.parent().unwrap().is_dir() => x.rootdir.parent().unwrap().to_path_buf(),
+        _ => env::current_dir().unwrap_or_default(),
+    };
+
+    report_path.push( format!( "ges_scriptutility_crash_report_{}.zip", timestamp ) );
+    report_path
+}
+
+/// Converts an error from the zip library into a standard error.
+fn convert_zip_error( error: ::zip::result::ZipError ) -> GesError
+{
+    GesError::Io(io::Error::new( io::ErrorKind::Other, error.to_string() ))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_crash_report_contents()
+    {
+        let mut args = get_barebones_args();
+        args.rootdir = get_root_test_directory();
+        args.rootdir.push("temp");
+        args.rootdir.push("gesource");
+
+        let report_text = build_report_text( &Some(args.clone()) );
+        assert!( report_text.contains( update_checker::CURRENT_VERSION ) );
+        assert!( report_text.contains( "baseweight: 700" ) );
+
+        // Anyone with the webhook can post to the channel, so it stays out of a report meant for a public issue.
+        args.discord_webhook = Some(String::from("https://discord.com/api/webhooks/1234/secret-token"));
+        let report_text = build_report_text( &Some(args.clone()) );
+        assert!( !report_text.contains("secret-token") );
+        assert!( report_text.contains( REDACTED_TEXT ) );
+
+        // The report belongs beside the root directory, not in it.
+        let report_path = get_crash_report_path( &Some(args.clone()) );
+        assert_eq!( report_path.parent().unwrap(), args.rootdir.parent().unwrap() );
+    }
+
+    #[test]
+    fn test_get_redacted_commandline()
+    {
+        let commandline: Vec<String> = ["ges_scriptutility", "fullcheck", "--discord-webhook", "https://discord.com/api/webhooks/1/a", "--discord-webhook=https://discord.com/api/webhooks/2/b", "-g", "gesource"]
+                                           .iter().map(|x| String::from(*x)).collect();
+
+        assert_eq!( get_redacted_commandline( &commandline ), vec!["ges_scriptutility", "fullcheck", "--discord-webhook", REDACTED_TEXT, "--discord-webhook=<redacted>", "-g", "gesource"] );
+    }
+}
@@ -0,0 +1,161 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// daemon: Keeps a record of each scheduled fullcheck of a server install, and speaks up when something new breaks.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::SystemTime;
+
+use argument_handler::Arguments;
+use file_writer;
+use generation_metadata;
+use rules::{Finding, Severity};
+use error::GesError;
+
+/// How long daemon mode waits between fullchecks if no interval is given, which is a day.
+pub const DEFAULT_INTERVAL: u64 = 24 * 60 * 60;
+
+/// Parses an interval like 24h, 30m, 90s or 2d into a number of seconds.  A bare number is in hours.
+pub fn parse_interval( interval: &str ) -> Option<u64>
+{
+    let interval = interval.trim().to_lowercase();
+
+    let (number, unit_seconds) = match interval.chars().last()
+    {
+        Some('d') => (&interval[..interval.len() - 1], 24 * 60 * 60),
+        Some('h') => (&interval[..interval.len() - 1], 60 * 60),
+        Some('m') => (&interval[..interval.len() - 1], 60),
+        Some('s') => (&interval[..interval.len() - 1], 1),
+        _ => (interval.as_str(), 60 * 60),
+    };
+
+    match number.parse::<u64>()
+    {
+        Ok(x) if x > 0 => x.checked_mul(unit_seconds),
+        _ => None,
+    }
+}
+
+/// Returns every failure in the given findings and printed errors, sorted so two runs can be compared.  Suppressed
+/// findings and warnings aren't failures, and neither are findings already part of a printed error.
+pub fn get_failures( findings: &[Finding], printed_errors: &[String] ) -> Vec<String>
+{
+    let mut failures: Vec<String> = findings.iter()
+                                            .filter(|x| x.severity == Severity::Error && !x.suppressed)
+                                            .filter(|x| !printed_errors.iter().any(|y| y.contains(&x.message)))
+                                            .map(|x| x.message.clone())
+                                            .chain( printed_errors.iter().cloned() )
+                                            .collect();
+    failures.sort();
+    failures.dedup();
+
+    failures
+}
+
+/// Writes a report of a fullcheck that started at the given time to the report directory, returning its path.
+pub fn write_report( args: &Arguments, start_time: SystemTime, error_code: i32, findings: &[Finding], printed_errors: &[String] ) -> Result<PathBuf, GesError>
+{
+    let timestamp = generation_metadata::format_utc_time( start_time );
+
+    let mut contents = String::new();
+    contents.push_str("Fullcheck of ");
+    contents.push_str( &args.gesdir.display().to_string() );
+    contents.push_str(" at ");
+    contents.push_str(&timestamp);
+    contents.push_str(&format!( " finished with exit code {:#06x}.\r\n", error_code ));
+
+    let failures = get_failures( findings, printed_errors );
+    let warnings: Vec<&Finding> = findings.iter().filter(|x| x.severity == Severity::Warning && !x.suppressed).collect();
+
+    contents.push_str(&format!( "\r\n{} failures:\r\n", failures.len() ));
+    for failure in &failures
+    {
+        contents.push_str( &failure.trim_end().replace("\n", "\r\n    ") );
+        contents.push_str("\r\n");
+    }
+
+    contents.push_str(&format!( "\r\n{} warnings:\r\n", warnings.len() ));
+    for warning in &warnings
+    {
+        contents.push_str( &warning.message.trim_end().replace("\n", "\r\n    ") );
+        contents.push_str("\r\n");
+    }
+
+    // Colons aren't allowed in Windows file names.
+    let mut report_path = args.report_dir.clone();
+    report_path.push( format!( "fullcheck_{}.txt", timestamp.replace(":", "-") ) );
+
+    file_writer::create_dir_all( &args.report_dir )?;
+    file_writer::write( &report_path, contents.as_bytes() )?;
+
+    Ok(report_path)
+}
+
+/// Runs the notification hook through the system shell, telling it where the report is and what newly broke
+/// through the GESMRA_REPORT, GESMRA_NEW_FAILURE_COUNT, and GESMRA_NEW_FAILURES environment variables.
+pub fn run_notify_hook( hook: &str, report_path: &PathBuf, new_failures: &[String] ) -> Result<(), GesError>
+{
+    let mut command = if cfg!(windows) { Command::new("cmd") } else { Command::new("sh") };
+    command.arg( if cfg!(windows) { "/C" } else { "-c" } ).arg(hook);
+
+    let status = command.env( "GESMRA_REPORT", report_path )
+                        .env( "GESMRA_NEW_FAILURE_COUNT", new_failures.len().to_string() )
+                        .env( "GESMRA_NEW_FAILURES", new_failures.join("\n") )
+                        .status()?;
+
+    if !status.success()
+    {
+        return Err(GesError::Config( format!( "Notification hook exited with {}!", status ) ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_parse_interval()
+    {
+        assert_eq!( parse_interval( "24h" ), Some(86400) );
+        assert_eq!( parse_interval( "30M" ), Some(1800) );
+        assert_eq!( parse_interval( "2d" ), Some(172_800) );
+        assert_eq!( parse_interval( "90s" ), Some(90) );
+        assert_eq!( parse_interval( "6" ), Some(21600) );
+        assert_eq!( parse_interval( "0h" ), None );
+        assert_eq!( parse_interval( "daily" ), None );
+    }
+
+    #[test]
+    fn test_write_report()
+    {
+        let mut args = get_barebones_args();
+        args.report_dir = get_root_test_directory();
+        args.report_dir.push("temp");
+        args.report_dir.push("daemon_reports");
+
+        let finding = |severity, message: &str| Finding { rule_id: "reslist-missing", severity, message: String::from(message), file: None, line: 0, suppressed: false };
+        let findings = vec![finding( Severity::Error, "Broken reslist" ), finding( Severity::Warning, "Missing reslist" ), finding( Severity::Error, "Broken reslist" )];
+        let printed_errors = vec![String::from("Failed reslist section")];
+
+        assert_eq!( get_failures( &findings, &printed_errors ), vec![String::from("Broken reslist"), String::from("Failed reslist section")] );
+        assert_eq!( get_failures( &findings, &[String::from("Failed with Broken reslist [reslist-syntax]")] ).len(), 1 );
+
+        let report_path = write_report( &args, SystemTime::UNIX_EPOCH, 0x0008, &findings, &printed_errors ).unwrap();
+        assert_eq!( report_path.file_name().unwrap(), "fullcheck_1970-01-01T00-00-00Z.txt" );
+
+        let contents = fs::read_to_string(&report_path).unwrap();
+        assert!( contents.contains("exit code 0x0008") && contents.contains("2 failures:") && contents.contains("1 warnings:") );
+
+        fs::remove_dir_all(&args.report_dir).unwrap();
+    }
+}
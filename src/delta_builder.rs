@@ -0,0 +1,321 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ----------------------------------------------------------------------------------------------------------
+// delta_builder: Builds update packages holding only what changed since a previous release's manifest.
+// ----------------------------------------------------------------------------------------------------------
+
+use std::io::prelude::*;
+
+use std::path::{Path, PathBuf};
+
+use argument_handler::Arguments;
+use preflight::PreflightReport;
+use manifest;
+use release_signing;
+use shared;
+use file_writer;
+use file_writer::FileWriter;
+use error::GesError;
+
+// The deletion list sits next to the manifest, as maps/[mapname].delete.
+pub static DELETION_LIST_EXTENSION: &str = "delete";
+
+/// Adds what the delta package needs before compression runs to the preflight report.
+pub fn preflight( args: &Arguments, _map_name: &str, report: &mut PreflightReport )
+{
+    match args.delta_from
+    {
+        Some(ref x) => report.require( "Delta", x, "the manifest of the previous release", x.is_file() ),
+        None => return,
+    }
+
+    match get_delta_directory( args )
+    {
+        Ok(x) =>
+        {
+            report.require( "Delta", &x, "a directory for the delta package rather than a file", !x.is_file() );
+            report.require( "Delta", &x, "write access to place the delta package into", file_writer::can_create_in(&x) );
+        },
+        Err(_) => report.require( "Delta", &args.rootdir, "a parent directory to place the delta package into", false ),
+    }
+}
+
+/// Copies every compressed file that changed or was added since the previous release's manifest into the delta directory,
+/// along with the new manifest and a list of files the previous release had that this one doesn't.
+pub fn construct_delta_package( args: &Arguments, map_name: &str, compressed_dir: &PathBuf, manifest_path: &PathBuf ) -> Result<(), GesError>
+{
+    let previous_manifest_path = match args.delta_from
+    {
+        Some(ref x) => x.clone(),
+        None => return Err(GesError::Config( String::from("No previous manifest supplied to build a delta package from!") )),
+    };
+
+    let delta_dir = get_delta_directory( args )?;
+
+    let previous_manifest = manifest::read_manifest( &previous_manifest_path ).map_err(|e| e.in_file( &previous_manifest_path ))?;
+    let current_manifest = manifest::read_manifest( manifest_path )?;
+
+    // Leftovers from an earlier delta would end up being uploaded as if they changed, so start from nothing.
+    if delta_dir.is_dir()
+    {
+        remove_delta_files( &delta_dir, map_name, &current_manifest )?;
+    }
+
+    let (changed_files, deleted_files) = compare_manifests( &previous_manifest, &current_manifest );
+
+    for relative_name in &changed_files
+    {
+        let compressed_name = format!( "{}.bz2", relative_name );
+
+        let mut source_path = compressed_dir.clone();
+        source_path.push(&compressed_name);
+
+        let mut delta_path = delta_dir.clone();
+        delta_path.push(&compressed_name);
+
+        copy_file( &source_path, &delta_path )?;
+
+        if args.verbose
+        {
+            println!( "Added {} to the delta package.", compressed_name );
+        }
+    }
+
+    // Servers need the new manifest to verify the result, and its signature if it has one.
+    let relative_manifest_path = manifest::get_relative_manifest_path( map_name );
+
+    let mut delta_manifest_path = delta_dir.clone();
+    delta_manifest_path.push(&relative_manifest_path);
+    copy_file( manifest_path, &delta_manifest_path )?;
+
+    let signature_path = release_signing::get_signature_path( manifest_path );
+
+    if signature_path.is_file()
+    {
+        copy_file( &signature_path, &release_signing::get_signature_path( &delta_manifest_path ) )?;
+    }
+
+    let mut deletion_list_path = delta_dir.clone();
+    deletion_list_path.push(&relative_manifest_path);
+    deletion_list_path.set_extension(DELETION_LIST_EXTENSION);
+
+    write_deletion_list( &deletion_list_path, &deleted_files )?;
+
+    println!( "{} is ready for upload, with {} changed files and {} to delete.", delta_dir.display(), changed_files.len(), deleted_files.len() );
+
+    Ok(())
+}
+
+/// Removes every file a delta package for the given map could have put in delta_dir: the compressed copy of each file in
+/// the current manifest or the manifest of the delta already there, along with that delta's manifest, signature, and
+/// deletion list.  The delta directory may be shared with other maps, so nothing else is touched.
+fn remove_delta_files( delta_dir: &PathBuf, map_name: &str, current_manifest: &manifest::Manifest ) -> Result<(), GesError>
+{
+    let delta_manifest_path = delta_dir.join( manifest::get_relative_manifest_path( map_name ) );
+
+    let mut relative_names: Vec<String> = current_manifest.entries.iter().map(|x| x.0.clone()).collect();
+
+    if delta_manifest_path.is_file()
+    {
+        relative_names.extend( manifest::read_manifest( &delta_manifest_path )?.entries.into_iter().map(|x| x.0) );
+    }
+
+    let mut delta_files: Vec<PathBuf> = relative_names.iter()
+                                                      .filter(|x| manifest::is_contained_entry(x))
+                                                      .map(|x| delta_dir.join( format!( "{}.bz2", x ) ))
+                                                      .collect();
+
+    let mut deletion_list_path = delta_manifest_path.clone();
+    deletion_list_path.set_extension(DELETION_LIST_EXTENSION);
+
+    delta_files.push( release_signing::get_signature_path( &delta_manifest_path ) );
+    delta_files.push( deletion_list_path );
+    delta_files.push( delta_manifest_path );
+
+    for delta_file in &delta_files
+    {
+        if delta_file.is_file()
+        {
+            file_writer::remove_file(delta_file)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compares the entries of two manifests, returning the paths that are new or changed in the current one and the paths
+/// that are only in the previous one.  Paths are compared without case, since that's how GE:S finds files on Windows.
+/// Hashes made with different algorithms can't be compared, so then every file counts as changed.
+fn compare_manifests( previous_manifest: &manifest::Manifest, current_manifest: &manifest::Manifest ) -> (Vec<String>, Vec<String>)
+{
+    let (previous_entries, current_entries) = (&previous_manifest.entries, &current_manifest.entries);
+    let same_algorithm = previous_manifest.algorithm == current_manifest.algorithm;
+
+    if !same_algorithm
+    {
+        shared::print_warning( &format!( "The previous manifest was hashed with {} rather than {}, so every file is treated as changed.",
+                                         previous_manifest.algorithm, current_manifest.algorithm ) );
+    }
+
+    let changed_files = current_entries.iter()
+                                       .filter(|&&(ref path, ref hash)| !same_algorithm || !previous_entries.iter().any(|x| x.0.to_lowercase() == path.to_lowercase() && x.1 == *hash))
+                                       .map(|x| x.0.clone())
+                                       .collect();
+
+    let deleted_files = previous_entries.iter()
+                                        .filter(|&&(ref path, _)| !current_entries.iter().any(|x| x.0.to_lowercase() == path.to_lowercase()))
+                                        .map(|x| x.0.clone())
+                                        .collect();
+
+    (changed_files, deleted_files)
+}
+
+/// Writes the list of files to delete using the same bracketed format as the other script files:
+/// "[path/to/file]"
+fn write_deletion_list( deletion_list_path: &PathBuf, deleted_files: &[String] ) -> Result<(), GesError>
+{
+    let mut contents = String::new();
+    contents.push_str("\"delete\"\r\n");
+    contents.push_str("{\r\n");
+
+    for deleted_file in deleted_files
+    {
+        contents.push_str("\t\""); contents.push_str(deleted_file); contents.push_str("\"\r\n");
+    }
+
+    contents.push_str("}\r\n");
+
+    let mut deletion_list_file = FileWriter::create(deletion_list_path)?;
+    deletion_list_file.write_all(contents.as_bytes())?;
+
+    Ok(())
+}
+
+/// Copies the file at source_path to destination_path, creating any directories it needs.
+fn copy_file( source_path: &Path, destination_path: &Path ) -> Result<(), GesError>
+{
+    match destination_path.parent()
+    {
+        Some(x) => file_writer::create_dir_all(x)?,
+        None => {},
+    }
+
+    file_writer::copy( source_path, destination_path ).map_err(|e| e.in_file( source_path ))?;
+
+    Ok(())
+}
+
+/// Returns the directory the delta package should be placed in.  This is the user supplied delta directory
+/// if there is one, and a gesource_delta directory adjacent to the root directory otherwise.
+fn get_delta_directory( args: &Arguments ) -> Result<PathBuf, GesError>
+{
+    match args.delta_out
+    {
+        Some(ref x) => return Ok(x.clone()),
+        None => {},
+    }
+
+    if args.rootdir.parent() == None
+    {
+        return Err(GesError::Config( String::from("The root gesource directory must have valid parent for the delta package to be placed into.") ));
+    }
+
+    let mut delta_root_pathbuf = args.rootdir.parent().unwrap().to_path_buf();
+    delta_root_pathbuf.push("gesource_delta");
+    delta_root_pathbuf.push("gesource");
+
+    Ok(delta_root_pathbuf)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use shared::get_barebones_args;
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_compare_manifests()
+    {
+        let previous_entries = vec![(String::from("maps/ge_foo_b2.bsp"), String::from("aa")),
+                                    (String::from("materials/foo/wall.vtf"), String::from("bb")),
+                                    (String::from("sound/music/Theme.mp3"), String::from("cc"))];
+
+        let current_entries = vec![(String::from("maps/ge_foo_b3.bsp"), String::from("dd")),
+                                   (String::from("materials/foo/wall.vtf"), String::from("ee")),
+                                   (String::from("sound/music/theme.mp3"), String::from("cc"))];
+
+        let previous_manifest = manifest::Manifest { algorithm: manifest::HashAlgorithm::Blake3, entries: previous_entries };
+        let mut current_manifest = manifest::Manifest { algorithm: manifest::HashAlgorithm::Blake3, entries: current_entries };
+
+        let (changed_files, deleted_files) = compare_manifests( &previous_manifest, &current_manifest );
+
+        assert_eq!( changed_files, vec!["maps/ge_foo_b3.bsp", "materials/foo/wall.vtf"] );
+        assert_eq!( deleted_files, vec!["maps/ge_foo_b2.bsp"] );
+
+        // Hashes made another way can't be compared, so nothing is left out.
+        current_manifest.algorithm = manifest::HashAlgorithm::Sha256;
+        assert_eq!( compare_manifests( &previous_manifest, &current_manifest ).0.len(), 3 );
+    }
+
+    #[test]
+    fn test_construct_delta_package()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("delta");
+
+        let mut compressed_dir = test_dir.clone();
+        compressed_dir.push("compressed");
+
+        let mut args = get_barebones_args();
+        args.delta_out = Some(test_dir.join("delta"));
+        args.hash_algorithm = manifest::HashAlgorithm::Sha256; // The previous manifest is from before blake3 was the default.
+
+        // Pretend the previous release had an older map and the same script.
+        let mut map_path = args.rootdir.clone();
+        map_path.push("maps/test_map.bsp");
+
+        let mut script_path = args.rootdir.clone();
+        script_path.push("scripts/maps/test_map.txt");
+
+        let mut previous_manifest_path = test_dir.clone();
+        previous_manifest_path.push("previous.manifest");
+
+        fs::create_dir_all(&test_dir).unwrap();
+        fs::File::create(&previous_manifest_path).unwrap().write_all( format!( "\"manifest\"\r\n{{\r\n\t\"maps/test_map_b1.bsp\"\t\"00\"\r\n\t\"scripts/maps/test_map.txt\"\t\"{}\"\r\n}}\r\n",
+                                                                                manifest::hash_file(&script_path).unwrap() ).as_bytes() ).unwrap();
+        args.delta_from = Some(previous_manifest_path);
+
+        let mut manifest_path = compressed_dir.clone();
+        manifest_path.push( manifest::get_relative_manifest_path("test_map") );
+        manifest::write_manifest( &args, &manifest_path, &args.rootdir, &[PathBuf::from("maps/test_map.bsp"), PathBuf::from("scripts/maps/test_map.txt")] ).unwrap();
+
+        // The contents don't matter, only that the right files get copied.
+        copy_file( &map_path, &compressed_dir.join("maps/test_map.bsp.bz2") ).unwrap();
+        copy_file( &script_path, &compressed_dir.join("scripts/maps/test_map.txt.bz2") ).unwrap();
+
+        // A delta from an earlier run has to go, but another map's files in the same directory have to stay.
+        let delta_dir = test_dir.join("delta");
+        copy_file( &manifest_path, &delta_dir.join("maps/test_map.manifest") ).unwrap();
+        copy_file( &script_path, &delta_dir.join("scripts/maps/test_map.txt.bz2") ).unwrap();
+        copy_file( &script_path, &delta_dir.join("maps/other_map.bsp.bz2") ).unwrap();
+
+        construct_delta_package( &args, "test_map", &compressed_dir, &manifest_path ).unwrap();
+
+        assert!( delta_dir.join("maps/test_map.bsp.bz2").is_file() );
+        assert!( delta_dir.join("maps/other_map.bsp.bz2").is_file() );
+        assert!( !delta_dir.join("scripts/maps/test_map.txt.bz2").exists() );
+        assert!( delta_dir.join("maps/test_map.manifest").is_file() );
+
+        let deletion_list = fs::read_to_string( delta_dir.join("maps/test_map.delete") ).unwrap();
+        assert!( deletion_list.contains("\"maps/test_map_b1.bsp\"") );
+        assert!( !deletion_list.contains("test_map.txt") );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
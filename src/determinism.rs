@@ -0,0 +1,241 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// determinism: Makes sure generating a release twice from the same inputs gives exactly the same files.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use walkdir::WalkDir;
+
+use argument_handler::Arguments;
+use pipeline;
+use pipeline::RunReport;
+use generation_metadata;
+use generation_metadata::EditState;
+use manifest::HashAlgorithm;
+use shared;
+use file_writer;
+use error::GesError;
+
+// How many times the release is generated.  Two is enough to catch anything that depends on when or where it ran.
+const RUN_COUNT: usize = 2;
+
+/// Generates the release into temporary copies of it and compares every file the runs end up with.  Returns how it went,
+/// failing with the utility error code if the runs gave different files.
+pub fn run_determinism_check( args: Arguments, map_name: String ) -> RunReport
+{
+    match check_determinism( &args, &map_name )
+    {
+        Ok(x) => x,
+        Err(e) => { shared::print_error( &format!( "Failed determinism check with error:\n{}\n", e ) ); RunReport::new( 0x0016 ) },
+    }
+}
+
+fn check_determinism( args: &Arguments, map_name: &str ) -> Result<RunReport, GesError>
+{
+    // The time generated files are stamped with is as much an input as the release itself, so every run gets the same one.
+    generation_metadata::pin_generation_time( generation_metadata::get_generation_time() );
+
+    let verify_dir = env::temp_dir().join( format!( "ges_scriptutility_deterministic_{}", process::id() ) );
+    let mut run_dirs = Vec::new();
+
+    for run_index in 0..RUN_COUNT
+    {
+        let run_dir = verify_dir.join( format!( "run{}", run_index + 1 ) );
+        let run_args = get_run_args( args, &run_dir );
+
+        copy_release( &args.rootdir, &run_args.rootdir )?;
+
+        println!( "Generating the release in {} ({} of {}).", run_dir.display(), run_index + 1, RUN_COUNT );
+
+        let report = pipeline::run_map_pipeline( run_args, String::from(map_name) );
+
+        if report.error_code != 0x0000
+        {
+            shared::print_error( "The release failed to generate, so it can't be checked for determinism!  Fix the problems above first.\n" );
+            file_writer::remove_dir_all( &verify_dir )?;
+
+            return Ok(report);
+        }
+
+        run_dirs.push(run_dir);
+    }
+
+    let differences = get_tree_differences( &run_dirs[0], &run_dirs[1] )?;
+
+    if differences.is_empty()
+    {
+        println!( "Generating the release {} times gave exactly the same files.", RUN_COUNT );
+        file_writer::remove_dir_all( &verify_dir )?;
+
+        return Ok(RunReport::new( 0x0000 ));
+    }
+
+    let mut error_text = String::new();
+    error_text.push_str("Generating the release twice gave different files!  The same inputs should always give the same release, so please report this:\n");
+
+    for difference in &differences
+    {
+        error_text.push_str("\t"); error_text.push_str(difference); error_text.push_str("\n");
+    }
+
+    error_text.push_str("Both runs are kept in ");
+    error_text.push_str( &verify_dir.display().to_string() );
+    error_text.push_str(" to compare.\n");
+
+    shared::print_error( &error_text );
+
+    Ok(RunReport::new( 0x0016 ))
+}
+
+/// Returns the arguments for generating the release in the given run directory.  Everything a run writes has to end up
+/// in its own directory, or the runs would write over each other.
+fn get_run_args( args: &Arguments, run_dir: &Path ) -> Arguments
+{
+    let get_run_path = |x: &PathBuf| run_dir.join( x.file_name().unwrap_or( x.as_os_str() ) );
+
+    let mut run_args = args.clone();
+    run_args.rootdir = run_dir.join("gesource");
+    run_args.compressed_out = None;
+    run_args.delta_out = None;
+    run_args.torrent = args.torrent.as_ref().map(&get_run_path);
+    run_args.changelog = args.changelog.as_ref().map(&get_run_path);
+
+    // Loading the map on a server doesn't change anything that's generated, and would only take twice as long.
+    run_args.smoke_test = None;
+
+    run_args
+}
+
+/// Copies the release in the given root directory to the given destination.  Scripts this program generated and nobody
+/// has edited since are left out, so they're generated again rather than just checked.
+fn copy_release( rootdir: &Path, destination_dir: &Path ) -> Result<(), GesError>
+{
+    for entry in WalkDir::new( rootdir ).sort_by_file_name()
+    {
+        let entry = entry?;
+
+        let destination_path = match entry.path().strip_prefix(rootdir)
+        {
+            Ok(x) => destination_dir.join(x),
+            Err(_) => continue,
+        };
+
+        if entry.file_type().is_dir()
+        {
+            file_writer::create_dir_all( &destination_path )?;
+        }
+        else if !is_regenerated( entry.path() )
+        {
+            file_writer::copy( entry.path(), &destination_path )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns true if the given file is a script this program generated that hasn't been edited since.
+fn is_regenerated( file_path: &Path ) -> bool
+{
+    let extension = shared::get_file_extension( file_path ).to_lowercase();
+
+    if extension != "txt" && extension != "res"
+    {
+        return false;
+    }
+
+    let edit_state = fs::read_to_string(file_path).ok().and_then(|x| generation_metadata::get_edit_state( &x ).ok());
+
+    matches!( edit_state, Some(EditState::Unedited(_)) )
+}
+
+/// Compares every file under the two directories, returning a description of each one that's missing from either of
+/// them or has different contents.
+fn get_tree_differences( first_dir: &Path, second_dir: &Path ) -> Result<Vec<String>, GesError>
+{
+    let first_hashes = get_file_hashes( first_dir )?;
+    let second_hashes = get_file_hashes( second_dir )?;
+
+    let relative_paths: BTreeSet<&String> = first_hashes.keys().chain(second_hashes.keys()).collect();
+    let mut differences = Vec::new();
+
+    for relative_path in relative_paths
+    {
+        match (first_hashes.get(relative_path), second_hashes.get(relative_path))
+        {
+            (Some(_), None) => differences.push( format!( "{} only came out of the first run", relative_path ) ),
+            (None, Some(_)) => differences.push( format!( "{} only came out of the second run", relative_path ) ),
+            (Some(x), Some(y)) if x != y => differences.push( format!( "{} came out different", relative_path ) ),
+            _ => {},
+        }
+    }
+
+    Ok(differences)
+}
+
+/// Returns the hash of every file under the given directory by its relative path.
+fn get_file_hashes( dir: &Path ) -> Result<BTreeMap<String, String>, GesError>
+{
+    let mut file_hashes = BTreeMap::new();
+
+    for entry in WalkDir::new( dir )
+    {
+        let entry = entry?;
+
+        if !entry.file_type().is_file()
+        {
+            continue;
+        }
+
+        match entry.path().strip_prefix(dir)
+        {
+            Ok(x) => { file_hashes.insert( x.to_string_lossy().replace("\\", "/"), HashAlgorithm::Blake3.hash_file( entry.path() )? ); },
+            Err(_) => {},
+        }
+    }
+
+    Ok(file_hashes)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_get_tree_differences()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("tree_differences");
+
+        let (first_dir, second_dir) = (test_dir.join("run1"), test_dir.join("run2"));
+
+        for &(dir, contents) in &[(&first_dir, "BaseWeight\t500\r\n"), (&second_dir, "BaseWeight\t700\r\n")]
+        {
+            fs::create_dir_all( dir.join("scripts").join("maps") ).unwrap();
+            fs::write( dir.join("scripts").join("maps").join("ge_test.txt"), contents ).unwrap();
+            fs::write( dir.join("gesrelease.lock"), "version = \"1.0.2\"\n" ).unwrap();
+        }
+
+        fs::write( first_dir.join("extra.res"), "" ).unwrap();
+
+        assert_eq!( get_tree_differences( &first_dir, &second_dir ).unwrap(), vec![String::from("extra.res only came out of the first run"),
+                                                                                   String::from("scripts/maps/ge_test.txt came out different")] );
+        assert!( get_tree_differences( &first_dir, &first_dir ).unwrap().is_empty() );
+
+        // Only generated scripts nobody touched are left to be generated again.
+        assert!( !is_regenerated( &first_dir.join("scripts").join("maps").join("ge_test.txt") ) );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
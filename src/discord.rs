@@ -0,0 +1,129 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// discord: Posts how a run went to a Discord webhook, since that's where GE:S teams coordinate their releases.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::io;
+use std::time::Duration;
+
+use serde_json::Value;
+use ureq;
+
+use argument_handler::Arguments;
+use daemon;
+use update_checker;
+use rules::Finding;
+use error::GesError;
+
+// How many failures are listed in the summary before the rest are left to the console.
+const TOP_FAILURE_COUNT: usize = 5;
+
+// Discord refuses embeds with a longer description than this.
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+
+// Embed colors for each outcome, as Discord expects them: a single RGB number.
+const PASSED_COLOR: u32 = 0x2ECC71;
+const WARNED_COLOR: u32 = 0xF1C40F;
+const FAILED_COLOR: u32 = 0xE74C3C;
+
+/// Builds a webhook message summarizing a run that ended with the given exit code and printed the given number of
+/// warnings: whether it passed, how many problems it found, and the first few failures.
+pub fn build_summary( args: &Arguments, exit_code: i32, findings: &[Finding], printed_errors: &[String], warning_count: usize ) -> Value
+{
+    let (outcome, color) = match exit_code
+    {
+        0x0000 => ("passed", PASSED_COLOR),
+        0x0080 => ("passed with warnings", WARNED_COLOR),
+        _ => ("failed", FAILED_COLOR),
+    };
+
+    let checked_dir = if args.fullcheck { &args.gesdir } else { &args.rootdir };
+    let title = format!( "{} of {} {}", if args.fullcheck { "Fullcheck" } else { "Release check" }, checked_dir.display(), outcome );
+
+    let failures = daemon::get_failures( findings, printed_errors );
+    let suppressed_count = findings.iter().filter(|x| x.suppressed).count();
+
+    let mut description = String::new();
+    for failure in failures.iter().take( TOP_FAILURE_COUNT )
+    {
+        // Each failure gets one line, which is plenty to tell what broke.
+        let first_line = failure.lines().map(|x| x.trim()).find(|x| !x.is_empty() && !x.starts_with("Failed ")).unwrap_or( failure.trim() );
+
+        description.push_str("• ");
+        description.push_str( &first_line.chars().take(300).collect::<String>() );
+        description.push('\n');
+    }
+
+    if failures.len() > TOP_FAILURE_COUNT
+    {
+        description.push_str(&format!( "...and {} more.", failures.len() - TOP_FAILURE_COUNT ));
+    }
+
+    json!({
+        "username": "GE:S Map Release Assistant",
+        "embeds": [{
+            "title": title,
+            "description": description.chars().take( MAX_DESCRIPTION_LENGTH ).collect::<String>(),
+            "color": color,
+            "fields": [
+                { "name": "Failures", "value": failures.len().to_string(), "inline": true },
+                { "name": "Warnings", "value": warning_count.to_string(), "inline": true },
+                { "name": "Suppressed", "value": suppressed_count.to_string(), "inline": true },
+                { "name": "Exit code", "value": format!( "{:#06x}", exit_code ), "inline": true },
+            ],
+            "footer": { "text": format!( "ges_scriptutility {}", update_checker::CURRENT_VERSION ) },
+        }],
+    })
+}
+
+/// Posts the given message to the Discord webhook at the given URL.
+pub fn post_message( webhook_url: &str, message: &Value ) -> Result<(), GesError>
+{
+    // A slow or unreachable Discord shouldn't hold up the end of the run for long.
+    let agent = ureq::AgentBuilder::new().timeout( Duration::from_secs(10) ).build();
+
+    match agent.post( webhook_url )
+               .set( "User-Agent", &format!( "ges_scriptutility/{}", update_checker::CURRENT_VERSION ) )
+               .set( "Content-Type", "application/json" )
+               .send_string( &message.to_string() )
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(GesError::Io(io::Error::new( io::ErrorKind::Other, format!( "Failed to post the summary to Discord: {}", e ) ))),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use rules::Severity;
+    use super::*;
+
+    #[test]
+    fn test_build_summary()
+    {
+        let args = get_barebones_args();
+
+        let finding = |severity, message: &str| Finding { rule_id: "reslist-missing", severity, message: String::from(message), file: None, line: 0, suppressed: false };
+        let findings = vec![finding( Severity::Warning, "Missing reslist" )];
+
+        let summary = build_summary( &args, 0x0080, &findings, &[], 1 );
+        assert!( summary["embeds"][0]["title"].as_str().unwrap().ends_with("passed with warnings") );
+        assert_eq!( summary["embeds"][0]["color"], WARNED_COLOR );
+        assert_eq!( summary["embeds"][0]["fields"][1]["value"], "1" );
+
+        // Only the first few failures are listed, each by the line that says what went wrong.
+        let printed_errors: Vec<String> = (0..7).map(|x| format!( "Failed reslist section with error:\n    Broken reslist {}\n", x )).collect();
+        let summary = build_summary( &args, 0x0008, &findings, &printed_errors, 1 );
+        let description = summary["embeds"][0]["description"].as_str().unwrap();
+
+        assert!( description.starts_with("• Broken reslist 0\n") );
+        assert!( description.ends_with("...and 2 more.") );
+        assert_eq!( summary["embeds"][0]["fields"][0]["value"], "7" );
+        assert_eq!( summary["embeds"][0]["color"], FAILED_COLOR );
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ---------------------------------------------------------------------------------------------------------
+// error: The error type shared by every part of the program, sorted into categories by what went wrong.
+// ---------------------------------------------------------------------------------------------------------
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use walkdir;
+
+/// Everything that can go wrong while checking or building a map release.
+#[derive(Debug)]
+pub enum GesError
+{
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// A script or data file isn't in the format the game expects.  Line is 0 when the problem isn't on any one line.
+    Parse { file: PathBuf, line: usize, message: String },
+    /// A file or directory that should exist doesn't.
+    MissingFile { path: PathBuf, message: String },
+    /// Everything can be read just fine, but breaks one of the rules for a map release.
+    PolicyViolation(String),
+    /// The arguments or directories the program was run with don't make sense.
+    Config(String),
+    /// Part of the program crashed.  File is whatever it was working on at the time, if anything.
+    Crash { message: String, file: Option<PathBuf> },
+}
+
+impl GesError
+{
+    /// Returns the name of this error's category, for machine readable output.
+    pub fn get_category( &self ) -> &'static str
+    {
+        match *self
+        {
+            GesError::Io(_) => "io",
+            GesError::Parse { .. } => "parse",
+            GesError::MissingFile { .. } => "missing_file",
+            GesError::PolicyViolation(_) => "policy_violation",
+            GesError::Config(_) => "config",
+            GesError::Crash { .. } => "crash",
+        }
+    }
+
+    /// Adds the file that was being processed to the error, unless it already says which file it's about.
+    pub fn in_file( self, file_path: &Path ) -> GesError
+    {
+        let mut context = String::new();
+        context.push_str("While processing ");
+        context.push_str( &file_path.display().to_string() );
+        context.push_str(" the following error was encountered:\n");
+
+        match self
+        {
+            GesError::Io(e) => { context.push_str( &e.to_string() ); GesError::Io(io::Error::new( e.kind(), context )) },
+            GesError::PolicyViolation(x) => { context.push_str(&x); GesError::PolicyViolation(context) },
+            GesError::Config(x) => { context.push_str(&x); GesError::Config(context) },
+            GesError::MissingFile { path, message } => { context.push_str(&message); GesError::MissingFile { path, message: context } },
+            GesError::Crash { message, file } => GesError::Crash { message, file: if file == None { Some(file_path.to_path_buf()) } else { file } },
+            parse_error => parse_error,
+        }
+    }
+}
+
+impl fmt::Display for GesError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match *self
+        {
+            GesError::Io(ref e) => write!( f, "{}", e ),
+            GesError::Parse { ref file, line, ref message } =>
+            {
+                if line > 0
+                {
+                    write!( f, "{}\n  at line {} of {}", message, line, file.display() )
+                }
+                else
+                {
+                    write!( f, "{}\n  in {}", message, file.display() )
+                }
+            },
+            GesError::MissingFile { ref message, .. } => write!( f, "{}", message ),
+            GesError::PolicyViolation(ref message) => write!( f, "{}", message ),
+            GesError::Config(ref message) => write!( f, "{}", message ),
+            GesError::Crash { ref message, ref file } =>
+            {
+                match *file
+                {
+                    Some(ref x) => write!( f, "Crashed with message: {}\nWhile processing {}", message, x.display() ),
+                    None => write!( f, "Crashed with message: {}", message ),
+                }
+            },
+        }
+    }
+}
+
+impl error::Error for GesError
+{
+    fn source( &self ) -> Option<&(dyn error::Error + 'static)>
+    {
+        match *self
+        {
+            GesError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for GesError
+{
+    fn from( error: io::Error ) -> GesError
+    {
+        GesError::Io(error)
+    }
+}
+
+impl From<walkdir::Error> for GesError
+{
+    fn from( error: walkdir::Error ) -> GesError
+    {
+        GesError::Io(io::Error::from(error))
+    }
+}
+
+/// Returns the line number of the given byte offset in the given text, counting from 1.
+pub fn get_line_number( contents: &str, byte_offset: usize ) -> usize
+{
+    contents[..byte_offset].matches('\n').count() + 1
+}
+
+/// Looks up the line numbers of many byte offsets in the same text.  get_line_number counts every line before the
+/// offset again each time, which adds up quickly for scripts with thousands of entries.
+pub struct LineNumbers
+{
+    line_starts: Vec<usize>,
+}
+
+impl LineNumbers
+{
+    pub fn new( contents: &str ) -> LineNumbers
+    {
+        let mut line_starts = vec![0];
+        line_starts.extend( contents.match_indices('\n').map(|x| x.0 + 1) );
+
+        LineNumbers { line_starts }
+    }
+
+    /// Returns the line number of the given byte offset, counting from 1.
+    pub fn get( &self, byte_offset: usize ) -> usize
+    {
+        match self.line_starts.binary_search( &byte_offset )
+        {
+            Ok(x) => x + 1,
+            Err(x) => x,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_error_display()
+    {
+        let parse_error = GesError::Parse { file: PathBuf::from("maps/test_map.res"), line: 3, message: String::from("Bad entry!") };
+        assert_eq!( parse_error.to_string(), "Bad entry!\n  at line 3 of maps/test_map.res" );
+        assert_eq!( parse_error.get_category(), "parse" );
+
+        // Parse errors already name their file, everything else has it added.
+        let parse_error = parse_error.in_file( Path::new("elsewhere.txt") );
+        assert!( !parse_error.to_string().contains("elsewhere.txt") );
+
+        let policy_error = GesError::PolicyViolation( String::from("Not allowed!") ).in_file( Path::new("maps/test_map.res") );
+        assert!( policy_error.to_string().contains("maps/test_map.res") );
+        assert_eq!( policy_error.get_category(), "policy_violation" );
+
+        assert_eq!( get_line_number( "a\nb\nc", 4 ), 3 );
+        assert_eq!( get_line_number( "abc", 0 ), 1 );
+
+        let line_numbers = LineNumbers::new( "a\nb\nc" );
+        assert_eq!( (line_numbers.get( 0 ), line_numbers.get( 1 ), line_numbers.get( 2 ), line_numbers.get( 4 )), (1, 1, 2, 3) );
+    }
+}
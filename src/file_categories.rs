@@ -0,0 +1,359 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// file_categories: Decides what kind of asset each file is by its extension, from a table a categories file can change.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use scan_service;
+use shared;
+use error::GesError;
+
+/// The kinds of files a release can hold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileCategory
+{
+    /// Compiled maps.  Clients download the map on its own, so these never go in a reslist.
+    Map,
+    /// Reslists, which clients don't need to download.
+    Reslist,
+    Script,
+    Material,
+    Model,
+    Sound,
+    /// Map sources, editor backups, and compile byproducts, which are only shipped if the user asks for them.
+    Source,
+    /// Programs, which are useless for a map and most likely just the map releaser itself.
+    Executable,
+    /// Files only the server uses, like python gamemodes, navigation meshes, and configs.  Clients can't download
+    /// these, so servers have to install them along with the release.
+    Server,
+    /// Anything the table doesn't know.
+    Other,
+}
+
+/// Every category, in the order they're reported.
+pub static CATEGORIES: &'static [FileCategory] = &[FileCategory::Map, FileCategory::Reslist, FileCategory::Script, FileCategory::Material, FileCategory::Model,
+                                                  FileCategory::Sound, FileCategory::Source, FileCategory::Executable, FileCategory::Server, FileCategory::Other];
+
+impl FileCategory
+{
+    /// Parses a category as written in a categories file.
+    pub fn from_name( category: &str ) -> Option<FileCategory>
+    {
+        CATEGORIES.iter().find(|x| x.to_string() == category.to_lowercase()).cloned()
+    }
+
+    /// Returns true if files of this category are always left out of the reslist and compression.
+    pub fn is_disallowed( self ) -> bool
+    {
+        self == FileCategory::Map || self == FileCategory::Reslist || self == FileCategory::Executable || self == FileCategory::Server
+    }
+
+    /// Returns the rough size of a bz2 compressed file of this category relative to the original, based on how GE:S
+    /// assets have historically compressed.  Leans towards overestimating so we don't pass files we can't fit.
+    pub fn get_compression_ratio( self ) -> f64
+    {
+        match self
+        {
+            FileCategory::Map => 0.45,
+            FileCategory::Material => 0.75,
+            FileCategory::Model => 0.5,
+            FileCategory::Script | FileCategory::Reslist => 0.35,
+            FileCategory::Sound => 1.0, // Already compressed, bz2 won't do much for these.
+            _ => 1.0,
+        }
+    }
+}
+
+impl fmt::Display for FileCategory
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        // Padding is respected so categories line up in tables.
+        match *self
+        {
+            FileCategory::Map => f.pad("map"),
+            FileCategory::Reslist => f.pad("reslist"),
+            FileCategory::Script => f.pad("script"),
+            FileCategory::Material => f.pad("material"),
+            FileCategory::Model => f.pad("model"),
+            FileCategory::Sound => f.pad("sound"),
+            FileCategory::Source => f.pad("source"),
+            FileCategory::Executable => f.pad("executable"),
+            FileCategory::Server => f.pad("server"),
+            FileCategory::Other => f.pad("other"),
+        }
+    }
+}
+
+/// The category of every extension the program knows about, unless a categories file says otherwise.
+pub static DEFAULT_CATEGORIES: &'static [(&'static str, FileCategory)] = &[
+    ("bsp", FileCategory::Map),
+    ("res", FileCategory::Reslist),
+    ("txt", FileCategory::Script), ("vbsp", FileCategory::Script),
+    ("vtf", FileCategory::Material), ("vmt", FileCategory::Material), ("raw", FileCategory::Material),
+    ("mdl", FileCategory::Model), ("vvd", FileCategory::Model), ("vtx", FileCategory::Model), ("phy", FileCategory::Model), ("ani", FileCategory::Model),
+    ("mp3", FileCategory::Sound), ("ogg", FileCategory::Sound), ("wav", FileCategory::Sound),
+    ("vmf", FileCategory::Source), ("vmx", FileCategory::Source), ("vmm", FileCategory::Source), ("log", FileCategory::Source),
+    ("prt", FileCategory::Source), ("lin", FileCategory::Source), ("bak", FileCategory::Source), ("vmf_autosave", FileCategory::Source),
+    ("exe", FileCategory::Executable),
+    ("py", FileCategory::Server), ("pyc", FileCategory::Server), ("cfg", FileCategory::Server), ("nav", FileCategory::Server),
+];
+
+lazy_static!
+{
+    static ref CATEGORY_OVERRIDES: RwLock<Vec<(String, FileCategory)>> = RwLock::new(Vec::new());
+}
+
+/// Replaces the categories a categories file gave to extensions.  Every later lookup sees the new table.
+pub fn set_category_overrides( category_overrides: Vec<(String, FileCategory)> )
+{
+    *CATEGORY_OVERRIDES.write().unwrap() = category_overrides;
+}
+
+/// Returns the category of files with the given extension.
+pub fn get_category( extension: &str ) -> FileCategory
+{
+    find_category( &CATEGORY_OVERRIDES.read().unwrap(), extension )
+}
+
+/// Returns the category of the file at the given relative path.
+pub fn get_file_category( file_path: &str ) -> FileCategory
+{
+    get_category( shared::get_string_file_extension( file_path ) )
+}
+
+/// Returns every extension that currently belongs to one of the given categories.
+pub fn get_extensions( categories: &[FileCategory] ) -> Vec<String>
+{
+    let category_overrides = CATEGORY_OVERRIDES.read().unwrap();
+
+    let known_extensions = DEFAULT_CATEGORIES.iter().map(|x| String::from(x.0)).chain( category_overrides.iter().map(|x| x.0.clone()) );
+
+    let mut extensions: Vec<String> = known_extensions.filter(|x| categories.contains( &find_category( &category_overrides, x ) )).collect();
+    extensions.sort();
+    extensions.dedup();
+
+    extensions
+}
+
+/// Looks the given extension up in the overrides, then the defaults.
+fn find_category( category_overrides: &[(String, FileCategory)], extension: &str ) -> FileCategory
+{
+    let extension = extension.to_lowercase();
+
+    // Later lines in a categories file win, just like in a rules file.
+    match category_overrides.iter().rev().find(|x| x.0 == extension)
+    {
+        Some(x) => return x.1,
+        None => {},
+    }
+
+    match DEFAULT_CATEGORIES.iter().find(|x| x.0 == extension)
+    {
+        Some(x) => x.1,
+        None => FileCategory::Other,
+    }
+}
+
+/// Reads the categories file at the given path.  Each line holds an extension and the category it belongs to,
+/// like "pcf material", and anything after // is a comment.
+pub fn read_categories_file( categories_path: &PathBuf ) -> Result<Vec<(String, FileCategory)>, GesError>
+{
+    let mut contents = String::new();
+    fs::File::open(categories_path)?.read_to_string(&mut contents)?;
+
+    parse_category_overrides( categories_path, &contents )
+}
+
+/// Parses the contents of a categories file, making sure every category exists.
+fn parse_category_overrides( categories_path: &PathBuf, contents: &str ) -> Result<Vec<(String, FileCategory)>, GesError>
+{
+    let mut category_overrides = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate()
+    {
+        let line = match line.find("//")
+        {
+            Some(x) => &line[..x],
+            None => line,
+        };
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if tokens.is_empty()
+        {
+            continue;
+        }
+
+        let parse_error = |message: String| GesError::Parse { file: categories_path.clone(), line: line_index + 1, message };
+
+        if tokens.len() != 2
+        {
+            return Err(parse_error( String::from("Expected an extension followed by the category it belongs to!") ));
+        }
+
+        // Extensions are matched without case, and are easy to write with their dot.
+        let extension = tokens[0].trim_start_matches('.').to_lowercase();
+
+        let category = match FileCategory::from_name( tokens[1] )
+        {
+            Some(x) => x,
+            None =>
+            {
+                let category_names: Vec<String> = CATEGORIES.iter().map(|x| x.to_string()).collect();
+                return Err(parse_error( format!( "Unknown category {}!  Expected one of {}.", tokens[1], category_names.join(", ") ) ));
+            },
+        };
+
+        category_overrides.push( (extension, category) );
+    }
+
+    Ok(category_overrides)
+}
+
+/// How many of the largest files the size report lists.
+const LARGEST_FILE_COUNT: usize = 10;
+
+/// Prints how much of the download each category and top level directory takes up, along with the largest files, so
+/// it's easy to see what's making it big.  Paths are relative to the root directory.
+pub fn print_size_report( rootdir: &PathBuf, relative_paths: &[String] )
+{
+    let file_sizes: Vec<(String, u64)> = relative_paths.iter()
+                                                       .map(|x| (x.clone(), scan_service::get_scan_service().read_file_info( &rootdir.join(x) ).map(|y| y.size).unwrap_or(0)))
+                                                       .collect();
+
+    let total_bytes: u64 = file_sizes.iter().map(|x| x.1).sum();
+    let get_percentage = |byte_count: u64| if total_bytes == 0 { 0.0 } else { byte_count as f64 * 100.0 / total_bytes as f64 };
+
+    println!( "Release size by category, {} in total:", shared::format_byte_count( total_bytes ) );
+
+    for (category, file_count, byte_count) in get_category_totals( &file_sizes )
+    {
+        println!( "\t{:<10} {:>6} files {:>10} {:>6.1}%", category, file_count, shared::format_byte_count( byte_count ), get_percentage( byte_count ) );
+    }
+
+    println!("Release size by directory:");
+
+    for (directory, file_count, byte_count) in get_directory_totals( &file_sizes )
+    {
+        println!( "\t{:<10} {:>6} files {:>10} {:>6.1}%", directory, file_count, shared::format_byte_count( byte_count ), get_percentage( byte_count ) );
+    }
+
+    println!("Largest files:");
+
+    for (relative_path, byte_count) in get_largest_files( &file_sizes, LARGEST_FILE_COUNT )
+    {
+        println!( "\t{:>10} {}", shared::format_byte_count( byte_count ), relative_path );
+    }
+}
+
+/// Returns the number of files and bytes in each category that has any files, in the order categories are reported.
+fn get_category_totals( file_sizes: &[(String, u64)] ) -> Vec<(FileCategory, usize, u64)>
+{
+    let mut totals: Vec<(FileCategory, usize, u64)> = CATEGORIES.iter().map(|x| (*x, 0, 0)).collect();
+
+    for &(ref relative_path, byte_count) in file_sizes
+    {
+        let category_index = CATEGORIES.iter().position(|x| *x == get_file_category( relative_path )).unwrap();
+
+        totals[category_index].1 += 1;
+        totals[category_index].2 += byte_count;
+    }
+
+    totals.retain(|x| x.1 != 0);
+    totals
+}
+
+/// Returns the number of files and bytes in each top level directory, largest first.  Files that aren't in a directory
+/// are grouped together as "(root)".
+fn get_directory_totals( file_sizes: &[(String, u64)] ) -> Vec<(String, usize, u64)>
+{
+    let mut totals: Vec<(String, usize, u64)> = Vec::new();
+
+    for &(ref relative_path, byte_count) in file_sizes
+    {
+        let directory = match relative_path.find('/')
+        {
+            Some(x) => relative_path[..x].to_lowercase(),
+            None => String::from("(root)"),
+        };
+
+        match totals.iter().position(|x| x.0 == directory)
+        {
+            Some(x) => { totals[x].1 += 1; totals[x].2 += byte_count; },
+            None => totals.push( (directory, 1, byte_count) ),
+        }
+    }
+
+    totals.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+/// Returns up to count of the largest files, largest first.
+fn get_largest_files( file_sizes: &[(String, u64)], count: usize ) -> Vec<(String, u64)>
+{
+    let mut largest_files = file_sizes.to_vec();
+
+    largest_files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    largest_files.truncate(count);
+
+    largest_files
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_parse_category_overrides()
+    {
+        let categories_path = PathBuf::from("categories.txt");
+
+        let category_overrides = parse_category_overrides( &categories_path, "// Particles ship with the materials.\n.PCF material\n\ntxt source // Notes\n" ).unwrap();
+        assert_eq!( category_overrides, vec![(String::from("pcf"), FileCategory::Material), (String::from("txt"), FileCategory::Source)] );
+
+        assert!( parse_category_overrides( &categories_path, "pcf" ).is_err() );
+        assert!( parse_category_overrides( &categories_path, "pcf particles" ).unwrap_err().to_string().contains("Unknown category") );
+    }
+
+    #[test]
+    fn test_find_category()
+    {
+        let category_overrides = vec![(String::from("pcf"), FileCategory::Material), (String::from("log"), FileCategory::Other)];
+
+        assert_eq!( find_category( &[], "BSP" ), FileCategory::Map );
+        assert_eq!( find_category( &[], "pcf" ), FileCategory::Other );
+        assert_eq!( find_category( &category_overrides, "pcf" ), FileCategory::Material );
+        assert_eq!( find_category( &category_overrides, "log" ), FileCategory::Other );
+        assert_eq!( find_category( &category_overrides, "vmf" ), FileCategory::Source );
+    }
+
+    #[test]
+    fn test_size_breakdown()
+    {
+        let file_sizes = vec![(String::from("maps/ge_test.bsp"), 5000),
+                              (String::from("materials/ge_test/a.vtf"), 3000),
+                              (String::from("Materials/ge_test/a.vmt"), 100),
+                              (String::from("sound/music/a.mp3"), 4000),
+                              (String::from("readme.txt"), 10)];
+
+        assert_eq!( get_category_totals( &file_sizes ), vec![(FileCategory::Map, 1, 5000), (FileCategory::Script, 1, 10),
+                                                             (FileCategory::Material, 2, 3100), (FileCategory::Sound, 1, 4000)] );
+
+        assert_eq!( get_directory_totals( &file_sizes ), vec![(String::from("maps"), 1, 5000), (String::from("sound"), 1, 4000),
+                                                              (String::from("materials"), 2, 3100), (String::from("(root)"), 1, 10)] );
+
+        assert_eq!( get_largest_files( &file_sizes, 2 ), vec![(String::from("maps/ge_test.bsp"), 5000), (String::from("sound/music/a.mp3"), 4000)] );
+    }
+}
@@ -0,0 +1,508 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// -----------------------------------------------------------------------------------------------------------
+// file_writer: The only way the program changes the filesystem, so check-only mode can guarantee it never does.
+// -----------------------------------------------------------------------------------------------------------
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{Seek, SeekFrom, Write};
+
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fs2::FileExt;
+use sha2::{Sha256, Digest};
+
+use shared;
+use error::GesError;
+
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+lazy_static!
+{
+    // Every lock file this process holds, and how many DirectoryLocks are using each.  The OS only cares which process
+    // holds a lock, so threads of the same run share it instead of locking each other out.
+    static ref HELD_LOCKS: Mutex<HashMap<PathBuf, (usize, fs::File)>> = Mutex::new(HashMap::new());
+
+    // Directories that can't be written to, and the user writable directory everything meant for each goes to instead.
+    static ref STAGED_DIRECTORIES: RwLock<Vec<(PathBuf, PathBuf)>> = RwLock::new(Vec::new());
+}
+
+/// Turns check-only mode on or off.  While it's on every write fails instead of touching the filesystem.
+pub fn set_read_only( read_only: bool )
+{
+    READ_ONLY.store(read_only, Ordering::SeqCst);
+}
+
+/// Returns true if we're in check-only mode and nothing may be written.
+pub fn is_read_only() -> bool
+{
+    READ_ONLY.load(Ordering::SeqCst)
+}
+
+/// Sends every later write to a path under protected_dir to the same place under staging_dir instead, for directories
+/// that can't be written to without elevation.  Files can be added or replaced this way, but not removed or renamed.
+pub fn stage_directory( protected_dir: &Path, staging_dir: &Path )
+{
+    STAGED_DIRECTORIES.write().unwrap().push( (protected_dir.to_path_buf(), staging_dir.to_path_buf()) );
+}
+
+/// Stops redirecting writes to the given protected directory, so a test staging one doesn't change how later tests write.
+#[cfg(test)]
+pub fn unstage_directory( protected_dir: &Path )
+{
+    STAGED_DIRECTORIES.write().unwrap().retain(|x| x.0 != protected_dir);
+}
+
+/// Returns a new directory to stage writes to a protected directory with the given name in.  It's somewhere the user can
+/// always write to, and every run gets its own, so files staged by an earlier run can't be mistaken for this one's.
+pub fn get_user_staging_directory( dir_name: &str ) -> PathBuf
+{
+    let user_data_dir = match (env::var_os("LOCALAPPDATA"), env::var_os("XDG_DATA_HOME"), env::var_os("HOME"))
+    {
+        (Some(x), _, _) if cfg!(windows) => PathBuf::from(x),
+        (_, Some(x), _) => PathBuf::from(x),
+        (_, _, Some(x)) => PathBuf::from(x).join(".local").join("share"),
+        _ => env::temp_dir(),
+    };
+
+    let run_time = SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0);
+
+    user_data_dir.join("ges_scriptutility").join("staging").join( format!( "{}_{}", dir_name, run_time ) )
+}
+
+/// Returns where a write to the given path actually goes, and whether that's somewhere else because it was staged.
+fn get_staged_path( target_path: &Path ) -> (PathBuf, bool)
+{
+    for &(ref protected_dir, ref staging_dir) in STAGED_DIRECTORIES.read().unwrap().iter()
+    {
+        match target_path.strip_prefix(protected_dir)
+        {
+            Ok(x) => return (staging_dir.join(x), true),
+            Err(_) => {},
+        }
+    }
+
+    (target_path.to_path_buf(), false)
+}
+
+/// A file opened for writing.  It can only be opened while writes are allowed.
+pub struct FileWriter
+{
+    file: fs::File,
+}
+
+impl FileWriter
+{
+    /// Creates the file at the given path, or truncates it if it already exists.
+    pub fn create( file_path: &Path ) -> Result<FileWriter, GesError>
+    {
+        check_writable( is_read_only(), file_path )?;
+        let file_path = &get_staged_path( file_path ).0;
+        let is_new = !file_path.exists();
+
+        let file = fs::File::create(file_path)?;
+        invalidate_if( is_new, file_path );
+
+        Ok(FileWriter { file })
+    }
+
+    /// Opens the file at the given path with the given options, which should include writing to it.
+    pub fn open( options: &OpenOptions, file_path: &Path ) -> Result<FileWriter, GesError>
+    {
+        check_writable( is_read_only(), file_path )?;
+        let file_path = &get_staged_path( file_path ).0;
+        let is_new = !file_path.exists();
+
+        let file = options.open(file_path)?;
+        invalidate_if( is_new, file_path );
+
+        Ok(FileWriter { file })
+    }
+
+    /// Sets the file's modification time.
+    pub fn set_modified( &self, time: SystemTime ) -> Result<(), GesError>
+    {
+        self.file.set_modified(time)?;
+
+        Ok(())
+    }
+}
+
+impl Write for FileWriter
+{
+    fn write( &mut self, buf: &[u8] ) -> io::Result<usize>
+    {
+        self.file.write(buf)
+    }
+
+    fn flush( &mut self ) -> io::Result<()>
+    {
+        self.file.flush()
+    }
+}
+
+impl Seek for FileWriter
+{
+    fn seek( &mut self, pos: SeekFrom ) -> io::Result<u64>
+    {
+        self.file.seek(pos)
+    }
+}
+
+/// Writes the given contents to the file at the given path, replacing anything already in it.
+pub fn write( file_path: &Path, contents: &[u8] ) -> Result<(), GesError>
+{
+    FileWriter::create( file_path )?.write_all(contents)?;
+
+    Ok(())
+}
+
+/// Creates the given directory along with any parents it's missing.
+pub fn create_dir_all( dir_path: &Path ) -> Result<(), GesError>
+{
+    check_writable( is_read_only(), dir_path )?;
+    let dir_path = &get_staged_path( dir_path ).0;
+    let is_new = !dir_path.exists();
+
+    fs::create_dir_all(dir_path)?;
+    invalidate_if( is_new, dir_path );
+
+    Ok(())
+}
+
+/// Deletes the file at the given path.
+pub fn remove_file( file_path: &Path ) -> Result<(), GesError>
+{
+    check_writable( is_read_only(), file_path )?;
+    check_unstaged( file_path, "remove" )?;
+
+    fs::remove_file(file_path)?;
+    shared::invalidate_directory_cache( file_path );
+
+    Ok(())
+}
+
+/// Deletes the given directory along with everything in it.
+pub fn remove_dir_all( dir_path: &Path ) -> Result<(), GesError>
+{
+    check_writable( is_read_only(), dir_path )?;
+    check_unstaged( dir_path, "remove" )?;
+
+    fs::remove_dir_all(dir_path)?;
+    shared::invalidate_directory_cache( dir_path );
+
+    Ok(())
+}
+
+/// Copies the file at source_path to destination_path, replacing anything already there.
+pub fn copy( source_path: &Path, destination_path: &Path ) -> Result<(), GesError>
+{
+    check_writable( is_read_only(), destination_path )?;
+    let destination_path = &get_staged_path( destination_path ).0;
+    let is_new = !destination_path.exists();
+
+    fs::copy( source_path, destination_path )?;
+    invalidate_if( is_new, destination_path );
+
+    Ok(())
+}
+
+/// Creates a hard link at destination_path to the file at source_path.
+pub fn hard_link( source_path: &Path, destination_path: &Path ) -> Result<(), GesError>
+{
+    check_writable( is_read_only(), destination_path )?;
+    check_unstaged( destination_path, "link" )?;
+
+    fs::hard_link( source_path, destination_path )?;
+    shared::invalidate_directory_cache( destination_path );
+
+    Ok(())
+}
+
+/// Renames the file or directory at source_path to destination_path.
+pub fn rename( source_path: &Path, destination_path: &Path ) -> Result<(), GesError>
+{
+    check_writable( is_read_only(), destination_path )?;
+    check_unstaged( destination_path, "rename" )?;
+
+    fs::rename( source_path, destination_path )?;
+
+    // Renaming something to itself only fixes its case, which the directory trees don't care about.
+    if source_path != destination_path
+    {
+        shared::invalidate_directory_cache( source_path );
+        shared::invalidate_directory_cache( destination_path );
+    }
+
+    Ok(())
+}
+
+/// Keeps other copies of the program from writing to a directory for as long as it's alive.
+pub struct DirectoryLock
+{
+    /// The lock file being held, or None if nothing will be written and there's nothing to protect.
+    lock_path: Option<PathBuf>,
+}
+
+impl Drop for DirectoryLock
+{
+    fn drop( &mut self )
+    {
+        let lock_path = match self.lock_path
+        {
+            Some(ref x) => x,
+            None => return,
+        };
+
+        let mut held_locks = HELD_LOCKS.lock().unwrap();
+
+        let is_last_user = match held_locks.get_mut( lock_path )
+        {
+            Some(x) => { x.0 -= 1; x.0 == 0 },
+            None => false,
+        };
+
+        // Closing the file lets go of the lock.
+        if is_last_user
+        {
+            held_locks.remove( lock_path );
+        }
+    }
+}
+
+/// Takes an advisory lock on the given directory, so a second copy of the program started on it, such as by a
+/// double-click, fails straight away instead of writing over the first one's files.  The lock file is kept in the temp
+/// directory rather than the directory itself, so it never ends up in a reslist or release.
+pub fn lock_directory( dir_path: &Path ) -> Result<DirectoryLock, GesError>
+{
+    if is_read_only()
+    {
+        return Ok(DirectoryLock { lock_path: None });
+    }
+
+    let lock_path = get_lock_path( dir_path );
+    let mut held_locks = HELD_LOCKS.lock().unwrap();
+
+    match held_locks.get_mut( &lock_path )
+    {
+        Some(x) => { x.0 += 1; return Ok(DirectoryLock { lock_path: Some(lock_path) }); },
+        None => {},
+    }
+
+    let lock_file = OpenOptions::new().write(true).create(true).truncate(false).open(&lock_path)?;
+
+    if lock_file.try_lock_exclusive().is_err()
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Another instance of the program is already writing to ");
+        error_text.push_str( &dir_path.display().to_string() );
+        error_text.push_str("!  Wait for it to finish, then try again.");
+
+        return Err(GesError::Config( error_text ));
+    }
+
+    held_locks.insert( lock_path.clone(), (1, lock_file) );
+
+    Ok(DirectoryLock { lock_path: Some(lock_path) })
+}
+
+/// Returns where the lock file for the given directory goes.  Every way of writing the directory's path should give
+/// the same lock, so it's named after the hash of the path with everything resolved.
+fn get_lock_path( dir_path: &Path ) -> PathBuf
+{
+    // Directories that haven't been made yet are resolved through their parent.
+    let resolved_path = match (fs::canonicalize(dir_path), dir_path.parent().map(fs::canonicalize), dir_path.file_name())
+    {
+        (Ok(x), _, _) => x,
+        (Err(_), Some(Ok(x)), Some(y)) => x.join(y),
+        _ => dir_path.to_path_buf(),
+    };
+
+    // Windows doesn't care about case, so neither can the lock.
+    let path_text = if cfg!(windows) { resolved_path.to_string_lossy().to_lowercase() } else { resolved_path.to_string_lossy().into_owned() };
+    let path_hash: String = Sha256::digest( path_text.as_bytes() ).iter().take(8).map(|x| format!("{:02x}", x)).collect();
+
+    env::temp_dir().join( format!( "ges_scriptutility_{}.lock", path_hash ) )
+}
+
+/// Returns true if files can be created in the given directory, or in the closest parent that exists if it hasn't
+/// been created yet.  Nothing can be created in check-only mode.
+pub fn can_create_in( dir_path: &Path ) -> bool
+{
+    if is_read_only()
+    {
+        return false;
+    }
+
+    let dir_path = &get_staged_path( dir_path ).0;
+
+    // Relative paths run out of ancestors at an empty path, which stands for the working directory.
+    let existing_dir = match dir_path.ancestors().find(|x| x.is_dir() || x.as_os_str().is_empty())
+    {
+        Some(x) if x.as_os_str().is_empty() => Path::new("."),
+        Some(x) => x,
+        None => return false,
+    };
+
+    // Permission bits don't account for ACLs, read-only mounts, or shares, so the only reliable check is to try it.
+    let probe_path = existing_dir.join( format!( ".ges_write_check_{}", process::id() ) );
+
+    match OpenOptions::new().write(true).create_new(true).open(&probe_path)
+    {
+        Ok(_) => { let _ = fs::remove_file(&probe_path); true },
+        Err(_) => false,
+    }
+}
+
+/// Drops any cached directory trees that include the given path if it was just created.  Overwriting a file that was
+/// already there doesn't change which files a tree holds.
+fn invalidate_if( is_new: bool, changed_path: &Path )
+{
+    if is_new
+    {
+        shared::invalidate_directory_cache( changed_path );
+    }
+}
+
+/// Returns an error if the given path can't be written to because we're in check-only mode.
+fn check_writable( read_only: bool, target_path: &Path ) -> Result<(), GesError>
+{
+    if read_only
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Check-only mode won't write to ");
+        error_text.push_str( &target_path.display().to_string() );
+        error_text.push_str("!");
+
+        return Err(GesError::Config( error_text ));
+    }
+
+    Ok(())
+}
+
+/// Returns an error if the given path was staged, since the change to it couldn't be staged along with it.
+fn check_unstaged( target_path: &Path, action: &str ) -> Result<(), GesError>
+{
+    if get_staged_path( target_path ).1
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Can't ");
+        error_text.push_str(action);
+        error_text.push_str(" ");
+        error_text.push_str( &target_path.display().to_string() );
+        error_text.push_str(" with --user-scope, which can only stage new and changed files!  Run the program as administrator instead.");
+
+        return Err(GesError::Config( error_text ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_check_writable()
+    {
+        // The mode itself is shared by every test, so only check what it decides.
+        assert!( check_writable( false, Path::new("gesource/maps/test_map.res") ).is_ok() );
+
+        let error_text = check_writable( true, Path::new("gesource/maps/test_map.res") ).unwrap_err().to_string();
+        assert!( error_text.contains("Check-only mode") );
+        assert!( error_text.contains("test_map.res") );
+    }
+
+    #[test]
+    fn test_lock_directory()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("lock_directory");
+        fs::create_dir_all(&test_dir).unwrap();
+
+        // Any other way of writing the same directory finds the same lock.
+        assert_eq!( get_lock_path( &test_dir ), get_lock_path( &test_dir.join("..").join("lock_directory") ) );
+        assert_ne!( get_lock_path( &test_dir ), get_lock_path( &test_dir.join("not_made_yet") ) );
+
+        // Another instance holding the lock is its own file handle, which gets in the way like another process would.
+        let other_instance = OpenOptions::new().write(true).create(true).truncate(false).open( get_lock_path( &test_dir ) ).unwrap();
+        other_instance.try_lock_exclusive().unwrap();
+
+        let error_text = lock_directory( &test_dir ).err().unwrap().to_string();
+        assert!( error_text.contains("Another instance") );
+
+        FileExt::unlock( &other_instance ).unwrap();
+
+        // Once it's free, the same run can lock it as many times as it likes.
+        let first_lock = lock_directory( &test_dir ).unwrap();
+        let second_lock = lock_directory( &test_dir ).unwrap();
+        drop(first_lock);
+        assert!( other_instance.try_lock_exclusive().is_err() );
+
+        drop(second_lock);
+        assert!( other_instance.try_lock_exclusive().is_ok() );
+
+        FileExt::unlock( &other_instance ).unwrap();
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_stage_directory()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("stage_directory");
+
+        let protected_dir = test_dir.join("protected").join("gesource");
+        let staging_dir = test_dir.join("staging");
+        stage_directory( &protected_dir, &staging_dir );
+
+        // New files end up in the staging directory instead, with the same layout.
+        let script_path = protected_dir.join("scripts").join("maps").join("ge_staged.txt");
+        create_dir_all( script_path.parent().unwrap() ).unwrap();
+        write( &script_path, b"BaseWeight\t500\r\n" ).unwrap();
+
+        assert!( !script_path.exists() );
+        assert_eq!( fs::read_to_string( staging_dir.join("scripts").join("maps").join("ge_staged.txt") ).unwrap(), "BaseWeight\t500\r\n" );
+
+        // Nothing else is affected, and whatever can't be staged is refused instead of half done.
+        assert_eq!( get_staged_path( &test_dir.join("elsewhere.txt") ), (test_dir.join("elsewhere.txt"), false) );
+        assert!( remove_file( &script_path ).unwrap_err().to_string().contains("--user-scope") );
+
+        unstage_directory( &protected_dir );
+        assert_eq!( get_staged_path( &script_path ), (script_path.clone(), false) );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_can_create_in()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("can_create_in");
+
+        // Directories that don't exist yet are checked through the closest one that does.
+        fs::create_dir_all(&test_dir).unwrap();
+        assert!( can_create_in( &test_dir ) );
+        assert!( can_create_in( &test_dir.join("not/made/yet") ) );
+        assert!( can_create_in( Path::new("not_made_yet") ) );
+        assert_eq!( fs::read_dir(&test_dir).unwrap().count(), 0 );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
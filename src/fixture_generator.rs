@@ -0,0 +1,289 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// fixture_generator: Builds the valid and invalid map script test fixtures from the scripts the program generates itself.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::PathBuf;
+
+use argument_handler::Arguments;
+use file_writer;
+use ges_version;
+use map_script_builder;
+use rules;
+use rules::Severity;
+use error::GesError;
+
+/// Generated fixtures start with this, so they can be told apart from handwritten ones and replaced each run.
+pub static GENERATED_PREFIX: &'static str = "gen_";
+
+/// Map script rules that can't be broken by a script on its own, along with what else it would take.
+pub static UNFIXTURED_RULES: &[(&'static str, &'static str)] = &[("map-script-group-mismatch", "a second map script naming a different group")];
+
+/// A way of writing the canonical map script that GE:S reads the same, so it should still pass.
+struct Variant
+{
+    name: &'static str,
+    rewrite: fn( &str ) -> String,
+}
+
+/// A change to the canonical map script that breaks exactly one rule.
+struct Mutation
+{
+    rule_id: &'static str,
+    mutate: fn( &str, &[String] ) -> String,
+}
+
+/// Writes a positive fixture for every variant of the canonical map script and a negative one for every map script rule
+/// into the map_script_tests directory of the given tests directory, replacing any generated before.  Each fixture is
+/// checked before it's kept, so one that doesn't do what it should fails the run instead.  Returns the path of each.
+pub fn generate_fixtures( args: &Arguments, tests_dir: &PathBuf ) -> Result<Vec<PathBuf>, GesError>
+{
+    let fixture_args = get_fixture_args( args, tests_dir );
+
+    let canonical_contents = map_script_builder::get_map_script_contents( &fixture_args )?;
+    let known_gamemodes = ges_version::get_known_gamemodes( &fixture_args )?;
+
+    let mut valid_dir = tests_dir.clone();
+    valid_dir.push("map_script_tests");
+    valid_dir.push("valid");
+
+    let mut invalid_dir = tests_dir.clone();
+    invalid_dir.push("map_script_tests");
+    invalid_dir.push("invalid");
+
+    for fixture_dir in &[&valid_dir, &invalid_dir]
+    {
+        file_writer::create_dir_all( fixture_dir )?;
+        remove_generated_fixtures( fixture_dir )?;
+    }
+
+    let mut fixture_paths = Vec::new();
+
+    for variant in get_map_script_variants()
+    {
+        let fixture_path = valid_dir.join( format!( "{}{}.txt", GENERATED_PREFIX, variant.name ) );
+
+        file_writer::write( &fixture_path, (variant.rewrite)( &canonical_contents ).as_bytes() )?;
+        verify_fixture( &fixture_args, &fixture_path, None, true )?;
+
+        fixture_paths.push( fixture_path );
+    }
+
+    for mutation in get_map_script_mutations()
+    {
+        // Breaking a warning still passes, so those fixtures are only invalid once the rule is made an error.
+        let should_pass = rules::get_severity( &fixture_args, mutation.rule_id ) != Severity::Error;
+        let fixture_dir = if should_pass { &valid_dir } else { &invalid_dir };
+        let fixture_path = fixture_dir.join( format!( "{}{}.txt", GENERATED_PREFIX, mutation.rule_id ) );
+
+        file_writer::write( &fixture_path, (mutation.mutate)( &canonical_contents, &known_gamemodes ).as_bytes() )?;
+        verify_fixture( &fixture_args, &fixture_path, Some(mutation.rule_id), should_pass )?;
+
+        fixture_paths.push( fixture_path );
+    }
+
+    Ok(fixture_paths)
+}
+
+/// Returns the id of every map script rule that doesn't have a mutation breaking it and isn't known to need more than a
+/// single script to break.  New rules show up here until they're given a fixture.
+pub fn get_unfixtured_rules() -> Vec<&'static str>
+{
+    let mutations = get_map_script_mutations();
+
+    rules::RULES.iter()
+                .map(|x| x.id)
+                .filter(|x| x.starts_with("map-script-"))
+                .filter(|x| !mutations.iter().any(|y| &y.rule_id == x) && !UNFIXTURED_RULES.iter().any(|y| &y.0 == x))
+                .collect()
+}
+
+/// Returns the arguments the test suite checks fixtures with: the given settings, but with the test directories and
+/// nothing that changes what a new map script looks like.
+fn get_fixture_args( args: &Arguments, tests_dir: &PathBuf ) -> Arguments
+{
+    let mut fixture_args = args.clone();
+
+    fixture_args.rootdir = tests_dir.join("rootdir").join("gesource");
+    fixture_args.gesdir = tests_dir.join("gesdir").join("gesource");
+    fixture_args.map_group = None;
+    fixture_args.only_gamemodes = Vec::new();
+    fixture_args.playtest_expires = None;
+    fixture_args.playtest_notes = None;
+    fixture_args.section_weights = Vec::new();
+    fixture_args.rule_overrides = Vec::new();
+
+    fixture_args
+}
+
+/// Checks a fixture passes or fails as it should.  A fixture for a rule also has to fail under that rule once it's made
+/// an error, so it can't pass the test by breaking something else.
+fn verify_fixture( fixture_args: &Arguments, fixture_path: &PathBuf, rule_id: Option<&str>, should_pass: bool ) -> Result<(), GesError>
+{
+    if map_script_builder::check_map_script_file( fixture_args, fixture_path ).is_ok() != should_pass
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Generated fixture ");
+        error_text.push_str( &fixture_path.display().to_string() );
+        error_text.push_str( if should_pass { " failed its check when it should pass!" } else { " passed its check when it should fail!" } );
+
+        return Err(GesError::Config( error_text ));
+    }
+
+    let rule_id = match rule_id
+    {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+
+    let mut strict_args = fixture_args.clone();
+    strict_args.rule_overrides.push( (String::from(rule_id), Severity::Error) );
+
+    match map_script_builder::check_map_script_file( &strict_args, fixture_path )
+    {
+        Err(ref e) if e.to_string().contains(&format!( "[{}]", rule_id )) => Ok(()),
+        result =>
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Generated fixture ");
+            error_text.push_str( &fixture_path.display().to_string() );
+            error_text.push_str(" doesn't break rule ");
+            error_text.push_str(rule_id);
+            error_text.push_str(" first!  Its check ");
+
+            match result
+            {
+                Ok(_) => error_text.push_str("passed."),
+                Err(e) => { error_text.push_str("failed with:\n"); error_text.push_str( &e.to_string() ); },
+            }
+
+            Err(GesError::Config( error_text ))
+        },
+    }
+}
+
+/// Removes every generated fixture from the given directory, leaving handwritten ones alone.
+fn remove_generated_fixtures( fixture_dir: &PathBuf ) -> Result<(), GesError>
+{
+    for entry in fs::read_dir(fixture_dir)?
+    {
+        let fixture_path = entry?.path();
+
+        if fixture_path.is_file() && fixture_path.file_name().is_some_and(|x| x.to_string_lossy().starts_with(GENERATED_PREFIX))
+        {
+            file_writer::remove_file( &fixture_path )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns every way of writing the canonical map script that should still pass.
+fn get_map_script_variants() -> Vec<Variant>
+{
+    vec![
+        Variant { name: "canonical", rewrite: |x| String::from(x) },
+        Variant { name: "unix_line_endings", rewrite: |x| x.replace("\r\n", "\n") },
+        Variant { name: "spaces", rewrite: |x| x.replace("\t", "    ") },
+        Variant { name: "uncommented", rewrite: |x| rewrite_lines( x, "//", |_| Vec::new() ) },
+        Variant { name: "quoted", rewrite: |x| rewrite_lines( x, "", quote_line ) },
+    ]
+}
+
+/// Returns a change to the canonical map script for every map script rule that one script can break on its own.
+fn get_map_script_mutations() -> Vec<Mutation>
+{
+    vec![
+        Mutation { rule_id: "map-script-syntax", mutate: |x, _| x.replacen("WeaponsetWeights\r\n{\r\n", "WeaponsetWeights\r\n", 1) },
+        Mutation { rule_id: "map-script-unknown-parameter", mutate: |x, _| rewrite_lines( x, "BaseWeight\t", |y| vec![y.replace("BaseWeight", "BaseWieght")] ) },
+        Mutation { rule_id: "map-script-duplicate-parameter", mutate: |x, _| rewrite_lines( x, "MaxPlayers\t", |y| vec![String::from(y), String::from(y)] ) },
+        Mutation { rule_id: "map-script-duplicate-entry", mutate: |x, _| rewrite_lines( x, "\tYOLT\t", |y| vec![String::from(y), String::from(y)] ) },
+        Mutation { rule_id: "map-script-no-gamemodes", mutate: zero_every_gamemode },
+        Mutation { rule_id: "map-script-unknown-gamemode", mutate: |x, _| rewrite_lines( x, "\tYOLT\t", |y| vec![y.replace("YOLT", "NotAGamemode")] ) },
+        Mutation { rule_id: "map-script-trailing-data", mutate: |x, _| rewrite_lines( x, "MinPlayers\t", |y| vec![format!( "{}\tjunk", y )] ) },
+        Mutation { rule_id: "map-script-invalid-value", mutate: |x, _| rewrite_lines( x, "ResIntensity\t", |_| vec![String::from("ResIntensity\theavy")] ) },
+        Mutation { rule_id: "map-script-value-out-of-range", mutate: |x, _| rewrite_lines( x, "ResIntensity\t", |_| vec![String::from("ResIntensity\t0")] ) },
+        Mutation { rule_id: "map-script-missing-parameter", mutate: |x, _| rewrite_lines( x, "TeamThreshold\t", |_| Vec::new() ) },
+        Mutation { rule_id: "map-script-playtest-expired", mutate: |x, _| format!( "// ges-playtest expires=2000-01-01 notes=Generated fixture\r\n{}", x ) },
+        Mutation { rule_id: "map-script-missing-group-map", mutate: |x, _| rewrite_lines( x, "TeamThreshold\t", |y| vec![String::from(y), String::from("MapGroup\tge_not_installed")] ) },
+    ]
+}
+
+/// Returns the contents with every line starting with the given text replaced by the lines the rewrite gives back for
+/// it.  Every line ends with a carriage return and newline, like the scripts the program generates.
+fn rewrite_lines( contents: &str, line_start: &str, rewrite: fn( &str ) -> Vec<String> ) -> String
+{
+    let mut rewritten_contents = String::new();
+
+    for line in contents.lines()
+    {
+        let rewritten_lines = if line.starts_with(line_start) { rewrite( line ) } else { vec![String::from(line)] };
+
+        for rewritten_line in rewritten_lines
+        {
+            rewritten_contents.push_str(&rewritten_line);
+            rewritten_contents.push_str("\r\n");
+        }
+    }
+
+    rewritten_contents
+}
+
+/// Puts quotes around every word of a line that isn't a comment or a bracket, the way KeyValues editors like to.
+fn quote_line( line: &str ) -> Vec<String>
+{
+    if line.starts_with("//") || line.starts_with('{') || line.starts_with('}') || line.trim().is_empty()
+    {
+        return vec![String::from(line)];
+    }
+
+    let indent = if line.starts_with('\t') { "\t" } else { "" };
+
+    vec![format!( "{}{}", indent, line.split_whitespace().map(|x| format!( "\"{}\"", x )).collect::<Vec<String>>().join("\t") )]
+}
+
+/// Replaces the example entry of the first gamemode section with every known gamemode at a weight of 0, leaving nothing
+/// to play the map with.
+fn zero_every_gamemode( contents: &str, known_gamemodes: &[String] ) -> String
+{
+    let zeroed_weights: String = known_gamemodes.iter().map(|x| format!( "\t{}\t\t0\r\n", x )).collect();
+
+    contents.replacen("\tYOLT\t\t0\r\n", &zeroed_weights, 1)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_every_rule_has_a_fixture()
+    {
+        assert_eq!( get_unfixtured_rules(), Vec::<&str>::new() );
+    }
+
+    #[test]
+    fn test_generate_fixtures()
+    {
+        let mut tests_dir = get_root_test_directory();
+        tests_dir.push("temp");
+        tests_dir.push("generated_fixtures");
+
+        let _ = fs::remove_dir_all(&tests_dir);
+
+        let fixture_paths = generate_fixtures( &get_barebones_args(), &tests_dir ).unwrap();
+        assert_eq!( fixture_paths.len(), get_map_script_variants().len() + get_map_script_mutations().len() );
+
+        // Generating again replaces the fixtures rather than adding to them.
+        generate_fixtures( &get_barebones_args(), &tests_dir ).unwrap();
+        assert_eq!( fs::read_dir( tests_dir.join("map_script_tests").join("invalid") ).unwrap().count(), 10 );
+
+        fs::remove_dir_all(&tests_dir).unwrap();
+    }
+}
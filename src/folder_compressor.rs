@@ -8,10 +8,11 @@
 // -----------------------------------------------------------------------------------------------------
 
 use std::fs;
+use std::collections::HashMap;
+use std::hash::Hash;
 use argument_handler::Arguments;
 
 use std::path::PathBuf;
-use std::io::{Error, ErrorKind};
 use std::io;
 
 use std::ffi::OsString;
@@ -20,35 +21,122 @@ use bzip2::Compression;
 use bzip2::read::BzEncoder;
 
 use std::fs::OpenOptions;
-use std::thread;
 
 use reslist_builder;
+use manifest;
+use release_signing;
+use delta_builder;
+use torrent_builder;
+use changelog;
+use cloud_files;
 use shared;
+use rules;
+use file_categories;
+use shared::FileInfo;
+use scan_service;
+use file_writer;
+use file_writer::FileWriter;
+use error::GesError;
+use preflight::PreflightReport;
+
+// Apache and IIS both pick up config files placed alongside what they serve.
+static APACHE_CONFIG_NAME: &str = ".htaccess";
+static IIS_CONFIG_NAME: &str = "web.config";
+
+/// Adds what the compression section needs before it runs to the preflight report.
+pub fn preflight( args: &Arguments, _map_name: &str, report: &mut PreflightReport )
+{
+    match get_compressed_directory( args )
+    {
+        Ok(x) =>
+        {
+            report.require( "Compression", &x, "a directory for compressed files rather than a file", !x.is_file() );
+            report.require( "Compression", &x, "write access to place compressed files into", file_writer::can_create_in(&x) );
+        },
+        Err(_) => report.require( "Compression", &args.rootdir, "a parent directory to place compressed files into", false ),
+    }
+
+    match args.sign_key
+    {
+        Some(ref x) => report.require( "Compression", x, "a secret key file to sign the manifest with", x.is_file() ),
+        None => {},
+    }
+}
 
-/// Compresses every file in the reslist with bz2 and moves them to an adjacent folder titled "gesource_compressed".
-/// Folder hierarchy is maintained.
-pub fn construct_compressed_filesystem( args: &Arguments, map_name: &str ) -> Result<(), Error>
+/// Compresses every file in the reslist with bz2 and moves them to an adjacent folder titled "gesource_compressed",
+/// or the compressed output directory if one was supplied.  Folder hierarchy is maintained.
+pub fn construct_compressed_filesystem( args: &Arguments, map_name: &str ) -> Result<(), GesError>
 {
     // Our fastdownload server should have everything the reslist has, plus the map itself.
     // Split these into two threads because the map is a lot bigger than the other files usually.
 
     // First figure out where our compressed files will be going.
-    let compressed_dir = get_compressed_directory( &args.rootdir )?;
+    let compressed_dir = get_compressed_directory( args )?;
 
-    // If our compressed directory already exists, and we've opted-in to a complete recompress,
-    // just delete every .bz2 file in the directory.
+    // Two runs compressing into the same fast download directory would each remove and rewrite the other's files.
+    let _compressed_lock = file_writer::lock_directory( &compressed_dir )?;
+
+    // The previous release is about to be replaced, so read what the changelog needs from it first.
+    let previous_release = match args.changelog
+    {
+        Some(_) => changelog::read_previous_release( args, map_name, &compressed_dir )?,
+        None => None,
+    };
+
+    // If our compressed directory already exists, and we've opted-in to a complete recompress, delete this release's
+    // compressed files.  The directory can be a fast download root shared with other maps, so nothing else is touched.
     if args.recompress && compressed_dir.is_dir()
     {
-        println!( "Removing all .bz2 files in directory {}!", compressed_dir.display() );
-        shared::remove_files_in_directory( &compressed_dir, "bz2" )?;
+        println!( "Removing the compressed files of {} in directory {}!", map_name, compressed_dir.display() );
+        remove_release_files( args, &compressed_dir, map_name )?;
+
+        // Server config files are only ours to remove if we're about to write them again.
+        if args.server_config
+        {
+            remove_server_config( &compressed_dir )?;
+        }
         println!( "Finished removal.");
+    }
 
-        if shared::count_files_in_directory( &compressed_dir )? != 0
+    // Cloud storage clients download online-only files as they're read, so get that over with before anything else.
+    if args.hydrate
+    {
+        cloud_files::hydrate_files( args, map_name )?;
+    }
+
+    // Make use of our cached result from the previous directory mapping.
+    let directory_tree = reslist_builder::generate_directory_tree( args )?;
+    let (ref _relevant_file_comp_list, ref relevant_file_write_list) = *directory_tree;
+
+    // Finding identical files can mean hashing some of them, so only look if someone's going to hear about it or use it.
+    let duplicate_files = if args.dedupe || rules::is_enabled( args, "compression-duplicate-files" )
+    {
+        find_duplicate_files( &args.rootdir, relevant_file_write_list )?
+    }
+    else
+    {
+        Vec::new()
+    };
+
+    warn_about_duplicate_files( args, &duplicate_files )?;
+
+    // Every copy after the first is linked to the first one's compressed file instead of being compressed itself.
+    let mut linked_files: HashMap<String, String> = HashMap::new();
+
+    if args.dedupe
+    {
+        for duplicates in &duplicate_files
         {
-            return Err(Error::new( ErrorKind::InvalidData, "gesource_compressed directory contains uncompressed or unremovable files!  Try deleting it and re-running the program." ));
+            for duplicate in &duplicates[1..]
+            {
+                linked_files.insert( duplicate.clone(), duplicates[0].clone() );
+            }
         }
     }
 
+    // Make sure we actually have room for everything before we start, rather than running out of space halfway through.
+    check_free_space( args, &compressed_dir, map_name, &linked_files )?;
+
     if args.verbose
     {
         println!("Starting file compression!");
@@ -59,7 +147,7 @@ pub fn construct_compressed_filesystem( args: &Arguments, map_name: &str ) -> Re
     let args_copy = args.clone();
     let compressed_dir_copy = compressed_dir.clone();
 
-    let map_compress_handle = thread::spawn( move || 
+    let map_compress_handle = shared::spawn_worker( "map compression", move ||
     {
         let mut map_path = PathBuf::from("maps");
         map_path.push(map_name_copy);
@@ -68,66 +156,184 @@ pub fn construct_compressed_filesystem( args: &Arguments, map_name: &str ) -> Re
         compress_file( &args_copy, &args_copy.rootdir, &compressed_dir_copy, &map_path )
     });
 
-    // Make use of our cached result from the previous directory mapping.
-    let &(ref _relevant_file_comp_list, ref relevant_file_write_list) = reslist_builder::generate_directory_tree( args )?;
-
+    // Duplicates always come after the file they're linked to, so it's been compressed by the time they're reached.
     for file_path in relevant_file_write_list
     {
         let os_path = OsString::from(file_path);
         let relative_path = PathBuf::from(&os_path);
 
-        compress_file( args, &args.rootdir, &compressed_dir, &relative_path )?;
+        match linked_files.get(file_path)
+        {
+            Some(x) =>
+            {
+                shared::print_trace( args, &format!( "{} linked instead of compressed, --dedupe is set and it's identical to {}.", file_path, x ) );
+                link_compressed_file( args, &compressed_dir, &PathBuf::from(x), &relative_path )?
+            },
+            None => compress_file( args, &args.rootdir, &compressed_dir, &relative_path )?,
+        }
+    }
+
+    // A panic while compressing the map comes back as an error, the same as any other problem with it.
+    shared::join_worker( map_compress_handle )?;
+
+    // Record what the uncompressed files should hash to, so anyone pulling them back down from a mirror can verify them.
+    let mut map_path = PathBuf::from("maps");
+    map_path.push(map_name);
+    map_path.set_extension("bsp");
+
+    let mut manifest_files = vec![map_path];
+    manifest_files.extend( relevant_file_write_list.iter().map(PathBuf::from) );
+
+    let mut manifest_path = compressed_dir.clone();
+    manifest_path.push( manifest::get_relative_manifest_path(map_name) );
+
+    manifest::write_manifest( args, &manifest_path, &args.rootdir, &manifest_files )?;
+
+    // A signature from an earlier run no longer matches the new manifest, so either replace it or get rid of it.
+    let signature_path = release_signing::get_signature_path( &manifest_path );
+
+    match args.sign_key
+    {
+        Some(ref x) => release_signing::sign_manifest( &manifest_path, x )?,
+        None => if signature_path.is_file() { file_writer::remove_file(&signature_path)?; },
+    }
+
+    if args.server_config
+    {
+        write_server_config( &compressed_dir )?;
+    }
+
+    println!("{} is ready for upload.", compressed_dir.display());
+
+    // Servers that already have the previous release only need what changed since then.
+    if args.delta_from != None
+    {
+        delta_builder::construct_delta_package( args, map_name, &compressed_dir, &manifest_path )?;
+    }
+
+    match args.torrent
+    {
+        Some(ref x) => torrent_builder::write_torrent( args, &compressed_dir, &manifest_path, x )?,
+        None => {},
+    }
+
+    match args.changelog
+    {
+        Some(ref x) => changelog::write_changelog( args, map_name, previous_release.as_ref(), &manifest_path, x )?,
+        None => {},
+    }
+
+
+    Ok(())
+}
+
+/// Removes the compressed copy of every file in this release and the last one compressed into c_root_path, along with
+/// the last one's manifest and signature.  The last release's files come from its manifest, which is how files dropped
+/// from the release since then are found without touching anything that belongs to another map.
+fn remove_release_files( args: &Arguments, c_root_path: &PathBuf, map_name: &str ) -> Result<(), GesError>
+{
+    let mut map_path = PathBuf::from("maps");
+    map_path.push(map_name);
+    map_path.set_extension("bsp");
+
+    let mut relative_paths = vec![map_path];
+    relative_paths.extend( reslist_builder::generate_directory_tree( args )?.1.iter().map(PathBuf::from) );
+
+    let manifest_path = c_root_path.join( manifest::get_relative_manifest_path(map_name) );
+
+    if manifest_path.is_file()
+    {
+        // A manifest is only read for paths inside the compressed directory, however it was edited.
+        let manifest_entries = manifest::read_manifest( &manifest_path )?.entries;
+        relative_paths.extend( manifest_entries.iter().filter(|x| manifest::is_contained_entry( &x.0 )).map(|x| PathBuf::from(&x.0)) );
     }
 
-    // Unwrap the first result so that if the child thread hit a panic it will carry up through to us.
-    // The second result carries an error that can be handled though so make sure that gets sent to 
-    // the calling function.
-    map_compress_handle.join().unwrap()?;
+    for relative_path in &relative_paths
+    {
+        let mut compressed_pathbuf = c_root_path.join( relative_path );
+        compressed_pathbuf.set_extension( create_compressed_extension(relative_path) );
 
-    println!("gesource_compressed directory is ready for upload.");
+        if compressed_pathbuf.is_file()
+        {
+            file_writer::remove_file(&compressed_pathbuf)?;
+        }
+    }
+
+    for release_file in &[release_signing::get_signature_path( &manifest_path ), manifest_path]
+    {
+        if release_file.is_file()
+        {
+            file_writer::remove_file(release_file)?;
+        }
+    }
 
     Ok(())
 }
 
 /// Compresses the file at root_path + relative_path, and places the result into c_root_path + relative_path.
-fn compress_file( args: &Arguments, root_path: &PathBuf, c_root_path: &PathBuf, relative_path: &PathBuf ) -> Result<(), Error>
+fn compress_file( args: &Arguments, root_path: &PathBuf, c_root_path: &PathBuf, relative_path: &PathBuf ) -> Result<(), GesError>
 {
     // First get the path of the original file.
     let mut uncompressed_pathbuf = root_path.clone();
     uncompressed_pathbuf.push(relative_path);
 
+    shared::set_current_file( &uncompressed_pathbuf );
+
     let mut compressed_pathbuf = c_root_path.clone();
     compressed_pathbuf.push( relative_path );
     compressed_pathbuf.set_extension( create_compressed_extension(&uncompressed_pathbuf) );
 
-    // If we don't want to remake the file, then it's good enough that it exists.
-    if !args.recompress && compressed_pathbuf.is_file()
+    let source_info = scan_service::get_scan_service().read_file_info(&uncompressed_pathbuf)?;
+
+    // If we don't want to remake the file, then it's good enough that it exists and was made from the same source.
+    if !args.recompress && is_compressed_file_current( &compressed_pathbuf, &source_info )
     {
+        shared::print_trace( args, &format!( "{} not compressed again, its compressed copy was made from this version of it.", relative_path.display() ) );
         return Ok(());
     }
 
-    // We only need to read our input file.
-    let input_file = OpenOptions::new().read(true).open(uncompressed_pathbuf)?;
+    let compress_reason = if args.recompress { "--recompress is set" } else if compressed_pathbuf.is_file() { "it changed since its compressed copy was made" } else { "it has no compressed copy yet" };
+    shared::print_trace( args, &format!( "{} compressed, {}.", relative_path.display(), compress_reason ) );
+
+    // We only need to read our input file.  Maps can easily be hundreds of megabytes, so larger files are
+    // memory-mapped to save us from pushing all of them through a read buffer.
+    let input_file = shared::open_file_source(&uncompressed_pathbuf)?;
 
     // Make sure the parent exists...but mostly just make sure that compressed_parent_folder
     // falls out of scope after we create the parent directory.
     if compressed_pathbuf.parent() != None
     {
         let compressed_parent_folder = compressed_pathbuf.parent().unwrap();
-        fs::create_dir_all(&compressed_parent_folder)?;
+        file_writer::create_dir_all(&compressed_parent_folder)?;
 
         // If this folder already exists, make sure the case matches.
-        fs::rename(&compressed_parent_folder, &compressed_parent_folder)?;
+        file_writer::rename( &compressed_parent_folder, &compressed_parent_folder )?;
     }
 
     // For the output file we want to be sure we're always overwriting any pre-existing files.
     // If it currently exists, it could be an old file.  If it's not old, we'll just get the same result.
-    // This avoids unintentional desyncs between compressed and uncompressed files.  It might be worth
-    // having an option to avoid overwriting files for savy server owners, however.
-    let mut output_file = OpenOptions::new().write(true).truncate(true).create(true).open(compressed_pathbuf)?; 
-    let mut compressor = BzEncoder::new(input_file, Compression::Best);
+    // This avoids unintentional desyncs between compressed and uncompressed files.  The file is written beside
+    // the old one and then moved over it, since with --dedupe the old one can be hard linked to other files
+    // that writing into it would change too.  It also means an interrupted run never leaves a half written file.
+    let mut partial_pathbuf = compressed_pathbuf.clone().into_os_string();
+    partial_pathbuf.push(".partial");
+    let partial_pathbuf = PathBuf::from(partial_pathbuf);
+
+    {
+        let mut output_file = FileWriter::open( OpenOptions::new().write(true).truncate(true).create(true), &partial_pathbuf )?;
+        let mut compressor = BzEncoder::new(input_file, Compression::Best);
+
+        io::copy(&mut compressor, &mut output_file)?;
 
-    io::copy(&mut compressor, &mut output_file)?;
+        // Matching the source's timestamp means mirrors that sync by modification time only pick up files that really changed.
+        match source_info.modified
+        {
+            Some(x) => output_file.set_modified(x)?,
+            None => {},
+        }
+    }
+
+    file_writer::rename( &partial_pathbuf, &compressed_pathbuf )?;
 
     if args.verbose
     {
@@ -137,6 +343,261 @@ fn compress_file( args: &Arguments, root_path: &PathBuf, c_root_path: &PathBuf,
     Ok(())
 }
 
+/// Places a hard link to the compressed copy of original_path at the compressed path of relative_path, for a file that's
+/// identical to one that's already been compressed.  Filesystems without hard links get a copy instead.
+fn link_compressed_file( args: &Arguments, c_root_path: &PathBuf, original_path: &PathBuf, relative_path: &PathBuf ) -> Result<(), GesError>
+{
+    let mut original_pathbuf = c_root_path.clone();
+    original_pathbuf.push( original_path );
+    original_pathbuf.set_extension( create_compressed_extension(original_path) );
+
+    let mut linked_pathbuf = c_root_path.clone();
+    linked_pathbuf.push( relative_path );
+    linked_pathbuf.set_extension( create_compressed_extension(relative_path) );
+
+    if linked_pathbuf.parent() != None
+    {
+        let linked_parent_folder = linked_pathbuf.parent().unwrap();
+        file_writer::create_dir_all(&linked_parent_folder)?;
+
+        // If this folder already exists, make sure the case matches.
+        file_writer::rename( &linked_parent_folder, &linked_parent_folder )?;
+    }
+
+    // An earlier run may have compressed it on its own, or linked it to a file that's changed since.
+    if linked_pathbuf.is_file()
+    {
+        file_writer::remove_file(&linked_pathbuf)?;
+    }
+
+    match file_writer::hard_link( &original_pathbuf, &linked_pathbuf )
+    {
+        Ok(_) => {},
+        Err(_) => file_writer::copy( &original_pathbuf, &linked_pathbuf ).map_err(|e| e.in_file( &original_pathbuf ))?,
+    }
+
+    if args.verbose
+    {
+        println!( "Linked {} to {}", relative_path.display(), original_path.display() );
+    }
+
+    Ok(())
+}
+
+/// Returns every set of the given files, relative to the root directory, that have identical contents.  Files are only
+/// hashed if another file has the same size, so most releases don't need any read here.
+fn find_duplicate_files( root_path: &PathBuf, relative_paths: &[String] ) -> Result<Vec<Vec<String>>, GesError>
+{
+    let scan_service = scan_service::get_scan_service();
+
+    let size_groups = group_files( relative_paths, |x| Ok(scan_service.read_file_info( &root_path.join(x) )?.size) )?;
+
+    let mut duplicate_files = Vec::new();
+
+    for size_group in size_groups.iter().filter(|x| x.len() > 1)
+    {
+        let hash_groups = group_files( size_group, |x| scan_service.get_file_hash( &root_path.join(x) ) )?;
+        duplicate_files.extend( hash_groups.into_iter().filter(|x| x.len() > 1) );
+    }
+
+    Ok(duplicate_files)
+}
+
+/// Splits the given files into groups that share the same key, keeping the order they were given in.
+fn group_files<K, F>( relative_paths: &[String], mut get_key: F ) -> Result<Vec<Vec<String>>, GesError>
+    where K: Hash + Eq, F: FnMut(&str) -> Result<K, GesError>
+{
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut group_indices: HashMap<K, usize> = HashMap::new();
+
+    for relative_path in relative_paths
+    {
+        let group_index = *group_indices.entry( get_key(relative_path)? ).or_insert_with(|| { groups.push(Vec::new()); groups.len() - 1 });
+        groups[group_index].push( relative_path.clone() );
+    }
+
+    Ok(groups)
+}
+
+/// Lets the user know about any files in the release that are identical to each other.
+fn warn_about_duplicate_files( args: &Arguments, duplicate_files: &[Vec<String>] ) -> Result<(), GesError>
+{
+    if duplicate_files.is_empty()
+    {
+        return Ok(());
+    }
+
+    let mut warning_text = String::new();
+    warning_text.push_str("The release ships identical files at more than one path:\n");
+
+    for duplicates in duplicate_files
+    {
+        warning_text.push_str("\t");
+        warning_text.push_str(&duplicates.join(", "));
+        warning_text.push_str("\n");
+    }
+
+    if args.dedupe
+    {
+        warning_text.push_str("Each is only compressed once, and its copies are hard linked to it.");
+    }
+    else
+    {
+        warning_text.push_str("Have the map use a single copy of each, or run with --dedupe to only compress each once and hard link its copies.");
+    }
+
+    rules::report_message( args, "compression-duplicate-files", warning_text )
+}
+
+/// Returns true if the compressed file exists and carries the modification time of its source, which compress_file gives
+/// every file it makes.  Anything else is either missing or was made from an older version of the source.
+fn is_compressed_file_current( compressed_pathbuf: &PathBuf, source_info: &FileInfo ) -> bool
+{
+    let compressed_modified = match fs::metadata(compressed_pathbuf)
+    {
+        Ok(ref x) if x.is_file() => x.modified().ok(),
+        _ => return false,
+    };
+
+    // Without a time to compare against, existing is all we can go on.
+    source_info.modified == None || compressed_modified == source_info.modified
+}
+
+/// Writes config files for Apache and IIS into the root of the compressed directory, so either one serves the compressed
+/// files as they are.  Both can otherwise send .bz2 files with a compression encoding the game doesn't expect, and IIS
+/// refuses to serve file types it doesn't know at all.
+fn write_server_config( c_root_path: &PathBuf ) -> Result<(), GesError>
+{
+    let mut contents = String::new();
+    contents.push_str("# Written by GE:S Map Release Assistant for fast download servers.\n");
+    contents.push_str("# Serve compressed files as they are, since the game decompresses them itself.\n");
+    contents.push_str("<IfModule mod_mime.c>\n");
+    contents.push_str("    RemoveEncoding .bz2\n");
+    contents.push_str("    AddType application/octet-stream .bz2 .sig\n");
+    contents.push_str("    AddType text/plain .manifest\n");
+    contents.push_str("</IfModule>\n");
+    contents.push_str("<IfModule mod_deflate.c>\n");
+    contents.push_str("    SetEnvIfNoCase Request_URI \\.bz2$ no-gzip\n");
+    contents.push_str("</IfModule>\n");
+
+    file_writer::write( &c_root_path.join(APACHE_CONFIG_NAME), contents.as_bytes() )?;
+
+    let mut contents = String::new();
+    contents.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\r\n");
+    contents.push_str("<!-- Written by GE:S Map Release Assistant for fast download servers. -->\r\n");
+    contents.push_str("<configuration>\r\n");
+    contents.push_str("  <system.webServer>\r\n");
+    contents.push_str("    <staticContent>\r\n");
+    contents.push_str("      <remove fileExtension=\".bz2\" />\r\n");
+    contents.push_str("      <mimeMap fileExtension=\".bz2\" mimeType=\"application/octet-stream\" />\r\n");
+    contents.push_str("      <remove fileExtension=\".sig\" />\r\n");
+    contents.push_str("      <mimeMap fileExtension=\".sig\" mimeType=\"application/octet-stream\" />\r\n");
+    contents.push_str("      <remove fileExtension=\".manifest\" />\r\n");
+    contents.push_str("      <mimeMap fileExtension=\".manifest\" mimeType=\"text/plain\" />\r\n");
+    contents.push_str("    </staticContent>\r\n");
+    contents.push_str("    <urlCompression doStaticCompression=\"false\" />\r\n");
+    contents.push_str("  </system.webServer>\r\n");
+    contents.push_str("</configuration>\r\n");
+
+    file_writer::write( &c_root_path.join(IIS_CONFIG_NAME), contents.as_bytes() )?;
+
+    println!( "Wrote fast download server config to {}.", c_root_path.display() );
+
+    Ok(())
+}
+
+/// Removes any server config files from the root of the compressed directory.
+fn remove_server_config( c_root_path: &PathBuf ) -> Result<(), GesError>
+{
+    for config_name in &[APACHE_CONFIG_NAME, IIS_CONFIG_NAME]
+    {
+        let config_path = c_root_path.join(config_name);
+
+        if config_path.is_file()
+        {
+            file_writer::remove_file(&config_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Estimates how much space the compressed files will take up and errors out if the target volume can't hold them.
+fn check_free_space( args: &Arguments, c_root_path: &PathBuf, map_name: &str, linked_files: &HashMap<String, String> ) -> Result<(), GesError>
+{
+    let mut map_path = PathBuf::from("maps");
+    map_path.push(map_name);
+    map_path.set_extension("bsp");
+
+    let directory_tree = reslist_builder::generate_directory_tree( args )?;
+    let (ref _relevant_file_comp_list, ref relevant_file_write_list) = *directory_tree;
+
+    let mut relative_paths = vec![map_path];
+    relative_paths.extend( relevant_file_write_list.iter().filter(|x| !linked_files.contains_key(*x)).map(PathBuf::from) );
+
+    let mut required_bytes: u64 = 0;
+
+    for relative_path in relative_paths
+    {
+        let mut uncompressed_pathbuf = args.rootdir.clone();
+        uncompressed_pathbuf.push(&relative_path);
+
+        let mut compressed_pathbuf = c_root_path.clone();
+        compressed_pathbuf.push(&relative_path);
+        compressed_pathbuf.set_extension( create_compressed_extension(&uncompressed_pathbuf) );
+
+        let source_info = scan_service::get_scan_service().read_file_info(&uncompressed_pathbuf)?;
+
+        // Files we're going to skip won't take up any new space.
+        if !args.recompress && is_compressed_file_current( &compressed_pathbuf, &source_info )
+        {
+            continue;
+        }
+
+        let file_size = source_info.size;
+        let ratio = file_categories::get_category( shared::get_file_extension(&uncompressed_pathbuf) ).get_compression_ratio();
+
+        required_bytes += (file_size as f64 * ratio) as u64;
+    }
+
+    // Leave ourselves some headroom since these are only estimates.
+    let required_bytes = required_bytes + required_bytes / 10;
+
+    // The compressed directory might not exist yet, so ask about the closest parent that does.
+    let mut query_path = c_root_path.as_path();
+    while !query_path.is_dir()
+    {
+        match query_path.parent()
+        {
+            Some(x) => query_path = x,
+            None => return Ok(()), // Nothing to ask about, just let the compression routine report any problems.
+        }
+    }
+
+    let available_bytes = fs2::available_space(query_path)?;
+
+    if args.verbose
+    {
+        println!( "Compression needs an estimated {} of free space, {} available.", shared::format_byte_count(required_bytes), shared::format_byte_count(available_bytes) );
+    }
+
+    if available_bytes < required_bytes
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Not enough free space to compress files into ");
+        error_text.push_str( &c_root_path.display().to_string() );
+        error_text.push_str("!\nAn estimated ");
+        error_text.push_str( &shared::format_byte_count(required_bytes) );
+        error_text.push_str(" is needed but only ");
+        error_text.push_str( &shared::format_byte_count(available_bytes) );
+        error_text.push_str(" is available.  Free up some space and try again.");
+
+        return Err(GesError::Io(io::Error::new( io::ErrorKind::StorageFull, error_text )));
+    }
+
+    Ok(())
+}
+
 /// Takes an extension "X" and returns an extension "X.bz2"
 fn create_compressed_extension( uncompressed_pathbuf: &PathBuf ) -> OsString
 {
@@ -157,13 +618,23 @@ fn create_compressed_extension( uncompressed_pathbuf: &PathBuf ) -> OsString
     compressed_extension
 }
 
-/// Returns the correct location of the gesource_compressed directory relative to the given root directory.
-fn get_compressed_directory( root_path: &PathBuf ) -> Result<PathBuf, Error>
+/// Returns the directory compressed files should be placed in.  This is the user supplied output directory if there
+/// is one, and the gesource_compressed directory adjacent to the root directory otherwise.
+fn get_compressed_directory( args: &Arguments ) -> Result<PathBuf, GesError>
 {
+    // Users uploading straight to a fast download root don't want the extra gesource folder, so we don't add one.
+    match args.compressed_out
+    {
+        Some(ref x) => return Ok(x.clone()),
+        None => {},
+    }
+
+    let root_path = &args.rootdir;
+
     // Now determine where we want the compressed version to go.
     if root_path.parent() == None
     {
-        return Err(Error::new( ErrorKind::InvalidData, "The root gesource directory must have valid parent for the compression routine to place files into." ));
+        return Err(GesError::Config( String::from("The root gesource directory must have valid parent for the compression routine to place files into.") ));
     }
 
     let mut compressed_root_pathbuf = root_path.parent().unwrap().to_path_buf();
@@ -171,4 +642,121 @@ fn get_compressed_directory( root_path: &PathBuf ) -> Result<PathBuf, Error>
     compressed_root_pathbuf.push("gesource");
 
     Ok(compressed_root_pathbuf)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests
+{
+    use std::io::Read;
+    use bzip2::read::BzDecoder;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_dedupe_then_change()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("dedupe_then_change");
+
+        let _ = fs::remove_dir_all(&test_dir);
+
+        let mut args = get_barebones_args();
+        args.rootdir = test_dir.join("gesource");
+        args.compressed_out = Some(test_dir.join("fastdl"));
+        args.compress = true;
+        args.dedupe = true;
+
+        let original_contents = b"\"LightmappedGeneric\" { \"$basetexture\" \"ge_test/wall\" }";
+
+        fs::create_dir_all( args.rootdir.join("maps") ).unwrap();
+        fs::create_dir_all( args.rootdir.join("materials/ge_test") ).unwrap();
+        fs::write( args.rootdir.join("maps/ge_test.bsp"), b"VBSP" ).unwrap();
+        fs::write( args.rootdir.join("materials/ge_test/wall_a.vmt"), &original_contents[..] ).unwrap();
+        fs::write( args.rootdir.join("materials/ge_test/wall_b.vmt"), &original_contents[..] ).unwrap();
+
+        construct_compressed_filesystem( &args, "ge_test" ).unwrap();
+
+        // Once they're no longer identical, compressing the changed one can't be allowed to change the other.
+        fs::write( args.rootdir.join("materials/ge_test/wall_a.vmt"), b"\"UnlitGeneric\" {}" ).unwrap();
+        shared::clear_directory_cache();
+
+        construct_compressed_filesystem( &args, "ge_test" ).unwrap();
+
+        let fastdl_dir = test_dir.join("fastdl");
+
+        for (file_name, expected_contents) in &[("wall_a.vmt.bz2", &b"\"UnlitGeneric\" {}"[..]), ("wall_b.vmt.bz2", &original_contents[..])]
+        {
+            let mut contents = Vec::new();
+            BzDecoder::new( fs::File::open( fastdl_dir.join("materials/ge_test").join(file_name) ).unwrap() ).read_to_end( &mut contents ).unwrap();
+
+            assert_eq!( &contents[..], *expected_contents );
+        }
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_recompress_keeps_other_files()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("recompress_other_files");
+
+        let _ = fs::remove_dir_all(&test_dir);
+
+        let mut args = get_barebones_args();
+        args.rootdir = test_dir.join("gesource");
+        args.compressed_out = Some(test_dir.join("fastdl"));
+        args.compress = true;
+        args.recompress = true;
+
+        fs::create_dir_all( args.rootdir.join("maps") ).unwrap();
+        fs::create_dir_all( args.rootdir.join("materials/ge_test") ).unwrap();
+        fs::write( args.rootdir.join("maps/ge_test.bsp"), b"VBSP" ).unwrap();
+        fs::write( args.rootdir.join("materials/ge_test/wall.vmt"), b"\"LightmappedGeneric\" {}" ).unwrap();
+
+        // Another map's files share the fast download root, and aren't ours to remove.
+        let fastdl_dir = test_dir.join("fastdl");
+        fs::create_dir_all( fastdl_dir.join("maps") ).unwrap();
+        fs::write( fastdl_dir.join("maps/ge_other.bsp.bz2"), b"BZh" ).unwrap();
+        fs::write( fastdl_dir.join("maps/ge_other.manifest"), b"\"manifest\"\n{\n}\n" ).unwrap();
+
+        construct_compressed_filesystem( &args, "ge_test" ).unwrap();
+
+        // A file dropped from the release since the last one is removed along with it.
+        fs::remove_file( args.rootdir.join("materials/ge_test/wall.vmt") ).unwrap();
+        shared::clear_directory_cache();
+
+        construct_compressed_filesystem( &args, "ge_test" ).unwrap();
+
+        assert!( fastdl_dir.join("maps/ge_other.bsp.bz2").is_file() );
+        assert!( fastdl_dir.join("maps/ge_other.manifest").is_file() );
+        assert!( fastdl_dir.join("maps/ge_test.bsp.bz2").is_file() );
+        assert!( fastdl_dir.join("maps/ge_test.manifest").is_file() );
+        assert!( !fastdl_dir.join("materials/ge_test/wall.vmt.bz2").exists() );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_find_duplicate_files()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("duplicate_files");
+
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+        fs::write( test_dir.join("a.txt"), b"same" ).unwrap();
+        fs::write( test_dir.join("b.txt"), b"diff" ).unwrap();
+        fs::write( test_dir.join("c.txt"), b"same" ).unwrap();
+        fs::write( test_dir.join("d.txt"), b"longer" ).unwrap();
+
+        let relative_paths: Vec<String> = ["a.txt", "b.txt", "c.txt", "d.txt"].iter().map(|x| String::from(*x)).collect();
+
+        // b.txt is the same size as the copies but not the same contents, so it's left out.
+        assert_eq!( find_duplicate_files( &test_dir, &relative_paths ).unwrap(), vec![vec![String::from("a.txt"), String::from("c.txt")]] );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
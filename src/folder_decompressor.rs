@@ -0,0 +1,238 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ---------------------------------------------------------------------------------------------------------
+// folder_decompressor: Turns a directory of bz2 compressed map files back into a normal gesource file tree.
+// ---------------------------------------------------------------------------------------------------------
+
+use argument_handler::Arguments;
+
+use std::path::PathBuf;
+use std::io;
+
+use bzip2::read::BzDecoder;
+
+use std::fs::OpenOptions;
+
+use manifest;
+use manifest::HashAlgorithm;
+use shared;
+use file_writer;
+use file_writer::FileWriter;
+use error::GesError;
+
+/// Decompresses every .bz2 file in the supplied compressed directory into the output directory, maintaining
+/// the folder hierarchy.  Any manifests found along the way are used to verify the decompressed files.
+pub fn expand_compressed_filesystem( args: &Arguments ) -> Result<(), GesError>
+{
+    let compressed_dir = match args.decompress
+    {
+        Some(ref x) => x.clone(),
+        None => return Err(GesError::Config( String::from("No compressed directory supplied to decompress!") )),
+    };
+
+    let output_dir = get_decompressed_directory( args, &compressed_dir )?;
+
+    if args.verbose
+    {
+        println!( "Decompressing {} into {}!", compressed_dir.display(), output_dir.display() );
+    }
+
+    // Gather up every hash we know about before we start, so each file can be checked as soon as it's written.
+    let mut manifest_entries: Vec<(String, String, HashAlgorithm)> = Vec::new();
+    let (_manifest_comp_names, manifest_write_names) = shared::get_files_in_directory( &compressed_dir, manifest::MANIFEST_EXTENSION, &[] )?;
+
+    for manifest_name in &manifest_write_names
+    {
+        let mut manifest_path = compressed_dir.clone();
+        manifest_path.push(manifest_name);
+
+        let manifest = manifest::read_manifest(&manifest_path)?;
+        let algorithm = manifest.algorithm;
+
+        manifest_entries.extend( manifest.entries.into_iter().map(|x| (x.0, x.1, algorithm)) );
+    }
+
+    if manifest_write_names.is_empty()
+    {
+        shared::print_warning( &format!( "No manifest found in {}, decompressed files will not be verified.", compressed_dir.display() ) );
+    }
+
+    let (_file_comp_names, file_write_names) = shared::get_files_in_directory( &compressed_dir, "bz2", &[] )?;
+
+    let mut decompressed_files: Vec<String> = Vec::new();
+    let mut mismatched_files: Vec<String> = Vec::new();
+
+    for compressed_name in file_write_names
+    {
+        // Source expects name.ext.bz2, so just chop off the last extension to get our original file.
+        let relative_name = String::from( &compressed_name[..compressed_name.len() - ".bz2".len()] );
+
+        let mut compressed_pathbuf = compressed_dir.clone();
+        compressed_pathbuf.push(&compressed_name);
+
+        let mut output_pathbuf = output_dir.clone();
+        output_pathbuf.push(&relative_name);
+
+        decompress_file( &compressed_pathbuf, &output_pathbuf )?;
+
+        if args.verbose
+        {
+            println!( "Decompressed {}", relative_name );
+        }
+
+        for &(ref manifest_path, ref manifest_hash, algorithm) in &manifest_entries
+        {
+            if *manifest_path == relative_name && *manifest_hash != algorithm.hash_file(&output_pathbuf)?
+            {
+                mismatched_files.push( relative_name.clone() );
+            }
+        }
+
+        decompressed_files.push(relative_name);
+    }
+
+    // Files the manifest knows about but the mirror didn't have are just as much of a problem as corrupted ones.
+    let missing_files: Vec<&String> = manifest_entries.iter().map(|x| &x.0).filter(|x| !decompressed_files.contains(x)).collect();
+
+    if !mismatched_files.is_empty() || !missing_files.is_empty()
+    {
+        let mut error_text = String::new();
+
+        if !mismatched_files.is_empty()
+        {
+            error_text.push_str("Decompressed files ");
+
+            for file in &mismatched_files
+            {
+                error_text.push_str(file); error_text.push_str(" ");
+            }
+
+            error_text.push_str("don't match the hashes in their manifest!  The mirror may have old or corrupted copies.\n");
+        }
+
+        if !missing_files.is_empty()
+        {
+            error_text.push_str("Files ");
+
+            for file in &missing_files
+            {
+                error_text.push_str(file); error_text.push_str(" ");
+            }
+
+            error_text.push_str("are listed in a manifest but weren't found in the compressed directory!");
+        }
+
+        return Err(GesError::PolicyViolation( error_text ));
+    }
+
+    println!( "Decompressed {} files into {}!", decompressed_files.len(), output_dir.display() );
+
+    Ok(())
+}
+
+/// Decompresses the bz2 file at compressed_path and writes the result to output_path.
+fn decompress_file( compressed_path: &PathBuf, output_path: &PathBuf ) -> Result<(), GesError>
+{
+    let input_file = shared::open_file_source(compressed_path)?;
+
+    if output_path.parent() != None
+    {
+        file_writer::create_dir_all( output_path.parent().unwrap() )?;
+    }
+
+    let mut output_file = FileWriter::open( OpenOptions::new().write(true).truncate(true).create(true), output_path )?;
+    let mut decompressor = BzDecoder::new(input_file);
+
+    io::copy(&mut decompressor, &mut output_file)?;
+
+    Ok(())
+}
+
+/// Returns the directory decompressed files should be placed in.  This is the user supplied output directory
+/// if there is one, and a gesource_decompressed directory adjacent to the compressed directory otherwise.
+fn get_decompressed_directory( args: &Arguments, compressed_dir: &PathBuf ) -> Result<PathBuf, GesError>
+{
+    match args.decompress_out
+    {
+        Some(ref x) => return Ok(x.clone()),
+        None => {},
+    }
+
+    if compressed_dir.parent() == None
+    {
+        return Err(GesError::Config( String::from("The compressed directory must have valid parent for the decompression routine to place files into.") ));
+    }
+
+    let mut decompressed_root_pathbuf = compressed_dir.parent().unwrap().to_path_buf();
+    decompressed_root_pathbuf.push("gesource_decompressed");
+    decompressed_root_pathbuf.push("gesource");
+
+    Ok(decompressed_root_pathbuf)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use bzip2::Compression;
+    use bzip2::read::BzEncoder;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_expand_compressed_filesystem()
+    {
+        let mut source_dir = get_root_test_directory();
+        source_dir.push("rootdir");
+        source_dir.push("gesource");
+
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("folder_decompressor");
+
+        let compressed_dir = test_dir.join("gesource");
+        let _ = fs::remove_dir_all(&test_dir);
+
+        let mut args = get_barebones_args();
+        args.decompress = Some(compressed_dir.clone());
+
+        let relative_paths = [PathBuf::from("maps/test_map.bsp"), PathBuf::from("scripts/maps/test_map.txt"), PathBuf::from("scripts/music/level_music_test_map.txt")];
+
+        for relative_path in &relative_paths
+        {
+            let compressed_path = compressed_dir.join( format!( "{}.bz2", relative_path.display() ) );
+            fs::create_dir_all( compressed_path.parent().unwrap() ).unwrap();
+
+            let mut compressor = BzEncoder::new( fs::File::open( source_dir.join(relative_path) ).unwrap(), Compression::Best );
+            io::copy( &mut compressor, &mut fs::File::create(&compressed_path).unwrap() ).unwrap();
+        }
+
+        manifest::write_manifest( &args, &compressed_dir.join("maps/test_map.manifest"), &source_dir, &relative_paths ).unwrap();
+
+        // Without an output directory, everything goes next to the compressed directory, exactly as it was compressed.
+        expand_compressed_filesystem( &args ).unwrap();
+
+        let output_dir = test_dir.join("gesource_decompressed").join("gesource");
+
+        for relative_path in &relative_paths
+        {
+            assert_eq!( fs::read( output_dir.join(relative_path) ).unwrap(), fs::read( source_dir.join(relative_path) ).unwrap() );
+        }
+
+        assert!( !output_dir.join("maps/test_map.manifest").exists() );
+
+        // A mirror with a different copy of a file, or without one at all, doesn't match the manifest.
+        let mut compressor = BzEncoder::new( &b"Not the released map."[..], Compression::Best );
+        io::copy( &mut compressor, &mut fs::File::create( compressed_dir.join("maps/test_map.bsp.bz2") ).unwrap() ).unwrap();
+        fs::remove_file( compressed_dir.join("scripts/music/level_music_test_map.txt.bz2") ).unwrap();
+
+        let error_text = expand_compressed_filesystem( &args ).unwrap_err().to_string();
+        assert!( error_text.contains("maps/test_map.bsp don't match the hashes in their manifest") );
+        assert!( error_text.contains("scripts/music/level_music_test_map.txt are listed in a manifest but weren't found") );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// fuzz_targets: Runs the script parsers over whatever input the cargo-fuzz harnesses in fuzz/ come up with.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::path::PathBuf;
+use std::str;
+use std::sync::Arc;
+
+use argument_handler::Arguments;
+use map_script_builder;
+use music_script_builder;
+use reslist_builder;
+use rules;
+use rules::Severity;
+use shared;
+
+// The name of the map the fuzzed scripts claim to be for.  Nothing by this name exists, so group and music area checks
+// have nothing to find.
+static FUZZ_MAP_NAME: &'static str = "ge_fuzz";
+
+/// Checks the given input as a map script.  Problems with the script are expected, only a panic or a hang is a bug.
+pub fn fuzz_map_script( data: &[u8] )
+{
+    let contents = match str::from_utf8( data )
+    {
+        Ok(x) => x,
+        Err(_) => return, // Scripts that aren't UTF-8 can't be read at all, so they never make it to the parser.
+    };
+
+    let args = get_fuzz_args();
+    let _ = map_script_builder::check_map_script_contents( &args, &get_fuzz_script_path( &args, "scripts/maps", "", "txt" ), contents );
+
+    rules::clear_findings();
+}
+
+/// Checks the given input as a music script, with no music available for it to use.
+pub fn fuzz_music_script( data: &[u8] )
+{
+    let contents = match str::from_utf8( data )
+    {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    let args = get_fuzz_args();
+    let _ = music_script_builder::check_music_script_contents( &args, &get_fuzz_script_path( &args, "scripts/music", "level_music_", "txt" ), contents,
+                                                               |_, _| Ok(Arc::new((Vec::new(), Vec::new()))) );
+
+    rules::clear_findings();
+}
+
+/// Checks the given input as a reslist, for a release with no files in it.
+pub fn fuzz_reslist( data: &[u8] )
+{
+    let contents = match str::from_utf8( data )
+    {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    let args = get_fuzz_args();
+    let _ = reslist_builder::check_reslist_contents( &args, &get_fuzz_script_path( &args, "maps", "", "res" ), contents, &(Vec::new(), Vec::new()) );
+
+    rules::clear_findings();
+}
+
+/// Returns the arguments scripts are fuzzed with.  Every rule that can be is only a warning, so one problem early in the
+/// script doesn't keep the fuzzer from reaching the rest of the parser.
+fn get_fuzz_args() -> Arguments
+{
+    let mut args = shared::get_barebones_args();
+
+    args.rule_overrides = rules::RULES.iter().map(|x| (String::from(x.id), Severity::Warning)).collect();
+
+    // Music runtime is read from the tracks themselves, which would have the fuzzer reading whatever files it names.
+    args.match_length = 0;
+
+    args
+}
+
+/// Returns where a script for the fuzzed map would be in the test release.
+fn get_fuzz_script_path( args: &Arguments, directory: &str, prefix: &str, extension: &str ) -> PathBuf
+{
+    let mut script_path = args.rootdir.join(directory);
+    script_path.push( format!( "{}{}", prefix, FUZZ_MAP_NAME ) );
+    script_path.set_extension(extension);
+
+    script_path
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    // Inputs bigger than anything real should still be checked well within this, rather than taking time that grows
+    // with the square of their size.
+    const MAX_CHECK_TIME_SECS: u64 = 20;
+
+    /// Runs the given check the way the fuzz targets do, but without forgetting findings other tests might be counting.
+    fn assert_check_finishes<F>( check: F )
+        where F: FnOnce( &Arguments )
+    {
+        let start = Instant::now();
+        check( &get_fuzz_args() );
+
+        assert!( start.elapsed() < Duration::from_secs( MAX_CHECK_TIME_SECS ) );
+    }
+
+    #[test]
+    fn test_pathological_map_scripts()
+    {
+        let path = PathBuf::from("ge_fuzz.txt");
+
+        let long_line = format!( "BaseWeight\t700\r\n{}\t1\r\n", "x".repeat( 2 * 1024 * 1024 ) );
+        let deep_quoting = format!( "{}BaseWeight{}\r\n", "\"".repeat( 100000 ), "\"".repeat( 100000 ) );
+        let unclosed_sections = "GamemodeWeights\r\n{\r\n\tLTK\t\t\"\"\"\r\n".repeat( 20000 );
+
+        for contents in &[long_line, deep_quoting, unclosed_sections, String::from("\""), String::from("{"), String::from("}\r\n")]
+        {
+            assert_check_finishes(|x| { let _ = map_script_builder::check_map_script_contents( x, &path, contents ); });
+        }
+    }
+
+    #[test]
+    fn test_pathological_music_scripts()
+    {
+        let path = PathBuf::from("level_music_ge_fuzz.txt");
+
+        let many_tracks = format!( "\"music\"\r\n{{\r\n{}}}\r\n", (0..20000).map(|x| format!( "\t\"file\"\t\"music/track{}.mp3\"\r\n", x % 1000 )).collect::<String>() );
+        let long_line = format!( "music {{ file {} }}", "a".repeat( 2 * 1024 * 1024 ) );
+        let deep_quoting = format!( "music {{ file {} }}", "\"".repeat( 100001 ) );
+
+        for contents in &[many_tracks, long_line, deep_quoting, String::from("music { \"\" { file a } }"), String::from("music")]
+        {
+            assert_check_finishes(|x| { let _ = music_script_builder::check_music_script_contents( x, &path, contents, |_, _| Ok(Arc::new((Vec::new(), Vec::new()))) ); });
+        }
+    }
+
+    #[test]
+    fn test_pathological_reslists()
+    {
+        let path = PathBuf::from("ge_fuzz.res");
+        let file_list: Vec<String> = (0..20000).map(|x| format!( "materials/ge_fuzz/texture{}.vtf", x )).collect();
+        let directory_tree = (file_list.clone(), file_list.clone());
+
+        let many_entries = format!( "\"resources\"\r\n{{\r\n{}}}\r\n", file_list.iter().map(|x| format!( "\t\"{}\"\t\"file\"\r\n", x.to_uppercase() )).collect::<String>() );
+        let long_line = format!( "resources {{ {} file }}", "a".repeat( 2 * 1024 * 1024 ) );
+        let deep_quoting = format!( "resources {{ {} file }}", "\"".repeat( 100001 ) );
+
+        for contents in &[many_entries, long_line, deep_quoting, String::from("resources { \"\" file }")]
+        {
+            assert_check_finishes(|x| { let _ = reslist_builder::check_reslist_contents( x, &path, contents, &directory_tree ); });
+        }
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// generation_metadata: Stamps generated files with what made them, so later runs can tell how they came to be.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::env;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use argument_handler::Arguments;
+use manifest;
+use update_checker::CURRENT_VERSION;
+use error::GesError;
+
+// Every generated file starts with a comment line beginning with this, which every format we write can hold.
+static METADATA_PREFIX: &'static str = "// ges-generated";
+
+// Only this much of the arguments hash is written, since it only has to tell runs apart rather than resist tampering.
+const ARGUMENTS_HASH_LENGTH: usize = 16;
+
+lazy_static!
+{
+    // The time every file generated from now on is stamped with, if it's been pinned to one.
+    static ref PINNED_TIME: Mutex<Option<SystemTime>> = Mutex::new(None);
+}
+
+/// What a generated file says about how it was made.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenerationMetadata
+{
+    /// The version of the program that wrote the file.
+    pub version: String,
+    /// When the file was written, in UTC.
+    pub time: String,
+    /// A hash of the settings the file was generated from.
+    pub arguments_hash: String,
+    /// A hash of everything in the file after the metadata line, ignoring carriage returns.
+    pub content_hash: String,
+}
+
+/// Whether a file was generated by this program, and whether it's been changed since.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EditState
+{
+    /// The file has no metadata line, so it was written by hand or by a version from before files were stamped.
+    Handwritten,
+    /// The file is exactly as this program generated it, so nothing is lost by generating it again.
+    Unedited(GenerationMetadata),
+    /// The file was generated, but someone has changed it since.
+    HandEdited(GenerationMetadata),
+}
+
+/// Returns the given contents of a generated file with a metadata line describing this run added to the top.
+pub fn stamp( args: &Arguments, contents: &str ) -> Result<String, GesError>
+{
+    let mut stamped_contents = String::new();
+    stamped_contents.push_str(METADATA_PREFIX);
+    stamped_contents.push_str(" version="); stamped_contents.push_str(CURRENT_VERSION);
+    stamped_contents.push_str(" time="); stamped_contents.push_str( &format_utc_time( get_generation_time() ) );
+    stamped_contents.push_str(" arguments="); stamped_contents.push_str( &get_arguments_hash( args )? );
+    stamped_contents.push_str(" content="); stamped_contents.push_str( &hash_contents( contents )? );
+    stamped_contents.push_str("\r\n");
+    stamped_contents.push_str(contents);
+
+    Ok(stamped_contents)
+}
+
+/// Returns the same contents with their metadata line, if they have one, replaced by one describing this run.  Only
+/// files that were unedited before being changed should be restamped, or hand edits would pass for generated content.
+pub fn restamp( args: &Arguments, contents: &str ) -> Result<String, GesError>
+{
+    match read_metadata( contents )
+    {
+        Some((_, x)) => stamp( args, x ),
+        None => stamp( args, contents ),
+    }
+}
+
+/// Reads the metadata line at the top of a generated file, returning it along with the rest of the contents.  Files
+/// without one weren't generated by this program, or were made by a version from before files were stamped.
+pub fn read_metadata( contents: &str ) -> Option<(GenerationMetadata, &str)>
+{
+    let (first_line, rest) = match contents.find('\n')
+    {
+        Some(x) => (&contents[..x], &contents[x + 1..]),
+        None => (contents, ""),
+    };
+
+    let fields = first_line.trim_end().strip_prefix(METADATA_PREFIX)?;
+
+    let get_field = |name: &str| fields.split_whitespace().find_map(|x| x.strip_prefix(name).and_then(|y| y.strip_prefix('='))).map(String::from);
+
+    let metadata = GenerationMetadata
+    {
+        version: get_field("version")?,
+        time: get_field("time")?,
+        arguments_hash: get_field("arguments")?,
+        content_hash: get_field("content")?,
+    };
+
+    Some((metadata, rest))
+}
+
+/// Works out whether the given file contents are still what this program generated.
+pub fn get_edit_state( contents: &str ) -> Result<EditState, GesError>
+{
+    let (metadata, rest) = match read_metadata( contents )
+    {
+        Some(x) => x,
+        None => return Ok(EditState::Handwritten),
+    };
+
+    if hash_contents( rest )? == metadata.content_hash
+    {
+        Ok(EditState::Unedited(metadata))
+    }
+    else
+    {
+        Ok(EditState::HandEdited(metadata))
+    }
+}
+
+/// Works out whether the given file is still what this program generated, and says so in verbose mode.
+pub fn report_edit_state( args: &Arguments, file_path: &Path, contents: &str ) -> Result<EditState, GesError>
+{
+    let edit_state = get_edit_state( contents )?;
+
+    if args.verbose
+    {
+        match edit_state
+        {
+            EditState::Handwritten => println!( "{} wasn't generated by this program.", file_path.display() ),
+            EditState::Unedited(ref x) => println!( "{} is unchanged since version {} generated it at {}.", file_path.display(), x.version, x.time ),
+            EditState::HandEdited(ref x) => println!( "{} has been edited by hand since version {} generated it at {}.", file_path.display(), x.version, x.time ),
+        }
+    }
+
+    Ok(edit_state)
+}
+
+/// Returns the time generated files are stamped with.  That's the time pinned with pin_generation_time, or else the one
+/// in SOURCE_DATE_EPOCH as reproducible build tools expect, or else the current time.
+pub fn get_generation_time() -> SystemTime
+{
+    match *PINNED_TIME.lock().unwrap()
+    {
+        Some(x) => return x,
+        None => {},
+    }
+
+    match env::var("SOURCE_DATE_EPOCH").ok().and_then(|x| parse_source_date_epoch( &x ))
+    {
+        Some(x) => x,
+        None => SystemTime::now(),
+    }
+}
+
+/// Stamps every file generated from now on with the given time, so generating the same files twice gives the same result.
+pub fn pin_generation_time( time: SystemTime )
+{
+    *PINNED_TIME.lock().unwrap() = Some(time);
+}
+
+/// Parses a SOURCE_DATE_EPOCH value, which is a count of seconds since 1970.
+fn parse_source_date_epoch( value: &str ) -> Option<SystemTime>
+{
+    value.trim().parse::<u64>().ok().map(|x| UNIX_EPOCH + Duration::from_secs(x))
+}
+
+/// Returns a hash of every argument that changes what's generated, so files made with the same settings share it.
+pub fn get_arguments_hash( args: &Arguments ) -> Result<String, GesError>
+{
+    let mut settings = format!( "{} {} {} {} {} {:?} {:?} {}", args.baseweight, args.minplayers, args.maxplayers, args.resintensity, args.teamthresh,
+                                args.map_group, args.section_weights, args.include_sources );
+
+    // Newer settings are only added when used, so files generated before they existed keep the same hash.
+    if args.reslist_dirs
+    {
+        settings.push_str(" reslist-dirs");
+    }
+
+    if args.sample_music > 0
+    {
+        settings.push_str( &format!( " sample-music={} music-seed={:?}", args.sample_music, args.music_seed ) );
+    }
+
+    let mut arguments_hash = manifest::hash_reader( &mut settings.as_bytes() )?;
+    arguments_hash.truncate(ARGUMENTS_HASH_LENGTH);
+
+    Ok(arguments_hash)
+}
+
+/// Hashes the contents of a generated file.  Carriage returns are left out so a file only counts as changed if
+/// something more than its line endings did.
+pub fn hash_contents( contents: &str ) -> Result<String, GesError>
+{
+    manifest::hash_reader( &mut contents.replace("\r", "").as_bytes() )
+}
+
+/// Formats a time as an ISO 8601 UTC timestamp, like 2018-06-01T12:30:00Z.
+pub fn format_utc_time( time: SystemTime ) -> String
+{
+    let seconds = match time.duration_since(UNIX_EPOCH)
+    {
+        Ok(x) => x.as_secs(),
+        Err(_) => 0,
+    };
+
+    // Turns a count of days since 1970 into a calendar date, counting years from March so leap days come last.
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!( "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, seconds % 86400 / 3600, seconds % 3600 / 60, seconds % 60 )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use super::*;
+
+    #[test]
+    fn test_format_utc_time()
+    {
+        assert_eq!( format_utc_time( UNIX_EPOCH ), "1970-01-01T00:00:00Z" );
+        assert_eq!( format_utc_time( UNIX_EPOCH + Duration::from_secs(951_782_400) ), "2000-02-29T00:00:00Z" );
+        assert_eq!( format_utc_time( UNIX_EPOCH + Duration::from_secs(1_792_173_845) ), "2026-10-16T18:04:05Z" );
+
+        assert_eq!( parse_source_date_epoch( "951782400\n" ), Some(UNIX_EPOCH + Duration::from_secs(951_782_400)) );
+        assert_eq!( parse_source_date_epoch( "yesterday" ), None );
+    }
+
+    #[test]
+    fn test_stamp()
+    {
+        let args = get_barebones_args();
+        let contents = "\"resources\"\r\n{\r\n}\r\n";
+
+        let stamped_contents = stamp( &args, contents ).unwrap();
+        let (metadata, rest) = read_metadata( &stamped_contents ).unwrap();
+
+        assert_eq!( rest, contents );
+        assert_eq!( metadata.version, CURRENT_VERSION );
+        assert_eq!( metadata.arguments_hash, get_arguments_hash( &args ).unwrap() );
+        assert_eq!( metadata.content_hash, hash_contents( "\"resources\"\n{\n}\n" ).unwrap() );
+
+        assert_eq!( read_metadata( contents ), None );
+    }
+
+    #[test]
+    fn test_get_edit_state()
+    {
+        let args = get_barebones_args();
+        let contents = "\"resources\"\r\n{\r\n\t\"sound/intro.wav\"\t\"file\"\r\n}\r\n";
+        let stamped_contents = stamp( &args, contents ).unwrap();
+
+        assert_eq!( get_edit_state( contents ).unwrap(), EditState::Handwritten );
+
+        let metadata = read_metadata( &stamped_contents ).unwrap().0;
+        assert_eq!( get_edit_state( &stamped_contents ).unwrap(), EditState::Unedited(metadata.clone()) );
+
+        // Line endings alone don't count as an edit, but anything else does.
+        assert_eq!( get_edit_state( &stamped_contents.replace("\r\n", "\n") ).unwrap(), EditState::Unedited(metadata.clone()) );
+        assert_eq!( get_edit_state( &stamped_contents.replace("intro", "outro") ).unwrap(), EditState::HandEdited(metadata) );
+
+        // Restamping keeps just the one metadata line.
+        let restamped_contents = restamp( &args, &stamped_contents ).unwrap();
+        assert_eq!( read_metadata( &restamped_contents ).unwrap().1, contents );
+    }
+}
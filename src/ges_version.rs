@@ -0,0 +1,255 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// ges_version: Works out which GE:S version a release is for, and what that version expects of its scripts.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use argument_handler::Arguments;
+use map_script_schema;
+use rules;
+use scan_service;
+use shared;
+use update_checker;
+use error::GesError;
+
+/// What a version of GE:S expects of the scripts released for it.  The map script parameters it reads are in the
+/// map script schema instead, so they can be updated without a new build.
+#[derive(Debug)]
+pub struct VersionProfile
+{
+    /// The major and minor version, like "5.0".  Patches of a version all read scripts the same way.
+    pub version: &'static str,
+    /// Gamemodes that ship with the version, which a map script can weight without a custom install.
+    pub gamemodes: &'static [&'static str],
+    /// Maps that ship with the version, whose names a custom map can't take without replacing them.
+    pub maps: &'static [&'static str],
+    /// Audio formats the version can play as music.
+    pub music_extensions: &'static [&'static str],
+}
+
+// Oldest first.  5.0 is the first version to read map scripts, so there's nothing older to check a release against.
+static VERSION_PROFILES: &[VersionProfile] = &[
+    VersionProfile
+    {
+        version: "5.0",
+        gamemodes: &["Arsenal", "CaptureTheFlag", "DeathMatch", "GunGame", "GunTrade", "LALD", "LivingDaylights", "LTK",
+                     "MWGG", "TournamentDM", "Uplink", "ViewToAKill", "YOLT"],
+        maps: &["ge_archives", "ge_aztec", "ge_basement", "ge_bunker_classic", "ge_caves", "ge_citadel", "ge_complex",
+                "ge_control", "ge_cradle", "ge_depot", "ge_facility", "ge_facility_backzone", "ge_library", "ge_runway",
+                "ge_silo", "ge_stack", "ge_temple", "ge_train"],
+        music_extensions: &["mp3"],
+    },
+];
+
+/// Decides which GE:S version the release's scripts are checked against.  That's the version given with
+/// --ges-version, or the version of the GE:S install if none was given.  Warns if the two don't match, since the
+/// release would then be tested on a different version than it's meant for.
+pub fn resolve_ges_version( args: &mut Arguments ) -> Result<(), GesError>
+{
+    let install_version = if args.offline { None } else { read_install_version( &args.gesdir ) };
+
+    match (args.ges_version.clone(), install_version)
+    {
+        (Some(ref x), Some(ref y)) if !is_same_version( x, y ) =>
+        {
+            let mut error_text = String::new();
+            error_text.push_str("This release targets GE:S ");
+            error_text.push_str(x);
+            error_text.push_str(", but the GE:S install is version ");
+            error_text.push_str(y);
+            error_text.push_str(".  Scripts will be checked against ");
+            error_text.push_str(x);
+            error_text.push_str(", so test the release on that version before putting it out.");
+
+            rules::report_message( args, "ges-version-mismatch", error_text )?;
+        },
+        (None, Some(y)) => args.ges_version = Some(y),
+        _ => {},
+    }
+
+    let profile = get_profile( args );
+    let schema = map_script_schema::get_schema( args );
+
+    // A schema file can know a version before the program does, which is enough to check its map scripts properly.
+    match args.ges_version
+    {
+        Some(ref x) if !is_same_version( x, profile.version ) && !is_same_version( x, &schema.version ) =>
+        {
+            let mut warning_text = String::new();
+            warning_text.push_str("No script rules are known for GE:S ");
+            warning_text.push_str(x);
+            warning_text.push_str(", so the rules for ");
+            warning_text.push_str(profile.version);
+            warning_text.push_str(" will be used instead.");
+
+            shared::print_warning( &warning_text );
+        },
+        _ => {},
+    }
+
+    if args.verbose
+    {
+        println!( "Checking scripts against the rules for GE:S {}, and map scripts against the schema for GE:S {}.", profile.version, schema.version );
+    }
+
+    Ok(())
+}
+
+/// Returns what the GE:S version the release targets expects of its scripts.  Versions without a profile of their
+/// own use the newest one that isn't newer than them, and releases without a known version use the newest of all.
+pub fn get_profile( args: &Arguments ) -> &'static VersionProfile
+{
+    let newest_profile = VERSION_PROFILES.last().unwrap();
+
+    let target_version = match args.ges_version
+    {
+        Some(ref x) => update_checker::get_version_numbers( x ),
+        None => return newest_profile,
+    };
+
+    VERSION_PROFILES.iter()
+                    .rev()
+                    .find(|x| update_checker::get_version_numbers( x.version ) <= target_version)
+                    .unwrap_or( newest_profile )
+}
+
+/// Returns every gamemode a map script can weight, which is the target version's stock ones along with any
+/// gameplay scripts the GE:S install has, and any the release ships itself.
+pub fn get_known_gamemodes( args: &Arguments ) -> Result<Vec<String>, GesError>
+{
+    let mut gamemodes: Vec<String> = get_profile( args ).gamemodes.iter().map(|x| String::from(*x)).collect();
+
+    if !args.offline
+    {
+        gamemodes.extend( get_gameplay_scripts( &args.gesdir )? );
+    }
+
+    if !args.fullcheck
+    {
+        gamemodes.extend( get_gameplay_scripts( &args.rootdir )? );
+    }
+
+    Ok(gamemodes)
+}
+
+/// Returns the name of every gamemode in the python gameplay directory of the given gesource directory.
+fn get_gameplay_scripts( ges_dir: &PathBuf ) -> Result<Vec<String>, GesError>
+{
+    let mut gameplay_dir = ges_dir.clone();
+    gameplay_dir.push("python");
+    gameplay_dir.push("ges");
+    gameplay_dir.push("GamePlay");
+
+    if !gameplay_dir.is_dir()
+    {
+        return Ok(Vec::new());
+    }
+
+    let gameplay_scripts = scan_service::get_scan_service().get_files( &gameplay_dir, "py", &[], shared::keep_all_files )?.1;
+
+    // Gameplay scripts sit right in the directory, and anything starting with an underscore is a helper module.
+    Ok(gameplay_scripts.iter()
+                       .filter(|x| !x.contains('/') && !x.starts_with('_'))
+                       .map(|x| String::from( x.trim_end_matches(".py") ))
+                       .collect())
+}
+
+/// Returns the version of the GE:S install in the given directory, if it says what it is.  That's the first line of
+/// version.txt, or failing that a version key in gameinfo.txt.
+pub fn read_install_version( gesdir: &PathBuf ) -> Option<String>
+{
+    match read_text_file( &gesdir.join("version.txt") )
+    {
+        Some(x) =>
+        {
+            match x.lines().next().and_then(find_version)
+            {
+                Some(y) => return Some(y),
+                None => {},
+            }
+        },
+        None => {},
+    }
+
+    let gameinfo = read_text_file( &gesdir.join("gameinfo.txt") )?;
+
+    gameinfo.lines()
+            .filter(|x| x.to_lowercase().contains("version"))
+            .filter_map(find_version)
+            .next()
+}
+
+/// Returns the first version number in the given text, like 5.0.6 in "GE:S v5.0.6".
+fn find_version( text: &str ) -> Option<String>
+{
+    text.split(|x: char| !x.is_ascii_digit() && x != '.')
+        .map(|x| x.trim_matches('.'))
+        .find(|x| x.contains('.') && !x.split('.').any(|y| y.is_empty()))
+        .map(String::from)
+}
+
+/// Returns true if the two versions are patches of the same major and minor version.
+fn is_same_version( first_version: &str, second_version: &str ) -> bool
+{
+    let first_numbers = update_checker::get_version_numbers( first_version );
+    let second_numbers = update_checker::get_version_numbers( second_version );
+
+    first_numbers.iter().take(2).eq( second_numbers.iter().take(2) )
+}
+
+/// Reads the given file as text, or returns None if it can't be read.
+fn read_text_file( file_path: &PathBuf ) -> Option<String>
+{
+    let mut contents = String::new();
+
+    match fs::File::open(file_path).and_then(|mut x| x.read_to_string(&mut contents))
+    {
+        Ok(_) => Some(contents),
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_read_install_version()
+    {
+        assert_eq!( find_version( "GoldenEye: Source v5.0.6" ), Some(String::from("5.0.6")) );
+        assert_eq!( find_version( "\"GameVersion\" \"5.0\"" ), Some(String::from("5.0")) );
+        assert_eq!( find_version( "Version 5" ), None );
+
+        // The test install's gameinfo.txt doesn't say what version it is.
+        let mut gesdir = get_root_test_directory();
+        gesdir.push("gesdir");
+        gesdir.push("gesource");
+        assert_eq!( read_install_version( &gesdir ), None );
+    }
+
+    #[test]
+    fn test_get_profile()
+    {
+        let mut args = get_barebones_args();
+        assert_eq!( get_profile( &args ).version, "5.0" );
+
+        args.ges_version = Some(String::from("5.0.6"));
+        assert_eq!( get_profile( &args ).version, "5.0" );
+
+        // Nothing is older than the first profile, so the newest one is the best guess.
+        args.ges_version = Some(String::from("4.2.4"));
+        assert_eq!( get_profile( &args ).version, VERSION_PROFILES.last().unwrap().version );
+
+        assert!( is_same_version( "5.0.6", "v5.0" ) );
+        assert!( !is_same_version( "5.1", "5.0.6" ) );
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// install_comparison: Finds the maps and scripts that differ between two GE:S installs, and brings one up to date with the other.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::path::PathBuf;
+
+use argument_handler::Arguments;
+use file_writer;
+use manifest;
+use reslist_builder;
+use scan_service;
+use shared;
+use error::GesError;
+
+// Directories holding the maps of an install and the scripts that go with them, relative to the gesource directory.
+static COMPARED_DIRECTORIES: &[&'static str] = &["maps", "scripts/maps", "scripts/music"];
+
+// Directories inside of those that each server fills in for itself, so they'd always differ.
+static SKIPPED_DIRECTORIES: &[&'static str] = &["maps/graphs/"];
+
+// The lowercase and actual path of files in an install, relative to its gesource directory.
+type InstallPaths = Vec<(String, String)>;
+
+/// The files that differ between two GE:S installs, as lowercase paths relative to their gesource directories.
+#[derive(Debug, Default)]
+pub struct InstallComparison
+{
+    pub only_in_first: Vec<String>,
+    pub only_in_second: Vec<String>,
+    /// Files both installs have, but with different contents.
+    pub differing: Vec<String>,
+    /// How many files both installs have with the same contents.
+    pub matching: usize,
+}
+
+impl InstallComparison
+{
+    /// Returns true if the installs have exactly the same maps and scripts.
+    pub fn is_identical( &self ) -> bool
+    {
+        self.only_in_first.is_empty() && self.only_in_second.is_empty() && self.differing.is_empty()
+    }
+}
+
+/// Compares the maps, map and music scripts, and everything the reslists of either install ship in the two GE:S
+/// installs.  Files only one install has are listed as such, and files both have are compared by hash.
+pub fn compare_installs( args: &Arguments, first_install: &PathBuf, second_install: &PathBuf ) -> Result<InstallComparison, GesError>
+{
+    let (first_paths, second_paths) = get_install_paths( first_install, second_install )?;
+
+    let scan_service = scan_service::get_scan_service();
+    let mut comparison = InstallComparison::default();
+
+    for &(ref comp_path, ref write_path) in &first_paths
+    {
+        let second_write_path = match second_paths.iter().find(|x| &x.0 == comp_path)
+        {
+            Some(x) => &x.1,
+            None => { comparison.only_in_first.push( comp_path.clone() ); continue; },
+        };
+
+        let first_file = first_install.join(write_path);
+        let second_file = second_install.join(second_write_path);
+
+        if args.verbose
+        {
+            println!( "Comparing {}", comp_path );
+        }
+
+        // Files of different sizes can't match, which saves hashing most of the ones that changed.
+        if scan_service.read_file_info( &first_file )?.size != scan_service.read_file_info( &second_file )?.size
+            || scan_service.get_file_hash( &first_file )? != scan_service.get_file_hash( &second_file )?
+        {
+            comparison.differing.push( comp_path.clone() );
+        }
+        else
+        {
+            comparison.matching += 1;
+        }
+    }
+
+    comparison.only_in_second = second_paths.into_iter()
+                                            .map(|x| x.0)
+                                            .filter(|x| !first_paths.iter().any(|y| &y.0 == x))
+                                            .collect();
+
+    Ok(comparison)
+}
+
+/// Copies every compared file the source install has that the destination install is missing or has a different
+/// version of, returning the path of each relative to the gesource directory.  Nothing is removed from the destination,
+/// and with dry_run nothing is copied at all.
+pub fn mirror_install( args: &Arguments, source_install: &PathBuf, destination_install: &PathBuf, dry_run: bool ) -> Result<Vec<String>, GesError>
+{
+    let comparison = compare_installs( args, source_install, destination_install )?;
+    let (source_paths, destination_paths) = get_install_paths( source_install, destination_install )?;
+
+    let mut mirrored_paths = Vec::new();
+
+    for &(ref comp_path, ref write_path) in &source_paths
+    {
+        if !comparison.only_in_first.contains(comp_path) && !comparison.differing.contains(comp_path)
+        {
+            continue;
+        }
+
+        // A changed file keeps the case it has in the destination, so a case-sensitive server doesn't end up with both.
+        let destination_path = match destination_paths.iter().find(|x| &x.0 == comp_path)
+        {
+            Some(x) => destination_install.join(&x.1),
+            None => destination_install.join(write_path),
+        };
+
+        if !dry_run
+        {
+            file_writer::create_dir_all( destination_path.parent().unwrap() )?;
+            file_writer::copy( &source_install.join(write_path), &destination_path )?;
+            shared::invalidate_directory_cache( &destination_path );
+        }
+
+        mirrored_paths.push( write_path.clone() );
+    }
+
+    Ok(mirrored_paths)
+}
+
+/// Prints which files differ between the two installs, grouped by how.
+pub fn print_comparison( first_install: &PathBuf, second_install: &PathBuf, comparison: &InstallComparison )
+{
+    let sections = [(format!( "Only in {}:", first_install.display() ), &comparison.only_in_first),
+                    (format!( "Only in {}:", second_install.display() ), &comparison.only_in_second),
+                    (String::from("Different in each install:"), &comparison.differing)];
+
+    for &(ref title, paths) in &sections
+    {
+        if paths.is_empty()
+        {
+            continue;
+        }
+
+        println!( "{}", title );
+
+        for path in paths
+        {
+            println!( "    {}", path );
+        }
+    }
+
+    println!( "{} files are only in one install, {} are different in each, and {} are the same.",
+              comparison.only_in_first.len() + comparison.only_in_second.len(), comparison.differing.len(), comparison.matching );
+}
+
+/// Returns the lowercase and actual path of every compared file in each of the two installs.
+fn get_install_paths( first_install: &PathBuf, second_install: &PathBuf ) -> Result<(InstallPaths, InstallPaths), GesError>
+{
+    let first_paths = get_compared_paths( first_install )?;
+    let second_paths = get_compared_paths( second_install )?;
+
+    // A map's content is whatever its reslist ships, and either install could have a reslist the other lacks.
+    let mut reslisted_paths = Vec::new();
+    for &(install_dir, ref install_paths) in &[(first_install, &first_paths), (second_install, &second_paths)]
+    {
+        for &(ref comp_path, ref write_path) in install_paths.iter()
+        {
+            if comp_path.starts_with("maps/") && comp_path.ends_with(".res")
+            {
+                let reslist_entries = reslist_builder::read_reslist_entries( &install_dir.join(write_path) ).map_err(|e| e.in_file( &install_dir.join(write_path) ))?;
+
+                // Reslists come with downloaded maps, so one pointing outside the install can't be allowed to pull in
+                // or overwrite files that aren't part of it.
+                for reslist_entry in reslist_entries
+                {
+                    if manifest::is_contained_entry( &reslist_entry )
+                    {
+                        reslisted_paths.push( reslist_entry );
+                    }
+                    else
+                    {
+                        shared::print_warning( &format!( "Skipping {} from {}, it points outside the install.", reslist_entry, install_dir.join(write_path).display() ) );
+                    }
+                }
+            }
+        }
+    }
+
+    Ok((add_reslisted_paths( first_install, first_paths, &reslisted_paths ), add_reslisted_paths( second_install, second_paths, &reslisted_paths )))
+}
+
+/// Returns the lowercase and actual path of every file in the compared directories of the install, relative to its
+/// gesource directory and sorted by the lowercase path.
+fn get_compared_paths( install_dir: &PathBuf ) -> Result<InstallPaths, GesError>
+{
+    let mut compared_paths = Vec::new();
+
+    for directory in COMPARED_DIRECTORIES
+    {
+        let compared_dir = install_dir.join(directory);
+
+        if !compared_dir.is_dir()
+        {
+            continue;
+        }
+
+        let (comp_paths, write_paths) = scan_service::get_scan_service().get_files( &compared_dir, "", &[], shared::keep_all_files )?;
+
+        compared_paths.extend( comp_paths.into_iter()
+                                         .zip( write_paths )
+                                         .map(|x| (format!( "{}/{}", directory, x.0 ), format!( "{}/{}", directory, x.1 )))
+                                         .filter(|x| !SKIPPED_DIRECTORIES.iter().any(|y| x.0.starts_with(y))) );
+    }
+
+    compared_paths.sort();
+    compared_paths.dedup_by(|x, y| x.0 == y.0);
+
+    Ok(compared_paths)
+}
+
+/// Adds every reslisted file the install has to its compared paths, unless it's already among them.
+fn add_reslisted_paths( install_dir: &PathBuf, mut compared_paths: InstallPaths, reslisted_paths: &[String] ) -> InstallPaths
+{
+    for reslisted_path in reslisted_paths
+    {
+        let comp_path = reslisted_path.to_lowercase();
+
+        if install_dir.join(reslisted_path).is_file() && !compared_paths.iter().any(|x| x.0 == comp_path)
+        {
+            compared_paths.push( (comp_path, reslisted_path.clone()) );
+        }
+    }
+
+    compared_paths.sort();
+    compared_paths
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use std::io::prelude::*;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_compare_and_mirror_installs()
+    {
+        let args = get_barebones_args();
+
+        let mut first_install = get_root_test_directory();
+        first_install.push("temp");
+        first_install.push("compare_installs_first");
+
+        let mut second_install = get_root_test_directory();
+        second_install.push("temp");
+        second_install.push("compare_installs_second");
+
+        for &(install_dir, files) in &[(&first_install, &[("maps/same.bsp", "map"), ("maps/changed.bsp", "old map"), ("maps/first.bsp", "map")]),
+                                       (&second_install, &[("maps/same.bsp", "map"), ("maps/Changed.bsp", "new map"), ("scripts/maps/second.txt", "script")])]
+        {
+            let _ = fs::remove_dir_all(install_dir);
+
+            for &(file_path, contents) in files.iter()
+            {
+                let file_path = install_dir.join(file_path);
+                fs::create_dir_all( file_path.parent().unwrap() ).unwrap();
+                fs::File::create(&file_path).unwrap().write_all( contents.as_bytes() ).unwrap();
+            }
+        }
+
+        let comparison = compare_installs( &args, &first_install, &second_install ).unwrap();
+
+        assert_eq!( comparison.only_in_first, vec![String::from("maps/first.bsp")] );
+        assert_eq!( comparison.only_in_second, vec![String::from("scripts/maps/second.txt")] );
+        assert_eq!( comparison.differing, vec![String::from("maps/changed.bsp")] );
+        assert_eq!( comparison.matching, 1 );
+        assert!( !comparison.is_identical() );
+
+        assert!( compare_installs( &args, &first_install, &first_install ).unwrap().is_identical() );
+
+        // A dry run lists what would be copied without touching anything.
+        let mirrored_paths = mirror_install( &args, &first_install, &second_install, true ).unwrap();
+        assert_eq!( mirrored_paths, vec![String::from("maps/changed.bsp"), String::from("maps/first.bsp")] );
+        assert!( !second_install.join("maps/first.bsp").is_file() );
+
+        // Changed files keep the destination's case, and files only the destination has are left alone.
+        mirror_install( &args, &first_install, &second_install, false ).unwrap();
+        let comparison = compare_installs( &args, &first_install, &second_install ).unwrap();
+        assert!( comparison.only_in_first.is_empty() && comparison.differing.is_empty() );
+        assert_eq!( comparison.only_in_second, vec![String::from("scripts/maps/second.txt")] );
+        assert!( second_install.join("maps/Changed.bsp").is_file() );
+
+        fs::remove_dir_all(&first_install).unwrap();
+        fs::remove_dir_all(&second_install).unwrap();
+    }
+
+    #[test]
+    fn test_mirror_install_output()
+    {
+        let args = get_barebones_args();
+
+        let mut source_install = get_root_test_directory();
+        source_install.push("rootdir");
+        source_install.push("gesource");
+
+        let mut destination_install = get_root_test_directory();
+        destination_install.push("temp");
+        destination_install.push("mirror_install_destination");
+
+        let _ = fs::remove_dir_all(&destination_install);
+        fs::create_dir_all( destination_install.join("maps") ).unwrap();
+        fs::File::create( destination_install.join("maps/local.bsp") ).unwrap().write_all(b"local map").unwrap();
+
+        let mirrored_paths = mirror_install( &args, &source_install, &destination_install, false ).unwrap();
+        assert_eq!( mirrored_paths, vec![String::from("maps/test_map.bsp"), String::from("scripts/maps/test_map.txt"), String::from("scripts/music/level_music_test_map.txt")] );
+
+        // Every mirrored file is an exact copy of the source's.
+        for mirrored_path in &mirrored_paths
+        {
+            assert_eq!( fs::read( destination_install.join(mirrored_path) ).unwrap(), fs::read( source_install.join(mirrored_path) ).unwrap() );
+        }
+
+        // Music no reslist ships isn't compared, so it isn't copied, and the destination's own files are left as they were.
+        assert!( !destination_install.join("sound/music/custom_song1.mp3").exists() );
+        assert_eq!( fs::read( destination_install.join("maps/local.bsp") ).unwrap(), b"local map" );
+
+        // Mirroring again has nothing left to copy.
+        assert!( mirror_install( &args, &source_install, &destination_install, false ).unwrap().is_empty() );
+
+        fs::remove_dir_all(&destination_install).unwrap();
+    }
+
+    #[test]
+    fn test_mirror_install_outside_entries()
+    {
+        let args = get_barebones_args();
+
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("mirror_install_outside");
+
+        let source_install = test_dir.join("source").join("gesource");
+        let destination_install = test_dir.join("destination").join("gesource");
+
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all( source_install.join("maps") ).unwrap();
+        fs::create_dir_all( test_dir.join("source").join("cfg") ).unwrap();
+        fs::create_dir_all( &destination_install ).unwrap();
+
+        fs::File::create( source_install.join("maps/ge_evil.bsp") ).unwrap().write_all(b"map").unwrap();
+        fs::File::create( source_install.join("maps/ge_evil.res") ).unwrap().write_all(b"\"resources\"\n{\n\t\"../cfg/server.cfg\"\t\"file\"\n}\n").unwrap();
+        fs::File::create( test_dir.join("source").join("cfg/server.cfg") ).unwrap().write_all(b"rcon_password hunter2").unwrap();
+
+        // The reslist's own files are mirrored, but nothing it names outside the install is.
+        let mirrored_paths = mirror_install( &args, &source_install, &destination_install, false ).unwrap();
+        assert_eq!( mirrored_paths, vec![String::from("maps/ge_evil.bsp"), String::from("maps/ge_evil.res")] );
+        assert!( !test_dir.join("destination").join("cfg/server.cfg").exists() );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
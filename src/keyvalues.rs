@@ -0,0 +1,302 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// keyvalues: Reads the KeyValues text that music scripts and reslists are written in, one token at a time.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::path::PathBuf;
+
+use shared;
+use error::GesError;
+
+/// What a token in a KeyValues script is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenKind
+{
+    /// A word, quoted or not.
+    Text,
+    /// A { opening a section.
+    Open,
+    /// A } closing a section.
+    Close,
+}
+
+/// A single token of a KeyValues script, along with where it is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Token<'a>
+{
+    pub kind: TokenKind,
+    /// The token as written, without its quotes if it had any.
+    pub text: &'a str,
+    pub quoted: bool,
+    /// Where the token starts and ends in the script, quotes included.
+    pub start: usize,
+    pub end: usize,
+    /// The line the token starts on, counting from 1.
+    pub line: usize,
+    /// True if there's whitespace between this token and the one before it.
+    pub spaced: bool,
+}
+
+impl<'a> Token<'a>
+{
+    /// Returns true if this is a word with exactly the given text, quoted or not.
+    pub fn is_text( &self, text: &str ) -> bool
+    {
+        self.kind == TokenKind::Text && self.text == text
+    }
+}
+
+/// Why a KeyValues script couldn't be read.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenError
+{
+    pub line: usize,
+    pub message: String,
+}
+
+impl TokenError
+{
+    pub fn new( line: usize, message: String ) -> TokenError
+    {
+        TokenError { line, message }
+    }
+
+    /// Returns an error saying the given token isn't what was expected there.
+    pub fn unexpected( token: &Token, expected: &str ) -> TokenError
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Expected ");
+        error_text.push_str(expected);
+        error_text.push_str(" but found ");
+        error_text.push_str( &describe( token ) );
+        error_text.push_str("!");
+
+        TokenError::new( token.line, error_text )
+    }
+
+    /// Turns the error into a parse error for the given script.
+    pub fn in_file( self, script_path: &PathBuf ) -> GesError
+    {
+        GesError::Parse { file: script_path.clone(), line: self.line, message: self.message }
+    }
+}
+
+/// Returns how to refer to the given token in a message.
+pub fn describe( token: &Token ) -> String
+{
+    match token.kind
+    {
+        TokenKind::Open => String::from("{"),
+        TokenKind::Close => String::from("}"),
+        TokenKind::Text => format!( "\"{}\"", shared::get_quotable_text( token.text ) ),
+    }
+}
+
+/// Reads the tokens of a KeyValues script in order, without looking any further ahead than the token it's on.  Comments
+/// should already be blanked out with shared::blank_out_comments.  A quote runs until the next quote, even onto later
+/// lines, but can't have a bracket in it.
+pub struct Tokenizer<'a>
+{
+    contents: &'a str,
+    position: usize,
+    line: usize,
+}
+
+impl<'a> Tokenizer<'a>
+{
+    pub fn new( contents: &'a str ) -> Tokenizer<'a>
+    {
+        Tokenizer { contents, position: 0, line: 1 }
+    }
+
+    /// Returns the next token, None at the end of the script, or an error if the rest of the script can't be read.
+    pub fn next_token( &mut self ) -> Result<Option<Token<'a>>, TokenError>
+    {
+        let whitespace_start = self.position;
+        self.skip_whitespace();
+        let spaced = self.position > whitespace_start;
+
+        let rest = &self.contents[self.position..];
+        let start = self.position;
+        let line = self.line;
+
+        let first_byte = match rest.bytes().next()
+        {
+            Some(x) => x,
+            None => return Ok(None),
+        };
+
+        let (kind, text, quoted, length) = match first_byte
+        {
+            b'{' => (TokenKind::Open, &rest[..1], false, 1),
+            b'}' => (TokenKind::Close, &rest[..1], false, 1),
+            b'"' =>
+            {
+                match rest[1..].find(['"', '{', '}'])
+                {
+                    Some(x) if rest.as_bytes()[x + 1] == b'"' => (TokenKind::Text, &rest[1..x + 1], true, x + 2),
+                    x =>
+                    {
+                        // Nothing after an unreadable quote can be trusted, so stop here.
+                        self.position = self.contents.len();
+
+                        let mut error_text = String::new();
+                        error_text.push_str("The quote opened on this line is never closed");
+                        error_text.push_str( if x == None { "!" } else { " before the next bracket!" } );
+                        error_text.push_str("  Make sure every quotation mark has a partner.");
+
+                        return Err(TokenError::new( line, error_text ));
+                    },
+                }
+            },
+            _ =>
+            {
+                let length = rest.find(|x: char| x.is_whitespace() || x == '"' || x == '{' || x == '}').unwrap_or( rest.len() );
+                (TokenKind::Text, &rest[..length], false, length)
+            },
+        };
+
+        // Quotes can run onto later lines, and the next token's line depends on it.
+        self.line += text.matches('\n').count();
+        self.position += length;
+
+        Ok(Some(Token { kind, text, quoted, start, end: start + length, line, spaced }))
+    }
+
+    /// Returns the next token, or an error naming what was expected if the script ends first.
+    pub fn expect_token( &mut self, expected: &str ) -> Result<Token<'a>, TokenError>
+    {
+        match self.next_token()?
+        {
+            Some(x) => Ok(x),
+            None =>
+            {
+                let mut error_text = String::new();
+                error_text.push_str("Expected ");
+                error_text.push_str(expected);
+                error_text.push_str(" but the script ended!");
+
+                Err(TokenError::new( self.line, error_text ))
+            },
+        }
+    }
+
+    /// Makes sure nothing but whitespace is left after the main section.
+    pub fn expect_end( &mut self, section: &str ) -> Result<(), TokenError>
+    {
+        match self.next_token()?
+        {
+            Some(x) =>
+            {
+                let mut error_text = String::new();
+                error_text.push_str("Found ");
+                error_text.push_str( &describe( &x ) );
+                error_text.push_str(" after the end of the ");
+                error_text.push_str(section);
+                error_text.push_str(" section!  Everything has to go inside of it.");
+
+                Err(TokenError::new( x.line, error_text ))
+            },
+            None => Ok(()),
+        }
+    }
+
+    fn skip_whitespace( &mut self )
+    {
+        let rest = &self.contents[self.position..];
+        let length = rest.find(|x: char| !x.is_whitespace()).unwrap_or( rest.len() );
+
+        self.line += rest[..length].matches('\n').count();
+        self.position += length;
+    }
+}
+
+#[cfg(test)]
+/// Returns the given tokens with a few of them deleted, repeated, or traded for one of the replacements, joined by a mix
+/// of whitespace.  Two unquoted words are never joined without any, since they'd just read as one word.  The same seed
+/// always gives the same script, so a failure can be reproduced.
+pub fn get_mutated_script( tokens: &[&str], replacements: &[&str], seed: u64 ) -> String
+{
+    let mut state = seed;
+    let mut next_random = |limit: usize|
+    {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 33) as usize % limit
+    };
+
+    let mut tokens: Vec<&str> = tokens.to_vec();
+
+    for _ in 0..next_random(4)
+    {
+        let index = next_random( tokens.len() );
+
+        match next_random(3)
+        {
+            0 => { tokens.remove( index ); },
+            1 => { let token = tokens[index]; tokens.insert( index, token ); },
+            _ => tokens[index] = replacements[next_random( replacements.len() )],
+        }
+
+        if tokens.is_empty()
+        {
+            break;
+        }
+    }
+
+    let is_bare_word = |x: &str| !x.starts_with('"') && !x.ends_with('"') && x != "{" && x != "}";
+    let mut script = String::new();
+
+    for (i, token) in tokens.iter().enumerate()
+    {
+        let separator = ["", " ", "\t", "\r\n"][next_random(4)];
+
+        if i > 0 && (!separator.is_empty() || !is_bare_word( tokens[i - 1] ) || !is_bare_word( token ))
+        {
+            script.push_str(separator);
+        }
+        else if i > 0
+        {
+            script.push_str(" ");
+        }
+
+        script.push_str(token);
+    }
+
+    script
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_tokenizer()
+    {
+        let mut tokenizer = Tokenizer::new( "\"music\"{\n\tfile \"a b\nc.mp3\"}bare\"q\"" );
+        let mut tokens = Vec::new();
+
+        while let Some(x) = tokenizer.next_token().unwrap()
+        {
+            tokens.push( (x.kind, x.text, x.quoted, x.line, x.spaced) );
+        }
+
+        assert_eq!( tokens, vec![(TokenKind::Text, "music", true, 1, false),
+                                 (TokenKind::Open, "{", false, 1, false),
+                                 (TokenKind::Text, "file", false, 2, true),
+                                 (TokenKind::Text, "a b\nc.mp3", true, 2, true),
+                                 (TokenKind::Close, "}", false, 3, false),
+                                 (TokenKind::Text, "bare", false, 3, false),
+                                 (TokenKind::Text, "q", true, 3, false)] );
+
+        // A quote can't swallow a bracket, and nothing after it is read.
+        let mut tokenizer = Tokenizer::new( "music\n{ \"a.mp3 }" );
+        assert!( tokenizer.next_token().is_ok() && tokenizer.next_token().is_ok() );
+        assert_eq!( tokenizer.next_token().unwrap_err().line, 2 );
+        assert_eq!( tokenizer.next_token(), Ok(None) );
+    }
+}
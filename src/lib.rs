@@ -0,0 +1,95 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ----------------------------------------------------------------------------------------
+// lib: Exposes the script builders and checkers for use by the program and other tools.
+// ----------------------------------------------------------------------------------------
+
+// The codebase predates most of these lints and consistently uses the patterns they flag,
+// so allow them crate-wide rather than sprinkling exceptions around.
+#![allow(clippy::needless_return, clippy::partialeq_to_none, clippy::ptr_arg, clippy::needless_borrow,
+         clippy::needless_borrowed_reference, clippy::needless_borrows_for_generic_args, clippy::single_char_add_str,
+         clippy::single_match, clippy::bool_comparison, clippy::collapsible_if, clippy::let_and_return,
+         clippy::manual_unwrap_or_default, clippy::needless_late_init, clippy::nonminimal_bool,
+         clippy::redundant_static_lifetimes, clippy::io_other_error)]
+
+// External Crates
+extern crate walkdir;
+extern crate clap;
+extern crate regex;
+extern crate bzip2;
+extern crate memmap2;
+extern crate fs2;
+extern crate sha2;
+extern crate sha1;
+extern crate blake3;
+extern crate ureq;
+#[macro_use] extern crate serde_json;
+extern crate zip;
+extern crate toml;
+extern crate ed25519_dalek;
+extern crate getrandom;
+#[macro_use] extern crate lazy_static;
+
+// Internal Modules
+pub mod argument_handler;
+pub mod map_script_builder;
+pub mod music_script_builder;
+pub mod reslist_builder;
+pub mod folder_compressor;
+pub mod folder_decompressor;
+pub mod manifest;
+pub mod release_signing;
+pub mod delta_builder;
+pub mod torrent_builder;
+pub mod changelog;
+pub mod texture_scanner;
+pub mod audio_scanner;
+pub mod bsp_reader;
+pub mod mdl_reader;
+pub mod consistency;
+pub mod localization;
+pub mod python_content;
+pub mod cfg_content;
+pub mod script_encoding;
+pub mod rules;
+pub mod sarif;
+pub mod annotations;
+pub mod validator;
+pub mod task_graph;
+pub mod watchdog;
+pub mod validation_cache;
+pub mod cloud_files;
+pub mod file_writer;
+pub mod preflight;
+pub mod update_checker;
+pub mod crash_report;
+pub mod error;
+pub mod shared;
+pub mod scan_service;
+pub mod file_categories;
+pub mod presets;
+pub mod user_config;
+pub mod release_lock;
+pub mod determinism;
+pub mod generation_metadata;
+pub mod map_origin;
+pub mod stock_content;
+pub mod ges_version;
+pub mod map_script_schema;
+pub mod install_comparison;
+pub mod daemon;
+pub mod discord;
+pub mod smoke_test;
+pub mod log_analyzer;
+pub mod playtest;
+pub mod weight_preview;
+pub mod keyvalues;
+pub mod fixture_generator;
+pub mod bench_fixtures;
+pub mod fuzz_targets;
+pub mod pipeline;
+pub mod compile_import;
+pub mod server_package;
@@ -0,0 +1,420 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// localization: Checks the translation and closed caption files a map ships in its resource directory.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::path::PathBuf;
+
+use argument_handler::Arguments;
+use keyvalues::{Tokenizer, TokenKind, TokenError};
+use shared;
+use rules;
+use error::GesError;
+
+// Every tag a closed caption can use to change how it's shown.
+static CAPTION_TAGS: &[&'static str] = &["b", "i", "cr", "clr", "playerclr", "sfx", "delay", "len", "norepeat", "sameline"];
+
+/// What a localization file is for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LocalizationKind
+{
+    /// gesource_[language].txt, which translates the text GE:S shows.
+    Translation,
+    /// closecaption_[language].txt, the source of the closed captions for sounds.  GE:S only reads the .dat
+    /// captioncompiler makes from it.
+    Captions,
+}
+
+/// One translated or captioned piece of text.
+#[derive(Clone, Debug, PartialEq)]
+struct LocalizationToken
+{
+    name: String,
+    text: String,
+    line: usize,
+}
+
+/// What a localization file holds.
+#[derive(Clone, Debug, PartialEq)]
+struct LocalizationContents
+{
+    /// The language the file says it's in, and the line it says so on.
+    language: Option<(String, usize)>,
+    tokens: Vec<LocalizationToken>,
+}
+
+/// Returns what the localization file with the given name is for and the language it's in, or None if it isn't one.
+pub fn get_localization_kind( file_name: &str ) -> Option<(LocalizationKind, String)>
+{
+    let file_name = file_name.to_lowercase();
+
+    let (kind, rest) = if let Some(x) = file_name.strip_prefix("gesource_") { (LocalizationKind::Translation, x) }
+                       else if let Some(x) = file_name.strip_prefix("closecaption_") { (LocalizationKind::Captions, x) }
+                       else { return None; };
+
+    match rest.strip_suffix(".txt")
+    {
+        Some(x) if !x.is_empty() && x.chars().all(|y| y.is_ascii_alphabetic()) => Some((kind, String::from(x))),
+        _ => None,
+    }
+}
+
+/// Checks every translation and closed caption file in the release's resource directory.  Problems with every file
+/// are collected so they can all be fixed in one go.
+pub fn check_localization_files( args: &Arguments ) -> Result<(), GesError>
+{
+    let mut resource_dir = args.rootdir.clone();
+    resource_dir.push("resource");
+
+    if !resource_dir.is_dir()
+    {
+        return Ok(());
+    }
+
+    let resource_tree = shared::get_cached_directory_tree( &[&resource_dir], "", &[], shared::keep_all_files )?;
+    let (ref resource_comp_names, ref resource_write_names) = *resource_tree;
+
+    let mut error_problems: Vec<String> = Vec::new();
+
+    // GE:S only looks for localization files right in the resource directory.
+    for file_name in resource_write_names.iter().filter(|x| !x.contains('/'))
+    {
+        let (kind, language) = match get_localization_kind( file_name )
+        {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let file_path = resource_dir.join(file_name);
+
+        if args.verbose
+        {
+            println!( "Checking localization file resource/{}.", file_name );
+        }
+
+        let token_count = match check_localization_file( args, &file_path, kind, &language )
+        {
+            Ok(x) => x,
+            Err(e) => { error_problems.push( e.to_string() ); continue; },
+        };
+
+        // Captions are only shown from the compiled file, so tokens without one never show up in game.
+        let compiled_name = format!( "closecaption_{}.dat", language );
+
+        if kind == LocalizationKind::Captions && token_count > 0 && !resource_comp_names.contains(&compiled_name)
+        {
+            let mut error_text = String::new();
+            error_text.push_str("resource/");
+            error_text.push_str(file_name);
+            error_text.push_str(" adds ");
+            error_text.push_str( &token_count.to_string() );
+            error_text.push_str(" caption tokens, but there's no resource/");
+            error_text.push_str(&compiled_name);
+            error_text.push_str(" to show them!  Compile it with captioncompiler and ship it alongside.");
+
+            match rules::report_in_file( args, "localization-missing-captions", &file_path, 0, GesError::PolicyViolation( error_text ) )
+            {
+                Ok(_) => {},
+                Err(e) => error_problems.push( e.to_string() ),
+            }
+        }
+    }
+
+    if !error_problems.is_empty()
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Some localization files can't be used:\n");
+
+        for problem in error_problems
+        {
+            error_text.push_str("  ");
+            error_text.push_str(&problem);
+            error_text.push_str("\n");
+        }
+
+        return Err(GesError::PolicyViolation( error_text ));
+    }
+
+    Ok(())
+}
+
+/// Checks the localization file at the given path, which should hold text in the given language.  Returns how many
+/// tokens it has.
+pub fn check_localization_file( args: &Arguments, file_path: &PathBuf, kind: LocalizationKind, language: &str ) -> Result<usize, GesError>
+{
+    shared::set_current_file( file_path );
+
+    let contents = match decode_localization_file( &shared::read_script_bytes( file_path )? )
+    {
+        Ok(x) => x,
+        Err(e) => return rules::report( args, "localization-encoding", GesError::Parse { file: file_path.clone(), line: 0, message: e } ).map(|_| 0),
+    };
+
+    rules::load_suppressions( file_path, &contents );
+
+    let localization_contents = parse_localization_contents( &shared::blank_out_comments( &contents ) ).map_err(|e| rules::fail( "localization-syntax", e.in_file( file_path ) ))?;
+
+    match localization_contents.language
+    {
+        Some((ref x, line)) if !x.eq_ignore_ascii_case(language) =>
+        {
+            let error_text = format!( "The file says it's in {}, but its name says {}!  GE:S goes by the name, so rename the file or fix its Language.", x, language );
+            rules::report( args, "localization-language-mismatch", GesError::Parse { file: file_path.clone(), line, message: error_text } )?;
+        },
+        _ => {},
+    }
+
+    let mut checked_tokens: Vec<String> = Vec::new();
+
+    for token in &localization_contents.tokens
+    {
+        let name = &token.name;
+
+        let problem = if name.is_empty() || name.contains(char::is_whitespace)
+        {
+            Some(format!( "Token {} can't be empty or have spaces in it!", shared::get_quotable_text( name ) ))
+        }
+        else if checked_tokens.contains( &name.to_lowercase() )
+        {
+            Some(format!( "Token {} is defined more than once!  GE:S will only use the last one, so remove the others.", name ))
+        }
+        else if kind == LocalizationKind::Captions
+        {
+            get_caption_tag_problem( &token.text ).map(|x| format!( "Caption for {} {}", name, x ))
+        }
+        else
+        {
+            None
+        };
+
+        match problem
+        {
+            Some(x) => rules::report( args, "localization-token", GesError::Parse { file: file_path.clone(), line: token.line, message: x } )?,
+            None => {},
+        }
+
+        checked_tokens.push( name.to_lowercase() );
+    }
+
+    Ok(localization_contents.tokens.len())
+}
+
+/// Decodes the contents of a localization file, which GE:S only reads as UTF-16 with a little endian byte order mark.
+/// Returns what's wrong with the encoding if it can't be read.
+fn decode_localization_file( contents: &[u8] ) -> Result<String, String>
+{
+    if contents.starts_with(&[0xFE, 0xFF])
+    {
+        return Err(String::from("This file is saved as big endian UTF-16, but GE:S can only read little endian UTF-16!  Save it as UTF-16 LE instead."));
+    }
+
+    if !contents.starts_with(&[0xFF, 0xFE])
+    {
+        return Err(String::from("This file isn't saved as UTF-16, so GE:S will show garbage instead of its text!  Save it as UTF-16 LE, or \"Unicode\" in Notepad."));
+    }
+
+    if !contents.len().is_multiple_of(2)
+    {
+        return Err(String::from("This file ends partway through a character, so it's most likely been cut short!"));
+    }
+
+    let characters: Vec<u16> = contents[2..].chunks(2).map(|x| u16::from_le_bytes([x[0], x[1]])).collect();
+
+    String::from_utf16( &characters ).map_err(|_| String::from("This file has characters that aren't valid UTF-16!  Save it again as UTF-16 LE."))
+}
+
+/// Reads a localization file the way GE:S does, returning the language it says it's in and every token.  Comments have to be blanked out already.  Everything sits in one root section, with the tokens in a
+/// "Tokens" section inside it.
+fn parse_localization_contents( contents: &str ) -> Result<LocalizationContents, TokenError>
+{
+    let mut tokenizer = Tokenizer::new( contents );
+
+    let root_token = tokenizer.expect_token( "\"lang\" to start the file" )?;
+    if root_token.kind != TokenKind::Text
+    {
+        return Err(TokenError::unexpected( &root_token, "\"lang\" to start the file" ));
+    }
+
+    let open_token = tokenizer.expect_token( "{ to open the lang section" )?;
+    if open_token.kind != TokenKind::Open
+    {
+        return Err(TokenError::unexpected( &open_token, "{ to open the lang section" ));
+    }
+
+    let mut language = None;
+    let mut tokens = Vec::new();
+
+    loop
+    {
+        let key_token = tokenizer.expect_token( "} to close the lang section" )?;
+
+        match key_token.kind
+        {
+            TokenKind::Close => break,
+            TokenKind::Open => return Err(TokenError::unexpected( &key_token, "Language or Tokens" )),
+            TokenKind::Text => {},
+        }
+
+        let value_token = tokenizer.expect_token( "a value" )?;
+
+        if key_token.text.eq_ignore_ascii_case("Tokens")
+        {
+            if value_token.kind != TokenKind::Open
+            {
+                return Err(TokenError::unexpected( &value_token, "{ to open the Tokens section" ));
+            }
+
+            loop
+            {
+                let name_token = tokenizer.expect_token( "} to close the Tokens section" )?;
+
+                match name_token.kind
+                {
+                    TokenKind::Close => break,
+                    TokenKind::Open => return Err(TokenError::unexpected( &name_token, "a token name" )),
+                    TokenKind::Text => {},
+                }
+
+                let text_token = tokenizer.expect_token( "the text of the token" )?;
+                if text_token.kind != TokenKind::Text
+                {
+                    return Err(TokenError::unexpected( &text_token, "the text of the token" ));
+                }
+
+                tokens.push( LocalizationToken { name: String::from(name_token.text), text: String::from(text_token.text), line: name_token.line } );
+            }
+
+            continue;
+        }
+
+        if value_token.kind != TokenKind::Text
+        {
+            return Err(TokenError::unexpected( &value_token, "a value" ));
+        }
+
+        if key_token.text.eq_ignore_ascii_case("Language")
+        {
+            language = Some((String::from(value_token.text), value_token.line));
+        }
+    }
+
+    tokenizer.expect_end( "lang" )?;
+
+    Ok(LocalizationContents { language, tokens })
+}
+
+/// Returns what's wrong with the tags in the given caption, like "has a < that's never closed!", or None if they're fine.
+fn get_caption_tag_problem( caption: &str ) -> Option<String>
+{
+    let mut rest = caption;
+
+    while let Some(tag_start) = rest.find('<')
+    {
+        let tag_end = match rest[tag_start..].find('>')
+        {
+            Some(x) => tag_start + x,
+            None => return Some(String::from("has a < that's never closed with >!")),
+        };
+
+        let tag = &rest[tag_start + 1..tag_end];
+        let tag_name = tag.split(':').next().unwrap_or("").trim().to_lowercase();
+
+        if !CAPTION_TAGS.contains( &tag_name.as_str() )
+        {
+            return Some(format!( "uses unknown tag <{}>!  Expected one of {}.", tag, CAPTION_TAGS.join(", ") ));
+        }
+
+        rest = &rest[tag_end + 1..];
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use shared::get_barebones_args;
+    use shared::get_root_test_directory;
+    use super::*;
+
+    /// Returns the given text as UTF-16 LE with a byte order mark, the way GE:S expects localization files.
+    fn encode_utf16( text: &str ) -> Vec<u8>
+    {
+        let mut contents = vec![0xFF, 0xFE];
+        contents.extend( text.encode_utf16().flat_map(|x| x.to_le_bytes().to_vec()) );
+        contents
+    }
+
+    #[test]
+    fn test_get_localization_kind()
+    {
+        assert_eq!( get_localization_kind( "gesource_english.txt" ), Some((LocalizationKind::Translation, String::from("english"))) );
+        assert_eq!( get_localization_kind( "CloseCaption_French.txt" ), Some((LocalizationKind::Captions, String::from("french"))) );
+        assert_eq!( get_localization_kind( "closecaption_english.dat" ), None );
+        assert_eq!( get_localization_kind( "gesource_.txt" ), None );
+        assert_eq!( get_localization_kind( "gameui_english.txt" ), None );
+    }
+
+    #[test]
+    fn test_decode_localization_file()
+    {
+        assert_eq!( decode_localization_file( &encode_utf16( "\"lang\" {}" ) ), Ok(String::from("\"lang\" {}")) );
+        assert!( decode_localization_file( b"\"lang\" {}" ).unwrap_err().contains("isn't saved as UTF-16") );
+        assert!( decode_localization_file( &[0xFE, 0xFF, 0x00, 0x22] ).unwrap_err().contains("big endian") );
+        assert!( decode_localization_file( &[0xFF, 0xFE, 0x22] ).is_err() );
+    }
+
+    #[test]
+    fn test_parse_localization_contents()
+    {
+        let localization_contents = parse_localization_contents( "\"lang\"\r\n{\r\n\"Language\" \"english\"\r\n\"Tokens\"\r\n{\r\n\"GE_Bunker_Alarm\" \"<clr:255,0,0>Alarm!\"\r\n}\r\n}\r\n" ).unwrap();
+        assert_eq!( localization_contents.language, Some((String::from("english"), 3)) );
+        assert_eq!( localization_contents.tokens, vec![LocalizationToken { name: String::from("GE_Bunker_Alarm"), text: String::from("<clr:255,0,0>Alarm!"), line: 6 }] );
+
+        assert!( parse_localization_contents( "\"lang\" { \"Tokens\" { \"GE_Bunker_Alarm\" } }" ).is_err() );
+        assert!( parse_localization_contents( "\"lang\" { \"Tokens\" { } } }" ).is_err() );
+
+        assert_eq!( get_caption_tag_problem( "<sfx><I>Distant gunfire<cr>Footsteps" ), None );
+        assert!( get_caption_tag_problem( "<clr:255,0,0 Alarm!" ).unwrap().contains("never closed") );
+        assert!( get_caption_tag_problem( "<color:255,0,0>Alarm!" ).unwrap().contains("unknown tag") );
+    }
+
+    #[test]
+    fn test_check_localization_files()
+    {
+        let mut args = get_barebones_args();
+        args.rootdir = get_root_test_directory();
+        args.rootdir.push("temp");
+        args.rootdir.push("localization");
+        args.rootdir.push("gesource");
+
+        let resource_dir = args.rootdir.join("resource");
+        fs::create_dir_all(&resource_dir).unwrap();
+
+        let captions = "\"lang\"\r\n{\r\n\"Language\" \"english\"\r\n\"Tokens\"\r\n{\r\n\"GE_Bunker_Alarm\" \"<sfx>Alarm!\"\r\n}\r\n}\r\n";
+        fs::write( resource_dir.join("closecaption_english.txt"), encode_utf16( captions ) ).unwrap();
+        fs::write( resource_dir.join("gesource_english.txt"), captions ).unwrap();
+
+        // Both problems should be reported at once, and missing captions are only a warning.
+        let error_text = check_localization_files( &args ).unwrap_err().to_string();
+        assert!( error_text.contains("[localization-encoding]") );
+        assert!( !error_text.contains("[localization-missing-captions]") );
+
+        fs::write( resource_dir.join("gesource_english.txt"), encode_utf16( &captions.replace("<sfx>", "") ) ).unwrap();
+        fs::write( resource_dir.join("gesource_french.txt"), encode_utf16( &captions.replace("<sfx>", "") ) ).unwrap();
+        shared::invalidate_directory_cache( &resource_dir );
+
+        // The file says it's English, but GE:S goes by the name, so that's only a warning too.
+        assert!( check_localization_files( &args ).is_ok() );
+
+        args.rule_overrides.push( (String::from("localization-missing-captions"), rules::Severity::Error) );
+        assert!( check_localization_files( &args ).unwrap_err().to_string().contains("closecaption_english.dat") );
+
+        fs::remove_dir_all( args.rootdir.parent().unwrap() ).unwrap();
+    }
+}
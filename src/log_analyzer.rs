@@ -0,0 +1,279 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// log_analyzer: Reads what a GE:S server or client complained about, and finds the scripts that need fixing for it.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use argument_handler::Arguments;
+use scan_service;
+use shared;
+use smoke_test;
+use error::GesError;
+use regex::Regex;
+
+// Lowercase text in a log line that means a client couldn't download something from the server.
+static DOWNLOAD_ERROR_MARKERS: &[&'static str] = &["download failed", "failed to download", "error downloading", "couldn't download", "unable to download"];
+
+// Lowercase text in a log line that means a map script GE:S went looking for isn't there.
+static MISSING_SCRIPT_MARKERS: &[&'static str] = &["missing map script", "no map script", "map script not found"];
+
+// What to do about a map script GE:S couldn't find.
+const MISSING_SCRIPT_SUGGESTION: &str = "Run this program on the map's release to create a map script for it, then ship it with the map.";
+
+/// Something a log says went wrong.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogProblem
+{
+    /// The lowercase path of the file the problem is about, relative to the gesource directory, or the whole log line
+    /// if it doesn't name a file.
+    pub subject: String,
+    /// What went wrong with it, worded to follow the subject.
+    pub description: &'static str,
+    /// The first line of the log it's mentioned on.
+    pub line: usize,
+    /// How many lines of the log mention it.
+    pub count: usize,
+}
+
+/// A line of a script that mentions a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptReference
+{
+    /// The path of the script relative to the gesource directory.
+    pub script: String,
+    pub line: usize,
+}
+
+/// Prints every problem in the given console log, along with the reslists and music scripts in the GE:S install that
+/// mention the file it's about.  Returns how many problems were found.
+pub fn analyze_log( args: &Arguments, log_path: &PathBuf ) -> Result<usize, GesError>
+{
+    let mut contents = Vec::new();
+    fs::File::open(log_path).and_then(|mut x| x.read_to_end(&mut contents)).map_err(|e| GesError::from(e).in_file( log_path ))?;
+
+    // Logs are whatever the engine felt like writing, which isn't always valid UTF-8.
+    let problems = find_log_problems( &String::from_utf8_lossy(&contents) );
+
+    let can_correlate = args.gesdir.is_dir();
+    if !can_correlate
+    {
+        shared::print_warning( "Without a GE:S install, problems can't be traced back to the scripts that cause them." );
+    }
+
+    for problem in &problems
+    {
+        let mentions = if problem.count == 1 { String::from("log line ") } else { format!( "{} log lines, first ", problem.count ) };
+
+        // A subject with a space in it is the whole log line, which says what went wrong better than we could.
+        if problem.subject.contains(' ')
+        {
+            println!( "\n{} ({}{})", problem.subject, mentions, problem.line );
+        }
+        else
+        {
+            println!( "\n{} {} ({}{})", problem.subject, problem.description, mentions, problem.line );
+        }
+
+        // Lines that don't name a file can't be traced anywhere, but a missing map script is fixed the same way
+        // no matter which map it's for.
+        if problem.description == "is a missing map script"
+        {
+            println!( "    {}", MISSING_SCRIPT_SUGGESTION );
+        }
+
+        if !can_correlate || problem.subject.contains(' ')
+        {
+            continue;
+        }
+
+        let references = find_script_references( &args.gesdir, &problem.subject )?;
+
+        for reference in &references
+        {
+            println!( "    Used by {} on line {}", reference.script, reference.line );
+        }
+
+        if problem.description != "is a missing map script"
+        {
+            println!( "    {}", get_fix_suggestion( args, problem, &references ) );
+        }
+    }
+
+    println!( "\nFound {} problems in {}.", problems.len(), log_path.display() );
+
+    Ok(problems.len())
+}
+
+/// Returns every problem in the given log contents, one per file, in the order they first came up.
+pub fn find_log_problems( contents: &str ) -> Vec<LogProblem>
+{
+    lazy_static!
+    {
+        static ref PATH_RE: Regex = Regex::new(r#"(?i)[\w\-./\\]+\.(bsp|res|txt|mp3|wav|vmt|vtf|mdl|vvd|vtx|phy|pcf|nav)\b"#).unwrap();
+    }
+
+    let mut problems: Vec<LogProblem> = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate()
+    {
+        let lowercase_line = line.to_lowercase();
+
+        let description = if DOWNLOAD_ERROR_MARKERS.iter().any(|x| lowercase_line.contains(x))
+        {
+            "failed to download"
+        }
+        else if MISSING_SCRIPT_MARKERS.iter().any(|x| lowercase_line.contains(x))
+        {
+            "is a missing map script"
+        }
+        else
+        {
+            match smoke_test::classify_output_line( line )
+            {
+                Some("smoke-script-error") => "couldn't be read",
+                Some("smoke-server-failed") => "stopped the map from loading",
+                Some(_) => "couldn't be found or loaded",
+                None => continue,
+            }
+        };
+
+        let subject = match PATH_RE.find( line )
+        {
+            Some(x) => get_install_path( x.as_str() ),
+            None => String::from( line.trim() ),
+        };
+
+        match problems.iter_mut().find(|x| x.subject == subject && x.description == description)
+        {
+            Some(x) => x.count += 1,
+            None => problems.push( LogProblem { subject, description, line: line_index + 1, count: 1 } ),
+        }
+    }
+
+    problems
+}
+
+/// Returns every line of a reslist or music script in the GE:S install that mentions the file at the given path.
+pub fn find_script_references( gesdir: &PathBuf, file_path: &str ) -> Result<Vec<ScriptReference>, GesError>
+{
+    // Music scripts list their tracks relative to the sound directory.
+    let script_sets = [("maps", "res", file_path), ("scripts/music", "txt", file_path.trim_start_matches("sound/"))];
+
+    let mut references = Vec::new();
+
+    for &(directory, extension, target) in &script_sets
+    {
+        let script_dir = gesdir.join(directory);
+
+        if !script_dir.is_dir()
+        {
+            continue;
+        }
+
+        let script_names = scan_service::get_scan_service().get_files( &script_dir, extension, &[], shared::keep_all_files )?.1;
+
+        for script_name in script_names
+        {
+            let script_path = script_dir.join(&script_name);
+
+            let mut contents = Vec::new();
+            fs::File::open(&script_path).and_then(|mut x| x.read_to_end(&mut contents)).map_err(|e| GesError::from(e).in_file( &script_path ))?;
+
+            for (line_index, line) in String::from_utf8_lossy(&contents).lines().enumerate()
+            {
+                if line.to_lowercase().replace("\\", "/").contains(target)
+                {
+                    references.push( ScriptReference { script: format!( "{}/{}", directory, script_name ), line: line_index + 1 } );
+                }
+            }
+        }
+    }
+
+    Ok(references)
+}
+
+/// Returns what should most likely be done about the problem, given the scripts that mention its file.
+fn get_fix_suggestion( args: &Arguments, problem: &LogProblem, references: &[ScriptReference] ) -> String
+{
+    let exists = args.gesdir.join(&problem.subject).is_file();
+    let reslisted = references.iter().any(|x| x.script.ends_with(".res"));
+
+    if problem.subject.starts_with("scripts/maps/")
+    {
+        String::from(MISSING_SCRIPT_SUGGESTION)
+    }
+    else if exists && !reslisted
+    {
+        String::from("The file is on the server but no reslist includes it, so clients are never sent it.  Add it to the map's reslist.")
+    }
+    else if exists
+    {
+        String::from("The file is on the server and reslisted, so check that the download server has the same copy.")
+    }
+    else if references.is_empty()
+    {
+        String::from("Nothing in the GE:S install mentions the file, so it's most likely used by the map itself.  Ship the file with the map.")
+    }
+    else
+    {
+        String::from("The file isn't on the server.  Ship it with the map, or remove it from the scripts above.")
+    }
+}
+
+/// Turns a path from a log into the lowercase path of the file relative to the gesource directory.  Sounds are logged
+/// relative to the sound directory.
+fn get_install_path( logged_path: &str ) -> String
+{
+    let install_path = logged_path.replace("\\", "/").to_lowercase();
+    let install_path = install_path.trim_start_matches("./");
+
+    match shared::get_string_file_extension( install_path )
+    {
+        "mp3" | "wav" if !install_path.starts_with("sound/") => format!( "sound/{}", install_path ),
+        _ => String::from(install_path),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_find_log_problems()
+    {
+        let contents = "Executing dedicated server config file server.cfg\n\
+                        Failed to load sound \"Music\\Custom_Song1.mp3\", file probably missing from disk/repository\n\
+                        Failed to load sound \"music/custom_song1.mp3\", file probably missing from disk/repository\n\
+                        Download failed for materials/custom/wall.vtf\n\
+                        Missing map script for ge_custom\n";
+
+        let problems = find_log_problems( contents );
+
+        assert_eq!( problems.len(), 3 );
+        assert_eq!( problems[0], LogProblem { subject: String::from("sound/music/custom_song1.mp3"), description: "couldn't be found or loaded", line: 2, count: 2 } );
+        assert_eq!( problems[1].subject, "materials/custom/wall.vtf" );
+        assert_eq!( problems[1].description, "failed to download" );
+        assert_eq!( problems[2].subject, "Missing map script for ge_custom" );
+    }
+
+    #[test]
+    fn test_find_script_references()
+    {
+        let mut gesdir = get_root_test_directory();
+        gesdir.push("rootdir");
+        gesdir.push("gesource");
+
+        let references = find_script_references( &gesdir, "sound/music/custom_song1.mp3" ).unwrap();
+
+        assert!( references.iter().any(|x| x.script.starts_with("scripts/music/")) );
+    }
+}
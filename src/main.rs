@@ -7,153 +7,569 @@
 // main: Entry point for the program, splits up and handles desired tasks.
 // -----------------------------------------------------------------------
 
-// External Crates
-extern crate walkdir;
-extern crate clap;
-extern crate regex;
-extern crate bzip2;
-#[macro_use] extern crate lazy_static;
+// See lib.rs, the same patterns are used here.
+#![allow(clippy::partialeq_to_none, clippy::single_match)]
+
+extern crate ges_scriptutility;
 
 // Standard Library
 use std::io;
 use std::io::prelude::*;
-use std::thread;
+use std::panic;
 
 // Internal Modules
-mod argument_handler;
-mod map_script_builder;
-mod music_script_builder;
-mod reslist_builder;
-mod folder_compressor;
-mod shared;
+use ges_scriptutility::{argument_handler, map_script_builder, music_script_builder, reslist_builder, folder_decompressor, release_signing,
+                        update_checker, crash_report, rules, sarif, annotations, shared, stock_content, install_comparison, daemon, discord,
+                        log_analyzer, weight_preview, fixture_generator, bench_fixtures, generation_metadata, determinism, compile_import};
+use ges_scriptutility::argument_handler::OutputFormat;
+use ges_scriptutility::validator::ScriptKind;
+use ges_scriptutility::error::GesError;
+use ges_scriptutility::pipeline;
+use ges_scriptutility::pipeline::RunReport;
+use ges_scriptutility::rules::Finding;
+
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 fn main()
+{
+    crash_report::install_panic_hook();
+
+    // A crash on the main thread would otherwise close the console before anyone got a chance to read it.
+    let report = match panic::catch_unwind( run )
+    {
+        Ok(x) => x,
+        Err(_) => RunReport::new( 0x0040 ), // Error 0x0040: the program crashed.
+    };
+
+    // The run is over and everything it used has been dropped, so this is the one place the program exits from.
+    exit_with_report( report );
+}
+
+/// Parses the arguments and runs whichever mode they ask for, returning how it went.
+fn run() -> RunReport
 {
     let (args, map_name) = match argument_handler::parse_and_validate_arguments()
     {
         Ok(x) => x,
-        Err(e) => { println!("[Error] failed argument parsing with error:\n{}", e); pause_then_exit( true, 0x0001 ); return; }, // Error 0x0001: invalid arguments.
+        Err(e) => { shared::print_error( &format!("failed argument parsing with error:\n{}", e) ); return RunReport::new( 0x0001 ); }, // Error 0x0001: invalid arguments.
     };
 
-    if !args.fullcheck // Default program behavior, check the script files for a given map release.
+    crash_report::set_arguments( &args );
+
+    if args.check_update // Update behavior, just let the user know if there's a newer version.
+    {
+        return check_for_update();
+    }
+
+    if args.list_rules // Rules behavior, show what each check is called and how seriously it's taken.
+    {
+        rules::print_rules( &args );
+        return RunReport::new( 0x0000 );
+    }
+
+    // Automatic checks are a courtesy, so being offline or rate limited shouldn't get in the way of the actual run.
+    if args.auto_update_check && !args.stdin
+    {
+        match update_checker::print_update_status()
+        {
+            Ok(_) => {},
+            Err(e) => if args.verbose { println!( "Skipping update check: {}", e ); },
+        }
+    }
+
+    if args.keygen != None // Keygen behavior, make a key pair for signing releases.
+    {
+        generate_signing_keys( args )
+    }
+    else if args.gen_manifest != None // Stock manifest behavior, record the hash of everything in a clean install.
+    {
+        generate_stock_manifest( args )
+    }
+    else if args.compare_installs != None // Comparison behavior, list what differs between two GE:S installs.
+    {
+        compare_ges_installs( args )
+    }
+    else if args.mirror != None // Mirror behavior, bring one GE:S install's maps and scripts up to date with another's.
+    {
+        mirror_ges_install( args )
+    }
+    else if args.gen_fixtures != None // Fixture behavior, rebuild the generated map script test fixtures.
+    {
+        generate_test_fixtures( args )
+    }
+    else if args.bench_fixtures != None // Benchmark behavior, write a synthetic release for the benchmarks to use.
+    {
+        generate_bench_fixtures( args )
+    }
+    else if args.preview_weight // Preview behavior, show how likely the map is to be picked at each player count.
+    {
+        preview_selection_weight( args )
+    }
+    else if args.analyze_log != None // Log behavior, trace what a server or client complained about back to its scripts.
+    {
+        analyze_console_log( args )
+    }
+    else if args.copy_music_from != None // Copy behavior, give the map another map's soundtrack.
+    {
+        copy_music_script( args, map_name )
+    }
+    else if args.verify_release != None // Verify behavior, check a signed release against its manifests.
+    {
+        verify_signed_release( args )
+    }
+    else if args.decompress != None // Decompress behavior, unpack a compressed directory back into a normal file tree.
+    {
+        decompress_directory( args )
+    }
+    else if args.stdin // Batch behavior, check each script path given to us on stdin.
+    {
+        validate_stdin_paths( args )
+    }
+    else if args.from_compile != None // Compile behavior, build the release from a compile output and then release it as normal.
+    {
+        release_compile_output( args, map_name )
+    }
+    else if !args.fullcheck && args.verify_deterministic // Determinism behavior, make sure the release comes out the same every time.
+    {
+        determinism::run_determinism_check( args, map_name )
+    }
+    else if !args.fullcheck // Default program behavior, check the script files for a given map release.
+    {
+        pipeline::run_map_pipeline( args, map_name )
+    }
+    else if args.daemon // Daemon behavior, keep running fullcheck on a schedule.
     {
-        create_or_verify_map_script_files( args, map_name );
+        run_fullcheck_daemon( args )
     }
     else // Fullcheck behavior, verify all script files in a given GE:S install.
     {
-        fullcheck_ges_directory( args );
+        pipeline::run_fullcheck( &args )
     }
 }
 
-/// Runs on the provided rootdir, checking to make sure that every script file exists and is valid.
-/// If a script file does not exist, it will be created.
-fn create_or_verify_map_script_files( args: argument_handler::Arguments, map_name: String )
+/// Runs fullcheck mode over and over, waiting the interval between each run.  Every run gets a report, and the
+/// notification hook is run whenever a run fails in a way the one before it didn't.  Never returns.
+fn run_fullcheck_daemon( args: argument_handler::Arguments ) -> !
 {
-    // If we made it here, we can assume we can read our target directory and the required files
-    // and directory structure are in place.  Time to start making our script files!  First let the user know.
-    if args.verbose
+    let mut previous_failures: Vec<String> = Vec::new();
+
+    loop
     {
-        println!( "Preparing to write script files for {}!", map_name );
-    }
+        // Whatever was scanned last time could have changed since, which is the whole point of checking again.
+        shared::clear_directory_cache();
 
-    // Clone the program input so rust will be happy.
-    let args_maps = args.clone();
-    let map_name_maps = map_name.clone();
+        // Each report only covers its own fullcheck, and nothing from earlier ones should pile up over days of running.
+        rules::clear_findings();
+        shared::clear_printed_errors();
+        shared::clear_warning_count();
 
-    if args.verbose
-    {
-        println!( "Verifying all script files in {}!", args.gesdir.display() );
+        let start_time = SystemTime::now();
+
+        let error_code = pipeline::run_fullcheck( &args ).error_code;
+
+        let findings = rules::get_findings();
+        let printed_errors = shared::get_printed_errors();
+        let failures = daemon::get_failures( &findings, &printed_errors );
+        let new_failures: Vec<String> = failures.iter().filter(|x| !previous_failures.contains(x)).cloned().collect();
+
+        match daemon::write_report( &args, start_time, error_code, &findings, &printed_errors )
+        {
+            Ok(report_path) =>
+            {
+                println!( "Wrote the fullcheck report to {}.", report_path.display() );
+
+                match args.notify_hook
+                {
+                    Some(ref x) if !new_failures.is_empty() =>
+                    {
+                        // A broken hook shouldn't stop the checks themselves, so it only gets a warning.
+                        match daemon::run_notify_hook( x, &report_path, &new_failures )
+                        {
+                            Ok(_) => {},
+                            Err(e) => shared::print_warning( &format!( "Failed to run the notification hook with error:\n{}", e ) ),
+                        }
+                    },
+                    _ => {},
+                }
+            },
+            Err(e) => shared::print_error( &format!( "Failed to write the fullcheck report with error:\n{}\n", e ) ),
+        }
+
+        match args.discord_webhook
+        {
+            Some(ref x) if !new_failures.is_empty() => post_discord_summary( &args, x, error_code, &findings, &printed_errors, shared::get_warning_count() ),
+            _ => {},
+        }
+
+        previous_failures = failures;
+
+        println!( "Next fullcheck at {}.", generation_metadata::format_utc_time( SystemTime::now() + Duration::from_secs( args.daemon_interval ) ) );
+        thread::sleep( Duration::from_secs( args.daemon_interval ) );
     }
+}
 
-    // Multithreading for the peformance boost and to take advantage of rust's nicer features.
-    // The error code of each thread is added and returned at the end.
-    let map_script_handle = thread::spawn( move || {
-    match map_script_builder::create_or_verify_map_script_file( &args_maps, &map_name_maps )
+/// Runs decompress mode on the supplied directory, restoring the original files and verifying them against any manifests.
+fn decompress_directory( args: argument_handler::Arguments ) -> RunReport
+{
+    let error_code = match folder_decompressor::expand_compressed_filesystem( &args )
     {
         Ok(_) => 0x0000,
-        Err(e) => { println!("[Error] Failed map script section with error:\n{}\n", e); 0x0002 },
-    }});
+        Err(e) => { shared::print_error( &format!("Failed decompression with error:\n{}\n", e) ); 0x0016 },
+    };
+
+    RunReport::new( error_code )
+}
 
-    let mut error_code = match music_script_builder::create_or_verify_music_script_file( &args, &map_name )
+/// Generates a key pair for signing releases, and tells the user which half goes where.
+fn generate_signing_keys( args: argument_handler::Arguments ) -> RunReport
+{
+    let error_code = match release_signing::generate_key_pair( args.keygen.as_ref().unwrap() )
     {
-        Ok(_) => 0x0000,
-        Err(e) => { println!("[Error] Failed music list section with error:\n{}\n", e); 0x0004 },
+        Ok((secret_key_path, public_key_path)) =>
+        {
+            println!( "Wrote the secret key to {}.  Keep it private, anyone with it can sign releases as you.", secret_key_path.display() );
+            println!( "Wrote the public key to {}.  Share it with anyone who needs to verify your releases.", public_key_path.display() );
+            0x0000
+        },
+        Err(e) => { shared::print_error( &format!("Failed key generation with error:\n{}\n", e) ); 0x0016 },
+    };
+
+    RunReport::new( error_code )
+}
+
+/// Writes a stock content file for the supplied GE:S install.
+fn generate_stock_manifest( args: argument_handler::Arguments ) -> RunReport
+{
+    let error_code = match stock_content::write_stock_content_file( &args, args.gen_manifest.as_ref().unwrap(), &args.gen_manifest_out )
+    {
+        Ok(x) =>
+        {
+            println!( "Wrote the hashes of {} stock files to {}.", x, args.gen_manifest_out.display() );
+            0x0000
+        },
+        Err(e) => { shared::print_error( &format!("Failed stock manifest generation with error:\n{}\n", e) ); 0x0016 },
+    };
+
+    RunReport::new( error_code )
+}
+
+/// Lists the maps and scripts that differ between the two supplied GE:S installs.  Installs that differ at all exit
+/// the same way warnings do, so scripts keeping servers in sync can tell when there's work to do.
+fn compare_ges_installs( args: argument_handler::Arguments ) -> RunReport
+{
+    let (ref first_install, ref second_install) = *args.compare_installs.as_ref().unwrap();
+
+    let error_code = match install_comparison::compare_installs( &args, first_install, second_install )
+    {
+        Ok(x) =>
+        {
+            install_comparison::print_comparison( first_install, second_install, &x );
+            if x.is_identical() { 0x0000 } else { 0x0080 }
+        },
+        Err(e) => { shared::print_error( &format!("Failed install comparison with error:\n{}\n", e) ); 0x0016 },
+    };
+
+    RunReport::new( error_code )
+}
+
+/// Copies whatever maps and scripts the destination install is missing or has a different version of from the
+/// source install, or just lists them in a dry run.
+fn mirror_ges_install( args: argument_handler::Arguments ) -> RunReport
+{
+    let (ref source_install, ref destination_install) = *args.mirror.as_ref().unwrap();
+
+    let error_code = match install_comparison::mirror_install( &args, source_install, destination_install, args.mirror_dry_run )
+    {
+        Ok(x) =>
+        {
+            for path in &x
+            {
+                println!( "{} {}", if args.mirror_dry_run { "Would copy" } else { "Copied" }, path );
+            }
+
+            println!( "{} {} files from {} to {}.", if args.mirror_dry_run { "Would copy" } else { "Copied" }, x.len(), source_install.display(), destination_install.display() );
+            0x0000
+        },
+        Err(e) => { shared::print_error( &format!("Failed install mirroring with error:\n{}\n", e) ); 0x0016 },
+    };
+
+    RunReport::new( error_code )
+}
+
+/// Writes the generated map script fixtures to the supplied tests directory, and lists any map script rule that still
+/// needs one.
+fn generate_test_fixtures( args: argument_handler::Arguments ) -> RunReport
+{
+    let tests_dir = args.gen_fixtures.as_ref().unwrap();
+
+    let error_code = match fixture_generator::generate_fixtures( &args, tests_dir )
+    {
+        Ok(x) =>
+        {
+            for fixture_path in &x
+            {
+                println!( "Generated {}", fixture_path.display() );
+            }
+
+            for rule_id in fixture_generator::get_unfixtured_rules()
+            {
+                shared::print_warning( &format!( "Rule {} has no generated fixture!  Add a mutation that breaks it to the fixture generator.", rule_id ) );
+            }
+
+            println!( "Generated {} map script fixtures in {}.", x.len(), tests_dir.display() );
+            0x0000
+        },
+        Err(e) => { shared::print_error( &format!("Failed fixture generation with error:\n{}\n", e) ); 0x0016 },
+    };
+
+    RunReport::new( error_code )
+}
+
+/// Writes a synthetic release with the requested number of content files to the supplied directory.
+fn generate_bench_fixtures( args: argument_handler::Arguments ) -> RunReport
+{
+    let install_dir = args.bench_fixtures.as_ref().unwrap();
+
+    let error_code = match bench_fixtures::generate_bench_install( install_dir, args.bench_file_count )
+    {
+        Ok(x) => { println!( "Generated a release of {} files for {} in {}.", x.len(), bench_fixtures::BENCH_MAP_NAME, install_dir.display() ); 0x0000 },
+        Err(e) => { shared::print_error( &format!("Failed benchmark fixture generation with error:\n{}\n", e) ); 0x0016 },
+    };
+
+    RunReport::new( error_code )
+}
+
+/// Prints the selection weight curve of the map script settings given on the commandline, or of the supplied map script.
+fn preview_selection_weight( args: argument_handler::Arguments ) -> RunReport
+{
+    let settings = weight_preview::WeightSettings { base_weight: args.baseweight, min_players: args.minplayers, max_players: args.maxplayers };
+
+    let settings = match args.preview_weight_script
+    {
+        Some(ref x) => weight_preview::read_script_settings( x, settings ).map_err(|e| e.in_file( x )),
+        None => Ok(settings),
+    };
+
+    let error_code = match settings
+    {
+        Ok(x) => { print!( "{}", weight_preview::format_weight_curve( &x, args.preview_weight_csv ) ); 0x0000 },
+        Err(e) => { shared::print_error( &format!("Failed weight preview with error:\n{}\n", e) ); 0x0016 },
+    };
+
+    RunReport::new( error_code )
+}
+
+/// Lists every problem in the supplied console log along with the scripts to fix for it.  Logs with problems exit the
+/// same way warnings do.
+fn analyze_console_log( args: argument_handler::Arguments ) -> RunReport
+{
+    let log_path = args.analyze_log.as_ref().unwrap();
+
+    let error_code = match log_analyzer::analyze_log( &args, log_path )
+    {
+        Ok(0) => 0x0000,
+        Ok(_) => 0x0080,
+        Err(e) => { shared::print_error( &format!("Failed log analysis with error:\n{}\n", e) ); 0x0016 },
     };
 
-    // We need to join here on the chance we're creating a reslist.
-    // If we start making our reslist before the other files have a chance to be made, 
-    // we could fail to include them in it!
-    error_code += map_script_handle.join().unwrap_or(0x0002);
+    RunReport::new( error_code )
+}
 
-    error_code += match reslist_builder::create_or_verify_reslist( &args, &map_name )
+/// Copies the music script of the map given with --from for the map in the root directory.
+fn copy_music_script( args: argument_handler::Arguments, map_name: String ) -> RunReport
+{
+    let source_map_name = args.copy_music_from.as_ref().unwrap();
+
+    let error_code = match music_script_builder::copy_music_script( &args, &map_name, source_map_name )
+    {
+        Ok(x) => { println!( "Copied the music script of {} to {}!", source_map_name, x.display() ); 0x0000 },
+        Err(e) => { shared::print_error( &format!("Failed to copy music script with error:\n{}\n", e) ); 0x0016 },
+    };
+
+    RunReport::new( error_code )
+}
+
+/// Copies the compile output given with --from-compile into the root directory, then releases it like any other.
+fn release_compile_output( args: argument_handler::Arguments, map_name: String ) -> RunReport
+{
+    match compile_import::import_compile_output( &args, &map_name )
+    {
+        Ok(x) => println!( "Copied {} files from the compile output into {}!", x, args.rootdir.display() ),
+        Err(e) => { shared::print_error( &format!("Failed to copy the compile output with error:\n{}\n", e) ); return RunReport::new( 0x0002 ); },
+    }
+
+    if args.verify_deterministic
+    {
+        determinism::run_determinism_check( args, map_name )
+    }
+    else
+    {
+        pipeline::run_map_pipeline( args, map_name )
+    }
+}
+
+/// Checks the signatures and hashes of a signed release.
+fn verify_signed_release( args: argument_handler::Arguments ) -> RunReport
+{
+    let error_code = match release_signing::verify_release( &args )
     {
         Ok(_) => 0x0000,
-        Err(e) => { println!("[Error] Failed reslist section with error:\n{}\n", e); 0x0008 },
+        Err(e) => { shared::print_error( &format!("Failed release verification with error:\n{}\n", e) ); 0x0016 },
     };
 
-    // We don't -always- want to build the compressed folder, as it's not ideal for map release.
-    // However, for server owners downloading the map it's quite useful so we provide the option.
-    if args.compress
+    RunReport::new( error_code )
+}
+
+/// Checks GitHub for a newer version of the program and reports what it finds.
+fn check_for_update() -> RunReport
+{
+    match update_checker::print_update_status()
     {
-        error_code += match folder_compressor::construct_compressed_filesystem( &args, &map_name )
+        Ok(_) => {},
+        Err(e) => shared::print_warning( &e.to_string() ),
+    }
+
+    RunReport::new( 0x0000 )
+}
+
+/// Runs batch mode, checking each script path read from stdin and writing the result of each check to stdout as a line of JSON.
+/// This lets editors and other tools keep a single process around instead of starting a new one for every file.
+fn validate_stdin_paths( args: argument_handler::Arguments ) -> RunReport
+{
+    let stdin = io::stdin();
+
+    for line in stdin.lock().lines()
+    {
+        let line = match line
         {
-            Ok(_) => 0x0000,
-            Err(e) => { println!("[Error] Failed compression with error:\n{}\n", e); 0x0016 },
+            Ok(x) => x,
+            Err(_) => break, // Our input is gone, so there's nothing left to do.
         };
+
+        let script_path = PathBuf::from( line.trim() );
+
+        if script_path.as_os_str().is_empty()
+        {
+            continue;
+        }
+
+        let kind = ScriptKind::from_path( &script_path );
+        let starting_warning_count = shared::get_warning_count();
+        let starting_suppressed_count = rules::get_suppressed_count();
+
+        let result = match kind
+        {
+            Some(ScriptKind::MapScript) => map_script_builder::check_map_script_file( &args, &script_path ),
+            Some(ScriptKind::MusicScript) => music_script_builder::check_music_script_file( &args, &script_path ),
+            Some(ScriptKind::Reslist) => reslist_builder::check_reslist( &args, &script_path ),
+            None => Err(GesError::Config( String::from("Not a map script, music script, or reslist!") )),
+        };
+
+        let kind_json = match kind
+        {
+            Some(x) => format!("\"{}\"", x.get_name()),
+            None => String::from("null"),
+        };
+
+        let (error_json, category_json) = match result
+        {
+            Ok(_) => (String::from("null"), String::from("null")),
+            Err(ref e) => (format!("\"{}\"", shared::escape_json_string( &e.to_string() )), format!("\"{}\"", e.get_category())),
+        };
+
+        println!( "{{\"path\":\"{}\",\"kind\":{},\"valid\":{},\"warnings\":{},\"suppressed\":{},\"category\":{},\"error\":{}}}",
+                  shared::escape_json_string( &script_path.to_string_lossy() ), kind_json, result.is_ok(),
+                  shared::get_warning_count() - starting_warning_count, rules::get_suppressed_count() - starting_suppressed_count,
+                  category_json, error_json );
+
+        // Whoever is on the other end is waiting on this result, so don't leave it sitting in a buffer.
+        let _ = io::stdout().flush();
     }
 
-    // We made it to the end!  Return our error code, which is the combined result of each module that may have failed.
-    pause_then_exit( !args.noexitprompt, error_code );
+    // There's nobody at a console to see an exit prompt in batch mode, and every script's warnings are already in its
+    // result, so they don't change how the run exits.
+    RunReport { error_code: 0x0000, warning_count: 0, interactive: false }
 }
 
-/// Runs fullcheck mode on the GE:S directory, checking every single script file for validity.
-fn fullcheck_ges_directory( args: argument_handler::Arguments )
+/// Writes every problem found during the run in whichever extra formats were asked for, and posts a summary of the
+/// run to Discord if asked to.
+fn write_finding_reports( args: &argument_handler::Arguments, exit_code: i32 )
 {
-    let args_maps = args.clone();
-    let args_music = args.clone();
+    match args.sarif
+    {
+        Some(ref x) => match sarif::write_sarif_log( args, x )
+        {
+            Ok(_) => if args.verbose { println!( "Wrote SARIF log to {}", x.display() ); },
+            Err(e) => shared::print_error( &format!( "Failed to write SARIF log to {} with error:\n{}\n", x.display(), e ) ),
+        },
+        None => {},
+    }
 
-    if args.verbose
+    if args.output == OutputFormat::Github
     {
-        println!( "Verifying all script files in {}!", args.gesdir.display() );
+        annotations::print_github_annotations();
     }
 
-    // Multithreading for the peformance boost and to take advantage of rust's nicer features.
-    // The error code of each thread is added and returned at the end.
-    let map_script_handle = thread::spawn( move || {
-    match map_script_builder::fullcheck_map_script_files( &args_maps )
+    match args.discord_webhook
     {
-        Ok(_) => 0x0000,
-        Err(e) => { println!("[Error] Failed map script section with error:\n{}\n", e); 0x0002 },
-    }});
+        Some(ref x) => post_discord_summary( args, x, exit_code, &rules::get_findings(), &shared::get_printed_errors(), shared::get_warning_count() ),
+        None => {},
+    }
+}
 
-    let music_script_handle = thread::spawn( move || {
-    match music_script_builder::fullcheck_music_script_files( &args_music )
+/// Posts a summary of the run to Discord.  The run is already over by then, so failing to only gets a warning.
+fn post_discord_summary( args: &argument_handler::Arguments, webhook_url: &str, exit_code: i32, findings: &[Finding], printed_errors: &[String], warning_count: usize )
+{
+    match discord::post_message( webhook_url, &discord::build_summary( args, exit_code, findings, printed_errors, warning_count ) )
     {
-        Ok(_) => 0x0000,
-        Err(e) => { println!("[Error] Failed music script section with error:\n{}\n", e); 0x0004 },
-    }});
+        Ok(_) => {},
+        Err(e) => shared::print_warning( &e.to_string() ),
+    }
+}
 
-    let mut error_code = match reslist_builder::fullcheck_reslist_files( &args )
+/// Writes whatever reports were asked for, offers a crash report and the exit prompt if anyone's there to see them, then
+/// exits the program with the run's exit code.
+fn exit_with_report( report: RunReport ) -> !
+{
+    let exit_code = report.get_exit_code();
+    let args = crash_report::get_arguments();
+
+    match args
     {
-        Ok(_) => 0x0000,
-        Err(e) => { println!("[Error] Failed reslist section with error:\n{}\n", e); 0x0008 },
+        Some(ref x) => write_finding_reports( x, exit_code ),
+        None => {}, // Arguments never made it far enough to ask for any reports.
+    }
+
+    // Without arguments there's no telling whether the prompt was turned off, so show it.
+    let show_exit_prompt = report.interactive && match args
+    {
+        Some(ref x) => !x.noexitprompt,
+        None => true,
     };
-    
-    error_code += music_script_handle.join().unwrap_or(0x0004);
-    error_code += map_script_handle.join().unwrap_or(0x0002);
 
-    // We made it to the end!  Return our error code, which is the combined result of each module that may have failed.
-    pause_then_exit( !args.noexitprompt, error_code );
-}
+    // Bad arguments are the user's to fix, but anything else going wrong could be a bug worth reporting.
+    if report.interactive && ((exit_code != 0x0000 && exit_code != 0x0080 && exit_code != 0x0001) || crash_report::has_panicked())
+    {
+        crash_report::offer_crash_report( show_exit_prompt );
+    }
 
-/// If enabled, provides a prompt to the user and then exits the program with the provided error code.
-fn pause_then_exit( show_exit_prompt: bool, exit_code: i32 )
-{
     // Prompt the user for input then proceed once that input has been given.
     if show_exit_prompt // But only if we haven't disabled it.
     {
+        let pause_timeout = match args
+        {
+            Some(ref x) => x.pause_timeout,
+            None => 0,
+        };
+
         println!("\nPress Enter to continue.");
-        let _ = io::stdin().read(&mut [0u8]);
+
+        if shared::read_line_with_timeout( pause_timeout ) == None && pause_timeout > 0
+        {
+            println!( "No input after {} seconds, exiting.", pause_timeout );
+        }
     }
 
     std::process::exit( exit_code );
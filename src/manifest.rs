@@ -0,0 +1,342 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// --------------------------------------------------------------------------------------------------
+// manifest: Records and verifies the hashes of released files so mirrored copies can be checked.
+// --------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::fmt;
+use std::io::prelude::*;
+use std::thread;
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sha2::{Sha256, Digest};
+use blake3;
+
+use regex::Regex;
+
+use shared;
+use scan_service;
+use file_writer;
+use generation_metadata;
+use argument_handler::Arguments;
+use file_writer::FileWriter;
+use error::GesError;
+
+// Manifests sit next to the compressed map so a fast download mirror carries them along with everything else.
+pub static MANIFEST_EXTENSION: &str = "manifest";
+
+/// The ways a manifest can hash the files it lists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm
+{
+    Sha256,
+    /// Several times faster than SHA-256, which adds up over a release that's gigabytes in size.
+    Blake3,
+}
+
+/// Every hash algorithm, by the name it's given on the command line and in manifests.
+pub static HASH_ALGORITHMS: &'static [HashAlgorithm] = &[HashAlgorithm::Sha256, HashAlgorithm::Blake3];
+
+impl HashAlgorithm
+{
+    /// Parses an algorithm as written on the command line or in a manifest.
+    pub fn from_name( algorithm: &str ) -> Option<HashAlgorithm>
+    {
+        HASH_ALGORITHMS.iter().find(|x| x.to_string() == algorithm.to_lowercase()).cloned()
+    }
+
+    /// Hashes the contents of the given file, returning the digest as a lowercase hex string.
+    pub fn hash_file( self, file_path: &Path ) -> Result<String, GesError>
+    {
+        self.hash_reader( &mut shared::open_file_source( file_path )? )
+    }
+
+    /// Hashes everything left in the given reader, returning the digest as a lowercase hex string.
+    pub fn hash_reader<R: Read>( self, source: &mut R ) -> Result<String, GesError>
+    {
+        let mut sha256_hasher = Sha256::new();
+        let mut blake3_hasher = blake3::Hasher::new();
+
+        let mut buffer = vec![0u8; 64 * 1024];
+
+        loop
+        {
+            let read_count = source.read(&mut buffer)?;
+
+            if read_count == 0
+            {
+                break;
+            }
+
+            match self
+            {
+                HashAlgorithm::Sha256 => sha256_hasher.update(&buffer[..read_count]),
+                HashAlgorithm::Blake3 => { blake3_hasher.update(&buffer[..read_count]); },
+            }
+        }
+
+        match self
+        {
+            HashAlgorithm::Sha256 => Ok(sha256_hasher.finalize().iter().map(|x| format!("{:02x}", x)).collect()),
+            HashAlgorithm::Blake3 => Ok(blake3_hasher.finalize().to_hex().to_string()),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match *self
+        {
+            HashAlgorithm::Sha256 => f.pad("sha256"),
+            HashAlgorithm::Blake3 => f.pad("blake3"),
+        }
+    }
+}
+
+/// The files a manifest lists and how their hashes were made.
+pub struct Manifest
+{
+    pub algorithm: HashAlgorithm,
+    /// The (relative path, hash) pairs of every file, in the order they're listed.
+    pub entries: Vec<(String, String)>,
+}
+
+/// Hashes the contents of the given file with SHA-256, returning the digest as a lowercase hex string.
+pub fn hash_file( file_path: &Path ) -> Result<String, GesError>
+{
+    HashAlgorithm::Sha256.hash_file( file_path )
+}
+
+/// Hashes everything left in the given reader with SHA-256, returning the digest as a lowercase hex string.
+pub fn hash_reader<R: Read>( source: &mut R ) -> Result<String, GesError>
+{
+    HashAlgorithm::Sha256.hash_reader( source )
+}
+
+/// Hashes every given file with the given algorithm, spread across as many threads as the machine has cores, and
+/// returns the hashes in the same order as the files.
+pub fn hash_files( algorithm: HashAlgorithm, file_paths: &[PathBuf] ) -> Result<Vec<String>, GesError>
+{
+    let thread_count = thread::available_parallelism().map(|x| x.get()).unwrap_or(1).min( file_paths.len() ).max(1);
+
+    // Each thread takes the next file nobody's started on, so one huge map doesn't leave the others sitting idle.
+    let next_index = AtomicUsize::new(0);
+    let hashes: Mutex<Vec<Option<String>>> = Mutex::new(vec![None; file_paths.len()]);
+
+    let results: Vec<Result<(), GesError>> = thread::scope(|scope|
+    {
+        let handles: Vec<_> = (0..thread_count).map(|_| shared::spawn_scoped_worker( scope, ||
+        {
+            loop
+            {
+                let index = next_index.fetch_add( 1, Ordering::SeqCst );
+
+                if index >= file_paths.len()
+                {
+                    return Ok(());
+                }
+
+                // So a panic while hashing is reported against the file that caused it.
+                shared::set_current_file( &file_paths[index] );
+
+                let file_hash = scan_service::get_scan_service().get_file_hash_with( &file_paths[index], algorithm )?;
+                hashes.lock().unwrap()[index] = Some(file_hash);
+            }
+        })).collect();
+
+        // A panic in one of the threads comes back as an error, just like with the compression threads.
+        handles.into_iter().map(shared::join_scoped_worker).collect()
+    });
+
+    for result in results
+    {
+        result?;
+    }
+
+    Ok(hashes.into_inner().unwrap().into_iter().map(|x| x.unwrap()).collect())
+}
+
+/// Writes a manifest containing the hash of every file in relative_paths, as found under root_path, made with the
+/// algorithm from the arguments.  The manifest uses the same bracketed format as the other script files:
+/// "algorithm" "[algorithm]"
+/// "[path/to/file]" "[hash]"
+pub fn write_manifest( args: &Arguments, manifest_path: &PathBuf, root_path: &PathBuf, relative_paths: &[PathBuf] ) -> Result<(), GesError>
+{
+    let file_paths: Vec<PathBuf> = relative_paths.iter().map(|x| root_path.join(x)).collect();
+    let file_hashes = hash_files( args.hash_algorithm, &file_paths )?;
+
+    let mut contents = String::new();
+    contents.push_str("\"manifest\"\r\n");
+    contents.push_str("{\r\n");
+    contents.push_str("\t\"algorithm\"\t\""); contents.push_str( &args.hash_algorithm.to_string() ); contents.push_str("\"\r\n");
+
+    for (relative_path, file_hash) in relative_paths.iter().zip(file_hashes.iter())
+    {
+        let path_string = relative_path.to_string_lossy().replace("\\", "/");
+
+        contents.push_str("\t\""); contents.push_str(&path_string); contents.push_str("\"\t\"");
+        contents.push_str(file_hash); contents.push_str("\"\r\n");
+    }
+
+    contents.push_str("}\r\n");
+
+    // A release that hasn't changed keeps its manifest as it was, so mirrors and torrents don't see a new one every run.
+    match fs::read_to_string(manifest_path).ok().as_ref().and_then(|x| generation_metadata::read_metadata(x))
+    {
+        Some((ref x, y)) if y == contents && x.arguments_hash == generation_metadata::get_arguments_hash( args )? => return Ok(()),
+        _ => {},
+    }
+
+    match manifest_path.parent()
+    {
+        Some(x) => file_writer::create_dir_all(x)?,
+        None => {},
+    }
+
+    let mut manifest_file = FileWriter::create(manifest_path)?;
+    manifest_file.write_all( generation_metadata::stamp( args, &contents )?.as_bytes() )?;
+
+    Ok(())
+}
+
+/// Reads a manifest file, returning the files it lists and how they were hashed.  Manifests from before the algorithm
+/// could be chosen don't name one, and were always SHA-256.
+pub fn read_manifest( manifest_path: &PathBuf ) -> Result<Manifest, GesError>
+{
+    let contents = fs::read_to_string(manifest_path)?;
+
+    lazy_static!
+    {
+        static ref FILE_RE: Regex = Regex::new(r#"(?x)^(\s*//[^\n]*\n)*\s*"manifest"\s*
+                                \{
+                                (\s*"algorithm"\s+"[0-9a-zA-Z]+")?
+                                (\s*"[^"\{\}]*"\s+"[0-9a-fA-F]+"\s*)*
+                                \}\s*$"#).unwrap();
+
+        static ref ALGORITHM_RE: Regex = Regex::new(r#""algorithm"\s+"([0-9a-zA-Z]+)""#).unwrap();
+
+        static ref RE: Regex = Regex::new(r#""([^"\{\}]*)"\s+"([0-9a-fA-F]+)""#).unwrap();
+    }
+
+    if !FILE_RE.is_match(&contents)
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Manifest ");
+        error_text.push_str( &manifest_path.display().to_string() );
+        error_text.push_str(" is not formatted correctly!");
+
+        return Err(GesError::Parse { file: manifest_path.clone(), line: 0, message: error_text });
+    }
+
+    let algorithm = match ALGORITHM_RE.captures(&contents)
+    {
+        Some(cap) => match HashAlgorithm::from_name( &cap[1] )
+        {
+            Some(x) => x,
+            None =>
+            {
+                let mut error_text = String::new();
+                error_text.push_str("Manifest ");
+                error_text.push_str( &manifest_path.display().to_string() );
+                error_text.push_str(" was hashed with ");
+                error_text.push_str(&cap[1]);
+                error_text.push_str(", which this version of the program doesn't know!");
+
+                return Err(GesError::Parse { file: manifest_path.clone(), line: 0, message: error_text });
+            },
+        },
+        None => HashAlgorithm::Sha256,
+    };
+
+    let entries = RE.captures_iter(&contents).map(|cap| (cap[1].replace("\\", "/"), cap[2].to_lowercase())).collect();
+
+    Ok(Manifest { algorithm, entries })
+}
+
+/// Returns true if the given manifest entry stays inside the tree it's relative to.  Manifests can come from anywhere,
+/// so an entry has to pass this before anything is removed because of it.
+pub fn is_contained_entry( entry_path: &str ) -> bool
+{
+    Path::new(entry_path).components().all(|x| matches!( x, Component::Normal(_) ))
+}
+
+/// Returns the location of the manifest for the given map, relative to the root of a file tree.
+pub fn get_relative_manifest_path( map_name: &str ) -> PathBuf
+{
+    let mut manifest_path = PathBuf::from("maps");
+    manifest_path.push(map_name);
+    manifest_path.set_extension(MANIFEST_EXTENSION);
+
+    manifest_path
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_manifest_round_trip()
+    {
+        let args = get_barebones_args();
+
+        let mut manifest_path = get_root_test_directory();
+        manifest_path.push("temp");
+        manifest_path.push("round_trip.manifest");
+
+        let relative_paths = vec![PathBuf::from("maps/test_map.bsp"), PathBuf::from("scripts/maps/test_map.txt")];
+
+        write_manifest( &args, &manifest_path, &args.rootdir, &relative_paths ).unwrap();
+        let manifest = read_manifest( &manifest_path ).unwrap();
+        fs::remove_file(&manifest_path).unwrap();
+
+        assert_eq!( manifest.algorithm, HashAlgorithm::Blake3 );
+        assert_eq!( manifest.entries.len(), 2 );
+        assert_eq!( manifest.entries[0].0, "maps/test_map.bsp" );
+        assert_eq!( manifest.entries[1].0, "scripts/maps/test_map.txt" );
+
+        let mut map_path = args.rootdir.clone();
+        map_path.push("maps/test_map.bsp");
+
+        assert_eq!( manifest.entries[0].1, HashAlgorithm::Blake3.hash_file(&map_path).unwrap() );
+    }
+
+    #[test]
+    fn test_hash_algorithms()
+    {
+        assert_eq!( HashAlgorithm::Sha256.hash_reader( &mut "abc".as_bytes() ).unwrap(), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad" );
+        assert_eq!( HashAlgorithm::Blake3.hash_reader( &mut "abc".as_bytes() ).unwrap(), "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85" );
+        assert_eq!( HashAlgorithm::from_name("BLAKE3"), Some(HashAlgorithm::Blake3) );
+        assert_eq!( HashAlgorithm::from_name("md5"), None );
+
+        // Hashing across threads still gives every file its own hash, in order.
+        let rootdir = get_barebones_args().rootdir;
+        let file_paths = vec![rootdir.join("maps/test_map.bsp"), rootdir.join("scripts/maps/test_map.txt"), rootdir.join("maps/test_map.bsp")];
+        let file_hashes = hash_files( HashAlgorithm::Sha256, &file_paths ).unwrap();
+
+        assert_eq!( file_hashes, file_paths.iter().map(|x| hash_file(x).unwrap()).collect::<Vec<String>>() );
+
+        // Manifests from before the algorithm was recorded are SHA-256.
+        let mut manifest_path = get_root_test_directory();
+        manifest_path.push("temp");
+        manifest_path.push("old_format.manifest");
+
+        fs::write( &manifest_path, format!( "\"manifest\"\r\n{{\r\n\t\"maps/test_map.bsp\"\t\"{}\"\r\n}}\r\n", file_hashes[0] ) ).unwrap();
+        let manifest = read_manifest( &manifest_path ).unwrap();
+        fs::remove_file(&manifest_path).unwrap();
+
+        assert_eq!( manifest.algorithm, HashAlgorithm::Sha256 );
+        assert_eq!( manifest.entries, vec![(String::from("maps/test_map.bsp"), file_hashes[0].clone())] );
+    }
+}
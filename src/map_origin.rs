@@ -0,0 +1,252 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// map_origin: Looks for signs that a map is someone else's work being released again, decompiled or as it was.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::path::{Path, PathBuf};
+
+use argument_handler::Arguments;
+use bsp_reader;
+use ges_version;
+use rules;
+use scan_service;
+use shared;
+use stock_content;
+use error::GesError;
+
+// Keys that decompilers and map protection tools add to an entity, along with what they say about the map.
+static DECOMPILE_KEYS: &[(&'static str, &'static str)] = &[("no_decomp", "a no_decomp key, which its author added so it wouldn't be decompiled")];
+
+// Text decompilers leave in entity values when the map is decompiled and then compiled again.
+static DECOMPILE_VALUE_MARKERS: &[(&'static str, &'static str)] = &[("bspsource", "a note left by the BSPSource decompiler"),
+                                                                     ("vmex", "a note left by the VMEX decompiler"),
+                                                                     ("decompiled by", "a note saying it was decompiled")];
+
+/// Fails if the map looks like it was decompiled from someone else's map, or is a copy of a map already in the GE:S
+/// install or the stock content database under another name.  The GE:S community doesn't allow either to be released as new work.
+pub fn check_map_origin( args: &Arguments, map_name: &str ) -> Result<(), GesError>
+{
+    let mut map_path = args.rootdir.clone();
+    map_path.push("maps");
+    map_path.push( map_name );
+    map_path.set_extension("bsp");
+
+    check_reserved_name( args, map_name )?;
+
+    // The reslist section already fails if the map is missing.
+    if !map_path.is_file()
+    {
+        return Ok(());
+    }
+
+    if rules::is_enabled( args, "map-decompiled" )
+    {
+        check_decompile_markers( args, &map_path )?;
+    }
+
+    if rules::is_enabled( args, "map-official-copy" )
+    {
+        check_stock_copies( args, &map_path )?;
+        check_installed_copies( args, &map_path )?;
+    }
+
+    Ok(())
+}
+
+/// Reports the map if it has the same name as an official map.  Installing it would silently replace the official map
+/// and its scripts on every server and client that downloads it, and whichever one a player has decides what they see.
+fn check_reserved_name( args: &Arguments, map_name: &str ) -> Result<(), GesError>
+{
+    if !is_official_map_name( args, map_name )
+    {
+        return Ok(());
+    }
+
+    let lowercase_name = map_name.to_lowercase();
+
+    let mut error_text = String::new();
+    error_text.push_str("Map ");
+    error_text.push_str(map_name);
+    error_text.push_str(" has the same name as an official GE:S map!  Installing it would replace the official map, along with its map script, music script, and reslist.  ");
+    error_text.push_str("Give the map a name of its own, like ");
+    error_text.push_str(&lowercase_name);
+    error_text.push_str("_v2, so both can be installed at once.");
+
+    rules::report( args, "map-reserved-name", GesError::PolicyViolation( error_text ) )
+}
+
+/// Returns true if an official map has the given name.  Windows doesn't care about case, so neither does this.
+pub fn is_official_map_name( args: &Arguments, map_name: &str ) -> bool
+{
+    let lowercase_name = map_name.to_lowercase();
+
+    ges_version::get_profile( args ).maps.contains( &lowercase_name.as_str() ) ||
+    stock_content::get_stock_file( &format!( "maps/{}.bsp", lowercase_name ) ) != None
+}
+
+/// Reports the map if its entities carry any of the marks decompilers and map protection tools leave behind.
+fn check_decompile_markers( args: &Arguments, map_path: &PathBuf ) -> Result<(), GesError>
+{
+    let entities = match bsp_reader::read_entity_lump( map_path )
+    {
+        Ok(x) => x,
+        Err(e) =>
+        {
+            // Placeholder maps used while working on scripts can't be read, and aren't worth a warning every run.
+            if args.verbose
+            {
+                println!( "Couldn't read the entities of {}, so it won't be checked for signs of decompiling:\n{}", map_path.display(), e );
+            }
+
+            return Ok(());
+        },
+    };
+
+    let markers = find_decompile_markers( &entities );
+
+    if markers.is_empty()
+    {
+        return Ok(());
+    }
+
+    let mut error_text = String::new();
+    error_text.push_str("Map ");
+    error_text.push_str( &map_path.display().to_string() );
+    error_text.push_str(" looks like it was decompiled from another map!  Its entities contain ");
+    error_text.push_str( &markers.join(", and ") );
+    error_text.push_str(".\nReleasing a decompiled map needs its original author's permission.  If you have it, turn this rule down in a rules file.");
+
+    rules::report_in_file( args, "map-decompiled", map_path, 0, GesError::PolicyViolation( error_text ) )
+}
+
+/// Returns a description of every decompiler or map protection mark in the given entity lump.
+pub fn find_decompile_markers( entities: &str ) -> Vec<&'static str>
+{
+    let mut markers = Vec::new();
+
+    for &(key, description) in DECOMPILE_KEYS
+    {
+        if !bsp_reader::get_entity_values( entities, key ).is_empty()
+        {
+            markers.push(description);
+        }
+    }
+
+    let values: Vec<String> = bsp_reader::get_all_entity_values( entities ).iter().map(|x| x.to_lowercase()).collect();
+
+    for &(marker, description) in DECOMPILE_VALUE_MARKERS
+    {
+        if values.iter().any(|x| x.contains(marker))
+        {
+            markers.push(description);
+        }
+    }
+
+    markers
+}
+
+/// Reports the map if it's identical to an official map with a different name in the stock content database, which
+/// works even without a GE:S install.
+fn check_stock_copies( args: &Arguments, map_path: &PathBuf ) -> Result<(), GesError>
+{
+    let map_name = map_path.file_name().map(|x| x.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let map_hash = scan_service::get_scan_service().get_file_hash( map_path )?;
+
+    let official_map = stock_content::find_stock_files_by_hash( &map_hash ).into_iter()
+                                                                           .find(|x| x.path.ends_with(".bsp") && !x.path.ends_with( &format!( "/{}", map_name ) ));
+
+    match official_map
+    {
+        Some(x) =>
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Map ");
+            error_text.push_str( &map_path.display().to_string() );
+            error_text.push_str(" is identical to the official map ");
+            error_text.push_str(&x.path);
+            error_text.push_str("!  Maps that are already released can't be released again under a new name.");
+
+            rules::report_in_file( args, "map-official-copy", map_path, 0, GesError::PolicyViolation( error_text ) )
+        },
+        None => Ok(()),
+    }
+}
+
+/// Reports the map if it's identical to a map with a different name in the GE:S install.  A map with the same name
+/// is most likely the mapper's own copy, installed to test it.
+fn check_installed_copies( args: &Arguments, map_path: &PathBuf ) -> Result<(), GesError>
+{
+    let mut installed_map_dir = args.gesdir.clone();
+    installed_map_dir.push("maps");
+
+    if args.offline || !installed_map_dir.is_dir() || installed_map_dir == map_path.parent().unwrap_or( Path::new("") )
+    {
+        return Ok(());
+    }
+
+    let scan_service = scan_service::get_scan_service();
+    let map_size = scan_service.read_file_info( map_path )?.size;
+
+    let map_name = map_path.file_name().map(|x| x.to_string_lossy().to_lowercase());
+    let installed_maps = scan_service.get_files( &installed_map_dir, "bsp", &[], shared::keep_all_files )?.1;
+
+    for installed_map in installed_maps
+    {
+        let installed_map_path = installed_map_dir.join(&installed_map);
+
+        if installed_map_path.file_name().map(|x| x.to_string_lossy().to_lowercase()) == map_name
+        {
+            continue;
+        }
+
+        // Only maps of exactly the same size could possibly match, which saves hashing nearly all of them.
+        if scan_service.read_file_info( &installed_map_path )?.size != map_size
+        {
+            continue;
+        }
+
+        if scan_service.get_file_hash( &installed_map_path )? == scan_service.get_file_hash( map_path )?
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Map ");
+            error_text.push_str( &map_path.display().to_string() );
+            error_text.push_str(" is identical to ");
+            error_text.push_str( &installed_map_path.display().to_string() );
+            error_text.push_str(" in the GE:S install!  Maps that are already released can't be released again under a new name.");
+
+            return rules::report_in_file( args, "map-official-copy", map_path, 0, GesError::PolicyViolation( error_text ) );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_find_decompile_markers()
+    {
+        let entities = "{\n\"classname\" \"worldspawn\"\n\"skyname\" \"sky_day01_01\"\n}\n{\n\"classname\" \"info_player_deathmatch\"\n}\n";
+        assert!( find_decompile_markers( entities ).is_empty() );
+
+        let entities = "{\n\"classname\" \"worldspawn\"\n\"No_Decomp\" \"1\"\n\"comment\" \"Decompiled by BSPSource v1.4.3\"\n}\n";
+        assert_eq!( find_decompile_markers( entities ).len(), 3 );
+    }
+
+    #[test]
+    fn test_is_official_map_name()
+    {
+        let args = shared::get_barebones_args();
+
+        assert!( is_official_map_name( &args, "ge_archives" ) );
+        assert!( is_official_map_name( &args, "GE_Facility_Backzone" ) );
+        assert!( !is_official_map_name( &args, "ge_archives_remake" ) );
+    }
+}
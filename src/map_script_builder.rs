@@ -7,27 +7,62 @@
 // map_script_builder: Contains functions for analyzing and building map script files for GoldenEye: Source maps.
 // --------------------------------------------------------------------------------------------------------------
 
-use std::fs;
 use std::io::prelude::*;
 use argument_handler::Arguments;
 
 use std::path::PathBuf;
-use std::io::{Error, ErrorKind};
-use std::io::BufReader;
 
 use shared;
+use rules;
+use file_writer;
+use script_encoding;
+use generation_metadata;
+use stock_content;
+use ges_version;
+use map_script_schema;
+use map_script_schema::{MapScriptSchema, SchemaTerm, TermType};
+use playtest;
+use file_writer::FileWriter;
+use error::GesError;
+use preflight::PreflightReport;
+
+// Terms that go on their own line, in the format [term] [value]
+pub static VALUE_TERMS: &[&'static str] = &["BaseWeight", "MaxPlayers", "MinPlayers", "ResIntensity", "TeamThreshold"];
+
+// Terms followed by a bracketed set of [name] [weight] lines.
+pub static BRACKET_TERMS: &[&'static str] = &["WeaponsetWeights", "GamemodeWeights", "TeamGamemodeWeights"];
+
+// Optional term naming the group a map belongs to, in the format MapGroup [map name].  Variants of a map like
+// ge_foo and ge_foo_classic all name the same map, and every map they name has to exist.
+static GROUP_TERM: &'static str = "MapGroup";
+
+// Weight given to each gamemode a map is restricted to, unless a preset gives it another.
+pub const DEFAULT_RESTRICTED_WEIGHT: i32 = 100;
+
+/// Where we are in relation to the bracketed sections of a map script.
+#[derive(PartialEq)]
+enum BraceState
+{
+    /// Outside of any section, looking for value terms and bracket terms.
+    TopLevel,
+    /// Just read a bracket term, so the next line has to open its section.
+    ExpectingOpen,
+    /// Inside a section, reading [name] [weight] lines until it's closed.
+    InBlock,
+}
 
 /// Generates the map script file used for random selection behavior.  
 /// Returns Ok() if successful and an error if not.
-pub fn create_or_verify_map_script_file( args: &Arguments, map_name: &str ) -> Result<(), Error>
+pub fn create_or_verify_map_script_file( args: &Arguments, map_name: &str ) -> Result<(), GesError>
 {
     let mut map_script_dir = args.rootdir.clone();
     map_script_dir.push("scripts");
     map_script_dir.push("maps");
 
-    if !map_script_dir.is_dir()
+    // Check-only mode reports the missing script below instead.
+    if !map_script_dir.is_dir() && !file_writer::is_read_only()
     {
-        fs::create_dir_all(&map_script_dir)?;
+        file_writer::create_dir_all(&map_script_dir)?;
     }
 
     // Just build the map path off of the existing script dir builder.
@@ -36,13 +71,18 @@ pub fn create_or_verify_map_script_file( args: &Arguments, map_name: &str ) -> R
     map_script_path.push(map_name);
     map_script_path.set_extension("txt");
 
-    if !map_script_path.is_file()
+    if !map_script_path.is_file() && file_writer::is_read_only()
+    {
+        return Err(GesError::MissingFile { message: format!( "No map script exists at {}, and check-only mode won't create one!", map_script_path.display() ), path: map_script_path });
+    }
+    else if !map_script_path.is_file()
     {
         create_map_script_file( args, &map_script_path )?;
         println!("Created map script for {}!", map_name);
     }
     else
     {
+        playtest::stamp_map_script( args, &map_script_path )?;
         check_map_script_file( args, &map_script_path )?;
         println!("Existing map script file for {} is valid!", map_name);
     }
@@ -51,7 +91,7 @@ pub fn create_or_verify_map_script_file( args: &Arguments, map_name: &str ) -> R
 }
 
 /// Checks every map script in the provided or autodetected GE:S directory.
-pub fn fullcheck_map_script_files( args: &Arguments ) -> Result<(), Error>
+pub fn fullcheck_map_script_files( args: &Arguments ) -> Result<(), GesError>
 {
     let mut map_script_dir = args.gesdir.clone();
     map_script_dir.push("scripts");
@@ -59,18 +99,74 @@ pub fn fullcheck_map_script_files( args: &Arguments ) -> Result<(), Error>
 
     if !map_script_dir.is_dir()
     {
-        return Err(Error::new( ErrorKind::InvalidData, "Map script directory does not exist!  Is this really a valid GE:S install?" ));
+        return Err(GesError::MissingFile { path: map_script_dir, message: String::from("Map script directory does not exist!  Is this really a valid GE:S install?") });
     }
 
-    shared::check_all_files_in_dir_with_func( args, &map_script_dir, "txt", "map scripts", check_map_script_file )?;
+    script_encoding::fullcheck_script_encodings( args, &map_script_dir, "txt" )?;
+
+    shared::check_all_files_in_dir_with_func( args, &map_script_dir, "txt", "map scripts", check_map_script_file, get_map_script_dependencies )?;
 
     Ok(())
 }
 
+/// Adds what the map script section needs before it runs to the preflight report.
+pub fn preflight( args: &Arguments, map_name: &str, report: &mut PreflightReport )
+{
+    if args.fullcheck
+    {
+        let mut map_script_dir = args.gesdir.clone();
+        map_script_dir.push("scripts");
+        map_script_dir.push("maps");
+
+        report.recommend( "Map scripts", &map_script_dir, "a map script directory to check", map_script_dir.is_dir() );
+    }
+    else
+    {
+        // In normal mode a missing script is created for us, which is the only time we need to write anything.
+        let mut map_script_dir = args.rootdir.clone();
+        map_script_dir.push("scripts");
+        map_script_dir.push("maps");
+
+        let mut map_script_path = map_script_dir.clone();
+        map_script_path.push(map_name);
+        map_script_path.set_extension("txt");
+
+        if !map_script_path.is_file() && !file_writer::is_read_only()
+        {
+            report.require( "Map scripts", &map_script_dir, "write access to create the missing map script", file_writer::can_create_in(&map_script_dir) );
+        }
+    }
+}
+
 /// Creates a map script file with the given path and arguments in the standard GE:S map script format.
-fn create_map_script_file( args: &Arguments, map_script_path: &PathBuf ) -> Result<(), Error>
+fn create_map_script_file( args: &Arguments, map_script_path: &PathBuf ) -> Result<(), GesError>
+{
+    let contents = get_map_script_contents( args )?;
+
+    // Write out our new file!
+    let mut map_script_file = FileWriter::create(map_script_path)?;
+    map_script_file.write_all( generation_metadata::stamp( args, &contents )?.as_bytes() )?;
+
+    Ok(())
+}
+
+/// Returns the contents of a new map script made with the given arguments, before it's stamped with how it was made.
+pub fn get_map_script_contents( args: &Arguments ) -> Result<String, GesError>
 {
-    let mut map_script_file = fs::File::create(map_script_path)?;
+    // Restricted maps zero every other gamemode by name, so they need to know which ones there are.
+    let known_gamemodes = if args.only_gamemodes.is_empty() { Vec::new() } else { ges_version::get_known_gamemodes( args )? };
+
+    for gamemode in args.only_gamemodes.iter().filter(|x| !known_gamemodes.iter().any(|y| y.eq_ignore_ascii_case(x)))
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Gamemode ");
+        error_text.push_str(gamemode);
+        error_text.push_str(" given with --only-gamemodes doesn't ship with GE:S ");
+        error_text.push_str( ges_version::get_profile( args ).version );
+        error_text.push_str(" or the GE:S install, so servers without it will never pick this map.");
+
+        rules::report_message( args, "map-script-unknown-gamemode", error_text )?;
+    }
 
     // Stick our program parameters into the core map details.
     // Individual weaponset and gamemode overrides didn't make a ton of sense to include as program
@@ -92,48 +188,170 @@ fn create_map_script_file( args: &Arguments, map_script_path: &PathBuf ) -> Resu
     contents.push_str("ResIntensity\t"); contents.push_str(&args.resintensity.to_string()); contents.push_str("\r\n");
     contents.push_str("TeamThreshold\t"); contents.push_str(&args.teamthresh.to_string()); contents.push_str("\r\n");
     contents.push_str("\r\n");
+
+    match args.map_group
+    {
+        Some(ref x) =>
+        {
+            contents.push_str("// Every variant of a map names the same map here, so they can be told apart from unrelated maps.\r\n");
+            contents.push_str("MapGroup\t"); contents.push_str(x); contents.push_str("\r\n");
+            contents.push_str("\r\n");
+        },
+        None => {},
+    }
     contents.push_str("// Overrides the default weaponset weights if any sets are specified here.  Can be used as a blacklist.\r\n");
     contents.push_str("// Will only override weaponsets that are already in rotation, to prevent overriding gamemode specific lists.\r\n");
     contents.push_str("WeaponsetWeights\r\n");
     contents.push_str("{\r\n");
-    contents.push_str("\tslappers\t\t0\r\n"); // slappers example
+    push_section_weights( args, "WeaponsetWeights", "slappers", &mut contents ); // slappers example
     contents.push_str("}\r\n");
     contents.push_str("\r\n");
     contents.push_str("// Weights for each gamemode if the map is switched to below the team threshold.\r\n");
     contents.push_str("// Overrides whatever weight is specified in default.txt, if there is one.\r\n");
     contents.push_str("// If a gamemode is not listed here or in default.txt it won't be used.\r\n");
+    push_restriction_comment( args, &mut contents );
     contents.push_str("GamemodeWeights\r\n");
     contents.push_str("{\r\n");
-    contents.push_str("\tYOLT\t\t0\r\n"); // YOLT example.
+    push_gamemode_weights( args, "GamemodeWeights", "YOLT", &known_gamemodes, &mut contents ); // YOLT example.
     contents.push_str("}\r\n");
     contents.push_str("\r\n");
     contents.push_str("// Gamemode weights used when the map is switched to while playercount is above the team threshold.\r\n");
+    push_restriction_comment( args, &mut contents );
     contents.push_str("TeamGamemodeWeights\r\n");
     contents.push_str("{\r\n");
-    contents.push_str("\tCaptureTheFlag\t\t0\r\n"); // CTF example.
+    push_gamemode_weights( args, "TeamGamemodeWeights", "CaptureTheFlag", &known_gamemodes, &mut contents ); // CTF example.
     contents.push_str("}\r\n");
     contents.push_str("\r\n");
 
-    // Write out our new file!
-    map_script_file.write_all(contents.as_bytes())?;
+    // Playtest releases say so up top, where anyone opening the script will see it.
+    match playtest::get_stamp_line( args )
+    {
+        Some(x) => Ok(playtest::replace_stamp( &contents, &x )),
+        None => Ok(contents),
+    }
+}
 
-    Ok(())
+/// Adds the weights of a gamemode section to the contents of a new map script.  Maps restricted with --only-gamemodes
+/// list every gamemode, zeroing the ones they aren't played with, and everything else is a normal section.
+fn push_gamemode_weights( args: &Arguments, section: &str, example_entry: &str, known_gamemodes: &[String], contents: &mut String )
+{
+    if args.only_gamemodes.is_empty()
+    {
+        push_section_weights( args, section, example_entry, contents );
+        return;
+    }
+
+    // Gamemodes left out would fall back to default.txt, so every one the server could have is zeroed by name.
+    let mut gamemodes: Vec<&String> = args.only_gamemodes.iter().collect();
+    for gamemode in known_gamemodes
+    {
+        if !gamemodes.iter().any(|x| x.eq_ignore_ascii_case(gamemode))
+        {
+            gamemodes.push( gamemode );
+        }
+    }
+
+    for gamemode in gamemodes
+    {
+        contents.push_str("\t"); contents.push_str(gamemode); contents.push_str("\t\t");
+        contents.push_str( &get_restricted_weight( args, section, gamemode ).to_string() );
+        contents.push_str("\r\n");
+    }
+}
+
+/// Adds a note above a gamemode section of a new map script saying the map is restricted, if it is.
+fn push_restriction_comment( args: &Arguments, contents: &mut String )
+{
+    if !args.only_gamemodes.is_empty()
+    {
+        contents.push_str("// This map is only played with "); contents.push_str( &args.only_gamemodes.join(", ") );
+        contents.push_str(", so every other gamemode is given a weight of 0.\r\n");
+    }
+}
+
+/// Returns the weight a map restricted with --only-gamemodes gives the gamemode in the given section.  Gamemodes it's
+/// restricted to get the weight a preset gave them or the default, and every other gamemode gets 0.
+pub fn get_restricted_weight( args: &Arguments, section: &str, gamemode: &str ) -> i32
+{
+    if !args.only_gamemodes.iter().any(|x| x.eq_ignore_ascii_case(gamemode))
+    {
+        return 0;
+    }
+
+    match args.section_weights.iter().find(|x| x.0 == section && x.1.eq_ignore_ascii_case(gamemode))
+    {
+        Some(x) => x.2,
+        None => DEFAULT_RESTRICTED_WEIGHT,
+    }
+}
+
+/// Adds the weights a preset gave the section to the contents of a new map script, or an example entry with a weight of
+/// 0 if it didn't give it any.
+fn push_section_weights( args: &Arguments, section: &str, example_entry: &str, contents: &mut String )
+{
+    let section_weights: Vec<&(String, String, i32)> = args.section_weights.iter().filter(|x| x.0 == section).collect();
+
+    if section_weights.is_empty()
+    {
+        contents.push_str("\t"); contents.push_str(example_entry); contents.push_str("\t\t0\r\n");
+    }
+
+    for &&(_, ref entry, weight) in &section_weights
+    {
+        contents.push_str("\t"); contents.push_str(entry); contents.push_str("\t\t"); contents.push_str(&weight.to_string()); contents.push_str("\r\n");
+    }
 }
 
 /// Checks the map script file for format and parameter validity.
 /// Take arguments here even though we don't use them so our function signature matches the other check functions.
-fn check_map_script_file( _args: &Arguments, map_script_path: &PathBuf ) -> Result<(), Error>
+pub fn check_map_script_file( args: &Arguments, map_script_path: &PathBuf ) -> Result<(), GesError>
 {
-    let map_script_file = fs::File::open(map_script_path)?;
-    let reader = BufReader::new(map_script_file);
+    shared::set_current_file( map_script_path );
+
+    let contents = match shared::read_script_file( map_script_path )
+    {
+        Err(e @ GesError::Parse { .. }) => return Err(rules::fail( "map-script-syntax", e )),
+        x => x?,
+    };
+
+    check_map_script_contents( args, map_script_path, &contents )
+}
+
+/// Same as check_map_script_file, but checks the given contents instead of reading them from the map script.  The
+/// path is only used to report problems against and to find the script's group.
+pub fn check_map_script_contents( args: &Arguments, map_script_path: &PathBuf, contents: &str ) -> Result<(), GesError>
+{
+    rules::load_suppressions( map_script_path, contents );
+    let edit_state = generation_metadata::report_edit_state( args, map_script_path, contents )?;
+
+    let legacy_args = rules::get_legacy_args( args, &edit_state );
+    let args = legacy_args.as_ref().unwrap_or( args );
+
+    playtest::check_playtest_expiry( args, map_script_path, contents )?;
 
     // All of the terms we're hoping to find.
     // value terms are on their own line, in the format [term] [value]
     // bracket terms consist of multiple lines, with a [term] followed by a set of bracketed value terms.
-    let mut needed_value_terms = vec!["BaseWeight", "MaxPlayers", "MinPlayers", "ResIntensity", "TeamThreshold"];
-    let mut needed_bracket_terms = vec!["WeaponsetWeights", "GamemodeWeights", "TeamGamemodeWeights"];
+    // Which terms those are, and the values they can have, comes from the schema for the GE:S version the script is for.
+    let profile = ges_version::get_profile( args );
+    let schema = map_script_schema::get_schema( args );
+    let mut needed_value_terms = schema.get_term_names( TermType::Integer );
+    let mut needed_bracket_terms = schema.get_term_names( TermType::Weights );
+
+    // Custom gamemodes are common enough that weighting one GE:S doesn't ship is only worth a warning.
+    let known_gamemodes = ges_version::get_known_gamemodes( args )?;
+    let check_unknown_gamemodes = rules::is_enabled( args, "map-script-unknown-gamemode" );
+
+    let mut map_group: Option<(String, usize)> = None;
 
     let mut checking_term = String::from("");
+    let mut brace_state = BraceState::TopLevel;
+    let mut section_line_number = 0;
+    let mut line_number = 0;
+
+    // GE:S quietly lets later entries overwrite earlier ones, so we keep track of what each section has already set.
+    let mut checked_entries: Vec<String> = Vec::new();
+    let mut section_weights: Vec<(String, i32)> = Vec::new();
 
     // Need to mimic the original GE:S map script parser here since that's what will read our files\
     // ...even if it's not how I would have made it today.
@@ -141,9 +359,9 @@ fn check_map_script_file( _args: &Arguments, map_script_path: &PathBuf ) -> Resu
     // I'll probably remake the format for 5.1 in such a way that it's backwards compatable with this one and much more intuitive.
 
     // Surprisingly I've never gotten a complaint about this format, even though it utterly defies the standards it implies it uses.
-    for line in reader.lines() 
+    for line in contents.lines() 
     {
-        let line = line?;
+        line_number += 1;
         
         // Comments only count if the first two characters are double slashes
         if line.starts_with("//")
@@ -151,12 +369,12 @@ fn check_map_script_file( _args: &Arguments, map_script_path: &PathBuf ) -> Resu
             continue;
         }
 
-        let mut line_iter = line.split_whitespace();
+        // Editors that write KeyValues files like to quote everything, which GE:S reads the same as the bare form.
+        let mut line_iter = line.split_whitespace().map(strip_quotes);
+        let line_identifier = line_iter.next();
 
-        if checking_term.is_empty()
+        if brace_state == BraceState::TopLevel
         {
-            let line_identifier = line_iter.next();
-
             if line_identifier == None
             {
                 continue;
@@ -164,77 +382,215 @@ fn check_map_script_file( _args: &Arguments, map_script_path: &PathBuf ) -> Resu
 
             let line_identifier = line_identifier.unwrap();
 
+            if line_identifier.starts_with("{") || line_identifier.starts_with("}")
+            {
+                let mut error_text = String::new();
+                error_text.push_str("Found ");
+                error_text.push_str( &line_identifier[..1] );
+                error_text.push_str(" outside of any section!  Brackets can only open and close the section named on the line before them.");
+
+                return Err(rules::fail( "map-script-syntax", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } ));
+            }
+
             if needed_value_terms.contains(&line_identifier)
             {
-                check_line_value_validity(line_identifier, line_iter.next())?;
+                check_line_value_validity(args, map_script_path, line_number, line_identifier, line_iter.next(), schema.get_term( line_identifier ))?;
+                check_for_trailing_data(args, map_script_path, line_number, line_iter.next())?;
                 needed_value_terms.retain(|x| x != &line_identifier);
             }
             else if needed_bracket_terms.contains(&line_identifier)
             {
+                check_for_trailing_data(args, map_script_path, line_number, line_iter.next())?;
                 checking_term = String::from(line_identifier);
+                checked_entries.clear();
+                section_weights.clear();
+                brace_state = BraceState::ExpectingOpen;
+                section_line_number = line_number;
+            }
+            else if line_identifier == GROUP_TERM && map_group == None
+            {
+                match line_iter.next()
+                {
+                    Some(x) => map_group = Some((String::from(x), line_number)),
+                    None =>
+                    {
+                        let error_text = String::from("Expected the name of a map for parameter MapGroup");
+                        rules::report( args, "map-script-invalid-value", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } )?;
+                    },
+                }
+
+                check_for_trailing_data(args, map_script_path, line_number, line_iter.next())?;
+            }
+            else if schema.get_term( line_identifier ) != None || line_identifier == GROUP_TERM
+            {
+                let mut error_text = String::new();
+                error_text.push_str("Parameter ");
+                error_text.push_str( &line_identifier );
+                error_text.push_str(" is defined more than once!  GE:S will only use the last one, so remove the others.");
+
+                rules::report( args, "map-script-duplicate-parameter", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } )?;
+
+                // If we're letting it slide, the section that follows still needs to be read like any other.
+                if schema.get_term( line_identifier ).map(|x| x.term_type) == Some(TermType::Weights)
+                {
+                    checking_term = String::from(line_identifier);
+                    checked_entries.clear();
+                    section_weights.clear();
+                    brace_state = BraceState::ExpectingOpen;
+                    section_line_number = line_number;
+                }
             }
             else
             {
                 let mut error_text = String::new();
                 error_text.push_str("Line identifier ");
-                error_text.push_str( &line_identifier );
+                error_text.push_str( &shared::get_quotable_text( line_identifier ) );
                 error_text.push_str(" is not a supported parameter!");
 
-                return Err(Error::new(ErrorKind::InvalidData, error_text ));
+                // Most unknown parameters are just typos of real ones, so point the user at what they probably meant.
+                match get_closest_term( &schema, line_identifier )
+                {
+                    Some(x) => { error_text.push_str("  Did you mean "); error_text.push_str(x); error_text.push_str("?"); },
+                    None => {},
+                }
+
+                rules::report( args, "map-script-unknown-parameter", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } )?;
             }
+
+            continue;
         }
-        else
+
+        if line_identifier == None
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Subvalue section for ");
+            error_text.push_str( &checking_term );
+            error_text.push_str(" contains an blank line when it must not contain any!");
+
+            return Err(rules::fail( "map-script-syntax", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } ));
+        }
+
+        let line_identifier = line_identifier.unwrap();
+
+        if brace_state == BraceState::ExpectingOpen
         {
             // GE:S just assumes opening bracket means the rest of the line is blank...
-            if line.starts_with( "{" )
+            match line.strip_prefix( "{" )
             {
-                continue;
+                Some(x) => check_for_trailing_data(args, map_script_path, line_number, x.split_whitespace().next())?,
+                None =>
+                {
+                    let mut error_text = String::new();
+                    error_text.push_str("Expected { at the start of the line to open the ");
+                    error_text.push_str( &checking_term );
+                    error_text.push_str(" section from line ");
+                    error_text.push_str( &section_line_number.to_string() );
+                    error_text.push_str(" but found ");
+                    error_text.push_str( &shared::get_quotable_text( line_identifier ) );
+                    error_text.push_str(" instead!");
+
+                    return Err(rules::fail( "map-script-syntax", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } ));
+                },
             }
 
-            // Same with closing bracket, except it also means we've hit the end of this section.
-            if line.starts_with( "}" )
+            brace_state = BraceState::InBlock;
+            continue;
+        }
+
+        // GE:S has no idea what to do with a section inside of a section, so it'll just read the rest of the script wrong.
+        if line_identifier.starts_with("{")
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Found { inside of the ");
+            error_text.push_str( &checking_term );
+            error_text.push_str(" section opened on line ");
+            error_text.push_str( &section_line_number.to_string() );
+            error_text.push_str("!  Sections can't be nested, so close it with } first.");
+
+            return Err(rules::fail( "map-script-syntax", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } ));
+        }
+
+        // Same with closing bracket, except it also means we've hit the end of this section.
+        match line.strip_prefix( "}" )
+        {
+            Some(x) =>
             {
+                check_for_trailing_data(args, map_script_path, line_number, x.split_whitespace().next())?;
+                check_gamemode_weights( args, map_script_path, section_line_number, &checking_term, &section_weights, &known_gamemodes )?;
                 needed_bracket_terms.retain(|x| x != &checking_term);
                 checking_term = String::from("");
+                brace_state = BraceState::TopLevel;
                 continue;
-            }
+            },
+            None => {},
+        }
 
-            let line_identifier = line_iter.next();
+        let line_value = line_iter.next();
+        check_line_value_validity(args, map_script_path, line_number, line_identifier, line_value, schema.get_term( &checking_term ))?;
 
-            if line_identifier == None
-            {
-                let mut error_text = String::new();
-                error_text.push_str("Subvalue section for ");
-                error_text.push_str( &checking_term );
-                error_text.push_str(" contains an blank line when it must not contain any!");
+        match line_value.map(|x| x.parse::<i32>())
+        {
+            Some(Ok(x)) => section_weights.push( (String::from(line_identifier), x) ),
+            _ => {},
+        }
 
-                return Err(Error::new(ErrorKind::InvalidData, error_text ));
-            }
+        if checked_entries.iter().any(|x| x.eq_ignore_ascii_case(line_identifier))
+        {
+            let mut error_text = String::new();
+            error_text.push_str( &shared::get_quotable_text( line_identifier ) );
+            error_text.push_str(" is given a weight more than once in the ");
+            error_text.push_str( &checking_term );
+            error_text.push_str(" section!  GE:S will only use the last one, so remove the others.");
 
-            let line_identifier = line_identifier.unwrap();
-            check_line_value_validity(line_identifier, line_iter.next())?;
+            rules::report( args, "map-script-duplicate-entry", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } )?;
+        }
+        else
+        {
+            checked_entries.push( String::from(line_identifier) );
+        }
 
-            // If we had a closing bracket anywhere on that line GE:S assumes that means it was right at the end.
-            if line.contains( "}" )
-            {
-                needed_bracket_terms.retain(|x| x != &checking_term);
-                checking_term = String::from("");
-                continue;
-            }
+        if checking_term.ends_with("GamemodeWeights") && check_unknown_gamemodes && !known_gamemodes.iter().any(|x| x.eq_ignore_ascii_case(line_identifier))
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Gamemode ");
+            error_text.push_str( &shared::get_quotable_text( line_identifier ) );
+            error_text.push_str(" in the ");
+            error_text.push_str( &checking_term );
+            error_text.push_str(" section doesn't ship with GE:S ");
+            error_text.push_str( profile.version );
+            error_text.push_str(" or the GE:S install, so servers without it will never pick it.");
+
+            rules::report( args, "map-script-unknown-gamemode", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } )?;
+        }
+
+        // A closing bracket can end the line, but anything else is ignored by GE:S and probably a mistake.
+        match line_iter.next()
+        {
+            Some("}") => check_for_trailing_data(args, map_script_path, line_number, line_iter.next())?,
+            x => check_for_trailing_data(args, map_script_path, line_number, x)?,
+        }
+
+        // If we had a closing bracket anywhere on that line GE:S assumes that means it was right at the end.
+        if line.contains( "}" )
+        {
+            check_gamemode_weights( args, map_script_path, section_line_number, &checking_term, &section_weights, &known_gamemodes )?;
+            needed_bracket_terms.retain(|x| x != &checking_term);
+            checking_term = String::from("");
+            brace_state = BraceState::TopLevel;
         }
     }
 
     // Now let's make sure we're ending in the correct state.
     // We should have no script terms unused, and we shouldn't currently be parsing a text block.
 
-    if !checking_term.is_empty()
+    if brace_state != BraceState::TopLevel
     {
         let mut error_text = String::new();
         error_text.push_str("Script ends in the middle of the ");
         error_text.push_str( &checking_term );
-        error_text.push_str("Section!");
+        error_text.push_str(" section!  It was never closed with }.");
 
-        return Err(Error::new(ErrorKind::InvalidData, error_text ));
+        return Err(rules::fail( "map-script-syntax", GesError::Parse { file: map_script_path.clone(), line: section_line_number, message: error_text } ));
     }
 
     if !needed_value_terms.is_empty()
@@ -247,7 +603,7 @@ fn check_map_script_file( _args: &Arguments, map_script_path: &PathBuf ) -> Resu
             error_text.push_str( " " );
         }
 
-        return Err(Error::new(ErrorKind::InvalidData, error_text ));
+        rules::report( args, "map-script-missing-parameter", GesError::Parse { file: map_script_path.clone(), line: 0, message: error_text } )?;
     }
 
     if !needed_bracket_terms.is_empty()
@@ -260,51 +616,243 @@ fn check_map_script_file( _args: &Arguments, map_script_path: &PathBuf ) -> Resu
             error_text.push_str( " " );
         }
 
-        return Err(Error::new(ErrorKind::InvalidData, error_text ));
+        rules::report( args, "map-script-missing-parameter", GesError::Parse { file: map_script_path.clone(), line: 0, message: error_text } )?;
+    }
+
+    match map_group
+    {
+        Some((ref x, y)) => check_map_group( args, map_script_path, x, y )?,
+        None => {},
+    }
+
+    Ok(())
+}
+
+/// Reports a gamemode section that gives every gamemode the server could have a weight of 0, which leaves nothing to
+/// play the map with.  Gamemodes a section leaves out fall back to default.txt, so only sections listing all of them count.
+fn check_gamemode_weights( args: &Arguments, map_script_path: &PathBuf, section_line_number: usize, section: &str, section_weights: &[(String, i32)], known_gamemodes: &[String] ) -> Result<(), GesError>
+{
+    if !section.ends_with("GamemodeWeights") || section_weights.iter().any(|x| x.1 > 0)
+    {
+        return Ok(());
+    }
+
+    if !known_gamemodes.iter().all(|x| section_weights.iter().any(|y| y.0.eq_ignore_ascii_case(x)))
+    {
+        return Ok(());
+    }
+
+    let mut error_text = String::new();
+    error_text.push_str("Every gamemode in the ");
+    error_text.push_str(section);
+    error_text.push_str(" section has a weight of 0, so GE:S has no gamemode to play the map with!  Give at least one gamemode a weight above 0.");
+
+    rules::report( args, "map-script-no-gamemodes", GesError::Parse { file: map_script_path.clone(), line: section_line_number, message: error_text } )
+}
+
+/// Makes sure the map a script names as its group exists, and that the group map's own script doesn't name another one.
+fn check_map_group( args: &Arguments, map_script_path: &PathBuf, group_name: &str, line_number: usize ) -> Result<(), GesError>
+{
+    let map_name = match map_script_path.file_stem()
+    {
+        Some(x) => x.to_string_lossy().into_owned(),
+        None => return Ok(()),
+    };
+
+    // A map naming itself is the one the rest of the group is built on, so there's nothing else to find.
+    if map_name.eq_ignore_ascii_case(group_name)
+    {
+        return Ok(());
+    }
+
+    let group_map_paths = get_group_paths( args, &["maps"], group_name, "bsp" );
+    let is_stock_map = stock_content::get_stock_file( &format!( "maps/{}.bsp", group_name ) ) != None;
+
+    if !group_map_paths.iter().any(|x| x.is_file()) && !is_stock_map
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Map group ");
+        error_text.push_str(group_name);
+        error_text.push_str(" doesn't exist!  Variants of a map have to ship or be installed alongside it, so make sure ");
+        error_text.push_str(group_name);
+        error_text.push_str(".bsp is in the root or GE:S maps directory.");
+
+        return rules::report( args, "map-script-missing-group-map", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } );
+    }
+
+    for group_script_path in get_group_paths( args, &["scripts", "maps"], group_name, "txt" ).iter().filter(|x| x.is_file())
+    {
+        match read_map_group( group_script_path )?
+        {
+            Some(ref x) if !x.eq_ignore_ascii_case(group_name) =>
+            {
+                let mut error_text = String::new();
+                error_text.push_str("Map group ");
+                error_text.push_str(group_name);
+                error_text.push_str(" is itself in the ");
+                error_text.push_str(x);
+                error_text.push_str(" group, according to ");
+                error_text.push_str( &group_script_path.display().to_string() );
+                error_text.push_str("!  Every variant should name the same map as its group.");
+
+                rules::report( args, "map-script-group-mismatch", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } )?;
+            },
+            _ => {},
+        }
     }
 
     Ok(())
 }
 
-// Makes sure the given line value for the provided line identifier exists and is valid.
-fn check_line_value_validity( line_identifier: &str, line_value: Option<&str> ) -> Result<(), Error>
+/// Returns where a file for the given group map would be in the root and GE:S directories, or just the root directory
+/// in offline mode.
+fn get_group_paths( args: &Arguments, directories: &[&str], group_name: &str, extension: &str ) -> Vec<PathBuf>
+{
+    let root_dirs = if args.offline { vec![&args.rootdir] } else { vec![&args.rootdir, &args.gesdir] };
+
+    root_dirs.iter().map(|x|
+    {
+        let mut group_path = (*x).clone();
+        for directory in directories { group_path.push(directory); }
+        group_path.push(group_name);
+        group_path.set_extension(extension);
+        group_path
+    }).collect()
+}
+
+/// Returns the other map scripts the check of the map script at the given path reads, which are those of the group
+/// it names.
+fn get_map_script_dependencies( args: &Arguments, map_script_path: &PathBuf ) -> Vec<PathBuf>
+{
+    match read_map_group( map_script_path )
+    {
+        Ok(Some(x)) => get_group_paths( args, &["scripts", "maps"], &x, "txt" ),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the group the map script at the given path puts its map in, if it names one.
+fn read_map_group( map_script_path: &PathBuf ) -> Result<Option<String>, GesError>
+{
+    let contents = shared::read_script_file( map_script_path ).map_err(|e| e.in_file( map_script_path ))?;
+
+    let map_group = contents.lines()
+                            .filter(|x| !x.starts_with("//"))
+                            .map(|x| x.split_whitespace().map(strip_quotes).collect::<Vec<&str>>())
+                            .find(|x| x.len() >= 2 && x[0] == GROUP_TERM)
+                            .map(|x| String::from(x[1]));
+
+    Ok(map_group)
+}
+
+// Makes sure nothing but a comment follows the part of a line GE:S actually reads, since it silently ignores the rest.
+fn check_for_trailing_data( args: &Arguments, map_script_path: &PathBuf, line_number: usize, next_token: Option<&str> ) -> Result<(), GesError>
+{
+    match next_token
+    {
+        Some(x) if !x.starts_with("//") =>
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Unexpected ");
+            error_text.push_str( &shared::get_quotable_text( x ) );
+            error_text.push_str(" at the end of the line!  GE:S ignores anything here, so it's most likely a mistake.");
+
+            rules::report( args, "map-script-trailing-data", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } )
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Removes the quotes from a "quoted" token, leaving anything else untouched.
+fn strip_quotes( token: &str ) -> &str
+{
+    if token.len() >= 2 && token.starts_with('"') && token.ends_with('"')
+    {
+        &token[1..token.len() - 1]
+    }
+    else
+    {
+        token
+    }
+}
+
+/// Returns the supported parameter closest to the given unknown one, if any are close enough to be a likely typo.
+fn get_closest_term<'a>( schema: &'a MapScriptSchema, line_identifier: &str ) -> Option<&'a str>
+{
+    let lowercase_identifier = line_identifier.to_lowercase();
+    let identifier_length = lowercase_identifier.chars().count();
+
+    // Terms with lengths too different to be close are skipped, so a garbage line megabytes long isn't compared in full.
+    schema.terms.iter().map(|x| x.name.as_str()).chain( [GROUP_TERM].iter().cloned() )
+                .filter(|x| (x.len() as isize - identifier_length as isize).abs() <= 2)
+                .map(|x| (x, shared::get_edit_distance( &lowercase_identifier, &x.to_lowercase() )))
+                .filter(|x| x.1 <= 2)
+                .min_by_key(|x| x.1)
+                .map(|x| x.0)
+}
+
+// Makes sure the given line value for the provided line identifier exists, is valid, and is within the limits the
+// schema gives the term it's for.  That's the line identifier itself for value terms, and the section for weights.
+fn check_line_value_validity( args: &Arguments, map_script_path: &PathBuf, line_number: usize, line_identifier: &str, line_value: Option<&str>, term: Option<&SchemaTerm> ) -> Result<(), GesError>
 {
     if line_value == None
     {
         let mut error_text = String::new();
         error_text.push_str("Expected value for parameter ");
-        error_text.push_str( line_identifier );
+        error_text.push_str( &shared::get_quotable_text( line_identifier ) );
 
-        return Err(Error::new(ErrorKind::InvalidData, error_text ));
+        return rules::report( args, "map-script-invalid-value", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } );
     }
 
     // We just made sure it's not None.
     let line_value = line_value.unwrap();
 
-    match line_value.parse::<i32>()
+    let value = match line_value.parse::<i32>()
     {
-        Ok(_) => {}, // If we can cast correctly so can GE:S.
+        Ok(x) => x, // If we can cast correctly so can GE:S.
         Err(_) => 
         {
             let mut error_text = String::new();
             error_text.push_str("Parameter for ");
-            error_text.push_str( line_identifier );
+            error_text.push_str( &shared::get_quotable_text( line_identifier ) );
             error_text.push_str(" not a valid whole number value!");
 
-            return Err(Error::new(ErrorKind::InvalidData, error_text ));
+            return rules::report( args, "map-script-invalid-value", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } );
         },
-    }
+    };
 
-    Ok(())
+    match term
+    {
+        Some(x) if !x.is_in_range( value ) =>
+        {
+            let mut error_text = String::new();
+            error_text.push_str( &shared::get_quotable_text( line_identifier ) );
+            error_text.push_str(" is set to ");
+            error_text.push_str( &value.to_string() );
+            error_text.push_str(", but GE:S ");
+            error_text.push_str( &x.version );
+            error_text.push_str(" expects ");
+            if x.term_type == TermType::Weights { error_text.push_str("weights in "); }
+            error_text.push_str( &x.name );
+            error_text.push_str(" to be ");
+            error_text.push_str( &x.to_string() );
+            error_text.push_str("!");
+
+            rules::report( args, "map-script-value-out-of-range", GesError::Parse { file: map_script_path.clone(), line: line_number, message: error_text } )
+        },
+        _ => Ok(()),
+    }
 }
 
 #[cfg(test)]
 mod tests 
 {
+    use std::fs;
     use shared::get_barebones_args;
     use shared::get_root_test_directory;
     use shared::do_validity_test;
     use shared::test_script_creator;
+    use rules::Severity;
     use super::*;
 
     #[test]
@@ -337,4 +885,69 @@ mod tests
         // Now that we've confirmed the script checker works...let's create a file and use it to check it!
         test_script_creator( &get_barebones_args(), "test_map.txt", create_map_script_file, check_map_script_file );
     }
+
+    #[test]
+    fn test_map_group_creator()
+    {
+        let mut args = get_barebones_args();
+        args.map_group = Some(String::from("test_map"));
+
+        // A different name than the other creator test, since they can run at the same time.
+        test_script_creator( &args, "test_map_classic.txt", create_map_script_file, check_map_script_file );
+    }
+
+    #[test]
+    fn test_restricted_map_script_creator()
+    {
+        let mut args = get_barebones_args();
+        args.only_gamemodes = vec![String::from("LTK"), String::from("yolt")];
+        args.section_weights.push( (String::from("TeamGamemodeWeights"), String::from("LTK"), 0) );
+
+        assert_eq!( get_restricted_weight( &args, "GamemodeWeights", "LTK" ), DEFAULT_RESTRICTED_WEIGHT );
+        assert_eq!( get_restricted_weight( &args, "TeamGamemodeWeights", "LTK" ), 0 );
+        assert_eq!( get_restricted_weight( &args, "GamemodeWeights", "DeathMatch" ), 0 );
+
+        test_script_creator( &args, "test_map_restricted.txt", create_map_script_file, check_map_script_file );
+
+        // Every other gamemode is zeroed by name, and the restricted ones keep the case they were given in.
+        let mut script_path = get_root_test_directory();
+        script_path.push("temp");
+        script_path.push("test_map_restricted.txt");
+
+        let contents = fs::read_to_string(&script_path).unwrap();
+        assert!( contents.contains("\tyolt\t\t100\r\n") && contents.contains("\tDeathMatch\t\t0\r\n") );
+        assert!( !contents.contains("\tYOLT\t") );
+
+        // Zeroing the last gamemode with a weight leaves the map unplayable.
+        let zeroed_contents = contents.replace("\tyolt\t\t100", "\tyolt\t\t0");
+        fs::write( &script_path, zeroed_contents ).unwrap();
+        assert!( check_map_script_file( &args, &script_path ).unwrap_err().to_string().contains("[map-script-no-gamemodes]") );
+    }
+
+    #[test]
+    fn test_demoted_rules()
+    {
+        let mut duplicate_script_path = get_root_test_directory();
+        duplicate_script_path.push("map_script_tests");
+        duplicate_script_path.push("invalid");
+        duplicate_script_path.push("test_duplicate1.txt");
+
+        let mut args = get_barebones_args();
+        assert!( check_map_script_file( &args, &duplicate_script_path ).unwrap_err().to_string().contains("[map-script-duplicate-parameter]") );
+
+        // Teams that don't care about duplicates can let them through, and the rest of the script is still checked.
+        args.rule_overrides.push( (String::from("map-script-duplicate-parameter"), Severity::Warning) );
+        assert!( check_map_script_file( &args, &duplicate_script_path ).is_ok() );
+    }
+
+    #[test]
+    fn test_closest_term()
+    {
+        let schema = map_script_schema::get_schema( &get_barebones_args() );
+
+        assert_eq!( get_closest_term( &schema, "BaseWieght" ), Some("BaseWeight") );
+        assert_eq!( get_closest_term( &schema, "maxplayer" ), Some("MaxPlayers") );
+        assert_eq!( get_closest_term( &schema, "MapGrup" ), Some("MapGroup") );
+        assert_eq!( get_closest_term( &schema, "MapName" ), None );
+    }
 }
\ No newline at end of file
@@ -0,0 +1,282 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// map_script_schema: Describes the parameters each GE:S version reads in map scripts, from a table a schema file can change.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use argument_handler::Arguments;
+use update_checker;
+use error::GesError;
+
+// The schema that comes with the program, which a schema file can add versions to or replace versions of.
+static BUILTIN_SCHEMA: &'static str = include_str!("../resources/map_script_schema.txt");
+
+/// How a map script parameter is written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TermType
+{
+    /// On its own line with a whole number, like BaseWeight.
+    Integer,
+    /// Followed by a bracketed section of names and whole number weights, like GamemodeWeights.
+    Weights,
+}
+
+impl TermType
+{
+    /// Parses a type as written in a schema file.
+    pub fn from_name( term_type: &str ) -> Option<TermType>
+    {
+        match term_type.to_lowercase().as_str()
+        {
+            "integer" => Some(TermType::Integer),
+            "weights" => Some(TermType::Weights),
+            _ => None,
+        }
+    }
+}
+
+/// One parameter a version of GE:S reads in map scripts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaTerm
+{
+    pub version: String,
+    pub name: String,
+    pub term_type: TermType,
+    /// The smallest value allowed, or for weights the smallest weight.  None if there's no limit.
+    pub min: Option<i32>,
+    pub max: Option<i32>,
+}
+
+impl SchemaTerm
+{
+    /// Returns true if the given value is within this parameter's limits.
+    pub fn is_in_range( &self, value: i32 ) -> bool
+    {
+        self.min.is_none_or(|x| value >= x) && self.max.is_none_or(|x| value <= x)
+    }
+}
+
+impl fmt::Display for SchemaTerm
+{
+    /// Describes the values the parameter allows, like "at least 0" or "from 1 to 10".
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match (self.min, self.max)
+        {
+            (Some(x), Some(y)) => write!( f, "from {} to {}", x, y ),
+            (Some(x), None) => write!( f, "at least {}", x ),
+            (None, Some(y)) => write!( f, "at most {}", y ),
+            (None, None) => write!( f, "any whole number" ),
+        }
+    }
+}
+
+/// Every parameter one version of GE:S reads in map scripts.
+#[derive(Clone, Debug)]
+pub struct MapScriptSchema
+{
+    /// The version the schema was written for, which may be older than the one the release targets.
+    pub version: String,
+    pub terms: Vec<SchemaTerm>,
+}
+
+impl MapScriptSchema
+{
+    /// Returns the parameter with the given name, if the schema has it.
+    pub fn get_term( &self, name: &str ) -> Option<&SchemaTerm>
+    {
+        self.terms.iter().find(|x| x.name == name)
+    }
+
+    /// Returns the name of every parameter of the given type, in the order the schema lists them.
+    pub fn get_term_names( &self, term_type: TermType ) -> Vec<&str>
+    {
+        self.terms.iter().filter(|x| x.term_type == term_type).map(|x| x.name.as_str()).collect()
+    }
+}
+
+lazy_static!
+{
+    static ref BUILTIN_TERMS: Vec<SchemaTerm> = parse_schema( &PathBuf::from("map_script_schema.txt"), BUILTIN_SCHEMA ).unwrap();
+    static ref SCHEMA_OVERRIDES: RwLock<Vec<SchemaTerm>> = RwLock::new(Vec::new());
+}
+
+/// Replaces the versions a schema file gave.  Every later check sees the new schema.
+pub fn set_schema_overrides( schema_overrides: Vec<SchemaTerm> )
+{
+    *SCHEMA_OVERRIDES.write().unwrap() = schema_overrides;
+}
+
+/// Returns the schema map scripts for the GE:S version the release targets are checked against.  Versions without a
+/// schema of their own use the newest one that isn't newer than them, and releases without a known version use the
+/// newest of all.
+pub fn get_schema( args: &Arguments ) -> MapScriptSchema
+{
+    let schema_overrides = SCHEMA_OVERRIDES.read().unwrap();
+    get_version_schema( &BUILTIN_TERMS, &schema_overrides, args.ges_version.as_deref() )
+}
+
+/// Picks the schema for the given version out of the built in terms and overrides.  A version the overrides have
+/// replaces that version of the built in ones entirely.
+fn get_version_schema( builtin_terms: &[SchemaTerm], schema_overrides: &[SchemaTerm], target_version: Option<&str> ) -> MapScriptSchema
+{
+    let mut terms: Vec<&SchemaTerm> = builtin_terms.iter().filter(|x| !schema_overrides.iter().any(|y| y.version == x.version)).collect();
+    terms.extend( schema_overrides.iter() );
+
+    let mut versions: Vec<&str> = terms.iter().map(|x| x.version.as_str()).collect();
+    versions.sort_by_key(|x| update_checker::get_version_numbers( x ));
+    versions.dedup();
+
+    let newest_version = versions.last().cloned().unwrap_or("");
+
+    let version = match target_version
+    {
+        Some(x) =>
+        {
+            let target_numbers = update_checker::get_version_numbers( x );
+            versions.iter().rev().find(|y| update_checker::get_version_numbers( y ) <= target_numbers).cloned().unwrap_or( newest_version )
+        },
+        None => newest_version,
+    };
+
+    MapScriptSchema
+    {
+        version: String::from(version),
+        terms: terms.into_iter().filter(|x| x.version == version).cloned().collect(),
+    }
+}
+
+/// Reads the schema file at the given path, which uses the same format as the built in schema.
+pub fn read_schema_file( schema_path: &PathBuf ) -> Result<Vec<SchemaTerm>, GesError>
+{
+    let mut contents = String::new();
+    fs::File::open(schema_path)?.read_to_string(&mut contents)?;
+
+    parse_schema( schema_path, &contents )
+}
+
+/// Parses a schema, where each line holds a GE:S version, a map script parameter, its type, and the smallest and
+/// largest value it can have, like "5.0 ResIntensity integer 1 -".  Anything after // is a comment.
+fn parse_schema( schema_path: &PathBuf, contents: &str ) -> Result<Vec<SchemaTerm>, GesError>
+{
+    let mut schema_terms: Vec<SchemaTerm> = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate()
+    {
+        let line = match line.find("//")
+        {
+            Some(x) => &line[..x],
+            None => line,
+        };
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if tokens.is_empty()
+        {
+            continue;
+        }
+
+        let parse_error = |message: String| GesError::Parse { file: schema_path.clone(), line: line_index + 1, message };
+
+        if tokens.len() != 5
+        {
+            return Err(parse_error( String::from("Expected a GE:S version, a map script parameter, its type, and the smallest and largest value it can have!") ));
+        }
+
+        if tokens[0].split('.').any(|x| x.is_empty() || !x.chars().all(|y| y.is_ascii_digit()))
+        {
+            return Err(parse_error( format!( "{} isn't a GE:S version!  Expected a version number like 5.0.", tokens[0] ) ));
+        }
+
+        let term_type = match TermType::from_name( tokens[2] )
+        {
+            Some(x) => x,
+            None => return Err(parse_error( format!( "Unknown type {} for {}!  Expected integer or weights.", tokens[2], tokens[1] ) )),
+        };
+
+        let mut limits = Vec::new();
+
+        for limit in &tokens[3..]
+        {
+            match *limit
+            {
+                "-" => limits.push(None),
+                x => match x.parse::<i32>()
+                {
+                    Ok(y) => limits.push(Some(y)),
+                    Err(_) => return Err(parse_error( format!( "Limit {} for {} isn't a whole number or -!", x, tokens[1] ) )),
+                },
+            }
+        }
+
+        match (limits[0], limits[1])
+        {
+            (Some(x), Some(y)) if x > y => return Err(parse_error( format!( "The smallest value for {} is larger than its largest!", tokens[1] ) )),
+            _ => {},
+        }
+
+        if schema_terms.iter().any(|x| x.version == tokens[0] && x.name == tokens[1])
+        {
+            return Err(parse_error( format!( "{} is already in the schema for GE:S {}!", tokens[1], tokens[0] ) ));
+        }
+
+        schema_terms.push( SchemaTerm { version: String::from(tokens[0]), name: String::from(tokens[1]), term_type, min: limits[0], max: limits[1] } );
+    }
+
+    Ok(schema_terms)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_parse_schema()
+    {
+        let schema_path = PathBuf::from("map_script_schema.txt");
+
+        assert!( parse_schema( &schema_path, BUILTIN_SCHEMA ).is_ok() );
+
+        let schema_terms = parse_schema( &schema_path, "5.1  RoundTime  integer  30  600 // Comment\n5.1 ArenaWeights weights 0 -\n" ).unwrap();
+        assert_eq!( schema_terms[0], SchemaTerm { version: String::from("5.1"), name: String::from("RoundTime"), term_type: TermType::Integer, min: Some(30), max: Some(600) } );
+        assert_eq!( schema_terms[1].term_type, TermType::Weights );
+        assert_eq!( schema_terms[1].to_string(), "at least 0" );
+
+        assert!( parse_schema( &schema_path, "5.1 RoundTime integer 30" ).is_err() );
+        assert!( parse_schema( &schema_path, "latest RoundTime integer 30 600" ).is_err() );
+        assert!( parse_schema( &schema_path, "5.1 RoundTime decimal 30 600" ).is_err() );
+        assert!( parse_schema( &schema_path, "5.1 RoundTime integer 600 30" ).is_err() );
+        assert!( parse_schema( &schema_path, "5.1 RoundTime integer 30 600\n5.1 RoundTime integer 0 -" ).is_err() );
+    }
+
+    #[test]
+    fn test_get_version_schema()
+    {
+        let schema_path = PathBuf::from("map_script_schema.txt");
+        let schema_overrides = parse_schema( &schema_path, "5.0 BaseWeight integer 0 1000\n5.1 BaseWeight integer 0 -\n5.1 RoundTime integer 30 600" ).unwrap();
+
+        // A version in the overrides replaces the built in one entirely.
+        let schema = get_version_schema( &BUILTIN_TERMS, &schema_overrides, Some("5.0.6") );
+        assert_eq!( schema.version, "5.0" );
+        assert_eq!( schema.get_term_names( TermType::Integer ), vec!["BaseWeight"] );
+        assert!( !schema.get_term( "BaseWeight" ).unwrap().is_in_range( 1001 ) );
+
+        assert_eq!( get_version_schema( &BUILTIN_TERMS, &schema_overrides, None ).version, "5.1" );
+        assert_eq!( get_version_schema( &BUILTIN_TERMS, &schema_overrides, Some("5.2") ).version, "5.1" );
+        assert_eq!( get_version_schema( &BUILTIN_TERMS, &[], Some("5.2") ).get_term_names( TermType::Weights ),
+                    vec!["WeaponsetWeights", "GamemodeWeights", "TeamGamemodeWeights"] );
+
+        // Nothing is older than the first version, so the newest one is the best guess.
+        assert_eq!( get_version_schema( &BUILTIN_TERMS, &schema_overrides, Some("4.2.4") ).version, "5.1" );
+    }
+}
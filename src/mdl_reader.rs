@@ -0,0 +1,219 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------
+// mdl_reader: Pulls the bits of information we care about out of compiled Source engine models.
+// ------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::path::Path;
+
+use error::GesError;
+
+// Every Source model starts with these bytes, followed by the version and the rest of the header.
+const MDL_IDENT: &[u8] = b"IDST";
+const TEXTURE_COUNT_OFFSET: usize = 204;
+const CD_TEXTURE_COUNT_OFFSET: usize = 212;
+const HEADER_LENGTH: usize = 220;
+
+// Each texture entry starts with the offset of its name, and is followed by flags and pointers the engine fills in.
+const TEXTURE_ENTRY_LENGTH: usize = 64;
+
+// Models only ever have a handful of materials, so anything more than this is a broken header.
+const MAX_TEXTURE_COUNT: usize = 1024;
+
+/// The materials a model uses, as named in the model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModelMaterials
+{
+    /// The name of each material, which can have directories of its own.
+    pub texture_names: Vec<String>,
+    /// The directories under materials the engine looks for each name in, in the order it looks.  These are the
+    /// $cdmaterials lines of the model's QC file.
+    pub search_paths: Vec<String>,
+}
+
+impl ModelMaterials
+{
+    /// Returns the lowercase path of every vmt the engine could load for the given texture, in the order it tries
+    /// them, relative to the gesource directory.
+    pub fn get_candidate_paths( &self, texture_name: &str ) -> Vec<String>
+    {
+        self.search_paths.iter()
+                         .map(|x| normalize_material_path( &format!( "materials/{}/{}.vmt", x, texture_name ) ))
+                         .collect()
+    }
+}
+
+/// Returns the materials the given model uses.
+pub fn read_model_materials( mdl_path: &Path ) -> Result<ModelMaterials, GesError>
+{
+    let mut contents = Vec::new();
+    fs::File::open(mdl_path)?.read_to_end(&mut contents)?;
+
+    parse_model_materials( &contents ).map_err(|e| GesError::Parse { file: mdl_path.to_path_buf(), line: 0, message: String::from(e) })
+}
+
+/// Reads the texture names and search paths out of the contents of a model.
+pub fn parse_model_materials( contents: &[u8] ) -> Result<ModelMaterials, &'static str>
+{
+    if contents.len() < HEADER_LENGTH || &contents[..4] != MDL_IDENT
+    {
+        return Err("Model is not a valid Source engine MDL file!");
+    }
+
+    let texture_count = read_i32( contents, TEXTURE_COUNT_OFFSET );
+    let texture_offset = read_i32( contents, TEXTURE_COUNT_OFFSET + 4 );
+    let cd_texture_count = read_i32( contents, CD_TEXTURE_COUNT_OFFSET );
+    let cd_texture_offset = read_i32( contents, CD_TEXTURE_COUNT_OFFSET + 4 );
+
+    if texture_count > MAX_TEXTURE_COUNT || cd_texture_count > MAX_TEXTURE_COUNT
+    {
+        return Err("Model says it has far more materials than any model could!  It may be corrupt.");
+    }
+
+    let mut texture_names = Vec::new();
+
+    for index in 0..texture_count
+    {
+        // Each name is stored relative to the start of its own texture entry.
+        let entry_offset = texture_offset + index * TEXTURE_ENTRY_LENGTH;
+
+        if entry_offset + 4 > contents.len()
+        {
+            return Err("Model's texture list extends past the end of the file!  It may be truncated.");
+        }
+
+        texture_names.push( read_string( contents, entry_offset + read_i32( contents, entry_offset ) )? );
+    }
+
+    let mut search_paths = Vec::new();
+
+    for index in 0..cd_texture_count
+    {
+        // Search paths are stored as a list of offsets from the start of the file.
+        let entry_offset = cd_texture_offset + index * 4;
+
+        if entry_offset + 4 > contents.len()
+        {
+            return Err("Model's material search paths extend past the end of the file!  It may be truncated.");
+        }
+
+        search_paths.push( read_string( contents, read_i32( contents, entry_offset ) )? );
+    }
+
+    Ok(ModelMaterials { texture_names, search_paths })
+}
+
+/// Lowercases a material path and gives it forward slashes, with no doubled ones where a search path already ended
+/// in a slash.
+fn normalize_material_path( material_path: &str ) -> String
+{
+    let mut normalized_path = material_path.replace("\\", "/").to_lowercase();
+
+    while normalized_path.contains("//")
+    {
+        normalized_path = normalized_path.replace("//", "/");
+    }
+
+    normalized_path
+}
+
+/// Reads a little endian i32 from the given position in the data as an offset or count.  Negative ones can't point at
+/// anything, so they're treated as pointing past the end.
+fn read_i32( data: &[u8], position: usize ) -> usize
+{
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice( &data[position..position + 4] );
+
+    match i32::from_le_bytes( bytes )
+    {
+        x if x < 0 => usize::MAX / 2,
+        x => x as usize,
+    }
+}
+
+/// Reads the null terminated string at the given position in the data.
+fn read_string( data: &[u8], position: usize ) -> Result<String, &'static str>
+{
+    if position >= data.len()
+    {
+        return Err("Model names a material past the end of the file!  It may be truncated.");
+    }
+
+    let text = &data[position..];
+    let text = match text.iter().position(|x| *x == 0)
+    {
+        Some(x) => &text[..x],
+        None => return Err("Model has a material name that never ends!  It may be truncated."),
+    };
+
+    Ok(String::from_utf8_lossy( text ).into_owned())
+}
+
+/// Builds just enough of a model to hold the given materials, for testing anything that reads them.
+#[cfg(test)]
+pub fn build_test_model( texture_names: &[&str], search_paths: &[&str] ) -> Vec<u8>
+{
+    let texture_offset = HEADER_LENGTH;
+    let cd_texture_offset = texture_offset + texture_names.len() * TEXTURE_ENTRY_LENGTH;
+    let mut string_offset = cd_texture_offset + search_paths.len() * 4;
+
+    let mut model = Vec::new();
+    model.extend_from_slice( MDL_IDENT );
+    model.resize( HEADER_LENGTH, 0 );
+    model[TEXTURE_COUNT_OFFSET..TEXTURE_COUNT_OFFSET + 4].copy_from_slice( &(texture_names.len() as i32).to_le_bytes() );
+    model[TEXTURE_COUNT_OFFSET + 4..TEXTURE_COUNT_OFFSET + 8].copy_from_slice( &(texture_offset as i32).to_le_bytes() );
+    model[CD_TEXTURE_COUNT_OFFSET..CD_TEXTURE_COUNT_OFFSET + 4].copy_from_slice( &(search_paths.len() as i32).to_le_bytes() );
+    model[CD_TEXTURE_COUNT_OFFSET + 4..CD_TEXTURE_COUNT_OFFSET + 8].copy_from_slice( &(cd_texture_offset as i32).to_le_bytes() );
+
+    let mut strings = Vec::new();
+
+    for (index, name) in texture_names.iter().enumerate()
+    {
+        let mut entry = vec![0u8; TEXTURE_ENTRY_LENGTH];
+        entry[..4].copy_from_slice( &((string_offset - (texture_offset + index * TEXTURE_ENTRY_LENGTH)) as i32).to_le_bytes() );
+        model.extend_from_slice( &entry );
+
+        strings.extend_from_slice( name.as_bytes() );
+        strings.push( 0 );
+        string_offset += name.len() + 1;
+    }
+
+    for path in search_paths
+    {
+        model.extend_from_slice( &(string_offset as i32).to_le_bytes() );
+
+        strings.extend_from_slice( path.as_bytes() );
+        strings.push( 0 );
+        string_offset += path.len() + 1;
+    }
+
+    model.extend_from_slice( &strings );
+    model
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_parse_model_materials()
+    {
+        let model = build_test_model( &["Crate_Wood", "metal/bolts"], &["models\\ge_test\\", "models/shared"] );
+        let materials = parse_model_materials( &model ).unwrap();
+
+        assert_eq!( materials.texture_names, vec!["Crate_Wood", "metal/bolts"] );
+        assert_eq!( materials.search_paths, vec!["models\\ge_test\\", "models/shared"] );
+        assert_eq!( materials.get_candidate_paths( "Crate_Wood" ), vec!["materials/models/ge_test/crate_wood.vmt", "materials/models/shared/crate_wood.vmt"] );
+
+        // A model cut off partway through its strings can't be trusted.
+        assert!( parse_model_materials( &model[..model.len() - 4] ).is_err() );
+        assert!( parse_model_materials( b"IDST" ).is_err() );
+        assert!( parse_model_materials( &vec![0u8; HEADER_LENGTH] ).is_err() );
+    }
+}
@@ -7,29 +7,81 @@
 // music_script_builder: Contains functions for analyzing and building music script files for GoldenEye: Source maps.
 // ------------------------------------------------------------------------------------------------------------------
 
-use std::fs;
 use std::io::prelude::*;
 use argument_handler::Arguments;
 
-use std::path::PathBuf;
-use std::io::{Error, ErrorKind};
-use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use shared;
+use shared::DirectoryTree;
+use rules;
+use bsp_reader;
+use audio_scanner;
+use file_writer;
+use script_encoding;
+use generation_metadata;
+use generation_metadata::EditState;
+use stock_content;
+use ges_version;
+use file_writer::FileWriter;
+use error::GesError;
+use keyvalues;
+use keyvalues::{Token, TokenError, TokenKind, Tokenizer};
+use preflight::PreflightReport;
 
-use regex::Regex;
+
+// How many times through the main music list is acceptable over a single match.  Any more and players will start to notice.
+const MUSIC_ROTATION_FACTOR: f64 = 0.5;
+
+/// A bracketed list of tracks in a music script.  The main "music" section has no name, the rest are named after the
+/// music area that plays them.
+struct MusicSection
+{
+    name: Option<String>,
+    line: usize,
+    /// Where the section is in the script, from its name to its closing bracket.
+    start: usize,
+    end: usize,
+    tracks: Vec<MusicTrack>,
+}
+
+/// A single "file" entry in a music script, along with where it is in the script.  The end includes its attributes.
+struct MusicTrack
+{
+    path: String,
+    line: usize,
+    start: usize,
+    end: usize,
+    attributes: Vec<TrackAttribute>,
+}
+
+/// An optional setting for a single track, given in a bracketed block right after its path such as { "volume" "0.5" }.
+struct TrackAttribute
+{
+    name: String,
+    value: String,
+    line: usize,
+}
+
+// The track attributes this program knows how to check, along with the smallest and largest value each can have.
+// Any others are left alone, since they may be meant for a newer version of GE:S.
+static TRACK_ATTRIBUTES: &[(&'static str, f64, f64)] = &[("volume", 0.0, 1.0), ("fade", 0.0, 60.0)];
 
 /// Generates the music script file used for music selection on the map
 /// Returns Ok() if successful and an error if not.
-pub fn create_or_verify_music_script_file( args: &Arguments, map_name: &str ) -> Result<(), Error>
+pub fn create_or_verify_music_script_file( args: &Arguments, map_name: &str ) -> Result<(), GesError>
 {
     let mut music_script_dir = args.rootdir.clone();
     music_script_dir.push("scripts");
     music_script_dir.push("music");
 
-    if !music_script_dir.is_dir()
+    // Check-only mode reports the missing script below instead.
+    if !music_script_dir.is_dir() && !file_writer::is_read_only()
     {
-        fs::create_dir_all(&music_script_dir)?;
+        file_writer::create_dir_all(&music_script_dir)?;
     }
 
     // Just build the music script path off of the existing script dir builder.
@@ -43,7 +95,11 @@ pub fn create_or_verify_music_script_file( args: &Arguments, map_name: &str ) ->
     music_script_path.push( music_script_name );
     music_script_path.set_extension("txt");
 
-    if !music_script_path.is_file()
+    if !music_script_path.is_file() && file_writer::is_read_only()
+    {
+        return Err(GesError::MissingFile { message: format!( "No music script exists at {}, and check-only mode won't create one!", music_script_path.display() ), path: music_script_path });
+    }
+    else if !music_script_path.is_file()
     {
         create_music_script_file( args, &music_script_path )?;
         println!("Created music script for {}!", map_name);
@@ -58,7 +114,7 @@ pub fn create_or_verify_music_script_file( args: &Arguments, map_name: &str ) ->
 }
 
 /// Checks every music script in the provided or autodetected GE:S directory.
-pub fn fullcheck_music_script_files( args: &Arguments ) -> Result<(), Error>
+pub fn fullcheck_music_script_files( args: &Arguments ) -> Result<(), GesError>
 {
     let mut music_script_dir = args.gesdir.clone();
     music_script_dir.push("scripts");
@@ -66,24 +122,102 @@ pub fn fullcheck_music_script_files( args: &Arguments ) -> Result<(), Error>
 
     if !music_script_dir.is_dir()
     {
-        return Err(Error::new( ErrorKind::InvalidData, "Music script directory does not exist!  Is this really a valid GE:S install?" ));
+        return Err(GesError::MissingFile { path: music_script_dir, message: String::from("Music script directory does not exist!  Is this really a valid GE:S install?") });
     }
 
-    shared::check_all_files_in_dir_with_func( args, &music_script_dir, "txt", "music scripts", check_music_script_file )?;
+    report_music_script_orphans( args, &music_script_dir )?;
+
+    script_encoding::fullcheck_script_encodings( args, &music_script_dir, "txt" )?;
+
+    shared::check_all_files_in_dir_with_func( args, &music_script_dir, "txt", "music scripts", check_music_script_file, get_music_script_map_paths )?;
 
     Ok(())
 }
 
+/// Lets server owners know about music scripts left behind by maps that have been removed, and maps that don't have
+/// a music script of their own.
+fn report_music_script_orphans( args: &Arguments, music_script_dir: &PathBuf ) -> Result<(), GesError>
+{
+    let mut map_dir = args.gesdir.clone();
+    map_dir.push("maps");
+
+    let map_names = shared::get_file_stems_in_dir( &map_dir, "bsp" )?;
+
+    let scripted_map_names: Vec<String> = shared::get_file_stems_in_dir( music_script_dir, "txt" )?.iter()
+                                                 .filter_map(|x| x.strip_prefix("level_music_").map(String::from))
+                                                 .collect();
+
+    let orphaned_scripts: Vec<String> = scripted_map_names.iter()
+                                                          .filter(|x| !map_names.contains(x))
+                                                          .map(|x| format!( "scripts/music/level_music_{}.txt", x ))
+                                                          .collect();
+
+    let unscripted_maps: Vec<String> = map_names.iter()
+                                                .filter(|x| !scripted_map_names.contains(x))
+                                                .map(|x| format!( "maps/{}.bsp", x ))
+                                                .collect();
+
+    shared::report_cleanup_list( args, "music-orphaned-script", "music scripts are for maps that aren't installed and can be removed", &orphaned_scripts )?;
+    shared::report_cleanup_list( args, "music-missing-script", "maps have no music script and will use the default music", &unscripted_maps )
+
+}
+
+/// Adds what the music script section needs before it runs to the preflight report.
+pub fn preflight( args: &Arguments, map_name: &str, report: &mut PreflightReport )
+{
+    if args.fullcheck
+    {
+        let mut music_script_dir = args.gesdir.clone();
+        music_script_dir.push("scripts");
+        music_script_dir.push("music");
+
+        report.recommend( "Music scripts", &music_script_dir, "a music script directory to check", music_script_dir.is_dir() );
+
+        // Checking is fine on a read-only install, only fixing has to write.
+        if args.fix && music_script_dir.is_dir()
+        {
+            report.require( "Music scripts", &music_script_dir, "write access for --fix to rewrite music scripts", file_writer::can_create_in(&music_script_dir) );
+        }
+    }
+    else
+    {
+        // Without any music of its own the map just gets a default music script.
+        let mut music_dir = args.rootdir.clone();
+        music_dir.push("sound");
+        music_dir.push("music");
+
+        report.recommend( "Music scripts", &music_dir, "a music directory (a default music script is provided without one)", music_dir.is_dir() );
+
+        let mut music_script_dir = args.rootdir.clone();
+        music_script_dir.push("scripts");
+        music_script_dir.push("music");
+
+        let mut music_script_path = music_script_dir.clone();
+        music_script_path.push( format!( "level_music_{}", map_name ) );
+        music_script_path.set_extension("txt");
+
+        if !music_script_path.is_file() && !file_writer::is_read_only()
+        {
+            report.require( "Music scripts", &music_script_dir, "write access to create the missing music script", file_writer::can_create_in(&music_script_dir) );
+        }
+    }
+}
+
 /// Creates a music script file at the given path using the files provided in the sound directory.
 /// If none are provided, it will create a default script instead.
-fn create_music_script_file( args: &Arguments, music_script_path: &PathBuf ) -> Result<(), Error>
+fn create_music_script_file( args: &Arguments, music_script_path: &PathBuf ) -> Result<(), GesError>
 {
     let mut music_files_dir = args.rootdir.clone();
     music_files_dir.push("sound");
 
-    let (_music_file_comp_names, mut music_file_write_names) = shared::get_files_in_directory( &music_files_dir, "mp3", &[] )?;
+    let mut music_file_write_names = shared::get_cached_directory_tree( &[&music_files_dir], "mp3", &[], shared::keep_all_files )?.1.clone();
 
     // We don't have a sound directory, or it's empty, so let's provide some example music instead!
+    if music_file_write_names.is_empty() && args.sample_music > 0
+    {
+        music_file_write_names = sample_stock_music( args )?;
+    }
+
     if music_file_write_names.is_empty() 
     {
         music_file_write_names.push(String::from("music/classy.mp3"));
@@ -107,167 +241,859 @@ fn create_music_script_file( args: &Arguments, music_script_path: &PathBuf ) ->
     contents.push_str("}\r\n");
 
     // Make it official and write the final string to the file.
-    let mut music_script_file = fs::File::create(music_script_path)?;
-    music_script_file.write_all(contents.as_bytes())?;
+    let mut music_script_file = FileWriter::create(music_script_path)?;
+    music_script_file.write_all( generation_metadata::stamp( args, &contents )?.as_bytes() )?;
 
     Ok(())
 }
 
-/// Ensures that the music script file follows the correct format and that every file reference is valid.
-fn check_music_script_file( args: &Arguments, music_script_path: &PathBuf ) -> Result<(), Error>
+/// Returns where the music script for the given map goes in the given gesource directory.
+fn get_music_script_path( gesource_dir: &PathBuf, map_name: &str ) -> PathBuf
 {
-    let music_script_file = fs::File::open(music_script_path)?;
-    let mut reader = BufReader::new(music_script_file);
+    let mut music_script_path = gesource_dir.clone();
+    music_script_path.push("scripts");
+    music_script_path.push("music");
+    music_script_path.push( format!( "level_music_{}.txt", map_name ) );
+
+    music_script_path
+}
+
+/// Writes a music script for the given map that plays the same music as the source map's, for maps that share a
+/// soundtrack.  Music area sections the map doesn't have are left out.  The copy is checked like any other music
+/// script before it's written, so one with tracks the install doesn't have is never made.  Returns where it went.
+pub fn copy_music_script( args: &Arguments, map_name: &str, source_map_name: &str ) -> Result<PathBuf, GesError>
+{
+    // Otherwise a second copy of the program could write its own script between the check below and the copy.
+    let _root_lock = file_writer::lock_directory( &args.rootdir )?;
+
+    let music_script_path = get_music_script_path( &args.rootdir, map_name );
+
+    if music_script_path.is_file()
+    {
+        let mut error_text = String::new();
+        error_text.push_str( &music_script_path.display().to_string() );
+        error_text.push_str(" already exists!  Remove it first if it should be replaced with a copy.");
+
+        return Err(GesError::Config( error_text ));
+    }
+
+    // The source is usually an installed map, but could be another map in the release.
+    let source_path = match [&args.rootdir, &args.gesdir].iter().map(|x| get_music_script_path( x, source_map_name )).find(|x| x.is_file())
+    {
+        Some(x) => x,
+        None =>
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Couldn't find a music script for ");
+            error_text.push_str(source_map_name);
+            error_text.push_str(" in either the root or GE:S directory!  Make sure the map name is spelled the way its music script is.");
+
+            return Err(GesError::MissingFile { path: get_music_script_path( &args.gesdir, source_map_name ), message: error_text });
+        },
+    };
+
+    let source_contents = match shared::read_script_file( &source_path )
+    {
+        Err(e @ GesError::Parse { .. }) => return Err(rules::fail( "music-script-syntax", e )),
+        x => x?,
+    };
+
+    // The copy gets a stamp of its own, so the source's is dropped.
+    let source_contents = match generation_metadata::read_metadata( &source_contents )
+    {
+        Some((_, x)) => String::from(x),
+        None => source_contents,
+    };
+
+    let uncommented_contents = shared::blank_out_comments( &source_contents );
+    let sections = parse_music_sections( &uncommented_contents ).map_err(|e| rules::fail( "music-script-syntax", e.in_file( &source_path ) ))?;
+
+    let known_areas = get_known_music_areas( args, &music_script_path );
+    let mut removed_spans = Vec::new();
+
+    for section in &sections
+    {
+        match (&section.name, &known_areas)
+        {
+            (&Some(ref x), &Some(ref y)) if !y.contains( &x.to_lowercase() ) =>
+            {
+                println!( "Leaving out music section {}, since {} has no music area by that name.", shared::get_quotable_text( x ), map_name );
+                // Take the whitespace before the section too, so the blank line separating it doesn't stay behind.
+                removed_spans.push( (source_contents[..section.start].trim_end().len(), section.end) );
+            },
+            _ => {},
+        }
+    }
 
     let mut contents = String::new();
-    reader.read_to_string( &mut contents )?;
-
-    // We'll use regular expressions to verify our format.
-    // We will have a music tag to start our file, then a large bracketed section.
-    // the bracketed section may have addtional bracketed sections inside it for X music and 
-    // area specific music, but these sections will not contain more bracketed sections.
-    // The main bracketed section and the subsections will contain lines like so:
-    // "file"   "[path/to/file]"
-    // where "file" is the exact text that appears in that part of the line and [path/to/file]
-    // contains the path where that file can be found.
-    // This makes a regex one of the more clean ways to verify the format is followed and then
-    // scan the individual entries to make sure the tracks are entered correctly.
-    // [^"\{\}] for every character that isn't a control character and
-    // [\S&&[^"\{\}]] for every non-whitespace character that isn't a control character.
-    // Lazy static is used to allow for compiler optimizations and to ensure costly regexs aren't compiled
-    // multiple times.
-    lazy_static!
-    {
-        static ref FILE_RE: Regex = Regex::new(r#"(?x)^\s*(("music")|(music))\s*
-                                        (\{\s*
-                                        (
-                                        (\s*(("file")|(file))\s+(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s*)
-                                        |
-                                        (
-                                        (("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s*
-                                        \{\s*
-                                        (\s*(("file")|(file))\s+(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s*)+
-                                        \}\s*
-                                        )
-                                        )*
-                                        \})\s*$"#).unwrap();
-    }
-
-    if !FILE_RE.is_match(&contents)
-    {
-        return Err(Error::new( ErrorKind::InvalidData, "Script contains core format mistake!\n  Make sure every \
-                                                        bracket and quotation mark has a partner, the main section \
-                                                        is labeled \"music\", each file path has a \"file\"\
-                                                        section before it, no bracketed sections are empty,\
-                                                        and that there are no nested bracketed sections inside\
-                                                        nested bracketed sections."));
+    contents.push_str("// Music copied from ");
+    contents.push_str(source_map_name);
+    contents.push_str( if source_contents.contains("\r\n") { ".\r\n" } else { ".\n" } );
+    contents.push_str( &remove_spans( &source_contents, removed_spans ) );
+
+    let contents = generation_metadata::stamp( args, &contents )?;
+
+    check_music_script_contents( args, &music_script_path, &contents, |x, y| generate_mp3_directory_tree( x, y, "mp3" ) )?;
+
+    match music_script_path.parent()
+    {
+        Some(x) => file_writer::create_dir_all( x )?,
+        None => {},
     }
 
+    file_writer::write( &music_script_path, contents.as_bytes() )?;
+
+    Ok(music_script_path)
+}
+
+/// Returns --sample-music tracks picked at random from the stock music, for maps without music of their own.  Picks
+/// from the GE:S install's music directory, or the stock content database without one.  The same --music-seed always
+/// picks the same tracks from the same music.
+fn sample_stock_music( args: &Arguments ) -> Result<Vec<String>, GesError>
+{
+    let mut gesource_sound_dir = args.gesdir.clone();
+    gesource_sound_dir.push("sound");
+
+    let mut stock_music = if stock_content::use_stock_database( args ) || !gesource_sound_dir.is_dir()
+    {
+        stock_content::get_stock_paths( "sound", "mp3" )
+    }
+    else
+    {
+        shared::get_cached_directory_tree( &[&gesource_sound_dir], "mp3", &[], shared::keep_all_files )?.1.clone()
+    };
+
+    stock_music.retain(|x| x.starts_with("music/"));
+
+    if stock_music.is_empty()
+    {
+        shared::print_warning( "Couldn't find any stock music to sample from, so the default music will be used instead." );
+        return Ok(stock_music);
+    }
+
+    let seed = match args.music_seed
+    {
+        Some(x) => x,
+        None => get_random_seed(),
+    };
+
+    println!( "Sampling the default music with seed {}.  Run with --music-seed {} to pick the same tracks again.", seed, seed );
+
+    Ok(sample_tracks( stock_music, args.sample_music, seed ))
+}
+
+/// Returns a seed for sampling music when none was given.
+pub fn get_random_seed() -> u64
+{
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_nanos() as u64).unwrap_or(0)
+}
+
+/// Returns the given number of tracks picked at random from the given ones, or all of them in a random order if there
+/// aren't that many.  The same seed always picks the same tracks in the same order.
+fn sample_tracks( mut tracks: Vec<String>, count: usize, seed: u64 ) -> Vec<String>
+{
+    // A plain LCG is plenty for picking songs, and unlike the OS it gives the same numbers for the same seed everywhere.
+    let mut state = seed;
+    let count = count.min( tracks.len() );
+
+    for i in 0..count
+    {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let picked = i + (state >> 33) as usize % (tracks.len() - i);
+        tracks.swap( i, picked );
+    }
+
+    tracks.truncate(count);
+    tracks
+}
+
+/// Ensures that the music script file follows the correct format and that every file reference is valid.
+pub fn check_music_script_file( args: &Arguments, music_script_path: &PathBuf ) -> Result<(), GesError>
+{
+    check_music_script_file_with_tree( args, music_script_path, |gesource_sound_dir, local_music_files_dir| generate_mp3_directory_tree( gesource_sound_dir, local_music_files_dir, "mp3" ) )
+}
+
+/// Same as check_music_script_file, but gets the list of available mp3 files from get_mp3_tree instead of the
+/// directory cache.  get_mp3_tree is given the GE:S and local sound directories and is only called if it's needed.
+pub fn check_music_script_file_with_tree<F>( args: &Arguments, music_script_path: &PathBuf, get_mp3_tree: F ) -> Result<(), GesError>
+    where F: FnOnce( &PathBuf, &PathBuf ) -> Result<Arc<DirectoryTree>, GesError>
+{
+    shared::set_current_file( music_script_path );
+
+    let original_contents = match shared::read_script_file( music_script_path )
+    {
+        Err(e @ GesError::Parse { .. }) => return Err(rules::fail( "music-script-syntax", e )),
+        x => x?,
+    };
+
+    check_music_script_contents( args, music_script_path, &original_contents, get_mp3_tree )
+}
+
+/// Same as check_music_script_file_with_tree, but checks the given contents instead of reading them from the music
+/// script.  The script is still rewritten at the given path if --fix removes anything from it.
+pub fn check_music_script_contents<F>( args: &Arguments, music_script_path: &PathBuf, original_contents: &str, get_mp3_tree: F ) -> Result<(), GesError>
+    where F: FnOnce( &PathBuf, &PathBuf ) -> Result<Arc<DirectoryTree>, GesError>
+{
+    rules::load_suppressions( music_script_path, original_contents );
+    let edit_state = generation_metadata::report_edit_state( args, music_script_path, original_contents )?;
+
+    let legacy_args = rules::get_legacy_args( args, &edit_state );
+    let args = legacy_args.as_ref().unwrap_or( args );
+
+    // GE:S reads music scripts as KeyValues, which allows // comments anywhere, so check the script without them.
+    let mut contents = shared::blank_out_comments( original_contents );
+
+    // A music script is a "music" section holding tracks, along with sections named after music areas that hold the
+    // tracks for those areas.  Each track is a "file" followed by the path of the track in the sound directory.
+    let mut sections = parse_music_sections( &contents ).map_err(|e| rules::fail( "music-script-syntax", e.in_file( music_script_path ) ))?;
+
+    // GE:S doesn't mind the same track being listed twice, but it makes that track twice as likely to play, which is
+    // rarely what anyone intended.
+    let duplicate_tracks = find_duplicate_tracks( &sections );
+
+    if !args.fix
+    {
+        for &(section, track) in &duplicate_tracks
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Music file ");
+            error_text.push_str( &shared::get_quotable_text( &track.path ) );
+            error_text.push_str(" is listed more than once in the ");
+            error_text.push_str( match section.name { Some(ref x) => x, None => "main" } );
+            error_text.push_str(" section!  Please remove the redundant entries, or run with --fix to remove them automatically.");
+
+            rules::report( args, "music-duplicate-track", GesError::Parse { file: music_script_path.clone(), line: track.line, message: error_text } )?;
+        }
+    }
+    else
+    {
+        // Duplicates someone has marked as deliberate stay put.
+        let removed_tracks: Vec<&MusicTrack> = duplicate_tracks.iter()
+                                                               .map(|x| x.1)
+                                                               .filter(|x| !rules::is_suppressed( music_script_path, x.line, "music-duplicate-track" ))
+                                                               .collect();
+
+        if !removed_tracks.is_empty()
+        {
+            let fixed_contents = remove_tracks( original_contents, &removed_tracks );
+
+            // A script that was still as we generated it stays recognized as ours, but a hand edited one keeps its old
+            // stamp so the edits aren't passed off as generated.
+            match edit_state
+            {
+                EditState::Unedited(_) => file_writer::write( music_script_path, generation_metadata::restamp( args, &fixed_contents )?.as_bytes() )?,
+                _ => file_writer::write( music_script_path, fixed_contents.as_bytes() )?,
+            }
+
+            println!( "Removed {} duplicate music entries from {}!", removed_tracks.len(), music_script_path.display() );
+
+            contents = shared::blank_out_comments( &fixed_contents );
+            sections = parse_music_sections( &contents ).map_err(|e| rules::fail( "music-script-syntax", e.in_file( music_script_path ) ))?;
+        }
+    }
+
+    // The format is right, so see if the sections make sense for the map they're for.
+    check_music_sections( args, music_script_path, &sections )?;
+    check_track_attributes( args, music_script_path, &sections )?;
+
     // Now let's make sure the music paths are valid!  This involves checking the script paths against the GE:S
     // install and the files in the local directory tree.
 
     let mut gesource_sound_dir = args.gesdir.clone();
     gesource_sound_dir.push("sound");
 
+    // Without an install, or in offline mode, stock music comes from the stock content database instead.
+    let use_stock_database = stock_content::use_stock_database( args ) || !gesource_sound_dir.is_dir();
+
     // Couldn't locate sound directory...which in pretty much all cases means that the gesdir isn't valid either
     // and it was mentioned in the program arguments checker.  If not, and the user for some reason has a corrupted
     // GE:S install somehow, the error message still makes a fair bit of sense.
-    if !gesource_sound_dir.is_dir()
+    if use_stock_database && !stock_content::has_stock_files()
     {
-        println!("[Warning] Without a valid GE:S directory, music file paths will not be checked, though file format will be!");
+        shared::print_warning( "Without a valid GE:S directory, music file paths will not be checked, though file format will be!" );
         return Ok(()); // We've already checked all we can without a GE:S music directory to cross reference our paths with.
     }
 
     let mut local_music_files_dir = args.rootdir.clone();
     local_music_files_dir.push("sound");
 
+    let stock_mp3_files = if use_stock_database { stock_content::get_stock_paths( "sound", "mp3" ) } else { Vec::new() };
+
+    // The local sound directory stands in for the GE:S one when the database has the stock music, and there may not even be one of those.
+    if use_stock_database
+    {
+        gesource_sound_dir = local_music_files_dir.clone();
+    }
+
     // Get all possible mp3 files that we can use.
     // You might wonder why this is preferable to just checking if the MP3 files in the script are valid files
     // on an as-needed basis.  Well, this would normally be ideal, but the assumption is that if a file is in
     // the sound directory it will probably be used, so we might as well scan them all at once.  This breaks down
     // a bit with the inclusion of scanning the local GE:S sound directory as well, but it does shave off a large
     // amount of syscalls on fullcheck mode and lets us share a lot of code between us and the reslist checker.
-    let &( ref mp3_files, ref _mp3_files_write) = generate_mp3_directory_tree( &gesource_sound_dir, &local_music_files_dir, "mp3" )?;
+    let mp3_tree = if use_stock_database && !local_music_files_dir.is_dir() { Arc::new((Vec::new(), Vec::new())) } else { get_mp3_tree( &gesource_sound_dir, &local_music_files_dir )? };
+    let ( ref mp3_files, ref _mp3_files_write) = *mp3_tree;
 
-    // If we made it here it means we have a valid file with at least one file entry.  Check those file entries
-    // to make sure they're formatted correctly and point to a valid music file.
+    // If we made it here it means we have a valid file.  Check its file entries, in the order they're written, to
+    // make sure each one points to a valid music file.
+    let mut tracks: Vec<&MusicTrack> = sections.iter().flat_map(|x| x.tracks.iter()).collect();
+    tracks.sort_by_key(|x| x.start);
 
-    lazy_static!
+    for track in tracks
     {
-        static ref RE: Regex = Regex::new(r#"\s*(("file")|(file))\s+(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s*"#).unwrap();
-    }
-
-    for cap in RE.captures_iter(&contents)
-    {
-        // We've already verified we've got a capture, and slot 4 is mandatory for us to have one.
-        let fixed_path = cap[4].replace("\"", "").replace("\\", "/").to_lowercase(); // Remove possible quotation marks and standardize slashes.
+        let fixed_path = &track.path;
 
-        // Make sure we're an mp3...or are at least claiming to be.
-        if shared::get_string_file_extension( fixed_path.as_str() ).to_lowercase() != "mp3"
+        // Make sure we're a format the targeted GE:S version can play...or are at least claiming to be.
+        if !ges_version::get_profile( args ).music_extensions.contains( &shared::get_string_file_extension( fixed_path ).to_lowercase().as_str() )
         {
             let mut error_text = String::new();
             error_text.push_str("File ");
-            error_text.push_str(&fixed_path);
+            error_text.push_str( &shared::get_quotable_text( fixed_path ) );
             error_text.push_str(" is not an MP3 file!  Please convert it to mp3 format.");
 
-            return Err(Error::new(ErrorKind::InvalidData, error_text ));
+            rules::report( args, "music-not-mp3", GesError::Parse { file: music_script_path.clone(), line: track.line, message: error_text } )?;
+            continue;
         }
 
         // Check to see if our MP3 file is one of the files we've detected in the relevant directories.
         // if not, our script is pointing to an invalid file and isn't ready for release!
-        if !mp3_files.contains(&fixed_path)
+        if !mp3_files.contains(fixed_path) && !stock_mp3_files.contains(fixed_path)
         {
             let mut error_text = String::new();
             error_text.push_str("Failed to locate music file ");
-            error_text.push_str(&fixed_path);
-            error_text.push_str(" in either the GE:S or local directory tree\nEnsure that the file path is valid and that the file exists.");
+            error_text.push_str( &shared::get_quotable_text( fixed_path ) );
+            error_text.push_str( if use_stock_database { " in either the stock content database or local directory tree" } else { " in either the GE:S or local directory tree" } );
+            error_text.push_str("\nEnsure that the file path is valid and that the file exists.");
 
-            return Err(Error::new(ErrorKind::InvalidData, error_text ));
+            rules::report_in_file( args, "music-missing-file", music_script_path, track.line,
+                                   GesError::MissingFile { path: PathBuf::from(fixed_path), message: error_text } )?;
         }
     }
 
+    // Reading every track is slow and a whole install's worth of stock music isn't what this is meant for, so only
+    // check the runtime when preparing a release.
+    if !args.fullcheck
+    {
+        check_music_runtime( args, music_script_path, &sections[0], &gesource_sound_dir, &local_music_files_dir )?;
+    }
+
     // We made sure the file format is correct and checked all the files for validity!
     // Our music script file is ready for release!
     Ok(())
 }
 
-use std::sync::Mutex;
+/// Warns if the main music list is short enough that it'll loop more than MUSIC_ROTATION_FACTOR times in a typical match.
+fn check_music_runtime( args: &Arguments, music_script_path: &PathBuf, main_section: &MusicSection, gesource_sound_dir: &PathBuf, local_sound_dir: &PathBuf ) -> Result<(), GesError>
+{
+    // Reading every track takes a while, so don't bother if nobody wants to hear about it.
+    if args.match_length == 0 || !rules::is_enabled( args, "music-runtime" )
+    {
+        return Ok(());
+    }
+
+    let mut track_paths: Vec<&str> = main_section.tracks.iter().map(|x| x.path.as_str()).collect();
+    track_paths.sort();
+    track_paths.dedup();
+
+    let mut total_runtime_secs = 0.0;
 
-/// Provides a reference to a vector storing strings that correspond to the relative paths of every file in
-/// the provided directory.  Subsequent calls return the cached value of the first call.
-pub fn generate_mp3_directory_tree( gesource_sound_dir: &PathBuf, local_sound_dir: &PathBuf, target_type: &str ) -> Result<&'static (Vec<String>, Vec<String>), Error>
+    for track_path in track_paths
+    {
+        // Local music takes priority over the stock music, just like it does in game.
+        let mut music_path = local_sound_dir.clone();
+        music_path.push(track_path);
+
+        if !music_path.is_file()
+        {
+            music_path = gesource_sound_dir.clone();
+            music_path.push(track_path);
+        }
+
+        match audio_scanner::read_mp3_info( &music_path )
+        {
+            Ok(x) => total_runtime_secs += x.duration_secs,
+            Err(e) =>
+            {
+                // Without every track we can't say how long the playlist is, and guessing would just be noise.
+                if args.verbose
+                {
+                    println!( "Skipping music runtime check for {} since {} couldn't be read: {}", music_script_path.display(), track_path, e );
+                }
+
+                return Ok(());
+            },
+        }
+    }
+
+    let runtime_budget_secs = args.match_length as f64 * 60.0 * MUSIC_ROTATION_FACTOR;
+
+    if total_runtime_secs < runtime_budget_secs
+    {
+        rules::report_in_file( args, "music-runtime", music_script_path, main_section.line, GesError::PolicyViolation( format!( "The main music list of {} only runs for {:.1} minutes, so it'll loop {:.1} times in a {} minute match.  \
+                                          Consider adding enough music to last at least {:.1} minutes.", music_script_path.display(), total_runtime_secs / 60.0,
+                                          args.match_length as f64 * 60.0 / total_runtime_secs.max(1.0), args.match_length, runtime_budget_secs / 60.0 ) ) )?;
+    }
+    else if args.verbose
+    {
+        println!( "The main music list of {} runs for {:.1} minutes.", music_script_path.display(), total_runtime_secs / 60.0 );
+    }
+
+    Ok(())
+}
+
+/// Returns every track listed anywhere in the given music script, relative to the sound directory.
+pub fn read_music_tracks( music_script_path: &PathBuf ) -> Result<Vec<String>, GesError>
 {
-    lazy_static!
+    let contents = shared::blank_out_comments( &shared::read_script_file( music_script_path )? );
+
+    let mut tokenizer = Tokenizer::new( &contents );
+    let mut tracks = Vec::new();
+    let mut previous_token: Option<Token> = None;
+
+    // A script GE:S can't read still names the tracks it was meant to play, so every track up to where it goes wrong counts.
+    while let Ok(Some(token)) = tokenizer.next_token()
     {
-        static ref DIRLIST_INIT_STATE: Mutex<bool> = Mutex::new(false);
+        match previous_token
+        {
+            Some(ref x) if x.is_text("file") && token.kind == TokenKind::Text && token.spaced =>
+            {
+                tracks.push( token.text.replace("\\", "/").to_lowercase() );
+                previous_token = None;
+                continue;
+            },
+            _ => {},
+        }
+
+        previous_token = Some(token);
     }
 
-    static mut DIRLIST: Option<(Vec<String>, Vec<String>)> = None;
+    Ok(tracks)
+}
+
+/// Reads the sections of a music script the way GE:S does, recording the line each section and track is on.  Comments
+/// have to be blanked out already.  The main "music" section can hold both tracks and sections named after music
+/// areas, but music area sections can only hold tracks.
+fn parse_music_sections( contents: &str ) -> Result<Vec<MusicSection>, TokenError>
+{
+    let mut tokenizer = Tokenizer::new( contents );
+
+    let music_token = tokenizer.expect_token( "\"music\" to start the script" )?;
+    if !music_token.is_text("music")
+    {
+        return Err(TokenError::unexpected( &music_token, "\"music\" to start the script" ));
+    }
 
-    // Unsafe because the alternative is more convoluted to use, the possibility of a data race is almost 0,
-    // and the negative outcome of one would be a performance penalty and nothing else.
-    unsafe
+    let open_token = tokenizer.expect_token( "{ to open the music section" )?;
+    if open_token.kind != TokenKind::Open
     {
-        let mut dirs_to_scan = vec![gesource_sound_dir];
+        return Err(TokenError::unexpected( &open_token, "{ to open the music section" ));
+    }
+
+    let mut sections = vec![MusicSection { name: None, line: music_token.line, start: music_token.start, end: contents.len(), tracks: Vec::new() }];
+
+    // A { right after a track opens that track's attributes.
+    let mut after_track = false;
+
+    loop
+    {
+        let token = tokenizer.expect_token( "} to close the music section" )?;
+
+        match token.kind
+        {
+            TokenKind::Text => {},
+            TokenKind::Close =>
+            {
+                sections[0].end = token.end;
+                break;
+            },
+            TokenKind::Open if after_track =>
+            {
+                read_track_attributes( &mut tokenizer, sections[0].tracks.last_mut().unwrap() )?;
+                after_track = false;
+                continue;
+            },
+            TokenKind::Open => return Err(TokenError::unexpected( &token, "a track or the name of a music area" )),
+        }
+
+        // A "file" is a track, unless it's the name of a section instead.
+        let next_token = tokenizer.expect_token( "a { or the path of a track" )?;
+
+        after_track = token.is_text("file") && next_token.kind != TokenKind::Open;
+        if after_track
+        {
+            sections[0].tracks.push( read_music_track( &token, &next_token )? );
+            continue;
+        }
+
+        if next_token.kind != TokenKind::Open
+        {
+            let mut expected_text = String::from("{ to open the ");
+            expected_text.push_str( &keyvalues::describe( &token ) );
+            expected_text.push_str(" section");
+
+            return Err(TokenError::unexpected( &next_token, &expected_text ));
+        }
+
+        let mut section = MusicSection { name: Some(String::from(token.text)), line: token.line, start: token.start, end: token.end, tracks: Vec::new() };
+        let mut after_track = false;
 
-        // Don't try to collect local sound files if we don't have a sound directory...which is very
-        // possible if the map uses entirely default music.
-        if local_sound_dir.is_dir() && local_sound_dir != gesource_sound_dir
+        loop
         {
-            dirs_to_scan.push(local_sound_dir);
+            let token = tokenizer.expect_token( "} to close the music area section" )?;
+
+            match token.kind
+            {
+                TokenKind::Close if section.tracks.is_empty() => return Err(TokenError::new( section.line, String::from("Music area section doesn't have any tracks in it!  Give it a track, or remove it.") )),
+                TokenKind::Close =>
+                {
+                    section.end = token.end;
+                    break;
+                },
+                TokenKind::Open if after_track =>
+                {
+                    read_track_attributes( &mut tokenizer, section.tracks.last_mut().unwrap() )?;
+                    after_track = false;
+                    continue;
+                },
+                TokenKind::Open => return Err(TokenError::new( token.line, String::from("Found { inside of a music area section!  Only the music section can have sections inside of it.") )),
+                TokenKind::Text if !token.is_text("file") => return Err(TokenError::unexpected( &token, "\"file\" or the } closing the music area section" )),
+                TokenKind::Text => {},
+            }
+
+            let path_token = tokenizer.expect_token( "the path of a track" )?;
+            section.tracks.push( read_music_track( &token, &path_token )? );
+            after_track = true;
         }
 
-        return shared::compute_or_get_safe_reference_to_directory_cache( dirs_to_scan, target_type, &[], &DIRLIST_INIT_STATE, &mut DIRLIST );
+        sections.push( section );
     }
+
+    tokenizer.expect_end( "music" )?;
+
+    Ok(sections)
+}
+
+/// Reads a track from the "file" token and the token after it, which has to be its path.
+fn read_music_track( file_token: &Token, path_token: &Token ) -> Result<MusicTrack, TokenError>
+{
+    if path_token.kind != TokenKind::Text
+    {
+        return Err(TokenError::unexpected( path_token, "the path of a track" ));
+    }
+
+    // GE:S needs something between the two to tell where one ends and the other starts.
+    if !path_token.spaced
+    {
+        return Err(TokenError::new( path_token.line, String::from("Expected whitespace between \"file\" and the path of its track!") ));
+    }
+
+    Ok(MusicTrack { path: path_token.text.replace("\\", "/").to_lowercase(), line: path_token.line, start: file_token.start, end: path_token.end, attributes: Vec::new() })
+}
+
+/// Reads the attributes of a track up to the } closing them, once the { opening them has been read.  Each attribute is
+/// a name followed by its value.
+fn read_track_attributes( tokenizer: &mut Tokenizer, track: &mut MusicTrack ) -> Result<(), TokenError>
+{
+    loop
+    {
+        let name_token = tokenizer.expect_token( "the name of a track attribute or the } closing them" )?;
+
+        match name_token.kind
+        {
+            TokenKind::Text => {},
+            TokenKind::Close =>
+            {
+                track.end = name_token.end;
+                return Ok(());
+            },
+            TokenKind::Open => return Err(TokenError::new( name_token.line, String::from("Found { inside of a track's attributes!  Attributes can't have sections inside of them.") )),
+        }
+
+        let value_token = tokenizer.expect_token( "the value of the track attribute" )?;
+        if value_token.kind != TokenKind::Text
+        {
+            return Err(TokenError::unexpected( &value_token, "the value of the track attribute" ));
+        }
+
+        if !value_token.spaced
+        {
+            return Err(TokenError::new( value_token.line, String::from("Expected whitespace between the name of the track attribute and its value!") ));
+        }
+
+        track.attributes.push( TrackAttribute { name: name_token.text.to_lowercase(), value: String::from(value_token.text), line: name_token.line } );
+    }
+}
+
+/// Makes sure every track attribute this program knows about has a value it can use, and warns about the ones it
+/// doesn't know so typos don't go unnoticed.
+fn check_track_attributes( args: &Arguments, music_script_path: &PathBuf, sections: &[MusicSection] ) -> Result<(), GesError>
+{
+    for track in sections.iter().flat_map(|x| x.tracks.iter())
+    {
+        let mut checked_names: HashSet<&str> = HashSet::new();
+
+        for attribute in &track.attributes
+        {
+            let quoted_name = shared::get_quotable_text( &attribute.name );
+
+            if !checked_names.insert( &attribute.name )
+            {
+                let mut error_text = String::new();
+                error_text.push_str("Track attribute ");
+                error_text.push_str(&quoted_name);
+                error_text.push_str(" is given more than once for ");
+                error_text.push_str( &shared::get_quotable_text( &track.path ) );
+                error_text.push_str("!  Only one of them can be used, so please remove the others.");
+
+                rules::report( args, "music-track-attribute", GesError::Parse { file: music_script_path.clone(), line: attribute.line, message: error_text } )?;
+                continue;
+            }
+
+            let (minimum, maximum) = match TRACK_ATTRIBUTES.iter().find(|x| x.0 == attribute.name)
+            {
+                Some(x) => (x.1, x.2),
+                None =>
+                {
+                    let mut error_text = String::new();
+                    error_text.push_str("Track attribute ");
+                    error_text.push_str(&quoted_name);
+                    error_text.push_str(" isn't one this program knows, so it can't be checked.  Known attributes are ");
+                    error_text.push_str( &TRACK_ATTRIBUTES.iter().map(|x| x.0).collect::<Vec<&str>>().join(", ") );
+                    error_text.push_str(".");
+
+                    rules::report( args, "music-unknown-attribute", GesError::Parse { file: music_script_path.clone(), line: attribute.line, message: error_text } )?;
+                    continue;
+                },
+            };
+
+            match attribute.value.trim().parse::<f64>()
+            {
+                Ok(x) if x >= minimum && x <= maximum => {},
+                _ =>
+                {
+                    let mut error_text = String::new();
+                    error_text.push_str("Track attribute ");
+                    error_text.push_str(&quoted_name);
+                    error_text.push_str(" has value ");
+                    error_text.push_str( &shared::get_quotable_text( &attribute.value ) );
+                    error_text.push_str( &format!( ", but it has to be a number from {} to {}.", minimum, maximum ) );
+
+                    rules::report( args, "music-track-attribute", GesError::Parse { file: music_script_path.clone(), line: attribute.line, message: error_text } )?;
+                },
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns every track that's already been listed earlier in the same section.
+fn find_duplicate_tracks( sections: &[MusicSection] ) -> Vec<(&MusicSection, &MusicTrack)>
+{
+    let mut duplicate_tracks = Vec::new();
+
+    for section in sections
+    {
+        // Sections can list thousands of tracks, so they're looked up by hash rather than one by one.
+        let mut checked_tracks: HashSet<&str> = HashSet::new();
+
+        for track in &section.tracks
+        {
+            if !checked_tracks.insert( &track.path )
+            {
+                duplicate_tracks.push( (section, track) );
+            }
+        }
+    }
+
+    duplicate_tracks
+}
+
+/// Returns the script contents with the given tracks taken out, along with any lines they leave blank.
+fn remove_tracks( contents: &str, tracks: &[&MusicTrack] ) -> String
+{
+    remove_spans( contents, tracks.iter().map(|x| (x.start, x.end)).collect() )
+}
+
+/// Returns the script contents with the given spans taken out, along with any lines they leave blank.
+fn remove_spans( contents: &str, removed_spans: Vec<(usize, usize)> ) -> String
+{
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+
+    for (mut start, mut end) in removed_spans
+    {
+        // If the span had its lines to itself, take the whole lines with it.
+        let line_start = match contents[..start].rfind('\n') { Some(x) => x + 1, None => 0 };
+        let line_end = match contents[end..].find('\n') { Some(x) => end + x + 1, None => contents.len() };
+
+        if contents[line_start..start].trim().is_empty() && contents[end..line_end].trim().is_empty()
+        {
+            start = line_start;
+            end = line_end;
+        }
+
+        spans.push( (start, end) );
+    }
+
+    spans.sort();
+
+    let mut fixed_contents = String::new();
+    let mut position = 0;
+
+    for (start, end) in spans
+    {
+        fixed_contents.push_str( &contents[position..start] );
+        position = end;
+    }
+
+    fixed_contents.push_str( &contents[position..] );
+    fixed_contents
+}
+
+/// Warns about music sections that GE:S will never play, that will get repetitive quickly when it does, or that play
+/// exactly what would have played anyway.
+fn check_music_sections( args: &Arguments, music_script_path: &PathBuf, sections: &[MusicSection] ) -> Result<(), GesError>
+{
+    let known_areas = get_known_music_areas( args, music_script_path );
+
+    let mut main_tracks: Vec<&str> = sections[0].tracks.iter().map(|x| x.path.as_str()).collect();
+    main_tracks.sort();
+    main_tracks.dedup();
+
+    // A match can go on for a while, and hearing the same one or two songs over and over gets old fast.
+    if main_tracks.len() < args.min_tracks
+    {
+        rules::report_in_file( args, "music-track-count", music_script_path, sections[0].line, GesError::PolicyViolation( format!( "The main music list of {} only has {} different tracks, so players will hear the same songs over and over in long matches.  \
+                                          Consider adding more until there are at least {}.", music_script_path.display(), main_tracks.len(), args.min_tracks ) ) )?;
+    }
+
+    for section in sections
+    {
+        let (name, quoted_name) = match section.name
+        {
+            Some(ref x) => (x, shared::get_quotable_text( x )),
+            None => continue,
+        };
+
+        if known_areas != None && !known_areas.as_ref().unwrap().contains( &name.to_lowercase() )
+        {
+            rules::report_in_file( args, "music-unknown-area", music_script_path, section.line, GesError::PolicyViolation( format!( "Music section {} on line {} of {} doesn't match any trigger in the map or name given with --music-areas.  \
+                                              GE:S will never play it unless it's a typo or the map is missing its trigger.", quoted_name, section.line, music_script_path.display() ) ) )?;
+        }
+
+        if section.tracks.len() < 2
+        {
+            rules::report_in_file( args, "music-single-track-section", music_script_path, section.line, GesError::PolicyViolation( format!( "Music section {} on line {} of {} only has one track, so the same song will play every time.  \
+                                              Consider giving it at least two.", quoted_name, section.line, music_script_path.display() ) ) )?;
+        }
+
+        let mut section_tracks: Vec<&str> = section.tracks.iter().map(|x| x.path.as_str()).collect();
+        section_tracks.sort();
+        section_tracks.dedup();
+
+        if section_tracks == main_tracks
+        {
+            rules::report_in_file( args, "music-redundant-section", music_script_path, section.line, GesError::PolicyViolation( format!( "Music section {} on line {} of {} has exactly the same tracks as the main music list, so it doesn't change anything.  \
+                                              Remove it, or give the area music of its own.", quoted_name, section.line, music_script_path.display() ) ) )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the lowercase names a music section can use to be played, which are the entity values in the script's map
+/// plus any given with --music-areas.  Returns None if we can't find the map and weren't given any names, since then
+/// we have nothing to go on.
+fn get_known_music_areas( args: &Arguments, music_script_path: &PathBuf ) -> Option<Vec<String>>
+{
+    let mut known_areas = args.music_areas.clone();
+    let mut found_map = false;
+
+    for map_path in get_music_script_map_paths( args, music_script_path )
+    {
+        match read_map_entity_values( &map_path )
+        {
+            Some(x) => { known_areas.extend(x); found_map = true; break; },
+            None => {},
+        }
+    }
+
+    if !found_map && known_areas.is_empty()
+    {
+        if args.verbose
+        {
+            println!( "Couldn't read a map for {}, so its music section names won't be checked.", music_script_path.display() );
+        }
+
+        return None;
+    }
+
+    Some(known_areas)
+}
+
+/// Returns where the map the music script at the given path is for would be, in the order they're looked for.
+fn get_music_script_map_paths( args: &Arguments, music_script_path: &PathBuf ) -> Vec<PathBuf>
+{
+    let map_name = match music_script_path.file_stem()
+    {
+        Some(x) => x.to_string_lossy().to_lowercase().replacen("level_music_", "", 1),
+        None => String::new(),
+    };
+
+    // Offline mode doesn't look at the GE:S install at all.
+    let root_dirs = if args.offline { vec![&args.rootdir] } else { vec![&args.rootdir, &args.gesdir] };
+
+    root_dirs.iter().map(|x|
+    {
+        let mut map_path = (*x).clone();
+        map_path.push("maps");
+        map_path.push(&map_name);
+        map_path.set_extension("bsp");
+        map_path
+    }).collect()
+}
+
+/// Returns every lowercase keyvalue in the given map, or None if the map can't be read.  Music areas can be named by
+/// more than one kind of entity, so rather than guess at which keys matter we take them all.
+fn read_map_entity_values( map_path: &Path ) -> Option<Vec<String>>
+{
+    if !map_path.is_file()
+    {
+        return None;
+    }
+
+    match bsp_reader::read_entity_lump( map_path )
+    {
+        Ok(x) => Some( bsp_reader::get_all_entity_values( &x ).iter().map(|x| x.to_lowercase()).collect() ),
+        Err(_) => None,
+    }
+}
+
+/// Returns the relative path of every file of the target type in the GE:S and local sound directories.  The directories
+/// are only walked once, until something in them changes.
+pub fn generate_mp3_directory_tree( gesource_sound_dir: &PathBuf, local_sound_dir: &PathBuf, target_type: &str ) -> Result<Arc<DirectoryTree>, GesError>
+{
+    let mut dirs_to_scan = vec![gesource_sound_dir];
+
+    // Don't try to collect local sound files if we don't have a sound directory...which is very
+    // possible if the map uses entirely default music.
+    if local_sound_dir.is_dir() && local_sound_dir != gesource_sound_dir
+    {
+        dirs_to_scan.push(local_sound_dir);
+    }
+
+    shared::get_cached_directory_tree( &dirs_to_scan, target_type, &[], shared::keep_all_files )
 }
 
 #[cfg(test)]
 mod tests 
 {
+    use std::fs;
     use shared::get_barebones_args;
     use shared::get_root_test_directory;
     use shared::do_validity_test;
     use shared::test_script_creator;
+    use regex::Regex;
     use super::*;
 
     #[test]
@@ -300,4 +1126,323 @@ mod tests
         // Now that we've confirmed the script checker works...let's create a file and use it to check it!
         test_script_creator( &get_barebones_args(), "level_music_test_map.txt", create_music_script_file, check_music_script_file );
     }
+
+    #[test]
+    fn test_regex_oracle()
+    {
+        // The regular expressions music scripts used to be checked with.  The tokenizer has to agree with them on which
+        // scripts can be read and which tracks they have, except that they could split one unquoted word into two.
+        lazy_static!
+        {
+            static ref FILE_RE: Regex = Regex::new(r#"(?x)^\s*(("music")|(music))\s*
+                                            (\{\s*
+                                            (
+                                            (\s*(("file")|(file))\s+(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s*)
+                                            |
+                                            (
+                                            (("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s*
+                                            \{\s*
+                                            (\s*(("file")|(file))\s+(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s*)+
+                                            \}\s*
+                                            )
+                                            )*
+                                            \})\s*$"#).unwrap();
+            static ref TRACK_RE: Regex = Regex::new(r#"\s*(("file")|(file))\s+(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s*"#).unwrap();
+
+            // An unquoted path right before a { is the only way the old expressions could split a word, reading the
+            // end of it as the name of a music area.
+            static ref SPLIT_RE: Regex = Regex::new(r#"(("file")|(file))\s+[^\s"\{\}]{2,}\s*\{"#).unwrap();
+        }
+
+        let mut scripts = Vec::new();
+
+        for directory in &["valid", "invalid"]
+        {
+            let mut music_script_dir = get_root_test_directory();
+            music_script_dir.push("music_script_tests");
+            music_script_dir.push(directory);
+
+            for entry in fs::read_dir(&music_script_dir).unwrap()
+            {
+                scripts.push( fs::read_to_string( entry.unwrap().path() ).unwrap() );
+            }
+        }
+
+        let tokens = ["music", "{", "file", "music/a.mp3", "\"area\"", "{", "\"file\"", "\"music/b c.mp3\"", "}", "file", "\"music/d.mp3\"", "}"];
+        let replacements = ["music", "\"music\"", "Music", "{", "}", "file", "\"file\"", "FILE", "a.mp3", "\"\"", "\"open"];
+
+        for seed in 0..5000
+        {
+            scripts.push( keyvalues::get_mutated_script( &tokens, &replacements, seed ) );
+        }
+
+        let mut readable_count = 0;
+
+        for script in &scripts
+        {
+            let contents = shared::blank_out_comments( script );
+            let sections = parse_music_sections( &contents );
+
+            // Track attributes came after the regexes, which read their { as the start of a music area.
+            if sections.as_ref().ok().is_some_and(|x| x.iter().flat_map(|y| y.tracks.iter()).any(|y| contents[y.start..y.end].ends_with('}')))
+            {
+                continue;
+            }
+
+            let split_word = sections.is_err() && SPLIT_RE.is_match(&contents);
+            assert!( sections.is_ok() == FILE_RE.is_match(&contents) || split_word, "{}", script );
+
+            match sections
+            {
+                Ok(x) =>
+                {
+                    let mut tracks: Vec<&MusicTrack> = x.iter().flat_map(|y| y.tracks.iter()).collect();
+                    tracks.sort_by_key(|y| y.start);
+
+                    let expected_paths: Vec<String> = TRACK_RE.captures_iter(&contents).map(|y| y[4].replace("\"", "").replace("\\", "/").to_lowercase()).collect();
+                    assert_eq!( tracks.iter().map(|y| y.path.clone()).collect::<Vec<String>>(), expected_paths, "{}", script );
+
+                    readable_count += 1;
+                },
+                Err(_) => {},
+            }
+        }
+
+        // Plenty of the mutated scripts should land on each side.
+        assert!( readable_count > 500 && scripts.len() - readable_count > 500 );
+    }
+
+    #[test]
+    fn test_parse_music_sections()
+    {
+        let mut music_script_path = get_root_test_directory();
+        music_script_path.push("music_script_tests");
+        music_script_path.push("valid");
+        music_script_path.push("level_music_test_nested3.txt");
+
+        let mut contents = String::new();
+        fs::File::open(&music_script_path).unwrap().read_to_string(&mut contents).unwrap();
+
+        let sections = parse_music_sections( &contents ).unwrap();
+        assert_eq!( sections.len(), 4 );
+
+        assert_eq!( sections[0].name, None );
+        assert_eq!( sections[0].tracks.len(), 4 );
+        assert_eq!( sections[0].tracks[2].path, "music/base_song3.mp3" );
+        assert_eq!( sections[0].tracks[2].line, 12 );
+
+        assert_eq!( sections[1].name, Some(String::from("sub_bracket1")) );
+        assert_eq!( sections[1].line, 6 );
+        assert_eq!( sections[1].tracks.len(), 2 );
+
+        assert_eq!( sections[2].name, Some(String::from("sub_bracket2")) );
+        assert_eq!( sections[2].tracks.len(), 1 );
+    }
+
+    #[test]
+    fn test_fix_duplicate_tracks()
+    {
+        let mut music_script_path = get_root_test_directory();
+        music_script_path.push("temp");
+        fs::create_dir_all(&music_script_path).unwrap();
+        music_script_path.push("level_music_test_duplicates.txt");
+
+        let contents = "\"music\"\r\n{\r\n\t\"file\"\t\"music/base_song1.mp3\"\r\n\t\"file\"\t\"music/base_song2.mp3\"\r\n\t\"file\"\t\"Music/Base_Song1.mp3\"\r\n}\r\n";
+        fs::File::create(&music_script_path).unwrap().write_all(contents.as_bytes()).unwrap();
+
+        let mut args = get_barebones_args();
+        assert!( check_music_script_file( &args, &music_script_path ).is_err() );
+
+        args.fix = true;
+        assert!( check_music_script_file( &args, &music_script_path ).is_ok() );
+
+        let mut fixed_contents = String::new();
+        fs::File::open(&music_script_path).unwrap().read_to_string(&mut fixed_contents).unwrap();
+        assert_eq!( fixed_contents, "\"music\"\r\n{\r\n\t\"file\"\t\"music/base_song1.mp3\"\r\n\t\"file\"\t\"music/base_song2.mp3\"\r\n}\r\n" );
+
+        // Once it's fixed there's nothing left to complain about.
+        args.fix = false;
+        assert!( check_music_script_file( &args, &music_script_path ).is_ok() );
+
+        fs::remove_file(&music_script_path).unwrap();
+    }
+
+    #[test]
+    fn test_track_attributes()
+    {
+        let contents = "\"music\"\r\n{\r\n\t\"file\"\t\"music/base_song1.mp3\"\r\n\t{\r\n\t\t\"Volume\"\t\"0.5\"\r\n\t}\r\n\tarea\r\n\t{\r\n\t\tfile music/base_song2.mp3 { fade 2 }\r\n\t}\r\n}\r\n";
+
+        let sections = parse_music_sections( contents ).unwrap();
+        assert_eq!( sections[0].tracks[0].attributes.len(), 1 );
+        assert_eq!( (sections[0].tracks[0].attributes[0].name.as_str(), sections[0].tracks[0].attributes[0].value.as_str(), sections[0].tracks[0].attributes[0].line), ("volume", "0.5", 5) );
+        assert!( contents[sections[0].tracks[0].start..sections[0].tracks[0].end].ends_with('}') );
+        assert_eq!( sections[1].tracks[0].attributes[0].name, "fade" );
+
+        // Attributes only go right after a track.
+        assert!( parse_music_sections( "music { { volume 1 } file music/base_song1.mp3 }" ).is_err() );
+        assert!( parse_music_sections( "music { area { file music/base_song1.mp3 { volume } } }" ).is_err() );
+
+        // Fixing a duplicate takes its attributes with it, and leaves the rest alone.
+        let mut music_script_path = get_root_test_directory();
+        music_script_path.push("temp");
+        fs::create_dir_all(&music_script_path).unwrap();
+        music_script_path.push("level_music_test_attribute_duplicates.txt");
+
+        let contents = "\"music\"\r\n{\r\n\t\"file\"\t\"music/base_song1.mp3\" { \"volume\" \"0.5\" }\r\n\t\"file\"\t\"music/base_song2.mp3\"\r\n\t\"file\"\t\"music/base_song1.mp3\"\r\n\t{\r\n\t\t\"fade\"\t\"1\"\r\n\t}\r\n}\r\n";
+        fs::File::create(&music_script_path).unwrap().write_all(contents.as_bytes()).unwrap();
+
+        let mut args = get_barebones_args();
+        args.fix = true;
+        assert!( check_music_script_file( &args, &music_script_path ).is_ok() );
+
+        let mut fixed_contents = String::new();
+        fs::File::open(&music_script_path).unwrap().read_to_string(&mut fixed_contents).unwrap();
+        assert_eq!( fixed_contents, "\"music\"\r\n{\r\n\t\"file\"\t\"music/base_song1.mp3\" { \"volume\" \"0.5\" }\r\n\t\"file\"\t\"music/base_song2.mp3\"\r\n}\r\n" );
+
+        fs::remove_file(&music_script_path).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_format()
+    {
+        let mut music_script_path = get_root_test_directory();
+        music_script_path.push("temp");
+        fs::create_dir_all(&music_script_path).unwrap();
+        music_script_path.push("level_music_test_legacy.txt");
+
+        // Written long before duplicates were frowned upon.
+        let contents = "\"music\"\r\n{\r\n\t\"file\"\t\"music/base_song1.mp3\"\r\n\t\"file\"\t\"music/base_song1.mp3\"\r\n}\r\n";
+        fs::File::create(&music_script_path).unwrap().write_all(contents.as_bytes()).unwrap();
+
+        let mut args = get_barebones_args();
+        assert!( check_music_script_file( &args, &music_script_path ).is_err() );
+
+        args.fullcheck = true;
+        args.legacy_format = true;
+        assert!( check_music_script_file( &args, &music_script_path ).is_ok() );
+
+        // A rules file still has the final say.
+        args.rule_overrides.push( (String::from("music-duplicate-track"), rules::Severity::Error) );
+        assert!( check_music_script_file( &args, &music_script_path ).is_err() );
+        args.rule_overrides.clear();
+
+        // Anything this program generated is from after the legacy era, so it's held to the usual rules.
+        fs::File::create(&music_script_path).unwrap().write_all(generation_metadata::stamp( &args, contents ).unwrap().as_bytes()).unwrap();
+        assert!( check_music_script_file( &args, &music_script_path ).is_err() );
+
+        fs::remove_file(&music_script_path).unwrap();
+    }
+
+    #[test]
+    fn test_copy_music_script()
+    {
+        let mut rootdir = get_root_test_directory();
+        rootdir.push("temp");
+        rootdir.push("copy_music");
+        rootdir.push("gesource");
+
+        let _ = fs::remove_dir_all(&rootdir);
+        fs::create_dir_all( rootdir.join("scripts").join("music") ).unwrap();
+
+        let mut args = get_barebones_args();
+        args.rootdir = rootdir.clone();
+        args.music_areas = vec![String::from("bunker")];
+
+        let source_contents = "\"music\"\r\n{\r\n\t\"file\"\t\"music/base_song1.mp3\" { volume 0.5 } // Quieter.\r\n\t\"file\"\t\"music/base_song2.mp3\"\r\n\
+                               \t\"file\"\t\"music/base_song3.mp3\"\r\n\t\"file\"\t\"music/base_song4.mp3\"\r\n\
+                               \r\n\t\"bunker\"\r\n\t{\r\n\t\t\"file\"\t\"music/base_song1.mp3\"\r\n\t\t\"file\"\t\"music/base_song2.mp3\"\r\n\t}\r\n\
+                               \r\n\t\"tower\"\r\n\t{\r\n\t\t\"file\"\t\"music/base_song3.mp3\"\r\n\t\t\"file\"\t\"music/base_song4.mp3\"\r\n\t}\r\n}\r\n";
+        fs::write( get_music_script_path( &rootdir, "ge_source" ), generation_metadata::stamp( &args, source_contents ).unwrap() ).unwrap();
+
+        let music_script_path = copy_music_script( &args, "ge_copy", "ge_source" ).unwrap();
+        assert_eq!( music_script_path, get_music_script_path( &rootdir, "ge_copy" ) );
+
+        // The copy keeps its attributes and comments, but not the section for an area the map doesn't have.
+        let contents = fs::read_to_string(&music_script_path).unwrap();
+        let (_, contents) = generation_metadata::read_metadata( &contents ).unwrap();
+        assert!( contents.starts_with("// Music copied from ge_source.\r\n") );
+        assert!( contents.contains("{ volume 0.5 } // Quieter.") && contents.contains("\"bunker\"") && !contents.contains("tower") );
+        assert!( contents.ends_with("\t}\r\n}\r\n") );
+        assert!( check_music_script_file( &args, &music_script_path ).is_ok() );
+
+        // An existing music script is never replaced.
+        assert!( copy_music_script( &args, "ge_copy", "ge_source" ).is_err() );
+        assert!( copy_music_script( &args, "ge_other", "ge_nonexistent" ).is_err() );
+
+        // Neither is one made for a soundtrack the install doesn't have.
+        fs::write( get_music_script_path( &rootdir, "ge_broken" ), source_contents.replace("base_song4", "missing_song") ).unwrap();
+        assert!( copy_music_script( &args, "ge_other", "ge_broken" ).is_err() );
+        assert!( !get_music_script_path( &rootdir, "ge_other" ).exists() );
+
+        fs::remove_dir_all( rootdir.parent().unwrap() ).unwrap();
+    }
+
+    #[test]
+    fn test_sample_stock_music()
+    {
+        let tracks: Vec<String> = (0..20).map(|x| format!( "music/track{}.mp3", x )).collect();
+
+        let sample = sample_tracks( tracks.clone(), 6, 42 );
+        assert_eq!( sample, sample_tracks( tracks.clone(), 6, 42 ) );
+        assert_ne!( sample, sample_tracks( tracks.clone(), 6, 43 ) );
+
+        let mut unique_sample = sample.clone();
+        unique_sample.sort();
+        unique_sample.dedup();
+        assert_eq!( unique_sample.len(), 6 );
+
+        // Asking for more than there are just shuffles all of them.
+        assert_eq!( sample_tracks( tracks.clone(), 50, 42 ).len(), 20 );
+
+        // A release without music of its own gets its sample from the install's music.
+        let mut rootdir = get_root_test_directory();
+        rootdir.push("temp");
+        rootdir.push("sample_music");
+        rootdir.push("gesource");
+
+        let _ = fs::remove_dir_all(&rootdir);
+        fs::create_dir_all(&rootdir).unwrap();
+
+        let mut args = get_barebones_args();
+        args.rootdir = rootdir.clone();
+        args.sample_music = 3;
+        args.music_seed = Some(7);
+
+        let music_script_path = rootdir.join("level_music_test_sample.txt");
+        create_music_script_file( &args, &music_script_path ).unwrap();
+
+        let contents = fs::read_to_string(&music_script_path).unwrap();
+        assert_eq!( contents.matches("\"music/base_song").count(), 3 );
+        assert!( !contents.contains("classy.mp3") );
+
+        fs::remove_dir_all( rootdir.parent().unwrap() ).unwrap();
+    }
+
+    #[test]
+    fn test_offline_music_paths()
+    {
+        let mut music_script_path = get_root_test_directory();
+        music_script_path.push("temp");
+        fs::create_dir_all(&music_script_path).unwrap();
+        music_script_path.push("level_music_test_offline.txt");
+
+        // Only the database knows about this track, since it isn't in the test GE:S install.
+        let hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+        stock_content::set_added_stock_files( vec![stock_content::StockFile { path: String::from("sound/music/offline_stock_song.mp3"), hash: String::from(hash) }] );
+
+        let mut args = get_barebones_args();
+        args.offline = true;
+
+        fs::File::create(&music_script_path).unwrap().write_all(b"\"music\"\r\n{\r\n\t\"file\"\t\"music/offline_stock_song.mp3\"\r\n}\r\n").unwrap();
+        assert!( check_music_script_file( &args, &music_script_path ).is_ok() );
+
+        // The install's music isn't looked at in offline mode.
+        fs::File::create(&music_script_path).unwrap().write_all(b"\"music\"\r\n{\r\n\t\"file\"\t\"music/base_song1.mp3\"\r\n}\r\n").unwrap();
+        assert!( check_music_script_file( &args, &music_script_path ).is_err() );
+
+        args.offline = false;
+        assert!( check_music_script_file( &args, &music_script_path ).is_ok() );
+
+        fs::remove_file(&music_script_path).unwrap();
+    }
 }
\ No newline at end of file
@@ -0,0 +1,283 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// pipeline: Runs the sections of a release or a fullcheck and reports how they went, leaving how to exit to main.
+// ------------------------------------------------------------------------------------------------------------
+
+use argument_handler::Arguments;
+use error::GesError;
+use task_graph::TaskGraph;
+use shared;
+use map_script_builder;
+use music_script_builder;
+use reslist_builder;
+use folder_compressor;
+use cloud_files;
+use texture_scanner;
+use audio_scanner;
+use consistency;
+use localization;
+use python_content;
+use cfg_content;
+use server_package;
+use map_origin;
+use smoke_test;
+use playtest;
+use file_categories;
+use file_writer;
+use release_lock;
+
+/// How a run of the program went.  Only main acts on it, so everything the run used has been cleaned up by the time
+/// the program exits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunReport
+{
+    /// The combined error code of every section that failed, or 0 if none did.  Commands without sections use 0x0016 if
+    /// they failed, and 0x0080 if they worked but found something, the same code a run with only warnings exits with.
+    pub error_code: i32,
+    /// How many warnings were printed by the end of the run.
+    pub warning_count: usize,
+    /// False when there's nobody at a console to answer a prompt, like in batch mode.
+    pub interactive: bool,
+}
+
+impl RunReport
+{
+    /// Returns a report for a run that ended with the given error code, counting every warning printed so far.
+    pub fn new( error_code: i32 ) -> RunReport
+    {
+        RunReport { error_code, warning_count: shared::get_warning_count(), interactive: true }
+    }
+
+    /// Returns the code the program should exit with.  A run that would otherwise succeed but printed warnings exits
+    /// with 0x0080 instead, so automated tools can tell the two apart.  Failure codes are added together, so this has to
+    /// be larger than all of them combined, or a run with several failed sections could exit looking like it only warned.
+    /// It also has to fit in a byte, since that's all of the exit code a Unix shell gets to see.
+    pub fn get_exit_code( &self ) -> i32
+    {
+        if self.error_code == 0x0000 && self.warning_count > 0 { 0x0080 } else { self.error_code }
+    }
+}
+
+/// Runs every release section for a single map, returning the combined error code of every section that failed.
+/// Nothing in here exits, so it can be run more than once if a release ever holds more than one map.
+pub fn run_map_pipeline( args: Arguments, map_name: String ) -> RunReport
+{
+    // If we made it here, we can assume we can read our target directory and the required files
+    // and directory structure are in place.  Time to start making our script files!  First let the user know.
+    if args.verbose
+    {
+        println!( "Preparing to write script files for {}!", map_name );
+        println!( "Verifying all script files in {}!", args.gesdir.display() );
+    }
+
+    // A second copy of the program started on the same release would write over this one's scripts as it went.
+    let _root_lock = match file_writer::lock_directory( &args.rootdir )
+    {
+        Ok(x) => x,
+        Err(e) => { shared::print_error( &format!( "Failed to start with error:\n{}\n", e ) ); return RunReport::new( 0x0001 ); },
+    };
+
+    // Files that aren't really on this machine slow down everything after this, so say so up front.
+    match cloud_files::warn_about_cloud_files( &args, &map_name )
+    {
+        Ok(_) => {},
+        Err(e) => shared::print_warning( &format!( "Failed cloud file scan with error:\n{}\n", e ) ),
+    }
+
+    // Each section runs on its own thread as soon as the sections it needs are done.  Any panics are turned into errors
+    // so they can be reported along with the section and file they happened in.
+    let mut graph = TaskGraph::new();
+
+    let (task_args, task_map_name) = (args.clone(), map_name.clone());
+    graph.add_task( "map script section", 0x0002, &[], move || map_script_builder::create_or_verify_map_script_file( &task_args, &task_map_name ) );
+
+    // Decompiled and copied maps break GE:S community policy, so they fail the map section like a broken map script would.
+    let (task_args, task_map_name) = (args.clone(), map_name.clone());
+    graph.add_task( "map origin check", 0x0002, &[], move || map_origin::check_map_origin( &task_args, &task_map_name ) );
+
+    // Final releases are what servers keep, so leftovers from a playtest shouldn't make it into one.  The map script
+    // has to be stamped first, if this run stamps it at all.
+    let (task_args, task_map_name) = (args.clone(), map_name.clone());
+    graph.add_task( "final release check", 0x0002, &["map script section"], move || playtest::check_final_release( &task_args, &task_map_name ) );
+
+    let (task_args, task_map_name) = (args.clone(), map_name.clone());
+    graph.add_task( "music list section", 0x0004, &[], move || music_script_builder::create_or_verify_music_script_file( &task_args, &task_map_name ) );
+
+    // If we start making our reslist before the other files have a chance to be made, we could fail to include them in it!
+    let (task_args, task_map_name) = (args.clone(), map_name.clone());
+    graph.add_task( "reslist section", 0x0008, &["map script section", "music list section"],
+                    move || reslist_builder::create_or_verify_reslist( &task_args, &task_map_name ) );
+
+    // Once every script is valid on its own, make sure they agree with each other.  A failure here is a reslist
+    // problem, so it shares the reslist's error code.
+    let (task_args, task_map_name) = (args.clone(), map_name.clone());
+    graph.add_dependent_task( "script consistency section", 0x0008, &["reslist section"],
+                              move || consistency::check_script_consistency( &task_args, &task_map_name ) );
+
+    // Translations and captions reach clients through the reslist, so a broken one fails it just like a broken script.
+    let task_args = args.clone();
+    graph.add_task( "localization section", 0x0008, &[], move || localization::check_localization_files( &task_args ) );
+
+    // Custom gamemodes are what the map script's gamemode weights pick from, so one GE:S won't load fails the map section.
+    let task_args = args.clone();
+    graph.add_task( "python section", 0x0002, &[], move || python_content::check_python_content( &task_args ) );
+
+    // Server owners exec a map's configs as they are, so one that changes server security fails the map section too.
+    let task_args = args.clone();
+    graph.add_task( "cfg section", 0x0002, &[], move || cfg_content::check_cfg_files( &task_args ) );
+
+    // Texture and music usage are informational by default, so only a rule that's been made an error can fail the release.
+    // Textures reach clients through the reslist, so they share its error code, and music shares the music section's.
+    let task_args = args.clone();
+    graph.add_task( "texture scan", 0x0008, &["script consistency section"], move || demote_scan_errors( "texture scan", texture_scanner::report_texture_usage( &task_args ) ) );
+
+    let task_args = args.clone();
+    graph.add_task( "music scan", 0x0004, &["script consistency section"], move || demote_scan_errors( "music scan", audio_scanner::report_music_usage( &task_args ) ) );
+
+    // Loading the map on a real server is the last word on whether it works, but only worth the wait once every other
+    // check is happy with it.  The server failing to load the map fails the map section.
+    if args.smoke_test != None
+    {
+        let (task_args, task_map_name) = (args.clone(), map_name.clone());
+        graph.add_dependent_task( "smoke test", 0x0002, &["map script section", "map origin check", "texture scan", "music scan"],
+                                  move || smoke_test::run_smoke_test( &task_args, &task_map_name ) );
+    }
+
+    // We don't -always- want to build the compressed folder, as it's not ideal for map release.
+    // However, for server owners downloading the map it's quite useful so we provide the option.
+    if args.compress
+    {
+        let (task_args, task_map_name) = (args.clone(), map_name.clone());
+        graph.add_task( "compression", 0x0016, &["texture scan", "music scan"], move || folder_compressor::construct_compressed_filesystem( &task_args, &task_map_name ) );
+    }
+
+    // Servers need the server only files too, so they get a package of their own with everything in it.
+    if args.server_package != None
+    {
+        let (task_args, task_map_name) = (args.clone(), map_name.clone());
+        graph.add_task( "server package", 0x0016, &["texture scan", "music scan", "python section", "cfg section"],
+                        move || server_package::construct_server_package( &task_args, &task_map_name ).map(|_| ()) );
+    }
+
+    let error_code = graph.run();
+
+    // Only worth looking at once the reslist is settled, since that's what decides what's in the download.
+    if graph.task_succeeded("reslist section")
+    {
+        match reslist_builder::generate_directory_tree( &args )
+        {
+            Ok(x) =>
+            {
+                let mut relative_paths = vec![format!( "maps/{}.bsp", map_name )];
+                relative_paths.extend( x.1.iter().cloned() );
+
+                file_categories::print_size_report( &args.rootdir, &relative_paths );
+            },
+            Err(e) => shared::print_warning( &format!( "Failed size report with error:\n{}\n", e ) ),
+        }
+    }
+
+    // Only a release that came out right is worth making again the same way.
+    if error_code == 0 && !args.check_only
+    {
+        match release_lock::write_release_lock( &args )
+        {
+            Ok(_) => {},
+            Err(e) => shared::print_warning( &format!( "Failed to write the release lock with error:\n{}\n", e ) ),
+        }
+    }
+
+    RunReport::new( error_code )
+}
+
+/// Scans only fail a section when a rule they report under has been made an error, anything else that goes wrong
+/// with them is just a warning.
+fn demote_scan_errors( scan_name: &str, result: Result<(), GesError> ) -> Result<(), GesError>
+{
+    match result
+    {
+        Err(GesError::PolicyViolation(e)) => Err(GesError::PolicyViolation(e)),
+        Err(e) => { shared::print_warning( &format!( "Failed {} with error:\n{}\n", scan_name, e ) ); Ok(()) },
+        Ok(_) => Ok(()),
+    }
+}
+
+/// Checks every script file in the GE:S install, returning the combined error code of each section.
+pub fn run_fullcheck( args: &Arguments ) -> RunReport
+{
+    if args.verbose
+    {
+        println!( "Verifying all script files in {}!", args.gesdir.display() );
+    }
+
+    // Fixes are written straight into the install, which a scheduled run and one started by hand could both be doing.
+    let _install_lock = match args.fix
+    {
+        true => match file_writer::lock_directory( &args.gesdir )
+        {
+            Ok(x) => Some(x),
+            Err(e) => { shared::print_error( &format!( "Failed to start with error:\n{}\n", e ) ); return RunReport::new( 0x0001 ); },
+        },
+        false => None,
+    };
+
+    // Every script type is checked independently of the others, so they can all run at once.
+    let mut graph = TaskGraph::new();
+
+    let task_args = args.clone();
+    graph.add_task( "map script section", 0x0002, &[], move || map_script_builder::fullcheck_map_script_files( &task_args ) );
+
+    let task_args = args.clone();
+    graph.add_task( "music script section", 0x0004, &[], move || music_script_builder::fullcheck_music_script_files( &task_args ) );
+
+    let task_args = args.clone();
+    graph.add_task( "reslist section", 0x0008, &[], move || reslist_builder::fullcheck_reslist_files( &task_args ) );
+
+    RunReport::new( graph.run() )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_run_report_exit_code()
+    {
+        let report = RunReport { error_code: 0x0000, warning_count: 0, interactive: true };
+        assert_eq!( report.get_exit_code(), 0x0000 );
+
+        let report = RunReport { error_code: 0x0000, warning_count: 2, interactive: true };
+        assert_eq!( report.get_exit_code(), 0x0080 );
+
+        let report = RunReport { error_code: 0x0008, warning_count: 2, interactive: true };
+        assert_eq!( report.get_exit_code(), 0x0008 );
+
+        // Map, reslist, and compression failing together used to add up to the warnings code.
+        let warned_report = RunReport { error_code: 0x0000, warning_count: 1, interactive: true };
+        let failed_report = RunReport { error_code: 0x0002 + 0x0008 + 0x0016, warning_count: 1, interactive: true };
+        assert_ne!( failed_report.get_exit_code(), warned_report.get_exit_code() );
+
+        let every_failure = RunReport { error_code: 0x0001 + 0x0002 + 0x0004 + 0x0008 + 0x0016 + 0x0040, warning_count: 1, interactive: true };
+        assert!( every_failure.get_exit_code() < warned_report.get_exit_code() );
+    }
+
+    #[test]
+    fn test_run_fullcheck()
+    {
+        // The test install has maps but no script directories, so only the script sections fail, and the run still comes
+        // back to say so.
+        let mut args = get_barebones_args();
+        args.fullcheck = true;
+        args.gesdir = get_root_test_directory();
+        args.gesdir.push("gesdir");
+        args.gesdir.push("gesource");
+
+        assert_eq!( run_fullcheck( &args ).error_code, 0x0002 | 0x0004 );
+    }
+}
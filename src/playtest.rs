@@ -0,0 +1,305 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// playtest: Marks beta releases as playtests that expire, and keeps beta leftovers out of final releases.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use argument_handler::Arguments;
+use file_writer;
+use generation_metadata;
+use generation_metadata::EditState;
+use rules;
+use scan_service;
+use shared;
+use error::GesError;
+
+// Map scripts of playtest releases carry a comment line beginning with this, followed by when the playtest expires and
+// what's being tested.
+static PLAYTEST_PREFIX: &'static str = "// ges-playtest";
+
+// Files with this in their path are left over from a beta release.
+static BETA_MARKER: &'static str = "_beta";
+
+// Releases with this in their name are meant to be the last of the map.
+static FINAL_MARKER: &'static str = "final";
+
+/// What a playtest map script says about the playtest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaytestStamp
+{
+    /// The last day the playtest runs, as YYYY-MM-DD.
+    pub expires: Option<String>,
+    /// What the playtest is for.
+    pub notes: Option<String>,
+}
+
+/// Returns true if the given text is a date in the form YYYY-MM-DD.
+pub fn is_valid_date( date: &str ) -> bool
+{
+    let parts: Vec<&str> = date.split('-').collect();
+
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 || !parts.iter().all(|x| x.chars().all(|y| y.is_ascii_digit()))
+    {
+        return false;
+    }
+
+    let (month, day) = (parts[1].parse::<u32>().unwrap(), parts[2].parse::<u32>().unwrap());
+
+    (1..=12).contains(&month) && (1..=31).contains(&day)
+}
+
+/// Returns the playtest line this run should stamp map scripts with, if --playtest-expires or --playtest-notes was given.
+pub fn get_stamp_line( args: &Arguments ) -> Option<String>
+{
+    if args.playtest_expires == None && args.playtest_notes == None
+    {
+        return None;
+    }
+
+    let mut stamp_line = String::from(PLAYTEST_PREFIX);
+
+    match args.playtest_expires
+    {
+        Some(ref x) => { stamp_line.push_str(" expires="); stamp_line.push_str(x); },
+        None => {},
+    }
+
+    // The notes go last since they're the only field that can have spaces in it.
+    match args.playtest_notes
+    {
+        Some(ref x) => { stamp_line.push_str(" notes="); stamp_line.push_str( &x.replace("\r", " ").replace("\n", " ") ); },
+        None => {},
+    }
+
+    Some(stamp_line)
+}
+
+/// Reads the playtest line of a map script, if it has one, returning it along with the line it's on.
+pub fn read_stamp( contents: &str ) -> Option<(PlaytestStamp, usize)>
+{
+    for (line_index, line) in contents.lines().enumerate()
+    {
+        let fields = match line.trim().strip_prefix(PLAYTEST_PREFIX)
+        {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let (fields, notes) = match fields.find(" notes=")
+        {
+            Some(x) => (&fields[..x], Some(String::from( fields[x + " notes=".len()..].trim() ))),
+            None => (fields, None),
+        };
+
+        let expires = fields.split_whitespace().find_map(|x| x.strip_prefix("expires=")).map(String::from);
+
+        return Some((PlaytestStamp { expires, notes }, line_index + 1));
+    }
+
+    None
+}
+
+/// Returns the given map script contents with the given playtest line in place of any they had, right after the
+/// generation metadata line if there is one.
+pub fn replace_stamp( contents: &str, stamp_line: &str ) -> String
+{
+    let (metadata_line, rest) = match generation_metadata::read_metadata( contents )
+    {
+        Some((_, x)) => (&contents[..contents.len() - x.len()], x),
+        None => ("", contents),
+    };
+
+    let mut stamped_contents = String::from(metadata_line);
+    stamped_contents.push_str(stamp_line);
+    stamped_contents.push_str("\r\n");
+
+    for line in rest.split_inclusive('\n').filter(|x| !x.trim().starts_with(PLAYTEST_PREFIX))
+    {
+        stamped_contents.push_str(line);
+    }
+
+    stamped_contents
+}
+
+/// Stamps an existing map script with this run's playtest line, unless it already has the same one.
+pub fn stamp_map_script( args: &Arguments, map_script_path: &PathBuf ) -> Result<(), GesError>
+{
+    let stamp_line = match get_stamp_line( args )
+    {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+
+    let contents = fs::read_to_string(map_script_path)?;
+
+    if contents.lines().any(|x| x.trim() == stamp_line)
+    {
+        return Ok(());
+    }
+
+    // A script that was still as we generated it stays recognized as ours, but a hand edited one keeps its old stamp
+    // so the edits aren't passed off as generated.
+    match generation_metadata::get_edit_state( &contents )?
+    {
+        EditState::Unedited(_) => file_writer::write( map_script_path, generation_metadata::restamp( args, &replace_stamp( &contents, &stamp_line ) )?.as_bytes() )?,
+        _ => file_writer::write( map_script_path, replace_stamp( &contents, &stamp_line ).as_bytes() )?,
+    }
+
+    println!( "Stamped {} as a playtest!", map_script_path.display() );
+
+    Ok(())
+}
+
+/// Reports the map script if it's stamped as a playtest that's already expired, since servers shouldn't still be
+/// running it.
+pub fn check_playtest_expiry( args: &Arguments, map_script_path: &PathBuf, contents: &str ) -> Result<(), GesError>
+{
+    let (stamp, line) = match read_stamp( contents )
+    {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+
+    let today = generation_metadata::format_utc_time( SystemTime::now() );
+
+    match stamp.expires
+    {
+        // Dates in this form sort the same way as text.
+        Some(ref x) if x.as_str() < &today[..10] =>
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Map script is stamped as a playtest that expired on ");
+            error_text.push_str(x);
+            error_text.push_str("!  Replace it with a newer release, or restamp it with a later --playtest-expires if the playtest is still running.");
+
+            rules::report( args, "map-script-playtest-expired", GesError::Parse { file: map_script_path.clone(), line, message: error_text } )
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Reports a release named as final that still has beta assets in it or a map script stamped as a playtest, since a
+/// final release is the one servers keep forever.
+pub fn check_final_release( args: &Arguments, map_name: &str ) -> Result<(), GesError>
+{
+    if !is_final_release( args, map_name )
+    {
+        return Ok(());
+    }
+
+    let beta_paths: Vec<String> = scan_service::get_scan_service().get_files( &args.rootdir, "", &[], shared::keep_all_files )?.1
+                                                                  .into_iter()
+                                                                  .filter(|x| x.to_lowercase().contains(BETA_MARKER))
+                                                                  .collect();
+
+    shared::report_cleanup_list( args, "release-final-beta", "files in this final release are still beta assets", &beta_paths )?;
+
+    let mut map_script_path = args.rootdir.clone();
+    map_script_path.push("scripts");
+    map_script_path.push("maps");
+    map_script_path.push(map_name);
+    map_script_path.set_extension("txt");
+
+    let is_stamped = match fs::read_to_string(&map_script_path)
+    {
+        Ok(x) => read_stamp( &x ) != None,
+        Err(_) => false,
+    };
+
+    if is_stamped || get_stamp_line( args ) != None
+    {
+        rules::report_in_file( args, "release-final-beta", &map_script_path, 0,
+                               GesError::PolicyViolation( String::from("The map script of this final release is stamped as a playtest!  Remove the ges-playtest line, and don't pass --playtest-expires or --playtest-notes for final releases.") ) )?;
+    }
+
+    Ok(())
+}
+
+/// Returns true if the map or the folder its release is in is named as a final release.  The root directory of a
+/// release is usually just called gesource, so the folder holding it counts too.
+fn is_final_release( args: &Arguments, map_name: &str ) -> bool
+{
+    let mut release_names = vec![String::from(map_name)];
+
+    // The root directory is usually given relative to the release folder, which wouldn't have a name to check.
+    let rootdir = fs::canonicalize(&args.rootdir).unwrap_or( args.rootdir.clone() );
+
+    for directory in rootdir.ancestors().take(2)
+    {
+        match directory.file_name()
+        {
+            Some(x) => release_names.push( x.to_string_lossy().into_owned() ),
+            None => {},
+        }
+    }
+
+    release_names.iter().any(|x| x.to_lowercase().contains(FINAL_MARKER))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use std::io::prelude::*;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_playtest_stamps()
+    {
+        let mut args = get_barebones_args();
+        assert_eq!( get_stamp_line( &args ), None );
+
+        args.playtest_expires = Some(String::from("2018-06-01"));
+        args.playtest_notes = Some(String::from("New spawn layout"));
+
+        let stamp_line = get_stamp_line( &args ).unwrap();
+        assert_eq!( stamp_line, "// ges-playtest expires=2018-06-01 notes=New spawn layout" );
+
+        // The stamp goes right under the metadata line, and replaces any stamp that was there before.
+        let contents = generation_metadata::stamp( &args, "// ges-playtest expires=2018-05-01\r\nBaseWeight\t500\r\n" ).unwrap();
+        let stamped_contents = replace_stamp( &contents, &stamp_line );
+
+        assert_eq!( generation_metadata::read_metadata( &stamped_contents ).unwrap().1, format!( "{}\r\nBaseWeight\t500\r\n", stamp_line ) );
+        assert_eq!( read_stamp( &stamped_contents ), Some((PlaytestStamp { expires: Some(String::from("2018-06-01")), notes: Some(String::from("New spawn layout")) }, 2)) );
+
+        assert!( is_valid_date( "2018-06-01" ) );
+        assert!( !is_valid_date( "2018-13-01" ) );
+        assert!( !is_valid_date( "06/01/2018" ) );
+    }
+
+    #[test]
+    fn test_check_final_release()
+    {
+        let mut args = get_barebones_args();
+        args.rootdir = get_root_test_directory();
+        args.rootdir.push("temp");
+        args.rootdir.push("playtest_ge_test_final");
+        args.rootdir.push("gesource");
+
+        let _ = fs::remove_dir_all( args.rootdir.parent().unwrap() );
+
+        for file_path in &["maps/ge_test.bsp", "materials/ge_test/wall_beta.vtf"]
+        {
+            let file_path = args.rootdir.join(file_path);
+            fs::create_dir_all( file_path.parent().unwrap() ).unwrap();
+            fs::File::create(&file_path).unwrap().write_all(b"test").unwrap();
+        }
+
+        assert!( is_final_release( &args, "ge_test" ) );
+
+        // Beta assets in a final release are a warning by default, which doesn't fail anything.
+        let finding_count = rules::get_findings().len();
+        check_final_release( &args, "ge_test" ).unwrap();
+        assert!( rules::get_findings()[finding_count..].iter().any(|x| x.rule_id == "release-final-beta" && x.message.contains("materials/ge_test/wall_beta.vtf")) );
+
+        fs::remove_dir_all( args.rootdir.parent().unwrap() ).unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ---------------------------------------------------------------------------------------------------------------
+// preflight: Collects the prerequisites of every builder into a single report before any work is done.
+// ---------------------------------------------------------------------------------------------------------------
+
+use argument_handler::Arguments;
+
+use std::path::{Path, PathBuf};
+
+use map_script_builder;
+use music_script_builder;
+use reslist_builder;
+use folder_compressor;
+use delta_builder;
+use compile_import;
+use server_package;
+use shared;
+use error::GesError;
+
+/// A single prerequisite of one section of the program.
+pub struct PreflightCheck
+{
+    pub section: &'static str,
+    pub path: PathBuf,
+    pub expected: String,
+    pub passed: bool,
+    pub required: bool,
+}
+
+/// Every prerequisite checked before running, and whether each one was met.
+#[derive(Default)]
+pub struct PreflightReport
+{
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport
+{
+    pub fn new() -> PreflightReport
+    {
+        PreflightReport { checks: Vec::new() }
+    }
+
+    /// Records a prerequisite the section can't run without.
+    pub fn require( &mut self, section: &'static str, path: &Path, expected: &str, passed: bool )
+    {
+        self.checks.push( PreflightCheck { section, path: path.to_path_buf(), expected: String::from(expected), passed, required: true } );
+    }
+
+    /// Records a prerequisite the section can do without, at the cost of some functionality.
+    pub fn recommend( &mut self, section: &'static str, path: &Path, expected: &str, passed: bool )
+    {
+        self.checks.push( PreflightCheck { section, path: path.to_path_buf(), expected: String::from(expected), passed, required: false } );
+    }
+
+    /// Checks to see if any required prerequisite wasn't met.
+    pub fn has_failures( &self ) -> bool
+    {
+        self.checks.iter().any(|x| x.required && !x.passed)
+    }
+
+    /// Prints a warning for every recommended prerequisite that wasn't met, and every check if we're verbose.
+    pub fn print( &self, verbose: bool )
+    {
+        for check in &self.checks
+        {
+            if !check.passed && !check.required
+            {
+                shared::print_warning( &format!( "{}: expected {} at {}", check.section, check.expected, check.path.display() ) );
+            }
+            else if verbose
+            {
+                println!( "\t[{}] {}: {} at {}", if check.passed { "OK" } else { "Missing" }, check.section, check.expected, check.path.display() );
+            }
+        }
+    }
+
+    /// Converts the report into an error listing every required prerequisite that wasn't met, if there were any.
+    pub fn into_result( self ) -> Result<(), GesError>
+    {
+        if !self.has_failures()
+        {
+            return Ok(());
+        }
+
+        let mut error_text = String::from("Preflight checks failed!");
+
+        for check in self.checks.iter().filter(|x| x.required && !x.passed)
+        {
+            error_text.push_str( &format!( "\n  {}: expected {} at {}", check.section, check.expected, check.path.display() ) );
+        }
+
+        Err(GesError::Config( error_text ))
+    }
+}
+
+/// Asks every section of the program that's going to run for its prerequisites, and reports on them all at once.
+pub fn run_preflight( args: &Arguments, map_name: &str ) -> Result<(), GesError>
+{
+    let mut report = PreflightReport::new();
+
+    compile_import::preflight( args, &mut report );
+    map_script_builder::preflight( args, map_name, &mut report );
+    music_script_builder::preflight( args, map_name, &mut report );
+    reslist_builder::preflight( args, map_name, &mut report );
+
+    if args.compress && !args.fullcheck
+    {
+        folder_compressor::preflight( args, map_name, &mut report );
+        delta_builder::preflight( args, map_name, &mut report );
+    }
+
+    if !args.fullcheck
+    {
+        server_package::preflight( args, &mut report );
+    }
+
+    if args.verbose
+    {
+        println!( "Preflight checks:" );
+    }
+
+    report.print( args.verbose );
+    report.into_result()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use super::*;
+
+    #[test]
+    fn test_preflight_report()
+    {
+        let args = get_barebones_args();
+        run_preflight( &args, "test_map" ).unwrap();
+
+        // A missing map should be reported along with where we expected to find it.
+        let error_text = run_preflight( &args, "missing_map" ).unwrap_err().to_string();
+        assert!( error_text.contains("missing_map.bsp") );
+
+        // Recommendations don't stop the program from running.
+        let mut report = PreflightReport::new();
+        report.recommend( "Test", &args.rootdir, "something optional", false );
+        assert!( !report.has_failures() );
+
+        report.require( "Test", &args.rootdir, "something required", false );
+        assert!( report.into_result().is_err() );
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// presets: Fills in map script settings suited to the kind of server a map is released for.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use argument_handler::Arguments;
+use map_script_builder;
+use error::GesError;
+
+// The presets that come with the program, which a presets file can add to or change.
+static BUILTIN_PRESETS: &'static str = include_str!("../resources/presets.txt");
+
+// The argument each map script value term is set with, so values given on the commandline win over the preset.
+static VALUE_TERM_ARGUMENTS: &[(&'static str, &'static str)] = &[("BaseWeight", "weight"), ("MinPlayers", "minplayers"), ("MaxPlayers", "maxplayers"),
+                                                                 ("ResIntensity", "resintensity"), ("TeamThreshold", "teamthresh")];
+
+/// One setting of one preset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PresetSetting
+{
+    pub preset: String,
+    /// Either a map script value term, or the section a weight goes in.
+    pub term: String,
+    /// The entry a weight is for, or None for value terms.
+    pub entry: Option<String>,
+    pub value: i32,
+}
+
+/// Applies every setting of the named preset to the arguments, unless the same value was given on the commandline.
+/// Section weights are kept for the map script to be created with.
+pub fn apply_preset( args: &mut Arguments, preset_name: &str ) -> Result<(), GesError>
+{
+    let mut preset_settings = parse_presets( &PathBuf::from("presets.txt"), BUILTIN_PRESETS )?;
+
+    match args.presets_file
+    {
+        Some(ref x) => preset_settings.extend( read_presets_file( x ).map_err(|e| e.in_file( x ))? ),
+        None => {},
+    }
+
+    if !preset_settings.iter().any(|x| x.preset.eq_ignore_ascii_case(preset_name))
+    {
+        let mut preset_names: Vec<&str> = preset_settings.iter().map(|x| x.preset.as_str()).collect();
+        preset_names.sort();
+        preset_names.dedup();
+
+        let mut error_text = String::new();
+        error_text.push_str("Unknown preset ");
+        error_text.push_str(preset_name);
+        error_text.push_str("!  Expected one of ");
+        error_text.push_str(&preset_names.join(", "));
+        error_text.push_str(".");
+
+        return Err(GesError::Config( error_text ));
+    }
+
+    // Later settings win, so a presets file can change the built in ones.
+    for setting in preset_settings.into_iter().filter(|x| x.preset.eq_ignore_ascii_case(preset_name))
+    {
+        match setting.entry
+        {
+            Some(ref x) =>
+            {
+                args.section_weights.retain(|y| !(y.0 == setting.term && y.1.eq_ignore_ascii_case(x)));
+                args.section_weights.push( (setting.term.clone(), x.clone(), setting.value) );
+            },
+            None =>
+            {
+                let argument_name = VALUE_TERM_ARGUMENTS.iter().find(|x| x.0 == setting.term).unwrap().1;
+
+                if args.given_values.contains(&argument_name)
+                {
+                    continue;
+                }
+
+                match argument_name
+                {
+                    "weight" => args.baseweight = setting.value,
+                    "minplayers" => args.minplayers = setting.value,
+                    "maxplayers" => args.maxplayers = setting.value,
+                    "resintensity" => args.resintensity = setting.value,
+                    _ => args.teamthresh = setting.value,
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the presets file at the given path, which uses the same format as the built in presets.
+fn read_presets_file( presets_path: &PathBuf ) -> Result<Vec<PresetSetting>, GesError>
+{
+    let mut contents = String::new();
+    fs::File::open(presets_path)?.read_to_string(&mut contents)?;
+
+    parse_presets( presets_path, &contents )
+}
+
+/// Parses presets, where each line holds a preset name, a map script setting, and its value, like
+/// "competitive GamemodeWeights/YOLT 0".  Anything after // is a comment.
+fn parse_presets( presets_path: &PathBuf, contents: &str ) -> Result<Vec<PresetSetting>, GesError>
+{
+    let mut preset_settings = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate()
+    {
+        let line = match line.find("//")
+        {
+            Some(x) => &line[..x],
+            None => line,
+        };
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if tokens.is_empty()
+        {
+            continue;
+        }
+
+        let parse_error = |message: String| GesError::Parse { file: presets_path.clone(), line: line_index + 1, message };
+
+        if tokens.len() != 3
+        {
+            return Err(parse_error( String::from("Expected a preset name, a map script setting, and its value!") ));
+        }
+
+        let (term, entry) = match tokens[1].find('/')
+        {
+            Some(x) => (&tokens[1][..x], Some(String::from(&tokens[1][x + 1..]))),
+            None => (tokens[1], None),
+        };
+
+        let is_known_term = match entry
+        {
+            Some(ref x) => map_script_builder::BRACKET_TERMS.contains(&term) && !x.is_empty(),
+            None => map_script_builder::VALUE_TERMS.contains(&term),
+        };
+
+        if !is_known_term
+        {
+            return Err(parse_error( format!( "Unknown setting {}!  Expected a map script value term like BaseWeight, or a section and entry like GamemodeWeights/YOLT.", tokens[1] ) ));
+        }
+
+        let value = match tokens[2].parse::<i32>()
+        {
+            Ok(x) => x,
+            Err(_) => return Err(parse_error( format!( "Value {} for {} isn't a whole number!", tokens[2], tokens[1] ) )),
+        };
+
+        preset_settings.push( PresetSetting { preset: tokens[0].to_lowercase(), term: String::from(term), entry, value } );
+    }
+
+    Ok(preset_settings)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use super::*;
+
+    #[test]
+    fn test_parse_presets()
+    {
+        let presets_path = PathBuf::from("presets.txt");
+
+        assert!( parse_presets( &presets_path, BUILTIN_PRESETS ).is_ok() );
+
+        let preset_settings = parse_presets( &presets_path, "LAN  BaseWeight  200 // Comment\nlan GamemodeWeights/YOLT 0\n" ).unwrap();
+        assert_eq!( preset_settings[0], PresetSetting { preset: String::from("lan"), term: String::from("BaseWeight"), entry: None, value: 200 } );
+        assert_eq!( preset_settings[1].entry, Some(String::from("YOLT")) );
+
+        assert!( parse_presets( &presets_path, "lan BaseWieght 200" ).is_err() );
+        assert!( parse_presets( &presets_path, "lan BaseWeight/YOLT 200" ).is_err() );
+        assert!( parse_presets( &presets_path, "lan BaseWeight lots" ).is_err() );
+    }
+
+    #[test]
+    fn test_apply_preset()
+    {
+        let mut args = get_barebones_args();
+        args.given_values = vec!["teamthresh"];
+        args.teamthresh = 6;
+
+        apply_preset( &mut args, "Tournament" ).unwrap();
+
+        // The commandline wins over the preset.
+        assert_eq!( args.baseweight, 0 );
+        assert_eq!( args.teamthresh, 6 );
+        assert!( args.section_weights.contains( &(String::from("GamemodeWeights"), String::from("YOLT"), 0) ) );
+
+        assert!( apply_preset( &mut args, "chaos" ).unwrap_err().to_string().contains("Unknown preset") );
+    }
+}
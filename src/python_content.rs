@@ -0,0 +1,187 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// python_content: Checks the python gamemodes and other server only files a map ships alongside itself.
+// ------------------------------------------------------------------------------------------------------------
+
+use argument_handler::Arguments;
+use ges_version;
+use reslist_builder;
+use shared;
+use rules;
+use stock_content;
+use error::GesError;
+
+// Where GE:S loads python from, relative to the gesource directory.  Linux servers care about case, so these do too.
+static PYTHON_DIRECTORIES: &[&'static str] = &["python/ges/GamePlay/", "python/ges/Ai/", "python/ges/Utils/"];
+
+/// Makes sure every python file in the release is somewhere GE:S will load it from, and lets the releaser know that
+/// server only files have to be installed by each server since clients can't download them.
+pub fn check_python_content( args: &Arguments ) -> Result<(), GesError>
+{
+    let server_files = reslist_builder::get_server_files( args )?;
+
+    if server_files.is_empty()
+    {
+        return Ok(());
+    }
+
+    // Collect every misplaced file so they can all be moved in one go, instead of one per run.
+    let mut error_problems: Vec<String> = Vec::new();
+
+    for server_file in &server_files
+    {
+        match get_layout_problem( server_file )
+        {
+            Some(x) => match rules::report_in_file( args, "python-layout", &args.rootdir.join( server_file ), 0, GesError::PolicyViolation( x ) )
+            {
+                Ok(_) => {},
+                Err(e) => error_problems.push( e.to_string() ),
+            },
+            None => {},
+        }
+    }
+
+    if !error_problems.is_empty()
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Python files in the release won't be loaded by GE:S:\n");
+
+        for problem in error_problems
+        {
+            error_text.push_str("  ");
+            error_text.push_str(&problem);
+            error_text.push_str("\n");
+        }
+
+        return Err(GesError::PolicyViolation( error_text ));
+    }
+
+    let shadowing_files: Vec<String> = server_files.iter().filter(|x| shadows_stock_gamemode( args, x )).cloned().collect();
+
+    shared::report_cleanup_list( args, "python-shadows-stock", "files have the same name as an official gamemode or python file, and will replace it on every server once installed.  \
+                                                                Give them names of their own", &shadowing_files )?;
+
+    shared::report_cleanup_list( args, "python-server-only", "files are left out of the reslist and compressed files since clients can't download them through fast download.  \
+                                                              Servers have to install them along with the map", &server_files )
+}
+
+/// Returns true if the server only file at the given relative path replaces an official gamemode or python file.
+/// Gamemodes are matched without case, since a server on Windows would load either one.
+fn shadows_stock_gamemode( args: &Arguments, file_path: &str ) -> bool
+{
+    if stock_content::get_stock_file( file_path ) != None
+    {
+        return true;
+    }
+
+    if !file_path.starts_with( PYTHON_DIRECTORIES[0] )
+    {
+        return false;
+    }
+
+    let gamemode_name = &file_path[PYTHON_DIRECTORIES[0].len()..];
+
+    let gamemode_name = match gamemode_name.rfind('.')
+    {
+        Some(x) if !gamemode_name.contains('/') => gamemode_name[..x].to_lowercase(),
+        _ => return false,
+    };
+
+    ges_version::get_profile( args ).gamemodes.iter().any(|x| x.to_lowercase() == gamemode_name)
+}
+
+/// Returns what's wrong with where the python file at the given relative path is, or None if GE:S will load it.
+fn get_layout_problem( file_path: &str ) -> Option<String>
+{
+    let extension = shared::get_string_file_extension( file_path ).to_lowercase();
+
+    // Other server only files, like ones a categories file adds, can go wherever the server expects them.
+    if extension != "py" && extension != "pyc"
+    {
+        return None;
+    }
+
+    if PYTHON_DIRECTORIES.iter().any(|x| file_path.starts_with(x))
+    {
+        return None;
+    }
+
+    let mut problem_text = String::new();
+    problem_text.push_str(file_path);
+
+    match PYTHON_DIRECTORIES.iter().find(|x| file_path.to_lowercase().starts_with( &x.to_lowercase() ))
+    {
+        Some(x) =>
+        {
+            problem_text.push_str(" has the wrong case for Linux servers to find it!  It belongs in ");
+            problem_text.push_str(x);
+        },
+        None =>
+        {
+            problem_text.push_str(" isn't anywhere GE:S loads python from!  Gamemodes belong in python/ges/GamePlay/, and anything else in ");
+            problem_text.push_str( &PYTHON_DIRECTORIES[1..].join(" or ") );
+        },
+    }
+
+    problem_text.push('.');
+
+    Some(problem_text)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_get_layout_problem()
+    {
+        assert_eq!( get_layout_problem( "python/ges/GamePlay/Arena.py" ), None );
+        assert_eq!( get_layout_problem( "python/ges/GamePlay/Utils/arena_helpers.py" ), None );
+        assert_eq!( get_layout_problem( "cfg/server_ge_test.cfg" ), None );
+
+        assert!( get_layout_problem( "python/ges/gameplay/Arena.py" ).unwrap().contains("wrong case") );
+        assert!( get_layout_problem( "python/GamePlay/Arena.py" ).unwrap().contains("python/ges/GamePlay/") );
+        assert!( get_layout_problem( "Arena.pyc" ).is_some() );
+    }
+
+    #[test]
+    fn test_shadows_stock_gamemode()
+    {
+        let args = get_barebones_args();
+
+        assert!( shadows_stock_gamemode( &args, "python/ges/GamePlay/DeathMatch.py" ) );
+        assert!( shadows_stock_gamemode( &args, "python/ges/GamePlay/yolt.pyc" ) );
+        assert!( !shadows_stock_gamemode( &args, "python/ges/GamePlay/Arena.py" ) );
+        assert!( !shadows_stock_gamemode( &args, "python/ges/GamePlay/Utils/DeathMatch.py" ) );
+        assert!( !shadows_stock_gamemode( &args, "cfg/DeathMatch.cfg" ) );
+    }
+
+    #[test]
+    fn test_check_python_content()
+    {
+        let mut args = get_barebones_args();
+        args.rootdir = get_root_test_directory();
+        args.rootdir.push("temp");
+        args.rootdir.push("python_content");
+        args.rootdir.push("gesource");
+
+        let _ = fs::remove_dir_all( &args.rootdir );
+        fs::create_dir_all( args.rootdir.join("python/ges/GamePlay") ).unwrap();
+        fs::write( args.rootdir.join("python/ges/GamePlay/Arena.py"), "class Arena: pass\n" ).unwrap();
+
+        // Server only files are just a warning, as long as they're where they belong.
+        assert!( check_python_content( &args ).is_ok() );
+
+        fs::write( args.rootdir.join("python/Arena.py"), "class Arena: pass\n" ).unwrap();
+        assert!( check_python_content( &args ).unwrap_err().to_string().contains("python/Arena.py") );
+
+        fs::remove_dir_all( args.rootdir.parent().unwrap() ).unwrap();
+    }
+}
@@ -0,0 +1,347 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// release_lock: Records the settings a release was made with, so running on it again later makes the same files.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use toml;
+
+use argument_handler::Arguments;
+use map_script_builder;
+use rules;
+use rules::Severity;
+use shared;
+use file_writer;
+use user_config;
+use update_checker::CURRENT_VERSION;
+use error::GesError;
+
+// Kept next to the release's gesource directory rather than in it, so it's never shipped along with the release.
+pub static LOCK_FILE_NAME: &'static str = "gesrelease.lock";
+
+lazy_static!
+{
+    // The lockfile of the release being run on, if it has one and it wasn't ignored.
+    static ref LOADED_LOCK: Mutex<Option<ReleaseLock>> = Mutex::new(None);
+}
+
+/// The settings a release was last successfully made with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReleaseLock
+{
+    /// The version of the program that made the release.
+    pub version: String,
+    /// Every argument that changes what's generated, as it would be given on the commandline.
+    pub arguments: Vec<(String, String)>,
+    /// Weights given to entries in the map script's sections, as (section, entry, weight).
+    pub section_weights: Vec<(String, String, i32)>,
+    /// How seriously each rule was taken.
+    pub rules: Vec<(String, Severity)>,
+}
+
+/// Returns where the lockfile of the release in the given root directory goes.
+pub fn get_lock_path( rootdir: &PathBuf ) -> PathBuf
+{
+    match rootdir.parent()
+    {
+        Some(x) => x.join(LOCK_FILE_NAME),
+        None => rootdir.join(LOCK_FILE_NAME),
+    }
+}
+
+/// Reads the lockfile of the release in the given root directory, if it has one, so its settings can fill in for
+/// arguments that weren't given.  Returns the path it was read from.
+pub fn load_release_lock( rootdir: &PathBuf ) -> Result<Option<PathBuf>, GesError>
+{
+    let lock_path = get_lock_path( rootdir );
+
+    if !lock_path.is_file()
+    {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(&lock_path).map_err(|e| GesError::from(e).in_file( &lock_path ))?;
+    let release_lock = parse_release_lock( &lock_path, &contents )?;
+
+    if release_lock.version != CURRENT_VERSION
+    {
+        let mut warning_text = String::new();
+        warning_text.push_str("The last release was made with version ");
+        warning_text.push_str(&release_lock.version);
+        warning_text.push_str(" of this program, but this is version ");
+        warning_text.push_str(CURRENT_VERSION);
+        warning_text.push_str(".\nIts settings will still be used, but use version ");
+        warning_text.push_str(&release_lock.version);
+        warning_text.push_str(" if the files have to come out exactly the same.");
+
+        shared::print_warning( &warning_text );
+    }
+
+    *LOADED_LOCK.lock().unwrap() = Some(release_lock);
+
+    Ok(Some(lock_path))
+}
+
+/// Returns the loaded lockfile's value for the given argument, if it has one.
+pub fn get_locked_argument( argument_name: &str ) -> Option<String>
+{
+    match *LOADED_LOCK.lock().unwrap()
+    {
+        Some(ref x) => x.arguments.iter().find(|y| y.0 == argument_name).map(|y| y.1.clone()),
+        None => None,
+    }
+}
+
+/// Returns how seriously the loaded lockfile takes each rule, which a rules file can still change.
+pub fn get_locked_rules() -> Vec<(String, Severity)>
+{
+    match *LOADED_LOCK.lock().unwrap()
+    {
+        Some(ref x) => x.rules.clone(),
+        None => Vec::new(),
+    }
+}
+
+/// Gives the map script sections the weights the loaded lockfile has for them.  These win over a preset's, just like
+/// the lockfile's values do, so a preset whose defaults have changed since doesn't change the release.
+pub fn apply_locked_section_weights( args: &mut Arguments )
+{
+    match *LOADED_LOCK.lock().unwrap()
+    {
+        Some(ref x) =>
+        {
+            for &(ref section, ref entry, weight) in &x.section_weights
+            {
+                args.section_weights.retain(|y| !(y.0 == *section && y.1.eq_ignore_ascii_case(entry)));
+                args.section_weights.push( (section.clone(), entry.clone(), weight) );
+            }
+        },
+        None => {},
+    }
+}
+
+/// Writes the lockfile for a release that was just made successfully, unless it already says the same thing.
+pub fn write_release_lock( args: &Arguments ) -> Result<(), GesError>
+{
+    let lock_path = get_lock_path( &args.rootdir );
+    let contents = get_lock_contents( &get_release_lock( args ) );
+
+    if fs::read_to_string(&lock_path).ok().as_ref() == Some(&contents)
+    {
+        return Ok(());
+    }
+
+    file_writer::write( &lock_path, contents.as_bytes() )?;
+
+    println!( "Recorded the settings of this release in {}.", lock_path.display() );
+
+    Ok(())
+}
+
+/// Returns the settings the given arguments make a release with.
+fn get_release_lock( args: &Arguments ) -> ReleaseLock
+{
+    let mut arguments = vec![(String::from("weight"), args.baseweight.to_string()),
+                             (String::from("minplayers"), args.minplayers.to_string()),
+                             (String::from("maxplayers"), args.maxplayers.to_string()),
+                             (String::from("resintensity"), args.resintensity.to_string()),
+                             (String::from("teamthresh"), args.teamthresh.to_string()),
+                             (String::from("include-sources"), args.include_sources.to_string()),
+                             (String::from("reslist-dirs"), args.reslist_dirs.to_string()),
+                             (String::from("sample-music"), args.sample_music.to_string()),
+                             (String::from("hash-algorithm"), args.hash_algorithm.to_string()),
+                             (String::from("dedupe"), args.dedupe.to_string()),
+                             (String::from("server-config"), args.server_config.to_string())];
+
+    let optional_arguments = [("map-group", args.map_group.clone()),
+                              ("music-seed", args.music_seed.map(|x| x.to_string())),
+                              ("ges-version", args.ges_version.clone())];
+
+    for &(argument_name, ref value) in &optional_arguments
+    {
+        match *value
+        {
+            Some(ref x) => arguments.push( (String::from(argument_name), x.clone()) ),
+            None => {},
+        }
+    }
+
+    if !args.only_gamemodes.is_empty()
+    {
+        arguments.push( (String::from("only-gamemodes"), args.only_gamemodes.join(",")) );
+    }
+
+    ReleaseLock
+    {
+        version: String::from(CURRENT_VERSION),
+        arguments,
+        section_weights: args.section_weights.clone(),
+        rules: rules::RULES.iter().map(|x| (String::from(x.id), rules::get_severity( args, x.id ))).collect(),
+    }
+}
+
+/// Returns the given lock as it's written to the lockfile.  Settings are always written in the same order, so the same
+/// release always gets the same lockfile.
+fn get_lock_contents( release_lock: &ReleaseLock ) -> String
+{
+    let mut argument_table = toml::Table::new();
+
+    for &(ref argument_name, ref value) in &release_lock.arguments
+    {
+        // Numbers and flags are written as what they are, so the file reads like a user config.
+        let value = match (value.parse::<i64>(), value.as_str())
+        {
+            (Ok(x), _) if argument_name != "music-seed" => toml::Value::Integer(x),
+            (_, "true") => toml::Value::Boolean(true),
+            (_, "false") => toml::Value::Boolean(false),
+            _ if argument_name == "only-gamemodes" => toml::Value::Array( value.split(',').map(|x| toml::Value::String(String::from(x))).collect() ),
+            _ => toml::Value::String(value.clone()),
+        };
+
+        argument_table.insert( argument_name.clone(), value );
+    }
+
+    let mut section_weight_table = toml::Table::new();
+
+    for &(ref section, ref entry, weight) in &release_lock.section_weights
+    {
+        section_weight_table.insert( format!( "{}/{}", section, entry ), toml::Value::Integer( weight as i64 ) );
+    }
+
+    let mut rule_table = toml::Table::new();
+
+    for &(ref rule_id, severity) in &release_lock.rules
+    {
+        rule_table.insert( rule_id.clone(), toml::Value::String( severity.to_string() ) );
+    }
+
+    let mut table = toml::Table::new();
+    table.insert( String::from("version"), toml::Value::String( release_lock.version.clone() ) );
+    table.insert( String::from("arguments"), toml::Value::Table(argument_table) );
+    table.insert( String::from("section-weights"), toml::Value::Table(section_weight_table) );
+    table.insert( String::from("rules"), toml::Value::Table(rule_table) );
+
+    let mut contents = String::new();
+    contents.push_str("# The settings the last successful release was made with, written by ges_scriptutility.  Later runs start\n");
+    contents.push_str("# from these instead of the defaults, and anything given on the commandline still wins.\n\n");
+    contents.push_str( &table.to_string() );
+
+    contents
+}
+
+/// Parses the contents of a lockfile.  Rules this version doesn't know about anymore are skipped with a warning.
+fn parse_release_lock( lock_path: &PathBuf, contents: &str ) -> Result<ReleaseLock, GesError>
+{
+    let table = user_config::parse_table( lock_path, contents )?;
+    let parse_error = |message: &str| GesError::Parse { file: lock_path.clone(), line: 0, message: String::from(message) };
+
+    let version = match table.get("version")
+    {
+        Some(&toml::Value::String(ref x)) => x.clone(),
+        _ => return Err(parse_error( "The lockfile doesn't say which version of the program wrote it!" )),
+    };
+
+    let empty_table = toml::Table::new();
+    let get_table = |name: &str| match table.get(name)
+    {
+        Some(&toml::Value::Table(ref x)) => Ok(x),
+        None => Ok(&empty_table),
+        Some(_) => Err(parse_error( &format!( "Expected {} to be a table of settings!", name ) )),
+    };
+
+    let arguments = user_config::get_argument_settings( lock_path, get_table("arguments")? )?;
+
+    let mut section_weights = Vec::new();
+
+    for (name, value) in get_table("section-weights")?
+    {
+        let (section, entry) = match name.find('/')
+        {
+            Some(x) => (&name[..x], &name[x + 1..]),
+            None => (name.as_str(), ""),
+        };
+
+        match *value
+        {
+            toml::Value::Integer(x) if map_script_builder::BRACKET_TERMS.contains(&section) && !entry.is_empty() =>
+            {
+                section_weights.push( (String::from(section), String::from(entry), x as i32) );
+            },
+            _ => return Err(parse_error( &format!( "Expected {} to be a section and entry like GamemodeWeights/YOLT with a whole number weight!", name ) )),
+        }
+    }
+
+    let mut rule_overrides = Vec::new();
+
+    for (rule_id, value) in get_table("rules")?
+    {
+        let severity = match *value
+        {
+            toml::Value::String(ref x) => Severity::from_name( x ),
+            _ => None,
+        };
+
+        match (rules::get_rule( rule_id ), severity)
+        {
+            (Some(x), Some(y)) if !x.locked || y == Severity::Error => rule_overrides.push( (rule_id.clone(), y) ),
+            (None, Some(_)) => shared::print_warning( &format!( "The lockfile has rule {}, which doesn't exist anymore!\nIt will be ignored.", rule_id ) ),
+            _ => return Err(parse_error( &format!( "Rule {} has a severity that can't be used!  Expected error, warning, or off.", rule_id ) )),
+        }
+    }
+
+    Ok(ReleaseLock { version, arguments, section_weights, rules: rule_overrides })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use super::*;
+
+    #[test]
+    fn test_release_lock()
+    {
+        let mut args = get_barebones_args();
+        args.baseweight = 700;
+        args.map_group = Some(String::from("ge_test"));
+        args.only_gamemodes = vec![String::from("LTK"), String::from("YOLT")];
+        args.music_seed = Some(u64::MAX);
+        args.section_weights = vec![(String::from("GamemodeWeights"), String::from("YOLT"), 0)];
+
+        let release_lock = get_release_lock( &args );
+        let contents = get_lock_contents( &release_lock );
+
+        assert!( contents.contains("weight = 700\n") );
+        assert!( contents.contains("only-gamemodes = [\"LTK\", \"YOLT\"]\n") );
+        assert!( contents.contains("\"GamemodeWeights/YOLT\" = 0\n") );
+
+        // Everything comes back as it went in, in the order the lockfile keeps it.
+        let mut parsed_lock = parse_release_lock( &PathBuf::from(LOCK_FILE_NAME), &contents ).unwrap();
+        parsed_lock.arguments.sort();
+        parsed_lock.rules.sort_by(|x, y| x.0.cmp(&y.0));
+
+        let mut expected_lock = release_lock.clone();
+        expected_lock.arguments.sort();
+        expected_lock.rules.sort_by(|x, y| x.0.cmp(&y.0));
+
+        assert_eq!( parsed_lock, expected_lock );
+        assert_eq!( get_lock_contents( &parsed_lock ), contents );
+
+        assert!( parse_release_lock( &PathBuf::from(LOCK_FILE_NAME), "[arguments]\nweight = 700\n" ).is_err() );
+        assert!( parse_release_lock( &PathBuf::from(LOCK_FILE_NAME), "version = \"1.0.2\"\n[section-weights]\nBaseWeight = 700\n" ).is_err() );
+    }
+
+    #[test]
+    fn test_lock_path()
+    {
+        assert_eq!( get_lock_path( &PathBuf::from("releases/ge_test/gesource") ), PathBuf::from("releases/ge_test/gesrelease.lock") );
+        assert_eq!( get_lock_path( &PathBuf::from("gesource") ), PathBuf::from("gesrelease.lock") );
+    }
+}
@@ -0,0 +1,391 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// -----------------------------------------------------------------------------------------------------------
+// release_signing: Signs release manifests with Ed25519 and verifies signed releases against their manifests.
+// -----------------------------------------------------------------------------------------------------------
+
+use std::collections::HashSet;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::prelude::*;
+
+use std::path::{Path, PathBuf};
+
+use bzip2::read::BzDecoder;
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use getrandom;
+
+use argument_handler::Arguments;
+use manifest;
+use delta_builder;
+use manifest::HashAlgorithm;
+use shared;
+use file_writer;
+use file_writer::FileWriter;
+use error::GesError;
+
+// Signatures sit right next to the manifest they sign, as maps/[mapname].manifest.sig.
+pub static SIGNATURE_EXTENSION: &str = "sig";
+pub static SECRET_KEY_EXTENSION: &str = "key";
+pub static PUBLIC_KEY_EXTENSION: &str = "pub";
+
+/// Generates a new key pair, writing the secret key to key_name.key and the public key to key_name.pub.
+/// Returns the paths of the secret and public key files.
+pub fn generate_key_pair( key_name: &PathBuf ) -> Result<(PathBuf, PathBuf), GesError>
+{
+    let mut secret_key_path = key_name.clone();
+    secret_key_path.set_extension(SECRET_KEY_EXTENSION);
+
+    let mut public_key_path = key_name.clone();
+    public_key_path.set_extension(PUBLIC_KEY_EXTENSION);
+
+    // Losing a secret key means nobody can verify releases signed with its replacement, so never overwrite one.
+    for key_path in &[&secret_key_path, &public_key_path]
+    {
+        if key_path.exists()
+        {
+            let mut error_text = String::new();
+            error_text.push_str( &key_path.display().to_string() );
+            error_text.push_str(" already exists!  Move it out of the way or pick a different name for the new key pair.");
+
+            return Err(GesError::Config( error_text ));
+        }
+    }
+
+    let mut secret_bytes = [0u8; 32];
+    getrandom::getrandom(&mut secret_bytes).map_err(|e| io::Error::new( io::ErrorKind::Other, format!( "Couldn't gather randomness for a new key: {}", e ) ))?;
+
+    let signing_key = SigningKey::from_bytes(&secret_bytes);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    // Keep other users on the machine from reading the secret key where the platform lets us.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    FileWriter::open( &options, &secret_key_path )?.write_all( format!( "{}\r\n", encode_hex( &signing_key.to_bytes() ) ).as_bytes() )?;
+    file_writer::write( &public_key_path, format!( "{}\r\n", encode_hex( signing_key.verifying_key().as_bytes() ) ).as_bytes() )?;
+
+    Ok((secret_key_path, public_key_path))
+}
+
+/// Returns where the signature of the given manifest is stored.
+pub fn get_signature_path( manifest_path: &Path ) -> PathBuf
+{
+    let mut signature_path = manifest_path.as_os_str().to_os_string();
+    signature_path.push(".");
+    signature_path.push(SIGNATURE_EXTENSION);
+
+    PathBuf::from(signature_path)
+}
+
+/// Signs the manifest at manifest_path with the secret key in key_path, writing the signature next to it.
+pub fn sign_manifest( manifest_path: &PathBuf, key_path: &PathBuf ) -> Result<(), GesError>
+{
+    let signing_key = read_signing_key( key_path )?;
+    let signature = signing_key.sign( &fs::read(manifest_path)? );
+
+    file_writer::write( &get_signature_path( manifest_path ), format!( "{}\r\n", encode_hex( &signature.to_bytes() ) ).as_bytes() )?;
+
+    Ok(())
+}
+
+/// Reads the secret key stored in the given key file.
+pub fn read_signing_key( key_path: &PathBuf ) -> Result<SigningKey, GesError>
+{
+    let key_bytes = read_hex_file( key_path, 32, "secret key" )?;
+
+    let mut secret_bytes = [0u8; 32];
+    secret_bytes.copy_from_slice(&key_bytes);
+
+    Ok(SigningKey::from_bytes(&secret_bytes))
+}
+
+/// Reads the public key stored in the given key file.
+pub fn read_verifying_key( key_path: &PathBuf ) -> Result<VerifyingKey, GesError>
+{
+    let key_bytes = read_hex_file( key_path, 32, "public key" )?;
+
+    let mut public_bytes = [0u8; 32];
+    public_bytes.copy_from_slice(&key_bytes);
+
+    VerifyingKey::from_bytes(&public_bytes).map_err(|_| GesError::Parse { file: key_path.clone(), line: 1, message: String::from("Public key isn't a valid Ed25519 key!") })
+}
+
+/// Checks the signature of every manifest in the release directory against the supplied public key, and then checks every
+/// file the signed manifests list against its hash.  Files can be either uncompressed or compressed with bz2.
+pub fn verify_release( args: &Arguments ) -> Result<(), GesError>
+{
+    let (release_dir, key_path) = match (&args.verify_release, &args.verify_key)
+    {
+        (&Some(ref x), &Some(ref y)) => (x.clone(), y.clone()),
+        _ => return Err(GesError::Config( String::from("Verifying a release needs both a release directory and a public key!") )),
+    };
+
+    let verifying_key = read_verifying_key( &key_path ).map_err(|e| e.in_file( &key_path ))?;
+
+    let (_manifest_comp_names, manifest_write_names) = shared::get_files_in_directory( &release_dir, manifest::MANIFEST_EXTENSION, &[] )?;
+
+    if manifest_write_names.is_empty()
+    {
+        let mut error_text = String::new();
+        error_text.push_str("No manifests found in ");
+        error_text.push_str( &release_dir.display().to_string() );
+        error_text.push_str(", so there's nothing to verify the release against!");
+
+        return Err(GesError::PolicyViolation( error_text ));
+    }
+
+    // Collect every problem so a broken release can be fixed in one go.
+    let mut problems: Vec<String> = Vec::new();
+    let mut verified_count = 0;
+
+    // Every file a trusted manifest vouches for, in the form it's listed in.
+    let mut listed_names: HashSet<String> = HashSet::new();
+
+    for manifest_name in &manifest_write_names
+    {
+        let mut manifest_path = release_dir.clone();
+        manifest_path.push(manifest_name);
+
+        // An unsigned or tampered manifest can't be trusted to say anything about the other files, so skip them.
+        match check_manifest_signature( &manifest_path, &verifying_key )
+        {
+            Ok(_) => {},
+            Err(e) => { problems.push( e.to_string() ); continue; },
+        }
+
+        if args.verbose
+        {
+            println!( "Signature of {} is valid.", manifest_name );
+        }
+
+        let manifest = manifest::read_manifest( &manifest_path )?;
+
+        for (relative_name, manifest_hash) in manifest.entries
+        {
+            match hash_release_file( &release_dir, &relative_name, manifest.algorithm )?
+            {
+                Some(ref x) if *x == manifest_hash => verified_count += 1,
+                Some(_) => problems.push( format!( "{} doesn't match the hash in {}!", relative_name, manifest_name ) ),
+                None => problems.push( format!( "{} is listed in {} but isn't in the release!", relative_name, manifest_name ) ),
+            }
+
+            listed_names.insert( relative_name );
+        }
+    }
+
+    // A file no manifest lists would still be shipped to everyone who downloads the release, with nothing vouching for it.
+    for release_name in shared::get_files_in_directory( &release_dir, "", &[] )?.1
+    {
+        let is_listed = listed_names.contains( &release_name ) || match release_name.strip_suffix(".bz2")
+        {
+            Some(x) => listed_names.contains(x),
+            None => false,
+        };
+
+        // A delta package's deletion list is copied in alongside its manifest, so it belongs with the manifest too.
+        let is_manifest_file = manifest_write_names.iter().any(|x|
+        {
+            let manifest_stem = &x[..x.len() - manifest::MANIFEST_EXTENSION.len()];
+            *x == release_name || format!( "{}.{}", x, SIGNATURE_EXTENSION ) == release_name || format!( "{}{}", manifest_stem, delta_builder::DELETION_LIST_EXTENSION ) == release_name
+        });
+
+        if !is_listed && !is_manifest_file
+        {
+            problems.push( format!( "{} is in the release but isn't listed in any manifest!", release_name ) );
+        }
+    }
+
+    if !problems.is_empty()
+    {
+        let mut error_text = String::new();
+        error_text.push_str("Release ");
+        error_text.push_str( &release_dir.display().to_string() );
+        error_text.push_str(" failed verification:\n");
+
+        for problem in problems
+        {
+            error_text.push_str("  ");
+            error_text.push_str(&problem);
+            error_text.push_str("\n");
+        }
+
+        return Err(GesError::PolicyViolation( error_text ));
+    }
+
+    println!( "Verified the signatures of {} manifests and the hashes of {} files in {}!", manifest_write_names.len(), verified_count, release_dir.display() );
+
+    Ok(())
+}
+
+/// Makes sure the given manifest has a signature, and that it was made by the owner of the given public key.
+fn check_manifest_signature( manifest_path: &PathBuf, verifying_key: &VerifyingKey ) -> Result<(), GesError>
+{
+    let signature_path = get_signature_path( manifest_path );
+
+    if !signature_path.is_file()
+    {
+        return Err(GesError::MissingFile { path: signature_path, message: format!( "{} isn't signed!", manifest_path.display() ) });
+    }
+
+    let signature_bytes = read_hex_file( &signature_path, 64, "signature" )?;
+
+    let mut signature_array = [0u8; 64];
+    signature_array.copy_from_slice(&signature_bytes);
+
+    match verifying_key.verify_strict( &fs::read(manifest_path)?, &Signature::from_bytes(&signature_array) )
+    {
+        Ok(_) => Ok(()),
+        Err(_) => Err(GesError::PolicyViolation( format!( "{} wasn't signed with the supplied key, or was changed after it was signed!", manifest_path.display() ) )),
+    }
+}
+
+/// Hashes the release file with the given relative path, decompressing it first if only a .bz2 copy is present.
+/// Returns None if the file isn't in the release at all.
+fn hash_release_file( release_dir: &PathBuf, relative_name: &str, algorithm: HashAlgorithm ) -> Result<Option<String>, GesError>
+{
+    let mut file_path = release_dir.clone();
+    file_path.push(relative_name);
+
+    if file_path.is_file()
+    {
+        return Ok(Some( algorithm.hash_file( &file_path )? ));
+    }
+
+    let mut compressed_path = release_dir.clone();
+    compressed_path.push( format!( "{}.bz2", relative_name ) );
+
+    if compressed_path.is_file()
+    {
+        return Ok(Some( algorithm.hash_reader( &mut BzDecoder::new( fs::File::open(&compressed_path)? ) )? ));
+    }
+
+    Ok(None)
+}
+
+/// Reads a file holding a single hex encoded value of the given length in bytes.
+fn read_hex_file( file_path: &PathBuf, byte_count: usize, description: &str ) -> Result<Vec<u8>, GesError>
+{
+    let contents = fs::read_to_string(file_path)?;
+
+    match decode_hex( contents.trim() )
+    {
+        Some(ref x) if x.len() == byte_count => Ok(x.clone()),
+        _ =>
+        {
+            let mut error_text = String::new();
+            error_text.push_str( &file_path.display().to_string() );
+            error_text.push_str(" doesn't hold a valid ");
+            error_text.push_str(description);
+            error_text.push_str("!");
+
+            Err(GesError::Parse { file: file_path.clone(), line: 1, message: error_text })
+        },
+    }
+}
+
+/// Returns the given bytes as a lowercase hex string.
+fn encode_hex( bytes: &[u8] ) -> String
+{
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// Returns the bytes held in the given hex string, or None if it isn't valid hex.
+fn decode_hex( text: &str ) -> Option<Vec<u8>>
+{
+    if !text.len().is_multiple_of(2) || !text.is_ascii()
+    {
+        return None;
+    }
+
+    (0..text.len()).step_by(2).map(|x| u8::from_str_radix( &text[x..x + 2], 16 ).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_decode_hex()
+    {
+        assert_eq!( decode_hex("00ff7A"), Some(vec![0x00, 0xff, 0x7a]) );
+        assert_eq!( decode_hex( &encode_hex(&[1, 2, 254]) ), Some(vec![1, 2, 254]) );
+        assert_eq!( decode_hex("abc"), None );
+        assert_eq!( decode_hex("zz"), None );
+    }
+
+    #[test]
+    fn test_sign_and_verify_release()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("release_signing");
+
+        let mut release_dir = test_dir.clone();
+        release_dir.push("gesource");
+
+        let mut maps_dir = release_dir.clone();
+        maps_dir.push("maps");
+        fs::create_dir_all(&maps_dir).unwrap();
+
+        let mut key_name = test_dir.clone();
+        key_name.push("release");
+
+        let (secret_key_path, public_key_path) = generate_key_pair( &key_name ).unwrap();
+
+        // Never overwrite an existing key pair.
+        assert!( generate_key_pair( &key_name ).is_err() );
+
+        let mut map_path = maps_dir.clone();
+        map_path.push("test_map.bsp");
+        fs::File::create(&map_path).unwrap().write_all(b"Not really a map.").unwrap();
+
+        let mut args = get_barebones_args();
+        args.verify_release = Some(release_dir.clone());
+        args.verify_key = Some(public_key_path.clone());
+
+        let mut manifest_path = maps_dir.clone();
+        manifest_path.push("test_map.manifest");
+        manifest::write_manifest( &args, &manifest_path, &release_dir, &[PathBuf::from("maps/test_map.bsp")] ).unwrap();
+
+
+        // Without a signature the release can't be trusted.
+        assert!( verify_release( &args ).unwrap_err().to_string().contains("isn't signed") );
+
+        sign_manifest( &manifest_path, &secret_key_path ).unwrap();
+        assert!( verify_release( &args ).is_ok() );
+
+        // Changing a released file should break its hash, but not the signature.
+        fs::File::create(&map_path).unwrap().write_all(b"A different map.").unwrap();
+        assert!( verify_release( &args ).unwrap_err().to_string().contains("doesn't match the hash") );
+
+        // A file slipped into the release afterwards isn't vouched for by anything.
+        let mut extra_path = maps_dir.clone();
+        extra_path.push("test_map.cfg");
+        fs::File::create(&extra_path).unwrap().write_all(b"sv_cheats 1").unwrap();
+        fs::File::create(&map_path).unwrap().write_all(b"Not really a map.").unwrap();
+        assert!( verify_release( &args ).unwrap_err().to_string().contains("maps/test_map.cfg is in the release but isn't listed in any manifest") );
+
+        fs::remove_file(&extra_path).unwrap();
+        assert!( verify_release( &args ).is_ok() );
+
+        // Changing the manifest to match should break the signature instead.
+        fs::File::create(&map_path).unwrap().write_all(b"A different map.").unwrap();
+        manifest::write_manifest( &args, &manifest_path, &release_dir, &[PathBuf::from("maps/test_map.bsp")] ).unwrap();
+        assert!( verify_release( &args ).unwrap_err().to_string().contains("changed after it was signed") );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
@@ -7,38 +7,62 @@
 // restlist_builder: Contains functions for analyzing and building reslist files for GoldenEye: Source maps.
 // ---------------------------------------------------------------------------------------------------------
 
-use std::fs;
 use std::io::prelude::*;
 use argument_handler::Arguments;
 
-use std::path::PathBuf;
-use std::io::{Error, ErrorKind};
-use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use shared;
+use file_categories;
+use file_categories::FileCategory;
+use shared::{DirectoryTree, FileInfo};
+use rules;
+use file_writer;
+use script_encoding;
+use validation_cache;
+use generation_metadata;
+use generation_metadata::EditState;
+use stock_content;
+use file_writer::FileWriter;
+use error::GesError;
+use keyvalues::{Token, TokenError, TokenKind, Tokenizer};
+use preflight::PreflightReport;
 
-use regex::Regex;
 
+// Files the OS or an editor leaves behind on its own.  Nobody means to ship these, so they're always left out.
+static JUNK_FILENAMES: &[&'static str] = &["thumbs.db", ".ds_store", "desktop.ini"];
 
-// Grab all files in our installation except for the disallowed file types, to make sure everything is included.
-// BSP files are not allowed as it wouldn't make sense to include the map itself in the reslist or any other maps with it.
-// Res files are not allowed as the reslist itself doesn't need to be included for clients to download.
-// Exe files are not allowed as executable files are useless for a map's purposes and most likely this category would just be
-// including the ges_mapreleaser.exe file if it was used with no parameters and placed in the root directory.
-static DISALLOWED_FILETYPES: &[&'static str] = &["bsp", "res", "exe"];
+// How the path of a directory entry ends, marking it as everything in that directory rather than a single file.
+static DIRECTORY_ENTRY_SUFFIX: &'static str = "/...";
 
 
 /// Generates or checks the reslist used for map asset downloads
 /// Returns Ok() if successful and an error if not.
-pub fn create_or_verify_reslist( args: &Arguments, map_name: &str ) -> Result<(), Error>
+pub fn create_or_verify_reslist( args: &Arguments, map_name: &str ) -> Result<(), GesError>
 {
+    check_mixed_case_directories( args )?;
+    warn_about_source_files( args )?;
+    warn_about_junk_files( args )?;
+    warn_about_stock_overrides( args )?;
+
+    if args.trace
+    {
+        trace_file_decisions( args )?;
+    }
+
     // Reslists go in the maps directory, which must exist for the program to even start.
     let mut relist_path = args.rootdir.clone();
     relist_path.push("maps");
     relist_path.push( map_name );
     relist_path.set_extension("res");
 
-    if !relist_path.is_file()
+    if !relist_path.is_file() && file_writer::is_read_only()
+    {
+        return Err(GesError::MissingFile { message: format!( "No reslist exists at {}, and check-only mode won't create one!", relist_path.display() ), path: relist_path });
+    }
+    else if !relist_path.is_file()
     {
         create_reslist( args, &relist_path )?;
         println!("Created reslist for {}!", map_name);
@@ -53,29 +77,99 @@ pub fn create_or_verify_reslist( args: &Arguments, map_name: &str ) -> Result<()
 }
 
 /// Checks every reslist in the provided or autodetected GE:S directory.
-pub fn fullcheck_reslist_files( args: &Arguments ) -> Result<(), Error>
+pub fn fullcheck_reslist_files( args: &Arguments ) -> Result<(), GesError>
 {
     let mut map_dir = args.gesdir.clone();
     map_dir.push("maps");
 
     if !map_dir.is_dir()
     {
-        return Err(Error::new( ErrorKind::InvalidData, "Maps directory does not exist!  Is this really a valid GE:S install?" ));
+        return Err(GesError::MissingFile { path: map_dir, message: String::from("Maps directory does not exist!  Is this really a valid GE:S install?") });
     }
 
-    shared::check_all_files_in_dir_with_func( args, &map_dir, "res", "reslists", check_reslist )?;
+    report_reslist_orphans( args, &map_dir )?;
+
+    script_encoding::fullcheck_script_encodings( args, &map_dir, "res" )?;
+
+    shared::check_all_files_in_dir_with_func( args, &map_dir, "res", "reslists", check_reslist, validation_cache::no_dependencies )?;
 
     Ok(())
 }
 
+/// Lets server owners know about reslists left behind by maps that have been removed, and maps without a reslist.
+fn report_reslist_orphans( args: &Arguments, map_dir: &PathBuf ) -> Result<(), GesError>
+{
+    let map_names = shared::get_file_stems_in_dir( map_dir, "bsp" )?;
+    let reslist_names = shared::get_file_stems_in_dir( map_dir, "res" )?;
+
+    let orphaned_reslists: Vec<String> = reslist_names.iter()
+                                                      .filter(|x| !map_names.contains(x))
+                                                      .map(|x| format!( "maps/{}.res", x ))
+                                                      .collect();
+
+    // Not every map needs a reslist, but any map with custom content that lacks one won't send that content to clients.
+    let unlisted_maps: Vec<String> = map_names.iter()
+                                              .filter(|x| !reslist_names.contains(x))
+                                              .map(|x| format!( "maps/{}.bsp", x ))
+                                              .collect();
+
+    shared::report_cleanup_list( args, "reslist-orphaned", "reslists are for maps that aren't installed and can be removed", &orphaned_reslists )?;
+    shared::report_cleanup_list( args, "reslist-missing", "maps have no reslist, so clients won't be sent any custom content they use", &unlisted_maps )
+
+}
+
+/// Adds what the reslist section needs before it runs to the preflight report.
+pub fn preflight( args: &Arguments, map_name: &str, report: &mut PreflightReport )
+{
+    if args.fullcheck
+    {
+        let mut map_dir = args.gesdir.clone();
+        map_dir.push("maps");
+
+        report.require( "Reslists", &map_dir, "a maps directory", map_dir.is_dir() );
+
+        // Checking is fine on a read-only install, only fixing has to write.
+        if args.fix && map_dir.is_dir()
+        {
+            report.require( "Reslists", &map_dir, "write access for --fix to rewrite reslists", file_writer::can_create_in(&map_dir) );
+        }
+    }
+    else
+    {
+        // Reslists live next to the map, so both the maps directory and the map itself have to be there, unless
+        // they're about to be copied in from a compile output.
+        let mut map_dir = args.rootdir.clone();
+        map_dir.push("maps");
+
+        let mut map_path = map_dir.clone();
+        map_path.push( map_name );
+        map_path.set_extension("bsp");
+
+        if args.from_compile == None
+        {
+            report.require( "Reslists", &map_dir, "a maps directory", map_dir.is_dir() );
+            report.require( "Reslists", &map_path, "a readable .bsp file", map_path.is_file() );
+        }
+
+        let mut reslist_path = map_path.clone();
+        reslist_path.set_extension("res");
+
+        if !reslist_path.is_file() && !file_writer::is_read_only()
+        {
+            report.require( "Reslists", &map_dir, "write access to create the missing reslist", file_writer::can_create_in(&map_dir) );
+        }
+    }
+}
+
 /// Creates a reslist that includes every file in the local directory.
-fn create_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), Error>
+fn create_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), GesError>
 {
     // Grab every file in the directory so we can make sure the server will download
     // them to clients when the time comes.
     // We don't want to include the map bsp itself however as it will get downloaded regardless.
     // We also don't want to include any reslists or exe files.
-    let &(ref _file_comp_list, ref file_write_list) = generate_directory_tree( args )?;
+    let directory_tree = generate_directory_tree( args )?;
+    let (ref _file_comp_list, ref file_write_list) = *directory_tree;
 
     // This should never happen in normal operation since the other script files should be created or validated
     // before this part of the program is run, and they must exist in the root directory else it would have errored out.
@@ -83,10 +177,78 @@ fn create_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), Erro
     // doesn't hurt to program defensively in this case.  If there are no files to download there's no point in making the reslist!
     if file_write_list.is_empty()
     {
-        println!("[Warning] Root directory seems to be empty!  There are no files to include in the reslist so it will be skipped.");
+        shared::print_warning( "Root directory seems to be empty!  There are no files to include in the reslist so it will be skipped." );
         return Ok(());
     }
 
+    let contents = get_generated_reslist_contents( args, reslist_path, file_write_list );
+
+    // Make it official and write the final string to the file.
+    let mut reslist_file = FileWriter::create(reslist_path)?;
+    reslist_file.write_all( generation_metadata::stamp( args, &contents )?.as_bytes() )?;
+
+    Ok(())
+}
+
+/// Returns the contents of the reslist the program would generate at the given path for the given files.  With
+/// --reslist-dirs, directories named after the map are listed as a whole instead of file by file.
+fn get_generated_reslist_contents( args: &Arguments, reslist_path: &PathBuf, file_write_list: &[String] ) -> String
+{
+    let map_directories = match reslist_path.file_stem()
+    {
+        Some(x) if args.reslist_dirs => get_map_directories( file_write_list, &x.to_string_lossy() ),
+        _ => Vec::new(),
+    };
+
+    get_reslist_contents( file_write_list, &map_directories )
+}
+
+/// Returns every directory named after the map that holds more than one of the given files, which is where a mapper's
+/// own content usually lives.  Only the outermost one on each path counts.
+fn get_map_directories( file_write_list: &[String], map_name: &str ) -> Vec<String>
+{
+    let mut map_directories: Vec<String> = Vec::new();
+    let mut file_counts: HashMap<String, usize> = HashMap::new();
+
+    for file in file_write_list
+    {
+        let components: Vec<&str> = file.split('/').collect();
+
+        // The last component is the file itself, so a file named after the map doesn't count.
+        match components[..components.len() - 1].iter().position(|x| x.eq_ignore_ascii_case( map_name ))
+        {
+            Some(x) =>
+            {
+                let directory = components[..x + 1].join("/");
+
+                if !file_counts.contains_key( &directory )
+                {
+                    map_directories.push( directory.clone() );
+                }
+
+                *file_counts.entry( directory ).or_insert(0) += 1;
+            },
+            None => {},
+        }
+    }
+
+    // A directory entry for a single file doesn't save anything.
+    map_directories.retain(|x| file_counts[x] > 1);
+    map_directories
+}
+
+/// Returns what the path of every file covered by the given directory entry starts with, such as "sound/ge_mymap/" for
+/// "sound/ge_mymap/...".
+fn get_directory_prefix( directory_path: &str ) -> String
+{
+    format!( "{}/", directory_path.trim_end_matches( DIRECTORY_ENTRY_SUFFIX ).trim_end_matches('/') )
+}
+
+/// Returns the contents of a reslist that includes every given file, listing the given directories with a single
+/// directory entry each instead of every file in them.  Entries are grouped by their top level folder under a comment
+/// naming it, so a long reslist can be looked over one folder at a time.
+fn get_reslist_contents( file_write_list: &[String], directories: &[String] ) -> String
+{
     // The reslist has a rather simple format, just stick all included files into it in this format:
     // "[path/to/file]" "file"
     // It's the reverse of the music files...not entirely sure why as I didn't design either but it's not a problem.
@@ -94,53 +256,96 @@ fn create_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), Erro
     contents.push_str("\"resources\"\r\n");
     contents.push_str("{\r\n");
 
+    let directory_prefixes: Vec<String> = directories.iter().map(|x| get_directory_prefix(x)).collect();
+    let mut written_directories: HashSet<&str> = HashSet::new();
+    let mut entries: Vec<(String, String)> = Vec::new();
+
     for file in file_write_list
     {
-        contents.push_str("\t\""); contents.push_str(&file); contents.push_str("\"\t\"file\"\r\n");
+        let entry = match directory_prefixes.iter().find(|x| file.starts_with(x.as_str()))
+        {
+            // The directory goes where its first file would have, and covers the rest of them too.
+            Some(x) if written_directories.insert( x.as_str() ) => format!( "\t\"{}{}\"\t\"dir\"\r\n", &x[..x.len() - 1], DIRECTORY_ENTRY_SUFFIX ),
+            Some(_) => continue,
+            None => format!( "\t\"{}\"\t\"file\"\r\n", file ),
+        };
+
+        entries.push( (get_top_level_folder( file ), entry) );
+    }
+
+    // The sort is stable, so entries keep their order within each folder.  Files outside of any folder go first.
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut current_folder: Option<&str> = None;
+
+    for &(ref folder, ref entry) in &entries
+    {
+        if current_folder != Some(folder.as_str()) && !folder.is_empty()
+        {
+            if current_folder != None
+            {
+                contents.push_str("\r\n");
+            }
+
+            contents.push_str("\t// "); contents.push_str(folder); contents.push_str("\r\n");
+        }
+
+        current_folder = Some(folder.as_str());
+        contents.push_str(entry);
     }
 
     contents.push_str("}\r\n");
 
-    // Make it official and write the final string to the file.
-    let mut reslist_file = fs::File::create(reslist_path)?;
-    reslist_file.write_all(contents.as_bytes())?;
+    contents
+}
 
-    Ok(())
+/// Returns the lowercase name of the folder the given path starts in, or nothing if it isn't in one.
+fn get_top_level_folder( file_path: &str ) -> String
+{
+    match file_path.find('/')
+    {
+        Some(x) => file_path[..x].to_lowercase(),
+        None => String::new(),
+    }
 }
 
 /// Makes sure every file in the local directory tree is included in the provided reslist, that the reslist is
 /// formatted correctly, and that every file in the reslist exists in the local directory path.
-fn check_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), Error>
+pub fn check_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), GesError>
 {
-    let reslist_file = fs::File::open(reslist_path)?;
-    let mut reader = BufReader::new(reslist_file);
+    let directory_tree = generate_directory_tree( args )?;
+    check_reslist_with_tree( args, reslist_path, &directory_tree )
+}
 
-    let mut contents = String::new();
-    reader.read_to_string( &mut contents )?;
+/// Same as check_reslist, but checks against the supplied directory tree instead of the process-wide cache.
+pub fn check_reslist_with_tree( args: &Arguments, reslist_path: &PathBuf, directory_tree: &DirectoryTree ) -> Result<(), GesError>
+{
+    shared::set_current_file( reslist_path );
+
+    let original_contents = match shared::read_script_file( reslist_path )
+    {
+        Err(e @ GesError::Parse { .. }) => return Err(rules::fail( "reslist-syntax", e )),
+        x => x?,
+    };
+
+    check_reslist_contents( args, reslist_path, &original_contents, directory_tree )
+}
+
+/// Same as check_reslist_with_tree, but checks the given contents instead of reading them from the reslist.  The
+/// reslist is still rewritten at the given path if --fix changes anything in it.
+pub fn check_reslist_contents( args: &Arguments, reslist_path: &PathBuf, original_contents: &str, directory_tree: &DirectoryTree ) -> Result<(), GesError>
+{
+    rules::load_suppressions( reslist_path, original_contents );
+    let edit_state = generation_metadata::report_edit_state( args, reslist_path, original_contents )?;
+
+    // Reslists are read as KeyValues, which allows // comments anywhere, so check the reslist without them.
+    let mut contents = shared::blank_out_comments( original_contents );
 
     // Reslist file format is simpler than the music list format and as such is a bit easier to handle.
     // It consists of a "resources" bracketed section with entries using the format:
     // "[path/to/file]" "file"  
     // No other complications or fancy setup to look for.
-    // Using [Rr] instead of the (?i) flag since the (?i) flag seems to increase runtimes significantly,
-    // and people probably don't need to call it "ReSoUrCeS" or something like that.
-    lazy_static! // Using lazy static as reccomended by the Rust documentation for optimization purposes.
-    {
-        static ref FILE_RE: Regex = Regex::new(r#"(?x)^\s*(("[Rr]esources")|([Rr]esources))\s*
-                                (\{
-                                (\s*(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s+(("file")|(file))\s*)+
-                                \})\s*$"#).unwrap();
-    }
-    
-    if !FILE_RE.is_match(&contents)
-    {
-        return Err(Error::new( ErrorKind::InvalidData, "Script contains core format mistake!\n  Make sure every \
-                                                        bracket and quotation mark has a partner, the main section \
-                                                        is labeled \"resources\", each file path has a \"file\"\
-                                                        section after it, no bracketed sections are empty,\
-                                                        and that there are no nested bracketed sections inside\
-                                                        the main bracketed section."));
-    }
+    parse_reslist_entries( &contents ).map_err(|e| rules::fail( "reslist-syntax", e.in_file( reslist_path ) ))?;
 
     // If we made it here it means we have a valid file with at least one file entry.  Check those file entries
     // to make sure they're formatted correctly and point to a valid file that we're including with the map.
@@ -151,54 +356,128 @@ fn check_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), Error
     // scan through it performs alright.
     // We actually want to do a case sensitive compairison here because some fast download servers are linux
     // based and won't download the right files to the client if the case doesn't match.
-    let &( ref file_comp_list, ref file_write_list) = generate_directory_tree( args )?;
+    let &( ref file_comp_list, ref file_write_list) = directory_tree;
 
-    let mut checked_file_list: Vec<String> = Vec::new(); 
+    // Reslists and releases can both run into the thousands of files, so everything is looked up by hash rather than
+    // by going through the whole list for every entry.
+    let file_write_set: HashSet<&str> = file_write_list.iter().map(|x| x.as_str()).collect();
+    let mut file_comp_indices: HashMap<&str, usize> = HashMap::new();
 
-    lazy_static!
+    for (i, file) in file_comp_list.iter().enumerate()
+    {
+        file_comp_indices.entry( file.as_str() ).or_insert( i );
+    }
+
+    let mut checked_file_list: HashSet<String> = HashSet::new();
+
+    // A reslist nobody has touched since we generated it can simply be generated again, which fixes every entry at
+    // once.  Anything else only gets its paths fixed, so whatever was changed by hand stays put.
+    match edit_state
     {
-        static ref RE: Regex = Regex::new(r#"\s*(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s+(("file")|(file))\s*"#).unwrap();
+        EditState::Unedited(_) if args.fix && !args.fullcheck =>
+        {
+            let fixed_contents = get_generated_reslist_contents( args, reslist_path, file_write_list );
+
+            if generation_metadata::read_metadata( original_contents ).map(|x| x.1) != Some(fixed_contents.as_str())
+            {
+                file_writer::write( reslist_path, generation_metadata::stamp( args, &fixed_contents )?.as_bytes() )?;
+                println!( "Regenerated {}, which hadn't been edited since it was generated!", reslist_path.display() );
+
+                contents = shared::blank_out_comments( &fixed_contents );
+            }
+        },
+        _ if args.fix =>
+        {
+            let fixed_contents = fix_reslist_paths( original_contents, directory_tree );
+
+            if fixed_contents != original_contents
+            {
+                file_writer::write( reslist_path, fixed_contents.as_bytes() )?;
+                println!( "Fixed the resource paths in {}!", reslist_path.display() );
+
+                match edit_state
+                {
+                    EditState::HandEdited(_) => println!( "{} has been edited by hand since it was generated, so only its paths were fixed.", reslist_path.display() ),
+                    _ => {},
+                }
+
+                contents = shared::blank_out_comments( &fixed_contents );
+            }
+        },
+        _ => {},
     }
 
-    for cap in RE.captures_iter(&contents)
+    // Fixing the reslist can't break its format, but it does move the entries around.
+    let entries = parse_reslist_entries( &contents ).map_err(|e| rules::fail( "reslist-syntax", e.in_file( reslist_path ) ))?;
+
+    for entry in entries
     {
-        // We've already verified we've got a capture, and slot 1 is mandatory for us to have one.
-        let fixed_path = cap[1].replace("\"", "").replace("\\", "/"); // Remove possible quotation marks and standardize slashes.
+        let fixed_path = entry.path.text.replace("\\", "/"); // Standardize slashes.
+        let quoted_path = shared::get_quotable_text( &fixed_path );
+        let entry_line = entry.path.line;
+
+        if entry.is_directory
+        {
+            check_directory_entry( args, reslist_path, &fixed_path, entry_line, directory_tree, &mut checked_file_list )?;
+            continue;
+        }
 
         // Make sure we're not using a disallowed extension.
-        if DISALLOWED_FILETYPES.contains( &shared::get_string_file_extension( &fixed_path.as_str() ).to_lowercase().as_str() )
+        if file_categories::get_file_category( &fixed_path ).is_disallowed()
         { 
             let mut error_text = String::new();
             error_text.push_str("Resource file ");
-            error_text.push_str(&fixed_path);
+            error_text.push_str(&quoted_path);
             error_text.push_str(" is of a filetype that should not be included in the reslist!  \
-                                  Map files and the reslist itself do not need to be included in the reslist.");
+                                  Map files, the reslist itself, and server only files like python do not need to be included in the reslist.");
 
-            return Err(Error::new(ErrorKind::InvalidData, error_text ));
+            rules::report_in_file( args, "reslist-disallowed-type", reslist_path, entry_line, GesError::PolicyViolation( error_text ) )?;
+        }
+
+        if !args.include_sources && is_source_file( &fixed_path )
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Resource file ");
+            error_text.push_str(&quoted_path);
+            error_text.push_str(" is a map source or compile file that clients don't need!  \
+                                  Remove it from the reslist, or run with --include-sources if you really mean to ship it.");
+
+            rules::report_in_file( args, "reslist-source-file", reslist_path, entry_line, GesError::PolicyViolation( error_text ) )?;
         }
 
         // Check to see if our MP3 file is one of the files we've detected in the relevant directories.
         // if not, our script is pointing to an invalid file and isn't ready for release!
-        if !file_write_list.contains(&fixed_path)
+        if !file_write_set.contains( fixed_path.as_str() )
         {
             let mut error_text = String::new();
             
-            if !file_comp_list.contains(&fixed_path.to_lowercase())
-            {
-                error_text.push_str("Failed to locate resource file ");
-                error_text.push_str(&fixed_path);
-                error_text.push_str("\nEnsure that the file path is valid, and that the file exists.");
-            }
-            else
+            match file_comp_indices.get( fixed_path.to_lowercase().as_str() )
             {
-                error_text.push_str("The case of resource file ");
-                error_text.push_str(&fixed_path);
-                error_text.push_str("\ndoes not match the reslist entry!\n");
-                error_text.push_str("Due to many fast download servers being run on linux,\n");       
-                error_text.push_str("reslists are case-sensitive.");          
-            }
+                None =>
+                {
+                    error_text.push_str("Failed to locate resource file ");
+                    error_text.push_str(&quoted_path);
+                    error_text.push_str("\nEnsure that the file path is valid, and that the file exists.");
+
+                    let mut resource_path = args.rootdir.clone();
+                    resource_path.push(&fixed_path);
+
+                    rules::report_in_file( args, "reslist-missing-file", reslist_path, entry_line, GesError::MissingFile { path: resource_path, message: error_text } )?;
+                },
+                Some(&x) =>
+                {
+                    error_text.push_str("The case of resource file ");
+                    error_text.push_str(&quoted_path);
+                    error_text.push_str("\ndoes not match the reslist entry!\n");
+                    error_text.push_str("Due to many fast download servers being run on linux,\n");       
+                    error_text.push_str("reslists are case-sensitive.");          
+
+                    rules::report_in_file( args, "reslist-case-mismatch", reslist_path, entry_line, GesError::PolicyViolation( error_text ) )?;
 
-            return Err(Error::new(ErrorKind::InvalidData, error_text ));
+                    // The entry still refers to the file, so don't also complain that the file is missing from the reslist.
+                    checked_file_list.insert( file_write_list[x].clone() );
+                },
+            }
         }
         else // It's a valid file, but might be repeated.
         {
@@ -207,14 +486,14 @@ fn check_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), Error
             {
                 let mut error_text = String::new();
                 error_text.push_str("Resource file ");
-                error_text.push_str(&fixed_path);
+                error_text.push_str(&quoted_path);
                 error_text.push_str(" is referenced multiple times!  Please remove the redundant references.");
 
-                return Err(Error::new(ErrorKind::InvalidData, error_text ));
+                rules::report( args, "reslist-duplicate-entry", GesError::Parse { file: reslist_path.clone(), line: entry_line, message: error_text } )?;
             }
 
             // Now that we've checked it, push the path to our checked array so we'll catch it if it comes up again.
-            checked_file_list.push(fixed_path.clone());
+            checked_file_list.insert(fixed_path.clone());
         }
     }
 
@@ -235,7 +514,7 @@ fn check_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), Error
     for file in file_write_list
     {
         // If we never checked it, it wasn't in the reslist.
-        if !checked_file_list.contains(&file)
+        if !checked_file_list.contains(file)
         {
             missing_file_list.push(&file);
         }
@@ -254,7 +533,7 @@ fn check_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), Error
 
         error_text.push_str(" aren't included in the reslist!  Be sure to include entries for them or remove them from the destribution folder.");
 
-        return Err(Error::new(ErrorKind::InvalidData, error_text ));
+        rules::report_in_file( args, "reslist-incomplete", reslist_path, 0, GesError::PolicyViolation( error_text ) )?;
     }
 
     // The reslist is in the correct format, all of our files are included, and no others.
@@ -263,34 +542,569 @@ fn check_reslist( args: &Arguments, reslist_path: &PathBuf ) -> Result<(), Error
 }
 
 
-use std::sync::Mutex;
+/// Checks that a directory entry of a reslist covers files that ship with the release, marking every one of them as
+/// included.
+fn check_directory_entry( args: &Arguments, reslist_path: &PathBuf, directory_path: &str, entry_line: usize, directory_tree: &DirectoryTree,
+                          checked_file_list: &mut HashSet<String> ) -> Result<(), GesError>
+{
+    let &( ref file_comp_list, ref file_write_list) = directory_tree;
+
+    let directory_prefix = get_directory_prefix( directory_path );
+    let quoted_path = shared::get_quotable_text( directory_path );
+
+    let covered_files: Vec<&String> = file_write_list.iter().filter(|x| x.starts_with( directory_prefix.as_str() )).collect();
+
+    if covered_files.is_empty()
+    {
+        let lowercase_prefix = directory_prefix.to_lowercase();
+        let case_insensitive_files: Vec<&String> = file_comp_list.iter()
+                                                                 .zip( file_write_list.iter() )
+                                                                 .filter(|x| x.0.starts_with( lowercase_prefix.as_str() ))
+                                                                 .map(|x| x.1)
+                                                                 .collect();
+
+        let mut error_text = String::new();
+
+        if case_insensitive_files.is_empty()
+        {
+            error_text.push_str("Failed to locate any files in resource directory ");
+            error_text.push_str(&quoted_path);
+            error_text.push_str("\nEnsure that the directory path is valid, and that it holds files that ship with the map.");
+
+            let mut resource_path = args.rootdir.clone();
+            resource_path.push(&directory_prefix);
+
+            return rules::report_in_file( args, "reslist-missing-file", reslist_path, entry_line, GesError::MissingFile { path: resource_path, message: error_text } );
+        }
+
+        error_text.push_str("The case of resource directory ");
+        error_text.push_str(&quoted_path);
+        error_text.push_str("\ndoes not match the reslist entry!\n");
+        error_text.push_str("Due to many fast download servers being run on linux,\n");
+        error_text.push_str("reslists are case-sensitive.");
+
+        rules::report_in_file( args, "reslist-case-mismatch", reslist_path, entry_line, GesError::PolicyViolation( error_text ) )?;
 
-/// Provides a reference to a vector storing strings that correspond to the relative paths of every file in
-/// the provided directory.  Subsequent calls return the cached value of the first call.
-pub fn generate_directory_tree( args: &Arguments ) -> Result<&'static (Vec<String>, Vec<String>), Error>
+        // The entry still refers to these files, so don't also complain that they're missing from the reslist.
+        checked_file_list.extend( case_insensitive_files.into_iter().cloned() );
+        return Ok(());
+    }
+
+    for file in covered_files
+    {
+        if !checked_file_list.insert( file.clone() )
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Resource file ");
+            error_text.push_str( &shared::get_quotable_text( file ) );
+            error_text.push_str(" is referenced multiple times, including by resource directory ");
+            error_text.push_str(&quoted_path);
+            error_text.push_str("!  Please remove the redundant references.");
+
+            rules::report( args, "reslist-duplicate-entry", GesError::Parse { file: reslist_path.clone(), line: entry_line, message: error_text } )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the path of every entry in the given reslist, with its slashes standardized.  Directory entries are replaced
+/// by every file in that directory of the install the reslist is in.
+pub fn read_reslist_entries( reslist_path: &PathBuf ) -> Result<Vec<String>, GesError>
 {
-    lazy_static!
+    let contents = shared::blank_out_comments( &shared::read_script_file( reslist_path )? );
+
+    let mut tokenizer = Tokenizer::new( &contents );
+    let mut entries = Vec::new();
+    let mut previous_token: Option<Token> = None;
+
+    // A reslist GE:S can't read still names the files it was meant to send, so every entry up to where it goes wrong counts.
+    while let Ok(Some(token)) = tokenizer.next_token()
     {
-        static ref DIRLIST_INIT_STATE: Mutex<bool> = Mutex::new(false);
+        match previous_token
+        {
+            Some(ref x) if x.kind == TokenKind::Text && token.is_text("dir") && token.spaced =>
+            {
+                entries.extend( get_directory_entry_files( reslist_path, &x.text.replace("\\", "/") )? );
+                previous_token = None;
+                continue;
+            },
+            Some(ref x) if x.kind == TokenKind::Text && token.is_text("file") && token.spaced =>
+            {
+                entries.push( x.text.replace("\\", "/") );
+                previous_token = None;
+                continue;
+            },
+            _ => {},
+        }
+
+        previous_token = Some(token);
     }
 
-    static mut DIRLIST: Option<(Vec<String>, Vec<String>)> = None;
+    Ok(entries)
+}
 
-    // Unsafe because the alternative is more convoluted to use, the possibility of a data race is almost 0,
-    // and the negative outcome of one would be a performance penalty and nothing else.
-    unsafe
+/// Returns the path of every file in the given directory entry of the given reslist, relative to the install the reslist
+/// is in.
+fn get_directory_entry_files( reslist_path: &PathBuf, directory_path: &str ) -> Result<Vec<String>, GesError>
+{
+    let directory_prefix = get_directory_prefix( directory_path );
+
+    // Reslists live in the maps directory, so the install is the directory above it.
+    let mut directory = match reslist_path.parent().and_then(|x| x.parent())
     {
-        return shared::compute_or_get_safe_reference_to_directory_cache( vec![&args.rootdir], "", DISALLOWED_FILETYPES, &DIRLIST_INIT_STATE, &mut DIRLIST );
+        Some(x) => x.to_path_buf(),
+        None => return Ok(Vec::new()),
+    };
+    directory.push( &directory_prefix );
+
+    let (_file_comp_list, file_write_list) = shared::get_files_in_directory( &directory, "", &[] )?;
+
+    Ok(file_write_list.iter().map(|x| format!( "{}{}", directory_prefix, x )).collect())
+}
+
+/// An entry of a reslist, as read by parse_reslist_entries.
+struct ReslistEntry<'a>
+{
+    /// The token holding the path of the file or directory.
+    path: Token<'a>,
+    /// True for a "dir" entry, which covers every file in the directory.
+    is_directory: bool,
+}
+
+/// Reads the entries of a reslist the way GE:S does, along with any directory entries.  Comments have to be blanked
+/// out already.
+fn parse_reslist_entries<'a>( contents: &'a str ) -> Result<Vec<ReslistEntry<'a>>, TokenError>
+{
+    let mut tokenizer = Tokenizer::new( contents );
+
+    // People probably don't need to call it "ReSoUrCeS" or something like that.
+    let resources_token = tokenizer.expect_token( "\"resources\" to start the reslist" )?;
+    if !resources_token.is_text("resources") && !resources_token.is_text("Resources")
+    {
+        return Err(TokenError::unexpected( &resources_token, "\"resources\" to start the reslist" ));
+    }
+
+    let open_token = tokenizer.expect_token( "{ to open the resources section" )?;
+    if open_token.kind != TokenKind::Open
+    {
+        return Err(TokenError::unexpected( &open_token, "{ to open the resources section" ));
+    }
+
+    let mut entries = Vec::new();
+
+    loop
+    {
+        let path_token = tokenizer.expect_token( "} to close the resources section" )?;
+
+        match path_token.kind
+        {
+            TokenKind::Close if entries.is_empty() => return Err(TokenError::new( open_token.line, String::from("The resources section doesn't have any files in it!") )),
+            TokenKind::Close => break,
+            TokenKind::Open => return Err(TokenError::new( path_token.line, String::from("Found { inside of the resources section!  Reslists can't have sections inside of them.") )),
+            TokenKind::Text => {},
+        }
+
+        let file_token = tokenizer.expect_token( "\"file\" or \"dir\" after the path of the resource" )?;
+        if !file_token.is_text("file") && !file_token.is_text("dir")
+        {
+            return Err(TokenError::unexpected( &file_token, "\"file\" or \"dir\" after the path of the resource" ));
+        }
+
+        // GE:S needs something between the two to tell where one ends and the other starts.
+        if !file_token.spaced
+        {
+            let mut error_text = String::new();
+            error_text.push_str("Expected whitespace between the path of the resource and \"");
+            error_text.push_str(file_token.text);
+            error_text.push_str("\"!");
+
+            return Err(TokenError::new( file_token.line, error_text ));
+        }
+
+        entries.push( ReslistEntry { path: path_token, is_directory: file_token.is_text("dir") } );
     }
+
+    tokenizer.expect_end( "resources" )?;
+
+    Ok(entries)
+}
+
+/// Returns the reslist contents with every resource path written the way the reslist expects it to be: forward slashes,
+/// no leading "./" or doubled up slashes, and the same case as the file it points to.  Paths to files that don't exist
+/// are lowercased, since that's the convention for everything GE:S ships.
+fn fix_reslist_paths( contents: &str, directory_tree: &DirectoryTree ) -> String
+{
+    let &( ref file_comp_list, ref file_write_list) = directory_tree;
+
+    let mut fixed_contents = String::new();
+    let mut position = 0;
+
+    // Paths in comments aren't entries, so look for them in a copy without comments.  Everything is in the same place.
+    let uncommented_contents = shared::blank_out_comments( contents );
+
+    // A reslist that can't be read has no entries we can be sure of, so it's left for the user to fix.
+    let entries = match parse_reslist_entries( &uncommented_contents )
+    {
+        Ok(x) => x,
+        Err(_) => return String::from(contents),
+    };
+
+    for entry in entries
+    {
+        let fixed_path = normalize_resource_path( entry.path.text );
+
+        let fixed_path = if entry.is_directory
+        {
+            // A directory has the case of the files in it, and always ends the same way.
+            let directory_prefix = get_directory_prefix( &fixed_path ).to_lowercase();
+            let directory_length = directory_prefix.chars().count() - 1;
+
+            match file_comp_list.iter().position(|x| x.starts_with( directory_prefix.as_str() ))
+            {
+                Some(x) => format!( "{}{}", file_write_list[x].chars().take( directory_length ).collect::<String>(), DIRECTORY_ENTRY_SUFFIX ),
+                None => format!( "{}{}", &directory_prefix[..directory_prefix.len() - 1], DIRECTORY_ENTRY_SUFFIX ),
+            }
+        }
+        else
+        {
+            match file_comp_list.iter().position(|x| x == &fixed_path.to_lowercase())
+            {
+                Some(x) => file_write_list[x].clone(),
+                None => fixed_path.to_lowercase(),
+            }
+        };
+
+        // Keep the quotes the way they were so only the paths themselves change.
+        let quote = if entry.path.quoted { "\"" } else { "" };
+
+        fixed_contents.push_str( &contents[position..entry.path.start] );
+        fixed_contents.push_str( quote );
+        fixed_contents.push_str( &fixed_path );
+        fixed_contents.push_str( quote );
+        position = entry.path.end;
+    }
+
+    fixed_contents.push_str( &contents[position..] );
+    fixed_contents
+}
+
+/// Standardizes the slashes in a resource path, removing any leading "./" and collapsing doubled up slashes.
+fn normalize_resource_path( resource_path: &str ) -> String
+{
+    let mut fixed_path = resource_path.trim().replace("\\", "/");
+
+    while fixed_path.contains("//")
+    {
+        fixed_path = fixed_path.replace("//", "/");
+    }
+
+    while let Some(x) = fixed_path.strip_prefix("./")
+    {
+        fixed_path = String::from(x);
+    }
+
+    fixed_path
+}
+
+/// Fails on any directories in the root directory whose names only differ by case.  Windows merges them into one, but
+/// Linux servers keep them apart, so everything outside the one the game looks in goes missing.
+fn check_mixed_case_directories( args: &Arguments ) -> Result<(), GesError>
+{
+    let directory_tree = shared::get_cached_directory_tree( &[&args.rootdir], "", &[], shared::keep_all_files )?;
+    let (ref _file_comp_list, ref file_write_list) = *directory_tree;
+
+    let mixed_case_directories = find_mixed_case_directories( file_write_list );
+
+    if mixed_case_directories.is_empty()
+    {
+        return Ok(());
+    }
+
+    let mut error_text = String::new();
+    error_text.push_str("The root directory contains directories whose names only differ by case:\n");
+
+    for directories in &mixed_case_directories
+    {
+        error_text.push_str("\t");
+        error_text.push_str(&directories.join(", "));
+        error_text.push_str("\n");
+    }
+
+    error_text.push_str("Windows treats each of these as one directory, but Linux servers don't, so files in all but one of them won't be found.  \
+                         Move their files into a single directory with one name.");
+
+    rules::report_message( args, "reslist-mixed-case-directories", error_text )
+}
+
+/// Returns every set of directories among the given relative file paths whose names only differ by case.  Only the
+/// outermost directories are returned, since everything inside them differs the same way.
+fn find_mixed_case_directories( file_paths: &[String] ) -> Vec<Vec<String>>
+{
+    // Sorted so the same tree always gets the same report.
+    let mut directory_variants: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for file_path in file_paths
+    {
+        let components: Vec<&str> = file_path.split('/').collect();
+
+        for depth in 1..components.len()
+        {
+            let directory = components[..depth].join("/");
+            let variants = directory_variants.entry( directory.to_lowercase() ).or_default();
+
+            if !variants.contains(&directory)
+            {
+                variants.push(directory);
+            }
+        }
+    }
+
+    directory_variants.iter()
+                      .filter(|&(lowercase_directory, variants)|
+                      {
+                          let has_mixed_case_parent = match lowercase_directory.rfind('/')
+                          {
+                              Some(x) => directory_variants[&lowercase_directory[..x]].len() > 1,
+                              None => false,
+                          };
+
+                          variants.len() > 1 && !has_mixed_case_parent
+                      })
+                      .map(|x| x.1.clone())
+                      .collect()
+}
+
+/// Lets the user know about any map sources or compile files in the root directory, since shipping them is usually a mistake.
+fn warn_about_source_files( args: &Arguments ) -> Result<(), GesError>
+{
+    let directory_tree = shared::get_cached_directory_tree( &[&args.rootdir], "", &[], shared::keep_all_files )?;
+    let (ref _file_comp_list, ref file_write_list) = *directory_tree;
+
+    let source_files: Vec<&String> = file_write_list.iter().filter(|x| is_source_file(x)).collect();
+
+    if source_files.is_empty()
+    {
+        return Ok(());
+    }
+
+    let mut warning_text = String::new();
+    warning_text.push_str("The root directory contains map source or compile files:\n");
+
+    for file in source_files
+    {
+        warning_text.push_str("\t");
+        warning_text.push_str(file);
+        warning_text.push_str("\n");
+    }
+
+    if args.include_sources
+    {
+        warning_text.push_str("These will be included in the reslist and compressed files since --include-sources is set.");
+    }
+    else
+    {
+        warning_text.push_str("These will be left out of the reslist and compressed files.  Remove them from the release, \
+                               or run with --include-sources if you really mean to ship them.");
+    }
+
+    rules::report_message( args, "reslist-source-files-present", warning_text )
+}
+
+/// Lets the user know about any files in the release at the same path as stock GE:S content, which replace that content
+/// for every map on the server once installed.  Only the stock content database is trusted for this, since a mapper's
+/// GE:S install usually holds their own files too from testing the map.
+fn warn_about_stock_overrides( args: &Arguments ) -> Result<(), GesError>
+{
+    if !rules::is_enabled( args, "reslist-overrides-stock" )
+    {
+        return Ok(());
+    }
+
+    let directory_tree = generate_directory_tree( args )?;
+
+    let stock_overrides: Vec<String> = directory_tree.1.iter()
+                                                       .filter(|x| stock_content::get_stock_file(x) != None)
+                                                       .cloned()
+                                                       .collect();
+
+    shared::report_cleanup_list( args, "reslist-overrides-stock", "files have the same path as stock GE:S content, and will replace it for every map once installed", &stock_overrides )
+}
+
+/// Lets the user know about any empty or temporary files in the root directory, which are left out of the release.
+fn warn_about_junk_files( args: &Arguments ) -> Result<(), GesError>
+{
+    let directory_tree = shared::get_cached_directory_tree( &[&args.rootdir], "", &[], is_junk_file )?;
+    let (ref _file_comp_list, ref file_write_list) = *directory_tree;
+
+    if file_write_list.is_empty()
+    {
+        return Ok(());
+    }
+
+    let mut warning_text = String::new();
+    warning_text.push_str("The root directory contains empty or temporary files:\n");
+
+    for file in file_write_list
+    {
+        warning_text.push_str("\t");
+        warning_text.push_str(file);
+        warning_text.push_str("\n");
+    }
+
+    warning_text.push_str("These will be left out of the reslist and compressed files, and can most likely be deleted.");
+
+    rules::report_message( args, "reslist-junk-files-present", warning_text )
+}
+
+/// Prints whether each file in the root directory goes out with the release and why.  The reslist and compression
+/// both use the same list of files, so one trace covers them both.
+fn trace_file_decisions( args: &Arguments ) -> Result<(), GesError>
+{
+    // Tracing is for tracking down a single file, so it walks the directory itself rather than trusting the cache.
+    let ((_file_comp_list, file_write_list), file_info) = shared::get_files_and_info_in_directory( &args.rootdir, "", &[] )?;
+
+    for (file, info) in file_write_list.iter().zip( file_info.iter() )
+    {
+        shared::print_trace( args, &get_file_decision( args, file, info ) );
+    }
+
+    Ok(())
+}
+
+/// Returns whether the file at the given relative path goes out with the release, along with why.  The checks are
+/// made in the same order generate_directory_tree makes them.
+fn get_file_decision( args: &Arguments, file: &str, file_info: &FileInfo ) -> String
+{
+    let extension = shared::get_file_extension( Path::new(file) ).to_lowercase();
+    let category = file_categories::get_category( &extension );
+
+    if get_excluded_filetypes( args ).contains( &extension )
+    {
+        if category == FileCategory::Source
+        {
+            return format!( "{} left out, .{} files are map sources.  Run with --include-sources to ship them.", file, extension );
+        }
+
+        if category == FileCategory::Map
+        {
+            return format!( "{} left out of the reslist, clients download maps on their own.  Only the release's own map is compressed.", file );
+        }
+
+        if category == FileCategory::Server
+        {
+            return format!( "{} left out of the reslist and compressed files, .{} files are only used by servers.  It still goes in the server package.", file, extension );
+        }
+
+        return format!( "{} left out, .{} files are {} files, which never go in the reslist or compressed files.", file, extension, category );
+    }
+
+    match get_junk_reason( Path::new(file), file_info )
+    {
+        Some(x) => format!( "{} left out, {}.", file, x ),
+        None if category == FileCategory::Source => format!( "{} included as a source file, since --include-sources is set.", file ),
+        None => format!( "{} included as a {} file.", file, category ),
+    }
+}
+
+/// Checks if the file at the given path should go out with the release.
+pub fn is_release_file( file_path: &Path, file_info: &FileInfo ) -> bool
+{
+    !is_junk_file( file_path, file_info )
+}
+
+/// Checks if the file at the given path is empty, or is something the OS or an editor left behind like Thumbs.db or a
+/// backup~ file.
+fn is_junk_file( file_path: &Path, file_info: &FileInfo ) -> bool
+{
+    get_junk_reason( file_path, file_info ) != None
+}
+
+/// Returns what makes the file at the given path junk, or None if it isn't.
+fn get_junk_reason( file_path: &Path, file_info: &FileInfo ) -> Option<String>
+{
+    let file_name = match file_path.file_name()
+    {
+        Some(x) => x.to_string_lossy().to_lowercase(),
+        None => return None,
+    };
+
+    if JUNK_FILENAMES.contains( &file_name.as_str() )
+    {
+        Some(format!( "{} files are left behind by the OS", file_name ))
+    }
+    else if file_name.ends_with('~')
+    {
+        Some(String::from("names ending in ~ are editor backups"))
+    }
+    else if file_name.ends_with(".tmp")
+    {
+        Some(String::from("names ending in .tmp are temporary files"))
+    }
+    else if file_info.size == 0
+    {
+        Some(String::from("the file is empty"))
+    }
+    else
+    {
+        None
+    }
+}
+
+/// Checks if the given relative path looks like a map source, editor autosave, or compile byproduct.  This goes by
+/// extension alone, the same as get_excluded_filetypes, so every file we warn about is one the release leaves out.
+fn is_source_file( file_path: &str ) -> bool
+{
+    // Hammer's autosaves are .vmf_autosave files, and Hammer++ saves plain .vmf files, so both are covered here.
+    file_categories::get_file_category( file_path ) == FileCategory::Source
+}
+
+/// Returns the file types that shouldn't be included in the reslist for the given arguments.  Map sources almost always
+/// end up in a release tree by accident, so unless the user asks for them they're left out just like the disallowed types.
+pub fn get_excluded_filetypes( args: &Arguments ) -> Vec<String>
+{
+    let excluded_categories: Vec<FileCategory> = file_categories::CATEGORIES.iter()
+                                                                            .filter(|x| x.is_disallowed() || (!args.include_sources && **x == FileCategory::Source))
+                                                                            .cloned()
+                                                                            .collect();
+
+    file_categories::get_extensions( &excluded_categories )
+}
+
+/// Returns the relative path of every file in the root directory that belongs in the release.  The tree is only walked
+/// once, until something in it changes.
+pub fn generate_directory_tree( args: &Arguments ) -> Result<Arc<DirectoryTree>, GesError>
+{
+    let excluded_filetypes = get_excluded_filetypes( args );
+    let excluded_filetypes: Vec<&str> = excluded_filetypes.iter().map(|x| x.as_str()).collect();
+
+    shared::get_cached_directory_tree( &[&args.rootdir], "", &excluded_filetypes, is_release_file )
+}
+
+/// Returns the relative path of every server only file in the root directory that belongs in the release.  These are
+/// left out of the directory tree, since clients never get them.
+pub fn get_server_files( args: &Arguments ) -> Result<Vec<String>, GesError>
+{
+    let ((_file_comp_list, file_write_list), file_info) = shared::get_files_and_info_in_directory( &args.rootdir, "", &[] )?;
+
+    Ok(file_write_list.into_iter()
+                      .zip( file_info.iter() )
+                      .filter(|x| file_categories::get_file_category( &x.0 ) == FileCategory::Server && is_release_file( Path::new(&x.0), x.1 ))
+                      .map(|x| x.0)
+                      .collect())
 }
 
 #[cfg(test)]
 mod tests 
 {
+    use std::fs;
     use shared::get_barebones_args;
     use shared::get_root_test_directory;
     use shared::do_validity_test;
     use shared::test_script_creator;
+    use keyvalues;
+    use std::time::Instant;
+    use regex::Regex;
     use super::*;
 
     #[test]
@@ -323,4 +1137,264 @@ mod tests
         // Now that we've confirmed the script checker works...let's create a file and use it to check it!
         test_script_creator( &get_barebones_args(), "test_map.res", create_reslist, check_reslist );
     }
+
+    #[test]
+    fn test_fix_regenerates_unedited_reslist()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("reslist_regenerate");
+
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&test_dir).unwrap();
+
+        let reslist_path = test_dir.join("test_map_regenerate.res");
+        let directory_tree = (vec![String::from("materials/test/wall.vtf"), String::from("sound/music/song.mp3")],
+                              vec![String::from("materials/Test/Wall.vtf"), String::from("sound/music/song.mp3")]);
+
+        let mut args = get_barebones_args();
+        args.fix = true;
+
+        // A generated reslist that's fallen behind the files is simply generated again.
+        let outdated_contents = generation_metadata::stamp( &args, &get_reslist_contents( &directory_tree.1[..1], &[] ) ).unwrap();
+        fs::write( &reslist_path, &outdated_contents ).unwrap();
+
+        assert!( check_reslist_with_tree( &args, &reslist_path, &directory_tree ).is_ok() );
+
+        let fixed_contents = fs::read_to_string(&reslist_path).unwrap();
+        assert!( fixed_contents.contains("sound/music/song.mp3") );
+        let metadata = generation_metadata::read_metadata( &fixed_contents ).unwrap().0;
+        assert_eq!( generation_metadata::get_edit_state( &fixed_contents ).unwrap(), EditState::Unedited(metadata) );
+
+        // One that's been edited by hand only gets its paths fixed, so the missing file is still reported.
+        let edited_contents = outdated_contents.replace("materials/Test/Wall.vtf", "materials/test/wall.vtf");
+        fs::write( &reslist_path, &edited_contents ).unwrap();
+
+        assert!( check_reslist_with_tree( &args, &reslist_path, &directory_tree ).is_err() );
+        assert_eq!( fs::read_to_string(&reslist_path).unwrap(), outdated_contents );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_source_file()
+    {
+        assert!( is_source_file("maps/test_map.vmf") );
+        assert!( is_source_file("maps/test_map.VMX") );
+        assert!( is_source_file("maps/test_map.log") );
+        assert!( is_source_file("maps/autosave/test_map_autosave_3.vmf") );
+        assert!( is_source_file("maps/autosave/test_map.vmf_autosave") );
+        assert!( !is_source_file("sound/test/autosave_reminder.wav") );
+
+        // Anything we warn about as a source file has to be one the release leaves out.
+        assert!( get_excluded_filetypes( &get_barebones_args() ).contains( &String::from("vmf_autosave") ) );
+        assert!( !is_source_file("maps/test_map.bsp") );
+        assert!( !is_source_file("materials/test/wall.vtf") );
+    }
+
+    #[test]
+    fn test_find_mixed_case_directories()
+    {
+        let file_paths = vec![String::from("Sound/music/theme.mp3"), String::from("sound/music/credits.mp3"), String::from("sound/ambient.wav"),
+                              String::from("materials/test/wall.vtf"), String::from("materials/Test/floor.vtf"), String::from("maps/test_map.bsp")];
+
+        // Sound/music and sound/music are left out, since they only differ because their parents do.
+        assert_eq!( find_mixed_case_directories( &file_paths ), vec![vec![String::from("materials/test"), String::from("materials/Test")],
+                                                                   vec![String::from("Sound"), String::from("sound")]] );
+
+        assert!( find_mixed_case_directories( &file_paths[2..4] ).is_empty() );
+    }
+
+    #[test]
+    fn test_is_junk_file()
+    {
+        let file_info = FileInfo { size: 10, modified: None };
+
+        assert!( is_junk_file( Path::new("materials/test/Thumbs.db"), &file_info ) );
+        assert!( is_junk_file( Path::new("materials/.DS_Store"), &file_info ) );
+        assert!( is_junk_file( Path::new("scripts/maps/test_map.txt~"), &file_info ) );
+        assert!( is_junk_file( Path::new("scripts/maps/test_map.TMP"), &file_info ) );
+        assert!( !is_junk_file( Path::new("materials/test/wall.vtf"), &file_info ) );
+
+        // Anything empty is junk too, since no working asset is zero bytes.
+        assert!( is_junk_file( Path::new("maps/some_other_map.bsp"), &FileInfo { size: 0, modified: None } ) );
+    }
+
+    #[test]
+    fn test_get_file_decision()
+    {
+        let mut args = get_barebones_args();
+        let file_info = FileInfo { size: 10, modified: None };
+
+        assert_eq!( get_file_decision( &args, "materials/test/wall.vtf", &file_info ), "materials/test/wall.vtf included as a material file." );
+        assert!( get_file_decision( &args, "maps/test_map.bsp", &file_info ).contains("clients download maps on their own") );
+        assert!( get_file_decision( &args, "ges_scriptutility.exe", &file_info ).contains("are executable files, which never go in the reslist") );
+        assert!( get_file_decision( &args, "maps/test_map.vmf", &file_info ).contains("--include-sources") );
+        assert_eq!( get_file_decision( &args, "materials/test/empty.vtf", &FileInfo { size: 0, modified: None } ), "materials/test/empty.vtf left out, the file is empty." );
+
+        assert_eq!( get_file_decision( &args, "materials/Thumbs.db", &file_info ), "materials/Thumbs.db left out, thumbs.db files are left behind by the OS." );
+
+        args.include_sources = true;
+        assert!( get_file_decision( &args, "maps/test_map.vmf", &file_info ).contains("included as a source file") );
+    }
+
+    // The regular expressions reslists used to be checked with.  The tokenizer has to agree with them on which
+    // reslists can be read and which entries they have.
+    lazy_static!
+    {
+        static ref FILE_RE: Regex = Regex::new(r#"(?x)^\s*(("[Rr]esources")|([Rr]esources))\s*
+                                (\{
+                                (\s*(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s+(("file")|(file))\s*)+
+                                \})\s*$"#).unwrap();
+        static ref ENTRY_RE: Regex = Regex::new(r#"\s*(("[^"\{\}]*")|([\S&&[^"\{\}]]+))\s+(("file")|(file))\s*"#).unwrap();
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_reslist_parsing()
+    {
+        // Run with cargo test --release -- --ignored --nocapture to compare the tokenizer with the old regexes.
+        let contents = format!( "\"resources\"\r\n{{\r\n{}}}\r\n", (0..50000).map(|x| format!( "\t\"materials/ge_bench/texture{}.vtf\"\t\"file\"\r\n", x )).collect::<String>() );
+
+        let start = Instant::now();
+        let entries = parse_reslist_entries( &contents ).unwrap();
+        let tokenizer_time = start.elapsed();
+
+        let start = Instant::now();
+        assert!( FILE_RE.is_match(&contents) );
+        let entry_count = ENTRY_RE.captures_iter(&contents).count();
+        let regex_time = start.elapsed();
+
+        assert_eq!( entries.len(), entry_count );
+        println!( "{} entries: tokenizer {:?}, regexes {:?}", entry_count, tokenizer_time, regex_time );
+    }
+
+    #[test]
+    fn test_regex_oracle()
+    {
+        let mut reslists = Vec::new();
+
+        for directory in &["valid", "invalid"]
+        {
+            let mut reslist_dir = get_root_test_directory();
+            reslist_dir.push("reslist_tests");
+            reslist_dir.push(directory);
+
+            for entry in fs::read_dir(&reslist_dir).unwrap()
+            {
+                reslists.push( fs::read_to_string( entry.unwrap().path() ).unwrap() );
+            }
+        }
+
+        // Directory entries came after the regexes, which never knew about them.
+        reslists.retain(|x| !x.contains("\"dir\""));
+
+        let tokens = ["resources", "{", "materials/a.vtf", "file", "\"sound/b c.wav\"", "\"file\"", "models/c.mdl", "file", "}"];
+        let replacements = ["Resources", "\"resources\"", "RESOURCES", "{", "}", "file", "\"file\"", "FILE", "a.vtf", "\"\"", "\"open"];
+
+        for seed in 0..5000
+        {
+            reslists.push( keyvalues::get_mutated_script( &tokens, &replacements, seed ) );
+        }
+
+        let mut readable_count = 0;
+
+        for reslist in &reslists
+        {
+            let contents = shared::blank_out_comments( reslist );
+            let entries = parse_reslist_entries( &contents );
+
+            assert_eq!( entries.is_ok(), FILE_RE.is_match(&contents), "{}", reslist );
+
+            match entries
+            {
+                Ok(x) =>
+                {
+                    let expected_paths: Vec<String> = ENTRY_RE.captures_iter(&contents).map(|y| y[1].replace("\"", "")).collect();
+                    assert_eq!( x.iter().map(|y| String::from(y.path.text)).collect::<Vec<String>>(), expected_paths, "{}", reslist );
+
+                    readable_count += 1;
+                },
+                Err(_) => {},
+            }
+        }
+
+        // Plenty of the mutated reslists should land on each side.
+        assert!( readable_count > 500 && reslists.len() - readable_count > 500 );
+    }
+
+    #[test]
+    fn test_reslist_grouping()
+    {
+        let file_list = vec![String::from("sound/music/song.mp3"), String::from("materials/test/wall.vtf"), String::from("readme.txt"),
+                             String::from("Sound/ambient.wav"), String::from("materials/test/floor.vtf")];
+
+        assert_eq!( get_reslist_contents( &file_list, &[] ),
+                    "\"resources\"\r\n{\r\n\t\"readme.txt\"\t\"file\"\r\n\
+                     \r\n\t// materials\r\n\t\"materials/test/wall.vtf\"\t\"file\"\r\n\t\"materials/test/floor.vtf\"\t\"file\"\r\n\
+                     \r\n\t// sound\r\n\t\"sound/music/song.mp3\"\t\"file\"\r\n\t\"Sound/ambient.wav\"\t\"file\"\r\n}\r\n" );
+
+        // The headers are only comments, so the reslist reads the same as one without them.
+        let contents = get_reslist_contents( &file_list[..2], &[] );
+        let directory_tree = (file_list[..2].to_vec(), file_list[..2].to_vec());
+        let args = get_barebones_args();
+
+        assert_eq!( parse_reslist_entries( &shared::blank_out_comments( &contents ) ).unwrap().len(), 2 );
+        assert!( check_reslist_contents( &args, &args.rootdir.join("maps/ge_grouped.res"), &contents, &directory_tree ).is_ok() );
+    }
+
+    #[test]
+    fn test_directory_entries()
+    {
+        let file_list = vec![String::from("materials/ge_dirmap/wall.vtf"), String::from("materials/ge_dirmap/floor.vtf"),
+                             String::from("models/ge_dirmap/crate.mdl"), String::from("sound/ge_dirmap.wav"), String::from("sound/music/song.mp3")];
+
+        // Only directories with more than one file in them are worth an entry, and a file named after the map isn't one.
+        assert_eq!( get_map_directories( &file_list, "ge_dirmap" ), vec![String::from("materials/ge_dirmap")] );
+        assert_eq!( get_map_directories( &file_list, "GE_DirMap" ), vec![String::from("materials/ge_dirmap")] );
+
+        let contents = get_reslist_contents( &file_list, &get_map_directories( &file_list, "ge_dirmap" ) );
+        assert_eq!( contents.matches("\"dir\"").count(), 1 );
+        assert!( contents.contains("\t\"materials/ge_dirmap/...\"\t\"dir\"\r\n") && !contents.contains("wall.vtf") );
+        assert!( contents.contains("\t\"models/ge_dirmap/crate.mdl\"\t\"file\"\r\n") );
+
+        let uncommented_contents = shared::blank_out_comments( &contents );
+        let entries = parse_reslist_entries( &uncommented_contents ).unwrap();
+        assert_eq!( entries.iter().filter(|x| x.is_directory).map(|x| x.path.text).collect::<Vec<&str>>(), vec!["materials/ge_dirmap/..."] );
+        assert!( parse_reslist_entries( "resources { materials/ge_dirmap/... folder }" ).is_err() );
+
+        // A directory entry covers every file in it, so the generated reslist checks out.
+        let args = get_barebones_args();
+        let reslist_path = args.rootdir.join("maps/ge_dirmap.res");
+        let directory_tree = (file_list.clone(), file_list.clone());
+
+        assert!( check_reslist_contents( &args, &reslist_path, &contents, &directory_tree ).is_ok() );
+
+        let missing_contents = contents.replace("materials/ge_dirmap/...", "materials/ge_elsewhere/...");
+        assert!( check_reslist_contents( &args, &reslist_path, &missing_contents, &directory_tree ).is_err() );
+
+        let duplicate_contents = contents.replace("\r\n}", "\r\n\t\"materials/ge_dirmap/wall.vtf\"\t\"file\"\r\n}");
+        assert!( check_reslist_contents( &args, &reslist_path, &duplicate_contents, &directory_tree ).is_err() );
+
+        // Fixing paths gives a directory the case of the files in it.
+        let wrong_case_contents = contents.replace("materials/ge_dirmap/...", "Materials\\GE_DirMap/...");
+        assert!( check_reslist_contents( &args, &reslist_path, &wrong_case_contents, &directory_tree ).is_err() );
+        assert_eq!( fix_reslist_paths( &wrong_case_contents, &directory_tree ), contents );
+    }
+
+    #[test]
+    fn test_fix_reslist_paths()
+    {
+        let directory_tree = (vec![String::from("materials/test/wall.vtf"), String::from("sound/music/song.mp3")],
+                              vec![String::from("materials/Test/Wall.vtf"), String::from("sound/music/song.mp3")]);
+
+        let contents = "\"resources\"\r\n{\r\n\t\"./materials\\test//wall.vtf\"\t\"file\"\r\n\tSound/Music/Song.mp3\tfile\r\n\t\"Models/Missing.mdl\"\t\"file\"\r\n}\r\n";
+
+        assert_eq!( fix_reslist_paths( contents, &directory_tree ),
+                    "\"resources\"\r\n{\r\n\t\"materials/Test/Wall.vtf\"\t\"file\"\r\n\tsound/music/song.mp3\tfile\r\n\t\"models/missing.mdl\"\t\"file\"\r\n}\r\n" );
+
+        // Paths that are already right are left alone.
+        let fixed_contents = fix_reslist_paths( contents, &directory_tree );
+        assert_eq!( fix_reslist_paths( &fixed_contents, &directory_tree ), fixed_contents );
+    }
 }
\ No newline at end of file
@@ -0,0 +1,602 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// rules: Names every check the program makes and decides whether each one fails a release, warns, or is off.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::cell::Cell;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use argument_handler::Arguments;
+use error::GesError;
+use generation_metadata::EditState;
+use shared;
+
+use regex::Regex;
+
+/// How seriously a rule is taken when a check breaks it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity
+{
+    /// The check is skipped entirely.
+    Off,
+    /// The problem is printed but the section still passes.
+    Warning,
+    /// The section fails.
+    Error,
+}
+
+impl Severity
+{
+    /// Parses a severity as written in a rules file.
+    pub fn from_name( severity: &str ) -> Option<Severity>
+    {
+        match severity.to_lowercase().as_str()
+        {
+            "off" => Some(Severity::Off),
+            "warning" => Some(Severity::Warning),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Severity
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        // Padding is respected so severities line up in tables.
+        match *self
+        {
+            Severity::Off => f.pad("off"),
+            Severity::Warning => f.pad("warning"),
+            Severity::Error => f.pad("error"),
+        }
+    }
+}
+
+/// A single check, along with how seriously it's taken unless a rules file says otherwise.
+pub struct Rule
+{
+    pub id: &'static str,
+    pub default_severity: Severity,
+    /// Locked rules cover problems that stop a file from being read at all, so they can't be turned down.
+    pub locked: bool,
+    pub description: &'static str,
+}
+
+/// Every rule the program knows about.
+pub static RULES: &'static [Rule] = &[
+    Rule { id: "map-script-syntax", default_severity: Severity::Error, locked: true, description: "Map script can't be read the way GE:S reads it." },
+    Rule { id: "map-script-unknown-parameter", default_severity: Severity::Error, locked: false, description: "Map script sets a parameter GE:S doesn't know." },
+    Rule { id: "map-script-duplicate-parameter", default_severity: Severity::Error, locked: false, description: "Map script sets the same parameter twice." },
+    Rule { id: "map-script-duplicate-entry", default_severity: Severity::Error, locked: false, description: "Map script lists the same gamemode or team entry twice." },
+    Rule { id: "map-script-no-gamemodes", default_severity: Severity::Error, locked: false, description: "Map script gives every gamemode a weight of 0, so the map can never be played." },
+    Rule { id: "map-script-unknown-gamemode", default_severity: Severity::Warning, locked: false, description: "Map script weights a gamemode the targeted GE:S version doesn't have." },
+    Rule { id: "map-script-trailing-data", default_severity: Severity::Error, locked: false, description: "Map script line has extra data GE:S ignores." },
+    Rule { id: "map-script-invalid-value", default_severity: Severity::Error, locked: false, description: "Map script parameter is missing its value or isn't a whole number." },
+    Rule { id: "map-script-value-out-of-range", default_severity: Severity::Error, locked: false, description: "Map script parameter or weight is outside the limits the schema gives it." },
+    Rule { id: "map-script-missing-parameter", default_severity: Severity::Error, locked: false, description: "Map script leaves out a parameter GE:S expects." },
+    Rule { id: "map-script-playtest-expired", default_severity: Severity::Warning, locked: false, description: "Map script is stamped as a playtest that has already expired." },
+    Rule { id: "map-script-missing-group-map", default_severity: Severity::Error, locked: false, description: "Map script names a map group that isn't in the root or GE:S directory." },
+    Rule { id: "map-script-group-mismatch", default_severity: Severity::Error, locked: false, description: "Map script names a map group that's in a different group itself." },
+    Rule { id: "map-decompiled", default_severity: Severity::Error, locked: false, description: "Map carries the marks of a decompiler or map protection tool." },
+    Rule { id: "map-reserved-name", default_severity: Severity::Warning, locked: false, description: "Map has the same name as an official map, which it would replace once installed." },
+    Rule { id: "map-official-copy", default_severity: Severity::Error, locked: false, description: "Map is identical to a differently named official or installed map." },
+    Rule { id: "release-final-beta", default_severity: Severity::Warning, locked: false, description: "Release named as final still has beta assets or a playtest stamp." },
+    Rule { id: "smoke-server-failed", default_severity: Severity::Error, locked: false, description: "Smoke test server couldn't load the map or shut down early." },
+    Rule { id: "smoke-missing-resource", default_severity: Severity::Warning, locked: false, description: "Smoke test server couldn't find or load a resource." },
+    Rule { id: "smoke-script-error", default_severity: Severity::Warning, locked: false, description: "Smoke test server couldn't read a script." },
+    Rule { id: "music-script-syntax", default_severity: Severity::Error, locked: true, description: "Music script can't be read the way GE:S reads it." },
+    Rule { id: "music-duplicate-track", default_severity: Severity::Error, locked: false, description: "Music section lists the same track twice." },
+    Rule { id: "music-track-attribute", default_severity: Severity::Error, locked: false, description: "Music track attribute has a value that can't be used." },
+    Rule { id: "music-unknown-attribute", default_severity: Severity::Warning, locked: false, description: "Music track has an attribute this program doesn't know." },
+    Rule { id: "music-not-mp3", default_severity: Severity::Error, locked: false, description: "Music script uses a track that isn't an mp3." },
+    Rule { id: "music-missing-file", default_severity: Severity::Error, locked: false, description: "Music script uses a track that doesn't exist." },
+    Rule { id: "music-track-count", default_severity: Severity::Warning, locked: false, description: "Main music list has fewer tracks than --min-tracks." },
+    Rule { id: "music-runtime", default_severity: Severity::Warning, locked: false, description: "Main music list loops too often in a match." },
+    Rule { id: "music-unknown-area", default_severity: Severity::Warning, locked: false, description: "Music section doesn't match any music area." },
+    Rule { id: "music-single-track-section", default_severity: Severity::Warning, locked: false, description: "Music section only has one track." },
+    Rule { id: "music-redundant-section", default_severity: Severity::Warning, locked: false, description: "Music section has the same tracks as the main list." },
+    Rule { id: "music-orphaned-script", default_severity: Severity::Warning, locked: false, description: "Fullcheck found a music script for a map that isn't installed." },
+    Rule { id: "music-missing-script", default_severity: Severity::Warning, locked: false, description: "Fullcheck found a map without a music script." },
+    Rule { id: "music-bitrate", default_severity: Severity::Warning, locked: false, description: "Music file has a needlessly high bitrate." },
+    Rule { id: "music-loudness", default_severity: Severity::Warning, locked: false, description: "Music file is much louder or quieter than the others." },
+    Rule { id: "reslist-syntax", default_severity: Severity::Error, locked: true, description: "Reslist can't be read the way GE:S reads it." },
+    Rule { id: "reslist-disallowed-type", default_severity: Severity::Error, locked: false, description: "Reslist includes a file type clients won't download." },
+    Rule { id: "reslist-source-file", default_severity: Severity::Error, locked: false, description: "Reslist includes a map source or compile file." },
+    Rule { id: "reslist-missing-file", default_severity: Severity::Error, locked: false, description: "Reslist includes a file that doesn't exist." },
+    Rule { id: "reslist-case-mismatch", default_severity: Severity::Error, locked: false, description: "Reslist entry's case doesn't match the file on disk." },
+    Rule { id: "reslist-duplicate-entry", default_severity: Severity::Error, locked: false, description: "Reslist includes the same file twice." },
+    Rule { id: "reslist-incomplete", default_severity: Severity::Error, locked: false, description: "Reslist leaves out files that ship with the map." },
+    Rule { id: "reslist-mixed-case-directories", default_severity: Severity::Error, locked: false, description: "Root directory has directories whose names only differ by case." },
+    Rule { id: "reslist-source-files-present", default_severity: Severity::Warning, locked: false, description: "Root directory contains map source or compile files." },
+    Rule { id: "reslist-junk-files-present", default_severity: Severity::Warning, locked: false, description: "Root directory contains empty or temporary files." },
+    Rule { id: "reslist-overrides-stock", default_severity: Severity::Warning, locked: false, description: "Release ships a file at the same path as stock GE:S content." },
+    Rule { id: "reslist-orphaned", default_severity: Severity::Warning, locked: false, description: "Fullcheck found a reslist for a map that isn't installed." },
+    Rule { id: "reslist-missing", default_severity: Severity::Warning, locked: false, description: "Fullcheck found a map without a reslist." },
+    Rule { id: "consistency-unreslisted-sound", default_severity: Severity::Error, locked: false, description: "Music script or soundscape uses a shipped sound that isn't in the reslist." },
+    Rule { id: "consistency-unreslisted-material", default_severity: Severity::Error, locked: false, description: "Shipped model or map entity uses a shipped material that isn't in the reslist." },
+    Rule { id: "consistency-missing-material", default_severity: Severity::Warning, locked: false, description: "Shipped model or map entity uses a material that isn't in the release or GE:S." },
+    Rule { id: "consistency-unreslisted-map-file", default_severity: Severity::Error, locked: false, description: "Map uses a shipped color correction or detail.vbsp file that isn't in the reslist." },
+    Rule { id: "consistency-missing-map-file", default_severity: Severity::Warning, locked: false, description: "Map uses a color correction or detail.vbsp file that isn't in the release or GE:S." },
+    Rule { id: "consistency-unexpected-script", default_severity: Severity::Error, locked: false, description: "Reslist includes a script GE:S never loads for the map." },
+    Rule { id: "script-encoding", default_severity: Severity::Error, locked: false, description: "Fullcheck found a script saved as UTF-16 or with a byte order mark." },
+    Rule { id: "localization-syntax", default_severity: Severity::Error, locked: true, description: "Translation or caption file can't be read the way GE:S reads it." },
+    Rule { id: "localization-encoding", default_severity: Severity::Error, locked: false, description: "Translation or caption file isn't saved as UTF-16 LE." },
+    Rule { id: "localization-token", default_severity: Severity::Error, locked: false, description: "Localization token is misnamed, defined twice, or has a broken caption tag." },
+    Rule { id: "localization-language-mismatch", default_severity: Severity::Warning, locked: false, description: "Localization file says it's in a different language than its name." },
+    Rule { id: "localization-missing-captions", default_severity: Severity::Warning, locked: false, description: "Release adds caption tokens without the compiled caption file to show them." },
+    Rule { id: "python-layout", default_severity: Severity::Error, locked: false, description: "Python file isn't where GE:S loads python from." },
+    Rule { id: "python-shadows-stock", default_severity: Severity::Warning, locked: false, description: "Release ships a gamemode with the same name as an official one, which it would replace once installed." },
+    Rule { id: "python-server-only", default_severity: Severity::Warning, locked: false, description: "Release ships python or other server only files, which clients can't download." },
+    Rule { id: "cfg-disallowed-command", default_severity: Severity::Error, locked: false, description: "Shipped config file runs a command that changes how the server is run or secured." },
+    Rule { id: "compression-duplicate-files", default_severity: Severity::Warning, locked: false, description: "Release ships identical files at more than one path." },
+    Rule { id: "texture-oversized", default_severity: Severity::Warning, locked: false, description: "Texture is 4096 pixels or more on a side." },
+    Rule { id: "texture-uncompressed", default_severity: Severity::Warning, locked: false, description: "Large texture is stored uncompressed." },
+    Rule { id: "texture-resintensity", default_severity: Severity::Warning, locked: false, description: "Resintensity is lower than the map's textures suggest." },
+    Rule { id: "ges-version-mismatch", default_severity: Severity::Warning, locked: false, description: "Release targets a different GE:S version than the install it was checked with." },
+];
+
+/// How rules are taken for scripts checked with --legacy-format that this program didn't write, which includes
+/// everything left over from the 4.2 era.  Those scripts were written before most of these rules existed, and often
+/// for gamemodes and parameters that have since changed, so only problems that stop GE:S from using them fail.
+pub static LEGACY_RULE_OVERRIDES: &[(&'static str, Severity)] = &[
+    ("map-script-unknown-parameter", Severity::Warning),
+    ("map-script-missing-parameter", Severity::Warning),
+    ("map-script-trailing-data", Severity::Warning),
+    ("map-script-duplicate-entry", Severity::Warning),
+    ("map-script-value-out-of-range", Severity::Warning),
+    ("map-script-unknown-gamemode", Severity::Off),
+    ("music-duplicate-track", Severity::Warning),
+    ("music-track-count", Severity::Off),
+    ("music-unknown-area", Severity::Off),
+    ("music-single-track-section", Severity::Off),
+    ("music-redundant-section", Severity::Off),
+];
+
+/// A rule a script has asked not to be held to with a ges-ignore comment.
+struct Suppression
+{
+    file: PathBuf,
+    rule_id: String,
+    /// The line the comment is on, or 0 if it covers the whole file.
+    line: usize,
+}
+
+/// A problem a check found, kept so it can be written out in other formats once the run is over.
+#[derive(Clone, Debug)]
+pub struct Finding
+{
+    pub rule_id: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// The script the problem is in, if it's in any one script.
+    pub file: Option<PathBuf>,
+    /// The line the problem is on, or 0 if it isn't on any one line.
+    pub line: usize,
+    pub suppressed: bool,
+}
+
+lazy_static!
+{
+    static ref SUPPRESSIONS: Mutex<Vec<Suppression>> = Mutex::new(Vec::new());
+    static ref FINDINGS: Mutex<Vec<Finding>> = Mutex::new(Vec::new());
+}
+
+static SUPPRESSED_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the rule with the given id, if there is one.
+pub fn get_rule( rule_id: &str ) -> Option<&'static Rule>
+{
+    RULES.iter().find(|x| x.id == rule_id)
+}
+
+/// Returns how seriously the given rule should be taken, taking any rules file into account.
+pub fn get_severity( args: &Arguments, rule_id: &str ) -> Severity
+{
+    let rule = get_rule( rule_id ).expect("Checked against a rule that isn't in the rules table!");
+
+    // Later lines in a rules file win, just like later arguments on a commandline.
+    match args.rule_overrides.iter().rev().find(|x| x.0 == rule_id)
+    {
+        Some(x) => x.1,
+        None => rule.default_severity,
+    }
+}
+
+/// Returns whether the given rule should be checked at all.  Lets expensive checks skip their work when turned off.
+pub fn is_enabled( args: &Arguments, rule_id: &str ) -> bool
+{
+    get_severity( args, rule_id ) != Severity::Off
+}
+
+/// Reports a broken rule at whatever severity it's configured for.  Errors are passed back to fail the section, warnings
+/// are printed, and anything turned off is dropped.  The rule id is added to the message so it's clear what to configure.
+/// Parse errors can be suppressed by a ges-ignore comment in their file.
+pub fn report( args: &Arguments, rule_id: &str, error: GesError ) -> Result<(), GesError>
+{
+    let location = match error
+    {
+        GesError::Parse { ref file, line, .. } => Some((file.clone(), line)),
+        _ => None,
+    };
+
+    report_with_location( args, rule_id, location, error )
+}
+
+/// Same as report, but for problems that belong to the given line of a script without being parse errors.  Line 0 means
+/// the problem is with the script as a whole, so only a ges-ignore-file comment can suppress it.
+pub fn report_in_file( args: &Arguments, rule_id: &str, file_path: &Path, line: usize, error: GesError ) -> Result<(), GesError>
+{
+    report_with_location( args, rule_id, Some((file_path.to_path_buf(), line)), error )
+}
+
+fn report_with_location( args: &Arguments, rule_id: &str, location: Option<(PathBuf, usize)>, error: GesError ) -> Result<(), GesError>
+{
+    let severity = get_severity( args, rule_id );
+
+    if severity == Severity::Off
+    {
+        return Ok(());
+    }
+
+    let suppressed = match location
+    {
+        Some((ref file, line)) => is_suppressed( file, line, rule_id ),
+        None => false,
+    };
+
+    record_finding( rule_id, severity, &error, location, suppressed );
+
+    let error = tag_with_rule( error, rule_id );
+
+    // A deliberate exception is still worth a mention, just not a failure.
+    if suppressed
+    {
+        SUPPRESSED_COUNT.fetch_add(1, Ordering::SeqCst);
+        println!( "[Suppressed] {}", error );
+        return Ok(());
+    }
+
+    match severity
+    {
+        Severity::Error => Err(error),
+        _ => { shared::print_warning( &error.to_string() ); Ok(()) },
+    }
+}
+
+/// Shorthand for reporting a broken rule that doesn't belong to any one file or line.
+pub fn report_message( args: &Arguments, rule_id: &str, message: String ) -> Result<(), GesError>
+{
+    report( args, rule_id, GesError::PolicyViolation( message ) )
+}
+
+/// Reports a broken locked rule, which always fails the section.  Returns the error to fail it with.
+pub fn fail( rule_id: &str, error: GesError ) -> GesError
+{
+    let location = match error
+    {
+        GesError::Parse { ref file, line, .. } => Some((file.clone(), line)),
+        _ => None,
+    };
+
+    record_finding( rule_id, Severity::Error, &error, location, false );
+
+    tag_with_rule( error, rule_id )
+}
+
+thread_local!
+{
+    static THREAD_FINDING_COUNT: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Keeps track of a problem so it can be written out in other formats later.
+fn record_finding( rule_id: &str, severity: Severity, error: &GesError, location: Option<(PathBuf, usize)>, suppressed: bool )
+{
+    let rule = get_rule( rule_id ).expect("Checked against a rule that isn't in the rules table!");
+
+    THREAD_FINDING_COUNT.with(|x| x.set( x.get() + 1 ));
+
+    // Parse errors already say where they are, and that's recorded separately.
+    let message = match *error
+    {
+        GesError::Parse { ref message, .. } => message.clone(),
+        _ => error.to_string(),
+    };
+
+    let (file, line) = match location
+    {
+        Some((file, line)) => (Some(file), line),
+        None => (None, 0),
+    };
+
+    match FINDINGS.lock()
+    {
+        Ok(mut x) => x.push( Finding { rule_id: rule.id, severity, message, file, line, suppressed } ),
+        Err(_) => {},
+    }
+}
+
+/// Returns every problem reported so far, in the order they were found.
+pub fn get_findings() -> Vec<Finding>
+{
+    match FINDINGS.lock()
+    {
+        Ok(x) => x.clone(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Returns how many problems have been reported from the current thread so far.  Sections each run on their own
+/// thread, so this tells whether a check found anything without the others getting in the way.
+pub fn get_thread_finding_count() -> usize
+{
+    THREAD_FINDING_COUNT.with(|x| x.get())
+}
+
+/// Forgets every problem reported so far, for anything that checks scripts over and over in the same process.
+pub fn clear_findings()
+{
+    match FINDINGS.lock()
+    {
+        Ok(mut x) => x.clear(),
+        Err(_) => {},
+    }
+}
+
+/// Adds the rule id to the end of the error's message.
+fn tag_with_rule( error: GesError, rule_id: &str ) -> GesError
+{
+    let tag = format!( " [{}]", rule_id );
+
+    match error
+    {
+        GesError::Parse { file, line, mut message } => { message.push_str(&tag); GesError::Parse { file, line, message } },
+        GesError::MissingFile { path, mut message } => { message.push_str(&tag); GesError::MissingFile { path, message } },
+        GesError::PolicyViolation(mut message) => { message.push_str(&tag); GesError::PolicyViolation(message) },
+        other_error => other_error,
+    }
+}
+
+/// Records the ges-ignore comments in the given script, replacing any recorded for it before.  A
+/// "// ges-ignore: rule-id" comment covers its own line and the line after it, so it can go at the end of the line or
+/// just above it, and "// ges-ignore-file: rule-id" covers the whole script.  Several ids can be separated by commas.
+pub fn load_suppressions( file_path: &Path, contents: &str )
+{
+    lazy_static!
+    {
+        static ref IGNORE_RE: Regex = Regex::new(r"//\s*ges-ignore(-file)?:\s*([\w-]+(\s*,\s*[\w-]+)*)").unwrap();
+    }
+
+    let mut file_suppressions = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate()
+    {
+        let cap = match IGNORE_RE.captures(line)
+        {
+            Some(x) => x,
+            None => continue,
+        };
+
+        for rule_id in cap[2].split(',').map(|x| x.trim())
+        {
+            match get_rule( rule_id )
+            {
+                Some(x) if x.locked => shared::print_warning( &format!( "Rule {} can't be ignored, but line {} of {} tries to.", rule_id, line_index + 1, file_path.display() ) ),
+                Some(_) => file_suppressions.push( Suppression { file: file_path.to_path_buf(), rule_id: String::from(rule_id),
+                                                                 line: if cap.get(1) == None { line_index + 1 } else { 0 } } ),
+                None => shared::print_warning( &format!( "Unknown rule {} in the ges-ignore comment on line {} of {}.", rule_id, line_index + 1, file_path.display() ) ),
+            }
+        }
+    }
+
+    match SUPPRESSIONS.lock()
+    {
+        Ok(mut x) =>
+        {
+            x.retain(|x| x.file != file_path);
+            x.extend( file_suppressions );
+        },
+        Err(_) => {},
+    }
+}
+
+/// Returns whether a ges-ignore comment covers the given rule on the given line of the given file.
+pub fn is_suppressed( file_path: &Path, line: usize, rule_id: &str ) -> bool
+{
+    match SUPPRESSIONS.lock()
+    {
+        Ok(x) => x.iter().any(|x| x.file == file_path && x.rule_id == rule_id &&
+                                  (x.line == 0 || (line != 0 && (line == x.line || line == x.line + 1)))),
+        Err(_) => false,
+    }
+}
+
+/// Returns the number of problems ges-ignore comments have let through so far.
+pub fn get_suppressed_count() -> usize
+{
+    SUPPRESSED_COUNT.load(Ordering::SeqCst)
+}
+
+/// Reads the rules file at the given path.  Each line holds a rule id and the severity it should have, like
+/// "reslist-duplicate-entry warning", and anything after // is a comment.
+pub fn read_rules_file( rules_path: &PathBuf ) -> Result<Vec<(String, Severity)>, GesError>
+{
+    let mut contents = String::new();
+    fs::File::open(rules_path)?.read_to_string(&mut contents)?;
+
+    parse_rule_overrides( rules_path, &contents )
+}
+
+/// Parses the contents of a rules file, making sure every rule exists and can be set to the given severity.
+fn parse_rule_overrides( rules_path: &PathBuf, contents: &str ) -> Result<Vec<(String, Severity)>, GesError>
+{
+    let mut rule_overrides = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate()
+    {
+        let line = match line.find("//")
+        {
+            Some(x) => &line[..x],
+            None => line,
+        };
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if tokens.is_empty()
+        {
+            continue;
+        }
+
+        let parse_error = |message: String| GesError::Parse { file: rules_path.clone(), line: line_index + 1, message };
+
+        if tokens.len() != 2
+        {
+            return Err(parse_error( String::from("Expected a rule id followed by error, warning, or off!") ));
+        }
+
+        let rule = match get_rule( tokens[0] )
+        {
+            Some(x) => x,
+            None => return Err(parse_error( format!( "Unknown rule {}!  Run with --list-rules to see every rule.", tokens[0] ) )),
+        };
+
+        let severity = match Severity::from_name( tokens[1] )
+        {
+            Some(x) => x,
+            None => return Err(parse_error( format!( "Unknown severity {}!  Expected error, warning, or off.", tokens[1] ) )),
+        };
+
+        if rule.locked && severity != Severity::Error
+        {
+            return Err(parse_error( format!( "Rule {} can't be turned down, since nothing else can be checked once it's broken.", rule.id ) ));
+        }
+
+        rule_overrides.push( (String::from(rule.id), severity) );
+    }
+
+    Ok(rule_overrides)
+}
+
+/// Returns the arguments to check a script with under --legacy-format, or None if it should be held to the usual rules.
+/// Only scripts this program didn't generate can be from before it.  A rules file still has the final say.
+pub fn get_legacy_args( args: &Arguments, edit_state: &EditState ) -> Option<Arguments>
+{
+    if !args.legacy_format || *edit_state != EditState::Handwritten
+    {
+        return None;
+    }
+
+    let mut legacy_args = args.clone();
+    legacy_args.rule_overrides = LEGACY_RULE_OVERRIDES.iter().map(|x| (String::from(x.0), x.1)).collect();
+    legacy_args.rule_overrides.extend( args.rule_overrides.iter().cloned() );
+
+    Some(legacy_args)
+}
+
+/// Prints every rule along with how seriously it's taken for this run.
+pub fn print_rules( args: &Arguments )
+{
+    for rule in RULES
+    {
+        let locked_text = if rule.locked { " (locked)" } else { "" };
+
+        println!( "{:<32} {:<8} {}{}", rule.id, get_severity( args, rule.id ), rule.description, locked_text );
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use super::*;
+
+    #[test]
+    fn test_parse_rule_overrides()
+    {
+        let rules_path = PathBuf::from("rules.txt");
+
+        let rule_overrides = parse_rule_overrides( &rules_path, "// Our release standards\n\
+                                                                 reslist-duplicate-entry warning\n\
+                                                                 \n\
+                                                                 music-track-count ERROR // Four tracks or bust.\n" ).unwrap();
+
+        assert_eq!( rule_overrides, vec![(String::from("reslist-duplicate-entry"), Severity::Warning),
+                                         (String::from("music-track-count"), Severity::Error)] );
+
+        assert!( parse_rule_overrides( &rules_path, "reslist-duplicate-entyr warning" ).is_err() );
+        assert!( parse_rule_overrides( &rules_path, "reslist-duplicate-entry sometimes" ).is_err() );
+        assert!( parse_rule_overrides( &rules_path, "reslist-duplicate-entry" ).is_err() );
+        assert!( parse_rule_overrides( &rules_path, "reslist-syntax off" ).is_err() );
+
+        // Locked rules can't go down, but saying they're errors is harmless.
+        assert!( parse_rule_overrides( &rules_path, "reslist-syntax error" ).is_ok() );
+    }
+
+    #[test]
+    fn test_report()
+    {
+        let mut args = get_barebones_args();
+
+        assert_eq!( get_severity( &args, "music-track-count" ), Severity::Warning );
+        assert!( report_message( &args, "music-track-count", String::from("Not enough tracks!") ).is_ok() );
+
+        args.rule_overrides.push( (String::from("music-track-count"), Severity::Error) );
+        let error_text = report_message( &args, "music-track-count", String::from("Not enough tracks!") ).unwrap_err().to_string();
+        assert_eq!( error_text, "Not enough tracks! [music-track-count]" );
+
+        // The last override for a rule is the one that counts.
+        args.rule_overrides.push( (String::from("music-track-count"), Severity::Off) );
+        assert!( !is_enabled( &args, "music-track-count" ) );
+        assert!( report_message( &args, "music-track-count", String::from("Not enough tracks!") ).is_ok() );
+
+        for rule in RULES
+        {
+            assert!( !rule.locked || rule.default_severity == Severity::Error );
+        }
+
+        // Legacy scripts can't be let off problems that stop them from being read.
+        for &(rule_id, _) in LEGACY_RULE_OVERRIDES
+        {
+            assert!( !get_rule( rule_id ).unwrap().locked );
+        }
+    }
+
+    #[test]
+    fn test_suppressions()
+    {
+        let args = get_barebones_args();
+        let script_path = PathBuf::from("gesource/scripts/maps/test_suppressions.txt");
+
+        load_suppressions( &script_path, "// ges-ignore: map-script-duplicate-entry, map-script-trailing-data\n\
+                                          YOLT 400\n\
+                                          YOLT 200 // ges-ignore-file: map-script-missing-parameter\n\
+                                          // ges-ignore: map-script-syntax\n" );
+
+        let parse_error = |rule_id: &str, line: usize| report( &args, rule_id, GesError::Parse { file: script_path.clone(), line, message: String::from("Problem!") } );
+
+        // Comments cover their own line and the one below, and file comments cover everything.
+        assert!( parse_error( "map-script-duplicate-entry", 2 ).is_ok() );
+        assert!( parse_error( "map-script-trailing-data", 1 ).is_ok() );
+        assert!( parse_error( "map-script-duplicate-entry", 3 ).is_err() );
+        assert!( parse_error( "map-script-missing-parameter", 0 ).is_ok() );
+        assert!( parse_error( "map-script-syntax", 4 ).is_err() );
+
+        // Only the file the comments are in is affected.
+        assert!( report_in_file( &args, "map-script-duplicate-entry", Path::new("elsewhere.txt"), 2, GesError::PolicyViolation( String::from("Problem!") ) ).is_err() );
+        assert!( report_in_file( &args, "map-script-duplicate-entry", &script_path, 2, GesError::PolicyViolation( String::from("Problem!") ) ).is_ok() );
+
+        // Checking the script again replaces what it said before.
+        load_suppressions( &script_path, "YOLT 400\n" );
+        assert!( parse_error( "map-script-duplicate-entry", 2 ).is_err() );
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ---------------------------------------------------------------------------------------------------------
+// sarif: Writes every problem found during a run as a SARIF log, so code scanning tools can annotate scripts.
+// ---------------------------------------------------------------------------------------------------------
+
+use std::path::{Path, PathBuf};
+
+use serde_json;
+use serde_json::Value;
+
+use argument_handler::Arguments;
+use rules;
+use rules::{Finding, Severity};
+use shared;
+use update_checker::CURRENT_VERSION;
+use file_writer;
+use error::GesError;
+
+const SARIF_VERSION: &str = "2.1.0";
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const INFORMATION_URI: &str = "https://github.com/Entropy-Soldier/ges-map-script-utility";
+
+/// Writes every problem reported so far to the given path as a SARIF log.
+pub fn write_sarif_log( args: &Arguments, sarif_path: &PathBuf ) -> Result<(), GesError>
+{
+    let sarif_log = build_sarif_log( args, &rules::get_findings() );
+
+    // A Value is always valid JSON, so there's nothing that can go wrong turning it into text.
+    let sarif_text = serde_json::to_string_pretty( &sarif_log ).unwrap();
+
+    file_writer::write( sarif_path, sarif_text.as_bytes() )?;
+
+    Ok(())
+}
+
+/// Builds a SARIF log holding every rule and the given findings.
+fn build_sarif_log( args: &Arguments, findings: &[Finding] ) -> Value
+{
+    // Rules are listed with the severity they have for this run, so anything a rules file changed shows up as such.
+    let sarif_rules: Vec<Value> = rules::RULES.iter()
+                                              .map(|x| json!({
+                                                  "id": x.id,
+                                                  "shortDescription": { "text": x.description },
+                                                  "defaultConfiguration": { "level": get_sarif_level( rules::get_severity( args, x.id ) ) },
+                                              }))
+                                              .collect();
+
+    let sarif_results: Vec<Value> = findings.iter().map(build_sarif_result).collect();
+
+    json!({
+        "version": SARIF_VERSION,
+        "$schema": SARIF_SCHEMA,
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "ges_scriptutility",
+                    "version": CURRENT_VERSION,
+                    "informationUri": INFORMATION_URI,
+                    "rules": sarif_rules,
+                },
+            },
+            "results": sarif_results,
+        }],
+    })
+}
+
+/// Builds the SARIF result for a single finding.
+fn build_sarif_result( finding: &Finding ) -> Value
+{
+    let mut sarif_result = json!({
+        "ruleId": finding.rule_id,
+        "ruleIndex": rules::RULES.iter().position(|x| x.id == finding.rule_id),
+        "level": get_sarif_level( finding.severity ),
+        "message": { "text": finding.message },
+    });
+
+    match finding.file
+    {
+        Some(ref x) =>
+        {
+            let mut physical_location = json!({ "artifactLocation": { "uri": get_artifact_uri( x ) } });
+
+            // Problems with a script as a whole don't have a line to point at.
+            if finding.line > 0
+            {
+                physical_location["region"] = json!({ "startLine": finding.line });
+            }
+
+            sarif_result["locations"] = json!([{ "physicalLocation": physical_location }]);
+        },
+        None => {},
+    }
+
+    if finding.suppressed
+    {
+        sarif_result["suppressions"] = json!([{ "kind": "inSource" }]);
+    }
+
+    sarif_result
+}
+
+/// Returns the SARIF level that matches the given severity.
+fn get_sarif_level( severity: Severity ) -> &'static str
+{
+    match severity
+    {
+        Severity::Off => "none",
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    }
+}
+
+/// Returns the uri of the given file.  Code scanning matches results against the repository it runs in, so files under
+/// the working directory get a relative uri and anything else gets an absolute file uri.
+fn get_artifact_uri( file_path: &Path ) -> String
+{
+    let relative_path = shared::get_working_relative_path( file_path );
+
+    let uri_path = relative_path.to_string_lossy().replace("\\", "/").replace(" ", "%20");
+
+    if !relative_path.is_absolute()
+    {
+        String::from( uri_path.trim_start_matches("./") )
+    }
+    else if uri_path.starts_with('/')
+    {
+        format!( "file://{}", uri_path )
+    }
+    else
+    {
+        format!( "file:///{}", uri_path )
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::env;
+    use shared::get_barebones_args;
+    use super::*;
+
+    #[test]
+    fn test_build_sarif_log()
+    {
+        let args = get_barebones_args();
+
+        let findings = vec![Finding { rule_id: "map-script-duplicate-entry", severity: Severity::Error, message: String::from("Duplicate!"),
+                                      file: Some(PathBuf::from("gesource/scripts/maps/test_map.txt")), line: 27, suppressed: true },
+                            Finding { rule_id: "reslist-missing", severity: Severity::Warning, message: String::from("No reslist!"),
+                                      file: None, line: 0, suppressed: false }];
+
+        let sarif_log = build_sarif_log( &args, &findings );
+        let run = &sarif_log["runs"][0];
+
+        assert_eq!( sarif_log["version"], "2.1.0" );
+        assert_eq!( run["tool"]["driver"]["rules"].as_array().unwrap().len(), rules::RULES.len() );
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!( results.len(), 2 );
+
+        assert_eq!( results[0]["ruleId"], "map-script-duplicate-entry" );
+        assert_eq!( run["tool"]["driver"]["rules"][results[0]["ruleIndex"].as_u64().unwrap() as usize]["id"], "map-script-duplicate-entry" );
+        assert_eq!( results[0]["level"], "error" );
+        assert_eq!( results[0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"], "gesource/scripts/maps/test_map.txt" );
+        assert_eq!( results[0]["locations"][0]["physicalLocation"]["region"]["startLine"], 27 );
+        assert_eq!( results[0]["suppressions"][0]["kind"], "inSource" );
+
+        // Problems that aren't in any one file don't get a location at all.
+        assert_eq!( results[1]["level"], "warning" );
+        assert!( results[1].get("locations") == None );
+        assert!( results[1].get("suppressions") == None );
+    }
+
+    #[test]
+    fn test_get_artifact_uri()
+    {
+        let mut script_path = env::current_dir().unwrap();
+        script_path.push("gesource");
+        script_path.push("my map.txt");
+
+        assert_eq!( get_artifact_uri( &script_path ), "gesource/my%20map.txt" );
+        assert_eq!( get_artifact_uri( Path::new("./gesource/maps/test_map.res") ), "gesource/maps/test_map.res" );
+    }
+}
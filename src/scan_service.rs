@@ -0,0 +1,301 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// -----------------------------------------------------------------------------------------------------------
+// scan_service: Walks each directory once and serves every part of it, and what's known about its files, to whichever module asks.
+// -----------------------------------------------------------------------------------------------------------
+
+use std::collections::HashMap;
+use std::fs;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use shared;
+use shared::{DirectoryTree, FileFilter, FileInfo};
+use manifest::HashAlgorithm;
+use error::GesError;
+
+/// Every file found under one walked directory, in walk order, along with where to find the files of each type.
+pub struct DirectoryScan
+{
+    /// The size and modification time of each file.
+    file_info: Vec<FileInfo>,
+    /// The position of every file by its lowercase relative path.
+    indices_by_path: HashMap<String, usize>,
+    /// Lowercase relative paths for case-insensitive comparisons.
+    comp_paths: Vec<String>,
+    /// The same relative paths, keeping their original case.
+    write_paths: Vec<String>,
+    /// Each file's lowercase extension.
+    extensions: Vec<String>,
+    /// The position of every file with a given lowercase extension.
+    files_by_extension: HashMap<String, Vec<usize>>,
+}
+
+impl DirectoryScan
+{
+    /// Indexes the files of a walked directory by extension.
+    fn new( directory_tree: DirectoryTree, file_info: Vec<FileInfo> ) -> DirectoryScan
+    {
+        let (comp_paths, write_paths) = directory_tree;
+
+        let indices_by_path = comp_paths.iter().enumerate().map(|(index, path)| (path.clone(), index)).collect();
+
+        let extensions: Vec<String> = comp_paths.iter().map(|x| String::from( shared::get_string_file_extension(x) )).collect();
+        let mut files_by_extension: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (index, extension) in extensions.iter().enumerate()
+        {
+            files_by_extension.entry( extension.clone() ).or_default().push(index);
+        }
+
+        DirectoryScan { file_info, indices_by_path, comp_paths, write_paths, extensions, files_by_extension }
+    }
+}
+
+// Every walked directory gets its own lock, so walking one never holds up a request for another.
+type ScanEntry = Arc<Mutex<Option<Arc<DirectoryScan>>>>;
+
+/// Hands out the files in any directory, walking it only if neither it nor a directory above it has been walked already.
+/// Once the root directory has been walked, the music, texture, and reslist checks are all served from that one walk.
+#[derive(Default)]
+pub struct ScanService
+{
+    scans: Mutex<HashMap<PathBuf, ScanEntry>>,
+    /// Hashes are kept across walks along with what the file looked like when it was hashed, so walking a directory
+    /// again after a change only rehashes the files that actually changed.
+    known_hashes: Mutex<HashMap<(PathBuf, HashAlgorithm), (FileInfo, String)>>,
+}
+
+impl ScanService
+{
+    pub fn new() -> ScanService
+    {
+        ScanService { scans: Mutex::new(HashMap::new()), known_hashes: Mutex::new(HashMap::new()) }
+    }
+
+    /// Returns the files in the given directory, relative to it, in the same form as shared::get_files_in_directory.
+    pub fn get_files( &self, files_dir: &PathBuf, target_extension: &str, excluded_extensions: &[&str], file_filter: FileFilter ) -> Result<DirectoryTree, GesError>
+    {
+        let (scan_root, scan) = self.get_scan( files_dir )?;
+
+        // Files under a subdirectory of the walked one start with its relative path.
+        let (prefix, prefix_depth) = match files_dir.strip_prefix(&scan_root)
+        {
+            Ok(x) if x != Path::new("") => (format!( "{}/", x.to_string_lossy().replace("\\", "/").to_lowercase() ), x.components().count()),
+            _ => (String::new(), 0),
+        };
+
+        // Only the files of the type we're after need to be looked at, if we're after one.
+        let file_indices: Vec<usize> = if target_extension.is_empty()
+        {
+            (0..scan.comp_paths.len()).collect()
+        }
+        else
+        {
+            scan.files_by_extension.get( &target_extension.to_lowercase() ).cloned().unwrap_or_default()
+        };
+
+        let mut comp_file_names: Vec<String> = Vec::new();
+        let mut write_file_names: Vec<String> = Vec::new();
+
+        for index in file_indices
+        {
+            let comp_path = &scan.comp_paths[index];
+            let write_path = &scan.write_paths[index];
+
+            if !comp_path.starts_with(&prefix) { continue; }
+            if excluded_extensions.contains( &scan.extensions[index].as_str() ) { continue; }
+            if !file_filter( &scan_root.join(write_path), &scan.file_info[index] ) { continue; }
+
+            // Lowercasing can change a path's length, so cut the prefix off the original by its directories instead.
+            comp_file_names.push( String::from( &comp_path[prefix.len()..] ) );
+            write_file_names.push( String::from( write_path.splitn( prefix_depth + 1, '/' ).last().unwrap_or("") ) );
+        }
+
+        Ok((comp_file_names, write_file_names))
+    }
+
+    /// Returns the size and modification time of the given file if it's in a directory that's already been walked.
+    /// Nothing is walked to find out, so None just means the filesystem has to be asked instead.
+    pub fn get_file_info( &self, file_path: &Path ) -> Option<FileInfo>
+    {
+        let (scan_root, scan) = self.find_scan( file_path )?;
+
+        let relative_path = file_path.strip_prefix(&scan_root).ok()?.to_string_lossy().replace("\\", "/").to_lowercase();
+
+        scan.indices_by_path.get(&relative_path).map(|x| scan.file_info[*x].clone())
+    }
+
+    /// Returns the size and modification time of the given file, only asking the filesystem if no walk has covered it.
+    pub fn read_file_info( &self, file_path: &Path ) -> Result<FileInfo, GesError>
+    {
+        match self.get_file_info( file_path )
+        {
+            Some(x) => return Ok(x),
+            None => {},
+        }
+
+        let metadata = fs::metadata( file_path ).map_err(|e| GesError::from(e).in_file( file_path ))?;
+
+        Ok(FileInfo { size: metadata.len(), modified: metadata.modified().ok() })
+    }
+
+    /// Returns the SHA-256 hash of the given file, the same as manifest::hash_file would.
+    pub fn get_file_hash( &self, file_path: &Path ) -> Result<String, GesError>
+    {
+        self.get_file_hash_with( file_path, HashAlgorithm::Sha256 )
+    }
+
+    /// Returns the hash of the given file made with the given algorithm.  The file is only read if it hasn't been hashed
+    /// that way before, or has changed size or modification time since it was.
+    pub fn get_file_hash_with( &self, file_path: &Path, algorithm: HashAlgorithm ) -> Result<String, GesError>
+    {
+        let file_info = self.read_file_info( file_path )?;
+        let hash_key = (file_path.to_path_buf(), algorithm);
+
+        match self.known_hashes.lock().unwrap().get( &hash_key )
+        {
+            Some(&(ref x, ref y)) if *x == file_info => return Ok(y.clone()),
+            _ => {},
+        }
+
+        let file_hash = algorithm.hash_file( file_path )?;
+
+        // Without a modification time there's no telling if the file changed later, so it can't be trusted next time.
+        if file_info.modified != None
+        {
+            self.known_hashes.lock().unwrap().insert( hash_key, (file_info, file_hash.clone()) );
+        }
+
+        Ok(file_hash)
+    }
+
+    /// Forgets every walk the given path is part of, so the next request walks it again.
+    pub fn invalidate( &self, changed_path: &Path )
+    {
+        self.scans.lock().unwrap().retain(|x, _| !changed_path.starts_with(x));
+    }
+
+    /// Forgets every walk, so the next request for any directory walks it again.  Known hashes are kept, since each
+    /// one is checked against its file before it's used.
+    pub fn invalidate_all( &self )
+    {
+        self.scans.lock().unwrap().clear();
+    }
+
+    /// Returns the walked directory holding files_dir along with its scan, walking files_dir if nothing above it has been.
+    fn get_scan( &self, files_dir: &PathBuf ) -> Result<(PathBuf, Arc<DirectoryScan>), GesError>
+    {
+        match self.find_scan( files_dir )
+        {
+            Some(x) => return Ok(x),
+            None => {},
+        }
+
+        let entry = self.scans.lock().unwrap().entry( files_dir.clone() ).or_insert_with(|| Arc::new(Mutex::new(None))).clone();
+
+        // Anyone else after the same directory waits here until it's been walked, rather than walking it again themselves.
+        let mut cached_scan = entry.lock().unwrap();
+
+        match *cached_scan
+        {
+            Some(ref x) => return Ok((files_dir.clone(), x.clone())),
+            None => {},
+        }
+
+        let (directory_tree, file_info) = shared::get_files_and_info_in_directory( files_dir, "", &[] )?;
+
+        let scan = Arc::new( DirectoryScan::new( directory_tree, file_info ) );
+        *cached_scan = Some(scan.clone());
+
+        Ok((files_dir.clone(), scan))
+    }
+
+    /// Returns the finished walk of the given path or a directory above it, if there is one.
+    fn find_scan( &self, path: &Path ) -> Option<(PathBuf, Arc<DirectoryScan>)>
+    {
+        let scans = self.scans.lock().unwrap();
+
+        for (scan_root, scan_entry) in scans.iter()
+        {
+            if !path.starts_with(scan_root)
+            {
+                continue;
+            }
+
+            // A walk that's still going could take a while, so only finished ones are worth reusing here.
+            match scan_entry.try_lock().ok().and_then(|x| x.clone())
+            {
+                Some(x) => return Some((scan_root.clone(), x)),
+                None => {},
+            }
+        }
+
+        None
+    }
+}
+
+lazy_static!
+{
+    static ref SCAN_SERVICE: ScanService = ScanService::new();
+}
+
+/// Returns the scan service shared by the whole program.
+pub fn get_scan_service() -> &'static ScanService
+{
+    &SCAN_SERVICE
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use shared::get_root_test_directory;
+    use manifest;
+    use super::*;
+
+    #[test]
+    fn test_scan_service()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("scan_service");
+
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all( test_dir.join("sound/music") ).unwrap();
+        fs::write( test_dir.join("sound/music/Theme.mp3"), b"theme" ).unwrap();
+        fs::write( test_dir.join("sound/intro.wav"), b"intro" ).unwrap();
+        fs::write( test_dir.join("readme.txt"), b"readme" ).unwrap();
+
+        let scan_service = ScanService::new();
+
+        // Walking the root first means the subdirectory is served from it.
+        let (comp_names, write_names) = scan_service.get_files( &test_dir, "", &["txt"], shared::keep_all_files ).unwrap();
+        assert_eq!( comp_names.len(), 2 );
+        assert!( write_names.contains( &String::from("sound/music/Theme.mp3") ) );
+
+        let (comp_names, write_names) = scan_service.get_files( &test_dir.join("sound"), "mp3", &[], shared::keep_all_files ).unwrap();
+        assert_eq!( comp_names, vec![String::from("music/theme.mp3")] );
+        assert_eq!( write_names, vec![String::from("music/Theme.mp3")] );
+        assert_eq!( scan_service.scans.lock().unwrap().len(), 1 );
+
+        // Until something changes, the new file isn't seen.
+        fs::write( test_dir.join("sound/music/Credits.mp3"), b"credits" ).unwrap();
+        assert_eq!( scan_service.get_files( &test_dir.join("sound"), "mp3", &[], shared::keep_all_files ).unwrap().0.len(), 1 );
+
+        scan_service.invalidate( &test_dir.join("sound/music/Credits.mp3") );
+        assert_eq!( scan_service.get_files( &test_dir.join("sound"), "mp3", &[], shared::keep_all_files ).unwrap().0.len(), 2 );
+
+        // What the walk found out about each file is kept too.
+        let theme_path = test_dir.join("sound/music/Theme.mp3");
+        assert_eq!( scan_service.get_file_info( &theme_path ).unwrap().size, 5 );
+        assert_eq!( scan_service.get_file_info( &test_dir.join("missing.mp3") ), None );
+        assert_eq!( scan_service.get_file_hash( &theme_path ).unwrap(), manifest::hash_file( &theme_path ).unwrap() );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+}
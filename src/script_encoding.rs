@@ -0,0 +1,176 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// script_encoding: Finds scripts saved in an encoding the engine's KeyValues parser will misread, and re-encodes them.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::path::PathBuf;
+
+use walkdir::WalkDir;
+
+use argument_handler::Arguments;
+use file_writer;
+use shared;
+use rules;
+use error::GesError;
+
+/// How a script file is saved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScriptEncoding
+{
+    /// Plain ASCII or UTF-8, which is all GE:S understands.
+    Utf8,
+    /// UTF-8 that starts with a byte order mark, which GE:S reads as part of the first key.
+    Utf8Bom,
+    /// UTF-16, which GE:S reads as a string of single characters with nothing between them.
+    Utf16Le,
+    Utf16Be,
+}
+
+impl ScriptEncoding
+{
+    /// Describes the encoding the way text editors tend to name it.
+    pub fn get_name( &self ) -> &'static str
+    {
+        match *self
+        {
+            ScriptEncoding::Utf8 => "UTF-8",
+            ScriptEncoding::Utf8Bom => "UTF-8 with a byte order mark",
+            ScriptEncoding::Utf16Le => "UTF-16 LE",
+            ScriptEncoding::Utf16Be => "UTF-16 BE",
+        }
+    }
+}
+
+/// Works out how the given script contents are saved.  UTF-16 is usually marked with a byte order mark, but some
+/// tools leave it off, so a file whose first character is ASCII with a zero byte beside it counts as well.
+pub fn get_script_encoding( contents: &[u8] ) -> ScriptEncoding
+{
+    if contents.starts_with(&[0xEF, 0xBB, 0xBF])
+    {
+        return ScriptEncoding::Utf8Bom;
+    }
+
+    if contents.starts_with(&[0xFF, 0xFE])
+    {
+        return ScriptEncoding::Utf16Le;
+    }
+
+    if contents.starts_with(&[0xFE, 0xFF])
+    {
+        return ScriptEncoding::Utf16Be;
+    }
+
+    match (contents.first(), contents.get(1))
+    {
+        (Some(&x), Some(&0)) if x != 0 && x.is_ascii() => ScriptEncoding::Utf16Le,
+        (Some(&0), Some(&x)) if x != 0 && x.is_ascii() => ScriptEncoding::Utf16Be,
+        _ => ScriptEncoding::Utf8,
+    }
+}
+
+/// Converts script contents in the given encoding to UTF-8 without a byte order mark.
+pub fn reencode_script( contents: &[u8], encoding: ScriptEncoding ) -> Result<String, String>
+{
+    let (contents, big_endian) = match encoding
+    {
+        ScriptEncoding::Utf8 => return String::from_utf8( contents.to_vec() ).map_err(|_| String::from("This file has characters that aren't valid UTF-8!")),
+        ScriptEncoding::Utf8Bom => return String::from_utf8( contents[3..].to_vec() ).map_err(|_| String::from("This file has characters that aren't valid UTF-8!")),
+        ScriptEncoding::Utf16Le => (if contents.starts_with(&[0xFF, 0xFE]) { &contents[2..] } else { contents }, false),
+        ScriptEncoding::Utf16Be => (if contents.starts_with(&[0xFE, 0xFF]) { &contents[2..] } else { contents }, true),
+    };
+
+    if !contents.len().is_multiple_of(2)
+    {
+        return Err(String::from("This file ends partway through a character, so it's most likely been cut short!"));
+    }
+
+    let characters: Vec<u16> = contents.chunks(2)
+                                       .map(|x| if big_endian { u16::from_be_bytes([x[0], x[1]]) } else { u16::from_le_bytes([x[0], x[1]]) })
+                                       .collect();
+
+    String::from_utf16( &characters ).map_err(|_| String::from("This file has characters that aren't valid UTF-16!"))
+}
+
+/// Checks the encoding of every script with the given extension in the given directory, re-encoding any that GE:S
+/// would misread when run with --fix.  This runs before the scripts themselves are checked, so with --fix they're
+/// checked as they'll be read from then on.
+pub fn fullcheck_script_encodings( args: &Arguments, dir: &PathBuf, extension: &str ) -> Result<(), GesError>
+{
+    for entry in WalkDir::new( dir )
+    {
+        let entry = entry?;
+        let script_path = entry.path();
+
+        if !script_path.is_file() || shared::get_file_extension( script_path ).to_lowercase() != extension { continue; }
+
+        let contents = shared::read_script_bytes( script_path )?;
+        let encoding = get_script_encoding( &contents );
+
+        if encoding == ScriptEncoding::Utf8
+        {
+            continue;
+        }
+
+        if !args.fix
+        {
+            let mut error_text = String::new();
+            error_text.push_str("This script is saved as ");
+            error_text.push_str( encoding.get_name() );
+            error_text.push_str(", which GE:S will misread!  Save it as UTF-8 without a byte order mark, or run with --fix to re-encode it automatically.");
+
+            rules::report_in_file( args, "script-encoding", script_path, 0, GesError::Parse { file: script_path.to_path_buf(), line: 0, message: error_text } )?;
+            continue;
+        }
+
+        // A file we can't decode is left as it is, since there's no telling what it was meant to say.
+        let fixed_contents = match reencode_script( &contents, encoding )
+        {
+            Ok(x) => x,
+            Err(e) =>
+            {
+                rules::report_in_file( args, "script-encoding", script_path, 0, GesError::Parse { file: script_path.to_path_buf(), line: 0, message: e } )?;
+                continue;
+            },
+        };
+
+        file_writer::write( script_path, fixed_contents.as_bytes() )?;
+
+        println!( "Re-encoded {} from {} to UTF-8!", script_path.display(), encoding.get_name() );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_get_script_encoding()
+    {
+        assert_eq!( get_script_encoding( b"\"Map\"\n{" ), ScriptEncoding::Utf8 );
+        assert_eq!( get_script_encoding( b"" ), ScriptEncoding::Utf8 );
+        assert_eq!( get_script_encoding( b"\xEF\xBB\xBF\"Map\"" ), ScriptEncoding::Utf8Bom );
+        assert_eq!( get_script_encoding( b"\xFF\xFE\"\x00M\x00" ), ScriptEncoding::Utf16Le );
+        assert_eq!( get_script_encoding( b"\xFE\xFF\x00\"\x00M" ), ScriptEncoding::Utf16Be );
+        assert_eq!( get_script_encoding( b"\"\x00M\x00" ), ScriptEncoding::Utf16Le );
+        assert_eq!( get_script_encoding( b"\x00\"\x00M" ), ScriptEncoding::Utf16Be );
+    }
+
+    #[test]
+    fn test_reencode_script()
+    {
+        assert_eq!( reencode_script( b"\xEF\xBB\xBF\"Map\"", ScriptEncoding::Utf8Bom ).unwrap(), "\"Map\"" );
+        assert_eq!( reencode_script( b"\xFF\xFE\"\x00M\x00\"\x00", ScriptEncoding::Utf16Le ).unwrap(), "\"M\"" );
+        assert_eq!( reencode_script( b"\xFE\xFF\x00\"\x00M\x00\"", ScriptEncoding::Utf16Be ).unwrap(), "\"M\"" );
+        assert_eq!( reencode_script( b"\"\x00M\x00", ScriptEncoding::Utf16Le ).unwrap(), "\"M" );
+
+        assert!( reencode_script( b"\xFF\xFE\"\x00M", ScriptEncoding::Utf16Le ).is_err() );
+        assert!( reencode_script( b"\xFF\xFE\x00\xD8", ScriptEncoding::Utf16Le ).is_err() );
+    }
+}
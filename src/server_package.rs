@@ -0,0 +1,125 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// server_package: Gathers everything a server needs to run the map, including what clients never download.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::path::PathBuf;
+
+use argument_handler::Arguments;
+use reslist_builder;
+use shared;
+use file_writer;
+use error::GesError;
+use preflight::PreflightReport;
+
+/// Adds what the server package needs before it runs to the preflight report.
+pub fn preflight( args: &Arguments, report: &mut PreflightReport )
+{
+    match args.server_package
+    {
+        Some(ref x) =>
+        {
+            report.require( "Server package", x, "a directory for the server package rather than a file", !x.is_file() );
+            report.require( "Server package", x, "a directory outside of the root directory", !x.starts_with( &args.rootdir ) );
+            report.require( "Server package", x, "write access to place the server package into", file_writer::can_create_in(x) );
+        },
+        None => {},
+    }
+}
+
+/// Copies the map, its reslist, every file clients download, and every server only file into the server package
+/// directory, laid out the same way as the root directory.  Returns how many files were copied.
+pub fn construct_server_package( args: &Arguments, map_name: &str ) -> Result<usize, GesError>
+{
+    let package_dir = args.server_package.as_ref().unwrap();
+
+    let mut package_files = get_package_files( args, map_name )?;
+    package_files.sort();
+
+    for file_path in &package_files
+    {
+        let destination_path = package_dir.join( file_path );
+
+        match destination_path.parent()
+        {
+            Some(x) => file_writer::create_dir_all( x )?,
+            None => {},
+        }
+
+        file_writer::copy( &args.rootdir.join( file_path ), &destination_path )?;
+        shared::print_trace( args, &format!( "{} copied into the server package.", file_path ) );
+    }
+
+    println!( "{} is ready to be installed on servers.", package_dir.display() );
+
+    Ok(package_files.len())
+}
+
+/// Returns the relative path of every file in the server package.  The reslist only goes to servers, which send
+/// clients what it lists.
+fn get_package_files( args: &Arguments, map_name: &str ) -> Result<Vec<String>, GesError>
+{
+    let mut map_path = PathBuf::from("maps");
+    map_path.push( map_name );
+    map_path.set_extension("bsp");
+
+    let mut reslist_path = map_path.clone();
+    reslist_path.set_extension("res");
+
+    let mut package_files = vec![map_path.to_string_lossy().into_owned()];
+
+    if args.rootdir.join( &reslist_path ).is_file()
+    {
+        package_files.push( reslist_path.to_string_lossy().into_owned() );
+    }
+
+    package_files.extend( reslist_builder::generate_directory_tree( args )?.1.iter().cloned() );
+    package_files.extend( reslist_builder::get_server_files( args )? );
+
+    Ok(package_files)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_construct_server_package()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("server_package");
+
+        let _ = fs::remove_dir_all( &test_dir );
+
+        let mut args = get_barebones_args();
+        args.rootdir = test_dir.join("gesource");
+        args.server_package = Some(test_dir.join("server"));
+
+        fs::create_dir_all( args.rootdir.join("maps") ).unwrap();
+        fs::create_dir_all( args.rootdir.join("materials/ge_test") ).unwrap();
+        fs::create_dir_all( args.rootdir.join("python/ges/GamePlay") ).unwrap();
+        fs::write( args.rootdir.join("maps/ge_test.bsp"), "VBSP" ).unwrap();
+        fs::write( args.rootdir.join("maps/ge_test.res"), "\"resources\"\n{\n}\n" ).unwrap();
+        fs::write( args.rootdir.join("maps/ge_test.vmf"), "versioninfo {}" ).unwrap();
+        fs::write( args.rootdir.join("materials/ge_test/wall.vmt"), "\"LightmappedGeneric\" {}" ).unwrap();
+        fs::write( args.rootdir.join("python/ges/GamePlay/Arena.py"), "class Arena: pass\n" ).unwrap();
+
+        // Clients get the material, servers get that along with everything else except the map source.
+        assert_eq!( construct_server_package( &args, "ge_test" ).unwrap(), 4 );
+
+        let package_dir = args.server_package.clone().unwrap();
+        assert!( package_dir.join("maps/ge_test.res").is_file() );
+        assert!( package_dir.join("python/ges/GamePlay/Arena.py").is_file() );
+        assert!( !package_dir.join("maps/ge_test.vmf").exists() );
+
+        fs::remove_dir_all( &test_dir ).unwrap();
+    }
+}
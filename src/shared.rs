@@ -8,20 +8,57 @@
 // ----------------------------------------------------------------------------
 
 use std::path::{Path, PathBuf};
-use std::io::{Error, ErrorKind};
-use std::error::Error as ErrorTrait; // Use an alias as it will conflict with the error object otherwise.
-
-use std::sync::Mutex;
-use std::ops::DerefMut;
 
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::any::Any;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::thread;
+use std::thread::{JoinHandle, Scope, ScopedJoinHandle};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use std::env;
 use std::fs;
+use std::fs::File;
+use std::time::{Duration, SystemTime};
+use std::io;
+use std::io::{Read, BufReader, Cursor};
 
 use walkdir::WalkDir;
+use memmap2::Mmap;
 
 use argument_handler::Arguments;
+use file_writer;
+use error::GesError;
+use rules;
+use watchdog;
+use scan_service;
+use validation_cache;
+use manifest::HashAlgorithm;
+
+/// The size and modification time of a file, as seen when its directory was walked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileInfo
+{
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
 
 /// Gets the file paths of all files in a given directory, relative to the root path supplied.
-pub fn get_files_in_directory( files_dir: &PathBuf, target_extension: &str, excluded_extensions: &[&str] ) -> Result<(Vec<String>, Vec<String>), Error>
+pub fn get_files_in_directory( files_dir: &PathBuf, target_extension: &str, excluded_extensions: &[&str] ) -> Result<(Vec<String>, Vec<String>), GesError>
+{
+    let (directory_tree, _file_info) = get_files_and_info_in_directory( files_dir, target_extension, excluded_extensions )?;
+
+    Ok(directory_tree)
+}
+
+/// Same as get_files_in_directory, but also returns the size and modification time of every file in the same order.
+/// Walking the directory has to look these up anyway, so keeping them saves asking the filesystem again later.
+pub fn get_files_and_info_in_directory( files_dir: &PathBuf, target_extension: &str, excluded_extensions: &[&str] ) -> Result<(DirectoryTree, Vec<FileInfo>), GesError>
 {
     // This is where the relative paths of our desired files will go.
     // For larger sets a hashmap would be better for the constant lookup time, but the linear lookup time
@@ -33,13 +70,14 @@ pub fn get_files_in_directory( files_dir: &PathBuf, target_extension: &str, excl
     // The comp list is lowercase to facilitate case-insensitive searches, while the write list keeps the original case.
     let mut comp_file_names: Vec<String> = Vec::new(); 
     let mut write_file_names: Vec<String> = Vec::new(); 
+    let mut file_info: Vec<FileInfo> = Vec::new();
 
     // Grab the directory path here for later.
     let dir_path = files_dir.to_str();
 
     if dir_path == None 
     {  
-        return Err(Error::new( ErrorKind::InvalidInput, "Could not construct directory path string!"));
+        return Err(GesError::Config( format!( "Could not construct directory path string for {}!", files_dir.display() ) ));
     }
 
     // We just made sure it's not None so we can unwrap it.
@@ -48,13 +86,20 @@ pub fn get_files_in_directory( files_dir: &PathBuf, target_extension: &str, excl
     // Make sure our  directory exists and if so scan it for files.
     if files_dir.is_dir()
     {
-        for entry in WalkDir::new( files_dir ) 
+        let _watchdog = watchdog::watch_walk( files_dir );
+
+        // Sorted so everything generated from the tree lists its files in the same order, whatever filesystem it's on.
+        for entry in WalkDir::new( files_dir ).sort_by_file_name()
         {
             let entry = entry?;
             let entrypath = entry.path();
 
             // Not a file we have access to, don't worry about it.
-            if !entrypath.is_file() { continue; }
+            let metadata = match fs::metadata(entrypath)
+            {
+                Ok(ref x) if x.is_file() => FileInfo { size: x.len(), modified: x.modified().ok() },
+                _ => continue,
+            };
 
             // Grab the file extension for comparison.
             let file_extension = get_file_extension(entrypath);
@@ -94,10 +139,345 @@ pub fn get_files_in_directory( files_dir: &PathBuf, target_extension: &str, excl
 
             comp_file_names.push( final_comp_path_string );
             write_file_names.push( final_write_path_string );
+            file_info.push( metadata );
         }
     }
 
-    Ok((comp_file_names, write_file_names))
+    Ok(((comp_file_names, write_file_names), file_info))
+}
+
+/// Decides whether a file should be included, given its path and what its directory walk found out about it.
+pub type FileFilter = fn(&Path, &FileInfo) -> bool;
+
+/// A file filter that lets every file through.
+pub fn keep_all_files( _file_path: &Path, _file_info: &FileInfo ) -> bool
+{
+    true
+}
+
+// Every warning printed during the run is counted, so we can tell a clean run apart from one that only had warnings.
+static WARNING_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Prints the given warning message to the user and counts it towards the total for this run.
+pub fn print_warning( message: &str )
+{
+    WARNING_COUNT.fetch_add(1, Ordering::SeqCst);
+    THREAD_WARNING_COUNT.with(|x| x.set( x.get() + 1 ));
+    println!( "[Warning] {}", message );
+}
+
+/// Prints why the program decided what it did about a file, if --trace is set.
+pub fn print_trace( args: &Arguments, message: &str )
+{
+    if args.trace
+    {
+        println!( "[Trace] {}", message );
+    }
+}
+
+/// Returns the number of warnings that have been printed so far.
+pub fn get_warning_count() -> usize
+{
+    WARNING_COUNT.load(Ordering::SeqCst)
+}
+
+/// Starts the warning count over, for anything that runs more than once in the same process.
+pub fn clear_warning_count()
+{
+    WARNING_COUNT.store(0, Ordering::SeqCst);
+}
+
+/// Returns the number of warnings the current thread has printed so far.
+pub fn get_thread_warning_count() -> usize
+{
+    THREAD_WARNING_COUNT.with(|x| x.get())
+}
+
+lazy_static!
+{
+    static ref ERROR_LOG: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    static ref LAST_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+thread_local!
+{
+    static CURRENT_FILE: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    static THREAD_WARNING_COUNT: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Prints an error message and keeps track of it so it can be included in a crash report.
+pub fn print_error( message: &str )
+{
+    match ERROR_LOG.lock()
+    {
+        Ok(mut x) => x.push( String::from(message) ),
+        Err(_) => {}, // Something panicked while holding the log, but the message still gets printed.
+    }
+
+    println!( "[Error] {}", message );
+}
+
+/// Returns every error message that has been printed so far.
+pub fn get_printed_errors() -> Vec<String>
+{
+    match ERROR_LOG.lock()
+    {
+        Ok(x) => x.clone(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Forgets every error message printed so far, for anything that runs more than once in the same process.
+pub fn clear_printed_errors()
+{
+    match ERROR_LOG.lock()
+    {
+        Ok(mut x) => x.clear(),
+        Err(_) => {},
+    }
+}
+
+/// Records the file the current thread is working on, so failures can point at it.
+pub fn set_current_file( file_path: &Path )
+{
+    CURRENT_FILE.with(|x| *x.borrow_mut() = Some(file_path.to_path_buf()));
+
+    match LAST_FILE.lock()
+    {
+        Ok(mut x) => *x = Some(file_path.to_path_buf()),
+        Err(_) => {},
+    }
+}
+
+/// Returns the file the current thread was last working on.
+pub fn get_current_file() -> Option<PathBuf>
+{
+    CURRENT_FILE.with(|x| x.borrow().clone())
+}
+
+/// Returns the file any thread was last working on.
+pub fn get_last_file() -> Option<PathBuf>
+{
+    match LAST_FILE.lock()
+    {
+        Ok(x) => x.clone(),
+        Err(_) => None,
+    }
+}
+
+lazy_static!
+{
+    // A read from stdin can't be given up on once it's started, so lines are read on a thread of their own and handed
+    // over as they come in.  Every prompt shares the one thread, so a prompt that timed out can't eat the next answer.
+    static ref STDIN_LINES: Mutex<Receiver<String>> =
+    {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn( move ||
+        {
+            let mut line = String::new();
+
+            while io::stdin().read_line( &mut line ).unwrap_or(0) > 0 && sender.send( line.clone() ).is_ok()
+            {
+                line.clear();
+            }
+        });
+
+        Mutex::new(receiver)
+    };
+}
+
+/// Waits for a line from stdin, giving up after the given number of seconds.  0 waits as long as it takes.  Returns None
+/// if time runs out or stdin is closed.
+pub fn read_line_with_timeout( timeout_secs: u64 ) -> Option<String>
+{
+    let receiver = match STDIN_LINES.lock()
+    {
+        Ok(x) => x,
+        Err(_) => return None,
+    };
+
+    if timeout_secs == 0
+    {
+        receiver.recv().ok()
+    }
+    else
+    {
+        receiver.recv_timeout( Duration::from_secs( timeout_secs ) ).ok()
+    }
+}
+
+/// Returns the message a panic was raised with.
+pub fn get_panic_message( payload: &(dyn Any + Send) ) -> String
+{
+    match payload.downcast_ref::<&str>()
+    {
+        Some(x) => String::from(*x),
+        None => match payload.downcast_ref::<String>()
+        {
+            Some(x) => x.clone(),
+            None => String::from("unknown panic"),
+        },
+    }
+}
+
+/// Converts a panic into an error describing what went wrong and which file was being processed at the time.
+/// Must be called from the thread that panicked for the file to be known.
+fn convert_panic_to_error( payload: Box<dyn Any + Send> ) -> GesError
+{
+    GesError::Crash { message: get_panic_message( &*payload ), file: get_current_file() }
+}
+
+/// Runs the given work, turning any panic into an error instead of taking the program down with it.
+pub fn run_catching_panics<F>( work: F ) -> Result<(), GesError>
+    where F: FnOnce() -> Result<(), GesError>
+{
+    match panic::catch_unwind( AssertUnwindSafe(work) )
+    {
+        Ok(x) => x,
+        Err(payload) => Err(convert_panic_to_error(payload)),
+    }
+}
+
+/// Runs the given work on its own named thread, with any panic turned into an error.  Use join_worker to get the result.
+pub fn spawn_worker<F>( section: &str, work: F ) -> JoinHandle<Result<(), GesError>>
+    where F: FnOnce() -> Result<(), GesError> + Send + 'static
+{
+    thread::Builder::new()
+        .name( String::from(section) )
+        .spawn( move || run_catching_panics(work) )
+        .expect("Failed to start worker thread!")
+}
+
+/// Waits for a worker thread to finish and returns its result.
+pub fn join_worker( handle: JoinHandle<Result<(), GesError>> ) -> Result<(), GesError>
+{
+    match handle.join()
+    {
+        Ok(x) => x,
+        Err(payload) => Err(convert_panic_to_error(payload)), // Shouldn't happen since workers catch their own panics.
+    }
+}
+
+/// Runs the given work on a thread in the given scope, with any panic turned into an error, for work that borrows from
+/// the thread that started it.  Use join_scoped_worker to get the result.
+pub fn spawn_scoped_worker<'scope, 'env, F>( scope: &'scope Scope<'scope, 'env>, work: F ) -> ScopedJoinHandle<'scope, Result<(), GesError>>
+    where F: FnOnce() -> Result<(), GesError> + Send + 'scope
+{
+    scope.spawn( move || run_catching_panics(work) )
+}
+
+/// Waits for a scoped worker thread to finish and returns its result.
+pub fn join_scoped_worker( handle: ScopedJoinHandle<Result<(), GesError>> ) -> Result<(), GesError>
+{
+    match handle.join()
+    {
+        Ok(x) => x,
+        Err(payload) => Err(convert_panic_to_error(payload)), // Shouldn't happen since workers catch their own panics.
+    }
+}
+
+/// Returns the given path relative to the working directory if it's inside it, which is how tools running in a
+/// repository expect to see it.  Anything outside it is returned as is.
+pub fn get_working_relative_path( file_path: &Path ) -> PathBuf
+{
+    match env::current_dir()
+    {
+        Ok(x) => file_path.strip_prefix(&x).unwrap_or(file_path).to_path_buf(),
+        Err(_) => file_path.to_path_buf(),
+    }
+}
+
+/// Returns the given KeyValues text with every // comment replaced by spaces.  Comments can't start inside a quoted
+/// string, and everything keeps its position so byte offsets and line numbers still point at the original text.
+pub fn blank_out_comments( contents: &str ) -> String
+{
+    let mut bytes = contents.as_bytes().to_vec();
+    let mut in_quotes = false;
+    let mut i = 0;
+
+    while i < bytes.len()
+    {
+        match bytes[i]
+        {
+            b'"' => in_quotes = !in_quotes,
+            b'\n' => in_quotes = false, // An unclosed quote doesn't carry on past the end of its line.
+            b'/' if !in_quotes && bytes.get(i + 1) == Some(&b'/') =>
+            {
+                while i < bytes.len() && bytes[i] != b'\n' && bytes[i] != b'\r'
+                {
+                    bytes[i] = b' ';
+                    i += 1;
+                }
+
+                continue;
+            },
+            _ => {},
+        }
+
+        i += 1;
+    }
+
+    // Every byte we touched was part of a comment, and whole characters are replaced with plain spaces.
+    String::from_utf8( bytes ).unwrap()
+}
+
+// Text from a script is cut down to this many characters when it's quoted in a message.
+const MAX_QUOTED_LENGTH: usize = 100;
+
+/// Returns the given text from a script, cut short if it's too long to quote in a message whole.  Otherwise a garbage
+/// line megabytes long would bury everything else that was reported.
+pub fn get_quotable_text( text: &str ) -> String
+{
+    match text.char_indices().nth( MAX_QUOTED_LENGTH )
+    {
+        Some((x, _)) => format!( "{}...", &text[..x] ),
+        None => String::from(text),
+    }
+}
+
+/// Returns the number of single character insertions, deletions, or substitutions needed to turn one string into the other.
+pub fn get_edit_distance( first: &str, second: &str ) -> usize
+{
+    let second_chars: Vec<char> = second.chars().collect();
+    let mut previous_row: Vec<usize> = (0..second_chars.len() + 1).collect();
+
+    for (i, first_char) in first.chars().enumerate()
+    {
+        let mut current_row = vec![i + 1];
+
+        for (j, second_char) in second_chars.iter().enumerate()
+        {
+            let substitution_cost = if first_char == *second_char { 0 } else { 1 };
+            current_row.push( (previous_row[j] + substitution_cost).min(previous_row[j + 1] + 1).min(current_row[j] + 1) );
+        }
+
+        previous_row = current_row;
+    }
+
+    previous_row[second_chars.len()]
+}
+
+/// Escapes the given string so it can be placed between quotes in JSON output.
+pub fn escape_json_string( text: &str ) -> String
+{
+    let mut escaped_text = String::new();
+
+    for character in text.chars()
+    {
+        match character
+        {
+            '"' => escaped_text.push_str("\\\""),
+            '\\' => escaped_text.push_str("\\\\"),
+            '\n' => escaped_text.push_str("\\n"),
+            '\r' => escaped_text.push_str("\\r"),
+            '\t' => escaped_text.push_str("\\t"),
+            x if (x as u32) < 0x20 => escaped_text.push_str( &format!("\\u{:04x}", x as u32) ),
+            x => escaped_text.push(x),
+        }
+    }
+
+    escaped_text
 }
 
 /// Get the extension of the given path as a &str.  
@@ -118,8 +498,61 @@ pub fn get_file_extension( filepath: &Path ) -> &str
     }
 }
 
-/// Checks every file in the given directory with the given extension using the supplied function.
-pub fn check_all_files_in_dir_with_func( args: &Arguments, dir: &PathBuf, extension: &str, print_type: &str, check_func: fn( args: &Arguments, music_script_path: &PathBuf ) -> Result<(), Error> ) -> Result<(), Error>
+/// Returns the lowercase name, without extension, of every file with the given extension directly inside the given directory.
+/// Returns an empty list if the directory doesn't exist.
+pub fn get_file_stems_in_dir( dir: &PathBuf, extension: &str ) -> Result<Vec<String>, GesError>
+{
+    let mut file_stems = Vec::new();
+
+    if !dir.is_dir()
+    {
+        return Ok(file_stems);
+    }
+
+    for entry in fs::read_dir( dir )?
+    {
+        let entrypath = entry?.path();
+
+        if !entrypath.is_file() || get_file_extension( &entrypath ).to_lowercase() != extension { continue; }
+
+        match entrypath.file_stem()
+        {
+            Some(x) => file_stems.push( x.to_string_lossy().to_lowercase() ),
+            None => {},
+        }
+    }
+
+    file_stems.sort();
+    Ok(file_stems)
+}
+
+/// Reports a single problem listing everything a server owner could clean up under the given rule, if there's anything to list.
+pub fn report_cleanup_list( args: &Arguments, rule_id: &str, description: &str, names: &[String] ) -> Result<(), GesError>
+{
+    if names.is_empty()
+    {
+        return Ok(());
+    }
+
+    let mut warning_text = String::new();
+    warning_text.push_str( &names.len().to_string() );
+    warning_text.push_str( " " );
+    warning_text.push_str( description );
+    warning_text.push_str( ":" );
+
+    for name in names
+    {
+        warning_text.push_str( "\n    " );
+        warning_text.push_str( name );
+    }
+
+    rules::report_message( args, rule_id, warning_text )
+}
+
+/// Checks every file in the given directory with the given extension using the supplied function.  The other function
+/// lists every file besides the checked one that the check reads, so daemon mode knows when to check it again.
+pub fn check_all_files_in_dir_with_func( args: &Arguments, dir: &PathBuf, extension: &str, print_type: &str, check_func: fn( args: &Arguments, music_script_path: &PathBuf ) -> Result<(), GesError>,
+                                         get_dependencies: fn( args: &Arguments, script_path: &PathBuf ) -> Vec<PathBuf> ) -> Result<(), GesError>
 {
     if args.verbose
     {
@@ -127,6 +560,11 @@ pub fn check_all_files_in_dir_with_func( args: &Arguments, dir: &PathBuf, extens
     }
 
     let mut scanned_file_count = 0;
+    let mut unchanged_file_count = 0;
+
+    let install_fingerprint = validation_cache::get_install_fingerprint( args )?;
+
+    let _watchdog = watchdog::watch_walk( dir );
 
     // Make sure our sound directory exists and if so scan it for files.
     for entry in WalkDir::new( &dir )
@@ -143,19 +581,11 @@ pub fn check_all_files_in_dir_with_func( args: &Arguments, dir: &PathBuf, extens
         if file_extension.to_lowercase() != extension { continue; }
 
         // Run the check func, appending the file that caused the error to the error message if it failed.
-        match check_func( args, &PathBuf::from(entrypath) )
+        match validation_cache::check_with_cache( args, print_type, &PathBuf::from(entrypath), install_fingerprint, check_func, get_dependencies )
         {
-            Ok(_) => (),
-            Err(e) => 
-            {
-                let mut error_text = String::new();
-                error_text.push_str("While proccessing ");
-                error_text.push_str( entrypath.to_str().unwrap_or("an unidentifiable file") );
-                error_text.push_str(" the following error was encountered:\n");
-                error_text.push_str(e.description());
-
-                return Err(Error::new(ErrorKind::InvalidData, error_text ));
-            }
+            Ok(true) => unchanged_file_count += 1,
+            Ok(false) => (),
+            Err(e) => return Err(e.in_file( entrypath )),
         }
         scanned_file_count += 1; // We've successfully scanned a file, so add it to the final count.
 
@@ -168,15 +598,22 @@ pub fn check_all_files_in_dir_with_func( args: &Arguments, dir: &PathBuf, extens
     // Let the user know of our success.
     println!("\nAll {} {} in {} are formatted correctly!", scanned_file_count, print_type, dir.display());
 
+    if unchanged_file_count > 0
+    {
+        println!("{} of them were unchanged since the last fullcheck, so they weren't checked again.", unchanged_file_count);
+    }
+
     Ok(())
 }
 
 /// Removes all files in the given directory tree with the given extension.
-pub fn remove_files_in_directory( files_dir: &PathBuf, target_extension: &str ) -> Result<(), Error>
+pub fn remove_files_in_directory( files_dir: &PathBuf, target_extension: &str ) -> Result<(), GesError>
 {
     // Make sure our  directory exists and if so scan it for files.
     if files_dir.is_dir()
     {
+        let _watchdog = watchdog::watch_walk( files_dir );
+
         for entry in WalkDir::new( files_dir ) 
         {
             let entry = entry?;
@@ -194,7 +631,7 @@ pub fn remove_files_in_directory( files_dir: &PathBuf, target_extension: &str )
             if !target_extension.is_empty() && file_extension.to_lowercase() != target_extension { continue; }
 
             // Looks like everything checks out...delete the file.
-            fs::remove_file(entrypath)?;
+            file_writer::remove_file(entrypath)?;
         }
     }
 
@@ -202,13 +639,15 @@ pub fn remove_files_in_directory( files_dir: &PathBuf, target_extension: &str )
 }
 
 /// Counts all files in the given directory tree.
-pub fn count_files_in_directory( files_dir: &PathBuf ) -> Result<u32, Error>
+pub fn count_files_in_directory( files_dir: &PathBuf ) -> Result<u32, GesError>
 {
     let mut file_count = 0;
 
     // Make sure our  directory exists and if so scan it for files.
     if files_dir.is_dir()
     {
+        let _watchdog = watchdog::watch_walk( files_dir );
+
         for entry in WalkDir::new( files_dir ) 
         {
             let entry = entry?;
@@ -224,6 +663,13 @@ pub fn count_files_in_directory( files_dir: &PathBuf ) -> Result<u32, Error>
     Ok(file_count)
 }
 
+/// Formats a byte count as a human readable string.
+pub fn format_byte_count( bytes: u64 ) -> String
+{
+    let megabytes = bytes as f64 / (1024.0 * 1024.0);
+    format!("{:.1} MB", megabytes)
+}
+
 /// Splits up a string representation of a filepath and extracts the last extension from it.
 /// ex: file.xxx.yyy will return a string slice of yyy.  Returns a blank string slice if no extension found.
 pub fn get_string_file_extension( filepath: &str ) -> &str
@@ -242,51 +688,166 @@ pub fn get_string_file_extension( filepath: &str ) -> &str
     file_extension
 }
 
-/// Walks each directory in cache_dirs and runs get_files_in_directory on them with the target_filetype and disallowed_filetype
-/// parameters.  After completion, the results will be stored in the contents of directory_cache and mutex will be set to true and
-/// a reference to the contents of directory_cache will be returned.
-/// On subsequent calls with references to the same two variables, the computation is skipped and the contents of
-/// directory cache are returned directly.  This saves us from having to walk a directory set multiple times when
-/// the contents will not change between invocations.
-pub fn compute_or_get_safe_reference_to_directory_cache( cache_dirs: Vec<&PathBuf>, target_filetype: &str, disallowed_filetypes: &[&str], mutex: &'static Mutex<bool>, directory_cache: &'static mut Option<(Vec<String>, Vec<String>)> ) -> Result<&'static (Vec<String>, Vec<String>), Error>
+/// The relative path of every file in a set of directories.  The first list is lowercase for case-insensitive
+/// comparisons, and the second keeps the original case for writing into scripts.
+pub type DirectoryTree = (Vec<String>, Vec<String>);
+
+/// Everything that decides what ends up in a cached directory tree.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct DirectoryCacheKey
+{
+    dirs: Vec<PathBuf>,
+    target_filetype: String,
+    disallowed_filetypes: Vec<String>,
+    // Filters are told apart by address, so pass the same function every time rather than a new closure.
+    file_filter: usize,
+}
+
+// Every entry has its own lock, so scanning one tree never holds up a request for a different one.
+type DirectoryCacheEntry = Arc<Mutex<Option<Arc<DirectoryTree>>>>;
+
+lazy_static!
+{
+    static ref DIRECTORY_CACHE: Mutex<HashMap<DirectoryCacheKey, DirectoryCacheEntry>> = Mutex::new(HashMap::new());
+}
+
+/// Gets every file in the given directories that passes the filters, in the same form as get_files_in_directory.
+/// The files come from the scan service, so directories are only walked once no matter how many modules ask about them,
+/// and the result is kept until something invalidates it.
+pub fn get_cached_directory_tree( dirs: &[&PathBuf], target_filetype: &str, disallowed_filetypes: &[&str], file_filter: FileFilter ) -> Result<Arc<DirectoryTree>, GesError>
 {
-    // First grab the mutex guard for the init variable.  If we're uninitalized, then we'll grab this and
-    // do the computations, and set the value to true.  If we're in the proccess of initalizing, we'll wait
-    // for the lock and after we aquire it we'll be initalized.  If we're initalized, we'll get the lock to
-    // confirm that and then just return a reference to dirlist.  Once we hit the end of the unsafe block we
-    // will drop the lock and let the next iteration take over.
-    let mut init_guard = mutex.lock().unwrap();
-    let has_init = init_guard.deref_mut();
+    let key = DirectoryCacheKey
+    {
+        dirs: dirs.iter().map(|x| x.to_path_buf()).collect(),
+        target_filetype: String::from(target_filetype),
+        disallowed_filetypes: disallowed_filetypes.iter().map(|x| String::from(*x)).collect(),
+        file_filter: file_filter as usize,
+    };
 
-    if !*has_init
+    let entry = DIRECTORY_CACHE.lock().unwrap().entry(key).or_insert_with(|| Arc::new(Mutex::new(None))).clone();
+
+    // Anyone else after the same tree waits here until it's been scanned, rather than scanning it again themselves.
+    let mut cached_tree = entry.lock().unwrap();
+
+    match *cached_tree
     {
-        *directory_cache = Some((Vec::new(), Vec::new()));
+        Some(ref x) => return Ok(x.clone()),
+        None => {},
     }
 
-    let dirlist_ref = match *directory_cache
+    let mut directory_tree: DirectoryTree = (Vec::new(), Vec::new());
+
+    for dir in dirs
     {
-            Some(ref mut x) => &mut *x,
-            None => return Err(Error::new(ErrorKind::Other, "Failed to create directory cache!")),
-    };
+        let (mut comp_file_paths, mut write_file_paths) = scan_service::get_scan_service().get_files( dir, target_filetype, disallowed_filetypes, file_filter )?;
+
+        directory_tree.0.append(&mut comp_file_paths);
+        directory_tree.1.append(&mut write_file_paths);
+    }
+
+    let directory_tree = Arc::new(directory_tree);
+    *cached_tree = Some(directory_tree.clone());
+
+    Ok(directory_tree)
+}
+
+/// Drops every cached directory tree and walk that the given path is part of, so the next request for one walks it again.
+/// Anyone still holding one of the dropped trees keeps their copy.
+pub fn invalidate_directory_cache( changed_path: &Path )
+{
+    scan_service::get_scan_service().invalidate( changed_path );
+    DIRECTORY_CACHE.lock().unwrap().retain(|key, _| !key.dirs.iter().any(|x| changed_path.starts_with(x)));
+}
+
+/// Drops every cached directory tree and walk, for when anything on disk could have changed since they were made.
+pub fn clear_directory_cache()
+{
+    scan_service::get_scan_service().invalidate_all();
+    DIRECTORY_CACHE.lock().unwrap().clear();
+}
+
+// Files smaller than this are read through a plain buffered reader, as the cost of setting up
+// a mapping outweighs any savings on small scripts and materials.
+const MMAP_THRESHOLD_BYTES: u64 = 1024 * 1024;
+
+/// A readable view of a file's contents, either memory-mapped or streamed through a buffered reader.
+pub enum FileSource
+{
+    Mapped(Cursor<Mmap>),
+    Streamed(BufReader<File>),
+}
+
+impl Read for FileSource
+{
+    fn read( &mut self, buf: &mut [u8] ) -> io::Result<usize>
+    {
+        match *self
+        {
+            FileSource::Mapped(ref mut x) => x.read(buf),
+            FileSource::Streamed(ref mut x) => x.read(buf),
+        }
+    }
+}
 
-    if !*has_init
+/// Opens the given file for reading, memory-mapping it if it's large enough to benefit from it.
+/// If the mapping fails for any reason (network drives, special files, etc) we fall back to streaming the file instead.
+pub fn open_file_source( file_path: &Path ) -> Result<FileSource, GesError>
+{
+    let file = File::open(file_path)?;
+    let file_size = file.metadata()?.len();
+
+    if file_size >= MMAP_THRESHOLD_BYTES
     {
-        for dir in cache_dirs
+        // Unsafe because the mapping is invalidated if another process truncates the file while we're reading it.
+        // The files we read are release assets that nothing else should be touching while the program runs.
+        match unsafe { Mmap::map(&file) }
         {
-            let (mut comp_file_paths, mut write_file_paths) = get_files_in_directory( &dir, target_filetype, disallowed_filetypes )?;
-            
-            dirlist_ref.0.append(&mut comp_file_paths);
-            dirlist_ref.1.append(&mut write_file_paths);
+            Ok(x) => return Ok(FileSource::Mapped(Cursor::new(x))),
+            Err(_) => {}, // Not every filesystem supports mapping, just stream it instead.
         }
-        *has_init = true;
     }
 
-    return Ok(dirlist_ref);
+    Ok(FileSource::Streamed(BufReader::new(file)))
+}
+
+// GE:S doesn't ship a script anywhere near this big, so anything larger is a stray file that happens to have a script's
+// extension.  Reading it all into memory could take the whole fullcheck down with it.
+const MAX_SCRIPT_SIZE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Reads the script at the given path, or returns a parse error if it's too big to be a real script.  The limit holds
+/// while reading too, so a file that grows after it's opened can't get past it.
+pub fn read_script_file( script_path: &Path ) -> Result<String, GesError>
+{
+    let contents = read_script_bytes( script_path )?;
+
+    // Same error read_to_string gives for a file that isn't UTF-8.
+    String::from_utf8( contents ).map_err(|_| GesError::from(io::Error::new( io::ErrorKind::InvalidData, "stream did not contain valid UTF-8" )))
+}
+
+/// Same as read_script_file, but leaves the contents as they are for scripts that aren't UTF-8.
+pub fn read_script_bytes( script_path: &Path ) -> Result<Vec<u8>, GesError>
+{
+    let script_file = File::open(script_path)?;
+    let mut contents = Vec::new();
+
+    // Checking the size up front saves reading the first part of a huge file just to find out it's too big.
+    if script_file.metadata()?.len() > MAX_SCRIPT_SIZE_BYTES
+       || BufReader::new(script_file).take( MAX_SCRIPT_SIZE_BYTES + 1 ).read_to_end( &mut contents )? as u64 > MAX_SCRIPT_SIZE_BYTES
+    {
+        let mut error_text = String::new();
+        error_text.push_str("This file is bigger than ");
+        error_text.push_str( &(MAX_SCRIPT_SIZE_BYTES / (1024 * 1024)).to_string() );
+        error_text.push_str(" MB, which is far too big to be a script.  If it isn't one, move it somewhere else or give it a different extension.");
+
+        return Err(GesError::Parse { file: script_path.to_path_buf(), line: 0, message: error_text });
+    }
+
+    Ok(contents)
 }
 
 #[cfg(test)]
 /// Tests every file in the given directory using the given parameters.
-pub fn do_validity_test( args: &Arguments, dir: &PathBuf, print_type: &str, check_func: fn( args: &Arguments, script_path: &PathBuf ) -> Result<(), Error>, should_pass: bool )
+pub fn do_validity_test( args: &Arguments, dir: &PathBuf, print_type: &str, check_func: fn( args: &Arguments, script_path: &PathBuf ) -> Result<(), GesError>, should_pass: bool )
 {
     for entry in WalkDir::new( dir )
     {
@@ -312,8 +873,8 @@ pub fn do_validity_test( args: &Arguments, dir: &PathBuf, print_type: &str, chec
 /// Tests the result of a given script creator with the given check function, passing if the check is valid and failing if it is not.
 pub fn test_script_creator( args: &Arguments, 
                         file_name: &str, 
-                        create_func: fn( args: &Arguments, script_path: &PathBuf ) -> Result<(), Error>,
-                        check_func: fn( args: &Arguments, script_path: &PathBuf ) -> Result<(), Error> ) 
+                        create_func: fn( args: &Arguments, script_path: &PathBuf ) -> Result<(), GesError>,
+                        check_func: fn( args: &Arguments, script_path: &PathBuf ) -> Result<(), GesError> ) 
 {
     // Now that we've confirmed the script checker works...let's create a file and use it to check it!
     let mut temp_dir = get_root_test_directory();
@@ -334,8 +895,7 @@ pub fn test_script_creator( args: &Arguments,
     // If we got here with no erors we passed the test!
 }
 
-#[cfg(test)]
-/// Creates a set of barebones arguments for testing.
+/// Creates a set of barebones arguments for testing and fuzzing.
 pub fn get_barebones_args() -> Arguments
 {
     let test_rootdir = get_root_test_directory();
@@ -358,15 +918,92 @@ pub fn get_barebones_args() -> Arguments
         maxplayers: 16,
         resintensity: 7,
         teamthresh: 12,
+        map_group: None,
+        only_gamemodes: Vec::new(),
+        playtest_expires: None,
+        playtest_notes: None,
+        preset: None,
+        presets_file: None,
+        given_values: Vec::new(),
+        section_weights: Vec::new(),
         compress: false,
         recompress: false,
         verbose: false,
+        trace: false,
         fullcheck: false,
+        daemon: false,
+        daemon_interval: ::daemon::DEFAULT_INTERVAL,
+        report_dir: PathBuf::from("fullcheck_reports"),
+        notify_hook: None,
         noexitprompt: true,
+        pause_timeout: 0,
+        compressed_out: None,
+        server_package: None,
+        include_sources: false,
+        reslist_dirs: false,
+        legacy_format: false,
+        stdin: false,
+        decompress: None,
+        decompress_out: None,
+        keygen: None,
+        verify_release: None,
+        verify_key: None,
+        gen_manifest: None,
+        gen_manifest_out: PathBuf::from("stock_content.txt"),
+        compare_installs: None,
+        mirror: None,
+        mirror_dry_run: false,
+        analyze_log: None,
+        preview_weight: false,
+        preview_weight_script: None,
+        preview_weight_csv: false,
+        gen_fixtures: None,
+        copy_music_from: None,
+        bench_fixtures: None,
+        bench_file_count: ::bench_fixtures::DEFAULT_FILE_COUNT,
+        sign_key: None,
+        hash_algorithm: HashAlgorithm::Blake3,
+        delta_from: None,
+        delta_out: None,
+        torrent: None,
+        changelog: None,
+        author: None,
+        ignore_lock: false,
+        verify_deterministic: false,
+        from_compile: None,
+        server_config: false,
+        dedupe: false,
+        trackers: Vec::new(),
+        web_seeds: Vec::new(),
+        walk_timeout: ::watchdog::DEFAULT_WALK_TIMEOUT,
+        hydrate: false,
+        check_only: false,
+        user_scope: false,
+        check_update: false,
+        auto_update_check: false,
+        crash_report: false,
+        music_areas: Vec::new(),
+        fix: false,
+        min_tracks: 4,
+        sample_music: 0,
+        music_seed: None,
+        match_length: 15,
+        rules_file: None,
+        rule_overrides: Vec::new(),
+        list_rules: false,
+        categories_file: None,
+        schema_file: None,
+        stock_content_file: None,
+        offline: false,
+        ges_version: None,
+        sarif: None,
+        discord_webhook: None,
+        smoke_test: None,
+        smoke_test_time: ::smoke_test::DEFAULT_SMOKE_TEST_TIME,
+        output: ::argument_handler::OutputFormat::Text,
     }
 }
 
-#[cfg(test)]
 /// Locates the project's root directory
 pub fn get_root_test_directory() -> PathBuf
 {
@@ -399,4 +1036,149 @@ mod tests
         assert_eq!( get_string_file_extension("some/folder/.git\\somefile"), "" );
         assert_eq!( get_string_file_extension("some/folder/.git\\somefile.good"), "good" );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_get_file_stems_in_dir()
+    {
+        let mut map_dir = get_root_test_directory();
+        map_dir.push("gesdir");
+        map_dir.push("gesource");
+        map_dir.push("maps");
+
+        assert_eq!( get_file_stems_in_dir( &map_dir, "bsp" ).unwrap(), vec![String::from("some_other_map")] );
+        assert!( get_file_stems_in_dir( &map_dir, "res" ).unwrap().is_empty() );
+
+        map_dir.push("not_a_real_directory");
+        assert!( get_file_stems_in_dir( &map_dir, "bsp" ).unwrap().is_empty() );
+    }
+
+    #[test]
+    fn test_directory_cache()
+    {
+        let mut test_dir = get_root_test_directory();
+        test_dir.push("temp");
+        test_dir.push("directory_cache");
+
+        let mut other_dir = test_dir.clone();
+        other_dir.push("other");
+
+        let _ = fs::remove_dir_all(&test_dir);
+        fs::create_dir_all(&other_dir).unwrap();
+        fs::write( test_dir.join("first.txt"), b"first" ).unwrap();
+
+        // Trees for different directories are kept apart, and later calls get the cached tree.
+        let first_tree = get_cached_directory_tree( &[&test_dir], "txt", &[], keep_all_files ).unwrap();
+        assert_eq!( first_tree.0, vec![String::from("first.txt")] );
+        assert!( get_cached_directory_tree( &[&other_dir], "txt", &[], keep_all_files ).unwrap().0.is_empty() );
+
+        fs::write( test_dir.join("second.txt"), b"second" ).unwrap();
+        assert_eq!( get_cached_directory_tree( &[&test_dir], "txt", &[], keep_all_files ).unwrap().0.len(), 1 );
+
+        // Once the new file is known about, the next call walks the directory again.
+        invalidate_directory_cache( &test_dir.join("second.txt") );
+        assert_eq!( get_cached_directory_tree( &[&test_dir], "txt", &[], keep_all_files ).unwrap().0.len(), 2 );
+        assert_eq!( first_tree.0.len(), 1 );
+
+        fs::remove_dir_all(&test_dir).unwrap();
+    }
+
+    #[test]
+    fn test_blank_out_comments()
+    {
+        assert_eq!( blank_out_comments("\"file\" \"a.mp3\" // é\r\n}"), "\"file\" \"a.mp3\"      \r\n}" );
+        assert_eq!( blank_out_comments("\"http://example\" file"), "\"http://example\" file" );
+        assert_eq!( blank_out_comments("// all\nkept"), "      \nkept" );
+    }
+
+    #[test]
+    fn test_escape_json_string()
+    {
+        assert_eq!( escape_json_string("plain"), "plain" );
+        assert_eq!( escape_json_string("say \"hi\""), "say \\\"hi\\\"" );
+        assert_eq!( escape_json_string("C:\\maps\nnext"), "C:\\\\maps\\nnext" );
+        assert_eq!( escape_json_string("\u{1}"), "\\u0001" );
+    }
+
+    #[test]
+    fn test_open_file_source()
+    {
+        // Both a small file and a large file should read back exactly what was written, however they were opened.
+        let mut temp_dir = get_root_test_directory();
+        temp_dir.push("temp");
+
+        let small_contents = vec![7u8; 16];
+        let large_contents: Vec<u8> = (0..MMAP_THRESHOLD_BYTES + 5).map(|x| (x % 251) as u8).collect();
+
+        for (file_name, contents) in &[("source_small.bin", &small_contents), ("source_large.bin", &large_contents)]
+        {
+            let mut file_path = temp_dir.clone();
+            file_path.push(file_name);
+            fs::write(&file_path, contents).unwrap();
+
+            let mut read_contents = Vec::new();
+            open_file_source(&file_path).unwrap().read_to_end(&mut read_contents).unwrap();
+            fs::remove_file(&file_path).unwrap();
+
+            assert!( read_contents == **contents, "{} did not read back correctly!", file_name );
+        }
+    }
+
+    #[test]
+    fn test_read_script_file()
+    {
+        let mut script_path = get_root_test_directory();
+        script_path.push("temp");
+        script_path.push("oversized_script.res");
+
+        fs::write( &script_path, "\"resources\" { \"a.vtf\" \"file\" }" ).unwrap();
+        assert!( read_script_file( &script_path ).unwrap().starts_with("\"resources\"") );
+
+        // The file is sparse, so this doesn't actually take up any room on disk.
+        File::create( &script_path ).unwrap().set_len( 2 * 1024 * 1024 * 1024 ).unwrap();
+        let result = read_script_file( &script_path );
+        fs::remove_file( &script_path ).unwrap();
+
+        match result
+        {
+            Err(GesError::Parse { line, .. }) => assert_eq!( line, 0 ),
+            x => panic!( "Expected a parse error but got {:?}", x.map(|y| y.len()) ),
+        }
+    }
+
+    #[test]
+    fn test_spawn_worker_panic()
+    {
+        // A worker that crashes should report what happened and what it was working on, not take the program with it.
+        let handle = spawn_worker( "test worker", ||
+        {
+            set_current_file( Path::new("gesource/maps/broken_map.res") );
+            panic!("something broke");
+        });
+
+        let error_text = join_worker( handle ).unwrap_err().to_string();
+        assert!( error_text.contains("something broke") );
+        assert!( error_text.contains("broken_map.res") );
+
+        assert!( join_worker( spawn_worker( "test worker", || Ok(()) ) ).is_ok() );
+    }
+
+    #[test]
+    fn test_spawn_scoped_worker_panic()
+    {
+        let file_path = Path::new("gesource/maps/broken_map.bsp");
+
+        let error_text = thread::scope(|scope|
+        {
+            let handle = spawn_scoped_worker( scope, ||
+            {
+                set_current_file( file_path );
+                panic!("something broke");
+            });
+
+            join_scoped_worker( handle ).unwrap_err().to_string()
+        });
+
+        assert!( error_text.contains("something broke") );
+        assert!( error_text.contains("broken_map.bsp") );
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// smoke_test: Loads the map on a real server for a little while and reports what the engine complains about.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use argument_handler::Arguments;
+use rules;
+use shared;
+use error::GesError;
+
+/// How long the server runs with the map loaded if no time is given, in seconds.
+pub const DEFAULT_SMOKE_TEST_TIME: u64 = 30;
+
+// Lowercase text in a line of engine output that means a script couldn't be read.  Checked before the resource
+// markers, since a script the engine can't parse is a worse problem than one it can't find.
+static SCRIPT_ERROR_MARKERS: &[&'static str] = &["keyvalues error", "error parsing", "parse error", "syntaxerror", "traceback (most recent call last)"];
+
+// Lowercase text in a line of engine output that means something the map uses couldn't be found or loaded.
+static MISSING_RESOURCE_MARKERS: &[&'static str] = &["not found", "missing from disk", "failed to load", "unable to load", "couldn't find", "could not find", "error loading"];
+
+// Lowercase text in a line of engine output that means the map itself never made it onto the server.
+static MAP_FAILURE_MARKERS: &[&'static str] = &["map load failed", "couldn't spawn server", "host_error"];
+
+// Reports list at most this many lines of output each, since one missing texture can repeat for every frame.
+const MAX_REPORTED_LINES: usize = 20;
+
+/// Launches the server given with --smoke-test on the map for --smoke-test-time seconds, then reports every line of
+/// its console output that mentions a missing resource or a script it couldn't read.  The map has to be installed
+/// in the GE:S install first, since that's where the server loads it from.
+pub fn run_smoke_test( args: &Arguments, map_name: &str ) -> Result<(), GesError>
+{
+    let server_path = match args.smoke_test
+    {
+        Some(ref x) => x,
+        None => return Ok(()),
+    };
+
+    let mut installed_map = args.gesdir.clone();
+    installed_map.push("maps");
+    installed_map.push( map_name );
+    installed_map.set_extension("bsp");
+
+    if !installed_map.is_file()
+    {
+        let mut warning_text = String::new();
+        warning_text.push_str( &installed_map.display().to_string() );
+        warning_text.push_str(" doesn't exist, so the smoke test was skipped.  Copy the release into the GE:S install to test it on a server.");
+
+        shared::print_warning( &warning_text );
+        return Ok(());
+    }
+
+    // The server only writes to its own console on Windows, so -condebug is the only way to see everything it says.
+    let mut console_log_path = args.gesdir.clone();
+    console_log_path.push("console.log");
+    let console_log_start = fs::metadata(&console_log_path).map(|x| x.len()).unwrap_or(0);
+
+    if args.verbose
+    {
+        println!( "Running {} on {} for {} seconds.", server_path.display(), map_name, args.smoke_test_time );
+    }
+
+    let mut command = Command::new( server_path );
+    command.arg("-game").arg( &args.gesdir )
+           .args( &["-console", "-norestart", "-condebug", "+sv_lan", "1", "+maxplayers", "2", "+map", map_name] )
+           .stdin( Stdio::null() )
+           .stdout( Stdio::piped() )
+           .stderr( Stdio::piped() );
+
+    // Launcher scripts like srcds_run start the real server as a child of their own, so it gets a process group
+    // that can be stopped all at once.
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    let mut server = command.spawn().map_err(|e| GesError::from(e).in_file( server_path ))?;
+
+    let output_lines = Arc::new(Mutex::new(Vec::new()));
+    collect_lines( server.stdout.take().unwrap(), output_lines.clone() );
+    collect_lines( server.stderr.take().unwrap(), output_lines.clone() );
+
+    let deadline = Instant::now() + Duration::from_secs( args.smoke_test_time );
+    let exit_status = loop
+    {
+        match server.try_wait()?
+        {
+            Some(x) => break Some(x),
+            None if Instant::now() >= deadline => break None,
+            None => thread::sleep( Duration::from_millis(250) ),
+        }
+    };
+
+    if exit_status == None
+    {
+        stop_server( &mut server );
+    }
+
+    // Launcher scripts can leave something else holding the output open, so give the readers a moment and move on
+    // rather than waiting on them.
+    thread::sleep( Duration::from_millis(500) );
+    let mut output_lines = output_lines.lock().unwrap().clone();
+    output_lines.extend( read_console_log( &console_log_path, console_log_start ) );
+
+    report_output_lines( args, &output_lines )?;
+
+    match exit_status
+    {
+        Some(x) =>
+        {
+            let mut error_text = String::new();
+            error_text.push_str("The server shut down with ");
+            error_text.push_str( &x.to_string() );
+            error_text.push_str(" before the smoke test was over!  The last of its output was:");
+
+            let skipped_count = output_lines.len().saturating_sub( MAX_REPORTED_LINES );
+            for line in output_lines.iter().skip( skipped_count )
+            {
+                error_text.push_str("\n    ");
+                error_text.push_str( line.trim_end() );
+            }
+
+            rules::report_message( args, "smoke-server-failed", error_text )
+        },
+        None => Ok(()),
+    }
+}
+
+/// Stops the server along with anything it started.
+fn stop_server( server: &mut Child )
+{
+    #[cfg(unix)]
+    {
+        let _ = Command::new("kill").arg("-KILL").arg("--").arg( format!( "-{}", server.id() ) ).stderr( Stdio::null() ).status();
+    }
+
+    let _ = server.kill();
+    let _ = server.wait();
+}
+
+/// Returns the rule a line of engine output should be reported under, if it's worth reporting at all.
+pub fn classify_output_line( line: &str ) -> Option<&'static str>
+{
+    let line = line.to_lowercase();
+
+    if MAP_FAILURE_MARKERS.iter().any(|x| line.contains(x))
+    {
+        Some("smoke-server-failed")
+    }
+    else if SCRIPT_ERROR_MARKERS.iter().any(|x| line.contains(x))
+    {
+        Some("smoke-script-error")
+    }
+    else if MISSING_RESOURCE_MARKERS.iter().any(|x| line.contains(x))
+    {
+        Some("smoke-missing-resource")
+    }
+    else
+    {
+        None
+    }
+}
+
+/// Reports every line of output worth reporting under its rule, each line only once.
+fn report_output_lines( args: &Arguments, output_lines: &[String] ) -> Result<(), GesError>
+{
+    // Warnings go first, since a failure stops anything after it from being reported.
+    let rule_descriptions = [("smoke-missing-resource", "lines of server output say something couldn't be found"),
+                             ("smoke-script-error", "lines of server output say a script couldn't be read"),
+                             ("smoke-server-failed", "lines of server output say the map couldn't be loaded")];
+
+    for &(rule_id, description) in &rule_descriptions
+    {
+        let mut reported_lines: Vec<String> = Vec::new();
+
+        for line in output_lines.iter().map(|x| x.trim()).filter(|x| classify_output_line( x ) == Some(rule_id))
+        {
+            if !reported_lines.iter().any(|x| x == line)
+            {
+                reported_lines.push( String::from(line) );
+            }
+        }
+
+        if reported_lines.len() > MAX_REPORTED_LINES
+        {
+            let hidden_count = reported_lines.len() - MAX_REPORTED_LINES;
+            reported_lines.truncate( MAX_REPORTED_LINES );
+            reported_lines.push( format!( "...and {} more.", hidden_count ) );
+        }
+
+        shared::report_cleanup_list( args, rule_id, description, &reported_lines )?;
+    }
+
+    Ok(())
+}
+
+/// Reads lines from the given output on another thread until it closes, adding each one to the given list.
+fn collect_lines<R>( output: R, lines: Arc<Mutex<Vec<String>>> )
+    where R: Read + Send + 'static
+{
+    thread::spawn( move ||
+    {
+        let mut reader = BufReader::new(output);
+        let mut line = Vec::new();
+
+        // Engine output isn't always valid UTF-8, but the parts worth reporting are.
+        while reader.read_until( b'\n', &mut line ).unwrap_or(0) > 0
+        {
+            lines.lock().unwrap().push( String::from_utf8_lossy(&line).into_owned() );
+            line.clear();
+        }
+    });
+}
+
+/// Returns every line written to the console log after the given byte offset.
+fn read_console_log( console_log_path: &PathBuf, start: u64 ) -> Vec<String>
+{
+    let mut contents = Vec::new();
+
+    match fs::File::open(console_log_path).and_then(|mut x| x.read_to_end(&mut contents))
+    {
+        Ok(_) => {},
+        Err(_) => return Vec::new(),
+    }
+
+    // A log shorter than before was cleared by the server, so all of it is new.
+    let start = if (start as usize) <= contents.len() { start as usize } else { 0 };
+
+    String::from_utf8_lossy(&contents[start..]).lines().map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_classify_output_line()
+    {
+        assert_eq!( classify_output_line( "Failed to load sound \"ambient/ges/drip.wav\", file probably missing from disk/repository" ), Some("smoke-missing-resource") );
+        assert_eq!( classify_output_line( "Material \"concrete/custom_floor\" not found." ), Some("smoke-missing-resource") );
+        assert_eq!( classify_output_line( "KeyValues Error: RecursiveLoadFromBuffer:  got EOF instead of keyname" ), Some("smoke-script-error") );
+        assert_eq!( classify_output_line( "Host_Error: Map load failed: maps/ge_custom.bsp not found or invalid" ), Some("smoke-server-failed") );
+        assert_eq!( classify_output_line( "Executing dedicated server config file server.cfg" ), None );
+    }
+}
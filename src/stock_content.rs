@@ -0,0 +1,254 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// stock_content: Knows the hash of every file that ships with GE:S, so releases can be checked against it without an install.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use argument_handler::Arguments;
+use scan_service;
+use shared;
+use file_writer;
+use generation_metadata;
+use error::GesError;
+
+// The stock content database that comes with the program, which a stock content file can add to or update.
+static BUILTIN_STOCK_CONTENT: &'static str = include_str!("../resources/stock_content.txt");
+
+// SHA-256 hashes are always this many hex digits.
+const HASH_LENGTH: usize = 64;
+
+// Directories the game fills with files from servers and players, which aren't stock content even in a fresh install.
+static PLAYER_DIRECTORIES: &[&'static str] = &["custom/", "download/", "downloads/", "maps/graphs/", "save/", "screenshots/"];
+
+/// A file that ships with GE:S.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StockFile
+{
+    /// The lowercase path of the file relative to the gesource directory.
+    pub path: String,
+    /// The lowercase SHA-256 hash of the file, the same as manifest::hash_file gives.
+    pub hash: String,
+}
+
+lazy_static!
+{
+    static ref BUILTIN_STOCK_FILES: Vec<StockFile> = parse_stock_content( &PathBuf::from("stock_content.txt"), BUILTIN_STOCK_CONTENT ).unwrap();
+    static ref ADDED_STOCK_FILES: RwLock<Vec<StockFile>> = RwLock::new(Vec::new());
+}
+
+/// Replaces the entries a stock content file added to the built in database.  Every later lookup sees them.
+pub fn set_added_stock_files( stock_files: Vec<StockFile> )
+{
+    *ADDED_STOCK_FILES.write().unwrap() = stock_files;
+}
+
+/// Returns true if stock content should be looked up in the database rather than a GE:S install, either because
+/// --offline asked for it or because there's no install to look in.
+pub fn use_stock_database( args: &Arguments ) -> bool
+{
+    args.offline || !args.gesdir.is_dir()
+}
+
+/// Returns true if the database knows about any stock content at all.
+pub fn has_stock_files() -> bool
+{
+    !BUILTIN_STOCK_FILES.is_empty() || !ADDED_STOCK_FILES.read().unwrap().is_empty()
+}
+
+/// Returns the lowercase path of every stock file with the given extension in the given directory, relative to it,
+/// the same way a directory tree would hold them.
+pub fn get_stock_paths( directory: &str, extension: &str ) -> Vec<String>
+{
+    let prefix = format!( "{}/", normalize_path( directory ) );
+    let extension = format!( ".{}", extension.to_lowercase() );
+
+    let added_stock_files = ADDED_STOCK_FILES.read().unwrap();
+
+    let mut stock_paths: Vec<String> = added_stock_files.iter().chain( BUILTIN_STOCK_FILES.iter() )
+                                                        .filter(|x| x.path.starts_with(&prefix) && x.path.ends_with(&extension))
+                                                        .map(|x| String::from( &x.path[prefix.len()..] ))
+                                                        .collect();
+    stock_paths.sort();
+    stock_paths.dedup();
+
+    stock_paths
+}
+
+/// Returns the stock file at the given path relative to the gesource directory, if there is one.
+pub fn get_stock_file( relative_path: &str ) -> Option<StockFile>
+{
+    let relative_path = normalize_path( relative_path );
+
+    // Entries from a stock content file win, since they're from a newer patch than the built in ones.
+    match ADDED_STOCK_FILES.read().unwrap().iter().rev().find(|x| x.path == relative_path)
+    {
+        Some(x) => return Some(x.clone()),
+        None => {},
+    }
+
+    BUILTIN_STOCK_FILES.iter().find(|x| x.path == relative_path).cloned()
+}
+
+/// Returns every stock file with the given hash.
+pub fn find_stock_files_by_hash( hash: &str ) -> Vec<StockFile>
+{
+    let hash = hash.to_lowercase();
+
+    let mut stock_files: Vec<StockFile> = ADDED_STOCK_FILES.read().unwrap().iter().chain( BUILTIN_STOCK_FILES.iter() ).filter(|x| x.hash == hash).cloned().collect();
+
+    // A file updated by a stock content file no longer has its old hash.
+    stock_files.retain(|x| get_stock_file( &x.path ).as_ref() == Some(x));
+    stock_files.sort_by(|x, y| x.path.cmp(&y.path));
+    stock_files.dedup();
+
+    stock_files
+}
+
+/// Writes the hash of every file in the given GE:S install to a stock content file at the given path, returning how
+/// many files it holds.  The install should be a clean one, since anything added to it would count as stock content.
+pub fn write_stock_content_file( args: &Arguments, install_dir: &PathBuf, stock_content_path: &PathBuf ) -> Result<usize, GesError>
+{
+    let scan_service = scan_service::get_scan_service();
+    let (comp_paths, write_paths) = scan_service.get_files( install_dir, "", &[], shared::keep_all_files )?;
+
+    let mut stock_paths: Vec<(String, String)> = comp_paths.into_iter()
+                                                           .zip( write_paths )
+                                                           .filter(|x| !PLAYER_DIRECTORIES.iter().any(|y| x.0.starts_with(y)))
+                                                           .collect();
+    stock_paths.sort();
+
+    let mut contents = String::new();
+    contents.push_str("// Stock content of ");
+    contents.push_str( &install_dir.display().to_string() );
+    contents.push_str(", written by gen-manifest.\r\n");
+
+    for (index, &(_, ref write_path)) in stock_paths.iter().enumerate()
+    {
+        if args.verbose && index % 1000 == 0
+        {
+            println!( "Hashed {} of {} stock files.", index, stock_paths.len() );
+        }
+
+        contents.push_str( &scan_service.get_file_hash( &install_dir.join(write_path) )? );
+        contents.push_str("  ");
+        contents.push_str(write_path);
+        contents.push_str("\r\n");
+    }
+
+    file_writer::write( stock_content_path, generation_metadata::stamp( args, &contents )?.as_bytes() )?;
+
+    Ok(stock_paths.len())
+}
+
+/// Reads the stock content file at the given path, which uses the same format as the built in database.
+pub fn read_stock_content_file( stock_content_path: &PathBuf ) -> Result<Vec<StockFile>, GesError>
+{
+    let mut contents = String::new();
+    fs::File::open(stock_content_path)?.read_to_string(&mut contents)?;
+
+    parse_stock_content( stock_content_path, &contents )
+}
+
+/// Parses stock content, where each line holds the hash of a file and its path, like sha256sum prints them.  Anything
+/// on a line starting with // is a comment.
+fn parse_stock_content( stock_content_path: &PathBuf, contents: &str ) -> Result<Vec<StockFile>, GesError>
+{
+    let mut stock_files = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate()
+    {
+        let line = line.trim();
+
+        // Paths can hold spaces, and comments could too, so only whole line comments are allowed.
+        if line.is_empty() || line.starts_with("//")
+        {
+            continue;
+        }
+
+        let parse_error = |message: String| GesError::Parse { file: stock_content_path.clone(), line: line_index + 1, message };
+
+        let (hash, relative_path) = match line.find(char::is_whitespace)
+        {
+            Some(x) => (&line[..x], line[x..].trim_start()),
+            None => return Err(parse_error( String::from("Expected a file hash followed by its path!") )),
+        };
+
+        if hash.len() != HASH_LENGTH || !hash.chars().all(|x| x.is_ascii_hexdigit())
+        {
+            return Err(parse_error( format!( "{} isn't a SHA-256 hash!", hash ) ));
+        }
+
+        // sha256sum marks files it read in binary mode with a * before their path.
+        let relative_path = relative_path.trim_start_matches('*');
+
+        stock_files.push( StockFile { path: normalize_path( relative_path ), hash: hash.to_lowercase() } );
+    }
+
+    Ok(stock_files)
+}
+
+/// Writes a relative path the way the database stores it: lowercase, with forward slashes and no leading "./".
+fn normalize_path( relative_path: &str ) -> String
+{
+    let relative_path = relative_path.replace("\\", "/").to_lowercase();
+
+    String::from( relative_path.trim_start_matches("./") )
+}
+
+#[cfg(test)]
+mod tests
+{
+    use manifest;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    #[test]
+    fn test_parse_stock_content()
+    {
+        let stock_content_path = PathBuf::from("stock_content.txt");
+
+        assert!( parse_stock_content( &stock_content_path, BUILTIN_STOCK_CONTENT ).is_ok() );
+
+        let hash = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+        let stock_files = parse_stock_content( &stock_content_path, &format!( "// Comment\n{}  ./Sound/Music/Classy Song.mp3\n{} *maps\\ge_archives.bsp\n", hash, hash ) ).unwrap();
+
+        assert_eq!( stock_files[0], StockFile { path: String::from("sound/music/classy song.mp3"), hash: hash.to_lowercase() } );
+        assert_eq!( stock_files[1].path, "maps/ge_archives.bsp" );
+
+        assert!( parse_stock_content( &stock_content_path, "maps/ge_archives.bsp" ).is_err() );
+        assert!( parse_stock_content( &stock_content_path, "notahash maps/ge_archives.bsp" ).is_err() );
+    }
+
+    #[test]
+    fn test_write_stock_content_file()
+    {
+        let mut install_dir = get_root_test_directory();
+        install_dir.push("gesdir");
+        install_dir.push("gesource");
+
+        let mut stock_content_path = get_root_test_directory();
+        stock_content_path.push("temp");
+        fs::create_dir_all(&stock_content_path).unwrap();
+        stock_content_path.push("test_stock_content.txt");
+
+        let file_count = write_stock_content_file( &get_barebones_args(), &install_dir, &stock_content_path ).unwrap();
+        let stock_files = read_stock_content_file( &stock_content_path ).unwrap();
+
+        // Everything written can be read back, and points at the file it was made from.
+        assert!( file_count > 0 );
+        assert_eq!( stock_files.len(), file_count );
+
+        let gameinfo = stock_files.iter().find(|x| x.path == "gameinfo.txt").unwrap();
+        assert_eq!( gameinfo.hash, manifest::hash_file( &install_dir.join("gameinfo.txt") ).unwrap() );
+
+        fs::remove_file(&stock_content_path).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ----------------------------------------------------------------------------------------------------------
+// task_graph: Runs the sections of the program on their own threads, each one as soon as what it needs is done.
+// ----------------------------------------------------------------------------------------------------------
+
+use std::sync::mpsc;
+use std::thread;
+
+use shared;
+use error::GesError;
+
+/// Where a task is in its life.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TaskState
+{
+    Waiting,
+    Running,
+    Succeeded,
+    Failed,
+    /// Never ran, since a task it needed to succeed didn't.
+    Skipped,
+}
+
+struct Task
+{
+    name: &'static str,
+    error_code: i32,
+    dependencies: Vec<&'static str>,
+    needs_success: bool,
+    run: Option<Box<dyn FnOnce() -> Result<(), GesError> + Send>>,
+    state: TaskState,
+}
+
+/// A set of tasks and the order they have to run in.  Every task runs on its own thread once the tasks it depends on
+/// have finished, and reports the section's error code if it fails.
+pub struct TaskGraph
+{
+    tasks: Vec<Task>,
+}
+
+impl Default for TaskGraph
+{
+    fn default() -> TaskGraph
+    {
+        TaskGraph::new()
+    }
+}
+
+impl TaskGraph
+{
+    pub fn new() -> TaskGraph
+    {
+        TaskGraph { tasks: Vec::new() }
+    }
+
+    /// Adds a task that runs once every one of its dependencies has finished, whether they failed or not.
+    /// Dependencies have to be added first, which also keeps the graph from ever having a cycle.
+    pub fn add_task<F>( &mut self, name: &'static str, error_code: i32, dependencies: &[&'static str], run: F )
+        where F: FnOnce() -> Result<(), GesError> + Send + 'static
+    {
+        self.push_task( name, error_code, dependencies, false, Box::new(run) );
+    }
+
+    /// Adds a task that only runs if every one of its dependencies succeeded, and is skipped otherwise.
+    pub fn add_dependent_task<F>( &mut self, name: &'static str, error_code: i32, dependencies: &[&'static str], run: F )
+        where F: FnOnce() -> Result<(), GesError> + Send + 'static
+    {
+        self.push_task( name, error_code, dependencies, true, Box::new(run) );
+    }
+
+    fn push_task( &mut self, name: &'static str, error_code: i32, dependencies: &[&'static str], needs_success: bool,
+                  run: Box<dyn FnOnce() -> Result<(), GesError> + Send> )
+    {
+        for dependency in dependencies
+        {
+            assert!( self.tasks.iter().any(|x| x.name == *dependency), "Task {} depends on {}, which hasn't been added!", name, dependency );
+        }
+
+        self.tasks.push( Task { name, error_code, dependencies: dependencies.to_vec(), needs_success, run: Some(run), state: TaskState::Waiting } );
+    }
+
+    /// Runs every task, printing the error of any that fail.  Returns the sum of the error codes of every failed task,
+    /// counting each code once so sections that share one don't add up to a different section's code.
+    pub fn run( &mut self ) -> i32
+    {
+        let (sender, receiver) = mpsc::channel();
+        let mut running_count = 0;
+
+        loop
+        {
+            for index in self.get_ready_tasks()
+            {
+                let task = &mut self.tasks[index];
+                let run = task.run.take().unwrap();
+                let task_sender = sender.clone();
+
+                task.state = TaskState::Running;
+                running_count += 1;
+
+                thread::Builder::new()
+                    .name( String::from(task.name) )
+                    .spawn( move || { let _ = task_sender.send( (index, shared::run_catching_panics(run)) ); } )
+                    .expect("Failed to start worker thread!");
+            }
+
+            if running_count == 0
+            {
+                break;
+            }
+
+            // Every running task sends exactly one result, and we still hold a sender ourselves, so this can't fail.
+            let (index, result) = receiver.recv().unwrap();
+            running_count -= 1;
+
+            let task = &mut self.tasks[index];
+
+            task.state = match result
+            {
+                Ok(_) => TaskState::Succeeded,
+                Err(e) => { shared::print_error( &format!("Failed {} with error:\n{}\n", task.name, e) ); TaskState::Failed },
+            };
+        }
+
+        let mut failed_codes: Vec<i32> = Vec::new();
+
+        for task in &self.tasks
+        {
+            if task.state == TaskState::Failed && !failed_codes.contains(&task.error_code)
+            {
+                failed_codes.push(task.error_code);
+            }
+        }
+
+        failed_codes.iter().sum()
+    }
+
+    /// Returns true if the task with the given name ran and succeeded.  The error code run returns is a sum, so it can't
+    /// say which of the sections sharing a code failed, or even which codes went into it.
+    pub fn task_succeeded( &self, name: &str ) -> bool
+    {
+        self.tasks.iter().any(|x| x.name == name && x.state == TaskState::Succeeded)
+    }
+
+    /// Returns the index of every waiting task whose dependencies have all finished, skipping any that can't run
+    /// because a dependency they needed didn't succeed.
+    fn get_ready_tasks( &mut self ) -> Vec<usize>
+    {
+        let mut ready_tasks = Vec::new();
+
+        // Tasks only ever depend on ones added before them, so one pass in order catches skips that cascade.
+        for index in 0..self.tasks.len()
+        {
+            if self.tasks[index].state != TaskState::Waiting
+            {
+                continue;
+            }
+
+            let dependency_states: Vec<TaskState> = self.tasks[index].dependencies.iter()
+                                                        .map(|x| self.tasks.iter().find(|y| y.name == *x).unwrap().state)
+                                                        .collect();
+
+            if dependency_states.iter().any(|x| *x == TaskState::Waiting || *x == TaskState::Running)
+            {
+                continue;
+            }
+
+            if self.tasks[index].needs_success && dependency_states.iter().any(|x| *x != TaskState::Succeeded)
+            {
+                self.tasks[index].state = TaskState::Skipped;
+                continue;
+            }
+
+            ready_tasks.push(index);
+        }
+
+        ready_tasks
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::{Arc, Mutex};
+    use super::*;
+
+    #[test]
+    fn test_task_order()
+    {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut graph = TaskGraph::new();
+
+        let (first_order, second_order, third_order) = (order.clone(), order.clone(), order.clone());
+        graph.add_task( "first", 0x0002, &[], move || { first_order.lock().unwrap().push("first"); Ok(()) } );
+        graph.add_task( "second", 0x0004, &["first"], move || { second_order.lock().unwrap().push("second"); Ok(()) } );
+        graph.add_task( "third", 0x0008, &["first", "second"], move || { third_order.lock().unwrap().push("third"); Ok(()) } );
+
+        assert_eq!( graph.run(), 0x0000 );
+        assert_eq!( *order.lock().unwrap(), vec!["first", "second", "third"] );
+    }
+
+    #[test]
+    fn test_task_failures()
+    {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let mut graph = TaskGraph::new();
+
+        let (after_ran, dependent_ran, skipped_ran) = (ran.clone(), ran.clone(), ran.clone());
+        graph.add_task( "failing", 0x0008, &[], || Err(GesError::PolicyViolation( String::from("Broken!") )) );
+        graph.add_task( "also failing", 0x0008, &[], || Err(GesError::PolicyViolation( String::from("Also broken!") )) );
+        graph.add_task( "panicking", 0x0004, &[], || panic!("Crashed!") );
+        graph.add_task( "after", 0x0002, &["failing"], move || { after_ran.lock().unwrap().push("after"); Ok(()) } );
+        graph.add_dependent_task( "dependent", 0x0008, &["failing"], move || { dependent_ran.lock().unwrap().push("dependent"); Ok(()) } );
+        graph.add_dependent_task( "skipped", 0x0016, &["dependent"], move || { skipped_ran.lock().unwrap().push("skipped"); Ok(()) } );
+
+        // Both failures with the same code only count it once.
+        assert_eq!( graph.run(), 0x0008 + 0x0004 );
+        assert_eq!( *ran.lock().unwrap(), vec!["after"] );
+
+        assert!( graph.task_succeeded("after") );
+        assert!( !graph.task_succeeded("failing") && !graph.task_succeeded("panicking") && !graph.task_succeeded("skipped") );
+    }
+}
@@ -0,0 +1,335 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ---------------------------------------------------------------------------------------------------------
+// texture_scanner: Inspects the textures shipped with a map and reports how much memory they'll take up.
+// ---------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::io::prelude::*;
+use argument_handler::Arguments;
+
+use std::path::{Path, PathBuf};
+
+use shared;
+use rules;
+use error::GesError;
+
+// Anything this big on either side is almost never worth the memory it costs in a GE:S map.
+const OVERSIZED_TEXTURE_DIMENSION: u32 = 4096;
+
+// Resintensity is a rough measure of texture memory, where 10 = 500 MB.
+const MEGABYTES_PER_RESINTENSITY: f64 = 50.0;
+
+// VTF flag marking the texture as a cubemap, which stores six faces per frame.
+const VTF_FLAG_ENVMAP: u32 = 0x4000;
+
+// Sides are at most 65535 pixels, so no real texture has anywhere near this many mipmaps.
+const MAX_MIPMAP_COUNT: u32 = 32;
+
+/// The parts of a VTF header we care about for estimating memory usage.
+pub struct VtfInfo
+{
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub frames: u32,
+    pub faces: u32,
+    pub mipmap_count: u32,
+    pub image_format: i32,
+}
+
+impl VtfInfo
+{
+    /// Estimates how many bytes the full image data, including mipmaps, will take up once loaded.
+    pub fn get_memory_size( &self ) -> u64
+    {
+        let bits_per_pixel = get_format_bits_per_pixel( self.image_format );
+        let mut total_bits: u64 = 0;
+
+        for mip_level in 0..self.mipmap_count.max(1)
+        {
+            let mip_width = self.width.checked_shr(mip_level).unwrap_or(0).max(1) as u64;
+            let mip_height = self.height.checked_shr(mip_level).unwrap_or(0).max(1) as u64;
+            let mip_depth = self.depth.checked_shr(mip_level).unwrap_or(0).max(1) as u64;
+
+            // Block compressed formats can't go below a 4x4 block.
+            let (mip_width, mip_height) = if is_compressed_format( self.image_format ) { (mip_width.max(4), mip_height.max(4)) } else { (mip_width, mip_height) };
+
+            total_bits += mip_width * mip_height * mip_depth * bits_per_pixel;
+        }
+
+        total_bits * self.frames.max(1) as u64 * self.faces as u64 / 8
+    }
+
+    /// Checks to see if the texture is large enough to be worth a warning.
+    pub fn is_oversized( &self ) -> bool
+    {
+        self.width >= OVERSIZED_TEXTURE_DIMENSION || self.height >= OVERSIZED_TEXTURE_DIMENSION
+    }
+}
+
+/// Scans every texture in the root directory and reports on oversized or uncompressed textures, along with the
+/// texture memory the map is expected to use and what that means for its resintensity.
+pub fn report_texture_usage( args: &Arguments ) -> Result<(), GesError>
+{
+    let mut materials_dir = args.rootdir.clone();
+    materials_dir.push("materials");
+
+    let texture_tree = shared::get_cached_directory_tree( &[&materials_dir], "vtf", &[], shared::keep_all_files )?;
+    let (ref _texture_comp_names, ref texture_write_names) = *texture_tree;
+
+    if texture_write_names.is_empty()
+    {
+        return Ok(());
+    }
+
+    let mut total_bytes: u64 = 0;
+    let mut oversized_textures: Vec<(String, u32, u32)> = Vec::new();
+    let mut uncompressed_textures: Vec<(String, u64)> = Vec::new();
+
+    for texture_name in texture_write_names
+    {
+        let mut texture_path = materials_dir.clone();
+        texture_path.push(&texture_name);
+
+        let texture_info = match read_vtf_info( &texture_path )
+        {
+            Ok(x) => x,
+            Err(e) => { shared::print_warning( &format!( "Could not read texture materials/{}: {}", texture_name, e ) ); continue; },
+        };
+
+        let texture_bytes = texture_info.get_memory_size();
+        total_bytes += texture_bytes;
+
+        if texture_info.is_oversized()
+        {
+            oversized_textures.push( (texture_name.clone(), texture_info.width, texture_info.height) );
+        }
+
+        // Tiny uncompressed textures are fine, it's the big ones that eat memory.
+        if !is_compressed_format( texture_info.image_format ) && texture_info.width * texture_info.height >= 512 * 512
+        {
+            uncompressed_textures.push( (texture_name.clone(), texture_bytes) );
+        }
+
+        if args.verbose
+        {
+            println!( "materials/{} is {}x{} and uses {:.1} MB", texture_name, texture_info.width, texture_info.height, to_megabytes(texture_bytes) );
+        }
+    }
+
+    // Collect every problem so they can all be fixed in one go, even once some of these rules are made errors.
+    let mut problems: Vec<(&str, String)> = Vec::new();
+
+    for &(ref texture_name, width, height) in &oversized_textures
+    {
+        problems.push( ("texture-oversized", format!( "Texture materials/{} is {}x{}!  Consider scaling it down to 2048 or less.", texture_name, width, height )) );
+    }
+
+    for &(ref texture_name, texture_bytes) in &uncompressed_textures
+    {
+        problems.push( ("texture-uncompressed", format!( "Texture materials/{} is uncompressed and uses {:.1} MB!  Consider converting it to DXT1 or DXT5.", texture_name, to_megabytes(texture_bytes) )) );
+    }
+
+    let total_megabytes = to_megabytes(total_bytes);
+    let suggested_resintensity = (total_megabytes / MEGABYTES_PER_RESINTENSITY).ceil() as i32;
+
+    println!( "Shipped textures use an estimated {:.1} MB of memory, which suggests a resintensity of at least {}.", total_megabytes, suggested_resintensity );
+
+    if suggested_resintensity > args.resintensity
+    {
+        problems.push( ("texture-resintensity", format!( "Your resintensity of {} is lower than your textures suggest!  Either raise it or trim down the textures above.", args.resintensity )) );
+    }
+
+    // Anything that isn't an error under the current rules is reported on its own, the rest are failed together.
+    let mut error_problems: Vec<String> = Vec::new();
+
+    for (rule_id, problem) in problems
+    {
+        match rules::report_message( args, rule_id, problem )
+        {
+            Ok(_) => {},
+            Err(e) => error_problems.push( e.to_string() ),
+        }
+    }
+
+    if !error_problems.is_empty()
+    {
+        let mut error_text = String::new();
+        error_text.push_str("The shipped textures have problems:\n");
+
+        for problem in error_problems
+        {
+            error_text.push_str("  ");
+            error_text.push_str(&problem);
+            error_text.push_str("\n");
+        }
+
+        return Err(GesError::PolicyViolation( error_text ));
+    }
+
+    Ok(())
+}
+
+/// Reads the header of the VTF file at the given path.
+pub fn read_vtf_info( texture_path: &PathBuf ) -> Result<VtfInfo, GesError>
+{
+    shared::set_current_file( texture_path );
+
+    let texture_file = fs::File::open(texture_path)?;
+
+    // The header is never larger than this, and we don't care about anything past it.
+    let mut header = Vec::new();
+    texture_file.take(80).read_to_end(&mut header)?;
+
+    parse_vtf_header( texture_path, &header )
+}
+
+/// Pulls the texture information out of the raw bytes of a VTF header.
+pub fn parse_vtf_header( texture_path: &Path, header: &[u8] ) -> Result<VtfInfo, GesError>
+{
+    if header.len() < 63 || &header[0..4] != b"VTF\0"
+    {
+        return Err(GesError::Parse { file: texture_path.to_path_buf(), line: 0, message: String::from("Not a valid VTF file!") });
+    }
+
+    let read_u16 = |offset: usize| (header[offset] as u32) | (header[offset + 1] as u32) << 8;
+    let read_u32 = |offset: usize| read_u16(offset) | read_u16(offset + 2) << 16;
+
+    let minor_version = read_u32(8);
+    let flags = read_u32(20);
+
+    // Depth was only added in version 7.2.
+    let depth = if minor_version >= 2 && header.len() >= 65 { read_u16(63) } else { 1 };
+
+    let mipmap_count = header[56] as u32;
+
+    if mipmap_count > MAX_MIPMAP_COUNT
+    {
+        return Err(GesError::Parse { file: texture_path.to_path_buf(), line: 0, message: format!( "Claims to have {} mipmaps, so the header must be corrupt!", mipmap_count ) });
+    }
+
+    Ok(VtfInfo
+    {
+        width: read_u16(16),
+        height: read_u16(18),
+        depth,
+        frames: read_u16(24),
+        faces: if flags & VTF_FLAG_ENVMAP != 0 { 6 } else { 1 },
+        mipmap_count,
+        image_format: read_u32(52) as i32,
+    })
+}
+
+/// Checks to see if the given VTF image format is one of the DXT block compressed formats.
+fn is_compressed_format( image_format: i32 ) -> bool
+{
+    match image_format
+    {
+        13 | 14 | 15 | 20 => true, // DXT1, DXT3, DXT5, DXT1_ONEBITALPHA
+        _ => false,
+    }
+}
+
+/// Returns how many bits each pixel takes up in the given VTF image format.
+fn get_format_bits_per_pixel( image_format: i32 ) -> u64
+{
+    match image_format
+    {
+        13 | 20 => 4, // DXT1, DXT1_ONEBITALPHA
+        5 | 7 | 8 | 14 | 15 => 8, // I8, P8, A8, DXT3, DXT5
+        4 | 6 | 17 | 18 | 19 | 21 | 22 => 16, // RGB565, IA88, BGR565, BGRX5551, BGRA4444, BGRA5551, UV88
+        2 | 3 | 9 | 10 => 24, // RGB888, BGR888, and their bluescreen variants
+        24 | 25 => 64, // RGBA16161616F, RGBA16161616
+        _ => 32, // Everything else is some flavor of 8 bits per channel with 4 channels.
+    }
+}
+
+/// Converts a byte count to megabytes.
+fn to_megabytes( bytes: u64 ) -> f64
+{
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rules::Severity;
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    /// Builds a minimal 7.2 VTF header with the given properties.
+    fn build_header( width: u16, height: u16, flags: u32, image_format: i32, mipmap_count: u8 ) -> Vec<u8>
+    {
+        let mut header = vec![0u8; 80];
+        header[0..4].copy_from_slice(b"VTF\0");
+        header[4] = 7;
+        header[8] = 2;
+        header[16..18].copy_from_slice(&width.to_le_bytes());
+        header[18..20].copy_from_slice(&height.to_le_bytes());
+        header[20..24].copy_from_slice(&flags.to_le_bytes());
+        header[24] = 1;
+        header[52..56].copy_from_slice(&image_format.to_le_bytes());
+        header[56] = mipmap_count;
+        header[63] = 1;
+
+        header
+    }
+
+    #[test]
+    fn test_parse_vtf_header()
+    {
+        let info = parse_vtf_header( Path::new("test.vtf"), &build_header(4096, 1024, 0, 15, 1) ).unwrap();
+
+        assert_eq!( info.width, 4096 );
+        assert_eq!( info.height, 1024 );
+        assert!( info.is_oversized() );
+
+        // A single DXT5 mip is one byte per pixel.
+        assert_eq!( info.get_memory_size(), 4096 * 1024 );
+
+        // Cubemaps store six faces.
+        let cubemap_info = parse_vtf_header( Path::new("test.vtf"), &build_header(256, 256, VTF_FLAG_ENVMAP, 0, 1) ).unwrap();
+        assert_eq!( cubemap_info.get_memory_size(), 256 * 256 * 4 * 6 );
+
+        assert!( parse_vtf_header( Path::new("test.vtf"), b"not a texture at all" ).is_err() );
+
+        // A corrupt mipmap count is refused rather than shifted past the width of the sides.
+        assert!( parse_vtf_header( Path::new("test.vtf"), &build_header(256, 256, 0, 15, 255) ).is_err() );
+
+        let info = VtfInfo { width: 256, height: 256, depth: 1, frames: 1, faces: 1, mipmap_count: 40, image_format: 0 };
+        assert!( info.get_memory_size() > 256 * 256 * 4 );
+    }
+
+    #[test]
+    fn test_report_texture_usage()
+    {
+        let mut args = get_barebones_args();
+        args.rootdir = get_root_test_directory();
+        args.rootdir.push("temp");
+        args.rootdir.push("texture_scanner");
+        args.rootdir.push("gesource");
+        args.resintensity = 0;
+        args.rule_overrides = vec![(String::from("texture-oversized"), Severity::Error), (String::from("texture-uncompressed"), Severity::Error),
+                                   (String::from("texture-resintensity"), Severity::Error)];
+
+        let materials_dir = args.rootdir.join("materials/ge_test");
+        let _ = fs::remove_dir_all( &args.rootdir );
+        fs::create_dir_all( &materials_dir ).unwrap();
+
+        fs::write( materials_dir.join("huge_a.vtf"), build_header(4096, 4096, 0, 15, 1) ).unwrap();
+        fs::write( materials_dir.join("huge_b.vtf"), build_header(4096, 1024, 0, 15, 1) ).unwrap();
+        fs::write( materials_dir.join("raw.vtf"), build_header(512, 512, 0, 0, 1) ).unwrap();
+
+        // The first oversized texture failing doesn't hide the rest of what's wrong.
+        let error_text = report_texture_usage( &args ).unwrap_err().to_string();
+        assert!( error_text.contains("huge_a.vtf") && error_text.contains("huge_b.vtf") );
+        assert!( error_text.contains("raw.vtf is uncompressed") );
+        assert!( error_text.contains("Your resintensity of 0") );
+
+        fs::remove_dir_all( args.rootdir.parent().unwrap() ).unwrap();
+    }
+}
@@ -0,0 +1,311 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ----------------------------------------------------------------------------------------------------------
+// torrent_builder: Writes a .torrent file for a compressed release, so large map packs can be shared peer to peer.
+// ----------------------------------------------------------------------------------------------------------
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::prelude::*;
+
+use std::path::PathBuf;
+
+use sha1::{Sha1, Digest};
+
+use argument_handler::Arguments;
+use manifest;
+use release_signing;
+use update_checker::CURRENT_VERSION;
+use file_writer;
+use error::GesError;
+
+// Pieces start at 256 KiB and double until there are few enough of them, which is what most clients pick too.
+const MIN_PIECE_LENGTH: u64 = 256 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+const TARGET_PIECE_COUNT: u64 = 1500;
+
+/// A value in the bencode format torrent files are written in.
+#[derive(Debug, PartialEq)]
+enum Bencode
+{
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    /// Bencoded dictionaries have to be sorted by key, which a BTreeMap does for us.
+    Dict(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode
+{
+    /// Returns a bencoded string.
+    fn text( text: &str ) -> Bencode
+    {
+        Bencode::Bytes( text.as_bytes().to_vec() )
+    }
+
+    /// Appends the bencoded form of this value to the given buffer.
+    fn encode( &self, buffer: &mut Vec<u8> )
+    {
+        match *self
+        {
+            Bencode::Int(x) => buffer.extend( format!( "i{}e", x ).as_bytes() ),
+            Bencode::Bytes(ref x) => { buffer.extend( format!( "{}:", x.len() ).as_bytes() ); buffer.extend(x); },
+            Bencode::List(ref x) =>
+            {
+                buffer.push(b'l');
+                for value in x { value.encode(buffer); }
+                buffer.push(b'e');
+            },
+            Bencode::Dict(ref x) =>
+            {
+                buffer.push(b'd');
+                for (key, value) in x
+                {
+                    Bencode::Bytes( key.clone() ).encode(buffer);
+                    value.encode(buffer);
+                }
+                buffer.push(b'e');
+            },
+        }
+    }
+}
+
+/// Writes a torrent holding every file in the release's manifest, along with the manifest itself and its signature,
+/// to the supplied torrent path.
+pub fn write_torrent( args: &Arguments, compressed_dir: &PathBuf, manifest_path: &PathBuf, torrent_path: &PathBuf ) -> Result<(), GesError>
+{
+    let torrent = build_torrent( args, compressed_dir, manifest_path )?;
+
+    let mut torrent_bytes = Vec::new();
+    torrent.encode(&mut torrent_bytes);
+
+    match torrent_path.parent()
+    {
+        Some(x) if x != PathBuf::new() => file_writer::create_dir_all(x)?,
+        _ => {},
+    }
+
+    file_writer::write( torrent_path, &torrent_bytes )?;
+
+    println!( "Wrote torrent for {} to {}.", compressed_dir.display(), torrent_path.display() );
+
+    Ok(())
+}
+
+/// Builds the torrent for the release whose manifest is at manifest_path.
+fn build_torrent( args: &Arguments, compressed_dir: &PathBuf, manifest_path: &PathBuf ) -> Result<Bencode, GesError>
+{
+    // Only this release's files go in, since the compressed directory may be a fast download root holding every map.
+    let mut relative_names: Vec<String> = manifest::read_manifest( manifest_path )?.entries.into_iter().map(|x| format!( "{}.bz2", x.0 )).collect();
+
+    match manifest_path.strip_prefix(compressed_dir)
+    {
+        Ok(x) =>
+        {
+            let manifest_name = x.to_string_lossy().replace("\\", "/");
+            let signature_name = format!( "{}.{}", manifest_name, release_signing::SIGNATURE_EXTENSION );
+
+            if release_signing::get_signature_path( manifest_path ).is_file()
+            {
+                relative_names.push(signature_name);
+            }
+
+            relative_names.push(manifest_name);
+        },
+        Err(_) => {},
+    }
+
+    // Torrent clients don't care about order, but sorting makes the same release always produce the same torrent.
+    relative_names.sort();
+
+    let mut file_sizes: Vec<u64> = Vec::new();
+
+    for relative_name in &relative_names
+    {
+        let mut file_path = compressed_dir.clone();
+        file_path.push(relative_name);
+
+        file_sizes.push( fs::metadata(&file_path).map_err(|e| GesError::from(e).in_file( &file_path ))?.len() );
+    }
+
+    let piece_length = get_piece_length( file_sizes.iter().sum() );
+    let pieces = hash_pieces( compressed_dir, &relative_names, piece_length )?;
+
+    let files = relative_names.iter().zip(file_sizes.iter()).map(|(name, size)|
+    {
+        let mut file_entry = BTreeMap::new();
+        file_entry.insert( b"length".to_vec(), Bencode::Int( *size as i64 ) );
+        file_entry.insert( b"path".to_vec(), Bencode::List( name.split('/').map(Bencode::text).collect() ) );
+        Bencode::Dict(file_entry)
+    }).collect();
+
+    // Web seeds add the torrent's name and each file's path to their url, so naming it after the compressed directory
+    // lets a fast download server that already hosts the release act as a seed.
+    let torrent_name = match compressed_dir.file_name()
+    {
+        Some(x) => x.to_string_lossy().into_owned(),
+        None => String::from("gesource"),
+    };
+
+    let mut info = BTreeMap::new();
+    info.insert( b"files".to_vec(), Bencode::List(files) );
+    info.insert( b"name".to_vec(), Bencode::text(&torrent_name) );
+    info.insert( b"piece length".to_vec(), Bencode::Int( piece_length as i64 ) );
+    info.insert( b"pieces".to_vec(), Bencode::Bytes(pieces) );
+
+    let mut torrent = BTreeMap::new();
+    torrent.insert( b"info".to_vec(), Bencode::Dict(info) );
+    torrent.insert( b"created by".to_vec(), Bencode::text( &format!( "ges_scriptutility {}", CURRENT_VERSION ) ) );
+
+    match args.author
+    {
+        Some(ref x) => { torrent.insert( b"comment".to_vec(), Bencode::text( &format!( "Released by {}", x ) ) ); },
+        None => {},
+    }
+
+    // Each tracker gets its own tier, so clients try them in the order they were given.
+    if !args.trackers.is_empty()
+    {
+        torrent.insert( b"announce".to_vec(), Bencode::text(&args.trackers[0]) );
+        torrent.insert( b"announce-list".to_vec(), Bencode::List( args.trackers.iter().map(|x| Bencode::List(vec![Bencode::text(x)])).collect() ) );
+    }
+
+    if !args.web_seeds.is_empty()
+    {
+        torrent.insert( b"url-list".to_vec(), Bencode::List( args.web_seeds.iter().map(|x| Bencode::text(x)).collect() ) );
+    }
+
+    Ok(Bencode::Dict(torrent))
+}
+
+/// Returns the piece length to use for a torrent holding the given number of bytes.
+fn get_piece_length( total_size: u64 ) -> u64
+{
+    let mut piece_length = MIN_PIECE_LENGTH;
+
+    while piece_length < MAX_PIECE_LENGTH && total_size / piece_length > TARGET_PIECE_COUNT
+    {
+        piece_length *= 2;
+    }
+
+    piece_length
+}
+
+/// Hashes the given files as if they were one long file split into pieces of piece_length bytes, returning
+/// every piece's SHA-1 digest one after another.
+fn hash_pieces( compressed_dir: &PathBuf, relative_names: &[String], piece_length: u64 ) -> Result<Vec<u8>, GesError>
+{
+    let mut pieces: Vec<u8> = Vec::new();
+    let mut hasher = Sha1::new();
+    let mut piece_fill: u64 = 0;
+
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    for relative_name in relative_names
+    {
+        let mut file_path = compressed_dir.clone();
+        file_path.push(relative_name);
+
+        let mut file = fs::File::open(&file_path)?;
+
+        loop
+        {
+            // Never read past the end of the current piece, so each piece gets exactly its own bytes.
+            let read_limit = buffer.len().min( (piece_length - piece_fill) as usize );
+            let read_count = file.read(&mut buffer[..read_limit])?;
+
+            if read_count == 0
+            {
+                break;
+            }
+
+            hasher.update(&buffer[..read_count]);
+            piece_fill += read_count as u64;
+
+            if piece_fill == piece_length
+            {
+                pieces.extend( hasher.finalize_reset().iter() );
+                piece_fill = 0;
+            }
+        }
+    }
+
+    // The last piece is usually shorter than the rest.
+    if piece_fill > 0
+    {
+        pieces.extend( hasher.finalize().iter() );
+    }
+
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_bencode()
+    {
+        let mut dict = BTreeMap::new();
+        dict.insert( b"spam".to_vec(), Bencode::List(vec![Bencode::text("a"), Bencode::Int(-3)]) );
+        dict.insert( b"cow".to_vec(), Bencode::text("moo") );
+
+        let mut buffer = Vec::new();
+        Bencode::Dict(dict).encode(&mut buffer);
+
+        assert_eq!( buffer, b"d3:cow3:moo4:spaml1:ai-3eee".to_vec() );
+    }
+
+    #[test]
+    fn test_get_piece_length()
+    {
+        assert_eq!( get_piece_length(0), MIN_PIECE_LENGTH );
+        assert_eq!( get_piece_length( 2 * 1024 * 1024 * 1024 ), 2 * 1024 * 1024 );
+        assert_eq!( get_piece_length( u64::MAX ), MAX_PIECE_LENGTH );
+    }
+
+    #[test]
+    fn test_build_torrent()
+    {
+        let mut compressed_dir = get_root_test_directory();
+        compressed_dir.push("temp");
+        compressed_dir.push("torrent");
+        compressed_dir.push("gesource");
+
+        let mut maps_dir = compressed_dir.clone();
+        maps_dir.push("maps");
+        fs::create_dir_all(&maps_dir).unwrap();
+
+        let mut manifest_path = maps_dir.clone();
+        manifest_path.push("test_map.manifest");
+        fs::File::create(&manifest_path).unwrap().write_all(b"\"manifest\"\r\n{\r\n\t\"maps/test_map.bsp\"\t\"00\"\r\n}\r\n").unwrap();
+
+        let mut map_path = maps_dir.clone();
+        map_path.push("test_map.bsp.bz2");
+        fs::File::create(&map_path).unwrap().write_all(&vec![7u8; 300 * 1024]).unwrap();
+
+        let mut args = get_barebones_args();
+        args.trackers = vec![String::from("udp://tracker.example.com:6969/announce"), String::from("https://tracker.example.org/announce")];
+        args.web_seeds = vec![String::from("https://fastdl.example.com/")];
+
+        let torrent = build_torrent( &args, &compressed_dir, &manifest_path ).unwrap();
+        fs::remove_dir_all( compressed_dir.parent().unwrap() ).unwrap();
+
+        let torrent = match torrent { Bencode::Dict(x) => x, _ => panic!("Torrent isn't a dictionary!") };
+        let info = match torrent[&b"info".to_vec()] { Bencode::Dict(ref x) => x, _ => panic!("Info isn't a dictionary!") };
+
+        assert_eq!( torrent[&b"announce".to_vec()], Bencode::text("udp://tracker.example.com:6969/announce") );
+        assert_eq!( torrent[&b"url-list".to_vec()], Bencode::List(vec![Bencode::text("https://fastdl.example.com/")]) );
+        assert_eq!( info[&b"name".to_vec()], Bencode::text("gesource") );
+
+        // The map and the manifest itself, spread over two pieces.
+        match info[&b"files".to_vec()] { Bencode::List(ref x) => assert_eq!( x.len(), 2 ), _ => panic!("Files isn't a list!") };
+        match info[&b"pieces".to_vec()] { Bencode::Bytes(ref x) => assert_eq!( x.len(), 2 * 20 ), _ => panic!("Pieces isn't a string!") };
+    }
+}
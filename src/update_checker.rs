@@ -0,0 +1,146 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ---------------------------------------------------------------------------------------------
+// update_checker: Asks GitHub if there's a newer release of the program than the one running.
+// ---------------------------------------------------------------------------------------------
+
+use std::io;
+use std::time::Duration;
+use std::path::PathBuf;
+
+use serde_json;
+use ureq;
+
+use error::GesError;
+
+static LATEST_RELEASE_URL: &'static str = "https://api.github.com/repos/Entropy-Soldier/ges-map-script-utility/releases/latest";
+
+/// The version of the program that's currently running.
+pub static CURRENT_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+
+/// Details of a published release.
+pub struct ReleaseInfo
+{
+    pub version: String,
+    pub changelog: String,
+    pub url: String,
+}
+
+/// Fetches the latest release from GitHub, returning it if it's newer than the running version and None if not.
+pub fn check_for_update() -> Result<Option<ReleaseInfo>, GesError>
+{
+    // Don't leave people staring at a frozen console if GitHub is slow or they're offline.
+    let agent = ureq::AgentBuilder::new().timeout( Duration::from_secs(5) ).build();
+
+    let response = match agent.get( LATEST_RELEASE_URL )
+                              .set( "User-Agent", &format!( "ges_scriptutility/{}", CURRENT_VERSION ) )
+                              .set( "Accept", "application/vnd.github+json" )
+                              .call()
+    {
+        Ok(x) => x,
+        Err(e) => return Err(GesError::Io(io::Error::new( io::ErrorKind::Other, format!( "Failed to reach GitHub to check for updates: {}", e ) ))),
+    };
+
+    let release = parse_release( &response.into_string()? )?;
+
+    if is_newer_version( &release.version, CURRENT_VERSION )
+    {
+        Ok(Some(release))
+    }
+    else
+    {
+        Ok(None)
+    }
+}
+
+/// Checks for an update and lets the user know about it if there is one.
+pub fn print_update_status() -> Result<(), GesError>
+{
+    match check_for_update()?
+    {
+        Some(release) =>
+        {
+            println!( "A newer version of this program is available!  You're running {} and the latest is {}.", CURRENT_VERSION, release.version );
+            println!( "Download it from {}\n", release.url );
+
+            if !release.changelog.trim().is_empty()
+            {
+                println!( "Changes in {}:\n{}\n", release.version, release.changelog.trim() );
+            }
+        },
+        None => println!( "You're running the latest version, {}.", CURRENT_VERSION ),
+    }
+
+    Ok(())
+}
+
+/// Pulls the release details out of a GitHub release API response.
+fn parse_release( response_text: &str ) -> Result<ReleaseInfo, GesError>
+{
+    let release: serde_json::Value = match serde_json::from_str( response_text )
+    {
+        Ok(x) => x,
+        Err(e) => return Err(GesError::Parse { file: PathBuf::from(LATEST_RELEASE_URL), line: e.line(), message: format!( "GitHub sent an unreadable release description: {}", e ) }),
+    };
+
+    let version = match release["tag_name"].as_str()
+    {
+        Some(x) => String::from(x),
+        None => return Err(GesError::Parse { file: PathBuf::from(LATEST_RELEASE_URL), line: 0, message: String::from("GitHub release description has no version tag!") }),
+    };
+
+    Ok(ReleaseInfo
+    {
+        version,
+        changelog: String::from( release["body"].as_str().unwrap_or("") ),
+        url: String::from( release["html_url"].as_str().unwrap_or("https://github.com/Entropy-Soldier/ges-map-script-utility/releases") ),
+    })
+}
+
+/// Checks to see if the candidate version is newer than the current version.
+/// Versions are compared number by number, so tags like "v1.0.10" and "1.0.2" work as expected.
+fn is_newer_version( candidate_version: &str, current_version: &str ) -> bool
+{
+    get_version_numbers( candidate_version ) > get_version_numbers( current_version )
+}
+
+/// Splits a version string into its numbers, ignoring any prefix or suffix like "v" or "-beta".
+pub fn get_version_numbers( version: &str ) -> Vec<u32>
+{
+    version.trim_start_matches(|x: char| !x.is_ascii_digit())
+           .split('.')
+           .map(|x| x.chars().take_while(|c| c.is_ascii_digit()).collect::<String>().parse::<u32>().unwrap_or(0))
+           .collect()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_version_comparison()
+    {
+        assert!( is_newer_version( "v1.0.3", "1.0.2" ) );
+        assert!( is_newer_version( "1.0.10", "1.0.9" ) );
+        assert!( is_newer_version( "v2.0", "1.9.9" ) );
+        assert!( !is_newer_version( "v1.0.2", "1.0.2" ) );
+        assert!( !is_newer_version( "1.0.1-beta", "1.0.2" ) );
+    }
+
+    #[test]
+    fn test_parse_release()
+    {
+        let release = parse_release( "{\"tag_name\":\"v1.1.0\",\"body\":\"* Fixed things\",\"html_url\":\"https://example.com/release\"}" ).unwrap();
+
+        assert_eq!( release.version, "v1.1.0" );
+        assert_eq!( release.changelog, "* Fixed things" );
+        assert_eq!( release.url, "https://example.com/release" );
+
+        assert!( parse_release( "{\"message\":\"Not Found\"}" ).is_err() );
+        assert!( parse_release( "not json" ).is_err() );
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// user_config: Reads the settings a mapper wants every run to start from, so they don't have to be given each time.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use toml;
+
+use shared;
+use error;
+use error::GesError;
+
+lazy_static!
+{
+    // Each setting of the user config by the argument it stands in for, and whether anything has looked it up yet.
+    static ref USER_SETTINGS: Mutex<Vec<(String, String, bool)>> = Mutex::new(Vec::new());
+}
+
+/// Returns where the user config is expected to be, which GESMRA_USER_CONFIG can point somewhere else.
+pub fn get_user_config_path() -> Option<PathBuf>
+{
+    match env::var_os("GESMRA_USER_CONFIG")
+    {
+        Some(x) => return Some(PathBuf::from(x)),
+        None => {},
+    }
+
+    let config_dir = match (env::var_os("APPDATA"), env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME"))
+    {
+        (Some(x), _, _) if cfg!(windows) => PathBuf::from(x),
+        (_, Some(x), _) => PathBuf::from(x),
+        (_, _, Some(x)) => PathBuf::from(x).join(".config"),
+        _ => return None,
+    };
+
+    Some(config_dir.join("ges-map-script-utility").join("config.toml"))
+}
+
+/// Reads the user config, if there is one, so its settings can fill in for arguments that weren't given.  Returns the
+/// path it was read from.
+pub fn load_user_config() -> Result<Option<PathBuf>, GesError>
+{
+    let config_path = match get_user_config_path()
+    {
+        Some(ref x) if x.is_file() => x.clone(),
+        _ => return Ok(None),
+    };
+
+    let contents = fs::read_to_string(&config_path).map_err(|e| GesError::from(e).in_file( &config_path ))?;
+    let settings = parse_user_config( &config_path, &contents )?;
+
+    *USER_SETTINGS.lock().unwrap() = settings.into_iter().map(|(x, y)| (x, y, false)).collect();
+
+    Ok(Some(config_path))
+}
+
+/// Parses a user config, where each setting is named after the argument it stands in for, like gesdir = "D:/gesource".
+/// Flags are set with true or false, and lists of values are written as lists.
+pub fn parse_user_config( config_path: &PathBuf, contents: &str ) -> Result<Vec<(String, String)>, GesError>
+{
+    get_argument_settings( config_path, &parse_table( config_path, contents )? )
+}
+
+/// Parses a TOML file, pointing at the line it breaks on if it can't be read.
+pub fn parse_table( config_path: &PathBuf, contents: &str ) -> Result<toml::Table, GesError>
+{
+    contents.parse::<toml::Table>().map_err(|e|
+    {
+        let line = match e.span()
+        {
+            Some(x) => error::get_line_number( contents, x.start ),
+            None => 0,
+        };
+
+        GesError::Parse { file: config_path.clone(), line, message: e.message().trim().to_string() }
+    })
+}
+
+/// Returns each setting of the given table as the argument it's named after and the value it would be given on the
+/// commandline with.
+pub fn get_argument_settings( config_path: &PathBuf, table: &toml::Table ) -> Result<Vec<(String, String)>, GesError>
+{
+    let mut settings = Vec::new();
+
+    for (argument_name, value) in table
+    {
+        let text = match *value
+        {
+            toml::Value::Array(ref x) => x.iter().map(get_value_text).collect::<Option<Vec<String>>>().map(|y| y.join(",")),
+            ref x => get_value_text(x),
+        };
+
+        match text
+        {
+            Some(x) => settings.push( (argument_name.clone(), x) ),
+            None =>
+            {
+                let mut error_text = String::new();
+                error_text.push_str("The ");
+                error_text.push_str(argument_name);
+                error_text.push_str(" setting has to be text, a whole number, true or false, or a list of text!");
+
+                return Err(GesError::Parse { file: config_path.clone(), line: 0, message: error_text });
+            },
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Returns the user config's value for the given argument, if it has one.
+pub fn get_user_setting( argument_name: &str ) -> Option<String>
+{
+    let mut user_settings = USER_SETTINGS.lock().unwrap();
+
+    match user_settings.iter_mut().find(|x| x.0 == argument_name)
+    {
+        Some(x) => { x.2 = true; Some(x.1.clone()) },
+        None => None,
+    }
+}
+
+/// Warns about every setting of the user config that no argument looked up, which is usually a typo.
+pub fn warn_unused_settings( config_path: &PathBuf )
+{
+    for setting in USER_SETTINGS.lock().unwrap().iter().filter(|x| !x.2)
+    {
+        let mut warning_text = String::new();
+        warning_text.push_str("The user config at ");
+        warning_text.push_str( &config_path.display().to_string() );
+        warning_text.push_str(" sets ");
+        warning_text.push_str(&setting.0);
+        warning_text.push_str(", which isn't an argument that can be set there!\nIt will be ignored.");
+
+        shared::print_warning( &warning_text );
+    }
+}
+
+/// Returns the text of a single setting value as it would be given on the commandline.
+fn get_value_text( value: &toml::Value ) -> Option<String>
+{
+    match *value
+    {
+        toml::Value::String(ref x) => Some(x.clone()),
+        toml::Value::Integer(x) => Some(x.to_string()),
+        toml::Value::Boolean(x) => Some(x.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_parse_user_config()
+    {
+        let config_path = PathBuf::from("config.toml");
+        let contents = "# Usual settings\ngesdir = \"D:/Games/gesource\"\nauthor = \"Entropy-Soldier\"\n\
+                        preset = \"competitive\"\nweight = 700\nverbose = true\ntrackers = [\"udp://a:80\", \"udp://b:80\"]\n";
+
+        let settings = parse_user_config( &config_path, contents ).unwrap();
+        assert!( settings.contains( &(String::from("gesdir"), String::from("D:/Games/gesource")) ) );
+        assert!( settings.contains( &(String::from("weight"), String::from("700")) ) );
+        assert!( settings.contains( &(String::from("verbose"), String::from("true")) ) );
+        assert!( settings.contains( &(String::from("trackers"), String::from("udp://a:80,udp://b:80")) ) );
+
+        // Broken files point at the line, and settings that can't stand in for an argument are refused.
+        match parse_user_config( &config_path, "weight = 700\npreset = \"casual" ).unwrap_err()
+        {
+            GesError::Parse { line, .. } => assert_eq!( line, 2 ),
+            e => panic!( "Unexpected error {}", e ),
+        }
+
+        assert!( parse_user_config( &config_path, "[metadata]\nauthor = \"Entropy-Soldier\"" ).unwrap_err().to_string().contains("metadata") );
+        assert!( parse_user_config( &config_path, "weight = 7.5" ).is_err() );
+    }
+}
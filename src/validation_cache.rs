@@ -0,0 +1,196 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// validation_cache: Remembers which scripts passed their checks, so repeated fullchecks only check what changed.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use argument_handler::Arguments;
+use rules;
+use scan_service;
+use shared;
+use error::GesError;
+
+/// What a script's check depends on.  Checks look at which files the install has, such as the tracks a music script
+/// plays, and some read other files too, such as the map a music script names its sections after.  All of those have
+/// to stay the same.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ValidationKey
+{
+    /// The kind of script, since the same file could be checked more than one way.
+    script_type: String,
+    script_path: PathBuf,
+    /// The script's SHA-256 hash.
+    script_hash: String,
+    /// A hash of the path of every file in the install.
+    install_fingerprint: u64,
+    /// The SHA-256 hash of every other file the check reads, or None for any that couldn't be read.
+    dependency_hashes: Vec<(PathBuf, Option<String>)>,
+}
+
+lazy_static!
+{
+    static ref PASSED_CHECKS: Mutex<HashSet<ValidationKey>> = Mutex::new(HashSet::new());
+}
+
+/// Runs the given check on a script unless the same script already passed it cleanly while the install had the same
+/// fingerprint and the same files to read, which the given function lists.  Returns true if the check was skipped.
+/// Without a fingerprint the check always runs.
+pub fn check_with_cache( args: &Arguments, script_type: &str, script_path: &PathBuf, install_fingerprint: Option<u64>, check_func: fn( args: &Arguments, script_path: &PathBuf ) -> Result<(), GesError>,
+                         get_dependencies: fn( args: &Arguments, script_path: &PathBuf ) -> Vec<PathBuf> ) -> Result<bool, GesError>
+{
+    let install_fingerprint = match install_fingerprint
+    {
+        Some(x) => x,
+        None => return check_func( args, script_path ).map(|_| false),
+    };
+
+    // Hashes are kept across walks, so an unchanged script isn't read again just to find out it's unchanged.
+    let scan_service = scan_service::get_scan_service();
+
+    let validation_key = ValidationKey
+    {
+        script_type: String::from( script_type ),
+        script_path: script_path.clone(),
+        script_hash: scan_service.get_file_hash( script_path )?,
+        install_fingerprint,
+        dependency_hashes: get_dependencies( args, script_path ).into_iter().map(|x| { let hash = scan_service.get_file_hash( &x ).ok(); (x, hash) }).collect(),
+    };
+
+    if PASSED_CHECKS.lock().unwrap().contains( &validation_key )
+    {
+        return Ok(true);
+    }
+
+    let starting_finding_count = rules::get_thread_finding_count();
+    let starting_warning_count = shared::get_thread_warning_count();
+
+    check_func( args, script_path )?;
+
+    // A check that warned has to run again next time, so its warnings make it into that run's report.
+    if rules::get_thread_finding_count() == starting_finding_count && shared::get_thread_warning_count() == starting_warning_count
+    {
+        PASSED_CHECKS.lock().unwrap().insert( validation_key );
+    }
+
+    Ok(false)
+}
+
+/// For checks that don't read any file but the script itself.
+pub fn no_dependencies( _args: &Arguments, _script_path: &PathBuf ) -> Vec<PathBuf>
+{
+    Vec::new()
+}
+
+/// Returns a hash of the path of every file in the GE:S install, which changes whenever a file is added, removed, or
+/// renamed.  Only daemon mode keeps results, since a single run never checks a script twice, so there's no fingerprint
+/// otherwise.  The walk it comes from is shared with the rest of the run.
+pub fn get_install_fingerprint( args: &Arguments ) -> Result<Option<u64>, GesError>
+{
+    if !args.daemon
+    {
+        return Ok(None);
+    }
+
+    let file_paths = scan_service::get_scan_service().get_files( &args.gesdir, "", &[], shared::keep_all_files )?.1;
+
+    let mut hasher = DefaultHasher::new();
+    file_paths.hash( &mut hasher );
+
+    Ok(Some(hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use shared::{get_barebones_args, get_root_test_directory};
+    use super::*;
+
+    static CHECK_COUNT: AtomicUsize = AtomicUsize::new(0);
+    static MAP_CHECK_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    fn count_check( _args: &Arguments, _script_path: &PathBuf ) -> Result<(), GesError>
+    {
+        CHECK_COUNT.fetch_add( 1, Ordering::SeqCst );
+        Ok(())
+    }
+
+    fn count_map_check( _args: &Arguments, _script_path: &PathBuf ) -> Result<(), GesError>
+    {
+        MAP_CHECK_COUNT.fetch_add( 1, Ordering::SeqCst );
+        Ok(())
+    }
+
+    fn get_map_dependency( args: &Arguments, _script_path: &PathBuf ) -> Vec<PathBuf>
+    {
+        vec![args.gesdir.join("maps/ge_test.bsp")]
+    }
+
+    #[test]
+    fn test_check_with_cache()
+    {
+        let mut args = get_barebones_args();
+        args.daemon = true;
+        args.gesdir = get_root_test_directory();
+        args.gesdir.push("temp");
+        args.gesdir.push("validation_cache");
+
+        let _ = fs::remove_dir_all( &args.gesdir );
+        fs::create_dir_all( &args.gesdir ).unwrap();
+
+        let script_path = args.gesdir.join("ge_test.txt");
+        fs::write( &script_path, "\"Music\" {}" ).unwrap();
+
+        assert!( !check_with_cache( &args, "test scripts", &script_path, get_install_fingerprint( &args ).unwrap(), count_check, no_dependencies ).unwrap() );
+        assert!( check_with_cache( &args, "test scripts", &script_path, get_install_fingerprint( &args ).unwrap(), count_check, no_dependencies ).unwrap() );
+        assert_eq!( CHECK_COUNT.load(Ordering::SeqCst), 1 );
+
+        // A new file in the install could be what the script needed, so it's checked again.
+        fs::write( args.gesdir.join("ge_other.txt"), "\"Music\" {}" ).unwrap();
+        shared::clear_directory_cache();
+
+        assert!( !check_with_cache( &args, "test scripts", &script_path, get_install_fingerprint( &args ).unwrap(), count_check, no_dependencies ).unwrap() );
+        assert_eq!( CHECK_COUNT.load(Ordering::SeqCst), 2 );
+
+        fs::remove_dir_all( &args.gesdir ).unwrap();
+    }
+
+    #[test]
+    fn test_check_with_cache_dependencies()
+    {
+        let mut args = get_barebones_args();
+        args.daemon = true;
+        args.gesdir = get_root_test_directory();
+        args.gesdir.push("temp");
+        args.gesdir.push("validation_cache_dependencies");
+
+        let _ = fs::remove_dir_all( &args.gesdir );
+        fs::create_dir_all( args.gesdir.join("maps") ).unwrap();
+
+        let script_path = args.gesdir.join("level_music_ge_test.txt");
+        fs::write( &script_path, "\"Music\" {}" ).unwrap();
+        fs::write( args.gesdir.join("maps/ge_test.bsp"), "VBSP" ).unwrap();
+
+        assert!( !check_with_cache( &args, "test scripts", &script_path, get_install_fingerprint( &args ).unwrap(), count_map_check, get_map_dependency ).unwrap() );
+        assert!( check_with_cache( &args, "test scripts", &script_path, get_install_fingerprint( &args ).unwrap(), count_map_check, get_map_dependency ).unwrap() );
+
+        // The map could have lost the area a music section was named after, so the script is checked again.
+        fs::write( args.gesdir.join("maps/ge_test.bsp"), "VBSP with new entities" ).unwrap();
+        shared::clear_directory_cache();
+
+        assert!( !check_with_cache( &args, "test scripts", &script_path, get_install_fingerprint( &args ).unwrap(), count_map_check, get_map_dependency ).unwrap() );
+        assert_eq!( MAP_CHECK_COUNT.load(Ordering::SeqCst), 2 );
+
+        fs::remove_dir_all( &args.gesdir ).unwrap();
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// --------------------------------------------------------------------------------------------------------------
+// validator: Long-lived validation of a map release, for editors and other tools that re-check files as they change.
+// --------------------------------------------------------------------------------------------------------------
+
+use argument_handler::Arguments;
+
+use std::path::{Path, PathBuf};
+
+use map_script_builder;
+use music_script_builder;
+use reslist_builder;
+use shared;
+use error::GesError;
+
+/// The script files that make up a map release.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ScriptKind
+{
+    MapScript,
+    MusicScript,
+    Reslist,
+}
+
+impl ScriptKind
+{
+    /// Works out which kind of script the given path points to from where it sits in the file tree.
+    pub fn from_path( script_path: &Path ) -> Option<ScriptKind>
+    {
+        let path_string = script_path.to_string_lossy().replace("\\", "/").to_lowercase();
+        let extension = shared::get_string_file_extension( &path_string );
+
+        if extension == "res"
+        {
+            Some(ScriptKind::Reslist)
+        }
+        else if extension == "txt" && path_string.contains("scripts/music/")
+        {
+            Some(ScriptKind::MusicScript)
+        }
+        else if extension == "txt" && path_string.contains("scripts/maps/")
+        {
+            Some(ScriptKind::MapScript)
+        }
+        else
+        {
+            None
+        }
+    }
+
+    /// Returns the name used for this kind of script in machine readable output.
+    pub fn get_name( &self ) -> &'static str
+    {
+        match *self
+        {
+            ScriptKind::MapScript => "map_script",
+            ScriptKind::MusicScript => "music_script",
+            ScriptKind::Reslist => "reslist",
+        }
+    }
+}
+
+/// The outcome of validating a single script file.
+pub struct ValidationResult
+{
+    pub kind: ScriptKind,
+    pub path: PathBuf,
+    pub result: Result<(), GesError>,
+}
+
+/// Validates the script files of a single map release.  The directory scans the checks need stay cached between
+/// validations, so repeated validations only rescan what has actually changed.
+/// Unlike the builders, the validator never creates missing scripts.  A missing script is reported as an error.
+pub struct Validator
+{
+    args: Arguments,
+    map_name: String,
+}
+
+impl Validator
+{
+    /// Creates a validator for the given map.  No scanning happens until something is validated.
+    pub fn new( args: Arguments, map_name: &str ) -> Validator
+    {
+        Validator
+        {
+            args,
+            map_name: String::from(map_name),
+        }
+    }
+
+    /// Returns the location of the given script in the root directory.
+    pub fn get_script_path( &self, kind: ScriptKind ) -> PathBuf
+    {
+        let mut script_path = self.args.rootdir.clone();
+
+        match kind
+        {
+            ScriptKind::MapScript => { script_path.push("scripts"); script_path.push("maps"); script_path.push(&self.map_name); script_path.set_extension("txt"); },
+            ScriptKind::MusicScript => { script_path.push("scripts"); script_path.push("music"); script_path.push(format!("level_music_{}", self.map_name)); script_path.set_extension("txt"); },
+            ScriptKind::Reslist => { script_path.push("maps"); script_path.push(&self.map_name); script_path.set_extension("res"); },
+        }
+
+        script_path
+    }
+
+    /// Validates every script file of the release.
+    pub fn validate_all( &mut self ) -> Vec<ValidationResult>
+    {
+        vec![self.validate( ScriptKind::MapScript ), self.validate( ScriptKind::MusicScript ), self.validate( ScriptKind::Reslist )]
+    }
+
+    /// Validates a single script file, scanning any directories it depends on that aren't already cached.
+    pub fn validate( &mut self, kind: ScriptKind ) -> ValidationResult
+    {
+        let script_path = self.get_script_path( kind );
+
+        let result = if !script_path.is_file()
+        {
+            Err(GesError::MissingFile { path: script_path.clone(), message: format!("{} does not exist!", script_path.display()) })
+        }
+        else
+        {
+            match kind
+            {
+                ScriptKind::MapScript => map_script_builder::check_map_script_file( &self.args, &script_path ),
+                ScriptKind::MusicScript => music_script_builder::check_music_script_file( &self.args, &script_path ),
+                ScriptKind::Reslist => reslist_builder::check_reslist( &self.args, &script_path ),
+            }
+        };
+
+        ValidationResult { kind, path: script_path, result }
+    }
+
+    /// Lets the validator know that the file or directory at the given path has changed, been added, or been removed.
+    /// Any cached scans that included it are dropped, and every script that could be affected by it is re-validated.
+    pub fn invalidate( &mut self, changed_path: &Path ) -> Vec<ValidationResult>
+    {
+        let mut affected_kinds: Vec<ScriptKind> = Vec::new();
+
+        for kind in [ScriptKind::MapScript, ScriptKind::MusicScript, ScriptKind::Reslist].iter()
+        {
+            if self.get_script_path( *kind ) == changed_path
+            {
+                affected_kinds.push( *kind );
+            }
+        }
+
+        // Any scan that included the path is out of date now.
+        shared::invalidate_directory_cache( changed_path );
+
+        let mut local_sound_dir = self.args.rootdir.clone();
+        local_sound_dir.push("sound");
+
+        let mut gesource_sound_dir = self.args.gesdir.clone();
+        gesource_sound_dir.push("sound");
+
+        // New, removed, or renamed sounds change which files the music script is allowed to reference.
+        if changed_path.starts_with(&local_sound_dir) || changed_path.starts_with(&gesource_sound_dir)
+        {
+            if !affected_kinds.contains( &ScriptKind::MusicScript )
+            {
+                affected_kinds.push( ScriptKind::MusicScript );
+            }
+        }
+
+        // Anything in the release tree might need to be in the reslist, including the other scripts.
+        if changed_path.starts_with(&self.args.rootdir)
+        {
+            if !affected_kinds.contains( &ScriptKind::Reslist )
+            {
+                affected_kinds.push( ScriptKind::Reslist );
+            }
+        }
+
+        affected_kinds.into_iter().map(|x| self.validate(x)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_barebones_args;
+    use super::*;
+
+    #[test]
+    fn test_validator_invalidation()
+    {
+        let args = get_barebones_args();
+        let mut validator = Validator::new( args.clone(), "test_map" );
+
+        // Editing the map script re-checks it, along with the reslist that has to include it.
+        let map_script_path = validator.get_script_path( ScriptKind::MapScript );
+        let results = validator.invalidate( &map_script_path );
+        let kinds: Vec<ScriptKind> = results.iter().map(|x| x.kind).collect();
+        assert_eq!( kinds, vec![ScriptKind::MapScript, ScriptKind::Reslist] );
+        assert!( results[0].result.is_ok() );
+
+        // A new song could be referenced by the music script and has to be in the reslist.
+        let mut song_path = args.rootdir.clone();
+        song_path.push("sound/music/custom_song1.mp3");
+        let kinds: Vec<ScriptKind> = validator.invalidate( &song_path ).iter().map(|x| x.kind).collect();
+        assert_eq!( kinds, vec![ScriptKind::MusicScript, ScriptKind::Reslist] );
+
+        // Files outside of the release don't affect anything.
+        assert!( validator.invalidate( Path::new("/somewhere/else.txt") ).is_empty() );
+    }
+
+    #[test]
+    fn test_script_kind_from_path()
+    {
+        assert_eq!( ScriptKind::from_path( Path::new("gesource/maps/test_map.res") ), Some(ScriptKind::Reslist) );
+        assert_eq!( ScriptKind::from_path( Path::new("gesource\\scripts\\music\\level_music_test_map.txt") ), Some(ScriptKind::MusicScript) );
+        assert_eq!( ScriptKind::from_path( Path::new("gesource/Scripts/Maps/test_map.txt") ), Some(ScriptKind::MapScript) );
+        assert_eq!( ScriptKind::from_path( Path::new("gesource/scripts/soundscapes_test_map.txt") ), None );
+    }
+}
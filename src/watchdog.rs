@@ -0,0 +1,124 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// -----------------------------------------------------------------------------------------------------------
+// watchdog: Warns about slow operations, so a hung drive looks like a hung drive instead of a frozen program.
+// -----------------------------------------------------------------------------------------------------------
+
+use std::path::Path;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use shared;
+
+// A walk of even a full GE:S install finishes in seconds, so two minutes means something is wrong with the drive.
+pub static DEFAULT_WALK_TIMEOUT: usize = 120;
+
+static WALK_TIMEOUT: AtomicUsize = AtomicUsize::new(DEFAULT_WALK_TIMEOUT);
+
+/// Sets how many seconds a directory walk can take before we warn about it.  0 turns the warning off.
+pub fn set_walk_timeout( seconds: usize )
+{
+    WALK_TIMEOUT.store(seconds, Ordering::SeqCst);
+}
+
+/// Watches an operation from a background thread, printing a warning if it's still going after the timeout and
+/// another once it finally finishes.  The operation is over once the watchdog is dropped.
+pub struct Watchdog
+{
+    // Dropping the sender is what tells the background thread we're done.
+    _sender: Option<mpsc::Sender<()>>,
+    fired: Arc<AtomicBool>,
+}
+
+impl Watchdog
+{
+    /// Starts watching the operation with the given description, which should read like "walking C:\maps".
+    /// A timeout of zero doesn't watch anything.
+    pub fn start( description: String, timeout: Duration ) -> Watchdog
+    {
+        let fired = Arc::new(AtomicBool::new(false));
+
+        if timeout == Duration::from_secs(0)
+        {
+            return Watchdog { _sender: None, fired };
+        }
+
+        let (sender, receiver) = mpsc::channel::<()>();
+        let thread_fired = fired.clone();
+        let start_time = Instant::now();
+
+        // If the thread can't be started we just lose the warning, which isn't worth failing over.
+        let _ = thread::Builder::new().name( String::from("watchdog") ).spawn( move ||
+        {
+            match receiver.recv_timeout(timeout)
+            {
+                Err(RecvTimeoutError::Timeout) => {},
+                _ => return,
+            }
+
+            thread_fired.store(true, Ordering::SeqCst);
+            shared::print_warning( &format!( "{} exceeded {}s!  It may be on a slow or unresponsive drive.", get_capitalized(&description), timeout.as_secs() ) );
+
+            // Now that we've said something, let the user know if it ever does finish.
+            let _ = receiver.recv();
+            println!( "{} finished after {}s.", get_capitalized(&description), start_time.elapsed().as_secs() );
+        });
+
+        Watchdog { _sender: Some(sender), fired }
+    }
+
+    /// Returns true if the operation took longer than its timeout.
+    pub fn has_fired( &self ) -> bool
+    {
+        self.fired.load(Ordering::SeqCst)
+    }
+}
+
+/// Starts watching a walk of the given directory, using the configured walk timeout.
+pub fn watch_walk( directory: &Path ) -> Watchdog
+{
+    let timeout = WALK_TIMEOUT.load(Ordering::SeqCst) as u64;
+
+    Watchdog::start( format!( "walking {}", directory.display() ), Duration::from_secs(timeout) )
+}
+
+/// Returns the given text with its first letter capitalized.
+fn get_capitalized( text: &str ) -> String
+{
+    let mut characters = text.chars();
+
+    match characters.next()
+    {
+        Some(x) => x.to_uppercase().chain(characters).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn test_watchdog()
+    {
+        let slow_watchdog = Watchdog::start( String::from("sleeping"), Duration::from_millis(10) );
+        thread::sleep( Duration::from_millis(200) );
+        assert!( slow_watchdog.has_fired() );
+
+        let fast_watchdog = Watchdog::start( String::from("not sleeping"), Duration::from_secs(10) );
+        assert!( !fast_watchdog.has_fired() );
+
+        let disabled_watchdog = Watchdog::start( String::from("sleeping"), Duration::from_secs(0) );
+        thread::sleep( Duration::from_millis(50) );
+        assert!( !disabled_watchdog.has_fired() );
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright 2018 Entropy-Soldier
+//
+// Licensed under the MIT license: http://opensource.org/licenses/MIT
+// This file may not be copied, modified, or distributed except according to those terms.
+
+// ------------------------------------------------------------------------------------------------------------
+// weight_preview: Shows how likely a map is to be picked at each player count, given its map script settings.
+// ------------------------------------------------------------------------------------------------------------
+
+use std::fs;
+use std::path::PathBuf;
+
+use error::GesError;
+
+// How many characters wide the bar of a map at its full BaseWeight is.
+const BAR_WIDTH: i32 = 50;
+
+// The curve always covers at least this many players, so small ranges are shown in the context of a full server.
+const MIN_PREVIEWED_PLAYERS: i32 = 16;
+
+/// The map script settings that decide how likely a map is to be picked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WeightSettings
+{
+    pub base_weight: i32,
+    pub min_players: i32,
+    pub max_players: i32,
+}
+
+/// Returns the weight a map with the given settings is picked with at the given player count.  This follows what the
+/// generated map script comments describe: the full BaseWeight at the average of MinPlayers and MaxPlayers, falling
+/// off evenly the further the player count is from it, and nothing outside of the range.  The falloff reaches 0 one
+/// player past each end of the range, so the edges themselves can still be picked, just rarely.
+pub fn get_effective_weight( settings: &WeightSettings, player_count: i32 ) -> i32
+{
+    if player_count < settings.min_players || player_count > settings.max_players || settings.base_weight <= 0
+    {
+        return 0;
+    }
+
+    let midpoint = (settings.min_players + settings.max_players) as f64 / 2.0;
+    let falloff_distance = (settings.max_players - settings.min_players) as f64 / 2.0 + 1.0;
+    let scale = 1.0 - (player_count as f64 - midpoint).abs() / falloff_distance;
+
+    (settings.base_weight as f64 * scale).round() as i32
+}
+
+/// Reads the BaseWeight, MinPlayers, and MaxPlayers of an existing map script.  Anything the script leaves out or
+/// can't be read keeps the value it has in the given settings.
+pub fn read_script_settings( map_script_path: &PathBuf, settings: WeightSettings ) -> Result<WeightSettings, GesError>
+{
+    let contents = fs::read_to_string(map_script_path)?;
+    let mut settings = settings;
+
+    for line in contents.lines().filter(|x| !x.starts_with("//"))
+    {
+        let mut line_iter = line.split_whitespace().map(|x| x.trim_matches('"'));
+
+        let value = match (line_iter.next(), line_iter.next().map(|x| x.parse::<i32>()))
+        {
+            (Some(x), Some(Ok(y))) => (x, y),
+            _ => continue,
+        };
+
+        match value.0
+        {
+            "BaseWeight" => settings.base_weight = value.1,
+            "MinPlayers" => settings.min_players = value.1,
+            "MaxPlayers" => settings.max_players = value.1,
+            _ => {},
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Returns every player count worth previewing for the given settings, from an empty server to a bit past MaxPlayers.
+pub fn get_previewed_player_counts( settings: &WeightSettings ) -> Vec<i32>
+{
+    (0..=(settings.max_players + 2).max(MIN_PREVIEWED_PLAYERS)).collect()
+}
+
+/// Formats the weight of a map with the given settings at each player count, either as a bar chart or as CSV.
+pub fn format_weight_curve( settings: &WeightSettings, csv: bool ) -> String
+{
+    let mut curve = String::new();
+
+    if csv
+    {
+        curve.push_str("players,weight\n");
+    }
+    else
+    {
+        curve.push_str(&format!( "BaseWeight {}, MinPlayers {}, MaxPlayers {}\n\n", settings.base_weight, settings.min_players, settings.max_players ));
+        curve.push_str("Players  Weight\n");
+    }
+
+    for player_count in get_previewed_player_counts( settings )
+    {
+        let weight = get_effective_weight( settings, player_count );
+
+        if csv
+        {
+            curve.push_str(&format!( "{},{}\n", player_count, weight ));
+        }
+        else
+        {
+            let bar_length = if settings.base_weight > 0 { weight * BAR_WIDTH / settings.base_weight } else { 0 };
+            curve.push_str(&format!( "{:>7}  {:>6} |{}\n", player_count, weight, "#".repeat( bar_length as usize ) ));
+        }
+    }
+
+    curve
+}
+
+#[cfg(test)]
+mod tests
+{
+    use shared::get_root_test_directory;
+    use super::*;
+
+    #[test]
+    fn test_get_effective_weight()
+    {
+        let settings = WeightSettings { base_weight: 1000, min_players: 4, max_players: 12 };
+
+        assert_eq!( get_effective_weight( &settings, 8 ), 1000 );
+        assert_eq!( get_effective_weight( &settings, 6 ), 600 );
+        assert_eq!( get_effective_weight( &settings, 10 ), 600 );
+        assert_eq!( get_effective_weight( &settings, 4 ), 200 );
+        assert_eq!( get_effective_weight( &settings, 3 ), 0 );
+        assert_eq!( get_effective_weight( &settings, 13 ), 0 );
+
+        let csv = format_weight_curve( &settings, true );
+        assert!( csv.starts_with("players,weight\n0,0\n") && csv.contains("\n8,1000\n") && csv.ends_with("16,0\n") );
+    }
+
+    #[test]
+    fn test_read_script_settings()
+    {
+        let mut map_script_path = get_root_test_directory();
+        map_script_path.push("map_script_tests");
+        map_script_path.push("valid");
+        map_script_path.push("test_basic1.txt");
+
+        let settings = read_script_settings( &map_script_path, WeightSettings { base_weight: 0, min_players: 0, max_players: 0 } ).unwrap();
+
+        assert_eq!( settings, WeightSettings { base_weight: 700, min_players: -4, max_players: 28 } );
+    }
+}